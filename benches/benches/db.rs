@@ -0,0 +1,54 @@
+use ckb_db::batch::Batch;
+use ckb_db::diskdb::RocksDB;
+use ckb_db::kvdb::KeyValueDB;
+use ckb_db::memorydb::MemoryKeyValueDB;
+use criterion::{criterion_group, criterion_main, Bencher, Criterion};
+
+const BATCH_SIZES: [usize; 3] = [100, 1000, 10000];
+// About the size of a cell output
+const VALUE_SIZE: usize = 100;
+
+// A batch of `size` new keys, the round keeping them apart from the ones of
+// the previous rounds
+fn batch(size: usize, round: usize) -> Batch {
+    let mut batch = Batch::new();
+    for index in 0..size {
+        let key = format!("{:016x}{:016x}", round, index).into_bytes();
+        batch.insert(Some(0), key, vec![0xab; VALUE_SIZE]);
+    }
+    batch
+}
+
+fn write_batches<DB: KeyValueDB>(b: &mut Bencher, db: &DB, size: usize) {
+    let mut round = 0;
+    b.iter_with_setup(
+        || {
+            round += 1;
+            batch(size, round)
+        },
+        |batch| db.write(batch).expect("write batch"),
+    );
+}
+
+fn bench(c: &mut Criterion) {
+    c.bench_function_over_inputs(
+        "memorydb_batch_write",
+        |b, &&size| write_batches(b, &MemoryKeyValueDB::open(1), size),
+        &BATCH_SIZES,
+    );
+
+    c.bench_function_over_inputs(
+        "rocksdb_batch_write",
+        |b, &&size| {
+            let dir = tempfile::Builder::new()
+                .prefix("rocksdb_batch_write")
+                .tempdir()
+                .unwrap();
+            write_batches(b, &RocksDB::open(dir.path(), 1), size);
+        },
+        &BATCH_SIZES,
+    );
+}
+
+criterion_group!(benches, bench);
+criterion_main!(benches);
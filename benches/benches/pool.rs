@@ -0,0 +1,108 @@
+use ckb_chain_spec::consensus::Consensus;
+use ckb_core::script::Script;
+use ckb_core::transaction::{CellInput, CellOutput, OutPoint, Transaction, TransactionBuilder};
+use ckb_pool::txs_pool::PoolConfig;
+use ckb_test::{always_success_script, Delivery, Node};
+use criterion::{criterion_group, criterion_main, Criterion};
+
+const TRANSACTIONS: [usize; 2] = [100, 1000];
+const CAPACITY: u64 = 1_000_000;
+
+// A node with `count` live cells of the always success lock, and proposed
+// transactions spending each of them, paying fees from 0 to 99
+fn funded_node(count: usize, pool_config: PoolConfig) -> (Node, Vec<Transaction>) {
+    let node = Node::start_with_pool(0, Consensus::default(), pool_config, &Delivery::Threads);
+    let script = always_success_script();
+    let lock = script.type_hash();
+    let funding = TransactionBuilder::default()
+        .input(CellInput::new(OutPoint::null(), Script::default()))
+        .outputs(vec![
+            CellOutput::new(CAPACITY, Vec::new(), lock.clone(), None);
+            count
+        ])
+        .build();
+    let transactions: Vec<_> = (0..count)
+        .map(|index| {
+            TransactionBuilder::default()
+                .input(CellInput::new(
+                    OutPoint::new(funding.hash(), index as u32),
+                    script.clone(),
+                ))
+                .output(CellOutput::new(
+                    CAPACITY - (index % 100) as u64,
+                    Vec::new(),
+                    lock.clone(),
+                    None,
+                ))
+                .build()
+        })
+        .collect();
+    let proposals = transactions
+        .iter()
+        .map(Transaction::proposal_short_id)
+        .collect();
+    node.mine_block(proposals, vec![funding]);
+    (node, transactions)
+}
+
+fn pool_config(max_pool_size: usize) -> PoolConfig {
+    PoolConfig {
+        max_pool_size,
+        max_local_size: max_pool_size,
+        min_fee_rate: 0,
+        ..Default::default()
+    }
+}
+
+fn bench(c: &mut Criterion) {
+    c.bench_function_over_inputs(
+        "pool_admission",
+        |b, &&count| {
+            let (node, transactions) = funded_node(count, pool_config(count));
+            b.iter_with_setup(
+                || node.tx_pool().clear(),
+                |_| {
+                    for tx in &transactions {
+                        node.tx_pool()
+                            .add_transaction(tx.clone())
+                            .expect("add transaction");
+                    }
+                },
+            );
+            node.stop();
+        },
+        &TRANSACTIONS,
+    );
+
+    // The pool is full of relayed transactions, each local one evicts the
+    // one paying the lowest fee rate
+    c.bench_function_over_inputs(
+        "pool_eviction",
+        |b, &&count| {
+            let (node, transactions) = funded_node(2 * count, pool_config(count));
+            let (relayed, local) = transactions.split_at(count);
+            b.iter_with_setup(
+                || {
+                    node.tx_pool().clear();
+                    for tx in relayed {
+                        node.tx_pool()
+                            .add_transaction(tx.clone())
+                            .expect("add relayed transaction");
+                    }
+                },
+                |_| {
+                    for tx in local {
+                        node.tx_pool()
+                            .add_local_transaction(tx.clone())
+                            .expect("add local transaction");
+                    }
+                },
+            );
+            node.stop();
+        },
+        &TRANSACTIONS,
+    );
+}
+
+criterion_group!(benches, bench);
+criterion_main!(benches);
@@ -0,0 +1,64 @@
+use ckb_chain_spec::consensus::Consensus;
+use ckb_core::block::{Block, BlockBuilder};
+use ckb_core::cell::CellStatus;
+use ckb_core::header::HeaderBuilder;
+use ckb_core::transaction::{CellInput, CellOutput, OutPoint, TransactionBuilder};
+use ckb_test::always_success_script;
+use ckb_verification::verify_transactions;
+use criterion::{criterion_group, criterion_main, Criterion};
+use numext_fixed_hash::H256;
+
+const TRANSACTIONS: [usize; 3] = [10, 100, 1000];
+const CAPACITY: u64 = 1_000_000;
+
+// A block of `count` transactions, each spending a live cell of the always
+// success lock
+fn block(count: usize) -> Block {
+    let script = always_success_script();
+    let lock = script.type_hash();
+    let cellbase = TransactionBuilder::default()
+        .input(CellInput::new_cellbase_input(1))
+        .output(CellOutput::new(CAPACITY, Vec::new(), lock.clone(), None))
+        .build();
+    let transactions = (0..count).map(|index| {
+        TransactionBuilder::default()
+            .input(CellInput::new(OutPoint::new(cell_hash(index), 0), script.clone()))
+            .output(CellOutput::new(CAPACITY, Vec::new(), lock.clone(), None))
+            .build()
+    });
+    BlockBuilder::default()
+        .commit_transaction(cellbase)
+        .commit_transactions(transactions.collect())
+        .with_header_builder(HeaderBuilder::default().number(1))
+}
+
+fn cell_hash(index: usize) -> H256 {
+    let mut hash = [0u8; 32];
+    hash[..8].copy_from_slice(&(index as u64).to_le_bytes());
+    H256::from_slice(&hash).expect("32 bytes hash")
+}
+
+fn bench(c: &mut Criterion) {
+    c.bench_function_over_inputs(
+        "verify_transactions",
+        |b, &&count| {
+            let block = block(count);
+            let consensus = Consensus::default();
+            let lock = always_success_script().type_hash();
+            let live = CellOutput::new(CAPACITY, Vec::new(), lock, None);
+            b.iter(|| {
+                verify_transactions(
+                    &block,
+                    consensus.max_block_cycles,
+                    consensus.max_tx_cycles,
+                    |_| CellStatus::Live(live.clone()),
+                )
+                .expect("verify transactions")
+            })
+        },
+        &TRANSACTIONS,
+    );
+}
+
+criterion_group!(benches, bench);
+criterion_main!(benches);
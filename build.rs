@@ -10,4 +10,13 @@ fn main() {
         "cargo:rustc-env=COMMIT_DATE={}",
         build_info::get_commit_date().unwrap_or_default()
     );
+    println!(
+        "cargo:rustc-env=COMMIT_HASH={}",
+        build_info::get_commit_hash().unwrap_or_default()
+    );
+    println!("cargo:rustc-env=BUILD_TIME={}", build_info::get_build_time());
+    println!(
+        "cargo:rustc-env=BUILD_FEATURES={}",
+        build_info::get_features().join(",")
+    );
 }
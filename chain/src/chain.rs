@@ -2,30 +2,43 @@ use crate::error::ProcessBlockError;
 use ckb_core::block::Block;
 use ckb_core::cell::CellProvider;
 use ckb_core::extras::BlockExt;
-use ckb_core::header::BlockNumber;
+use ckb_core::header::{BlockNumber, Header};
 use ckb_core::service::{Request, DEFAULT_CHANNEL_SIZE, SIGNAL_CHANNEL_SIZE};
 use ckb_core::transaction::OutPoint;
 use ckb_db::batch::Batch;
-use ckb_notify::{ForkBlocks, NotifyController};
+use ckb_metrics::{registry, Counter, Gauge, Histogram, LATENCY_BUCKETS};
+use ckb_notify::{ForkSwitch, NotifyController};
 use ckb_shared::error::SharedError;
 use ckb_shared::index::ChainIndex;
 use ckb_shared::shared::{ChainProvider, ChainState, Shared};
 use ckb_shared::txo_set::TxoSetDiff;
-use ckb_verification::{verify_transactions, BlockVerifier, Verifier};
+use ckb_time::unix_time_as_millis;
+use ckb_verification::{
+    verify_transactions, BlockVerifier, DifficultyError, Error as VerifyError,
+    HeaderResolverWrapper, HeaderVerifier, TimestampError, Verifier,
+};
 use crossbeam_channel::{self, select, Receiver, Sender};
-use faketime::unix_time_as_millis;
 use fnv::{FnvHashMap, FnvHashSet};
 use log::{self, debug, error, log_enabled};
+use lru_cache::LruCache;
 use numext_fixed_hash::H256;
 use numext_fixed_uint::U256;
 use std::cmp;
 use std::sync::Arc;
 use std::thread;
+use std::time::Instant;
 use stop_handler::{SignalSender, StopHandler};
 
+// Bound the work done after a single block when pruning catches up
+const MAX_PRUNE_BLOCKS_PER_ROUND: BlockNumber = 1000;
+const INVALID_BLOCKS_CACHE_SIZE: usize = 4096;
+// Block counts over which the average block interval is reported
+const STATS_BLOCK_INTERVAL_WINDOWS: [BlockNumber; 3] = [10, 100, 1000];
+
 #[derive(Clone)]
 pub struct ChainController {
     process_block_sender: Sender<Request<Arc<Block>, Result<(), ProcessBlockError>>>,
+    stats_sender: Sender<Request<(), ChainStats>>,
     stop: StopHandler<()>,
 }
 
@@ -39,18 +52,60 @@ impl ChainController {
     pub fn process_block(&self, block: Arc<Block>) -> Result<(), ProcessBlockError> {
         Request::call(&self.process_block_sender, block).expect("process_block() failed")
     }
+
+    pub fn stats(&self) -> ChainStats {
+        Request::call(&self.stats_sender, ()).expect("stats() failed")
+    }
+
+    /// Stops the service after the block being processed, if any
+    pub fn stop(&self) {
+        self.stop.stop();
+    }
 }
 
 struct ChainReceivers {
     process_block_receiver: Receiver<Request<Arc<Block>, Result<(), ProcessBlockError>>>,
+    stats_receiver: Receiver<Request<(), ChainStats>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChainStats {
+    pub tip_header: Header,
+    pub total_difficulty: U256,
+    pub total_transactions_count: u64,
+    pub live_cells_count: u64,
+    /// `(blocks, milliseconds)`: average interval of the latest blocks
+    pub average_block_intervals: Vec<(BlockNumber, u64)>,
 }
 
 #[derive(Debug, Clone)]
 pub struct BlockInsertionResult {
-    pub fork_blks: ForkBlocks,
+    pub fork_switch: ForkSwitch,
     pub new_best_block: bool,
 }
 
+struct ChainMetrics {
+    process_durations: Arc<Histogram>,
+    rejected_blocks: Arc<Counter>,
+    tip_number: Arc<Gauge>,
+    switched_forks: Arc<Counter>,
+}
+
+impl ChainMetrics {
+    fn new() -> Self {
+        ChainMetrics {
+            process_durations: registry().histogram(
+                "chain_process_block_duration_us",
+                Vec::new(),
+                LATENCY_BUCKETS,
+            ),
+            rejected_blocks: registry().counter("chain_rejected_blocks_total", Vec::new()),
+            tip_number: registry().gauge("chain_tip_number", Vec::new()),
+            switched_forks: registry().counter("chain_switched_forks_total", Vec::new()),
+        }
+    }
+}
+
 struct Fork {
     new_blocks: Vec<Block>,
     old_blocks: Vec<Block>,
@@ -61,6 +116,14 @@ pub struct ChainService<CI> {
     shared: Shared<CI>,
     notify: NotifyController,
     verification: bool,
+    prune_depth: Option<BlockNumber>,
+    ancient_depth: Option<BlockNumber>,
+    // Blocks whose header failed verification and their descendants, so that
+    // the same bad block relayed by several peers is only verified once. A
+    // block whose body failed is not kept, as a peer may have sent the header
+    // of a valid block with a broken body
+    invalid_blocks: LruCache<H256, VerifyError>,
+    metrics: ChainMetrics,
 }
 
 impl<CI: ChainIndex + 'static> ChainService<CI> {
@@ -68,11 +131,17 @@ impl<CI: ChainIndex + 'static> ChainService<CI> {
         shared: Shared<CI>,
         notify: NotifyController,
         verification: bool,
+        prune_depth: Option<BlockNumber>,
+        ancient_depth: Option<BlockNumber>,
     ) -> ChainService<CI> {
         ChainService {
             shared,
             notify,
             verification,
+            prune_depth,
+            ancient_depth,
+            invalid_blocks: LruCache::new(INVALID_BLOCKS_CACHE_SIZE),
+            metrics: ChainMetrics::new(),
         }
     }
 
@@ -81,6 +150,7 @@ impl<CI: ChainIndex + 'static> ChainService<CI> {
             crossbeam_channel::bounded::<()>(SIGNAL_CHANNEL_SIZE);
         let (process_block_sender, process_block_receiver) =
             crossbeam_channel::bounded(DEFAULT_CHANNEL_SIZE);
+        let (stats_sender, stats_receiver) = crossbeam_channel::bounded(DEFAULT_CHANNEL_SIZE);
 
         // Mainly for test: give a empty thread_name
         let mut thread_builder = thread::Builder::new();
@@ -90,40 +160,121 @@ impl<CI: ChainIndex + 'static> ChainService<CI> {
 
         let receivers = ChainReceivers {
             process_block_receiver,
+            stats_receiver,
         };
         let thread = thread_builder
-            .spawn(move || loop {
+            .spawn(ckb_time::inherit(move || loop {
                 select! {
                     recv(signal_receiver) -> _ => {
                         break;
                     },
                     recv(receivers.process_block_receiver) -> msg => match msg {
                         Ok(Request { responder, arguments: block }) => {
-                            let _ = responder.send(self.process_block(block));
+                            let started_at = Instant::now();
+                            let result = self.process_block(block);
+                            self.metrics.process_durations.observe_duration(started_at.elapsed());
+                            if result.is_err() {
+                                self.metrics.rejected_blocks.inc();
+                            }
+                            let _ = responder.send(result);
                         },
                         _ => {
                             error!(target: "chain", "process_block_receiver closed");
                             break;
                         },
+                    },
+                    recv(receivers.stats_receiver) -> msg => match msg {
+                        Ok(Request { responder, .. }) => {
+                            let _ = responder.send(self.stats());
+                        },
+                        _ => {
+                            error!(target: "chain", "stats_receiver closed");
+                            break;
+                        },
                     }
                 }
-            })
+            }))
             .expect("Start ChainService failed");
         let stop = StopHandler::new(SignalSender::Crossbeam(signal_sender), thread);
 
         ChainController {
             process_block_sender,
+            stats_sender,
             stop,
         }
     }
 
+    fn stats(&self) -> ChainStats {
+        let chain_state = self.shared.chain_state().read();
+        let tip_header = chain_state.tip_header().clone();
+        let total_transactions_count = self
+            .shared
+            .block_ext(&tip_header.hash())
+            .map_or(0, |ext| ext.total_transactions_count);
+
+        let average_block_intervals = STATS_BLOCK_INTERVAL_WINDOWS
+            .iter()
+            .cloned()
+            .filter(|window| *window <= tip_header.number())
+            .filter_map(|window| {
+                self.shared
+                    .get_ancestor(&tip_header.hash(), tip_header.number() - window)
+                    .map(|start| {
+                        let elapsed = tip_header.timestamp().saturating_sub(start.timestamp());
+                        (window, elapsed / window)
+                    })
+            })
+            .collect();
+
+        ChainStats {
+            total_difficulty: chain_state.total_difficulty().clone(),
+            total_transactions_count,
+            live_cells_count: chain_state.txo_set().live_cells_count(),
+            average_block_intervals,
+            tip_header,
+        }
+    }
+
     fn process_block(&mut self, block: Arc<Block>) -> Result<(), ProcessBlockError> {
+        let _scope = ckb_profiler::scope("process_block");
+        let _context = logger::context(vec![
+            ("block", format!("{:#x}", block.header().hash())),
+            ("number", block.header().number().to_string()),
+        ]);
         debug!(target: "chain", "begin processing block: {}", block.header().hash());
         if self.verification {
+            let hash = block.header().hash();
+            let cached = self.invalid_blocks.get_mut(&hash).cloned().or_else(|| {
+                self.invalid_blocks
+                    .get_mut(block.header().parent_hash())
+                    .cloned()
+            });
+            if let Some(err) = cached {
+                debug!(target: "chain", "reject known invalid block: {}", hash);
+                self.invalid_blocks.insert(hash, err.clone());
+                return Err(ProcessBlockError::Verification(err));
+            }
+
+            let consensus = self.shared.consensus();
+            let header_verifier = HeaderVerifier::new(
+                self.shared.clone(),
+                consensus.pow_engine(),
+                consensus.max_block_time_drift(),
+            );
+            let resolver = HeaderResolverWrapper::new(block.header(), self.shared.clone());
+            if let Err(err) = header_verifier.verify(&resolver) {
+                if is_invalid_header(&err) {
+                    self.invalid_blocks.insert(hash, err.clone());
+                }
+                return Err(ProcessBlockError::Verification(err));
+            }
+
             let block_verifier = BlockVerifier::new(self.shared.clone());
-            block_verifier
-                .verify(&block)
-                .map_err(ProcessBlockError::Verification)?
+            let verified = {
+                let _scope = ckb_profiler::scope("verify_block");
+                block_verifier.verify(&block)
+            };
+            verified.map_err(ProcessBlockError::Verification)?;
         }
         let insert_result = self
             .insert_block(&block)
@@ -135,6 +286,7 @@ impl<CI: ChainIndex + 'static> ChainService<CI> {
 
     #[allow(clippy::op_ref)]
     fn insert_block(&self, block: &Block) -> Result<BlockInsertionResult, SharedError> {
+        let _scope = ckb_profiler::scope("insert_block");
         let mut new_best_block = false;
         let mut total_difficulty = U256::zero();
 
@@ -165,6 +317,8 @@ impl<CI: ChainIndex + 'static> ChainService<CI> {
             received_at: unix_time_as_millis(),
             total_difficulty: cannon_total_difficulty.clone(),
             total_uncles_count: parent_ext.total_uncles_count + block.uncles().len() as u64,
+            total_transactions_count: parent_ext.total_transactions_count
+                + block.commit_transactions().len() as u64,
             valid: if parent_ext.valid == Some(false) {
                 Some(false)
             } else {
@@ -174,6 +328,7 @@ impl<CI: ChainIndex + 'static> ChainService<CI> {
 
         self.shared.store().save_with_batch(|batch| {
             self.shared.store().insert_block(batch, block);
+            self.shared.record_deployment_states(batch, block.header());
 
             if &cannon_total_difficulty > current_total_difficulty
                 || (current_total_difficulty == &cannon_total_difficulty
@@ -214,27 +369,43 @@ impl<CI: ChainIndex + 'static> ChainService<CI> {
             chain_state.update_header(block.header().clone());
             chain_state.update_difficulty(total_difficulty);
             chain_state.update_txo_set(txo_set_diff);
+            for new_block in &new_blocks {
+                self.shared.warm_cell_cache(new_block.commit_transactions());
+            }
 
             debug!(target: "chain", "update index release");
+
+            // The block is stored already, a round failing is tried again with the next one
+            let moved = if let Some(depth) = self.prune_depth {
+                self.prune(&*chain_state, depth)
+            } else if let Some(depth) = self.ancient_depth {
+                self.freeze(&*chain_state, depth)
+            } else {
+                Ok(())
+            };
+            if let Err(err) = moved {
+                error!(target: "chain", "move the old block bodies failed: {:?}", err);
+            }
         }
 
         Ok(BlockInsertionResult {
             new_best_block,
-            fork_blks: ForkBlocks::new(old_blocks, new_blocks),
+            fork_switch: ForkSwitch::new(old_blocks, new_blocks),
         })
     }
 
     fn post_insert_result(&mut self, block: Arc<Block>, result: BlockInsertionResult) {
         let BlockInsertionResult {
             new_best_block,
-            mut fork_blks,
+            fork_switch,
         } = result;
-        if !fork_blks.old_blks().is_empty() {
-            fork_blks.push_new(Block::clone(&block));
-            self.notify.notify_switch_fork(Arc::new(fork_blks.clone()));
+        if !fork_switch.detached.is_empty() {
+            self.metrics.switched_forks.inc();
+            self.notify.notify_switch_fork(Arc::new(fork_switch));
         }
 
         if new_best_block {
+            self.metrics.tip_number.set(block.header().number() as usize);
             self.notify.notify_new_tip(block);
             if log_enabled!(target: "chain", log::Level::Debug) {
                 self.print_chain(10);
@@ -244,6 +415,76 @@ impl<CI: ChainIndex + 'static> ChainService<CI> {
         }
     }
 
+    // Prunes main chain blocks deeper than `depth` below the tip, genesis is always kept
+    fn prune(&self, chain_state: &ChainState, depth: BlockNumber) -> Result<(), SharedError> {
+        let store = self.shared.store();
+        let start = cmp::max(store.get_pruned_below(), 1);
+        let end = cmp::min(
+            chain_state.tip_number().saturating_sub(depth),
+            start + MAX_PRUNE_BLOCKS_PER_ROUND,
+        );
+        if start >= end {
+            return Ok(());
+        }
+
+        debug!(target: "chain", "prune blocks [{}, {})", start, end);
+        store.save_with_batch(|batch| {
+            let mut spent = FnvHashSet::default();
+            for number in start..end {
+                let block = self
+                    .shared
+                    .block_hash(number)
+                    .and_then(|hash| store.get_block(&hash))
+                    .expect("main chain block must be stored");
+                store.prune_block(batch, &block, chain_state.txo_set());
+                spent.extend(
+                    block
+                        .commit_transactions()
+                        .iter()
+                        .filter(|tx| !tx.is_cellbase())
+                        .flat_map(|tx| tx.input_pts())
+                        .map(|out_point| out_point.hash),
+                );
+            }
+            // The transactions kept by the blocks pruned before may be spent
+            // by these ones
+            let spent: Vec<_> = spent.into_iter().collect();
+            store.prune_spent_transactions(batch, &spent, chain_state.txo_set());
+            store.insert_pruned_below(batch, end);
+            Ok(())
+        })
+    }
+
+    // Moves the bodies of main chain blocks deeper than `depth` below the tip
+    // to the ancient store. Pruned blocks have no body to move, so nothing is
+    // frozen when pruning.
+    fn freeze(&self, chain_state: &ChainState, depth: BlockNumber) -> Result<(), SharedError> {
+        let store = self.shared.store();
+        let start = match store.ancient_len() {
+            Some(len) => len,
+            None => return Ok(()),
+        };
+        let end = cmp::min(
+            chain_state.tip_number().saturating_sub(depth),
+            start + MAX_PRUNE_BLOCKS_PER_ROUND,
+        );
+        if start >= end {
+            return Ok(());
+        }
+
+        debug!(target: "chain", "freeze blocks [{}, {})", start, end);
+        store.save_with_batch(|batch| {
+            for number in start..end {
+                let hash = self
+                    .shared
+                    .block_hash(number)
+                    .expect("main chain block must be stored");
+                store.freeze_block(batch, number, &hash)?;
+            }
+            Ok(())
+        })
+    }
+
     fn update_index(&self, batch: &mut Batch, old_blocks: &[Block], new_blocks: &[Block]) {
         let old_number = match old_blocks.get(0) {
             Some(b) => b.header().number(),
@@ -418,10 +659,11 @@ impl<CI: ChainIndex + 'static> ChainService<CI> {
         }
 
         let max_cycles = self.shared.consensus().max_block_cycles();
+        let max_tx_cycles = self.shared.consensus().max_tx_cycles();
         // The verify function
         let verify =
             |b, new_inputs: &FnvHashSet<OutPoint>, new_outputs: &FnvHashMap<H256, usize>| -> bool {
-                verify_transactions(b, max_cycles, |op| {
+                verify_transactions(b, max_cycles, max_tx_cycles, |op| {
                     self.shared.cell_at(op, |op| {
                         if new_inputs.contains(op) {
                             Some(true)
@@ -488,6 +730,7 @@ impl<CI: ChainIndex + 'static> ChainService<CI> {
         self.update_index(batch, &fork.old_blocks, &fork.new_blocks);
 
         fork.new_blocks.reverse();
+        fork.old_blocks.sort_by_key(|b| cmp::Reverse(b.header().number()));
 
         let old_inputs: Vec<OutPoint> = old_inputs.into_iter().collect();
         let old_outputs: Vec<H256> = old_outputs.into_iter().collect();
@@ -548,10 +791,26 @@ impl<CI: ChainIndex + 'static> ChainService<CI> {
     }
 }
 
+// Errors which may go away once more blocks are known or time passes
+// The errors the header determines, with the ancestors its parent hash
+// fixes, while a block too new for the local clock or an orphan may become
+// valid
+fn is_invalid_header(err: &VerifyError) -> bool {
+    match err {
+        VerifyError::Pow(_)
+        | VerifyError::Number(_)
+        | VerifyError::Difficulty(DifficultyError::MixMismatch { .. })
+        | VerifyError::Timestamp(TimestampError::BlockTimeTooOld { .. }) => true,
+        _ => false,
+    }
+}
+
 pub struct ChainBuilder<CI> {
     shared: Shared<CI>,
     notify: NotifyController,
     verification: bool,
+    prune_depth: Option<BlockNumber>,
+    ancient_depth: Option<BlockNumber>,
 }
 
 impl<CI: ChainIndex + 'static> ChainBuilder<CI> {
@@ -560,6 +819,8 @@ impl<CI: ChainIndex + 'static> ChainBuilder<CI> {
             shared,
             notify,
             verification: true,
+            prune_depth: None,
+            ancient_depth: None,
         }
     }
 
@@ -573,26 +834,48 @@ impl<CI: ChainIndex + 'static> ChainBuilder<CI> {
         self
     }
 
+    /// Keeps only the bodies of the latest `depth` blocks
+    pub fn prune_depth(mut self, depth: Option<BlockNumber>) -> Self {
+        self.prune_depth = depth;
+        self
+    }
+
+    /// Moves the bodies of the blocks deeper than `depth` to the ancient
+    /// store of the chain, when the store has one and pruning is disabled
+    pub fn ancient_depth(mut self, depth: Option<BlockNumber>) -> Self {
+        self.ancient_depth = depth;
+        self
+    }
+
     pub fn build(self) -> ChainService<CI> {
-        ChainService::new(self.shared, self.notify, self.verification)
+        ChainService::new(
+            self.shared,
+            self.notify,
+            self.verification,
+            self.prune_depth,
+            self.ancient_depth,
+        )
     }
 }
 
 #[cfg(test)]
 pub mod test {
     use super::*;
+    use crate::test_utils::{self, Fork};
     use ckb_chain_spec::consensus::Consensus;
     use ckb_core::block::BlockBuilder;
-    use ckb_core::cell::CellProvider;
+    use ckb_core::cell::{CellProvider, CellStatus};
     use ckb_core::header::{Header, HeaderBuilder};
     use ckb_core::transaction::{
         CellInput, CellOutput, OutPoint, ProposalShortId, Transaction, TransactionBuilder,
     };
     use ckb_core::uncle::UncleBlock;
+    use ckb_db::kvdb::KeyValueDB;
     use ckb_db::memorydb::MemoryKeyValueDB;
     use ckb_notify::NotifyService;
     use ckb_shared::shared::SharedBuilder;
-    use ckb_shared::store::ChainKVStore;
+    use ckb_shared::store::{ChainKVStore, ChainStore};
+    use ckb_shared::COLUMN_BLOCK_BODY;
     use numext_fixed_uint::U256;
 
     fn start_chain(
@@ -654,8 +937,8 @@ pub mod test {
             .build()
     }
 
-    #[test]
-    fn test_genesis_transaction_spend() {
+    // A genesis committing a transaction with 100 outputs to spend
+    fn spendable_genesis() -> (Consensus, H256) {
         let tx = TransactionBuilder::default()
             .input(CellInput::new(OutPoint::null(), Default::default()))
             .outputs(vec![
@@ -668,14 +951,44 @@ pub mod test {
                 100
             ])
             .build();
-
-        let mut root_hash = tx.hash().clone();
+        let root_hash = tx.hash().clone();
 
         let genesis_block = BlockBuilder::default()
             .commit_transaction(tx)
             .with_header_builder(HeaderBuilder::default().difficulty(U256::from(1000u64)));
+        (
+            Consensus::default().set_genesis_block(genesis_block),
+            root_hash,
+        )
+    }
 
-        let consensus = Consensus::default().set_genesis_block(genesis_block);
+    // Processes `count` blocks on the tip, each one spending an output of the
+    // transaction of the previous one
+    fn process_spending_blocks(
+        chain_controller: &ChainController,
+        shared: &Shared<ChainKVStore<MemoryKeyValueDB>>,
+        mut root_hash: H256,
+        count: u64,
+    ) -> Vec<Block> {
+        let mut blocks = vec![];
+        let mut parent = shared.chain_state().read().tip_header().clone();
+        for i in 1..=count {
+            let difficulty = parent.difficulty().clone();
+            let tx = create_transaction(root_hash);
+            root_hash = tx.hash().clone();
+            let new_block = gen_block(&parent, i, difficulty, vec![tx], vec![]);
+            assert!(chain_controller
+                .process_block(Arc::new(new_block.clone()))
+                .is_ok());
+            parent = new_block.header().clone();
+            blocks.push(new_block);
+        }
+        blocks
+    }
+
+    #[test]
+    fn test_genesis_transaction_spend() {
+        let (consensus, mut root_hash) = spendable_genesis();
         let (chain_controller, shared) = start_chain(Some(consensus));
 
         let end = 21;
@@ -698,6 +1011,200 @@ pub mod test {
         }
     }
 
+    #[test]
+    fn test_transaction_address() {
+        let (consensus, root_hash) = spendable_genesis();
+        let (chain_controller, shared) = start_chain(Some(consensus));
+
+        let parent = shared.block_header(&shared.block_hash(0).unwrap()).unwrap();
+        let difficulty = parent.difficulty().clone();
+        let tx = create_transaction(root_hash);
+        let block = gen_block(&parent, 1, difficulty, vec![tx.clone()], vec![]);
+        assert!(chain_controller
+            .process_block(Arc::new(block.clone()))
+            .is_ok());
+
+        let address = shared.get_transaction_address(&tx.hash()).unwrap();
+        assert_eq!(address.block_hash, block.header().hash());
+        assert_eq!(address.index, 1);
+        assert_eq!(shared.get_transaction(&tx.hash()), Some(tx));
+    }
+
+    #[test]
+    fn test_prune_blocks() {
+        let (consensus, root_hash) = spendable_genesis();
+        let shared = SharedBuilder::<ChainKVStore<MemoryKeyValueDB>>::new_memory()
+            .consensus(consensus)
+            .build();
+        let notify = NotifyService::default().start::<&str>(None);
+        let chain_controller = ChainBuilder::new(shared.clone(), notify)
+            .verification(false)
+            .prune_depth(Some(5))
+            .build()
+            .start::<&str>(None);
+
+        let blocks = process_spending_blocks(&chain_controller, &shared, root_hash, 10);
+
+        assert_eq!(shared.store().get_pruned_below(), 5);
+        for block in &blocks[0..4] {
+            let hash = block.header().hash();
+            assert_eq!(shared.block(&hash), None);
+            assert_eq!(shared.block_header(&hash).as_ref(), Some(block.header()));
+
+            // transactions with live outputs are kept
+            let tx = &block.commit_transactions()[1];
+            assert_eq!(shared.get_transaction(&tx.hash()).as_ref(), Some(tx));
+            assert_eq!(
+                shared.cell(&OutPoint::new(tx.hash(), 1)),
+                CellStatus::Live(tx.outputs()[1].clone())
+            );
+        }
+        assert!(shared.block(&blocks[4].header().hash()).is_some());
+
+        let txo_set = Shared::<ChainKVStore<MemoryKeyValueDB>>::init_txo_set(shared.store(), 10);
+        assert_eq!(&txo_set, shared.chain_state().read().txo_set());
+    }
+
+    #[test]
+    fn test_freeze_blocks() {
+        let (consensus, root_hash) = spendable_genesis();
+        let tmp_dir = tempfile::Builder::new()
+            .prefix("freeze_blocks")
+            .tempdir()
+            .unwrap();
+        let shared = SharedBuilder::<ChainKVStore<MemoryKeyValueDB>>::new_memory()
+            .consensus(consensus)
+            .ancient(tmp_dir.path())
+            .build();
+        let notify = NotifyService::default().start::<&str>(None);
+        let chain_controller = ChainBuilder::new(shared.clone(), notify)
+            .verification(false)
+            .ancient_depth(Some(5))
+            .build()
+            .start::<&str>(None);
+
+        let blocks = process_spending_blocks(&chain_controller, &shared, root_hash, 10);
+
+        assert_eq!(shared.store().ancient_len(), Some(5));
+        for block in &blocks {
+            let hash = block.header().hash();
+            let in_db = shared
+                .store()
+                .db
+                .read(COLUMN_BLOCK_BODY, hash.as_bytes())
+                .unwrap()
+                .is_some();
+            assert_eq!(in_db, block.header().number() >= 5);
+            assert_eq!(shared.block(&hash).as_ref(), Some(block));
+            let tx = &block.commit_transactions()[1];
+            assert_eq!(shared.get_transaction(&tx.hash()).as_ref(), Some(tx));
+        }
+    }
+
+    #[test]
+    fn test_invalid_block_cache() {
+        let shared = SharedBuilder::<ChainKVStore<MemoryKeyValueDB>>::new_memory().build();
+        let notify = NotifyService::default().start::<&str>(None);
+        let mut chain_service = ChainBuilder::new(shared.clone(), notify).build();
+
+        let parent = shared.block_header(&shared.block_hash(0).unwrap()).unwrap();
+        // A block numbered 2 on top of the genesis never becomes valid
+        let block = BlockBuilder::default()
+            .commit_transaction(test_utils::cellbase(&shared, 2, &H256::zero()))
+            .with_header_builder(
+                HeaderBuilder::default()
+                    .parent_hash(parent.hash().clone())
+                    .number(2)
+                    .timestamp(unix_time_as_millis())
+                    .difficulty(shared.calculate_difficulty(&parent).unwrap()),
+            );
+        let err = chain_service
+            .process_block(Arc::new(block.clone()))
+            .unwrap_err();
+        match err {
+            ProcessBlockError::Verification(VerifyError::Number(_)) => {}
+            ref x => panic!("Unexpected result {:?}", x),
+        }
+        assert!(chain_service
+            .invalid_blocks
+            .contains_key(&block.header().hash()));
+        assert_eq!(chain_service.process_block(Arc::new(block.clone())), Err(err.clone()));
+
+        let child = gen_block(block.header(), 0, parent.difficulty().clone(), vec![], vec![]);
+        assert_eq!(chain_service.process_block(Arc::new(child.clone())), Err(err));
+        assert!(chain_service
+            .invalid_blocks
+            .contains_key(&child.header().hash()));
+    }
+
+    #[test]
+    fn test_invalid_body_not_cached() {
+        let shared = SharedBuilder::<ChainKVStore<MemoryKeyValueDB>>::new_memory().build();
+        let notify = NotifyService::default().start::<&str>(None);
+        let mut chain_service = ChainBuilder::new(shared.clone(), notify).build();
+
+        let parent = shared.block_header(&shared.block_hash(0).unwrap()).unwrap();
+        let honest = BlockBuilder::default()
+            .commit_transaction(test_utils::cellbase(&shared, 1, &H256::zero()))
+            .with_header_builder(
+                HeaderBuilder::default()
+                    .parent_hash(parent.hash().clone())
+                    .number(1)
+                    .timestamp(unix_time_as_millis())
+                    .difficulty(shared.calculate_difficulty(&parent).unwrap()),
+            );
+        // The header of the honest block with a transaction it does not commit
+        let tampered = BlockBuilder::default()
+            .block(honest.clone())
+            .commit_transaction(create_transaction(H256::zero()))
+            .build();
+        assert_eq!(tampered.header().hash(), honest.header().hash());
+
+        assert_eq!(
+            chain_service.process_block(Arc::new(tampered)),
+            Err(ProcessBlockError::Verification(VerifyError::CommitTransactionsRoot))
+        );
+        assert!(!chain_service
+            .invalid_blocks
+            .contains_key(&honest.header().hash()));
+        assert_eq!(chain_service.process_block(Arc::new(honest.clone())), Ok(()));
+        assert_eq!(shared.chain_state().read().tip_hash(), honest.header().hash());
+    }
+
+    #[test]
+    fn test_chain_stats() {
+        let genesis_block = BlockBuilder::default()
+            .commit_transaction(create_cellbase(0))
+            .with_header_builder(HeaderBuilder::default().difficulty(U256::from(1000u64)));
+        let consensus = Consensus::default().set_genesis_block(genesis_block);
+        let (chain_controller, shared) = start_chain(Some(consensus));
+
+        let mut parent = shared.block_header(&shared.block_hash(0).unwrap()).unwrap();
+        for i in 1..=10 {
+            let header = HeaderBuilder::default()
+                .parent_hash(parent.hash())
+                .timestamp(i * 2000)
+                .number(i)
+                .difficulty(parent.difficulty().clone())
+                .build();
+            let block = BlockBuilder::default()
+                .header(header)
+                .commit_transaction(create_cellbase(i))
+                .build();
+            chain_controller
+                .process_block(Arc::new(block.clone()))
+                .expect("process block ok");
+            parent = block.header().clone();
+        }
+
+        let stats = chain_controller.stats();
+        assert_eq!(stats.tip_header, parent);
+        assert_eq!(stats.total_difficulty, U256::from(11000u64));
+        assert_eq!(stats.total_transactions_count, 11);
+        assert_eq!(stats.live_cells_count, 11);
+        assert_eq!(stats.average_block_intervals, vec![(10, 2000)]);
+    }
+
     #[test]
     fn test_genesis_transaction_fetch() {
         let tx = TransactionBuilder::default()
@@ -775,6 +1282,74 @@ pub mod test {
         );
     }
 
+    #[test]
+    fn test_switch_fork_notify() {
+        let shared = SharedBuilder::<ChainKVStore<MemoryKeyValueDB>>::new_memory().build();
+        let notify = NotifyService::default().start::<&str>(None);
+        let switch_fork_receiver = notify.subscribe_switch_fork("test");
+        let chain_controller = ChainBuilder::new(shared.clone(), notify)
+            .verification(false)
+            .build()
+            .start::<&str>(None);
+
+        let genesis = shared.block_header(&shared.block_hash(0).unwrap()).unwrap();
+        let difficulty = genesis.difficulty().clone();
+
+        let mut chain1 = Vec::new();
+        let mut parent = genesis.clone();
+        for i in 1..4 {
+            let new_block = gen_block(&parent, i, difficulty.clone(), vec![], vec![]);
+            parent = new_block.header().clone();
+            chain1.push(new_block);
+        }
+
+        let block_a = gen_block(&genesis, 100, difficulty.clone(), vec![], vec![]);
+        let block_b = gen_block(
+            block_a.header(),
+            101,
+            difficulty * U256::from(3u64),
+            vec![],
+            vec![],
+        );
+
+        for block in chain1.iter().chain(vec![&block_a, &block_b]) {
+            chain_controller
+                .process_block(Arc::new(block.clone()))
+                .expect("process block ok");
+        }
+
+        let fork_switch = switch_fork_receiver.recv().unwrap();
+        chain1.reverse();
+        assert_eq!(fork_switch.detached, chain1);
+        assert_eq!(fork_switch.attached, vec![block_a, block_b]);
+    }
+
+    #[test]
+    fn test_fork_overtakes_main_chain() {
+        let notify = NotifyService::default().start::<&str>(None);
+        let switch_fork_receiver = notify.subscribe_switch_fork("test");
+        let (chain_controller, shared) =
+            test_utils::start_chain(Consensus::default(), notify, true);
+
+        let mut main = Fork::from_number(&shared, &chain_controller, 0);
+        main.extend(5);
+        let mut fork = Fork::from_number(&shared, &chain_controller, 2);
+        fork.extend(2);
+        assert!(main.is_main());
+        assert!(!fork.is_main());
+
+        fork.overtake();
+        assert!(fork.is_main());
+        assert!(!main.is_main());
+        assert!(fork.total_difficulty() > main.total_difficulty());
+
+        let fork_switch = switch_fork_receiver.recv().unwrap();
+        let mut detached = main.blocks()[2..].to_vec();
+        detached.reverse();
+        assert_eq!(fork_switch.detached, detached);
+        assert_eq!(fork_switch.attached, fork.blocks().to_vec());
+    }
+
     #[test]
     fn test_chain_fork_by_hash() {
         let (chain_controller, shared) = start_chain(None);
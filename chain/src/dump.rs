@@ -0,0 +1,192 @@
+//! Portable block dump: a sequence of records, each a little endian `u64`
+//! length followed by the bincode encoded block.
+
+use crate::chain::ChainController;
+use crate::error::DumpError;
+use bincode::{deserialize, serialize};
+use ckb_core::block::Block;
+use ckb_core::BlockNumber;
+use ckb_shared::index::ChainIndex;
+use ckb_shared::shared::{ChainProvider, Shared};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::cmp;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::ops::Range;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Writes the main chain blocks in `range` to a new file at `path`, returns
+/// the number of blocks written.
+pub fn export_blocks<CI: ChainIndex>(
+    shared: &Shared<CI>,
+    range: Range<BlockNumber>,
+    path: &Path,
+) -> Result<u64, DumpError> {
+    let f = fs::OpenOptions::new()
+        .create_new(true)
+        .write(true)
+        .open(path)?;
+    let mut writer = io::BufWriter::new(f);
+
+    let tip_number = shared.chain_state().read().tip_number();
+    let end = cmp::min(range.end, tip_number + 1);
+    let mut written = 0;
+    for block in shared.store().block_iter(range.start..end) {
+        write_record(&mut writer, &block)?;
+        written += 1;
+    }
+    if range.start + written < end {
+        return Err(DumpError::BlockNotFound(range.start + written));
+    }
+    writer.flush()?;
+    Ok(written)
+}
+
+/// Processes the blocks dumped in `path` in order, returns the number of
+/// blocks imported. A dumped genesis must match the chain's own.
+pub fn import_blocks<CI: ChainIndex>(
+    chain: &ChainController,
+    shared: &Shared<CI>,
+    path: &Path,
+) -> Result<u64, DumpError> {
+    let f = fs::File::open(path)?;
+    let mut reader = io::BufReader::new(f);
+
+    let max_len = shared.consensus().max_block_bytes();
+    let mut imported = 0;
+    while let Some(block) = read_record::<_, Block>(&mut reader, max_len)? {
+        if block.is_genesis() {
+            if block.header().hash() != shared.genesis_hash() {
+                return Err(DumpError::GenesisMismatch);
+            }
+            continue;
+        }
+        chain
+            .process_block(Arc::new(block))
+            .map_err(DumpError::ProcessBlock)?;
+        imported += 1;
+    }
+    Ok(imported)
+}
+
+pub(crate) fn write_record<W: Write, T: Serialize>(
+    writer: &mut W,
+    value: &T,
+) -> Result<(), DumpError> {
+    let encoded = serialize(value)?;
+    writer.write_all(&(encoded.len() as u64).to_le_bytes())?;
+    writer.write_all(&encoded)?;
+    Ok(())
+}
+
+/// Reads the next record, `None` at the end of the input. A record longer than
+/// `max_len` is refused before its buffer is allocated.
+pub(crate) fn read_record<R: Read, T: DeserializeOwned>(
+    reader: &mut R,
+    max_len: u64,
+) -> Result<Option<T>, DumpError> {
+    let mut len = [0u8; 8];
+    match reader.read_exact(&mut len) {
+        Ok(()) => {}
+        Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into()),
+    }
+    let len = u64::from_le_bytes(len);
+    if len > max_len {
+        return Err(DumpError::RecordTooLarge(len));
+    }
+    let mut encoded = vec![0u8; len as usize];
+    reader.read_exact(&mut encoded)?;
+    Ok(Some(deserialize(&encoded)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::start_chain;
+    use ckb_chain_spec::consensus::Consensus;
+    use ckb_core::block::BlockBuilder;
+    use ckb_core::header::HeaderBuilder;
+    use ckb_core::transaction::{CellInput, CellOutput, TransactionBuilder};
+    use ckb_notify::{NotifyController, NotifyService};
+    use numext_fixed_hash::H256;
+    use numext_fixed_uint::U256;
+
+    fn notify() -> NotifyController {
+        NotifyService::default().start::<&str>(None)
+    }
+
+    fn gen_block(parent: &Block) -> Block {
+        let number = parent.header().number() + 1;
+        let cellbase = TransactionBuilder::default()
+            .input(CellInput::new_cellbase_input(number))
+            .output(CellOutput::new(0, vec![], H256::zero(), None))
+            .build();
+        BlockBuilder::default()
+            .commit_transaction(cellbase)
+            .with_header_builder(
+                HeaderBuilder::default()
+                    .parent_hash(parent.header().hash())
+                    .number(number)
+                    .difficulty(parent.header().difficulty().clone()),
+            )
+    }
+
+    #[test]
+    fn test_export_and_import_blocks() {
+        let genesis = BlockBuilder::default()
+            .with_header_builder(HeaderBuilder::default().difficulty(U256::from(1000u64)));
+        let consensus = Consensus::default().set_genesis_block(genesis);
+        let (chain1, shared1) = start_chain(consensus.clone(), notify(), false);
+        let mut parent = consensus.genesis_block().clone();
+        for _ in 0..5 {
+            let block = gen_block(&parent);
+            assert!(chain1.process_block(Arc::new(block.clone())).is_ok());
+            parent = block;
+        }
+
+        let tmp_dir = tempfile::Builder::new()
+            .prefix("export_blocks")
+            .tempdir()
+            .unwrap();
+        let path = tmp_dir.path().join("blocks.bin");
+        assert_eq!(export_blocks(&shared1, 0..100, &path).unwrap(), 6);
+
+        let (chain2, shared2) = start_chain(consensus, notify(), false);
+        assert_eq!(import_blocks(&chain2, &shared2, &path).unwrap(), 5);
+        assert_eq!(
+            shared2.chain_state().read().tip_hash(),
+            parent.header().hash()
+        );
+
+        let other_genesis = BlockBuilder::default()
+            .with_header_builder(HeaderBuilder::default().difficulty(U256::from(2000u64)));
+        let consensus = Consensus::default().set_genesis_block(other_genesis);
+        let (chain3, shared3) = start_chain(consensus, notify(), false);
+        match import_blocks(&chain3, &shared3, &path) {
+            Err(DumpError::GenesisMismatch) => {}
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_read_record_too_large() {
+        let mut dump = Vec::new();
+        write_record(&mut dump, &vec![0u8; 100]).unwrap();
+        let record: Option<Vec<u8>> = read_record(&mut &dump[..], 108).unwrap();
+        assert_eq!(record, Some(vec![0u8; 100]));
+        match read_record::<_, Vec<u8>>(&mut &dump[..], 107) {
+            Err(DumpError::RecordTooLarge(108)) => {}
+            result => panic!("unexpected result {:?}", result),
+        }
+
+        // A corrupted length is not allocated
+        let corrupted = u64::max_value().to_le_bytes();
+        match read_record::<_, Vec<u8>>(&mut &corrupted[..], 1_000) {
+            Err(DumpError::RecordTooLarge(_)) => {}
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+}
@@ -1,8 +1,45 @@
+use bincode::Error as BcError;
+use ckb_core::BlockNumber;
 use ckb_shared::error::SharedError;
 use ckb_verification::Error as VerifyError;
+use std::io;
 
 #[derive(Debug, PartialEq, Clone, Eq)]
 pub enum ProcessBlockError {
     Shared(SharedError),
     Verification(VerifyError),
 }
+
+#[derive(Debug)]
+pub enum DumpError {
+    Io(io::Error),
+    Serialization(String),
+    ProcessBlock(ProcessBlockError),
+    BlockNotFound(BlockNumber),
+    GenesisMismatch,
+    /// A snapshot can only be restored into an empty store
+    StoreNotEmpty,
+    /// The snapshot is truncated or its blocks are not a chain
+    InvalidSnapshot,
+    /// A record longer than a block may be, the length is corrupted
+    RecordTooLarge(u64),
+    Shared(SharedError),
+}
+
+impl From<io::Error> for DumpError {
+    fn from(err: io::Error) -> Self {
+        DumpError::Io(err)
+    }
+}
+
+impl From<SharedError> for DumpError {
+    fn from(err: SharedError) -> Self {
+        DumpError::Shared(err)
+    }
+}
+
+impl From<BcError> for DumpError {
+    fn from(err: BcError) -> Self {
+        DumpError::Serialization(err.to_string())
+    }
+}
@@ -8,4 +8,8 @@
 //!   implement `ChainProvider`
 
 pub mod chain;
+pub mod dump;
 pub mod error;
+pub mod snapshot;
+#[cfg(any(test, feature = "test-utils"))]
+pub mod test_utils;
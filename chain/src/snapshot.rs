@@ -0,0 +1,504 @@
+//! Chain state snapshot: the main chain headers and metadata up to a block,
+//! together with the transactions which still have live outputs at that
+//! block. Records use the same framing as [dump](crate::dump).
+//!
+//! A node restored from a snapshot has every block up to the snapshot tip
+//! pruned, so it can not switch to a fork below the tip. The restored headers
+//! are verified as the chain verifies them, the transactions can not be, their
+//! blocks are incomplete.
+
+use crate::dump::{read_record, write_record};
+use crate::error::{DumpError, ProcessBlockError};
+use ckb_chain_spec::consensus::Consensus;
+use ckb_chain_spec::difficulty::DifficultyProvider;
+use ckb_core::block::BlockBuilder;
+use ckb_core::extras::{BlockExt, TransactionAddress};
+use ckb_core::header::Header;
+use ckb_core::transaction::{OutPoint, ProposalShortId, Transaction};
+use ckb_core::uncle::UncleBlock;
+use ckb_core::BlockNumber;
+use ckb_shared::block_median_time_context::BlockMedianTimeContext;
+use ckb_shared::index::ChainIndex;
+use ckb_shared::shared::Shared;
+use ckb_shared::store::PrunedBlock;
+use ckb_verification::{HeaderResolver, HeaderVerifier, Verifier};
+use numext_fixed_hash::H256;
+use numext_fixed_uint::U256;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+#[derive(Serialize, Deserialize)]
+struct SnapshotInfo {
+    genesis_hash: H256,
+    tip_number: BlockNumber,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SnapshotBlock {
+    header: Header,
+    ext: BlockExt,
+    uncles: Vec<UncleBlock>,
+    proposal_transactions: Vec<ProposalShortId>,
+    transactions: Vec<SnapshotTransaction>,
+    // The outputs of the snapshot transactions spent by the block
+    inputs: Vec<OutPoint>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SnapshotTransaction {
+    transaction: Transaction,
+    // Position in the block
+    index: usize,
+}
+
+/// Writes the chain state at main chain block `number` to a new file at
+/// `path`. The chain is not updated while the snapshot is written.
+pub fn create_snapshot<CI: ChainIndex>(
+    shared: &Shared<CI>,
+    number: BlockNumber,
+    path: &Path,
+) -> Result<(), DumpError> {
+    let chain_state = shared.chain_state().read();
+    if number > chain_state.tip_number() {
+        return Err(DumpError::BlockNotFound(number));
+    }
+
+    let f = fs::OpenOptions::new()
+        .create_new(true)
+        .write(true)
+        .open(path)?;
+    let mut writer = io::BufWriter::new(f);
+
+    let store = shared.store();
+    let txo_set = Shared::<CI>::init_txo_set(store, number);
+    let info = SnapshotInfo {
+        genesis_hash: store.get_block_hash(0).expect("genesis must be stored"),
+        tip_number: number,
+    };
+    write_record(&mut writer, &info)?;
+
+    // Genesis is stored in full on restore, so it keeps the spent state of
+    // all its transactions
+    let genesis: HashSet<H256> = shared
+        .consensus()
+        .genesis_block()
+        .commit_transactions()
+        .iter()
+        .map(|tx| tx.hash())
+        .collect();
+    let restored = |tx_hash: &H256| {
+        genesis.contains(tx_hash)
+            || txo_set
+                .get(tx_hash)
+                .map_or(false, |meta| !meta.is_fully_spent())
+    };
+
+    for n in 0..=number {
+        let hash = store
+            .get_block_hash(n)
+            .ok_or(DumpError::BlockNotFound(n))?;
+        let header = store.get_header(&hash).ok_or(DumpError::BlockNotFound(n))?;
+        let ext = store
+            .get_block_ext(&hash)
+            .ok_or(DumpError::BlockNotFound(n))?;
+        let (candidates, inputs) = match store.get_block_body(&hash) {
+            Some(txs) => {
+                let inputs = txs.iter().flat_map(|tx| tx.input_pts()).collect();
+                (txs, inputs)
+            }
+            None => {
+                let pruned = store
+                    .get_pruned_block(&hash)
+                    .ok_or(DumpError::BlockNotFound(n))?;
+                let txs = pruned
+                    .live_transactions
+                    .iter()
+                    .filter_map(|tx_hash| store.get_transaction(tx_hash))
+                    .collect();
+                (txs, pruned.inputs)
+            }
+        };
+
+        let mut transactions = Vec::new();
+        for transaction in candidates {
+            let tx_hash = transaction.hash();
+            if !restored(&tx_hash) {
+                continue;
+            }
+            let index = store
+                .get_transaction_address(&tx_hash)
+                .map(|address| address.index)
+                .ok_or(DumpError::BlockNotFound(n))?;
+            transactions.push(SnapshotTransaction { transaction, index });
+        }
+        let inputs = inputs
+            .into_iter()
+            .filter(|out_point| restored(&out_point.hash))
+            .collect();
+
+        let block = SnapshotBlock {
+            header,
+            ext,
+            uncles: store.get_block_uncles(&hash).unwrap_or_default(),
+            proposal_transactions: store.get_block_proposal_txs_ids(&hash).unwrap_or_default(),
+            transactions,
+            inputs,
+        };
+        write_record(&mut writer, &block)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Initializes an empty `store` from the snapshot at `path`, returns the
+/// snapshot tip number. The blocks above the tip are then processed as usual.
+pub fn restore_snapshot<CI: ChainIndex>(
+    store: &CI,
+    consensus: &Consensus,
+    path: &Path,
+) -> Result<BlockNumber, DumpError> {
+    if store.get_tip_header().is_some() {
+        return Err(DumpError::StoreNotEmpty);
+    }
+
+    let f = fs::File::open(path)?;
+    let mut reader = io::BufReader::new(f);
+
+    let max_len = consensus.max_block_bytes();
+    let info: SnapshotInfo =
+        read_record(&mut reader, max_len)?.ok_or(DumpError::InvalidSnapshot)?;
+    let genesis = consensus.genesis_block();
+    if info.genesis_hash != genesis.header().hash() {
+        return Err(DumpError::GenesisMismatch);
+    }
+
+    let view = StoreView { store, consensus };
+    let header_verifier = HeaderVerifier::new(
+        view.clone(),
+        consensus.pow_engine(),
+        consensus.max_block_time_drift(),
+    );
+    let mut parent: Option<(Header, BlockExt)> = None;
+    for n in 0..=info.tip_number {
+        let block: SnapshotBlock =
+            read_record(&mut reader, max_len)?.ok_or(DumpError::InvalidSnapshot)?;
+        let hash = block.header.hash();
+        if block.header.number() != n {
+            return Err(DumpError::InvalidSnapshot);
+        }
+
+        let (parent_header, parent_ext) = match parent.take() {
+            Some(parent) => parent,
+            None => {
+                if hash != info.genesis_hash {
+                    return Err(DumpError::InvalidSnapshot);
+                }
+                store.init(genesis);
+                parent = Some((block.header, block.ext));
+                continue;
+            }
+        };
+        if block.header.parent_hash() != &parent_header.hash() {
+            return Err(DumpError::InvalidSnapshot);
+        }
+        let resolver = RestoredHeader {
+            view: view.clone(),
+            header: &block.header,
+            parent: &parent_header,
+        };
+        header_verifier
+            .verify(&resolver)
+            .map_err(|err| DumpError::ProcessBlock(ProcessBlockError::Verification(err)))?;
+        // The chain selection relies on the total difficulty
+        if block.ext.total_difficulty != parent_ext.total_difficulty + block.header.difficulty()
+            || block.ext.total_uncles_count
+                != parent_ext.total_uncles_count + block.uncles.len() as u64
+        {
+            return Err(DumpError::InvalidSnapshot);
+        }
+
+        store.save_with_batch(|batch| {
+            let mut pruned = PrunedBlock::default();
+            for tx in &block.transactions {
+                let address = TransactionAddress {
+                    block_hash: hash.clone(),
+                    index: tx.index,
+                    offset: 0,
+                    length: 0,
+                };
+                store.insert_pruned_transaction_address(batch, &tx.transaction.hash(), &address);
+                pruned.live_transactions.push(tx.transaction.hash());
+            }
+            pruned.inputs = block.inputs.clone();
+            if n == info.tip_number {
+                store.insert_tip_header(batch, &block.header);
+                store.insert_pruned_below(batch, n + 1);
+            }
+
+            let pruned_block = BlockBuilder::default()
+                .header(block.header.clone())
+                .uncles(block.uncles.clone())
+                .proposal_transactions(block.proposal_transactions.clone())
+                .commit_transactions(
+                    block
+                        .transactions
+                        .iter()
+                        .map(|tx| tx.transaction.clone())
+                        .collect(),
+                )
+                .build();
+            store.insert_pruned_block(batch, &pruned_block, &pruned);
+            store.insert_block_ext(batch, &hash, &block.ext);
+            store.insert_block_hash(batch, n, &hash);
+            store.insert_block_number(batch, &hash, n);
+            Ok(())
+        })?;
+        parent = Some((block.header, block.ext));
+    }
+    Ok(info.tip_number)
+}
+
+// The main chain restored so far, for the header verifier
+struct StoreView<'a, CI> {
+    store: &'a CI,
+    consensus: &'a Consensus,
+}
+
+impl<'a, CI> Clone for StoreView<'a, CI> {
+    fn clone(&self) -> Self {
+        StoreView {
+            store: self.store,
+            consensus: self.consensus,
+        }
+    }
+}
+
+impl<'a, CI: ChainIndex> BlockMedianTimeContext for StoreView<'a, CI> {
+    fn block_count(&self) -> u32 {
+        self.consensus.median_time_block_count() as u32
+    }
+    fn timestamp(&self, hash: &H256) -> Option<u64> {
+        self.store.get_header(hash).map(|header| header.timestamp())
+    }
+    fn parent_hash(&self, hash: &H256) -> Option<H256> {
+        self.store
+            .get_header(hash)
+            .map(|header| header.parent_hash().to_owned())
+    }
+}
+
+impl<'a, CI: ChainIndex> DifficultyProvider for StoreView<'a, CI> {
+    fn header(&self, hash: &H256) -> Option<Header> {
+        self.store.get_header(hash)
+    }
+
+    // Only main chain blocks are restored
+    fn ancestor(&self, _base: &H256, number: BlockNumber) -> Option<Header> {
+        self.store
+            .get_block_hash(number)
+            .and_then(|hash| self.store.get_header(&hash))
+    }
+
+    fn total_uncles_count(&self, hash: &H256) -> Option<u64> {
+        self.store
+            .get_block_ext(hash)
+            .map(|ext| ext.total_uncles_count)
+    }
+}
+
+struct RestoredHeader<'a, CI> {
+    view: StoreView<'a, CI>,
+    header: &'a Header,
+    parent: &'a Header,
+}
+
+impl<'a, CI: ChainIndex> HeaderResolver for RestoredHeader<'a, CI> {
+    fn header(&self) -> &Header {
+        self.header
+    }
+
+    fn parent(&self) -> Option<&Header> {
+        Some(self.parent)
+    }
+
+    fn calculate_difficulty(&self) -> Option<U256> {
+        self.view.consensus.difficulty_engine().next_difficulty(
+            self.view.consensus,
+            &self.view,
+            self.parent,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{start_chain, start_chain_on};
+    use ckb_core::block::Block;
+    use ckb_core::cell::{CellProvider, CellStatus};
+    use ckb_core::header::HeaderBuilder;
+    use ckb_core::transaction::{CellInput, CellOutput, TransactionBuilder};
+    use ckb_db::memorydb::MemoryKeyValueDB;
+    use ckb_notify::NotifyService;
+    use ckb_shared::shared::ChainProvider;
+    use ckb_shared::store::ChainKVStore;
+    use ckb_shared::COLUMNS;
+    use numext_fixed_uint::U256;
+    use std::sync::Arc;
+
+    fn gen_block(parent: &Header, spent: &H256) -> Block {
+        let number = parent.number() + 1;
+        let cellbase = TransactionBuilder::default()
+            .input(CellInput::new_cellbase_input(number))
+            .output(CellOutput::new(0, vec![], H256::zero(), None))
+            .build();
+        let tx = TransactionBuilder::default()
+            .input(CellInput::new(OutPoint::new(spent.clone(), 0), Default::default()))
+            .outputs(vec![CellOutput::new(100, vec![], H256::zero(), None); 2])
+            .build();
+        BlockBuilder::default()
+            .commit_transaction(cellbase)
+            .commit_transaction(tx)
+            .with_header_builder(
+                HeaderBuilder::default()
+                    .parent_hash(parent.hash())
+                    .number(number)
+                    .timestamp(parent.timestamp() + 1)
+                    .difficulty(parent.difficulty().clone()),
+            )
+    }
+
+    #[test]
+    fn test_create_and_restore_snapshot() {
+        let root = TransactionBuilder::default()
+            .input(CellInput::new(OutPoint::null(), Default::default()))
+            .outputs(vec![CellOutput::new(100, vec![], H256::zero(), None); 2])
+            .build();
+        let genesis = BlockBuilder::default()
+            .commit_transaction(root.clone())
+            .with_header_builder(HeaderBuilder::default().difficulty(U256::from(1000u64)));
+        let consensus = Consensus::default().set_genesis_block(genesis);
+
+        let notify = NotifyService::default().start::<&str>(None);
+        let (chain1, shared1) = start_chain(consensus.clone(), notify.clone(), false);
+        let mut parent = consensus.genesis_block().header().clone();
+        let mut spent = root.hash();
+        let mut blocks = Vec::new();
+        for _ in 0..5 {
+            let block = gen_block(&parent, &spent);
+            chain1
+                .process_block(Arc::new(block.clone()))
+                .expect("process block ok");
+            spent = block.commit_transactions()[1].hash();
+            parent = block.header().clone();
+            blocks.push(block);
+        }
+
+        let tmp_dir = tempfile::Builder::new()
+            .prefix("create_snapshot")
+            .tempdir()
+            .unwrap();
+        let path = tmp_dir.path().join("snapshot.bin");
+        create_snapshot(&shared1, 4, &path).expect("create snapshot");
+
+        let store = ChainKVStore::new(MemoryKeyValueDB::open(COLUMNS as usize));
+        assert_eq!(restore_snapshot(&store, &consensus, &path).unwrap(), 4);
+        match restore_snapshot(&store, &consensus, &path) {
+            Err(DumpError::StoreNotEmpty) => {}
+            result => panic!("unexpected result {:?}", result),
+        }
+
+        // The outputs are spent by the blocks spending them
+        assert_eq!(
+            store
+                .get_pruned_block(&blocks[1].header().hash())
+                .unwrap()
+                .inputs,
+            vec![OutPoint::new(blocks[0].commit_transactions()[1].hash(), 0)]
+        );
+
+        let shared2 = Shared::new(store, consensus);
+        assert_eq!(
+            shared2.chain_state().read().tip_hash(),
+            blocks[3].header().hash()
+        );
+        assert_eq!(
+            shared2.block_ext(&blocks[3].header().hash()),
+            shared1.block_ext(&blocks[3].header().hash())
+        );
+        assert_eq!(shared2.cell(&OutPoint::new(root.hash(), 0)), CellStatus::Dead);
+        assert_eq!(
+            shared2.cell(&OutPoint::new(root.hash(), 1)),
+            CellStatus::Live(root.outputs()[1].clone())
+        );
+        let last = &blocks[3].commit_transactions()[1];
+        assert_eq!(
+            shared2.cell(&OutPoint::new(last.hash(), 0)),
+            CellStatus::Live(last.outputs()[0].clone())
+        );
+
+        // Continues with the blocks above the snapshot
+        let chain2 = start_chain_on(shared2.clone(), notify, false);
+        chain2
+            .process_block(Arc::new(blocks[4].clone()))
+            .expect("process block ok");
+        assert_eq!(
+            shared2.chain_state().read().tip_hash(),
+            blocks[4].header().hash()
+        );
+        assert_eq!(shared2.cell(&OutPoint::new(last.hash(), 0)), CellStatus::Dead);
+    }
+
+    #[test]
+    fn test_restore_verifies_headers() {
+        let consensus = Consensus::default();
+        let notify = NotifyService::default().start::<&str>(None);
+        let (chain, shared) = start_chain(consensus.clone(), notify, false);
+        let mut parent = consensus.genesis_block().header().clone();
+        for _ in 0..3 {
+            let block = gen_block(&parent, &H256::zero());
+            chain
+                .process_block(Arc::new(block.clone()))
+                .expect("process block ok");
+            parent = block.header().clone();
+        }
+
+        let tmp_dir = tempfile::Builder::new()
+            .prefix("restore_verifies_headers")
+            .tempdir()
+            .unwrap();
+        let path = tmp_dir.path().join("snapshot.bin");
+        create_snapshot(&shared, 3, &path).expect("create snapshot");
+
+        // The difficulty of block 2 is raised, and so its total difficulty
+        let tampered = tmp_dir.path().join("tampered.bin");
+        {
+            let mut reader = io::BufReader::new(fs::File::open(&path).unwrap());
+            let mut writer = io::BufWriter::new(fs::File::create(&tampered).unwrap());
+            let info: SnapshotInfo = read_record(&mut reader, 1 << 20).unwrap().unwrap();
+            write_record(&mut writer, &info).unwrap();
+            while let Some(mut block) =
+                read_record::<_, SnapshotBlock>(&mut reader, 1 << 20).unwrap()
+            {
+                if block.header.number() == 2 {
+                    let difficulty = block.header.difficulty().clone() + U256::one();
+                    block.ext.total_difficulty = block.ext.total_difficulty + U256::one();
+                    block.header = HeaderBuilder::default()
+                        .header(block.header)
+                        .difficulty(difficulty)
+                        .build();
+                }
+                write_record(&mut writer, &block).unwrap();
+            }
+        }
+
+        let store = ChainKVStore::new(MemoryKeyValueDB::open(COLUMNS as usize));
+        match restore_snapshot(&store, &consensus, &tampered) {
+            Err(DumpError::ProcessBlock(ProcessBlockError::Verification(_))) => {}
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+}
@@ -0,0 +1,203 @@
+//! Builds the competing branches of the tests of the reorganizations, of the
+//! chain and of the services following it such as the pool and the indexer.
+//!
+//! The blocks of a [Fork] are valid on top of their parent: the difficulty
+//! is the one the consensus requires and the cellbase claims the block
+//! reward. Each block is processed as it is built, as the difficulty of the
+//! next one depends on the stored ancestors.
+
+use crate::chain::{ChainBuilder, ChainController};
+use ckb_chain_spec::consensus::Consensus;
+use ckb_core::block::{Block, BlockBuilder};
+use ckb_core::header::{BlockNumber, Header, HeaderBuilder};
+use ckb_core::transaction::{
+    CellInput, CellOutput, ProposalShortId, Transaction, TransactionBuilder,
+};
+use ckb_db::memorydb::MemoryKeyValueDB;
+use ckb_notify::NotifyController;
+use ckb_shared::index::ChainIndex;
+use ckb_shared::shared::{ChainProvider, Shared, SharedBuilder};
+use ckb_shared::store::ChainKVStore;
+use ckb_time::unix_time_as_millis;
+use numext_fixed_hash::H256;
+use numext_fixed_uint::U256;
+use std::cmp;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+pub type MemoryShared = Shared<ChainKVStore<MemoryKeyValueDB>>;
+
+// Numbers the forks, so that the empty blocks of two of them differ
+static NEXT_FORK: AtomicUsize = AtomicUsize::new(1);
+
+/// Starts a chain of `consensus` on a memory store, verifying the blocks
+/// when `verification` is set. The pending transactions of the blocks are
+/// only checked against their proposals when it is.
+pub fn start_chain(
+    consensus: Consensus,
+    notify: NotifyController,
+    verification: bool,
+) -> (ChainController, MemoryShared) {
+    let shared = SharedBuilder::<ChainKVStore<MemoryKeyValueDB>>::new_memory()
+        .consensus(consensus)
+        .build();
+    let chain_controller = start_chain_on(shared.clone(), notify, verification);
+    (chain_controller, shared)
+}
+
+/// Starts a chain on an existing `shared`, such as a restored store
+pub fn start_chain_on<CI: ChainIndex + 'static>(
+    shared: Shared<CI>,
+    notify: NotifyController,
+    verification: bool,
+) -> ChainController {
+    ChainBuilder::new(shared, notify)
+        .verification(verification)
+        .build()
+        .start::<&str>(None)
+}
+
+/// The cellbase of block `number`, paying its whole block reward to `lock`
+pub fn cellbase<CI: ChainIndex>(
+    shared: &Shared<CI>,
+    number: BlockNumber,
+    lock: &H256,
+) -> Transaction {
+    TransactionBuilder::default()
+        .input(CellInput::new_cellbase_input(number))
+        .output(CellOutput::new(
+            shared.block_reward(number),
+            Vec::new(),
+            lock.clone(),
+            None,
+        ))
+        .build()
+}
+
+/// A branch growing from any stored block, the main chain tip or one of its
+/// ancestors or a block of another branch
+pub struct Fork<'a, CI: ChainIndex> {
+    shared: &'a Shared<CI>,
+    chain: &'a ChainController,
+    id: u64,
+    lock: H256,
+    tip: Header,
+    blocks: Vec<Block>,
+}
+
+impl<'a, CI: ChainIndex> Fork<'a, CI> {
+    /// Panics when `ancestor` is not stored
+    pub fn new(shared: &'a Shared<CI>, chain: &'a ChainController, ancestor: &H256) -> Self {
+        let tip = shared
+            .block_header(ancestor)
+            .unwrap_or_else(|| panic!("unknown fork ancestor {:#x}", ancestor));
+        Fork {
+            shared,
+            chain,
+            id: NEXT_FORK.fetch_add(1, Ordering::SeqCst) as u64,
+            lock: H256::zero(),
+            tip,
+            blocks: Vec::new(),
+        }
+    }
+
+    /// A branch from the block `number` of the main chain
+    pub fn from_number(
+        shared: &'a Shared<CI>,
+        chain: &'a ChainController,
+        number: BlockNumber,
+    ) -> Self {
+        let ancestor = shared
+            .block_hash(number)
+            .unwrap_or_else(|| panic!("no main chain block {}", number));
+        Self::new(shared, chain, &ancestor)
+    }
+
+    /// The lock of the cellbase outputs, zero by default
+    pub fn lock(mut self, lock: H256) -> Self {
+        self.lock = lock;
+        self
+    }
+
+    /// Builds the next block of the branch, without processing it
+    pub fn build_block(
+        &self,
+        transactions: Vec<Transaction>,
+        proposals: Vec<ProposalShortId>,
+    ) -> Block {
+        let number = self.tip.number() + 1;
+        let difficulty = self
+            .shared
+            .calculate_difficulty(&self.tip)
+            .expect("difficulty of the parent");
+        // The blocks of different branches differ even when empty
+        let nonce = (self.id << 32) + number;
+        let timestamp = cmp::max(unix_time_as_millis(), self.tip.timestamp() + 1);
+        let header_builder = HeaderBuilder::default()
+            .parent_hash(self.tip.hash())
+            .number(number)
+            .timestamp(timestamp)
+            .difficulty(difficulty)
+            .nonce(nonce);
+        BlockBuilder::default()
+            .commit_transaction(cellbase(self.shared, number, &self.lock))
+            .commit_transactions(transactions)
+            .proposal_transactions(proposals)
+            .with_header_builder(header_builder)
+    }
+
+    /// Appends and processes a block committing `transactions` and proposing
+    /// `proposals`, panics when the chain rejects it
+    pub fn push(
+        &mut self,
+        transactions: Vec<Transaction>,
+        proposals: Vec<ProposalShortId>,
+    ) -> &Block {
+        let block = self.build_block(transactions, proposals);
+        self.chain
+            .process_block(Arc::new(block.clone()))
+            .unwrap_or_else(|err| {
+                panic!("fork block {} rejected: {:?}", block.header().number(), err)
+            });
+        self.tip = block.header().clone();
+        self.blocks.push(block);
+        self.blocks.last().expect("just pushed")
+    }
+
+    /// Appends `count` empty blocks
+    pub fn extend(&mut self, count: usize) -> &mut Self {
+        for _ in 0..count {
+            self.push(Vec::new(), Vec::new());
+        }
+        self
+    }
+
+    /// Appends empty blocks until the branch is the main chain
+    pub fn overtake(&mut self) -> &mut Self {
+        while self.shared.chain_state().read().tip_hash() != self.tip.hash() {
+            self.push(Vec::new(), Vec::new());
+        }
+        self
+    }
+
+    pub fn tip(&self) -> &Header {
+        &self.tip
+    }
+
+    /// The blocks appended, from the first after the ancestor
+    pub fn blocks(&self) -> &[Block] {
+        &self.blocks
+    }
+
+    /// The total difficulty from the genesis to the tip of the branch
+    pub fn total_difficulty(&self) -> U256 {
+        self.shared
+            .block_ext(&self.tip.hash())
+            .expect("fork tip stored")
+            .total_difficulty
+    }
+
+    pub fn is_main(&self) -> bool {
+        self.shared.block_hash(self.tip.number()) == Some(self.tip.hash())
+    }
+}
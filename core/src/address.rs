@@ -0,0 +1,216 @@
+//! Human readable addresses of locks.
+//!
+//! An address is the [bech32](crypto::bech32) encoding of the format version
+//! `0` followed by the lock hash, behind the prefix of its network: `ckb` on
+//! the main network, `ckt` on the test network and `ckd` on the others. The
+//! checksum catches a mistyped address and the prefix an address of another
+//! network.
+
+use crypto::bech32::{Bech32, Error as Bech32Error};
+use numext_fixed_hash::H256;
+use std::fmt;
+use std::str::FromStr;
+
+const VERSION: u8 = 0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum AddressNetwork {
+    Mainnet,
+    Testnet,
+    Dev,
+}
+
+impl AddressNetwork {
+    /// The network of the chain spec `network_id`
+    pub fn from_network_id(network_id: u32) -> Self {
+        match network_id {
+            0 => AddressNetwork::Mainnet,
+            1 => AddressNetwork::Testnet,
+            _ => AddressNetwork::Dev,
+        }
+    }
+
+    pub fn prefix(self) -> &'static str {
+        match self {
+            AddressNetwork::Mainnet => "ckb",
+            AddressNetwork::Testnet => "ckt",
+            AddressNetwork::Dev => "ckd",
+        }
+    }
+
+    fn from_prefix(prefix: &str) -> Option<Self> {
+        match prefix {
+            "ckb" => Some(AddressNetwork::Mainnet),
+            "ckt" => Some(AddressNetwork::Testnet),
+            "ckd" => Some(AddressNetwork::Dev),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum AddressError {
+    Bech32(Bech32Error),
+    UnknownPrefix(String),
+    UnsupportedVersion(u8),
+    InvalidLength,
+    /// The address is of this other network
+    WrongNetwork(AddressNetwork),
+}
+
+impl fmt::Display for AddressError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AddressError::Bech32(err) => write!(f, "invalid address: {}", err),
+            AddressError::UnknownPrefix(prefix) => write!(f, "unknown address prefix {}", prefix),
+            AddressError::UnsupportedVersion(version) => {
+                write!(f, "unsupported address version {}", version)
+            }
+            AddressError::InvalidLength => write!(f, "invalid address length"),
+            AddressError::WrongNetwork(network) => {
+                write!(f, "address of the {:?} network", network)
+            }
+        }
+    }
+}
+
+impl From<Bech32Error> for AddressError {
+    fn from(e: Bech32Error) -> Self {
+        AddressError::Bech32(e)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Address {
+    pub network: AddressNetwork,
+    pub lock: H256,
+}
+
+impl Address {
+    pub fn new(network: AddressNetwork, lock: H256) -> Self {
+        Address { network, lock }
+    }
+
+    /// The lock of an address, which must be of `network`
+    pub fn parse_lock(address: &str, network: AddressNetwork) -> Result<H256, AddressError> {
+        let address = address.parse::<Address>()?;
+        if address.network != network {
+            return Err(AddressError::WrongNetwork(address.network));
+        }
+        Ok(address.lock)
+    }
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut data = vec![VERSION];
+        data.extend(convert_bits(self.lock.as_bytes(), 8, 5, true).expect("8 bits groups"));
+        let encoded = Bech32::new(self.network.prefix().to_owned(), data)
+            .encode()
+            .expect("address within bech32 limits");
+        write!(f, "{}", encoded)
+    }
+}
+
+impl FromStr for Address {
+    type Err = AddressError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let decoded = Bech32::decode(s)?;
+        let network = AddressNetwork::from_prefix(&decoded.hrp)
+            .ok_or_else(|| AddressError::UnknownPrefix(decoded.hrp.clone()))?;
+        let (version, data) = decoded
+            .data
+            .split_first()
+            .ok_or(AddressError::InvalidLength)?;
+        if *version != VERSION {
+            return Err(AddressError::UnsupportedVersion(*version));
+        }
+        let lock = convert_bits(data, 5, 8, false).ok_or(AddressError::InvalidLength)?;
+        let lock = H256::from_slice(&lock).map_err(|_| AddressError::InvalidLength)?;
+        Ok(Address { network, lock })
+    }
+}
+
+// Regroups the bits of `data` from `from` bits values to `to` bits ones, as
+// BIP173 does, None when the padding is not zero or, unless `pad`, too long
+fn convert_bits(data: &[u8], from: u32, to: u32, pad: bool) -> Option<Vec<u8>> {
+    let mut acc = 0u32;
+    let mut bits = 0u32;
+    let max = (1u32 << to) - 1;
+    let max_acc = (1u32 << (from + to - 1)) - 1;
+    let mut result = Vec::with_capacity(data.len() * from as usize / to as usize + 1);
+    for value in data {
+        let value = u32::from(*value);
+        if value >> from != 0 {
+            return None;
+        }
+        acc = ((acc << from) | value) & max_acc;
+        bits += from;
+        while bits >= to {
+            bits -= to;
+            result.push(((acc >> bits) & max) as u8);
+        }
+    }
+    if pad {
+        if bits > 0 {
+            result.push(((acc << (to - bits)) & max) as u8);
+        }
+    } else if bits >= from || ((acc << (to - bits)) & max) != 0 {
+        return None;
+    }
+    Some(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_address_roundtrip() {
+        let lock = H256::from_hex_str(
+            "c9279bf4ed6b8ee8dd61cab9fc2ee9575e8a0c5e6a8b0280770d8c5ed31a7b9d",
+        )
+        .unwrap();
+        let address = Address::new(AddressNetwork::Testnet, lock.clone());
+        let encoded = address.to_string();
+        assert!(encoded.starts_with("ckt1q"));
+        assert_eq!(encoded.parse::<Address>(), Ok(address));
+        assert_eq!(encoded.to_uppercase().parse::<Address>().unwrap().lock, lock);
+
+        assert_eq!(
+            Address::parse_lock(&encoded, AddressNetwork::Testnet),
+            Ok(lock)
+        );
+        assert_eq!(
+            Address::parse_lock(&encoded, AddressNetwork::Mainnet),
+            Err(AddressError::WrongNetwork(AddressNetwork::Testnet))
+        );
+    }
+
+    #[test]
+    fn test_invalid_address() {
+        let address = Address::new(AddressNetwork::Mainnet, H256::zero()).to_string();
+        // A mistyped character breaks the checksum
+        let mut mistyped = address.into_bytes();
+        let last = mistyped.len() - 1;
+        mistyped[last] = if mistyped[last] == b'q' { b'p' } else { b'q' };
+        assert_eq!(
+            String::from_utf8(mistyped).unwrap().parse::<Address>(),
+            Err(AddressError::Bech32(Bech32Error::InvalidChecksum))
+        );
+
+        let other = Bech32::new("ckx".to_owned(), vec![0; 53]).encode().unwrap();
+        assert_eq!(
+            other.parse::<Address>(),
+            Err(AddressError::UnknownPrefix("ckx".to_owned()))
+        );
+        let short = Bech32::new("ckb".to_owned(), vec![0; 20]).encode().unwrap();
+        assert_eq!(short.parse::<Address>(), Err(AddressError::InvalidLength));
+        let version = Bech32::new("ckb".to_owned(), vec![1; 53]).encode().unwrap();
+        assert_eq!(
+            version.parse::<Address>(),
+            Err(AddressError::UnsupportedVersion(1))
+        );
+    }
+}
@@ -0,0 +1,237 @@
+//! The proptest strategies of the core types, for the round-trip properties
+//! of their encodings.
+//!
+//! The values are any the types can hold, valid or not: a generated block
+//! has no cellbase and its header roots match nothing. The byte strings and
+//! the lists are kept short so that a case stays fast.
+
+use crate::block::{Block, BlockBuilder};
+use crate::header::{Header, HeaderBuilder};
+use crate::script::Script;
+use crate::transaction::{
+    CellInput, CellOutput, OutPoint, ProposalShortId, Transaction, TransactionBuilder,
+};
+use crate::uncle::UncleBlock;
+use numext_fixed_hash::H256;
+use numext_fixed_uint::U256;
+use proptest::array::{uniform10, uniform32};
+use proptest::collection::vec;
+use proptest::option;
+use proptest::prelude::{any, Strategy};
+
+const MAX_BYTES: usize = 64;
+const MAX_ITEMS: usize = 4;
+const MAX_UNCLES: usize = 2;
+
+pub fn bytes() -> impl Strategy<Value = Vec<u8>> {
+    vec(any::<u8>(), 0..MAX_BYTES)
+}
+
+pub fn h256() -> impl Strategy<Value = H256> {
+    uniform32(any::<u8>()).prop_map(|bytes| H256::from_slice(&bytes).expect("32 bytes"))
+}
+
+pub fn u256() -> impl Strategy<Value = U256> {
+    uniform32(any::<u8>()).prop_map(|bytes| U256::from_little_endian(&bytes).expect("32 bytes"))
+}
+
+pub fn script() -> impl Strategy<Value = Script> {
+    (
+        any::<u8>(),
+        vec(bytes(), 0..MAX_ITEMS),
+        option::of(h256()),
+        option::of(bytes()),
+        vec(bytes(), 0..MAX_ITEMS),
+    )
+        .prop_map(|(version, args, reference, binary, signed_args)| {
+            Script::new(version, args, reference, binary, signed_args)
+        })
+}
+
+pub fn out_point() -> impl Strategy<Value = OutPoint> {
+    (h256(), any::<u32>()).prop_map(|(hash, index)| OutPoint::new(hash, index))
+}
+
+pub fn cell_input() -> impl Strategy<Value = CellInput> {
+    (out_point(), script()).prop_map(|(previous_output, unlock)| {
+        CellInput::new(previous_output, unlock)
+    })
+}
+
+pub fn cell_output() -> impl Strategy<Value = CellOutput> {
+    (any::<u64>(), bytes(), h256(), option::of(script())).prop_map(
+        |(capacity, data, lock, type_)| CellOutput::new(capacity, data, lock, type_),
+    )
+}
+
+pub fn transaction() -> impl Strategy<Value = Transaction> {
+    (
+        any::<u32>(),
+        vec(out_point(), 0..MAX_ITEMS),
+        vec(cell_input(), 0..MAX_ITEMS),
+        vec(cell_output(), 0..MAX_ITEMS),
+    )
+        .prop_map(|(version, deps, inputs, outputs)| {
+            TransactionBuilder::default()
+                .version(version)
+                .deps(deps)
+                .inputs(inputs)
+                .outputs(outputs)
+                .build()
+        })
+}
+
+pub fn proposal_short_id() -> impl Strategy<Value = ProposalShortId> {
+    uniform10(any::<u8>()).prop_map(ProposalShortId::new)
+}
+
+pub fn header() -> impl Strategy<Value = Header> {
+    let raw = (
+        any::<u32>(),
+        h256(),
+        any::<u64>(),
+        any::<u64>(),
+        h256(),
+        h256(),
+        u256(),
+    );
+    let rest = (h256(), h256(), any::<u32>(), any::<u64>(), bytes());
+    (raw, rest).prop_map(
+        |(
+            (version, parent_hash, timestamp, number, txs_commit, txs_proposal, difficulty),
+            (cellbase_id, uncles_hash, uncles_count, nonce, proof),
+        )| {
+            HeaderBuilder::default()
+                .version(version)
+                .parent_hash(parent_hash)
+                .timestamp(timestamp)
+                .number(number)
+                .txs_commit(txs_commit)
+                .txs_proposal(txs_proposal)
+                .difficulty(difficulty)
+                .cellbase_id(cellbase_id)
+                .uncles_hash(uncles_hash)
+                .uncles_count(uncles_count)
+                .nonce(nonce)
+                .proof(proof)
+                .build()
+        },
+    )
+}
+
+pub fn uncle_block() -> impl Strategy<Value = UncleBlock> {
+    (
+        header(),
+        transaction(),
+        vec(proposal_short_id(), 0..MAX_ITEMS),
+    )
+        .prop_map(|(header, cellbase, proposals)| UncleBlock::new(header, cellbase, proposals))
+}
+
+pub fn block() -> impl Strategy<Value = Block> {
+    (
+        header(),
+        vec(uncle_block(), 0..MAX_UNCLES),
+        vec(transaction(), 0..MAX_ITEMS),
+        vec(proposal_short_id(), 0..MAX_ITEMS),
+    )
+        .prop_map(|(header, uncles, transactions, proposals)| {
+            BlockBuilder::default()
+                .header(header)
+                .uncles(uncles)
+                .commit_transactions(transactions)
+                .proposal_transactions(proposals)
+                .build()
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::canonical::{CanonicalDeserialize, CanonicalSerialize, CANONICAL_VERSION};
+    use bincode::{deserialize, serialize};
+    use proptest::{proptest, proptest_helper};
+
+    // Two values have the same canonical bytes only when they are the same,
+    // as told by their serde encoding since the headers and blocks compare
+    // by hash
+    fn assert_canonical_injective<T: CanonicalSerialize + serde::Serialize>(a: &T, b: &T) {
+        let same = serialize(a).unwrap() == serialize(b).unwrap();
+        assert_eq!(same, a.canonical_bytes() == b.canonical_bytes());
+        assert_eq!(a.canonical_bytes()[0], CANONICAL_VERSION);
+    }
+
+    proptest! {
+        #[test]
+        fn header_serde_round_trip(header in header()) {
+            let bytes = serialize(&header).unwrap();
+            let decoded: Header = deserialize(&bytes).unwrap();
+            assert_eq!(decoded.hash(), header.hash());
+            assert_eq!(serialize(&decoded).unwrap(), bytes);
+            assert_eq!(HeaderBuilder::new(&bytes).build().hash(), header.hash());
+        }
+
+        #[test]
+        fn transaction_serde_round_trip(transaction in transaction()) {
+            let bytes = serialize(&transaction).unwrap();
+            let decoded: Transaction = deserialize(&bytes).unwrap();
+            assert_eq!(decoded, transaction);
+            assert_eq!(decoded.hash(), transaction.hash());
+            assert_eq!(TransactionBuilder::new(&bytes).build(), transaction);
+        }
+
+        #[test]
+        fn block_serde_round_trip(block in block()) {
+            let bytes = serialize(&block).unwrap();
+            let decoded: Block = deserialize(&bytes).unwrap();
+            assert_eq!(serialize(&decoded).unwrap(), bytes);
+            assert_eq!(decoded.commit_transactions(), block.commit_transactions());
+            assert_eq!(decoded.uncles(), block.uncles());
+        }
+
+        #[test]
+        fn proposal_short_id_round_trip(id in proposal_short_id()) {
+            assert_eq!(ProposalShortId::from_slice(&id.into_inner()), Some(id));
+            let decoded: ProposalShortId = deserialize(&serialize(&id).unwrap()).unwrap();
+            assert_eq!(decoded, id);
+        }
+
+        #[test]
+        fn canonical_out_point_injective(a in out_point(), b in out_point()) {
+            assert_canonical_injective(&a, &b);
+        }
+
+        #[test]
+        fn canonical_transaction_injective(a in transaction(), b in transaction()) {
+            assert_canonical_injective(&a, &b);
+            // A value differing in one field only
+            let mut outputs = a.outputs().to_vec();
+            outputs.push(CellOutput::default());
+            let extended = TransactionBuilder::default()
+                .transaction(a.clone())
+                .outputs_clear()
+                .outputs(outputs)
+                .build();
+            assert_canonical_injective(&a, &extended);
+        }
+
+        #[test]
+        fn canonical_round_trip(transaction in transaction(), block in block()) {
+            let bytes = transaction.canonical_bytes();
+            assert_eq!(Transaction::from_canonical_bytes(&bytes), Ok(transaction));
+            let decoded = Block::from_canonical_bytes(&block.canonical_bytes()).unwrap();
+            assert_eq!(decoded.canonical_bytes(), block.canonical_bytes());
+            assert_eq!(decoded.header().hash(), block.header().hash());
+        }
+
+        #[test]
+        fn canonical_header_injective(a in header(), b in header()) {
+            assert_canonical_injective(&a, &b);
+            let resealed = HeaderBuilder::default()
+                .header(a.clone())
+                .nonce(a.nonce().wrapping_add(1))
+                .build();
+            assert_canonical_injective(&a, &resealed);
+        }
+    }
+}
@@ -1,3 +1,6 @@
+use crate::canonical::{
+    read_seq, write_seq, CanonicalDeserialize, CanonicalError, CanonicalSerialize,
+};
 use crate::header::{Header, HeaderBuilder};
 use crate::transaction::{ProposalShortId, Transaction};
 use crate::uncle::{uncles_hash, UncleBlock};
@@ -82,6 +85,27 @@ impl PartialEq for Block {
     }
 }
 
+/// header, uncles, commit_transactions, proposal_transactions
+impl CanonicalSerialize for Block {
+    fn write_canonical(&self, out: &mut Vec<u8>) {
+        self.header.write_canonical(out);
+        write_seq(&self.uncles, out);
+        write_seq(&self.commit_transactions, out);
+        write_seq(&self.proposal_transactions, out);
+    }
+}
+
+impl CanonicalDeserialize for Block {
+    fn read_canonical(input: &mut &[u8]) -> Result<Self, CanonicalError> {
+        Ok(Block {
+            header: CanonicalDeserialize::read_canonical(input)?,
+            uncles: read_seq(input)?,
+            commit_transactions: read_seq(input)?,
+            proposal_transactions: read_seq(input)?,
+        })
+    }
+}
+
 #[derive(Default)]
 pub struct BlockBuilder {
     inner: Block,
@@ -132,6 +156,9 @@ impl BlockBuilder {
         self.inner
     }
 
+    /// Builds the block with the header from `header_builder`, filling in the
+    /// transaction roots, the uncles hash and count, and the cellbase id when
+    /// the first commit transaction is a cellbase.
     pub fn with_header_builder(mut self, header_builder: HeaderBuilder) -> Block {
         let txs_commit = merkle_root(
             &self
@@ -153,6 +180,11 @@ impl BlockBuilder {
 
         let uncles_hash = uncles_hash(&self.inner.uncles);
 
+        let header_builder = match self.inner.commit_transactions.first() {
+            Some(cellbase) if cellbase.is_cellbase() => header_builder.cellbase_id(cellbase.hash()),
+            _ => header_builder,
+        };
+
         self.inner.header = header_builder
             .txs_commit(txs_commit)
             .txs_proposal(txs_proposal)
@@ -162,3 +194,36 @@ impl BlockBuilder {
         self.inner
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transaction::{CellInput, CellOutput, TransactionBuilder};
+
+    #[test]
+    fn test_with_header_builder() {
+        let cellbase = TransactionBuilder::default()
+            .input(CellInput::new_cellbase_input(1))
+            .output(CellOutput::new(100, vec![], H256::zero(), None))
+            .build();
+        let proposal = ProposalShortId::from_slice(&[1; 10]).unwrap();
+        let uncle = UncleBlock {
+            header: HeaderBuilder::default().nonce(1).build(),
+            cellbase: cellbase.clone(),
+            proposal_transactions: vec![],
+        };
+        let block = BlockBuilder::default()
+            .commit_transaction(cellbase.clone())
+            .proposal_transaction(proposal)
+            .uncle(uncle.clone())
+            .with_header_builder(HeaderBuilder::default().number(1));
+
+        let header = block.header();
+        assert_eq!(header.number(), 1);
+        assert_eq!(header.cellbase_id(), &cellbase.hash());
+        assert_eq!(header.txs_commit(), &merkle_root(&[cellbase.hash()]));
+        assert_eq!(header.txs_proposal(), &merkle_root(&[proposal.hash()]));
+        assert_eq!(header.uncles_hash(), &uncles_hash(&[uncle]));
+        assert_eq!(header.uncles_count(), 1);
+    }
+}
@@ -0,0 +1,288 @@
+//! Canonical serialization of the hashed core types.
+//!
+//! Every field is written in the order documented on the type, integers are
+//! fixed width little endian, byte strings and sequences are prefixed with
+//! their length as `u32`, and options with a `0` or `1` tag. The layout does
+//! not depend on the Rust struct definitions or on serde, so reordering
+//! fields or changing the wire encoding never changes a hash.
+//!
+//! The encoding of every hashed value starts with [CANONICAL_VERSION]; a
+//! layout change must bump it. The decoding refuses the bytes of any other
+//! version, so that a node never reads a value of a layout it does not know.
+//!
+//! The network messages are still encoded with the flatbuffers schemas of the
+//! protocol crate. Moving them to this layout, behind a protocol version, is
+//! left to a follow-up; the values decoded from them are hashed here and do
+//! not depend on their wire encoding.
+
+use hash::sha3_256;
+use numext_fixed_hash::H256;
+use numext_fixed_uint::U256;
+use std::fmt;
+
+pub const CANONICAL_VERSION: u8 = 0;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CanonicalError {
+    /// The bytes are of another layout version
+    UnsupportedVersion(u8),
+    /// The bytes end within a value
+    UnexpectedEnd,
+    /// Bytes are left after the value, their count
+    TrailingBytes(usize),
+    /// An option tag other than `0` and `1`
+    InvalidTag(u8),
+}
+
+impl fmt::Display for CanonicalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CanonicalError::UnsupportedVersion(version) => {
+                write!(f, "unsupported canonical version {}", version)
+            }
+            CanonicalError::UnexpectedEnd => write!(f, "unexpected end of the canonical bytes"),
+            CanonicalError::TrailingBytes(count) => {
+                write!(f, "{} bytes after the canonical value", count)
+            }
+            CanonicalError::InvalidTag(tag) => write!(f, "invalid option tag {}", tag),
+        }
+    }
+}
+
+pub trait CanonicalSerialize {
+    fn write_canonical(&self, out: &mut Vec<u8>);
+
+    fn canonical_bytes(&self) -> Vec<u8> {
+        let mut out = vec![CANONICAL_VERSION];
+        self.write_canonical(&mut out);
+        out
+    }
+
+    fn canonical_hash(&self) -> H256 {
+        sha3_256(self.canonical_bytes()).into()
+    }
+}
+
+impl CanonicalSerialize for u8 {
+    fn write_canonical(&self, out: &mut Vec<u8>) {
+        out.push(*self);
+    }
+}
+
+impl CanonicalSerialize for u32 {
+    fn write_canonical(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_le_bytes());
+    }
+}
+
+impl CanonicalSerialize for u64 {
+    fn write_canonical(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_le_bytes());
+    }
+}
+
+impl CanonicalSerialize for H256 {
+    fn write_canonical(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(self.as_bytes());
+    }
+}
+
+impl CanonicalSerialize for U256 {
+    fn write_canonical(&self, out: &mut Vec<u8>) {
+        let mut bytes = [0u8; 32];
+        self.into_little_endian(&mut bytes).expect("U256 fits in 32 bytes");
+        out.extend_from_slice(&bytes);
+    }
+}
+
+impl CanonicalSerialize for [u8] {
+    fn write_canonical(&self, out: &mut Vec<u8>) {
+        (self.len() as u32).write_canonical(out);
+        out.extend_from_slice(self);
+    }
+}
+
+impl CanonicalSerialize for Vec<u8> {
+    fn write_canonical(&self, out: &mut Vec<u8>) {
+        self.as_slice().write_canonical(out);
+    }
+}
+
+impl<T: CanonicalSerialize> CanonicalSerialize for Option<T> {
+    fn write_canonical(&self, out: &mut Vec<u8>) {
+        match self {
+            Some(value) => {
+                out.push(1);
+                value.write_canonical(out);
+            }
+            None => out.push(0),
+        }
+    }
+}
+
+/// Writes the length of `items` followed by each item
+pub fn write_seq<T: CanonicalSerialize>(items: &[T], out: &mut Vec<u8>) {
+    (items.len() as u32).write_canonical(out);
+    for item in items {
+        item.write_canonical(out);
+    }
+}
+
+pub trait CanonicalDeserialize: Sized {
+    /// Reads a value from the front of `input`, advancing it past the value
+    fn read_canonical(input: &mut &[u8]) -> Result<Self, CanonicalError>;
+
+    /// Decodes the bytes of `canonical_bytes`, refusing the other versions
+    /// and the bytes left after the value
+    fn from_canonical_bytes(bytes: &[u8]) -> Result<Self, CanonicalError> {
+        let mut input = bytes;
+        let version = u8::read_canonical(&mut input)?;
+        if version != CANONICAL_VERSION {
+            return Err(CanonicalError::UnsupportedVersion(version));
+        }
+        let value = Self::read_canonical(&mut input)?;
+        if !input.is_empty() {
+            return Err(CanonicalError::TrailingBytes(input.len()));
+        }
+        Ok(value)
+    }
+}
+
+/// Takes the `len` bytes at the front of `input`
+pub fn take<'a>(input: &mut &'a [u8], len: usize) -> Result<&'a [u8], CanonicalError> {
+    if input.len() < len {
+        return Err(CanonicalError::UnexpectedEnd);
+    }
+    let (bytes, rest) = input.split_at(len);
+    *input = rest;
+    Ok(bytes)
+}
+
+impl CanonicalDeserialize for u8 {
+    fn read_canonical(input: &mut &[u8]) -> Result<Self, CanonicalError> {
+        Ok(take(input, 1)?[0])
+    }
+}
+
+impl CanonicalDeserialize for u32 {
+    fn read_canonical(input: &mut &[u8]) -> Result<Self, CanonicalError> {
+        let mut bytes = [0u8; 4];
+        bytes.copy_from_slice(take(input, 4)?);
+        Ok(u32::from_le_bytes(bytes))
+    }
+}
+
+impl CanonicalDeserialize for u64 {
+    fn read_canonical(input: &mut &[u8]) -> Result<Self, CanonicalError> {
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(take(input, 8)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+}
+
+impl CanonicalDeserialize for H256 {
+    fn read_canonical(input: &mut &[u8]) -> Result<Self, CanonicalError> {
+        Ok(H256::from_slice(take(input, 32)?).expect("32 bytes"))
+    }
+}
+
+impl CanonicalDeserialize for U256 {
+    fn read_canonical(input: &mut &[u8]) -> Result<Self, CanonicalError> {
+        Ok(U256::from_little_endian(take(input, 32)?).expect("32 bytes"))
+    }
+}
+
+impl CanonicalDeserialize for Vec<u8> {
+    fn read_canonical(input: &mut &[u8]) -> Result<Self, CanonicalError> {
+        let len = u32::read_canonical(input)? as usize;
+        Ok(take(input, len)?.to_vec())
+    }
+}
+
+impl<T: CanonicalDeserialize> CanonicalDeserialize for Option<T> {
+    fn read_canonical(input: &mut &[u8]) -> Result<Self, CanonicalError> {
+        match u8::read_canonical(input)? {
+            0 => Ok(None),
+            1 => T::read_canonical(input).map(Some),
+            tag => Err(CanonicalError::InvalidTag(tag)),
+        }
+    }
+}
+
+/// Reads the items written by `write_seq`
+pub fn read_seq<T: CanonicalDeserialize>(input: &mut &[u8]) -> Result<Vec<T>, CanonicalError> {
+    let len = u32::read_canonical(input)?;
+    // Not allocated upfront, the length is not trusted
+    let mut items = Vec::new();
+    for _ in 0..len {
+        items.push(T::read_canonical(input)?);
+    }
+    Ok(items)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::header::{Header, HeaderBuilder};
+    use crate::transaction::{CellOutput, OutPoint, Transaction, TransactionBuilder};
+
+    #[test]
+    fn test_canonical_layout() {
+        let out_point = OutPoint::new(H256::zero(), 1);
+        let mut expected = vec![CANONICAL_VERSION];
+        expected.extend_from_slice(&[0; 32]);
+        expected.extend_from_slice(&[1, 0, 0, 0]);
+        assert_eq!(out_point.canonical_bytes(), expected);
+
+        let mut bytes = Vec::new();
+        Some(vec![7u8]).write_canonical(&mut bytes);
+        None::<Vec<u8>>.write_canonical(&mut bytes);
+        assert_eq!(bytes, vec![1, 1, 0, 0, 0, 7, 0]);
+    }
+
+    #[test]
+    fn test_canonical_hash() {
+        let tx = TransactionBuilder::default()
+            .output(CellOutput::new(5, vec![1, 2], H256::zero(), None))
+            .build();
+        assert_eq!(tx.hash(), H256::from(sha3_256(tx.canonical_bytes())));
+
+        let header = HeaderBuilder::default().number(1).nonce(2).build();
+        assert_eq!(header.hash(), header.canonical_hash());
+        assert_ne!(header.hash(), header.pow_hash());
+    }
+
+    #[test]
+    fn test_canonical_decode() {
+        let tx = TransactionBuilder::default()
+            .output(CellOutput::new(5, vec![1, 2], H256::zero(), None))
+            .build();
+        let bytes = tx.canonical_bytes();
+        assert_eq!(Transaction::from_canonical_bytes(&bytes), Ok(tx));
+
+        let header = HeaderBuilder::default().number(1).nonce(2).build();
+        let decoded = Header::from_canonical_bytes(&header.canonical_bytes()).unwrap();
+        assert_eq!(decoded.hash(), header.hash());
+
+        let mut other_version = bytes.clone();
+        other_version[0] = CANONICAL_VERSION + 1;
+        assert_eq!(
+            Transaction::from_canonical_bytes(&other_version),
+            Err(CanonicalError::UnsupportedVersion(CANONICAL_VERSION + 1))
+        );
+        assert_eq!(
+            Transaction::from_canonical_bytes(&bytes[..bytes.len() - 1]),
+            Err(CanonicalError::UnexpectedEnd)
+        );
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert_eq!(
+            Transaction::from_canonical_bytes(&trailing),
+            Err(CanonicalError::TrailingBytes(1))
+        );
+        assert_eq!(
+            Option::<u8>::read_canonical(&mut &[2u8][..]),
+            Err(CanonicalError::InvalidTag(2))
+        );
+    }
+}
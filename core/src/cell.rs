@@ -1,4 +1,5 @@
 use crate::transaction::{CellOutput, OutPoint, Transaction};
+use crate::Capacity;
 use fnv::FnvHashSet;
 use std::iter::Chain;
 use std::slice;
@@ -125,11 +126,28 @@ impl ResolvedTransaction {
     pub fn is_fully_resolved(&self) -> bool {
         self.cells_iter().all(|state| state.is_live())
     }
+
+    /// Input capacity minus output capacity, `None` when an input is not live or
+    /// the outputs exceed the inputs.
+    pub fn fee(&self) -> Option<Capacity> {
+        let mut inputs_capacity: Capacity = 0;
+        for cell in &self.input_cells {
+            inputs_capacity = inputs_capacity.checked_add(cell.get_live()?.capacity)?;
+        }
+        let outputs_capacity = self
+            .transaction
+            .outputs()
+            .iter()
+            .map(|output| output.capacity)
+            .sum();
+        inputs_capacity.checked_sub(outputs_capacity)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::transaction::TransactionBuilder;
     use numext_fixed_hash::H256;
     use std::collections::HashMap;
 
@@ -186,4 +204,24 @@ mod tests {
         assert_eq!(CellStatus::Dead, db.cell(&p2));
         assert_eq!(CellStatus::Unknown, db.cell(&p3));
     }
+
+    #[test]
+    fn resolved_transaction_fee() {
+        let output = |capacity| CellOutput::new(capacity, vec![], H256::default(), None);
+        let transaction = TransactionBuilder::default()
+            .outputs(vec![output(3), output(4)])
+            .build();
+        let mut rtx = ResolvedTransaction {
+            transaction,
+            dep_cells: vec![],
+            input_cells: vec![CellStatus::Live(output(5)), CellStatus::Live(output(6))],
+        };
+        assert_eq!(Some(4), rtx.fee());
+
+        rtx.input_cells[1] = CellStatus::Live(output(1));
+        assert_eq!(None, rtx.fee());
+
+        rtx.input_cells[1] = CellStatus::Unknown;
+        assert_eq!(None, rtx.fee());
+    }
 }
@@ -7,13 +7,19 @@ pub struct BlockExt {
     pub received_at: u64,
     pub total_difficulty: U256,
     pub total_uncles_count: u64,
+    pub total_transactions_count: u64,
     pub valid: Option<bool>,
 }
 
+/// Where a committed transaction is, written for every transaction of the main chain. The
+/// index is not optional: the duplicate checks of the pool and of the verification, and
+/// `get_transaction`, look the transactions up by it.
 #[derive(Clone, Serialize, Deserialize, Eq, PartialEq, Debug)]
 pub struct TransactionAddress {
     // Block hash
     pub block_hash: H256,
+    // Index of the transaction in block
+    pub index: usize,
     // Offset of block transaction in serialized bytes
     pub offset: usize,
     pub length: usize,
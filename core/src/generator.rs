@@ -0,0 +1,377 @@
+//! Random transactions and blocks of the tests, the workloads of the pool,
+//! the miner and the verification instead of hard-coded fixtures.
+//!
+//! A [Generator] owns cells locked by the [default lock](default_lock) of its
+//! key, the ones it is given and the outputs of the valid transactions it
+//! generates, and each transaction spends some of them. It is seeded, the
+//! same seed and config generate the same transactions, so that a failing
+//! workload replays.
+
+use crate::block::{Block, BlockBuilder};
+use crate::header::{BlockNumber, Header, HeaderBuilder};
+use crate::signing::{default_lock, default_unlock_script, sign_inputs};
+use crate::transaction::{CellInput, CellOutput, OutPoint, Transaction, TransactionBuilder};
+use crate::Capacity;
+use crypto::secp::{Privkey, Pubkey};
+use numext_fixed_hash::H256;
+use occupied_capacity::OccupiedCapacity;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::slice;
+
+#[derive(Clone, Debug)]
+pub struct GeneratorConfig {
+    /// Transactions of a block besides its cellbase
+    pub txs: usize,
+    /// Cells spent by a transaction, fewer when the generator owns fewer
+    pub inputs: usize,
+    /// Cells created by a transaction, fewer when its inputs cannot pay for
+    /// the capacity they occupy
+    pub outputs: usize,
+    /// Bytes of random data of each output
+    pub output_data: usize,
+    /// Capacity of the inputs left to the miner by each transaction
+    pub fee: Capacity,
+    /// Signs the inputs, or gives them broken signatures
+    pub valid_signatures: bool,
+    /// Bytes occupied by the transactions of a block, which stops before
+    /// `txs` once they reach it
+    pub block_size: Option<usize>,
+}
+
+impl Default for GeneratorConfig {
+    fn default() -> Self {
+        GeneratorConfig {
+            txs: 10,
+            inputs: 1,
+            outputs: 2,
+            output_data: 0,
+            fee: 0,
+            valid_signatures: true,
+            block_size: None,
+        }
+    }
+}
+
+/// The cells are spent in a random order and the outputs of a transaction
+/// may be spent by the next one, so the transactions of a batch depend on
+/// each other as the sent ones do. The transactions with broken signatures
+/// are rejected, their inputs stay spendable and their outputs are not kept.
+pub struct Generator {
+    config: GeneratorConfig,
+    rng: StdRng,
+    privkey: Privkey,
+    pubkey: Pubkey,
+    cells: Vec<(OutPoint, Capacity)>,
+}
+
+impl Generator {
+    pub fn new(config: GeneratorConfig, seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        // Almost any 32 bytes are a valid key
+        let (privkey, pubkey) = loop {
+            let bytes = rng.gen::<[u8; 32]>();
+            let privkey = Privkey::from(H256::from_slice(&bytes).expect("32 bytes"));
+            if let Ok(pubkey) = privkey.pubkey() {
+                break (privkey, pubkey);
+            }
+        };
+        Generator {
+            config,
+            rng,
+            privkey,
+            pubkey,
+            cells: Vec::new(),
+        }
+    }
+
+    pub fn config(&self) -> &GeneratorConfig {
+        &self.config
+    }
+
+    pub fn config_mut(&mut self) -> &mut GeneratorConfig {
+        &mut self.config
+    }
+
+    /// The lock of the cells the generator can spend
+    pub fn lock(&self) -> H256 {
+        default_lock(&self.pubkey)
+    }
+
+    pub fn privkey(&self) -> &Privkey {
+        &self.privkey
+    }
+
+    /// Keeps the outputs of `transaction` locked by [lock](Generator::lock),
+    /// such as the ones of a genesis block or a matured cellbase
+    pub fn add_cells(&mut self, transaction: &Transaction) {
+        let lock = self.lock();
+        let hash = transaction.hash();
+        for (index, output) in transaction.outputs().iter().enumerate() {
+            if output.lock == lock {
+                self.cells
+                    .push((OutPoint::new(hash.clone(), index as u32), output.capacity));
+            }
+        }
+    }
+
+    /// The number of cells the generator can spend
+    pub fn cells_count(&self) -> usize {
+        self.cells.len()
+    }
+
+    /// The cellbase of block `number` paying `reward` to the generator in
+    /// `cells` equal cells, which are kept at once. A chain verifying the
+    /// cellbase maturity rejects the transactions spending them before.
+    pub fn cellbase(
+        &mut self,
+        number: BlockNumber,
+        cells: usize,
+        reward: Capacity,
+    ) -> Transaction {
+        let capacity = reward / cells.max(1) as Capacity;
+        let cellbase = TransactionBuilder::default()
+            .input(CellInput::new_cellbase_input(number))
+            .outputs(vec![
+                CellOutput::new(capacity, Vec::new(), self.lock(), None);
+                cells
+            ])
+            .build();
+        self.add_cells(&cellbase);
+        cellbase
+    }
+
+    /// A transaction spending `inputs` of the cells, or none when the
+    /// generator owns no cell, or too few to pay the fee and one output
+    pub fn transaction(&mut self) -> Option<Transaction> {
+        let count = self.config.inputs.min(self.cells.len());
+        let mut spent = Vec::with_capacity(count);
+        for _ in 0..count {
+            let index = self.rng.gen_range(0, self.cells.len());
+            spent.push(self.cells.swap_remove(index));
+        }
+        let total: Capacity = spent.iter().map(|(_, capacity)| capacity).sum();
+
+        let lock = self.lock();
+        let mut outputs: Vec<_> = (0..self.config.outputs)
+            .map(|_| {
+                let data = (0..self.config.output_data).map(|_| self.rng.gen()).collect();
+                CellOutput::new(0, data, lock.clone(), None)
+            })
+            .collect();
+        let occupied = outputs.first().map_or(0, |output| output.occupied_capacity());
+        let affordable = total
+            .checked_sub(self.config.fee)
+            .map_or(0, |spare| spare / (occupied as Capacity).max(1));
+        outputs.truncate(affordable as usize);
+        if count == 0 || outputs.is_empty() {
+            self.cells.extend(spent);
+            return None;
+        }
+
+        // The capacity above the occupied one goes to the outputs in random
+        // shares, split at random cut points
+        let spare = total - self.config.fee - occupied as Capacity * outputs.len() as Capacity;
+        let mut cuts: Vec<Capacity> = (1..outputs.len())
+            .map(|_| self.rng.gen_range(0, spare + 1))
+            .collect();
+        cuts.push(spare);
+        cuts.sort();
+        let mut last = 0;
+        for (output, cut) in outputs.iter_mut().zip(cuts) {
+            output.capacity = occupied as Capacity + cut - last;
+            last = cut;
+        }
+
+        let unlock = default_unlock_script(&self.pubkey);
+        let unsigned = TransactionBuilder::default()
+            .inputs(
+                spent
+                    .iter()
+                    .map(|(out_point, _)| CellInput::new(out_point.clone(), unlock.clone()))
+                    .collect(),
+            )
+            .outputs(outputs)
+            .build();
+        let signed = sign_inputs(&unsigned, slice::from_ref(&self.privkey))
+            .expect("sign with the key of the inputs");
+
+        if self.config.valid_signatures {
+            self.add_cells(&signed);
+            Some(signed)
+        } else {
+            self.cells.extend(spent);
+            Some(break_signatures(signed))
+        }
+    }
+
+    /// Up to `count` transactions, fewer when the generator runs out of cells
+    pub fn transactions(&mut self, count: usize) -> Vec<Transaction> {
+        let mut transactions = Vec::with_capacity(count);
+        while transactions.len() < count {
+            match self.transaction() {
+                Some(transaction) => transactions.push(transaction),
+                None => break,
+            }
+        }
+        transactions
+    }
+
+    /// The transactions of a block, `txs` of them or the ones reaching
+    /// `block_size`
+    pub fn block_transactions(&mut self) -> Vec<Transaction> {
+        let mut transactions = Vec::new();
+        let mut size = 0;
+        while transactions.len() < self.config.txs
+            && self.config.block_size.map_or(true, |limit| size < limit)
+        {
+            match self.transaction() {
+                Some(transaction) => {
+                    size += transaction.occupied_capacity();
+                    transactions.push(transaction);
+                }
+                None => break,
+            }
+        }
+        transactions
+    }
+
+    /// The child of `parent` committing a cellbase paying `reward` to the
+    /// generator and the [block transactions](Generator::block_transactions).
+    /// It is not sealed and its difficulty is the one of the parent, for the
+    /// chains not verifying them, and its transactions are not proposed.
+    pub fn block(&mut self, parent: &Header, reward: Capacity) -> Block {
+        let number = parent.number() + 1;
+        let transactions = self.block_transactions();
+        let cellbase = self.cellbase(number, 1, reward);
+        let header_builder = HeaderBuilder::default()
+            .parent_hash(parent.hash())
+            .number(number)
+            .timestamp(parent.timestamp() + 1)
+            .difficulty(parent.difficulty().clone());
+        BlockBuilder::default()
+            .commit_transaction(cellbase)
+            .commit_transactions(transactions)
+            .with_header_builder(header_builder)
+    }
+}
+
+// Flips a bit of each signature, so that it no longer matches the message
+fn break_signatures(transaction: Transaction) -> Transaction {
+    let inputs: Vec<_> = transaction
+        .inputs()
+        .iter()
+        .map(|input| {
+            let mut input = input.clone();
+            for signature in &mut input.unlock.args {
+                signature[0] ^= 1;
+            }
+            input
+        })
+        .collect();
+    TransactionBuilder::default()
+        .transaction(transaction)
+        .inputs_clear()
+        .inputs(inputs)
+        .build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signing::{verify_inputs, SigningError};
+
+    fn funded(config: GeneratorConfig, seed: u64) -> Generator {
+        let mut generator = Generator::new(config, seed);
+        generator.cellbase(0, 10, 10_000_000);
+        generator
+    }
+
+    #[test]
+    fn test_transactions_conserve_capacity() {
+        let config = GeneratorConfig {
+            inputs: 3,
+            outputs: 4,
+            output_data: 16,
+            fee: 100,
+            ..Default::default()
+        };
+        let mut generator = funded(config, 1);
+        for _ in 0..20 {
+            let before = generator.cells_count();
+            let transaction = generator.transaction().expect("funded");
+            let outputs = transaction.outputs().len();
+            assert_eq!(transaction.inputs().len(), 3);
+            assert!(outputs >= 1 && outputs <= 4);
+            assert_eq!(generator.cells_count(), before + outputs - 3);
+            assert!(transaction
+                .outputs()
+                .iter()
+                .all(|output| output.capacity >= output.occupied_capacity() as Capacity
+                    && output.data.len() == 16));
+            assert_eq!(verify_inputs(&transaction), Ok(()));
+        }
+        let total: Capacity = generator.cells.iter().map(|(_, capacity)| capacity).sum();
+        assert_eq!(total, 10_000_000 - 20 * 100);
+    }
+
+    #[test]
+    fn test_broken_signatures() {
+        let config = GeneratorConfig {
+            valid_signatures: false,
+            ..Default::default()
+        };
+        let mut generator = funded(config, 2);
+        let transaction = generator.transaction().expect("funded");
+        assert_eq!(
+            verify_inputs(&transaction),
+            Err(SigningError::InvalidSignatures(0))
+        );
+        // Nothing was spent
+        assert_eq!(generator.cells_count(), 10);
+    }
+
+    #[test]
+    fn test_same_seed_same_transactions() {
+        let mut a = funded(GeneratorConfig::default(), 3);
+        let mut b = funded(GeneratorConfig::default(), 3);
+        assert_eq!(a.transactions(10), b.transactions(10));
+        let mut c = funded(GeneratorConfig::default(), 4);
+        assert_ne!(a.transactions(10), c.transactions(10));
+    }
+
+    #[test]
+    fn test_block_size() {
+        let config = GeneratorConfig {
+            txs: 1000,
+            output_data: 100,
+            block_size: Some(2_000),
+            ..Default::default()
+        };
+        let mut generator = funded(config, 5);
+        let transactions = generator.block_transactions();
+        let size: usize = transactions.iter().map(|tx| tx.occupied_capacity()).sum();
+        let last = transactions.last().expect("some").occupied_capacity();
+        assert!(size >= 2_000 && size - last < 2_000);
+
+        let block = generator.block(&HeaderBuilder::default().build(), 1_000);
+        assert_eq!(block.header().number(), 1);
+        assert!(block.commit_transactions()[0].is_cellbase());
+    }
+
+    #[test]
+    fn test_too_few_cells() {
+        let config = GeneratorConfig {
+            fee: 100,
+            ..Default::default()
+        };
+        let mut generator = Generator::new(config, 6);
+        assert_eq!(generator.transaction(), None);
+        assert!(generator.transactions(10).is_empty());
+
+        // A cell paying the fee but not the capacity of an output is kept
+        generator.cellbase(0, 1, 120);
+        assert_eq!(generator.transaction(), None);
+        assert_eq!(generator.cells_count(), 1);
+    }
+}
@@ -1,5 +1,5 @@
-use bincode::{deserialize, serialize};
-use hash::sha3_256;
+use crate::canonical::{CanonicalDeserialize, CanonicalError, CanonicalSerialize};
+use bincode::deserialize;
 use numext_fixed_hash::H256;
 use numext_fixed_uint::U256;
 use serde_derive::{Deserialize, Serialize};
@@ -48,7 +48,7 @@ pub struct RawHeader {
 
 impl RawHeader {
     pub fn pow_hash(&self) -> H256 {
-        sha3_256(serialize(self).unwrap()).into()
+        self.canonical_hash()
     }
 
     pub fn with_seal(self, seal: Seal) -> Header {
@@ -112,7 +112,7 @@ impl Header {
     }
 
     pub fn hash(&self) -> H256 {
-        sha3_256(serialize(&self).unwrap()).into()
+        self.canonical_hash()
     }
 
     pub fn pow_hash(&self) -> H256 {
@@ -156,6 +156,74 @@ impl Header {
     }
 }
 
+/// version, parent_hash, timestamp, number, txs_commit, txs_proposal,
+/// difficulty, cellbase_id, uncles_hash, uncles_count
+impl CanonicalSerialize for RawHeader {
+    fn write_canonical(&self, out: &mut Vec<u8>) {
+        self.version.write_canonical(out);
+        self.parent_hash.write_canonical(out);
+        self.timestamp.write_canonical(out);
+        self.number.write_canonical(out);
+        self.txs_commit.write_canonical(out);
+        self.txs_proposal.write_canonical(out);
+        self.difficulty.write_canonical(out);
+        self.cellbase_id.write_canonical(out);
+        self.uncles_hash.write_canonical(out);
+        self.uncles_count.write_canonical(out);
+    }
+}
+
+impl CanonicalDeserialize for RawHeader {
+    fn read_canonical(input: &mut &[u8]) -> Result<Self, CanonicalError> {
+        Ok(RawHeader {
+            version: CanonicalDeserialize::read_canonical(input)?,
+            parent_hash: CanonicalDeserialize::read_canonical(input)?,
+            timestamp: CanonicalDeserialize::read_canonical(input)?,
+            number: CanonicalDeserialize::read_canonical(input)?,
+            txs_commit: CanonicalDeserialize::read_canonical(input)?,
+            txs_proposal: CanonicalDeserialize::read_canonical(input)?,
+            difficulty: CanonicalDeserialize::read_canonical(input)?,
+            cellbase_id: CanonicalDeserialize::read_canonical(input)?,
+            uncles_hash: CanonicalDeserialize::read_canonical(input)?,
+            uncles_count: CanonicalDeserialize::read_canonical(input)?,
+        })
+    }
+}
+
+/// nonce, proof
+impl CanonicalSerialize for Seal {
+    fn write_canonical(&self, out: &mut Vec<u8>) {
+        self.nonce.write_canonical(out);
+        self.proof.write_canonical(out);
+    }
+}
+
+impl CanonicalDeserialize for Seal {
+    fn read_canonical(input: &mut &[u8]) -> Result<Self, CanonicalError> {
+        Ok(Seal {
+            nonce: CanonicalDeserialize::read_canonical(input)?,
+            proof: CanonicalDeserialize::read_canonical(input)?,
+        })
+    }
+}
+
+/// raw, seal
+impl CanonicalSerialize for Header {
+    fn write_canonical(&self, out: &mut Vec<u8>) {
+        self.raw.write_canonical(out);
+        self.seal.write_canonical(out);
+    }
+}
+
+impl CanonicalDeserialize for Header {
+    fn read_canonical(input: &mut &[u8]) -> Result<Self, CanonicalError> {
+        Ok(Header {
+            raw: CanonicalDeserialize::read_canonical(input)?,
+            seal: CanonicalDeserialize::read_canonical(input)?,
+        })
+    }
+}
+
 impl PartialEq for Header {
     fn eq(&self, other: &Header) -> bool {
         self.hash() == other.hash()
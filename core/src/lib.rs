@@ -2,14 +2,22 @@
 //!
 //! This Library provides the essential types for building ckb.
 
+pub mod address;
+#[cfg(any(test, feature = "arbitrary"))]
+pub mod arbitrary;
 pub mod block;
+pub mod canonical;
 pub mod cell;
 pub mod difficulty;
 pub mod error;
 pub mod extras;
+#[cfg(any(test, feature = "test-utils"))]
+pub mod generator;
 pub mod header;
+pub mod multisig;
 pub mod script;
 pub mod service;
+pub mod signing;
 pub mod transaction;
 pub mod transaction_meta;
 pub mod uncle;
@@ -0,0 +1,111 @@
+//! Built-in m-of-n multisig lock.
+//!
+//! A multisig unlock script references [multisig_code_hash], which no cell
+//! carries: the verifier checks it natively. Its `signed_args` are the
+//! threshold as a single byte followed by the 64 bytes public keys, so the
+//! lock hash commits to both. Its `args` are the 65 bytes recoverable
+//! signatures of [signing_message], in the same order as the keys.
+
+use crate::script::Script;
+use crate::transaction::{Transaction, TransactionBuilder};
+use crypto::secp::{Error, Privkey, Pubkey};
+use hash::sha3_256;
+use numext_fixed_hash::{H256, H512};
+
+pub const MAX_MULTISIG_KEYS: usize = 16;
+
+pub fn multisig_code_hash() -> H256 {
+    sha3_256(b"ckb-builtin-multisig").into()
+}
+
+/// Unlock script requiring `threshold` signatures from `pubkeys`, carrying
+/// `signatures`
+pub fn multisig_script(threshold: u8, pubkeys: &[Pubkey], signatures: Vec<Vec<u8>>) -> Script {
+    let mut signed_args = vec![vec![threshold]];
+    signed_args.extend(pubkeys.iter().map(|pubkey| pubkey.as_bytes().to_vec()));
+    Script::new(0, signatures, Some(multisig_code_hash()), None, signed_args)
+}
+
+/// Lock of cells which `threshold` of `pubkeys` must sign to spend
+pub fn multisig_lock(threshold: u8, pubkeys: &[Pubkey]) -> H256 {
+    multisig_script(threshold, pubkeys, Vec::new()).type_hash()
+}
+
+pub fn is_multisig_script(script: &Script) -> bool {
+    script.binary.is_none() && script.reference == Some(multisig_code_hash())
+}
+
+/// The threshold and the public keys in the `signed_args` of a multisig
+/// script, None when they are malformed
+pub fn multisig_keys(script: &Script) -> Option<(usize, Vec<Pubkey>)> {
+    let (threshold, keys) = script.signed_args.split_first()?;
+    let threshold = match threshold.as_slice() {
+        [threshold] => *threshold as usize,
+        _ => return None,
+    };
+    if threshold == 0 || threshold > keys.len() || keys.len() > MAX_MULTISIG_KEYS {
+        return None;
+    }
+    let pubkeys = keys
+        .iter()
+        .map(|key| H512::from_slice(key).ok().map(Pubkey::from))
+        .collect::<Option<Vec<_>>>()?;
+    Some((threshold, pubkeys))
+}
+
+/// Message signed by the keys: the hash of `transaction` with the `args` of
+/// every unlock script cleared, so signatures do not sign each other.
+pub fn signing_message(transaction: &Transaction) -> H256 {
+    let inputs = transaction
+        .inputs()
+        .iter()
+        .map(|input| {
+            let mut input = input.clone();
+            input.unlock.args.clear();
+            input
+        })
+        .collect();
+    TransactionBuilder::default()
+        .transaction(transaction.clone())
+        .inputs_clear()
+        .inputs(inputs)
+        .build()
+        .hash()
+}
+
+pub fn sign(transaction: &Transaction, privkey: &Privkey) -> Result<Vec<u8>, Error> {
+    privkey
+        .sign_recoverable(&signing_message(transaction))
+        .map(|signature| signature.serialize())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transaction::{CellInput, OutPoint};
+    use crypto::secp::Generator;
+
+    #[test]
+    fn test_signing_message_ignores_signatures() {
+        let (_, pubkey) = Generator::new().random_keypair().unwrap();
+        let pubkeys = vec![pubkey];
+        let unsigned = TransactionBuilder::default()
+            .input(CellInput::new(
+                OutPoint::new(H256::zero(), 0),
+                multisig_script(1, &pubkeys, Vec::new()),
+            ))
+            .build();
+        let signed = TransactionBuilder::default()
+            .input(CellInput::new(
+                OutPoint::new(H256::zero(), 0),
+                multisig_script(1, &pubkeys, vec![vec![1; 65]]),
+            ))
+            .build();
+        assert_ne!(unsigned.hash(), signed.hash());
+        assert_eq!(signing_message(&unsigned), signing_message(&signed));
+        assert_eq!(
+            unsigned.inputs()[0].unlock.type_hash(),
+            signed.inputs()[0].unlock.type_hash()
+        );
+    }
+}
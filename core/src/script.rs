@@ -1,3 +1,6 @@
+use crate::canonical::{
+    read_seq, write_seq, CanonicalDeserialize, CanonicalError, CanonicalSerialize,
+};
 use hash::sha3_256;
 use numext_fixed_hash::H256;
 use occupied_capacity::OccupiedCapacity;
@@ -50,6 +53,29 @@ pub struct Script {
     pub signed_args: Vec<Vec<u8>>,
 }
 
+/// version, args, reference, binary, signed_args
+impl CanonicalSerialize for Script {
+    fn write_canonical(&self, out: &mut Vec<u8>) {
+        self.version.write_canonical(out);
+        write_seq(&self.args, out);
+        self.reference.write_canonical(out);
+        self.binary.write_canonical(out);
+        write_seq(&self.signed_args, out);
+    }
+}
+
+impl CanonicalDeserialize for Script {
+    fn read_canonical(input: &mut &[u8]) -> Result<Self, CanonicalError> {
+        Ok(Script {
+            version: CanonicalDeserialize::read_canonical(input)?,
+            args: read_seq(input)?,
+            reference: CanonicalDeserialize::read_canonical(input)?,
+            binary: CanonicalDeserialize::read_canonical(input)?,
+            signed_args: read_seq(input)?,
+        })
+    }
+}
+
 type ScriptTuple = (
     u8,
     Vec<Vec<u8>>,
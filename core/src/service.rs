@@ -1,4 +1,4 @@
-use crossbeam_channel::{self, Sender};
+use crossbeam_channel::{self, Receiver, Sender};
 
 const ONESHOT_CHANNEL_SIZE: usize = 1;
 pub const SIGNAL_CHANNEL_SIZE: usize = 1;
@@ -18,4 +18,14 @@ impl<A, R> Request<A, R> {
         });
         response.recv().ok()
     }
+
+    /// Send without waiting, the response arrives on the returned receiver
+    pub fn send(sender: &Sender<Request<A, R>>, arguments: A) -> Receiver<R> {
+        let (responder, response) = crossbeam_channel::bounded(ONESHOT_CHANNEL_SIZE);
+        let _ = sender.send(Request {
+            responder,
+            arguments,
+        });
+        response
+    }
 }
@@ -0,0 +1,244 @@
+//! Signing the inputs of a transaction.
+//!
+//! The default lock of a key is the 1-of-1 [multisig](crate::multisig) lock,
+//! the only lock checking signatures which every chain can verify. The
+//! signatures are the `args` of the unlock scripts, which [signing_message]
+//! leaves out, so the inputs can be signed in any order, or by several
+//! parties each signing its own inputs.
+//!
+//! The keys sign through a [Signer], which may keep them out of the node,
+//! in another process or a hardware device.
+
+use crate::multisig::{is_multisig_script, multisig_keys, multisig_lock, multisig_script};
+use crate::script::Script;
+use crate::transaction::{CellInput, Transaction, TransactionBuilder};
+use crypto::secp::{Error as CrypError, Privkey, Pubkey, Signature};
+use numext_fixed_hash::{H256, H512};
+
+pub use crate::multisig::signing_message;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum SigningError {
+    Crypto(CrypError),
+    /// The multisig unlock script of the input is malformed
+    InvalidScript(usize),
+    /// Fewer of the keys of the input than its threshold were given
+    MissingKeys(usize),
+    /// The signatures of the input do not meet its threshold
+    InvalidSignatures(usize),
+    /// The signer failed, for this reason
+    Signer(String),
+}
+
+impl From<CrypError> for SigningError {
+    fn from(e: CrypError) -> Self {
+        SigningError::Crypto(e)
+    }
+}
+
+/// Holder of private keys signing messages with them
+pub trait Signer {
+    fn has_key(&self, pubkey: &Pubkey) -> bool;
+
+    /// Recoverable signature of `message` by the key of `pubkey`
+    fn sign(&self, pubkey: &Pubkey, message: &H256) -> Result<Signature, SigningError>;
+}
+
+impl Signer for [Privkey] {
+    fn has_key(&self, pubkey: &Pubkey) -> bool {
+        self.iter()
+            .any(|privkey| privkey.pubkey().ok().as_ref() == Some(pubkey))
+    }
+
+    fn sign(&self, pubkey: &Pubkey, message: &H256) -> Result<Signature, SigningError> {
+        let privkey = self
+            .iter()
+            .find(|privkey| privkey.pubkey().ok().as_ref() == Some(pubkey))
+            .ok_or_else(|| SigningError::Signer(format!("no key of {}", pubkey)))?;
+        Ok(privkey.sign_recoverable(message)?)
+    }
+}
+
+/// Lock of the cells spendable with the signature of `pubkey`
+pub fn default_lock(pubkey: &Pubkey) -> H256 {
+    multisig_lock(1, &[copy(pubkey)])
+}
+
+/// Unsigned unlock script of the cells locked by [default_lock]
+pub fn default_unlock_script(pubkey: &Pubkey) -> Script {
+    multisig_script(1, &[copy(pubkey)], Vec::new())
+}
+
+/// Signs every multisig input of `transaction` with `privkeys`, replacing the
+/// signatures it has. The keys of an input sign in its order until its
+/// threshold is met, the inputs which are not multisig are left as they are.
+pub fn sign_inputs(
+    transaction: &Transaction,
+    privkeys: &[Privkey],
+) -> Result<Transaction, SigningError> {
+    sign_inputs_with(transaction, privkeys)
+}
+
+/// Signs as [sign_inputs] with the keys of `signer`. The signature of a key
+/// is checked before being used, a signer returning a wrong one fails the
+/// input.
+pub fn sign_inputs_with<S: Signer + ?Sized>(
+    transaction: &Transaction,
+    signer: &S,
+) -> Result<Transaction, SigningError> {
+    let message = signing_message(transaction);
+    let mut inputs = Vec::with_capacity(transaction.inputs().len());
+    for (index, input) in transaction.inputs().iter().enumerate() {
+        if !is_multisig_script(&input.unlock) {
+            inputs.push(input.clone());
+            continue;
+        }
+        let (threshold, pubkeys) =
+            multisig_keys(&input.unlock).ok_or(SigningError::InvalidScript(index))?;
+        let mut signatures = Vec::with_capacity(threshold);
+        for pubkey in &pubkeys {
+            if signatures.len() == threshold {
+                break;
+            }
+            if signer.has_key(pubkey) {
+                let signature = signer.sign(pubkey, &message)?;
+                if pubkey.verify(&message, &signature).is_err() {
+                    return Err(SigningError::InvalidSignatures(index));
+                }
+                signatures.push(signature.serialize());
+            }
+        }
+        if signatures.len() < threshold {
+            return Err(SigningError::MissingKeys(index));
+        }
+        let mut unlock = input.unlock.clone();
+        unlock.args = signatures;
+        inputs.push(CellInput::new(input.previous_output.clone(), unlock));
+    }
+
+    Ok(TransactionBuilder::default()
+        .transaction(transaction.clone())
+        .inputs_clear()
+        .inputs(inputs)
+        .build())
+}
+
+/// Checks the signatures of the multisig inputs of `transaction` as the
+/// script verifier does, without running the other scripts
+pub fn verify_inputs(transaction: &Transaction) -> Result<(), SigningError> {
+    let message = signing_message(transaction);
+    for (index, input) in transaction.inputs().iter().enumerate() {
+        if !is_multisig_script(&input.unlock) {
+            continue;
+        }
+        let (threshold, pubkeys) =
+            multisig_keys(&input.unlock).ok_or(SigningError::InvalidScript(index))?;
+        let signatures = &input.unlock.args;
+        if signatures.len() < threshold || signatures.iter().any(|arg| arg.len() != 65) {
+            return Err(SigningError::InvalidSignatures(index));
+        }
+        // Each signature must match a key after the one of the previous
+        let mut pubkeys = pubkeys.iter();
+        for signature in signatures {
+            let signature = Signature::from(signature.clone());
+            if !pubkeys.any(|pubkey| pubkey.verify(&message, &signature).is_ok()) {
+                return Err(SigningError::InvalidSignatures(index));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn copy(pubkey: &Pubkey) -> Pubkey {
+    Pubkey::from(H512::clone(pubkey))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transaction::{CellOutput, OutPoint};
+    use crypto::secp::Generator;
+
+    fn unsigned(unlock: Script) -> Transaction {
+        TransactionBuilder::default()
+            .input(CellInput::new(OutPoint::new(H256::zero(), 0), unlock))
+            .input(CellInput::new(OutPoint::new(H256::zero(), 1), Script::default()))
+            .output(CellOutput::new(50, vec![], H256::zero(), None))
+            .build()
+    }
+
+    #[test]
+    fn test_sign_and_verify_inputs() {
+        let privkey = Generator::new().random_privkey();
+        let pubkey = privkey.pubkey().unwrap();
+        let unlock = default_unlock_script(&pubkey);
+        assert_eq!(unlock.type_hash(), default_lock(&pubkey));
+
+        let transaction = unsigned(unlock);
+        assert_eq!(
+            verify_inputs(&transaction),
+            Err(SigningError::InvalidSignatures(0))
+        );
+        let signed = sign_inputs(&transaction, &[privkey]).unwrap();
+        assert_eq!(signed.inputs()[0].unlock.args.len(), 1);
+        assert_eq!(signed.inputs()[1], transaction.inputs()[1]);
+        assert_eq!(signing_message(&signed), signing_message(&transaction));
+        assert_eq!(verify_inputs(&signed), Ok(()));
+
+        // Changing the outputs invalidates the signatures
+        let tampered = TransactionBuilder::default()
+            .transaction(signed)
+            .outputs_clear()
+            .output(CellOutput::new(60, vec![], H256::zero(), None))
+            .build();
+        assert_eq!(
+            verify_inputs(&tampered),
+            Err(SigningError::InvalidSignatures(0))
+        );
+    }
+
+    #[test]
+    fn test_sign_inputs_missing_keys() {
+        let gen = Generator::new();
+        let privkeys = vec![gen.random_privkey(), gen.random_privkey()];
+        let pubkeys: Vec<_> = privkeys.iter().map(|key| key.pubkey().unwrap()).collect();
+        let transaction = unsigned(multisig_script(2, &pubkeys, Vec::new()));
+
+        assert_eq!(
+            sign_inputs(&transaction, &privkeys[..1]),
+            Err(SigningError::MissingKeys(0))
+        );
+        // The keys sign in the order of the script, whatever order given
+        let reversed: Vec<_> = privkeys
+            .iter()
+            .rev()
+            .map(|privkey| Privkey::from(H256::clone(privkey)))
+            .collect();
+        let signed = sign_inputs(&transaction, &reversed).unwrap();
+        assert_eq!(verify_inputs(&signed), Ok(()));
+    }
+
+    // Signs with its key whatever key is asked for
+    struct Impostor(Privkey);
+
+    impl Signer for Impostor {
+        fn has_key(&self, _pubkey: &Pubkey) -> bool {
+            true
+        }
+
+        fn sign(&self, _pubkey: &Pubkey, message: &H256) -> Result<Signature, SigningError> {
+            Ok(self.0.sign_recoverable(message)?)
+        }
+    }
+
+    #[test]
+    fn test_sign_inputs_with_wrong_signer() {
+        let gen = Generator::new();
+        let pubkey = gen.random_privkey().pubkey().unwrap();
+        let transaction = unsigned(default_unlock_script(&pubkey));
+        assert_eq!(
+            sign_inputs_with(&transaction, &Impostor(gen.random_privkey())),
+            Err(SigningError::InvalidSignatures(0))
+        );
+    }
+}
@@ -1,9 +1,12 @@
 //! Transaction using Cell.
 //! It is similar to Bitcoin Tx <https://en.bitcoin.it/wiki/Protocol_documentation#tx/>
+use crate::canonical::{
+    read_seq, take, write_seq, CanonicalDeserialize, CanonicalError, CanonicalSerialize,
+};
 use crate::script::Script;
 pub use crate::Capacity;
 use crate::{BlockNumber, Version};
-use bincode::{deserialize, serialize};
+use bincode::{deserialize, serialized_size};
 use faster_hex::hex_string;
 use hash::sha3_256;
 use numext_fixed_hash::H256;
@@ -128,6 +131,82 @@ pub struct Transaction {
     outputs: Vec<CellOutput>,
 }
 
+/// hash, index
+impl CanonicalSerialize for OutPoint {
+    fn write_canonical(&self, out: &mut Vec<u8>) {
+        self.hash.write_canonical(out);
+        self.index.write_canonical(out);
+    }
+}
+
+impl CanonicalDeserialize for OutPoint {
+    fn read_canonical(input: &mut &[u8]) -> Result<Self, CanonicalError> {
+        Ok(OutPoint {
+            hash: CanonicalDeserialize::read_canonical(input)?,
+            index: CanonicalDeserialize::read_canonical(input)?,
+        })
+    }
+}
+
+/// previous_output, unlock
+impl CanonicalSerialize for CellInput {
+    fn write_canonical(&self, out: &mut Vec<u8>) {
+        self.previous_output.write_canonical(out);
+        self.unlock.write_canonical(out);
+    }
+}
+
+impl CanonicalDeserialize for CellInput {
+    fn read_canonical(input: &mut &[u8]) -> Result<Self, CanonicalError> {
+        Ok(CellInput {
+            previous_output: CanonicalDeserialize::read_canonical(input)?,
+            unlock: CanonicalDeserialize::read_canonical(input)?,
+        })
+    }
+}
+
+/// capacity, data, lock, type
+impl CanonicalSerialize for CellOutput {
+    fn write_canonical(&self, out: &mut Vec<u8>) {
+        self.capacity.write_canonical(out);
+        self.data.write_canonical(out);
+        self.lock.write_canonical(out);
+        self.type_.write_canonical(out);
+    }
+}
+
+impl CanonicalDeserialize for CellOutput {
+    fn read_canonical(input: &mut &[u8]) -> Result<Self, CanonicalError> {
+        Ok(CellOutput {
+            capacity: CanonicalDeserialize::read_canonical(input)?,
+            data: CanonicalDeserialize::read_canonical(input)?,
+            lock: CanonicalDeserialize::read_canonical(input)?,
+            type_: CanonicalDeserialize::read_canonical(input)?,
+        })
+    }
+}
+
+/// version, deps, inputs, outputs
+impl CanonicalSerialize for Transaction {
+    fn write_canonical(&self, out: &mut Vec<u8>) {
+        self.version.write_canonical(out);
+        write_seq(&self.deps, out);
+        write_seq(&self.inputs, out);
+        write_seq(&self.outputs, out);
+    }
+}
+
+impl CanonicalDeserialize for Transaction {
+    fn read_canonical(input: &mut &[u8]) -> Result<Self, CanonicalError> {
+        Ok(Transaction {
+            version: CanonicalDeserialize::read_canonical(input)?,
+            deps: read_seq(input)?,
+            inputs: read_seq(input)?,
+            outputs: read_seq(input)?,
+        })
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct IndexTransaction {
     pub index: usize,
@@ -161,6 +240,19 @@ impl DerefMut for ProposalShortId {
     }
 }
 
+/// The 10 bytes of the id, without a length prefix
+impl CanonicalSerialize for ProposalShortId {
+    fn write_canonical(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.0);
+    }
+}
+
+impl CanonicalDeserialize for ProposalShortId {
+    fn read_canonical(input: &mut &[u8]) -> Result<Self, CanonicalError> {
+        Ok(ProposalShortId::from_slice(take(input, 10)?).expect("10 bytes"))
+    }
+}
+
 impl ProposalShortId {
     pub fn new(inner: [u8; 10]) -> Self {
         ProposalShortId(inner)
@@ -184,7 +276,7 @@ impl ProposalShortId {
     }
 
     pub fn hash(&self) -> H256 {
-        sha3_256(serialize(self).unwrap()).into()
+        self.canonical_hash()
     }
 
     pub fn zero() -> Self {
@@ -218,7 +310,7 @@ impl Transaction {
     }
 
     pub fn hash(&self) -> H256 {
-        sha3_256(serialize(&self).unwrap()).into()
+        self.canonical_hash()
     }
 
     pub fn check_lock(&self, unlock: &[u8], lock: &[u8]) -> bool {
@@ -263,6 +355,11 @@ impl Transaction {
     pub fn get_output(&self, i: usize) -> Option<CellOutput> {
         self.outputs.get(i).cloned()
     }
+
+    /// Bytes of the transaction as it is stored
+    pub fn serialized_size(&self) -> usize {
+        serialized_size(self).expect("transaction serializing should be ok") as usize
+    }
 }
 
 #[derive(Default)]
@@ -1,8 +1,11 @@
 use crate::block::Block;
+use crate::canonical::{
+    read_seq, write_seq, CanonicalDeserialize, CanonicalError, CanonicalSerialize,
+    CANONICAL_VERSION,
+};
 use crate::header::Header;
 use crate::transaction::{ProposalShortId, Transaction};
 use crate::BlockNumber;
-use bincode::serialize;
 use hash::sha3_256;
 use numext_fixed_hash::H256;
 use serde_derive::{Deserialize, Serialize};
@@ -58,10 +61,31 @@ impl UncleBlock {
     }
 }
 
+/// header, cellbase, proposal_transactions
+impl CanonicalSerialize for UncleBlock {
+    fn write_canonical(&self, out: &mut Vec<u8>) {
+        self.header.write_canonical(out);
+        self.cellbase.write_canonical(out);
+        write_seq(&self.proposal_transactions, out);
+    }
+}
+
+impl CanonicalDeserialize for UncleBlock {
+    fn read_canonical(input: &mut &[u8]) -> Result<Self, CanonicalError> {
+        Ok(UncleBlock {
+            header: CanonicalDeserialize::read_canonical(input)?,
+            cellbase: CanonicalDeserialize::read_canonical(input)?,
+            proposal_transactions: read_seq(input)?,
+        })
+    }
+}
+
 pub fn uncles_hash(uncles: &[UncleBlock]) -> H256 {
     if uncles.is_empty() {
         H256::zero()
     } else {
-        sha3_256(serialize(uncles).unwrap()).into()
+        let mut bytes = vec![CANONICAL_VERSION];
+        write_seq(uncles, &mut bytes);
+        sha3_256(bytes).into()
     }
 }
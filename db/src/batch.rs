@@ -23,6 +23,8 @@ impl Operation {
     }
 }
 
+/// Operations written together by [KeyValueDB::write](crate::kvdb::KeyValueDB::write):
+/// either all of them are applied or none.
 #[derive(Debug, Default, Clone)]
 pub struct Batch {
     pub operations: Vec<Operation>,
@@ -0,0 +1,46 @@
+use serde_derive::Deserialize;
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+pub enum DBBackend {
+    /// Persistent storage, needed for any chain beyond toy size
+    RocksDB,
+    /// Nothing is kept on exit, for development chains
+    Memory,
+}
+
+impl Default for DBBackend {
+    fn default() -> Self {
+        DBBackend::RocksDB
+    }
+}
+
+/// How RocksDB merges its sorted files
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+pub enum CompactionStyle {
+    /// Least space used and best reads, the default
+    Level,
+    /// Less write amplification, for nodes syncing on slow disks
+    Universal,
+}
+
+impl Default for CompactionStyle {
+    fn default() -> Self {
+        CompactionStyle::Level
+    }
+}
+
+/// The RocksDB settings are left to RocksDB when unset
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct DBConfig {
+    #[serde(default)]
+    pub backend: DBBackend,
+    /// Bytes of the block cache of every column family, each one has its own
+    /// cache so the block caches take this times the columns plus one
+    #[serde(default)]
+    pub block_cache_size: Option<usize>,
+    /// Bytes of the memtable of every column, written to disk once full
+    #[serde(default)]
+    pub write_buffer_size: Option<usize>,
+    #[serde(default)]
+    pub compaction_style: CompactionStyle,
+}
@@ -1,33 +1,165 @@
 use crate::batch::{Batch, Col, Operation};
-use crate::kvdb::{ErrorKind, KeyValueDB, Result};
-use rocksdb::{ColumnFamily, Options, WriteBatch, DB};
+use crate::config::{CompactionStyle, DBConfig};
+use crate::kvdb::{ErrorKind, KeyValueDB, KeyValueIter, Result};
+use rocksdb::{
+    BlockBasedOptions, ColumnFamily, ColumnFamilyDescriptor, DBCompactionStyle, Direction,
+    IteratorMode, Options, WriteBatch, WriteOptions, DB,
+};
+use std::fs;
 use std::ops::Range;
 use std::path::Path;
 
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Primary,
+    ReadOnly,
+    Secondary,
+}
+
 struct Inner {
     db: DB,
     cfnames: Vec<String>,
+    mode: Mode,
 }
 
 pub struct RocksDB {
     inner: Inner,
 }
 
+const SIZE_PROPERTY: &str = "rocksdb.estimate-live-data-size";
+// Pairs written per batch to a backup
+const BACKUP_BATCH_SIZE: usize = 10_000;
+
+// Options for a chain store: most reads are point lookups by hash, so every
+// table has a bloom filter, and writes come in large batches.
+fn chain_options(config: &DBConfig) -> Options {
+    let mut opts = Options::default();
+    opts.create_if_missing(true);
+    opts.create_missing_column_families(true);
+    opts.increase_parallelism(4);
+    opts.set_max_background_compactions(4);
+    opts.set_max_background_flushes(2);
+    opts.set_bytes_per_sync(1 << 20);
+    if let Some(size) = config.write_buffer_size {
+        opts.set_write_buffer_size(size);
+    }
+    opts.set_compaction_style(match config.compaction_style {
+        CompactionStyle::Level => DBCompactionStyle::Level,
+        CompactionStyle::Universal => DBCompactionStyle::Universal,
+    });
+
+    let mut block_opts = BlockBasedOptions::default();
+    block_opts.set_block_size(16 * 1024);
+    block_opts.set_bloom_filter(10, false);
+    block_opts.set_cache_index_and_filter_blocks(true);
+    // The cache is not shared, each column family gets one of this size
+    if let Some(size) = config.block_cache_size {
+        block_opts.set_lru_cache(size);
+    }
+    opts.set_block_based_table_factory(&block_opts);
+    opts
+}
+
 impl RocksDB {
     pub fn open<P: AsRef<Path>>(path: P, columns: u32) -> Self {
-        let mut opts = Options::default();
-        opts.create_if_missing(true);
-        opts.create_missing_column_families(true);
+        Self::open_with_config(path, columns, &DBConfig::default())
+    }
+
+    pub fn open_with_config<P: AsRef<Path>>(path: P, columns: u32, config: &DBConfig) -> Self {
+        let opts = chain_options(config);
         let cfnames: Vec<_> = (0..columns).map(|c| format!("c{}", c)).collect();
-        let cf_options: Vec<&str> = cfnames.iter().map(|n| n as &str).collect();
-        let db = DB::open_cf(&opts, path, &cf_options).expect("rocksdb open");
+        // The columns are tuned as the default one
+        let cf_descriptors = cfnames
+            .iter()
+            .map(|name| ColumnFamilyDescriptor::new(name.as_str(), chain_options(config)))
+            .collect();
+        let db = DB::open_cf_descriptors(&opts, path, cf_descriptors).expect("rocksdb open");
         let inner = Inner {
             db,
             cfnames: cfnames.clone(),
+            mode: Mode::Primary,
+        };
+        RocksDB { inner }
+    }
+
+    /// Opens an existing db without taking its lock, so that it can be read
+    /// while a node has it open. Writes fail, and what the node writes after
+    /// the open is not seen.
+    pub fn open_read_only<P: AsRef<Path>>(path: P, columns: u32, config: &DBConfig) -> Self {
+        let mut opts = chain_options(config);
+        opts.create_if_missing(false);
+        let cfnames: Vec<_> = (0..columns).map(|c| format!("c{}", c)).collect();
+        let db = DB::open_cf_for_read_only(&opts, path, &cfnames, false).expect("rocksdb open");
+        let inner = Inner {
+            db,
+            cfnames,
+            mode: Mode::ReadOnly,
         };
         RocksDB { inner }
     }
 
+    /// Opens a follower of the db at `primary_path`, which a node may have
+    /// open, keeping its own logs in `secondary_path`. Writes fail, and what
+    /// the node writes is seen after [catch_up](KeyValueDB::catch_up).
+    pub fn open_secondary<P: AsRef<Path>, S: AsRef<Path>>(
+        primary_path: P,
+        secondary_path: S,
+        columns: u32,
+        config: &DBConfig,
+    ) -> Self {
+        let mut opts = chain_options(config);
+        opts.create_if_missing(false);
+        // A secondary has to keep every file open to follow the primary
+        opts.set_max_open_files(-1);
+        let cfnames: Vec<_> = (0..columns).map(|c| format!("c{}", c)).collect();
+        let db = DB::open_cf_as_secondary(&opts, primary_path, secondary_path, &cfnames)
+            .expect("rocksdb open");
+        let inner = Inner {
+            db,
+            cfnames,
+            mode: Mode::Secondary,
+        };
+        RocksDB { inner }
+    }
+
+    fn check_writable(&self) -> Result<()> {
+        match self.inner.mode {
+            Mode::Primary => Ok(()),
+            Mode::ReadOnly | Mode::Secondary => {
+                Err(ErrorKind::DBError("db is open read only".to_owned()))
+            }
+        }
+    }
+
+    /// Creates a db at `path`, missing or empty, holding the `pairs`, which are
+    /// `(col, key, value)`
+    pub(crate) fn create_backup<I>(path: &Path, columns: u32, pairs: I) -> Result<()>
+    where
+        I: Iterator<Item = (Col, Vec<u8>, Vec<u8>)>,
+    {
+        let is_empty = path
+            .read_dir()
+            .map(|mut entries| entries.next().is_none())
+            .unwrap_or(true);
+        if !is_empty {
+            return Err(ErrorKind::DBError(format!(
+                "backup path {} is not empty",
+                path.display()
+            )));
+        }
+        fs::create_dir_all(path).map_err(|err| ErrorKind::DBError(err.to_string()))?;
+        let backup = RocksDB::open(path, columns);
+        let mut batch = Batch::new();
+        for (col, key, value) in pairs {
+            batch.insert(col, key, value);
+            if batch.operations.len() == BACKUP_BATCH_SIZE {
+                backup.write(batch)?;
+                batch = Batch::new();
+            }
+        }
+        backup.write(batch)
+    }
+
     fn cf_handle(&self, col: Option<u32>) -> Result<Option<ColumnFamily>> {
         if let Some(col) = col {
             self.inner
@@ -47,6 +179,7 @@ impl KeyValueDB for RocksDB {
     }
 
     fn write(&self, batch: Batch) -> Result<()> {
+        self.check_writable()?;
         let mut wb = WriteBatch::default();
         for op in batch.operations {
             match op {
@@ -90,6 +223,64 @@ impl KeyValueDB for RocksDB {
         .map(|v| v.and_then(|vi| vi.get(range.start..range.end).map(|slice| slice.to_vec())))
         .map_err(Into::into)
     }
+
+    fn iter_from<'a>(&'a self, col: Col, from: &[u8]) -> Result<KeyValueIter<'a>> {
+        let mode = IteratorMode::From(from, Direction::Forward);
+        let iter = match self.cf_handle(col)? {
+            Some(cf) => self.inner.db.iterator_cf(cf, mode)?,
+            None => self.inner.db.iterator(mode),
+        };
+        Ok(Box::new(iter.map(|(key, value)| (key.into_vec(), value.into_vec()))))
+    }
+
+    fn size(&self, col: Col) -> Result<u64> {
+        let property = match self.cf_handle(col)? {
+            Some(cf) => self.inner.db.property_value_cf(cf, SIZE_PROPERTY)?,
+            None => self.inner.db.property_value(SIZE_PROPERTY)?,
+        };
+        Ok(property.and_then(|size| size.parse().ok()).unwrap_or(0))
+    }
+
+    fn compact(&self, col: Col) -> Result<()> {
+        self.check_writable()?;
+        match self.cf_handle(col)? {
+            Some(cf) => self.inner.db.compact_range_cf(cf, None, None),
+            None => self.inner.db.compact_range(None, None),
+        }
+        Ok(())
+    }
+
+    fn catch_up(&self) -> Result<()> {
+        if self.inner.mode == Mode::Secondary {
+            self.inner.db.try_catch_up_with_primary()?;
+        }
+        Ok(())
+    }
+
+    fn sync(&self) -> Result<()> {
+        if self.inner.mode != Mode::Primary {
+            return Ok(());
+        }
+        // A synced write syncs the log holding the writes before it
+        let mut opts = WriteOptions::default();
+        opts.set_sync(true);
+        self.inner.db.write_opt(WriteBatch::default(), &opts)?;
+        Ok(())
+    }
+
+    fn backup(&self, path: &Path) -> Result<()> {
+        // Every column is read from the same snapshot
+        let snapshot = self.inner.db.snapshot();
+        let mut iters = vec![(None, snapshot.iterator(IteratorMode::Start))];
+        for col in 0..self.cols() {
+            let cf = self.cf_handle(Some(col))?.expect("column family");
+            iters.push((Some(col), snapshot.iterator_cf(cf, IteratorMode::Start)?));
+        }
+        let pairs = iters.into_iter().flat_map(|(col, iter)| {
+            iter.map(move |(key, value)| (col, key.into_vec(), value.into_vec()))
+        });
+        RocksDB::create_backup(path, self.cols(), pairs)
+    }
 }
 
 #[cfg(test)]
@@ -167,4 +358,134 @@ mod tests {
             db.partial_read(None, &[0, 0], &(1..4)).unwrap()
         );
     }
+
+    #[test]
+    fn write_and_iter_from() {
+        let tmp_dir = tempfile::Builder::new()
+            .prefix("write_and_iter_from")
+            .tempdir()
+            .unwrap();
+        let db = RocksDB::open(tmp_dir, 2);
+        let mut batch = Batch::default();
+        batch.insert(Some(1), vec![2, 0], vec![2]);
+        batch.insert(Some(1), vec![0, 1], vec![0]);
+        batch.insert(Some(1), vec![1, 0], vec![1]);
+        batch.insert(Some(0), vec![1, 1], vec![3]);
+        db.write(batch).unwrap();
+
+        let pairs: Vec<_> = db.iter_from(Some(1), &[1]).unwrap().collect();
+        assert_eq!(pairs, vec![(vec![1, 0], vec![1]), (vec![2, 0], vec![2])]);
+        assert!(db.iter_from(Some(2), &[]).is_err());
+    }
+
+    #[test]
+    fn open_read_only() {
+        let tmp_dir = tempfile::Builder::new()
+            .prefix("open_read_only")
+            .tempdir()
+            .unwrap();
+        let db = RocksDB::open(tmp_dir.path(), 2);
+        let mut batch = Batch::default();
+        batch.insert(Some(1), vec![1], vec![1]);
+        db.write(batch).unwrap();
+
+        // Along with the writable instance
+        let reader = RocksDB::open_read_only(tmp_dir.path(), 2, &DBConfig::default());
+        assert_eq!(Some(vec![1]), reader.read(Some(1), &[1]).unwrap());
+        let mut batch = Batch::default();
+        batch.insert(Some(1), vec![2], vec![2]);
+        assert!(reader.write(batch).is_err());
+        assert!(reader.compact(Some(1)).is_err());
+    }
+
+    #[test]
+    fn open_secondary() {
+        let tmp_dir = tempfile::Builder::new()
+            .prefix("open_secondary")
+            .tempdir()
+            .unwrap();
+        let primary_path = tmp_dir.path().join("primary");
+        let db = RocksDB::open(&primary_path, 2);
+        let mut batch = Batch::default();
+        batch.insert(Some(1), vec![1], vec![1]);
+        db.write(batch).unwrap();
+
+        let follower = RocksDB::open_secondary(
+            &primary_path,
+            tmp_dir.path().join("secondary"),
+            2,
+            &DBConfig::default(),
+        );
+        assert_eq!(Some(vec![1]), follower.read(Some(1), &[1]).unwrap());
+        let mut batch = Batch::default();
+        batch.insert(Some(1), vec![2], vec![2]);
+        assert!(follower.write(batch.clone()).is_err());
+
+        db.write(batch).unwrap();
+        follower.catch_up().unwrap();
+        assert_eq!(Some(vec![2]), follower.read(Some(1), &[2]).unwrap());
+    }
+
+    #[test]
+    fn open_with_config() {
+        let tmp_dir = tempfile::Builder::new()
+            .prefix("open_with_config")
+            .tempdir()
+            .unwrap();
+        let config = DBConfig {
+            block_cache_size: Some(1 << 20),
+            write_buffer_size: Some(1 << 20),
+            compaction_style: CompactionStyle::Universal,
+            ..Default::default()
+        };
+        let db = RocksDB::open_with_config(tmp_dir, 2, &config);
+        let mut batch = Batch::default();
+        batch.insert(Some(1), vec![1], vec![1]);
+        db.write(batch).unwrap();
+        assert_eq!(Some(vec![1]), db.read(Some(1), &[1]).unwrap());
+    }
+
+    #[test]
+    fn write_and_backup() {
+        let tmp_dir = tempfile::Builder::new()
+            .prefix("write_and_backup")
+            .tempdir()
+            .unwrap();
+        let db = RocksDB::open(tmp_dir.path().join("db"), 2);
+        let mut batch = Batch::default();
+        batch.insert(None, vec![0], vec![0]);
+        batch.insert(Some(1), vec![1], vec![1]);
+        db.write(batch).unwrap();
+
+        let backup_path = tmp_dir.path().join("backup");
+        db.backup(&backup_path).unwrap();
+        assert!(db.backup(&backup_path).is_err());
+        let mut batch = Batch::default();
+        batch.insert(Some(1), vec![2], vec![2]);
+        db.write(batch).unwrap();
+
+        let backup = RocksDB::open(&backup_path, 2);
+        assert_eq!(Some(vec![0]), backup.read(None, &[0]).unwrap());
+        assert_eq!(Some(vec![1]), backup.read(Some(1), &[1]).unwrap());
+        assert_eq!(None, backup.read(Some(1), &[2]).unwrap());
+    }
+
+    #[test]
+    fn write_compact_and_size() {
+        let tmp_dir = tempfile::Builder::new()
+            .prefix("write_compact_and_size")
+            .tempdir()
+            .unwrap();
+        let db = RocksDB::open(tmp_dir, 2);
+        let mut batch = Batch::default();
+        for i in 0..100u8 {
+            batch.insert(Some(1), vec![i], vec![i; 100]);
+        }
+        db.write(batch).unwrap();
+
+        db.compact(Some(1)).unwrap();
+        assert!(db.size(Some(1)).unwrap() > 0);
+        assert_eq!(db.size(Some(0)).unwrap(), 0);
+        assert!(db.compact(Some(2)).is_err());
+    }
 }
@@ -3,10 +3,12 @@ use bincode::Error as BcError;
 use rocksdb::Error as RdbError;
 use std::error::Error as StdError;
 use std::ops::Range;
+use std::path::Path;
 use std::result;
 
 pub type Error = ErrorKind;
 pub type Result<T> = result::Result<T, Error>;
+pub type KeyValueIter<'a> = Box<dyn Iterator<Item = (Vec<u8>, Vec<u8>)> + 'a>;
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum ErrorKind {
@@ -27,11 +29,45 @@ impl From<RdbError> for Error {
 }
 
 pub trait KeyValueDB: Sync + Send {
+    /// Applies all the operations of `batch` atomically, nothing is written
+    /// when an error is returned.
     fn write(&self, batch: Batch) -> Result<()>;
     fn read(&self, col: Col, key: &[u8]) -> Result<Option<Vec<u8>>>;
     fn len(&self, col: Col, key: &[u8]) -> Result<Option<usize>>;
     fn partial_read(&self, col: Col, key: &[u8], range: &Range<usize>) -> Result<Option<Vec<u8>>>;
     fn cols(&self) -> u32;
+    /// Visits the pairs of `col` in key order, starting at the first key not
+    /// less than `from`.
+    fn iter_from<'a>(&'a self, col: Col, from: &[u8]) -> Result<KeyValueIter<'a>>;
+    /// Approximate number of bytes stored in `col`
+    fn size(&self, col: Col) -> Result<u64>;
+    /// Reclaims the space of the deleted and overwritten pairs of `col`
+    fn compact(&self, col: Col) -> Result<()>;
+    /// Writes a consistent copy of the whole db, as it is when called, to a
+    /// new RocksDB at `path`, while the db stays in use.
+    fn backup(&self, path: &Path) -> Result<()>;
+    /// Reads what the primary instance wrote since the open or the last call,
+    /// for a follower of a db another process writes
+    fn catch_up(&self) -> Result<()> {
+        Ok(())
+    }
+    /// Makes the writes done so far durable, they are otherwise left to the
+    /// OS to write out
+    fn sync(&self) -> Result<()> {
+        Ok(())
+    }
+    /// Visits the pairs of `col` whose key starts with `prefix`, in key order
+    fn iter_prefix<'a>(&'a self, col: Col, prefix: &[u8]) -> Result<KeyValueIter<'a>> {
+        let prefix = prefix.to_vec();
+        let iter = self.iter_from(col, &prefix)?;
+        Ok(Box::new(iter.take_while(move |(key, _)| key.starts_with(&prefix))))
+    }
+    /// Visits the pairs of `col` with a key in `from..to`, in key order
+    fn iter_range<'a>(&'a self, col: Col, from: &[u8], to: &[u8]) -> Result<KeyValueIter<'a>> {
+        let to = to.to_vec();
+        let iter = self.iter_from(col, from)?;
+        Ok(Box::new(iter.take_while(move |(key, _)| key.as_slice() < to.as_slice())))
+    }
     fn batch(&self) -> Batch {
         Batch::new()
     }
@@ -4,6 +4,7 @@
 //! which provides key-value store interface
 
 pub mod batch;
+pub mod config;
 pub mod diskdb;
 pub mod kvdb;
 pub mod memorydb;
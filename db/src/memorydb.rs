@@ -1,29 +1,93 @@
 use crate::batch::{Batch, Col, Operation};
-use crate::kvdb::{ErrorKind, KeyValueDB, Result};
+use crate::diskdb::RocksDB;
+use crate::kvdb::{ErrorKind, KeyValueDB, KeyValueIter, Result};
 use ckb_util::RwLock;
 use fnv::FnvHashMap;
-use std::ops::Range;
+use std::collections::BTreeMap;
+use std::ops::{Bound, Range};
+use std::path::Path;
+use std::sync::Arc;
 
 pub type MemoryKey = Vec<u8>;
 pub type MemoryValue = Vec<u8>;
-pub type MemoryTable = FnvHashMap<Col, FnvHashMap<MemoryKey, MemoryValue>>;
+/// Keys are ordered bytewise, as in RocksDB. Columns are shared with the
+/// snapshots taken before a write, which copies them.
+pub type MemoryTable = FnvHashMap<Col, Arc<BTreeMap<MemoryKey, MemoryValue>>>;
 
 #[derive(Default, Debug)]
 pub struct MemoryKeyValueDB {
     db: RwLock<MemoryTable>,
 }
 
+/// Read only view of a [MemoryKeyValueDB] at the time it was taken, which
+/// later writes do not change
+#[derive(Clone, Debug)]
+pub struct MemorySnapshot {
+    table: MemoryTable,
+}
+
 impl MemoryKeyValueDB {
     pub fn open(cols: usize) -> MemoryKeyValueDB {
         let mut table = FnvHashMap::with_capacity_and_hasher(cols, Default::default());
-        table.insert(None, FnvHashMap::default());
+        table.insert(None, Arc::default());
         for idx in 0..cols {
-            table.insert(Some(idx as u32), FnvHashMap::default());
+            table.insert(Some(idx as u32), Arc::default());
         }
         MemoryKeyValueDB {
             db: RwLock::new(table),
         }
     }
+
+    pub fn snapshot(&self) -> MemorySnapshot {
+        MemorySnapshot {
+            table: self.db.read().clone(),
+        }
+    }
+}
+
+impl MemorySnapshot {
+    pub fn read(&self, col: Col, key: &[u8]) -> Result<Option<MemoryValue>> {
+        Ok(column(&self.table, col)?.get(key).cloned())
+    }
+
+    pub fn iter_from(&self, col: Col, from: &[u8]) -> Result<KeyValueIter<'static>> {
+        Ok(iter_column(column(&self.table, col)?.clone(), from))
+    }
+}
+
+fn column(table: &MemoryTable, col: Col) -> Result<&Arc<BTreeMap<MemoryKey, MemoryValue>>> {
+    table
+        .get(&col)
+        .ok_or_else(|| ErrorKind::DBError(format!("column {:?} not found ", col)))
+}
+
+// Visits the pairs from `from` as they are in `map`, without holding the DB
+// lock, the way a RocksDB iterator reads from an implicit snapshot
+fn iter_column(map: Arc<BTreeMap<MemoryKey, MemoryValue>>, from: &[u8]) -> KeyValueIter<'static> {
+    Box::new(ColumnIter {
+        map,
+        next: Bound::Included(from.to_vec()),
+    })
+}
+
+// Owns the column it visits, and resumes after the last key it returned
+struct ColumnIter {
+    map: Arc<BTreeMap<MemoryKey, MemoryValue>>,
+    next: Bound<MemoryKey>,
+}
+
+impl Iterator for ColumnIter {
+    type Item = (MemoryKey, MemoryValue);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (key, value) = self
+            .map
+            .range((self.next.clone(), Bound::Unbounded))
+            .next()
+            .map(|(key, value)| (key.clone(), value.clone()))?;
+        self.next = Bound::Excluded(key.clone());
+        Some((key, value))
+    }
 }
 
 impl KeyValueDB for MemoryKeyValueDB {
@@ -33,15 +97,26 @@ impl KeyValueDB for MemoryKeyValueDB {
 
     fn write(&self, batch: Batch) -> Result<()> {
         let mut db = self.db.write();
+        // Checked beforehand so that a bad batch writes nothing
+        if let Some(col) = batch
+            .operations
+            .iter()
+            .map(|op| match op {
+                Operation::Insert { col, .. } | Operation::Delete { col, .. } => col,
+            })
+            .find(|col| !db.contains_key(col))
+        {
+            return Err(ErrorKind::DBError(format!("column {:?} not found ", col)));
+        }
         batch.operations.into_iter().for_each(|op| match op {
             Operation::Insert { col, key, value } => {
                 if let Some(map) = db.get_mut(&col) {
-                    map.insert(key, value);
+                    Arc::make_mut(map).insert(key, value);
                 }
             }
             Operation::Delete { col, key } => {
                 if let Some(map) = db.get_mut(&col) {
-                    map.remove(&key);
+                    Arc::make_mut(map).remove(&key);
                 }
             }
         });
@@ -49,12 +124,7 @@ impl KeyValueDB for MemoryKeyValueDB {
     }
 
     fn read(&self, col: Col, key: &[u8]) -> Result<Option<MemoryValue>> {
-        let db = self.db.read();
-
-        match db.get(&col) {
-            None => Err(ErrorKind::DBError(format!("column {:?} not found ", col))),
-            Some(map) => Ok(map.get(key).cloned()),
-        }
+        Ok(column(&self.db.read(), col)?.get(key).cloned())
     }
 
     fn len(&self, col: Col, key: &[u8]) -> Result<Option<usize>> {
@@ -77,6 +147,42 @@ impl KeyValueDB for MemoryKeyValueDB {
                 .map(|slice| slice.to_vec())),
         }
     }
+
+    fn iter_from<'a>(&'a self, col: Col, from: &[u8]) -> Result<KeyValueIter<'a>> {
+        let map = Arc::clone(column(&self.db.read(), col)?);
+        Ok(iter_column(map, from))
+    }
+
+    fn size(&self, col: Col) -> Result<u64> {
+        let db = self.db.read();
+
+        match db.get(&col) {
+            None => Err(ErrorKind::DBError(format!("column {:?} not found ", col))),
+            Some(map) => Ok(map
+                .iter()
+                .map(|(key, value)| (key.len() + value.len()) as u64)
+                .sum()),
+        }
+    }
+
+    fn compact(&self, col: Col) -> Result<()> {
+        if self.db.read().contains_key(&col) {
+            Ok(())
+        } else {
+            Err(ErrorKind::DBError(format!("column {:?} not found ", col)))
+        }
+    }
+
+    fn backup(&self, path: &Path) -> Result<()> {
+        let table = self.db.read().clone();
+        let pairs = table.into_iter().flat_map(|(col, map)| {
+            Arc::try_unwrap(map)
+                .unwrap_or_else(|map| (*map).clone())
+                .into_iter()
+                .map(move |(key, value)| (col, key, value))
+        });
+        RocksDB::create_backup(path, self.cols(), pairs)
+    }
 }
 
 #[cfg(test)]
@@ -101,6 +207,16 @@ mod tests {
         assert!(db.read(Some(2), &[0, 0]).is_err());
     }
 
+    #[test]
+    fn write_is_atomic() {
+        let db = MemoryKeyValueDB::open(2);
+        let mut batch = Batch::default();
+        batch.insert(Some(1), vec![1], vec![1]);
+        batch.insert(Some(2), vec![2], vec![2]);
+        assert!(db.write(batch).is_err());
+        assert_eq!(None, db.read(Some(1), &[1]).unwrap());
+    }
+
     #[test]
     fn write_and_len() {
         let db = MemoryKeyValueDB::open(2);
@@ -141,4 +257,119 @@ mod tests {
             db.partial_read(None, &[0, 0], &(1..4)).unwrap()
         );
     }
+
+    #[test]
+    fn write_and_iter_from() {
+        let db = MemoryKeyValueDB::open(2);
+        let mut batch = Batch::default();
+        batch.insert(Some(1), vec![2, 0], vec![2]);
+        batch.insert(Some(1), vec![0, 1], vec![0]);
+        batch.insert(Some(1), vec![1, 0], vec![1]);
+        batch.insert(Some(0), vec![1, 1], vec![3]);
+        db.write(batch).unwrap();
+
+        let pairs: Vec<_> = db.iter_from(Some(1), &[1]).unwrap().collect();
+        assert_eq!(pairs, vec![(vec![1, 0], vec![1]), (vec![2, 0], vec![2])]);
+        assert!(db.iter_from(Some(2), &[]).is_err());
+    }
+
+    #[test]
+    fn write_and_iter_prefix_and_range() {
+        let db = MemoryKeyValueDB::open(2);
+        let mut batch = Batch::default();
+        batch.insert(Some(1), vec![1, 2], vec![2]);
+        batch.insert(Some(1), vec![0, 1], vec![0]);
+        batch.insert(Some(1), vec![1, 0], vec![1]);
+        batch.insert(Some(1), vec![2, 0], vec![3]);
+        db.write(batch).unwrap();
+
+        let keys = |iter: KeyValueIter| iter.map(|(key, _)| key).collect::<Vec<_>>();
+        assert_eq!(
+            keys(db.iter_prefix(Some(1), &[1]).unwrap()),
+            vec![vec![1, 0], vec![1, 2]]
+        );
+        assert!(keys(db.iter_prefix(Some(1), &[3]).unwrap()).is_empty());
+        assert_eq!(
+            keys(db.iter_range(Some(1), &[0, 1], &[1, 2]).unwrap()),
+            vec![vec![0, 1], vec![1, 0]]
+        );
+        assert!(db.iter_range(Some(2), &[], &[1]).is_err());
+    }
+
+    #[test]
+    fn keys_are_ordered_bytewise() {
+        let db = MemoryKeyValueDB::open(1);
+        let mut batch = Batch::default();
+        batch.insert(Some(0), vec![1, 0], vec![2]);
+        batch.insert(Some(0), vec![0, 255], vec![0]);
+        batch.insert(Some(0), vec![1], vec![1]);
+        batch.insert(Some(0), vec![255], vec![3]);
+        db.write(batch).unwrap();
+
+        let keys: Vec<_> = db
+            .iter_from(Some(0), &[])
+            .unwrap()
+            .map(|(key, _)| key)
+            .collect();
+        assert_eq!(keys, vec![vec![0, 255], vec![1], vec![1, 0], vec![255]]);
+    }
+
+    #[test]
+    fn snapshot_is_consistent() {
+        let db = MemoryKeyValueDB::open(2);
+        let mut batch = Batch::default();
+        batch.insert(Some(1), vec![1], vec![1]);
+        batch.insert(Some(1), vec![2], vec![2]);
+        db.write(batch).unwrap();
+
+        let snapshot = db.snapshot();
+        let iter = db.iter_from(Some(1), &[]).unwrap();
+        let mut batch = Batch::default();
+        batch.delete(Some(1), vec![1]);
+        batch.insert(Some(1), vec![3], vec![3]);
+        db.write(batch).unwrap();
+
+        assert_eq!(Some(vec![1]), snapshot.read(Some(1), &[1]).unwrap());
+        assert_eq!(None, snapshot.read(Some(1), &[3]).unwrap());
+        assert_eq!(None, db.read(Some(1), &[1]).unwrap());
+        let pairs: Vec<_> = snapshot.iter_from(Some(1), &[]).unwrap().collect();
+        assert_eq!(pairs, vec![(vec![1], vec![1]), (vec![2], vec![2])]);
+        // Iterators read the db as it was when created
+        assert_eq!(iter.count(), 2);
+        assert!(snapshot.read(Some(2), &[1]).is_err());
+    }
+
+    #[test]
+    fn write_and_size() {
+        let db = MemoryKeyValueDB::open(2);
+        let mut batch = Batch::default();
+        batch.insert(Some(0), vec![0], vec![0, 0]);
+        batch.insert(Some(1), vec![1, 1], vec![1, 1, 1]);
+        batch.insert(Some(1), vec![2], vec![2]);
+        db.write(batch).unwrap();
+
+        assert_eq!(db.size(Some(0)).unwrap(), 3);
+        assert_eq!(db.size(Some(1)).unwrap(), 7);
+        assert!(db.compact(Some(1)).is_ok());
+        assert!(db.size(Some(2)).is_err());
+    }
+
+    #[test]
+    fn write_and_backup() {
+        let tmp_dir = tempfile::Builder::new()
+            .prefix("memory_write_and_backup")
+            .tempdir()
+            .unwrap();
+        let db = MemoryKeyValueDB::open(2);
+        let mut batch = Batch::default();
+        batch.insert(None, vec![0], vec![0]);
+        batch.insert(Some(1), vec![1], vec![1]);
+        db.write(batch).unwrap();
+
+        let backup_path = tmp_dir.path().join("backup");
+        db.backup(&backup_path).unwrap();
+        let backup = RocksDB::open(&backup_path, 2);
+        assert_eq!(Some(vec![0]), backup.read(None, &[0]).unwrap());
+        assert_eq!(Some(vec![1]), backup.read(Some(1), &[1]).unwrap());
+    }
 }
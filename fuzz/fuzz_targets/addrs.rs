@@ -0,0 +1,26 @@
+#![no_main]
+use ckb_network::peer_store::{PeerStore, SqlitePeerStore};
+use ckb_network::{random_peer_id, Multiaddr};
+use libfuzzer_sys::fuzz_target;
+use std::cmp;
+
+// The listen addresses a peer gossips in its identify message, each one
+// behind its length, stored in the peer store as the identify service does
+fuzz_target!(|data: &[u8]| {
+    let mut addrs = Vec::new();
+    let mut rest = data;
+    while let Some((len, tail)) = rest.split_first() {
+        let len = cmp::min(usize::from(*len), tail.len());
+        if let Ok(addr) = Multiaddr::from_bytes(tail[..len].to_vec()) {
+            let _ = addr.to_string();
+            addrs.push(addr);
+        }
+        rest = &tail[len..];
+    }
+    let peer_id = random_peer_id().expect("random peer id");
+    let mut peer_store = SqlitePeerStore::default();
+    let count = addrs.len() as u32;
+    let _ = peer_store.add_discovered_addresses(&peer_id, addrs);
+    let _ = peer_store.peer_addrs(&peer_id, count);
+    let _ = peer_store.peers_to_attempt(count);
+});
@@ -0,0 +1,12 @@
+#![no_main]
+use ckb_core::block::Block;
+use flatbuffers::get_root;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let block: Block = get_root::<ckb_protocol::Block>(data).into();
+    let _ = block.header().hash();
+    for transaction in block.commit_transactions() {
+        let _ = transaction.hash();
+    }
+});
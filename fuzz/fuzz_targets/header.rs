@@ -0,0 +1,9 @@
+#![no_main]
+use ckb_core::header::Header;
+use flatbuffers::get_root;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let header: Header = get_root::<ckb_protocol::Header>(data).into();
+    let _ = header.hash();
+});
@@ -0,0 +1,42 @@
+#![no_main]
+use ckb_sync::{RELAY_PROTOCOL_ID, SYNC_PROTOCOL_ID, TIME_PROTOCOL_ID};
+use ckb_test::{Net, SimConfig};
+use libfuzzer_sys::fuzz_target;
+use std::time::Duration;
+
+// Longer than the latency of the simulation, so that the message is handled
+// before the next input
+const DELIVERY: Duration = Duration::from_millis(200);
+
+thread_local! {
+    // Node 1 sends the inputs to node 0, which has a chain to serve
+    static NET: Net = {
+        let net = Net::simulated(2, SimConfig::default());
+        net[0].mine_blocks(3);
+        net.connect(1, 0);
+        net.assert_converged(3);
+        net
+    };
+}
+
+// The first byte of the input picks the protocol handler receiving the rest
+// from a connected peer
+fuzz_target!(|data: &[u8]| {
+    let (protocol, message) = match data.split_first() {
+        Some((protocol, message)) => (*protocol, message),
+        None => return,
+    };
+    let protocol = match protocol % 3 {
+        0 => SYNC_PROTOCOL_ID,
+        1 => RELAY_PROTOCOL_ID,
+        _ => TIME_PROTOCOL_ID,
+    };
+    NET.with(|net| {
+        // A message may get node 1 banned
+        if !net[1].is_connected(&net[0]) {
+            net.connect(1, 0);
+        }
+        net[1].transport().broadcast(protocol, message);
+        net.run_for(DELIVERY);
+    });
+});
@@ -0,0 +1,87 @@
+#![no_main]
+use ckb_core::header::Header;
+use ckb_core::transaction::{IndexTransaction, ProposalShortId, Transaction};
+use ckb_core::uncle::UncleBlock;
+use ckb_protocol::{FlatbuffersVectorIterator, RelayMessage, RelayPayload};
+use flatbuffers::get_root;
+use libfuzzer_sys::fuzz_target;
+use numext_fixed_hash::H256;
+
+// Decodes the message as the relayer reads it from a peer, with the chain
+// types of its payload
+fuzz_target!(|data: &[u8]| {
+    let message = get_root::<RelayMessage>(data);
+    match message.payload_type() {
+        RelayPayload::CompactBlock => {
+            if let Some(compact_block) = message.payload_as_compact_block() {
+                let _ = compact_block.nonce();
+                let _: Option<Header> = compact_block.header().map(Into::into);
+                if let Some(short_ids) = compact_block.short_ids() {
+                    let _: Vec<_> = FlatbuffersVectorIterator::new(short_ids)
+                        .map(|bytes| bytes.seq())
+                        .collect();
+                }
+                if let Some(prefilled) = compact_block.prefilled_transactions() {
+                    let _: Vec<IndexTransaction> = FlatbuffersVectorIterator::new(prefilled)
+                        .map(Into::into)
+                        .collect();
+                }
+                if let Some(uncles) = compact_block.uncles() {
+                    let _: Vec<UncleBlock> = FlatbuffersVectorIterator::new(uncles)
+                        .map(Into::into)
+                        .collect();
+                }
+                proposals(compact_block.proposal_transactions());
+            }
+        }
+        RelayPayload::Transaction => {
+            if let Some(transaction) = message.payload_as_transaction() {
+                let _: Transaction = transaction.into();
+            }
+        }
+        RelayPayload::GetBlockTransactions => {
+            if let Some(get_block_transactions) = message.payload_as_get_block_transactions() {
+                let _: Option<H256> = get_block_transactions.hash().map(Into::into);
+                if let Some(indexes) = get_block_transactions.indexes() {
+                    let _: Vec<u32> = FlatbuffersVectorIterator::new(indexes).collect();
+                }
+            }
+        }
+        RelayPayload::BlockTransactions => {
+            if let Some(block_transactions) = message.payload_as_block_transactions() {
+                let _: Option<H256> = block_transactions.hash().map(Into::into);
+                transactions(block_transactions.transactions());
+            }
+        }
+        RelayPayload::GetBlockProposal => {
+            if let Some(get_block_proposal) = message.payload_as_get_block_proposal() {
+                let _ = get_block_proposal.block_number();
+                proposals(get_block_proposal.proposal_transactions());
+            }
+        }
+        RelayPayload::BlockProposal => {
+            if let Some(block_proposal) = message.payload_as_block_proposal() {
+                transactions(block_proposal.transactions());
+            }
+        }
+        RelayPayload::NONE => {}
+    }
+});
+
+fn proposals(short_ids: Option<&[ckb_protocol::ProposalShortId]>) {
+    if let Some(short_ids) = short_ids {
+        let _: Vec<ProposalShortId> = short_ids.iter().map(Into::into).collect();
+    }
+}
+
+fn transactions<'a>(
+    transactions: Option<
+        flatbuffers::Vector<'a, flatbuffers::ForwardsUOffset<ckb_protocol::Transaction<'a>>>,
+    >,
+) {
+    if let Some(transactions) = transactions {
+        let _: Vec<Transaction> = FlatbuffersVectorIterator::new(transactions)
+            .map(Into::into)
+            .collect();
+    }
+}
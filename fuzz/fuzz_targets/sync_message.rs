@@ -0,0 +1,79 @@
+#![no_main]
+use ckb_core::block::Block;
+use ckb_core::header::Header;
+use ckb_core::transaction::Transaction;
+use ckb_protocol::{FlatbuffersVectorIterator, SyncMessage, SyncPayload};
+use flatbuffers::get_root;
+use libfuzzer_sys::fuzz_target;
+use numext_fixed_hash::H256;
+
+// Decodes the message as the synchronizer reads it from a peer, with the
+// chain types of its payload
+fuzz_target!(|data: &[u8]| {
+    let message = get_root::<SyncMessage>(data);
+    match message.payload_type() {
+        SyncPayload::GetHeaders => {
+            if let Some(get_headers) = message.payload_as_get_headers() {
+                let _ = get_headers.version();
+                if let Some(hashes) = get_headers.block_locator_hashes() {
+                    let _: Vec<H256> = hashes.iter().map(Into::into).collect();
+                }
+                let _: Option<H256> = get_headers.hash_stop().map(Into::into);
+            }
+        }
+        SyncPayload::Headers => {
+            if let Some(headers) = message.payload_as_headers() {
+                let _ = headers.pruned_below();
+                if let Some(headers) = headers.headers() {
+                    let _: Vec<Header> = FlatbuffersVectorIterator::new(headers)
+                        .map(Into::into)
+                        .collect();
+                }
+            }
+        }
+        SyncPayload::GetBlocks => {
+            if let Some(hashes) = message
+                .payload_as_get_blocks()
+                .and_then(|get_blocks| get_blocks.block_hashes())
+            {
+                let _: Vec<H256> = hashes.iter().map(Into::into).collect();
+            }
+        }
+        SyncPayload::Block => {
+            if let Some(block) = message.payload_as_block() {
+                let _: Block = block.into();
+            }
+        }
+        SyncPayload::SetFilter => {
+            if let Some(set_filter) = message.payload_as_set_filter() {
+                let _ = set_filter.filter();
+                let _ = set_filter.num_hashes();
+                let _ = set_filter.hash_seed();
+            }
+        }
+        SyncPayload::AddFilter => {
+            if let Some(add_filter) = message.payload_as_add_filter() {
+                let _ = add_filter.filter();
+            }
+        }
+        SyncPayload::FilteredBlock => {
+            if let Some(filtered_block) = message.payload_as_filtered_block() {
+                let _: Option<Header> = filtered_block.header().map(Into::into);
+                if let Some(transactions) = filtered_block.transactions() {
+                    let _: Vec<Transaction> = FlatbuffersVectorIterator::new(transactions)
+                        .map(Into::into)
+                        .collect();
+                }
+                if let Some(proof) = filtered_block.proof() {
+                    if let Some(indices) = proof.indices() {
+                        let _: Vec<u32> = FlatbuffersVectorIterator::new(indices).collect();
+                    }
+                    if let Some(lemmas) = proof.lemmas() {
+                        let _: Vec<H256> = lemmas.iter().map(Into::into).collect();
+                    }
+                }
+            }
+        }
+        SyncPayload::ClearFilter | SyncPayload::NONE => {}
+    }
+});
@@ -0,0 +1,9 @@
+#![no_main]
+use ckb_core::transaction::Transaction;
+use flatbuffers::get_root;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let transaction: Transaction = get_root::<ckb_protocol::Transaction>(data).into();
+    let _ = transaction.hash();
+});
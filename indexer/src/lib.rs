@@ -0,0 +1,49 @@
+//! Index of live cells and transactions by lock script hash.
+//!
+//! The indexer keeps its own database next to the chain database and follows
+//! the main chain through the new tip and switch fork notifications.
+//!
+//! The locks registered as watched accounts also have their [Balance] kept up
+//! to date block by block, a watch-only wallet.
+
+mod service;
+mod store;
+
+pub use crate::service::{IndexerController, IndexerService};
+pub use crate::store::IndexerStore;
+
+use ckb_core::transaction::{CellOutput, OutPoint};
+use ckb_core::{BlockNumber, Capacity};
+use ckb_db::batch::Col;
+use numext_fixed_hash::H256;
+use serde_derive::{Deserialize, Serialize};
+
+pub const COLUMNS: u32 = 5;
+pub const COLUMN_META: Col = Some(0);
+pub const COLUMN_LIVE_CELLS: Col = Some(1);
+pub const COLUMN_LOCK_HASH_CELLS: Col = Some(2);
+pub const COLUMN_LOCK_HASH_TRANSACTIONS: Col = Some(3);
+pub const COLUMN_WATCHED: Col = Some(4);
+
+/// An unspent output on the main chain
+#[derive(Clone, Serialize, Deserialize, PartialEq, Eq, Debug)]
+pub struct LiveCell {
+    pub out_point: OutPoint,
+    pub output: CellOutput,
+    pub block_number: BlockNumber,
+}
+
+/// The live cells of a watched lock on the main chain
+#[derive(Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, Debug)]
+pub struct Balance {
+    pub capacity: Capacity,
+    pub cells_count: u64,
+}
+
+/// Location of a transaction which creates or spends a cell of a lock
+#[derive(Clone, Serialize, Deserialize, PartialEq, Eq, Debug)]
+pub struct TransactionPoint {
+    pub tx_hash: H256,
+    pub block_number: BlockNumber,
+    pub index: usize,
+}
@@ -0,0 +1,261 @@
+use crate::{Balance, IndexerStore, LiveCell, TransactionPoint};
+use ckb_core::service::SIGNAL_CHANNEL_SIZE;
+use ckb_core::transaction::OutPoint;
+use ckb_core::{BlockNumber, Capacity};
+use ckb_db::kvdb::KeyValueDB;
+use ckb_notify::{EventKind, NotifyController};
+use ckb_shared::index::ChainIndex;
+use ckb_shared::shared::{ChainProvider, Shared};
+use crossbeam_channel::{self, select, Receiver};
+use log::{debug, error, info};
+use numext_fixed_hash::H256;
+use std::thread;
+use stop_handler::{SignalSender, StopHandler};
+
+const INDEXER_SUBSCRIBER: &str = "indexer";
+
+pub struct IndexerController<T: KeyValueDB> {
+    store: IndexerStore<T>,
+    stop: StopHandler<()>,
+}
+
+impl<T: KeyValueDB> Clone for IndexerController<T> {
+    fn clone(&self) -> Self {
+        IndexerController {
+            store: self.store.clone(),
+            stop: self.stop.clone(),
+        }
+    }
+}
+
+impl<T: KeyValueDB> Drop for IndexerController<T> {
+    fn drop(&mut self) {
+        self.stop.try_send();
+    }
+}
+
+impl<T: 'static + KeyValueDB> IndexerController<T> {
+    /// Stops indexing the new blocks and syncs the index, which can still
+    /// be read
+    pub fn stop(&self) {
+        self.stop.stop();
+        self.store.sync();
+    }
+
+    pub fn get_live_cells(&self, lock_hash: &H256) -> Vec<LiveCell> {
+        self.store.get_live_cells(lock_hash)
+    }
+
+    pub fn get_live_cells_in(
+        &self,
+        lock_hash: &H256,
+        from: BlockNumber,
+        to: BlockNumber,
+    ) -> Vec<LiveCell> {
+        self.store.get_live_cells_in(lock_hash, from, to)
+    }
+
+    pub fn get_transactions(&self, lock_hash: &H256) -> Vec<TransactionPoint> {
+        self.store.get_transactions(lock_hash)
+    }
+
+    pub fn get_transactions_page(
+        &self,
+        lock_hash: &H256,
+        skip: usize,
+        count: usize,
+    ) -> Vec<TransactionPoint> {
+        self.store.get_transactions_page(lock_hash, skip, count)
+    }
+
+    pub fn get_capacity(&self, lock_hash: &H256) -> Capacity {
+        self.store.get_capacity(lock_hash)
+    }
+
+    /// Registers the lock as a watched account, returns false when it is
+    /// already
+    pub fn watch(&self, lock_hash: &H256) -> bool {
+        self.store.watch(lock_hash)
+    }
+
+    pub fn unwatch(&self, lock_hash: &H256) -> bool {
+        self.store.unwatch(lock_hash)
+    }
+
+    /// The balance of a watched account, None when the lock is not watched
+    pub fn get_balance(&self, lock_hash: &H256) -> Option<Balance> {
+        self.store.get_balance(lock_hash)
+    }
+
+    pub fn get_watched(&self) -> Vec<(H256, Balance)> {
+        self.store.get_watched()
+    }
+
+    /// The block indexed last, None until the genesis block is indexed
+    pub fn tip(&self) -> Option<(BlockNumber, H256)> {
+        self.store.tip()
+    }
+}
+
+pub struct IndexerService<CI, T: KeyValueDB> {
+    shared: Shared<CI>,
+    store: IndexerStore<T>,
+}
+
+impl<CI: ChainIndex + 'static, T: 'static + KeyValueDB> IndexerService<CI, T> {
+    pub fn new(shared: Shared<CI>, db: T) -> Self {
+        IndexerService {
+            shared,
+            store: IndexerStore::new(db),
+        }
+    }
+
+    pub fn start<S: ToString>(
+        self,
+        thread_name: Option<S>,
+        notify: &NotifyController,
+    ) -> IndexerController<T> {
+        let (signal_sender, signal_receiver) =
+            crossbeam_channel::bounded::<()>(SIGNAL_CHANNEL_SIZE);
+
+        let mut thread_builder = thread::Builder::new();
+        // Mainly for test: give a empty thread_name
+        if let Some(name) = thread_name {
+            thread_builder = thread_builder.name(name.to_string());
+        }
+
+        let chain_event_receiver =
+            notify.subscribe(INDEXER_SUBSCRIBER, &[EventKind::SwitchFork, EventKind::NewTip]);
+        let store = self.store.clone();
+        let thread = thread_builder
+            .spawn(move || {
+                if self.store.reset_outdated() {
+                    info!(target: "indexer", "the index layout changed, reindex from genesis");
+                }
+                // Catch up with the blocks connected while the indexer was
+                // offline, the node starts meanwhile
+                if !self.sync(&signal_receiver) {
+                    return;
+                }
+                loop {
+                    select! {
+                        recv(signal_receiver) -> _ => {
+                            break;
+                        }
+                        recv(chain_event_receiver) -> msg => match msg {
+                            Ok(_) => {
+                                if !self.sync(&signal_receiver) {
+                                    break;
+                                }
+                            }
+                            _ => {
+                                error!(target: "indexer", "channel chain_event_receiver closed");
+                                break;
+                            }
+                        }
+                    }
+                }
+            })
+            .expect("Start IndexerService failed");
+        let stop = StopHandler::new(SignalSender::Crossbeam(signal_sender), thread);
+
+        IndexerController { store, stop }
+    }
+
+    /// Moves the indexed tip onto the main chain: detaches the blocks which
+    /// are no longer on it, then attaches the main chain blocks above. Returns
+    /// false when a stop `signal` interrupts it.
+    fn sync(&self, signal: &Receiver<()>) -> bool {
+        while let Some((number, hash)) = self.store.tip() {
+            if self.shared.block_hash(number).as_ref() == Some(&hash) {
+                break;
+            }
+            match self.shared.block(&hash) {
+                Some(block) => {
+                    debug!(target: "indexer", "detach block {} {:#x}", number, hash);
+                    self.store
+                        .detach_block(&block, |out_point| self.resolve_cell(out_point));
+                }
+                None => {
+                    error!(target: "indexer", "indexed block {:#x} not found", hash);
+                    return true;
+                }
+            }
+        }
+
+        let start = self.store.tip().map(|(number, _)| number + 1).unwrap_or(0);
+        let tip_number = self.shared.chain_state().read().tip_number();
+        for number in start..=tip_number {
+            if signal.try_recv().is_ok() {
+                return false;
+            }
+            match self
+                .shared
+                .block_hash(number)
+                .and_then(|hash| self.shared.block(&hash))
+            {
+                Some(block) => self.store.attach_block(&block),
+                None => break,
+            }
+        }
+        true
+    }
+
+    fn resolve_cell(&self, out_point: &OutPoint) -> Option<LiveCell> {
+        let tx = self.shared.get_transaction(&out_point.hash)?;
+        let address = self.shared.get_transaction_address(&out_point.hash)?;
+        let block_number = self.shared.block_number(&address.block_hash)?;
+        tx.outputs()
+            .get(out_point.index as usize)
+            .map(|output| LiveCell {
+                out_point: out_point.clone(),
+                output: output.clone(),
+                block_number,
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::COLUMNS;
+    use ckb_chain::test_utils::{start_chain, Fork};
+    use ckb_chain_spec::consensus::Consensus;
+    use ckb_db::memorydb::MemoryKeyValueDB;
+    use ckb_notify::NotifyService;
+    use std::time::{Duration, Instant};
+
+    fn wait_for_tip<T: 'static + KeyValueDB>(indexer: &IndexerController<T>, hash: &H256) {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while indexer.tip().map(|(_, tip)| tip).as_ref() != Some(hash) {
+            assert!(Instant::now() < deadline, "block {:#x} not indexed", hash);
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn follow_fork_switch() {
+        let notify = NotifyService::default().start::<&str>(None);
+        let (chain, shared) = start_chain(Consensus::default(), notify.clone(), false);
+        let db = MemoryKeyValueDB::open(COLUMNS as usize);
+        let indexer = IndexerService::new(shared.clone(), db).start::<&str>(None, &notify);
+        let alice = H256::from_slice(&[1u8; 32]).unwrap();
+        let bob = H256::from_slice(&[2u8; 32]).unwrap();
+
+        let mut main = Fork::from_number(&shared, &chain, 0).lock(alice.clone());
+        main.extend(2);
+        wait_for_tip(&indexer, &main.tip().hash());
+        assert_eq!(indexer.get_live_cells(&alice).len(), 2);
+        assert_eq!(indexer.get_transactions(&alice).len(), 2);
+
+        // The cells of the detached blocks are dropped
+        let mut fork = Fork::from_number(&shared, &chain, 0).lock(bob.clone());
+        fork.overtake();
+        wait_for_tip(&indexer, &fork.tip().hash());
+        assert!(indexer.get_live_cells(&alice).is_empty());
+        assert!(indexer.get_transactions(&alice).is_empty());
+        assert_eq!(indexer.get_live_cells(&bob).len(), fork.blocks().len());
+        assert_eq!(indexer.get_capacity(&alice), 0);
+        indexer.stop();
+    }
+}
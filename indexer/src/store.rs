@@ -0,0 +1,670 @@
+use crate::{
+    Balance, LiveCell, TransactionPoint, COLUMN_LIVE_CELLS, COLUMN_LOCK_HASH_CELLS,
+    COLUMN_LOCK_HASH_TRANSACTIONS, COLUMN_META, COLUMN_WATCHED,
+};
+use bincode::{deserialize, serialize};
+use ckb_core::block::Block;
+use ckb_core::transaction::OutPoint;
+use ckb_core::{BlockNumber, Capacity};
+use ckb_db::batch::{Batch, Col};
+use ckb_db::kvdb::KeyValueDB;
+use ckb_util::Mutex;
+use fnv::FnvHashMap;
+use numext_fixed_hash::H256;
+use serde::de::DeserializeOwned;
+use std::sync::Arc;
+
+const META_TIP_KEY: &[u8] = b"TIP";
+const META_VERSION_KEY: &[u8] = b"VERSION";
+// Bumped when the layout of the index changes, an index of another version
+// is rebuilt
+const VERSION: u32 = 1;
+
+// The cells and the transactions of a lock have a key each, made of the lock
+// hash and the big endian block number, so that they are stored in block order
+fn lock_cell_key(lock_hash: &H256, block_number: BlockNumber, out_point: &OutPoint) -> Vec<u8> {
+    let mut key = Vec::with_capacity(76);
+    key.extend_from_slice(lock_hash.as_bytes());
+    key.extend_from_slice(&block_number.to_be_bytes());
+    key.extend_from_slice(out_point.hash.as_bytes());
+    key.extend_from_slice(&out_point.index.to_be_bytes());
+    key
+}
+
+fn lock_transaction_key(lock_hash: &H256, point: &TransactionPoint) -> Vec<u8> {
+    let mut key = Vec::with_capacity(48);
+    key.extend_from_slice(lock_hash.as_bytes());
+    key.extend_from_slice(&point.block_number.to_be_bytes());
+    key.extend_from_slice(&(point.index as u64).to_be_bytes());
+    key
+}
+
+fn number_from_key(key: &[u8]) -> BlockNumber {
+    let mut number = [0u8; 8];
+    number.copy_from_slice(&key[32..40]);
+    BlockNumber::from_be_bytes(number)
+}
+
+pub struct IndexerStore<T: KeyValueDB> {
+    db: Arc<T>,
+    // Held while writing, so that a balance is not computed from the cells
+    // while a block changes them
+    write_lock: Arc<Mutex<()>>,
+}
+
+impl<T: KeyValueDB> Clone for IndexerStore<T> {
+    fn clone(&self) -> Self {
+        IndexerStore {
+            db: Arc::clone(&self.db),
+            write_lock: Arc::clone(&self.write_lock),
+        }
+    }
+}
+
+impl<T: 'static + KeyValueDB> IndexerStore<T> {
+    pub fn new(db: T) -> Self {
+        let db = Arc::new(db);
+        IndexerStore {
+            db,
+            write_lock: Arc::new(Mutex::new(())),
+        }
+    }
+
+    fn get<V: DeserializeOwned>(&self, col: Col, key: &[u8]) -> Option<V> {
+        self.db
+            .read(col, key)
+            .expect("db operation should be ok")
+            .map(|raw| deserialize(&raw[..]).expect("deserialize should be ok"))
+    }
+
+    /// Makes the index written so far durable
+    pub fn sync(&self) {
+        self.db.sync().expect("db operation should be ok");
+    }
+
+    /// Number and hash of the last block applied to the index
+    pub fn tip(&self) -> Option<(BlockNumber, H256)> {
+        self.get(COLUMN_META, META_TIP_KEY)
+    }
+
+    /// Drops an index written with another layout, so that it is rebuilt from
+    /// genesis. The watched locks are kept, their balances are computed again.
+    /// Returns whether the index was dropped.
+    pub fn reset_outdated(&self) -> bool {
+        let _write_lock = self.write_lock.lock();
+        let version: Option<u32> = self.get(COLUMN_META, META_VERSION_KEY);
+        if version == Some(VERSION) || self.tip().is_none() {
+            return false;
+        }
+        let mut batch = Batch::new();
+        for col in &[
+            COLUMN_META,
+            COLUMN_LIVE_CELLS,
+            COLUMN_LOCK_HASH_CELLS,
+            COLUMN_LOCK_HASH_TRANSACTIONS,
+        ] {
+            for (key, _) in self.db.iter_from(*col, &[]).expect("db operation should be ok") {
+                batch.delete(*col, key);
+            }
+        }
+        for (key, _) in self
+            .db
+            .iter_from(COLUMN_WATCHED, &[])
+            .expect("db operation should be ok")
+        {
+            batch.insert(
+                COLUMN_WATCHED,
+                key,
+                serialize(&Balance::default()).expect("serialize should be ok"),
+            );
+        }
+        self.db.write(batch).expect("db operation should be ok");
+        true
+    }
+
+    pub fn get_live_cell(&self, out_point: &OutPoint) -> Option<LiveCell> {
+        self.get(COLUMN_LIVE_CELLS, &serialize(out_point).expect("serialize should be ok"))
+    }
+
+    // The pairs of `col` whose key starts with the lock hash, from the key
+    // of the lock hash followed by `from`
+    fn iter_lock<'a>(
+        &'a self,
+        col: Col,
+        lock_hash: &H256,
+        from: &[u8],
+    ) -> impl Iterator<Item = (Vec<u8>, Vec<u8>)> + 'a {
+        let prefix = lock_hash.as_bytes().to_vec();
+        let mut start = prefix.clone();
+        start.extend_from_slice(from);
+        self.db
+            .iter_from(col, &start)
+            .expect("db operation should be ok")
+            .take_while(move |(key, _)| key.starts_with(&prefix))
+    }
+
+    /// The live cells of the lock, in block order
+    pub fn get_live_cells(&self, lock_hash: &H256) -> Vec<LiveCell> {
+        self.get_live_cells_in(lock_hash, 0, BlockNumber::max_value())
+    }
+
+    /// The live cells of the lock created in the blocks `from` to `to`, in
+    /// block order. Only the keys of that range are read.
+    pub fn get_live_cells_in(
+        &self,
+        lock_hash: &H256,
+        from: BlockNumber,
+        to: BlockNumber,
+    ) -> Vec<LiveCell> {
+        self.iter_lock(COLUMN_LOCK_HASH_CELLS, lock_hash, &from.to_be_bytes())
+            .take_while(|(key, _)| number_from_key(key) <= to)
+            .filter_map(|(key, _)| {
+                let out_point = OutPoint::new(
+                    H256::from_slice(&key[40..72]).expect("db safe access"),
+                    u32::from_be_bytes([key[72], key[73], key[74], key[75]]),
+                );
+                self.get_live_cell(&out_point)
+            })
+            .collect()
+    }
+
+    /// The transactions creating or spending cells of the lock, in block order
+    pub fn get_transactions(&self, lock_hash: &H256) -> Vec<TransactionPoint> {
+        self.get_transactions_page(lock_hash, 0, usize::max_value())
+    }
+
+    /// `count` transactions of the lock after the first `skip` ones, in block
+    /// order. The skipped ones are stepped over in the db, not loaded.
+    pub fn get_transactions_page(
+        &self,
+        lock_hash: &H256,
+        skip: usize,
+        count: usize,
+    ) -> Vec<TransactionPoint> {
+        self.iter_lock(COLUMN_LOCK_HASH_TRANSACTIONS, lock_hash, &[])
+            .skip(skip)
+            .take(count)
+            .map(|(key, value)| {
+                let mut index = [0u8; 8];
+                index.copy_from_slice(&key[40..48]);
+                TransactionPoint {
+                    tx_hash: H256::from_slice(&value).expect("db safe access"),
+                    block_number: number_from_key(&key),
+                    index: u64::from_be_bytes(index) as usize,
+                }
+            })
+            .collect()
+    }
+
+    /// Total capacity of the live cells guarded by the lock
+    pub fn get_capacity(&self, lock_hash: &H256) -> Capacity {
+        self.get_live_cells(lock_hash)
+            .iter()
+            .map(|cell| cell.output.capacity)
+            .sum()
+    }
+
+    /// Keeps the balance of the lock from now on, starting from its live
+    /// cells. Returns false when it is watched already.
+    pub fn watch(&self, lock_hash: &H256) -> bool {
+        let _write_lock = self.write_lock.lock();
+        if self.get_balance(lock_hash).is_some() {
+            return false;
+        }
+        let balance = self
+            .get_live_cells(lock_hash)
+            .iter()
+            .fold(Balance::default(), |balance, cell| Balance {
+                capacity: balance.capacity + cell.output.capacity,
+                cells_count: balance.cells_count + 1,
+            });
+        self.put_balance(lock_hash, Some(balance));
+        true
+    }
+
+    /// Returns false when the lock is not watched
+    pub fn unwatch(&self, lock_hash: &H256) -> bool {
+        let _write_lock = self.write_lock.lock();
+        if self.get_balance(lock_hash).is_none() {
+            return false;
+        }
+        self.put_balance(lock_hash, None);
+        true
+    }
+
+    /// The balance of a watched lock, None when it is not watched
+    pub fn get_balance(&self, lock_hash: &H256) -> Option<Balance> {
+        self.get(COLUMN_WATCHED, lock_hash.as_bytes())
+    }
+
+    /// The watched locks with their balances, ordered by lock hash
+    pub fn get_watched(&self) -> Vec<(H256, Balance)> {
+        self.db
+            .iter_from(COLUMN_WATCHED, &[])
+            .expect("db operation should be ok")
+            .map(|(key, value)| {
+                let lock_hash = H256::from_slice(&key).expect("db safe access");
+                let balance = deserialize(&value).expect("deserialize should be ok");
+                (lock_hash, balance)
+            })
+            .collect()
+    }
+
+    fn put_balance(&self, lock_hash: &H256, balance: Option<Balance>) {
+        let mut batch = Batch::new();
+        let key = lock_hash.as_bytes().to_vec();
+        match balance {
+            Some(balance) => batch.insert(
+                COLUMN_WATCHED,
+                key,
+                serialize(&balance).expect("serialize should be ok"),
+            ),
+            None => batch.delete(COLUMN_WATCHED, key),
+        }
+        self.db.write(batch).expect("db operation should be ok");
+    }
+
+    /// Applies a block on top of the indexed tip
+    pub fn attach_block(&self, block: &Block) {
+        let _write_lock = self.write_lock.lock();
+        let number = block.header().number();
+        let mut overlay = Overlay::new(self);
+
+        for (index, tx) in block.commit_transactions().iter().enumerate() {
+            let point = TransactionPoint {
+                tx_hash: tx.hash(),
+                block_number: number,
+                index,
+            };
+            if !tx.is_cellbase() {
+                for out_point in tx.input_pts() {
+                    if let Some(cell) = overlay.remove_cell(&out_point) {
+                        overlay.push_point(&cell.output.lock, &point);
+                    }
+                }
+            }
+            for (i, output) in tx.outputs().iter().enumerate() {
+                overlay.push_point(&output.lock, &point);
+                overlay.insert_cell(LiveCell {
+                    out_point: OutPoint::new(point.tx_hash.clone(), i as u32),
+                    output: output.clone(),
+                    block_number: number,
+                });
+            }
+        }
+
+        self.commit(overlay, (number, block.header().hash()));
+    }
+
+    /// Reverts the indexed tip block. `resolve` looks up the cells spent by
+    /// the block so that they become live again.
+    pub fn detach_block<F>(&self, block: &Block, resolve: F)
+    where
+        F: Fn(&OutPoint) -> Option<LiveCell>,
+    {
+        let _write_lock = self.write_lock.lock();
+        let number = block.header().number();
+        let mut overlay = Overlay::new(self);
+
+        for (index, tx) in block.commit_transactions().iter().enumerate().rev() {
+            let point = TransactionPoint {
+                tx_hash: tx.hash(),
+                block_number: number,
+                index,
+            };
+            for (i, output) in tx.outputs().iter().enumerate() {
+                overlay.remove_cell(&OutPoint::new(point.tx_hash.clone(), i as u32));
+                overlay.remove_point(&output.lock, &point);
+            }
+            if !tx.is_cellbase() {
+                for out_point in tx.input_pts() {
+                    if let Some(cell) = resolve(&out_point) {
+                        overlay.remove_point(&cell.output.lock, &point);
+                        overlay.insert_cell(cell);
+                    }
+                }
+            }
+        }
+
+        let parent = (number.saturating_sub(1), block.header().parent_hash().clone());
+        self.commit(overlay, parent);
+    }
+
+    fn commit(&self, overlay: Overlay<T>, tip: (BlockNumber, H256)) {
+        let mut batch = overlay.into_batch();
+        batch.insert(
+            COLUMN_META,
+            META_TIP_KEY.to_vec(),
+            serialize(&tip).expect("serialize should be ok"),
+        );
+        batch.insert(
+            COLUMN_META,
+            META_VERSION_KEY.to_vec(),
+            serialize(&VERSION).expect("serialize should be ok"),
+        );
+        self.db.write(batch).expect("db operation should be ok");
+    }
+}
+
+/// Pending changes of a single block, written to the db in one batch
+struct Overlay<'a, T: KeyValueDB> {
+    store: &'a IndexerStore<T>,
+    cells: FnvHashMap<OutPoint, Option<LiveCell>>,
+    // The keys of the lock columns, inserted when true or Some and deleted
+    // otherwise
+    lock_cells: FnvHashMap<Vec<u8>, bool>,
+    lock_transactions: FnvHashMap<Vec<u8>, Option<H256>>,
+    // None for the locks not watched
+    balances: FnvHashMap<H256, Option<Balance>>,
+}
+
+impl<'a, T: 'static + KeyValueDB> Overlay<'a, T> {
+    fn new(store: &'a IndexerStore<T>) -> Self {
+        Overlay {
+            store,
+            cells: FnvHashMap::default(),
+            lock_cells: FnvHashMap::default(),
+            lock_transactions: FnvHashMap::default(),
+            balances: FnvHashMap::default(),
+        }
+    }
+
+    fn balance_mut(&mut self, lock_hash: &H256) -> Option<&mut Balance> {
+        let store = self.store;
+        self.balances
+            .entry(lock_hash.clone())
+            .or_insert_with(|| store.get_balance(lock_hash))
+            .as_mut()
+    }
+
+    fn insert_cell(&mut self, cell: LiveCell) {
+        let key = lock_cell_key(&cell.output.lock, cell.block_number, &cell.out_point);
+        self.lock_cells.insert(key, true);
+        if let Some(balance) = self.balance_mut(&cell.output.lock) {
+            balance.capacity += cell.output.capacity;
+            balance.cells_count += 1;
+        }
+        self.cells.insert(cell.out_point.clone(), Some(cell));
+    }
+
+    fn remove_cell(&mut self, out_point: &OutPoint) -> Option<LiveCell> {
+        let cell = match self.cells.get(out_point) {
+            Some(cell) => cell.clone(),
+            None => self.store.get_live_cell(out_point),
+        }?;
+        let key = lock_cell_key(&cell.output.lock, cell.block_number, out_point);
+        self.lock_cells.insert(key, false);
+        if let Some(balance) = self.balance_mut(&cell.output.lock) {
+            balance.capacity -= cell.output.capacity;
+            balance.cells_count -= 1;
+        }
+        self.cells.insert(out_point.clone(), None);
+        Some(cell)
+    }
+
+    fn push_point(&mut self, lock_hash: &H256, point: &TransactionPoint) {
+        self.lock_transactions.insert(
+            lock_transaction_key(lock_hash, point),
+            Some(point.tx_hash.clone()),
+        );
+    }
+
+    fn remove_point(&mut self, lock_hash: &H256, point: &TransactionPoint) {
+        self.lock_transactions
+            .insert(lock_transaction_key(lock_hash, point), None);
+    }
+
+    fn into_batch(self) -> Batch {
+        let mut batch = Batch::new();
+        for (out_point, cell) in self.cells {
+            let key = serialize(&out_point).expect("serialize should be ok");
+            match cell {
+                Some(cell) => batch.insert(
+                    COLUMN_LIVE_CELLS,
+                    key,
+                    serialize(&cell).expect("serialize should be ok"),
+                ),
+                None => batch.delete(COLUMN_LIVE_CELLS, key),
+            }
+        }
+        for (key, live) in self.lock_cells {
+            if live {
+                batch.insert(COLUMN_LOCK_HASH_CELLS, key, Vec::new());
+            } else {
+                batch.delete(COLUMN_LOCK_HASH_CELLS, key);
+            }
+        }
+        for (key, tx_hash) in self.lock_transactions {
+            match tx_hash {
+                Some(tx_hash) => batch.insert(COLUMN_LOCK_HASH_TRANSACTIONS, key, tx_hash.to_vec()),
+                None => batch.delete(COLUMN_LOCK_HASH_TRANSACTIONS, key),
+            }
+        }
+        for (lock_hash, balance) in self.balances {
+            if let Some(balance) = balance {
+                batch.insert(
+                    COLUMN_WATCHED,
+                    lock_hash.as_bytes().to_vec(),
+                    serialize(&balance).expect("serialize should be ok"),
+                );
+            }
+        }
+        batch
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::COLUMNS;
+    use ckb_core::block::BlockBuilder;
+    use ckb_core::header::HeaderBuilder;
+    use ckb_core::script::Script;
+    use ckb_core::transaction::{CellInput, CellOutput, Transaction, TransactionBuilder};
+    use ckb_db::memorydb::MemoryKeyValueDB;
+
+    fn create_block(number: BlockNumber, parent_hash: H256, txs: Vec<Transaction>) -> Block {
+        BlockBuilder::default()
+            .commit_transactions(txs)
+            .with_header_builder(HeaderBuilder::default().number(number).parent_hash(parent_hash))
+    }
+
+    fn cellbase(number: BlockNumber, lock: &H256) -> Transaction {
+        TransactionBuilder::default()
+            .input(CellInput::new_cellbase_input(number))
+            .output(CellOutput::new(100, vec![], lock.clone(), None))
+            .build()
+    }
+
+    #[test]
+    fn attach_and_detach() {
+        let store = IndexerStore::new(MemoryKeyValueDB::open(COLUMNS as usize));
+        let alice = H256::from_trimmed_hex_str("a").unwrap();
+        let bob = H256::from_trimmed_hex_str("b").unwrap();
+
+        let cellbase0 = cellbase(0, &alice);
+        let block0 = create_block(0, H256::zero(), vec![cellbase0.clone()]);
+        store.attach_block(&block0);
+
+        assert_eq!(store.tip(), Some((0, block0.header().hash())));
+        assert_eq!(store.get_capacity(&alice), 100);
+        assert_eq!(store.get_transactions(&alice).len(), 1);
+
+        let spent = OutPoint::new(cellbase0.hash(), 0);
+        let transfer = TransactionBuilder::default()
+            .input(CellInput::new(spent.clone(), Script::default()))
+            .output(CellOutput::new(60, vec![], bob.clone(), None))
+            .output(CellOutput::new(40, vec![], alice.clone(), None))
+            .build();
+        let block1 = create_block(
+            1,
+            block0.header().hash(),
+            vec![cellbase(1, &bob), transfer.clone()],
+        );
+        store.attach_block(&block1);
+
+        assert_eq!(store.tip(), Some((1, block1.header().hash())));
+        assert_eq!(store.get_capacity(&alice), 40);
+        assert_eq!(store.get_capacity(&bob), 160);
+        assert_eq!(store.get_live_cells(&bob).len(), 2);
+        assert_eq!(
+            store.get_transactions(&alice),
+            vec![
+                TransactionPoint {
+                    tx_hash: cellbase0.hash(),
+                    block_number: 0,
+                    index: 0,
+                },
+                TransactionPoint {
+                    tx_hash: transfer.hash(),
+                    block_number: 1,
+                    index: 1,
+                },
+            ]
+        );
+        assert!(store.get_live_cell(&spent).is_none());
+
+        let restored = LiveCell {
+            out_point: spent.clone(),
+            output: cellbase0.outputs()[0].clone(),
+            block_number: 0,
+        };
+        store.detach_block(&block1, |out_point| {
+            if out_point == &spent {
+                Some(restored.clone())
+            } else {
+                None
+            }
+        });
+
+        assert_eq!(store.tip(), Some((0, block0.header().hash())));
+        assert_eq!(store.get_live_cells(&alice), vec![restored]);
+        assert!(store.get_live_cells(&bob).is_empty());
+        assert!(store.get_transactions(&bob).is_empty());
+        assert_eq!(store.get_transactions(&alice).len(), 1);
+    }
+
+    #[test]
+    fn paged_reads() {
+        let store = IndexerStore::new(MemoryKeyValueDB::open(COLUMNS as usize));
+        let alice = H256::from_trimmed_hex_str("a").unwrap();
+        let bob = H256::from_trimmed_hex_str("b").unwrap();
+
+        let mut parent_hash = H256::zero();
+        let mut cellbases = Vec::new();
+        for number in 0..5 {
+            let txs = vec![cellbase(number, &alice), cellbase(number + 100, &bob)];
+            let block = create_block(number, parent_hash, txs.clone());
+            store.attach_block(&block);
+            parent_hash = block.header().hash();
+            cellbases.push(txs[0].hash());
+        }
+
+        let numbers: Vec<_> = store
+            .get_live_cells_in(&alice, 1, 3)
+            .into_iter()
+            .map(|cell| cell.block_number)
+            .collect();
+        assert_eq!(numbers, vec![1, 2, 3]);
+        assert!(store.get_live_cells_in(&alice, 5, 10).is_empty());
+
+        let hashes: Vec<_> = store
+            .get_transactions_page(&alice, 2, 2)
+            .into_iter()
+            .map(|point| point.tx_hash)
+            .collect();
+        assert_eq!(hashes, cellbases[2..4].to_vec());
+        assert_eq!(store.get_transactions_page(&alice, 4, 2).len(), 1);
+        assert!(store.get_transactions_page(&alice, 5, 2).is_empty());
+    }
+
+    #[test]
+    fn watched_balances() {
+        let store = IndexerStore::new(MemoryKeyValueDB::open(COLUMNS as usize));
+        let alice = H256::from_trimmed_hex_str("a").unwrap();
+        let bob = H256::from_trimmed_hex_str("b").unwrap();
+
+        let cellbase0 = cellbase(0, &alice);
+        let block0 = create_block(0, H256::zero(), vec![cellbase0.clone()]);
+        store.attach_block(&block0);
+        // Watching starts from the live cells
+        assert!(store.watch(&alice));
+        assert!(!store.watch(&alice));
+        assert!(store.watch(&bob));
+        let initial = Balance {
+            capacity: 100,
+            cells_count: 1,
+        };
+        assert_eq!(store.get_balance(&alice), Some(initial));
+        assert_eq!(store.get_balance(&bob), Some(Balance::default()));
+
+        let spent = OutPoint::new(cellbase0.hash(), 0);
+        let transfer = TransactionBuilder::default()
+            .input(CellInput::new(spent.clone(), Script::default()))
+            .output(CellOutput::new(60, vec![], bob.clone(), None))
+            .output(CellOutput::new(30, vec![], alice.clone(), None))
+            .output(CellOutput::new(10, vec![], alice.clone(), None))
+            .build();
+        let block1 = create_block(1, block0.header().hash(), vec![transfer]);
+        store.attach_block(&block1);
+        assert_eq!(
+            store.get_watched(),
+            vec![
+                (
+                    alice.clone(),
+                    Balance {
+                        capacity: 40,
+                        cells_count: 2,
+                    }
+                ),
+                (
+                    bob.clone(),
+                    Balance {
+                        capacity: 60,
+                        cells_count: 1,
+                    }
+                ),
+            ]
+        );
+
+        let restored = LiveCell {
+            out_point: spent.clone(),
+            output: cellbase0.outputs()[0].clone(),
+            block_number: 0,
+        };
+        store.detach_block(&block1, |_| Some(restored.clone()));
+        assert_eq!(store.get_balance(&alice), Some(initial));
+        assert_eq!(store.get_balance(&bob), Some(Balance::default()));
+
+        assert!(store.unwatch(&bob));
+        assert!(!store.unwatch(&bob));
+        assert_eq!(store.get_balance(&bob), None);
+        assert_eq!(store.get_watched().len(), 1);
+    }
+
+    #[test]
+    fn reset_outdated_index() {
+        let store = IndexerStore::new(MemoryKeyValueDB::open(COLUMNS as usize));
+        let alice = H256::from_trimmed_hex_str("a").unwrap();
+        assert!(!store.reset_outdated());
+
+        let block0 = create_block(0, H256::zero(), vec![cellbase(0, &alice)]);
+        store.attach_block(&block0);
+        assert!(store.watch(&alice));
+        assert!(!store.reset_outdated());
+
+        let mut batch = Batch::new();
+        batch.insert(COLUMN_META, META_VERSION_KEY.to_vec(), serialize(&0u32).unwrap());
+        store.db.write(batch).unwrap();
+        assert!(store.reset_outdated());
+        assert_eq!(store.tip(), None);
+        assert!(store.get_live_cells(&alice).is_empty());
+        assert!(store.get_transactions(&alice).is_empty());
+        assert_eq!(store.get_balance(&alice), Some(Balance::default()));
+
+        // Indexed again from genesis
+        store.attach_block(&block0);
+        assert_eq!(store.get_capacity(&alice), 100);
+        assert_eq!(store.get_balance(&alice).unwrap().capacity, 100);
+    }
+}
@@ -5,22 +5,25 @@ use ckb_core::transaction::{CellInput, CellOutput, Transaction, TransactionBuild
 use ckb_core::uncle::UncleBlock;
 use ckb_core::BlockNumber;
 use ckb_core::{Cycle, Version};
+use ckb_metrics::{registry, Gauge, Histogram, LATENCY_BUCKETS};
 use ckb_notify::NotifyController;
 use ckb_pool::txs_pool::TransactionPoolController;
 use ckb_shared::error::SharedError;
 use ckb_shared::index::ChainIndex;
 use ckb_shared::shared::{ChainProvider, Shared};
+use ckb_time::unix_time_as_millis;
 use ckb_util::Mutex;
 use crossbeam_channel::{self, select, Receiver, Sender};
-use faketime::unix_time_as_millis;
 use fnv::FnvHashSet;
 use jsonrpc_types::{BlockTemplate, CellbaseTemplate, TransactionTemplate, UncleTemplate};
 use log::error;
 use lru_cache::LruCache;
 use numext_fixed_hash::H256;
 use std::cmp;
-use std::sync::{atomic::AtomicUsize, atomic::Ordering, Arc};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
 use std::thread;
+use std::time::Instant;
 use stop_handler::{SignalSender, StopHandler};
 
 const MAX_CANDIDATE_UNCLES: usize = 42;
@@ -55,6 +58,8 @@ impl TemplateCache {
 #[derive(Clone)]
 pub struct BlockAssemblerController {
     get_block_template_sender: Sender<Request<BlockTemplateParams, BlockTemplateResult>>,
+    // Shared by the clones, the templates are still assembled
+    paused: Arc<AtomicBool>,
     stop: StopHandler<()>,
 }
 
@@ -81,6 +86,20 @@ impl BlockAssemblerController {
         )
         .expect("get_block_template() failed")
     }
+
+    /// Tells the miners there is no work while paused, such as during
+    /// maintenance
+    pub fn set_paused(&self, paused: bool) {
+        self.paused.store(paused, Ordering::SeqCst);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+
+    pub fn stop(&self) {
+        self.stop.stop();
+    }
 }
 
 pub struct BlockAssembler<CI> {
@@ -91,6 +110,8 @@ pub struct BlockAssembler<CI> {
     work_id: AtomicUsize,
     last_uncles_updated_at: AtomicUsize,
     template_caches: Mutex<LruCache<(Cycle, u64, Version), TemplateCache>>,
+    template_durations: Arc<Histogram>,
+    candidate_uncles_count: Arc<Gauge>,
 }
 
 impl<CI: ChainIndex + 'static> BlockAssembler<CI> {
@@ -103,6 +124,12 @@ impl<CI: ChainIndex + 'static> BlockAssembler<CI> {
             work_id: AtomicUsize::new(0),
             last_uncles_updated_at: AtomicUsize::new(0),
             template_caches: Mutex::new(LruCache::new(TEMPLATE_CACHE_SIZE)),
+            template_durations: registry().histogram(
+                "miner_block_template_duration_us",
+                Vec::new(),
+                LATENCY_BUCKETS,
+            ),
+            candidate_uncles_count: registry().gauge("miner_candidate_uncles", Vec::new()),
         }
     }
 
@@ -128,7 +155,7 @@ impl<CI: ChainIndex + 'static> BlockAssembler<CI> {
 
         let new_uncle_receiver = notify.subscribe_new_uncle(BLOCK_ASSEMBLER_SUBSCRIBER);
         let thread = thread_builder
-            .spawn(move || loop {
+            .spawn(ckb_time::inherit(move || loop {
                 select! {
                     recv(signal_receiver) -> _ => {
                         break;
@@ -137,6 +164,7 @@ impl<CI: ChainIndex + 'static> BlockAssembler<CI> {
                         Ok(uncle_block) => {
                             let hash = uncle_block.header().hash().clone();
                             self.candidate_uncles.insert(hash, uncle_block);
+                            self.candidate_uncles_count.set(self.candidate_uncles.len());
                             self.last_uncles_updated_at
                                 .store(unix_time_as_millis() as usize, Ordering::SeqCst);
                         }
@@ -147,7 +175,10 @@ impl<CI: ChainIndex + 'static> BlockAssembler<CI> {
                     },
                     recv(receivers.get_block_template_receiver) -> msg => match msg {
                         Ok(Request { responder, arguments: (cycles_limit, bytes_limit, max_version) }) => {
-                            let _ = responder.send(self.get_block_template(cycles_limit, bytes_limit, max_version));
+                            let started_at = Instant::now();
+                            let result = self.get_block_template(cycles_limit, bytes_limit, max_version);
+                            self.template_durations.observe_duration(started_at.elapsed());
+                            let _ = responder.send(result);
                         },
                         _ => {
                             error!(target: "miner", "get_block_template_receiver closed");
@@ -155,11 +186,12 @@ impl<CI: ChainIndex + 'static> BlockAssembler<CI> {
                         },
                     }
                 }
-            }).expect("Start MinerAgent failed");
+            })).expect("Start MinerAgent failed");
         let stop = StopHandler::new(SignalSender::Crossbeam(signal_sender), thread);
 
         BlockAssemblerController {
             get_block_template_sender,
+            paused: Arc::new(AtomicBool::new(false)),
             stop,
         }
     }
@@ -240,6 +272,7 @@ impl<CI: ChainIndex + 'static> BlockAssembler<CI> {
         let header = chain_state.tip_header();
         let number = chain_state.tip_number() + 1;
         let current_time = cmp::max(unix_time_as_millis(), header.timestamp() + 1);
+        let version = self.shared.signalling_version(header, version);
 
         let mut template_caches = self.template_caches.lock();
 
@@ -413,18 +446,21 @@ mod tests {
     use ckb_chain::chain::ChainController;
     use ckb_chain_spec::consensus::Consensus;
     use ckb_core::block::BlockBuilder;
+    use ckb_core::generator::{Generator, GeneratorConfig};
     use ckb_core::header::HeaderBuilder;
+    use ckb_core::transaction::ProposalShortId;
     use ckb_db::memorydb::MemoryKeyValueDB;
     use ckb_notify::{NotifyController, NotifyService};
     use ckb_pool::txs_pool::{PoolConfig, TransactionPoolController, TransactionPoolService};
     use ckb_pow::Pow;
     use ckb_shared::index::ChainIndex;
     use ckb_shared::shared::Shared;
-    use ckb_shared::shared::SharedBuilder;
+    use ckb_shared::shared::{ChainProvider, SharedBuilder};
     use ckb_shared::store::ChainKVStore;
     use ckb_verification::{BlockVerifier, HeaderResolverWrapper, HeaderVerifier, Verifier};
     use jsonrpc_types::{BlockTemplate, CellbaseTemplate};
     use numext_fixed_hash::H256;
+    use numext_fixed_uint::U256;
 
     fn start_chain(
         consensus: Option<Consensus>,
@@ -456,6 +492,11 @@ mod tests {
             max_proposal_size: 1000,
             max_cache_size: 1000,
             max_pending_size: 1000,
+            max_ancestors_count: 1000,
+            max_descendants_count: 1000,
+            min_fee_rate: 0,
+            free_tx_allowance: 0,
+            max_local_size: 1000,
             trace: Some(100),
         };
         let tx_pool_service = TransactionPoolService::new(config, shared, notify);
@@ -518,10 +559,44 @@ mod tests {
             .with_header_builder(header_builder);
 
         let resolver = HeaderResolverWrapper::new(block.header(), shared.clone());
-        let header_verifier = HeaderVerifier::new(shared.clone(), Pow::Dummy.engine());
+        let header_verifier = HeaderVerifier::new(
+            shared.clone(),
+            Pow::Dummy.engine(),
+            shared.consensus().max_block_time_drift(),
+        );
         assert!(header_verifier.verify(&resolver).is_ok());
 
         let block_verify = BlockVerifier::new(shared.clone());
         assert!(block_verify.verify(&block).is_ok());
     }
+
+    #[test]
+    fn test_propose_generated_transactions() {
+        let mut generator = Generator::new(GeneratorConfig::default(), 1);
+        let genesis = BlockBuilder::default()
+            .commit_transaction(generator.cellbase(0, 10, 10_000_000))
+            .with_header_builder(HeaderBuilder::default().difficulty(U256::from(1000u64)));
+        let consensus = Consensus::default().set_genesis_block(genesis);
+        let (_chain_controller, shared, notify) = start_chain(Some(consensus), None);
+        let tx_pool_controller = setup_tx_pool(shared.clone(), notify.clone());
+        let transactions = generator.transactions(20);
+        for tx in &transactions {
+            tx_pool_controller.add_transaction(tx.clone()).unwrap();
+        }
+        let mut block_assembler =
+            setup_block_assembler(tx_pool_controller, shared.clone(), H256::zero());
+
+        let block_template = block_assembler
+            .get_block_template(None, None, None)
+            .unwrap();
+        let proposals: Vec<ProposalShortId> = block_template
+            .proposal_transactions
+            .into_iter()
+            .map(Into::into)
+            .collect();
+        assert_eq!(proposals.len(), transactions.len());
+        for tx in &transactions {
+            assert!(proposals.contains(&tx.proposal_short_id()));
+        }
+    }
 }
@@ -14,4 +14,8 @@ pub struct MinerConfig {
 #[derive(Clone, Debug, PartialEq, Deserialize)]
 pub struct BlockAssemblerConfig {
     pub type_hash: H256,
+    /// Seals a block as soon as a transaction enters the pool, on the chain
+    /// specs whose pow engine is Dummy
+    #[serde(default)]
+    pub instant_seal: bool,
 }
@@ -2,11 +2,13 @@ mod block_assembler;
 mod client;
 mod config;
 mod miner;
+mod sealer;
 
 pub use crate::block_assembler::{BlockAssembler, BlockAssemblerController};
 pub use crate::client::Client;
 pub use crate::config::{BlockAssemblerConfig, MinerConfig};
-pub use crate::miner::Miner;
+pub use crate::miner::{block_from_template, Miner};
+pub use crate::sealer::{seal_block, InstantSealer, InstantSealerController};
 use ckb_util::RwLock;
 use jsonrpc_types::BlockTemplate;
 use std::sync::Arc;
@@ -40,43 +40,7 @@ impl Miner {
 
     fn mine(&self) -> Option<(String, Block)> {
         if let Some(template) = self.current_work.read().clone() {
-            let BlockTemplate {
-                version,
-                difficulty,
-                current_time,
-                number,
-                parent_hash,
-                uncles, // Vec<UncleTemplate>
-                commit_transactions, // Vec<TransactionTemplate>
-                proposal_transactions, // Vec<ProposalShortId>
-                cellbase, // CellbaseTemplate
-                work_id,
-                ..
-                // cycles_limit,
-                // bytes_limit,
-                // uncles_count_limit,
-            } = template;
-
-            let (cellbase_id, cellbase) = {
-                let CellbaseTemplate { hash, data, .. } = cellbase;
-                (hash, data)
-            };
-
-            let header_builder = HeaderBuilder::default()
-                .version(version)
-                .number(number)
-                .difficulty(difficulty)
-                .timestamp(current_time)
-                .parent_hash(parent_hash)
-                .cellbase_id(cellbase_id);
-
-            let block = BlockBuilder::default()
-                .uncles(uncles.into_iter().map(Into::into).collect())
-                .commit_transaction(cellbase.into())
-                .commit_transactions(commit_transactions.into_iter().map(Into::into).collect())
-                .proposal_transactions(proposal_transactions.into_iter().map(Into::into).collect())
-                .with_header_builder(header_builder);
-
+            let (work_id, block) = block_from_template(template);
             let raw_header = block.header().raw().clone();
 
             self.mine_loop(&raw_header)
@@ -107,3 +71,45 @@ impl Miner {
         }
     }
 }
+
+/// The block of `template`, to seal, with the work id to submit it with
+pub fn block_from_template(template: BlockTemplate) -> (String, Block) {
+    let BlockTemplate {
+        version,
+        difficulty,
+        current_time,
+        number,
+        parent_hash,
+        uncles, // Vec<UncleTemplate>
+        commit_transactions, // Vec<TransactionTemplate>
+        proposal_transactions, // Vec<ProposalShortId>
+        cellbase, // CellbaseTemplate
+        work_id,
+        ..
+        // cycles_limit,
+        // bytes_limit,
+        // uncles_count_limit,
+    } = template;
+
+    let (cellbase_id, cellbase) = {
+        let CellbaseTemplate { hash, data, .. } = cellbase;
+        (hash, data)
+    };
+
+    let header_builder = HeaderBuilder::default()
+        .version(version)
+        .number(number)
+        .difficulty(difficulty)
+        .timestamp(current_time)
+        .parent_hash(parent_hash)
+        .cellbase_id(cellbase_id);
+
+    let block = BlockBuilder::default()
+        .uncles(uncles.into_iter().map(Into::into).collect())
+        .commit_transaction(cellbase.into())
+        .commit_transactions(commit_transactions.into_iter().map(Into::into).collect())
+        .proposal_transactions(proposal_transactions.into_iter().map(Into::into).collect())
+        .with_header_builder(header_builder);
+
+    (work_id, block)
+}
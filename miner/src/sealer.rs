@@ -0,0 +1,236 @@
+use crate::block_assembler::BlockAssemblerController;
+use crate::miner::block_from_template;
+use ckb_chain::chain::ChainController;
+use ckb_core::block::{Block, BlockBuilder};
+use ckb_core::header::Seal;
+use ckb_core::service::SIGNAL_CHANNEL_SIZE;
+use ckb_notify::{NotifyController, PoolEvent};
+use ckb_pool::txs_pool::TransactionPoolController;
+use crossbeam_channel::{self, select};
+use jsonrpc_types::BlockTemplate;
+use log::{error, info};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use stop_handler::{SignalSender, StopHandler};
+
+const INSTANT_SEALER_SUBSCRIBER: &str = "instant_sealer";
+// Enough to propose then commit the transactions
+const MAX_SEALED_BLOCKS: usize = 12;
+// The pool reconciles a sealed block before the next template is asked for
+const RECONCILE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Seals `template` with an empty seal and processes the block, for the chain
+/// specs whose pow engine accepts any seal, such as Dummy
+pub fn seal_block(template: BlockTemplate, chain: &ChainController) -> Result<Arc<Block>, String> {
+    let (_, block) = block_from_template(template);
+    let header = block.header().raw().clone().with_seal(Seal::new(0, Vec::new()));
+    let block = Arc::new(BlockBuilder::default().block(block).header(header).build());
+    chain
+        .process_block(Arc::clone(&block))
+        .map_err(|err| format!("sealed block rejected: {:?}", err))?;
+    Ok(block)
+}
+
+/// Seals blocks as soon as transactions enter the pool, until the pool has
+/// none left to propose or commit, so that a dev chain commits a transaction
+/// right after it is sent.
+pub struct InstantSealer {
+    block_assembler: BlockAssemblerController,
+    chain: ChainController,
+    tx_pool: TransactionPoolController,
+}
+
+pub struct InstantSealerController {
+    stop: StopHandler<()>,
+}
+
+impl Drop for InstantSealerController {
+    fn drop(&mut self) {
+        self.stop.try_send();
+    }
+}
+
+impl InstantSealerController {
+    pub fn stop(&self) {
+        self.stop.stop();
+    }
+}
+
+impl InstantSealer {
+    pub fn new(
+        block_assembler: BlockAssemblerController,
+        chain: ChainController,
+        tx_pool: TransactionPoolController,
+    ) -> Self {
+        InstantSealer {
+            block_assembler,
+            chain,
+            tx_pool,
+        }
+    }
+
+    pub fn start<S: ToString>(
+        self,
+        thread_name: Option<S>,
+        notify: &NotifyController,
+    ) -> InstantSealerController {
+        let (signal_sender, signal_receiver) =
+            crossbeam_channel::bounded::<()>(SIGNAL_CHANNEL_SIZE);
+        let pool_receiver = notify.subscribe_pool_event(INSTANT_SEALER_SUBSCRIBER);
+
+        let mut thread_builder = thread::Builder::new();
+        if let Some(name) = thread_name {
+            thread_builder = thread_builder.name(name.to_string());
+        }
+        let thread = thread_builder
+            .spawn(ckb_time::inherit(move || loop {
+                select! {
+                    recv(signal_receiver) -> _ => break,
+                    recv(pool_receiver) -> msg => match msg {
+                        Ok(event) => {
+                            if let PoolEvent::TxAdded { .. } = *event {
+                                // The transactions added meanwhile go in the
+                                // same blocks
+                                while pool_receiver.try_recv().is_ok() {}
+                                self.seal_pending();
+                            }
+                        }
+                        _ => {
+                            error!(target: "miner", "pool_receiver closed");
+                            break;
+                        }
+                    },
+                }
+            }))
+            .expect("Start InstantSealer failed");
+
+        InstantSealerController {
+            stop: StopHandler::new(SignalSender::Crossbeam(signal_sender), thread),
+        }
+    }
+
+    fn seal_pending(&self) {
+        for _ in 0..MAX_SEALED_BLOCKS {
+            let template = match self.block_assembler.get_block_template(None, None, None) {
+                Ok(template) => template,
+                Err(err) => {
+                    error!(target: "miner", "get block template failed: {:?}", err);
+                    return;
+                }
+            };
+            if template.commit_transactions.is_empty()
+                && template.proposal_transactions.is_empty()
+            {
+                return;
+            }
+            let block = match seal_block(template, &self.chain) {
+                Ok(block) => block,
+                Err(err) => {
+                    error!(target: "miner", "{}", err);
+                    return;
+                }
+            };
+            info!(
+                target: "miner",
+                "sealed block {} with {} transactions",
+                block.header().number(),
+                block.commit_transactions().len() - 1
+            );
+            if !self
+                .tx_pool
+                .wait_for_tip(&block.header().hash(), RECONCILE_TIMEOUT)
+            {
+                error!(target: "miner", "the pool did not reconcile the sealed block");
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block_assembler::BlockAssembler;
+    use ckb_chain::chain::ChainBuilder;
+    use ckb_chain_spec::consensus::Consensus;
+    use ckb_core::header::HeaderBuilder;
+    use ckb_core::script::Script;
+    use ckb_core::transaction::{CellInput, CellOutput, OutPoint, TransactionBuilder};
+    use ckb_db::memorydb::MemoryKeyValueDB;
+    use ckb_notify::NotifyService;
+    use ckb_pool::txs_pool::{PoolConfig, TransactionPoolService};
+    use ckb_shared::shared::{ChainProvider, SharedBuilder};
+    use ckb_shared::store::ChainKVStore;
+    use numext_fixed_hash::H256;
+    use std::fs::File;
+    use std::io::Read;
+    use std::path::Path;
+    use std::time::Instant;
+
+    fn always_success() -> Script {
+        let mut file = File::open(
+            Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("../nodes_template/spec/cells/always_success"),
+        )
+        .unwrap();
+        let mut buffer = Vec::new();
+        file.read_to_end(&mut buffer).unwrap();
+        Script::new(0, Vec::new(), None, Some(buffer), Vec::new())
+    }
+
+    // The node of `ckb run --dev` commits a transaction it is sent
+    #[test]
+    fn test_seal_sent_transaction() {
+        let lock = always_success().type_hash();
+        let root = TransactionBuilder::default()
+            .input(CellInput::new(OutPoint::null(), Default::default()))
+            .output(CellOutput::new(100_000_000, Vec::new(), lock.clone(), None))
+            .build();
+        let genesis = BlockBuilder::default()
+            .commit_transaction(root.clone())
+            .with_header_builder(HeaderBuilder::default());
+        let shared = SharedBuilder::<ChainKVStore<MemoryKeyValueDB>>::new_memory()
+            .consensus(Consensus::default().set_genesis_block(genesis))
+            .build();
+
+        let notify = NotifyService::default().start::<&str>(None);
+        let chain = ChainBuilder::new(shared.clone(), notify.clone())
+            .verification(false)
+            .build()
+            .start::<&str>(None);
+        let config = PoolConfig {
+            max_pool_size: 1000,
+            max_orphan_size: 1000,
+            max_proposal_size: 1000,
+            max_cache_size: 1000,
+            max_pending_size: 1000,
+            max_ancestors_count: 1000,
+            max_descendants_count: 1000,
+            min_fee_rate: 0,
+            free_tx_allowance: 0,
+            max_local_size: 1000,
+            trace: None,
+        };
+        let tx_pool = TransactionPoolService::new(config, shared.clone(), notify.clone())
+            .start::<&str>(None);
+        let block_assembler = BlockAssembler::new(shared.clone(), tx_pool.clone(), H256::zero())
+            .start::<&str>(None, &notify);
+        let _sealer = InstantSealer::new(block_assembler, chain, tx_pool.clone())
+            .start::<&str>(None, &notify);
+
+        let tx = TransactionBuilder::default()
+            .input(CellInput::new(OutPoint::new(root.hash(), 0), always_success()))
+            .output(CellOutput::new(100_000_000, Vec::new(), lock, None))
+            .build();
+        tx_pool.add_local_transaction(tx.clone()).unwrap();
+
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while shared.get_transaction_address(&tx.hash()).is_none() {
+            assert!(Instant::now() < deadline, "the transaction was not committed");
+            thread::sleep(Duration::from_millis(10));
+        }
+        let tip = shared.chain_state().read().tip_number();
+        assert!(tip > 1 && tip <= MAX_SEALED_BLOCKS as u64);
+    }
+}
@@ -8,7 +8,7 @@ use crate::protocol_service::ProtocolService;
 use crate::CKBProtocolHandler;
 use crate::Network;
 use crate::PeerId;
-use faketime::unix_time_as_millis;
+use ckb_time::unix_time_as_millis;
 use futures::future::{self, Future};
 use futures::Stream;
 use libp2p::core::{Endpoint, Multiaddr, UniqueConnecState};
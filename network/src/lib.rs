@@ -15,6 +15,8 @@ mod peers_registry;
 mod ping_service;
 mod protocol;
 mod protocol_service;
+#[cfg(any(test, feature = "test-utils"))]
+pub mod test_utils;
 #[cfg(test)]
 mod tests;
 mod timer_service;
@@ -63,13 +65,13 @@ pub struct Config {
 }
 
 impl Config {
-    fn max_outbound_peers(&self) -> u32 {
+    pub fn max_outbound_peers(&self) -> u32 {
         self.max_peers
             / self
                 .outbound_peers_ratio
                 .unwrap_or_else(|| DEFAULT_OUTGOING_PEERS_RATIO)
     }
-    fn max_inbound_peers(&self) -> u32 {
+    pub fn max_inbound_peers(&self) -> u32 {
         self.max_peers - self.max_outbound_peers()
     }
 }
@@ -16,6 +16,7 @@ use crate::transport::{new_transport, TransportOutput};
 use crate::NetworkConfig;
 use crate::{Error, ErrorKind, PeerIndex, ProtocolId};
 use bytes::Bytes;
+use ckb_metrics::registry;
 use ckb_util::{Mutex, RwLock};
 use fnv::FnvHashMap;
 use futures::future::{self, select_all, Future};
@@ -83,6 +84,17 @@ impl Network {
         self.peers_registry.write().drop_peer(&peer_id);
     }
 
+    pub fn peer_limits(&self) -> (u32, u32) {
+        let status = self.connection_status();
+        (status.max_inbound, status.max_outbound)
+    }
+
+    pub fn set_peer_limits(&self, max_inbound: u32, max_outbound: u32) {
+        self.peers_registry
+            .write()
+            .set_limits(max_inbound, max_outbound);
+    }
+
     pub fn local_peer_id(&self) -> &PeerId {
         &self.local_peer_id
     }
@@ -323,6 +335,21 @@ impl Network {
             None => None,
         }
     }
+    /// Connected peers with what is known about them
+    pub fn peers(&self) -> Vec<PeerInfo> {
+        self.peers_registry
+            .read()
+            .peers_iter()
+            .map(|(peer_id, peer)| PeerInfo {
+                peer_id: peer_id.to_owned(),
+                endpoint_role: peer.endpoint_role,
+                last_ping_time: peer.last_ping_time,
+                connected_addr: peer.connected_addr.clone(),
+                identify_info: peer.identify_info.clone(),
+            })
+            .collect()
+    }
+
     pub fn session_info(&self, peer_id: &PeerId, protocol_id: ProtocolId) -> Option<SessionInfo> {
         let peers_registry = self.peers_registry.read();
         match peers_registry.get(peer_id) {
@@ -498,7 +525,10 @@ impl Network {
             .map(|addr| (addr.to_owned(), std::u8::MAX))
             .collect();
         let peer_store: Arc<RwLock<dyn PeerStore>> = {
-            let mut peer_store = SqlitePeerStore::default();
+            let mut peer_store = match config.config_dir_path {
+                Some(ref dir_path) => SqlitePeerStore::file(format!("{}/peer_store.db", dir_path)),
+                None => SqlitePeerStore::default(),
+            };
             let bootnodes = config.bootnodes()?;
             for (peer_id, addr) in bootnodes {
                 peer_store.add_bootnode(peer_id, addr);
@@ -526,9 +556,31 @@ impl Network {
             local_private_key: local_private_key.clone(),
             local_peer_id: local_private_key.to_peer_id(),
         });
+        Self::register_metrics(&network);
         Ok(network)
     }
 
+    // The collector only holds a weak reference, it is unregistered once the
+    // network is dropped
+    fn register_metrics(network: &Arc<Self>) {
+        let network = Arc::downgrade(network);
+        let registry = registry();
+        let gauge = |name| registry.gauge(name, Vec::new());
+        let connected = gauge("network_connected_peers");
+        let inbound = gauge("network_unreserved_inbound_peers");
+        let outbound = gauge("network_unreserved_outbound_peers");
+        registry.on_collect(move || {
+            let status = match network.upgrade() {
+                Some(network) => network.connection_status(),
+                None => return false,
+            };
+            connected.set(status.total as usize);
+            inbound.set(status.unreserved_inbound as usize);
+            outbound.set(status.unreserved_outbound as usize);
+            true
+        });
+    }
+
     pub(crate) fn build_network_future(
         network: Arc<Network>,
         config: &NetworkConfig,
@@ -1,7 +1,7 @@
 use crate::ckb_protocol::CKBProtocol;
 use crate::ckb_protocol_handler::CKBProtocolHandler;
 use crate::ckb_protocol_handler::{CKBProtocolContext, DefaultCKBProtocolContext};
-use crate::network::Network;
+use crate::network::{Network, PeerInfo};
 use crate::NetworkConfig;
 use crate::{Error, ErrorKind, ProtocolId};
 use ckb_util::Mutex;
@@ -47,6 +47,22 @@ impl NetworkService {
         self.network.node_id()
     }
 
+    #[inline]
+    pub fn peers(&self) -> Vec<PeerInfo> {
+        self.network.peers()
+    }
+
+    /// The limits of the inbound and outbound peers
+    pub fn peer_limits(&self) -> (u32, u32) {
+        self.network.peer_limits()
+    }
+
+    /// Applies new limits of the inbound and outbound peers, the connected
+    /// peers above them are not dropped
+    pub fn set_peer_limits(&self, max_inbound: u32, max_outbound: u32) {
+        self.network.set_peer_limits(max_inbound, max_outbound)
+    }
+
     pub fn with_protocol_context<F, T>(&self, protocol_id: ProtocolId, f: F) -> Option<T>
     where
         F: FnOnce(&CKBProtocolContext) -> T,
@@ -2,7 +2,7 @@ use super::{Behaviour, Multiaddr, PeerId, PeerStore, ReportResult, Score, Scorin
 use crate::network_group::MultiaddrExt;
 use crate::peer_store::db;
 use crate::peer_store::sqlite::{self, ConnectionPool, ConnectionPoolExt};
-use faketime::unix_time;
+use ckb_time::unix_time;
 use fnv::FnvHashMap;
 use libp2p::core::Endpoint;
 use log::debug;
@@ -51,6 +51,13 @@ impl SqlitePeerStore {
         SqlitePeerStore::new(pool)
     }
 
+    /// Keeps the peers in the file `path`, every change is written when made so
+    /// that nothing is lost when the node stops
+    pub fn file(path: String) -> Self {
+        let pool = sqlite::open_pool(sqlite::StorePath::File(path), DEFAULT_POOL_SIZE);
+        SqlitePeerStore::new(pool)
+    }
+
     #[allow(dead_code)]
     pub fn temp() -> Self {
         let pool = sqlite::open_pool(sqlite::StorePath::File("".into()), DEFAULT_POOL_SIZE);
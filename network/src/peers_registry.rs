@@ -2,8 +2,8 @@ use crate::network_group::{Group, NetworkGroup};
 use crate::peer_store::PeerStore;
 use crate::{Error, ErrorKind, PeerId, PeerIndex, ProtocolId};
 use bytes::Bytes;
+use ckb_time::unix_time_as_millis;
 use ckb_util::RwLock;
-use faketime::unix_time_as_millis;
 use fnv::{FnvHashMap, FnvHashSet};
 use futures::sync::mpsc::UnboundedSender;
 use libp2p::core::{Endpoint, Multiaddr, UniqueConnec};
@@ -215,6 +215,13 @@ impl PeersRegistry {
         }
     }
 
+    /// Changes the limits of the unreserved peers, the connected peers above
+    /// them are kept
+    pub fn set_limits(&mut self, max_inbound: u32, max_outbound: u32) {
+        self.max_inbound = max_inbound;
+        self.max_outbound = max_outbound;
+    }
+
     #[inline]
     pub fn get_peer_id(&self, peer_index: PeerIndex) -> Option<&PeerId> {
         self.peers.get_peer_id(peer_index)
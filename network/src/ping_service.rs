@@ -4,7 +4,7 @@ use crate::protocol_service::ProtocolService;
 use crate::transport::TransportOutput;
 use crate::Network;
 use crate::PeerId;
-use faketime::unix_time_as_millis;
+use ckb_time::unix_time_as_millis;
 use futures::future::{self, Future};
 use futures::stream::FuturesUnordered;
 use futures::Stream;
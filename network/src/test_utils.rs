@@ -0,0 +1,173 @@
+//! A [CKBProtocolContext] for the unit tests of the protocol handlers,
+//! recording what a handler asks of the network instead of doing it.
+
+use crate::{
+    random_peer_id, CKBProtocolContext, Endpoint, Error, PeerIndex, PeerInfo, ProtocolId,
+    SessionInfo, Severity, TimerToken, ToMultiaddr,
+};
+use ckb_util::Mutex;
+use fnv::FnvHashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// A message a handler sent through the context
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SentMessage {
+    pub peer: PeerIndex,
+    pub protocol: ProtocolId,
+    pub data: Vec<u8>,
+}
+
+#[derive(Default)]
+struct Records {
+    sessions: FnvHashMap<PeerIndex, SessionInfo>,
+    sent: Vec<SentMessage>,
+    // The debug output of each severity, which borrows its reason
+    reported: Vec<(PeerIndex, String)>,
+    banned: Vec<(PeerIndex, Duration)>,
+    disconnected: Vec<PeerIndex>,
+    timers: Vec<(TimerToken, Duration)>,
+}
+
+/// The clones share the records, so that a test keeps one while the handler
+/// gets the boxed others. The peers are connected by the test, and the ones
+/// banned or disconnected are not anymore. The timers are recorded, never
+/// fired, the test calls `timer_triggered` itself.
+#[derive(Clone)]
+pub struct MockProtocolContext {
+    protocol: ProtocolId,
+    records: Arc<Mutex<Records>>,
+}
+
+impl MockProtocolContext {
+    pub fn new(protocol: ProtocolId) -> Self {
+        MockProtocolContext {
+            protocol,
+            records: Default::default(),
+        }
+    }
+
+    /// Connects `peer` as an outbound peer
+    pub fn connect(&self, peer: PeerIndex) {
+        let session = SessionInfo {
+            peer: PeerInfo {
+                peer_id: random_peer_id().expect("random peer id"),
+                endpoint_role: Endpoint::Dialer,
+                last_ping_time: None,
+                connected_addr: "/ip4/127.0.0.1".to_multiaddr().expect("parse multiaddr"),
+                identify_info: None,
+            },
+            protocol_version: None,
+        };
+        self.connect_with(peer, session);
+    }
+
+    pub fn connect_with(&self, peer: PeerIndex, session: SessionInfo) {
+        self.records.lock().sessions.insert(peer, session);
+    }
+
+    /// A context sharing the records, to give to the handler
+    pub fn boxed(&self) -> Box<dyn CKBProtocolContext> {
+        Box::new(self.clone())
+    }
+
+    pub fn sent(&self) -> Vec<SentMessage> {
+        self.records.lock().sent.clone()
+    }
+
+    /// The messages sent since the last call
+    pub fn take_sent(&self) -> Vec<SentMessage> {
+        self.records.lock().sent.drain(..).collect()
+    }
+
+    /// The data of the messages sent to `peer`
+    pub fn sent_to(&self, peer: PeerIndex) -> Vec<Vec<u8>> {
+        self.records
+            .lock()
+            .sent
+            .iter()
+            .filter(|message| message.peer == peer)
+            .map(|message| message.data.clone())
+            .collect()
+    }
+
+    /// The peers reported with the debug output of their severity, such as
+    /// `Bad("invalid block")`
+    pub fn reported(&self) -> Vec<(PeerIndex, String)> {
+        self.records.lock().reported.clone()
+    }
+
+    pub fn banned(&self) -> Vec<(PeerIndex, Duration)> {
+        self.records.lock().banned.clone()
+    }
+
+    pub fn disconnected(&self) -> Vec<PeerIndex> {
+        self.records.lock().disconnected.clone()
+    }
+
+    pub fn timers(&self) -> Vec<(TimerToken, Duration)> {
+        self.records.lock().timers.clone()
+    }
+}
+
+impl CKBProtocolContext for MockProtocolContext {
+    fn send(&self, peer: PeerIndex, data: Vec<u8>) -> Result<(), Error> {
+        self.send_protocol(peer, self.protocol, data)
+    }
+
+    fn send_protocol(
+        &self,
+        peer: PeerIndex,
+        protocol: ProtocolId,
+        data: Vec<u8>,
+    ) -> Result<(), Error> {
+        self.records.lock().sent.push(SentMessage {
+            peer,
+            protocol,
+            data,
+        });
+        Ok(())
+    }
+
+    fn report_peer(&self, peer: PeerIndex, reason: Severity) {
+        self.records
+            .lock()
+            .reported
+            .push((peer, format!("{:?}", reason)));
+    }
+
+    fn ban_peer(&self, peer: PeerIndex, timeout: Duration) {
+        let mut records = self.records.lock();
+        records.sessions.remove(&peer);
+        records.banned.push((peer, timeout));
+    }
+
+    fn disconnect(&self, peer: PeerIndex) {
+        let mut records = self.records.lock();
+        records.sessions.remove(&peer);
+        records.disconnected.push(peer);
+    }
+
+    fn register_timer(&self, token: TimerToken, delay: Duration) -> Result<(), Error> {
+        self.records.lock().timers.push((token, delay));
+        Ok(())
+    }
+
+    fn session_info(&self, peer: PeerIndex) -> Option<SessionInfo> {
+        self.records.lock().sessions.get(&peer).cloned()
+    }
+
+    fn protocol_version(&self, peer: PeerIndex, _protocol: ProtocolId) -> Option<u8> {
+        self.records.lock().sessions.get(&peer).map(|_| 1)
+    }
+
+    fn protocol_id(&self) -> ProtocolId {
+        self.protocol
+    }
+
+    fn connected_peers(&self) -> Vec<PeerIndex> {
+        let mut peers: Vec<_> = self.records.lock().sessions.keys().cloned().collect();
+        peers.sort();
+        peers
+    }
+}
@@ -3,8 +3,8 @@ use crate::{
     peers_registry::{PeersRegistry, EVICTION_PROTECT_PEERS},
     random_peer_id, ToMultiaddr,
 };
+use ckb_time::unix_time_as_millis;
 use ckb_util::RwLock;
-use faketime::unix_time_as_millis;
 use std::sync::Arc;
 
 fn new_peer_store() -> impl PeerStore {
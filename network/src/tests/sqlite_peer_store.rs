@@ -5,6 +5,7 @@ use crate::{
     },
     random_peer_id, Endpoint, ToMultiaddr,
 };
+use ckb_time::{unix_time, Clock};
 use std::time::Duration;
 
 #[test]
@@ -127,10 +128,8 @@ fn test_delete_peer_info() {
     {
         // make sure these 2 peers become candidate in eviction
         let recent_not_seen_time =
-            faketime::unix_time() - Duration::from_secs(u64::from(PEER_NOT_SEEN_TIMEOUT_SECS + 1));
-        let faketime_file = faketime::millis_tempfile(recent_not_seen_time.as_secs() * 1000)
-            .expect("create faketime file");
-        faketime::enable(&faketime_file);
+            unix_time() - Duration::from_secs(u64::from(PEER_NOT_SEEN_TIMEOUT_SECS + 1));
+        let _clock = Clock::new(recent_not_seen_time.as_secs() * 1000).enter();
         peer_store.new_connected_peer(&evict_target, addr1.clone(), Endpoint::Listener);
         peer_store.new_connected_peer(&fake_target, addr2, Endpoint::Listener);
     }
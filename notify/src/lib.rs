@@ -1,47 +1,412 @@
 #![allow(clippy::needless_pass_by_value)]
 
+//! Publishes the node events to the services subscribed to them.
+//!
+//! Every event goes through one channel, so subscribers get them in the
+//! order they were published, a fork switch before the new tip it leads to.
+//! A subscriber either gets one kind of event on a channel of its message
+//! type, or the [Event]s of the kinds it picked. Subscriptions are bounded,
+//! a full one is handled by its [Overflow].
+//!
+//! Every subscriber is reported to the [ckb_metrics] registry, labelled by
+//! `subscriber`: `notify_queue_depth` is the events it has queued after the
+//! last delivery, `notify_delivery_duration_us` the time from publishing to
+//! queuing and `notify_dropped_events_total` the events lost on overflow.
+
+#[cfg(unix)]
+mod publisher;
+
 use ckb_core::block::Block;
 use ckb_core::service::Request;
-use crossbeam_channel::{select, Receiver, Sender};
+use ckb_core::BlockNumber;
+use ckb_metrics::{registry, Counter, Gauge, Histogram, LATENCY_BUCKETS};
+use crossbeam_channel::{select, Receiver, Sender, TrySendError};
 use fnv::FnvHashMap;
 use log::{debug, trace, warn};
+use numext_fixed_hash::H256;
+use serde_derive::Deserialize;
+use std::collections::VecDeque;
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::thread;
+use std::time::Instant;
 use stop_handler::{SignalSender, StopHandler};
 
+#[cfg(unix)]
+pub use crate::publisher::start_publisher;
+
 pub const SIGNAL_CHANNEL_SIZE: usize = 1;
 pub const REGISTER_CHANNEL_SIZE: usize = 2;
 pub const NOTIFY_CHANNEL_SIZE: usize = 128;
+/// Events of every kind kept to be replayed to new subscribers
+pub const REPLAY_BUFFER_SIZE: usize = 16;
+const DROPPED_EVENTS: &str = "notify_dropped_events_total";
+const QUEUE_DEPTH: &str = "notify_queue_depth";
+const DELIVERY_DURATIONS: &str = "notify_delivery_duration_us";
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct Config {
+    /// Unix domain socket to publish the events on, see [start_publisher]
+    #[serde(default)]
+    pub publisher_socket: Option<PathBuf>,
+}
 
+/// Published when the main chain switches to another fork
 #[derive(Clone, PartialEq, Debug, Default)]
-pub struct ForkBlocks {
-    olds: Vec<Block>,
-    news: Vec<Block>,
+pub struct ForkSwitch {
+    /// Blocks removed from the main chain, from the old tip downward
+    pub detached: Vec<Block>,
+    /// Blocks added to the main chain, in ascending order ending with the new tip
+    pub attached: Vec<Block>,
 }
 
-impl ForkBlocks {
-    pub fn new(olds: Vec<Block>, news: Vec<Block>) -> Self {
-        ForkBlocks { olds, news }
+impl ForkSwitch {
+    pub fn new(detached: Vec<Block>, attached: Vec<Block>) -> Self {
+        ForkSwitch { detached, attached }
     }
+}
 
-    pub fn old_blks(&self) -> &Vec<Block> {
-        &self.olds
-    }
+/// Why a transaction left the pool without being committed
+#[derive(Clone, PartialEq, Debug)]
+pub enum RemovedReason {
+    /// Conflicts with a committed transaction, or spends the outputs of one that does
+    Conflict,
+    /// Failed verification when resolved again
+    Invalid,
+    /// Rejected by pool policy
+    Rejected,
+    /// Evicted to make room for a local transaction
+    Evicted,
+    /// Dropped with the whole pool by `clear_tx_pool`
+    Cleared,
+}
 
-    pub fn new_blks(&self) -> &Vec<Block> {
-        &self.news
-    }
+#[derive(Clone, PartialEq, Debug)]
+pub enum PoolEvent {
+    TxAdded {
+        hash: H256,
+        size: usize,
+    },
+    TxRemoved {
+        hash: H256,
+        reason: RemovedReason,
+    },
+    TxReplaced {
+        hash: H256,
+        by: H256,
+    },
+    TxExpired {
+        hash: H256,
+    },
+    TxConfirmed {
+        hash: H256,
+        block_hash: H256,
+        block_number: BlockNumber,
+    },
+}
 
-    pub fn push_new(&mut self, b: Block) {
-        self.news.push(b);
+impl PoolEvent {
+    pub fn tx_hash(&self) -> &H256 {
+        match self {
+            PoolEvent::TxAdded { hash, .. }
+            | PoolEvent::TxRemoved { hash, .. }
+            | PoolEvent::TxReplaced { hash, .. }
+            | PoolEvent::TxExpired { hash }
+            | PoolEvent::TxConfirmed { hash, .. } => hash,
+        }
     }
 }
 
 pub type MsgNewTransaction = ();
 pub type MsgNewTip = Arc<Block>;
 pub type MsgNewUncle = Arc<Block>;
-pub type MsgSwitchFork = Arc<ForkBlocks>;
-pub type NotifyRegister<M> = Sender<Request<(String, usize), Receiver<M>>>;
+pub type MsgSwitchFork = Arc<ForkSwitch>;
+pub type MsgPoolEvent = Arc<PoolEvent>;
+/// Same as `ckb_network::PeerIndex`
+pub type PeerIndex = usize;
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Event {
+    /// A transaction entered the pool
+    NewTransaction,
+    NewTip(MsgNewTip),
+    /// An uncle block was stored
+    NewUncle(MsgNewUncle),
+    SwitchFork(MsgSwitchFork),
+    Pool(MsgPoolEvent),
+    /// A peer opened the sync protocol
+    PeerConnected(PeerIndex),
+    PeerDisconnected(PeerIndex),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum EventKind {
+    NewTransaction,
+    NewTip,
+    NewUncle,
+    SwitchFork,
+    Pool,
+    PeerConnected,
+    PeerDisconnected,
+}
+
+impl Event {
+    pub fn kind(&self) -> EventKind {
+        match self {
+            Event::NewTransaction => EventKind::NewTransaction,
+            Event::NewTip(_) => EventKind::NewTip,
+            Event::NewUncle(_) => EventKind::NewUncle,
+            Event::SwitchFork(_) => EventKind::SwitchFork,
+            Event::Pool(_) => EventKind::Pool,
+            Event::PeerConnected(_) => EventKind::PeerConnected,
+            Event::PeerDisconnected(_) => EventKind::PeerDisconnected,
+        }
+    }
+}
+
+/// What to do with an event for a subscriber whose channel is full
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Overflow {
+    /// Wait for the subscriber, which stalls the publishers once the notify
+    /// channel is full too
+    Block,
+    /// Drop the oldest queued event
+    DropOldest,
+    /// Drop the queued events of the same kind, keeping the new one, or the
+    /// oldest event if none is of the same kind
+    Coalesce,
+}
+
+/// Narrows the events carrying blocks, the others are not filtered
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Filter {
+    /// Blocks above the number, a fork switch attaching one of them
+    BlocksAbove(BlockNumber),
+    /// Blocks committing a transaction which creates cells of the lock, a
+    /// fork switch attaching or detaching one of them
+    LockHash(H256),
+}
+
+impl Filter {
+    fn matches(&self, event: &Event) -> bool {
+        match event {
+            Event::NewTip(block) | Event::NewUncle(block) => self.matches_block(block),
+            Event::SwitchFork(fork) => match self {
+                Filter::BlocksAbove(_) => fork.attached.iter().any(|b| self.matches_block(b)),
+                Filter::LockHash(_) => fork
+                    .attached
+                    .iter()
+                    .chain(fork.detached.iter())
+                    .any(|b| self.matches_block(b)),
+            },
+            _ => true,
+        }
+    }
+
+    fn matches_block(&self, block: &Block) -> bool {
+        match self {
+            Filter::BlocksAbove(number) => block.header().number() > *number,
+            Filter::LockHash(lock_hash) => block
+                .commit_transactions()
+                .iter()
+                .flat_map(|tx| tx.outputs())
+                .any(|output| &output.lock == lock_hash),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SubscriptionConfig {
+    /// Events queued for the subscriber before [Overflow] applies
+    pub capacity: usize,
+    pub overflow: Overflow,
+    /// Only the events matching all of them are sent
+    pub filters: Vec<Filter>,
+    /// Sends the last events of the subscribed kinds on registration, up to
+    /// [REPLAY_BUFFER_SIZE] of every kind
+    pub replay: bool,
+}
+
+impl Default for SubscriptionConfig {
+    fn default() -> Self {
+        SubscriptionConfig {
+            capacity: NOTIFY_CHANNEL_SIZE,
+            overflow: Overflow::Block,
+            filters: Vec::new(),
+            replay: false,
+        }
+    }
+}
+
+/// The sending side of one subscription, with a receiver of its own to take
+/// queued events out on overflow.
+///
+/// That receiver keeps the channel connected, so a subscriber which does not
+/// block stays registered after dropping its receiver. Its queue is bounded.
+struct Channel<M> {
+    sender: Sender<M>,
+    capacity: usize,
+    queue: Option<Receiver<M>>,
+    overflow: Overflow,
+    dropped: Arc<Counter>,
+    depth: Arc<Gauge>,
+    latency: Arc<Histogram>,
+}
+
+impl<M> Channel<M> {
+    fn new(name: &str, config: &SubscriptionConfig) -> (Channel<M>, Receiver<M>) {
+        let capacity = config.capacity.max(1);
+        let (sender, receiver) = crossbeam_channel::bounded(capacity);
+        let queue = match config.overflow {
+            Overflow::Block => None,
+            _ => Some(receiver.clone()),
+        };
+        let labels = || vec![("subscriber", name.to_owned())];
+        let channel = Channel {
+            sender,
+            capacity,
+            queue,
+            overflow: config.overflow,
+            dropped: registry().counter(DROPPED_EVENTS, labels()),
+            depth: registry().gauge(QUEUE_DEPTH, labels()),
+            latency: registry().histogram(DELIVERY_DURATIONS, labels(), LATENCY_BUCKETS),
+        };
+        (channel, receiver)
+    }
+
+    /// Queues the event published at `published_at`, false once the
+    /// subscriber is gone
+    fn send<F: Fn(&M) -> bool>(&self, msg: M, same_kind: F, published_at: Instant) -> bool {
+        let sent = self.push(msg, same_kind);
+        if sent {
+            self.depth.set(self.sender.len());
+            self.latency.observe_duration(published_at.elapsed());
+        }
+        sent
+    }
+
+    fn push<F: Fn(&M) -> bool>(&self, msg: M, same_kind: F) -> bool {
+        let queue = match self.queue {
+            Some(ref queue) => queue,
+            None => return self.sender.send(msg).is_ok(),
+        };
+        let mut msg = msg;
+        loop {
+            match self.sender.try_send(msg) {
+                Ok(()) => return true,
+                Err(TrySendError::Disconnected(_)) => return false,
+                Err(TrySendError::Full(full)) => msg = full,
+            }
+            // The subscriber may take events meanwhile, which only leaves
+            // more room
+            let dropped = match self.overflow {
+                Overflow::Coalesce => {
+                    let queued: Vec<M> = queue.try_iter().collect();
+                    let len = queued.len();
+                    let mut kept: Vec<M> = queued.into_iter().filter(|m| !same_kind(m)).collect();
+                    if kept.len() == len && !kept.is_empty() {
+                        kept.remove(0);
+                    }
+                    let dropped = len - kept.len();
+                    for queued in kept {
+                        let _ = self.sender.try_send(queued);
+                    }
+                    dropped
+                }
+                _ => queue.try_recv().map(|_| 1).unwrap_or(0),
+            };
+            self.dropped.add(dropped);
+        }
+    }
+}
+
+enum Subscriber {
+    NewTransaction(Channel<MsgNewTransaction>),
+    NewTip(Channel<MsgNewTip>),
+    NewUncle(Channel<MsgNewUncle>),
+    SwitchFork(Channel<MsgSwitchFork>),
+    Pool(Channel<MsgPoolEvent>),
+    Events(Vec<EventKind>, Vec<Filter>, Channel<Event>),
+}
+
+impl Subscriber {
+    /// Sends the event if the subscriber wants it, false once its receiver
+    /// is dropped
+    fn send(&self, event: &Event, published_at: Instant) -> bool {
+        match (self, event) {
+            (Subscriber::NewTransaction(channel), Event::NewTransaction) => {
+                channel.send((), |_| true, published_at)
+            }
+            (Subscriber::NewTip(channel), Event::NewTip(block)) => {
+                channel.send(Arc::clone(block), |_| true, published_at)
+            }
+            (Subscriber::NewUncle(channel), Event::NewUncle(block)) => {
+                channel.send(Arc::clone(block), |_| true, published_at)
+            }
+            (Subscriber::SwitchFork(channel), Event::SwitchFork(fork)) => {
+                channel.send(Arc::clone(fork), |_| true, published_at)
+            }
+            (Subscriber::Pool(channel), Event::Pool(pool_event)) => {
+                channel.send(Arc::clone(pool_event), |_| true, published_at)
+            }
+            (Subscriber::Events(kinds, filters, channel), event)
+                if kinds.contains(&event.kind()) && filters.iter().all(|f| f.matches(event)) =>
+            {
+                let kind = event.kind();
+                channel.send(event.clone(), |queued| queued.kind() == kind, published_at)
+            }
+            _ => true,
+        }
+    }
+
+    /// Sends the recent events the subscriber wants, no more than its
+    /// channel holds as it is received from once registered only
+    fn replay(&self, recent: &Recent) {
+        if let Subscriber::Events(kinds, filters, channel) = self {
+            let events: Vec<_> = recent
+                .replay()
+                .into_iter()
+                .filter(|event| {
+                    kinds.contains(&event.kind()) && filters.iter().all(|f| f.matches(event))
+                })
+                .collect();
+            let skip = events.len().saturating_sub(channel.capacity);
+            for event in events.into_iter().skip(skip) {
+                channel.push(event.clone(), |_| false);
+            }
+        }
+    }
+}
+
+/// The last published events of every kind, numbered to be replayed in
+/// publishing order
+#[derive(Default)]
+struct Recent {
+    next: u64,
+    events: FnvHashMap<EventKind, VecDeque<(u64, Event)>>,
+}
+
+impl Recent {
+    fn push(&mut self, event: &Event) {
+        let events = self
+            .events
+            .entry(event.kind())
+            .or_insert_with(VecDeque::new);
+        if events.len() >= REPLAY_BUFFER_SIZE {
+            events.pop_front();
+        }
+        events.push_back((self.next, event.clone()));
+        self.next += 1;
+    }
+
+    fn replay(&self) -> Vec<&Event> {
+        let mut events: Vec<_> = self.events.values().flat_map(VecDeque::iter).collect();
+        events.sort_by_key(|(number, _)| *number);
+        events.into_iter().map(|(_, event)| event).collect()
+    }
+}
+
+type NotifyRegister = Sender<Request<(String, Subscriber, bool), ()>>;
 
 #[derive(Default)]
 pub struct NotifyService {}
@@ -49,14 +414,8 @@ pub struct NotifyService {}
 #[derive(Clone)]
 pub struct NotifyController {
     stop: StopHandler<()>,
-    new_transaction_register: NotifyRegister<MsgNewTransaction>,
-    new_tip_register: NotifyRegister<MsgNewTip>,
-    new_uncle_register: NotifyRegister<MsgNewUncle>,
-    switch_fork_register: NotifyRegister<MsgSwitchFork>,
-    new_transaction_notifier: Sender<MsgNewTransaction>,
-    new_tip_notifier: Sender<MsgNewTip>,
-    new_uncle_notifier: Sender<MsgNewUncle>,
-    switch_fork_notifier: Sender<MsgSwitchFork>,
+    register: NotifyRegister,
+    notifier: Sender<(Instant, Event)>,
 }
 
 impl Drop for NotifyController {
@@ -69,28 +428,12 @@ impl NotifyService {
     pub fn start<S: ToString>(self, thread_name: Option<S>) -> NotifyController {
         let (signal_sender, signal_receiver) =
             crossbeam_channel::bounded::<()>(SIGNAL_CHANNEL_SIZE);
-        let (new_transaction_register, new_transaction_register_receiver) =
-            crossbeam_channel::bounded(REGISTER_CHANNEL_SIZE);
-        let (new_tip_register, new_tip_register_receiver) =
-            crossbeam_channel::bounded(REGISTER_CHANNEL_SIZE);
-        let (new_uncle_register, new_uncle_register_receiver) =
-            crossbeam_channel::bounded(REGISTER_CHANNEL_SIZE);
-        let (switch_fork_register, switch_fork_register_receiver) =
-            crossbeam_channel::bounded(REGISTER_CHANNEL_SIZE);
-
-        let (new_transaction_sender, new_transaction_receiver) =
-            crossbeam_channel::bounded::<MsgNewTransaction>(NOTIFY_CHANNEL_SIZE);
-        let (new_tip_sender, new_tip_receiver) =
-            crossbeam_channel::bounded::<MsgNewTip>(NOTIFY_CHANNEL_SIZE);
-        let (new_uncle_sender, new_uncle_receiver) =
-            crossbeam_channel::bounded::<MsgNewUncle>(NOTIFY_CHANNEL_SIZE);
-        let (switch_fork_sender, switch_fork_receiver) =
-            crossbeam_channel::bounded::<MsgSwitchFork>(NOTIFY_CHANNEL_SIZE);
-
-        let mut new_transaction_subscribers = FnvHashMap::default();
-        let mut new_tip_subscribers = FnvHashMap::default();
-        let mut new_uncle_subscribers = FnvHashMap::default();
-        let mut switch_fork_subscribers = FnvHashMap::default();
+        let (register, register_receiver) = crossbeam_channel::bounded(REGISTER_CHANNEL_SIZE);
+        let (notifier, event_receiver) = crossbeam_channel::bounded(NOTIFY_CHANNEL_SIZE);
+
+        // Names are only for the logs, two subscribers may share one
+        let mut subscribers: Vec<(String, Subscriber)> = Vec::new();
+        let mut recent = Recent::default();
 
         let mut thread_builder = thread::Builder::new();
         // Mainly for test: give a empty thread_name
@@ -103,222 +446,153 @@ impl NotifyService {
                     recv(signal_receiver) -> _ => {
                         break;
                     }
-
-                    recv(new_transaction_register_receiver) -> msg => Self::handle_register_new_transaction(
-                        &mut new_transaction_subscribers, msg
-                    ),
-                    recv(new_tip_register_receiver) -> msg => Self::handle_register_new_tip(
-                        &mut new_tip_subscribers, msg
-                    ),
-                    recv(new_uncle_register_receiver) -> msg => Self::handle_register_new_uncle(
-                        &mut new_uncle_subscribers, msg
-                    ),
-                    recv(switch_fork_register_receiver) -> msg => Self::handle_register_switch_fork(
-                        &mut switch_fork_subscribers, msg
-                    ),
-
-                    recv(new_transaction_receiver) -> msg => Self::handle_notify_new_transaction(
-                        &new_transaction_subscribers, msg
-                    ),
-                    recv(new_tip_receiver) -> msg => Self::handle_notify_new_tip(
-                        &new_tip_subscribers, msg
-                    ),
-                    recv(new_uncle_receiver) -> msg => Self::handle_notify_new_uncle(
-                        &new_uncle_subscribers, msg
-                    ),
-                    recv(switch_fork_receiver) -> msg => Self::handle_notify_switch_fork(
-                        &switch_fork_subscribers, msg
-                    )
+                    recv(register_receiver) -> msg => {
+                        Self::handle_register(&mut subscribers, &recent, msg)
+                    }
+                    recv(event_receiver) -> msg => {
+                        Self::handle_notify(&mut subscribers, &mut recent, msg)
+                    }
                 }
             }).expect("Start notify service failed");
 
         NotifyController {
-            new_transaction_register,
-            new_tip_register,
-            new_uncle_register,
-            switch_fork_register,
-            new_transaction_notifier: new_transaction_sender,
-            new_tip_notifier: new_tip_sender,
-            new_uncle_notifier: new_uncle_sender,
-            switch_fork_notifier: switch_fork_sender,
+            register,
+            notifier,
             stop: StopHandler::new(SignalSender::Crossbeam(signal_sender), join_handle),
         }
     }
 
-    fn handle_register_new_transaction(
-        subscribers: &mut FnvHashMap<String, Sender<MsgNewTransaction>>,
-        msg: Result<
-            Request<(String, usize), Receiver<MsgNewTransaction>>,
-            crossbeam_channel::RecvError,
-        >,
-    ) {
-        match msg {
-            Ok(Request {
-                responder,
-                arguments: (name, capacity),
-            }) => {
-                debug!(target: "notify", "Register new_transaction {:?}", name);
-                let (sender, receiver) = crossbeam_channel::bounded::<MsgNewTransaction>(capacity);
-                subscribers.insert(name, sender);
-                let _ = responder.send(receiver);
-            }
-            _ => warn!(target: "notify", "Register new_transaction channel is closed"),
-        }
-    }
-
-    fn handle_register_new_tip(
-        subscribers: &mut FnvHashMap<String, Sender<MsgNewTip>>,
-        msg: Result<Request<(String, usize), Receiver<MsgNewTip>>, crossbeam_channel::RecvError>,
+    fn handle_register(
+        subscribers: &mut Vec<(String, Subscriber)>,
+        recent: &Recent,
+        msg: Result<Request<(String, Subscriber, bool), ()>, crossbeam_channel::RecvError>,
     ) {
         match msg {
             Ok(Request {
                 responder,
-                arguments: (name, capacity),
+                arguments: (name, subscriber, replay),
             }) => {
-                debug!(target: "notify", "Register new_tip {:?}", name);
-                let (sender, receiver) = crossbeam_channel::bounded::<MsgNewTip>(capacity);
-                subscribers.insert(name, sender);
-                let _ = responder.send(receiver);
-            }
-            _ => warn!(target: "notify", "Register new_tip channel is closed"),
-        }
-    }
-
-    fn handle_register_new_uncle(
-        subscribers: &mut FnvHashMap<String, Sender<MsgNewUncle>>,
-        msg: Result<Request<(String, usize), Receiver<MsgNewUncle>>, crossbeam_channel::RecvError>,
-    ) {
-        match msg {
-            Ok(Request {
-                responder,
-                arguments: (name, capacity),
-            }) => {
-                debug!(target: "notify", "Register new_uncle {:?}", name);
-                let (sender, receiver) = crossbeam_channel::bounded::<MsgNewUncle>(capacity);
-                subscribers.insert(name, sender);
-                let _ = responder.send(receiver);
+                debug!(target: "notify", "Register {:?}", name);
+                if replay {
+                    subscriber.replay(recent);
+                }
+                subscribers.push((name, subscriber));
+                let _ = responder.send(());
             }
-            _ => warn!(target: "notify", "Register new_uncle channel is closed"),
+            _ => warn!(target: "notify", "Register channel is closed"),
         }
     }
 
-    fn handle_register_switch_fork(
-        subscribers: &mut FnvHashMap<String, Sender<MsgSwitchFork>>,
-        msg: Result<
-            Request<(String, usize), Receiver<MsgSwitchFork>>,
-            crossbeam_channel::RecvError,
-        >,
+    fn handle_notify(
+        subscribers: &mut Vec<(String, Subscriber)>,
+        recent: &mut Recent,
+        msg: Result<(Instant, Event), crossbeam_channel::RecvError>,
     ) {
         match msg {
-            Ok(Request {
-                responder,
-                arguments: (name, capacity),
-            }) => {
-                debug!(target: "notify", "Register switch_fork {:?}", name);
-                let (sender, receiver) = crossbeam_channel::bounded::<MsgSwitchFork>(capacity);
-                subscribers.insert(name, sender);
-                let _ = responder.send(receiver);
+            Ok((published_at, event)) => {
+                trace!(target: "notify", "event {:?}", event);
+                recent.push(&event);
+                subscribers.retain(|(name, subscriber)| {
+                    let sent = subscriber.send(&event, published_at);
+                    if !sent {
+                        debug!(target: "notify", "Unregister {:?}, receiver dropped", name);
+                    }
+                    sent
+                });
             }
-            _ => warn!(target: "notify", "Register switch_fork channel is closed"),
+            _ => warn!(target: "notify", "event channel is closed"),
         }
     }
+}
 
-    fn handle_notify_new_transaction(
-        subscribers: &FnvHashMap<String, Sender<MsgNewTransaction>>,
-        msg: Result<MsgNewTransaction, crossbeam_channel::RecvError>,
-    ) {
-        match msg {
-            Ok(()) => {
-                trace!(target: "notify", "event new transaction {:?}", msg);
-                for subscriber in subscribers.values() {
-                    let _ = subscriber.send(());
-                }
-            }
-            _ => warn!(target: "notify", "new transaction channel is closed"),
-        }
+impl NotifyController {
+    fn register<S: ToString>(&self, name: S, subscriber: Subscriber, replay: bool) {
+        Request::call(&self.register, (name.to_string(), subscriber, replay))
+            .expect("Subscribe failed")
     }
 
-    fn handle_notify_new_tip(
-        subscribers: &FnvHashMap<String, Sender<MsgNewTip>>,
-        msg: Result<MsgNewTip, crossbeam_channel::RecvError>,
-    ) {
-        match msg {
-            Ok(msg) => {
-                trace!(target: "notify", "event new tip {:?}", msg);
-                for subscriber in subscribers.values() {
-                    let _ = subscriber.send(Arc::clone(&msg));
-                }
-            }
-            _ => warn!(target: "notify", "new tip channel is closed"),
-        }
+    /// Stops delivering the events, the receivers of the subscribers are
+    /// closed once they took the queued ones
+    pub fn stop(&self) {
+        self.stop.stop();
     }
 
-    fn handle_notify_new_uncle(
-        subscribers: &FnvHashMap<String, Sender<MsgNewUncle>>,
-        msg: Result<MsgNewUncle, crossbeam_channel::RecvError>,
-    ) {
-        match msg {
-            Ok(msg) => {
-                trace!(target: "notify", "event new uncle {:?}", msg);
-                for subscriber in subscribers.values() {
-                    let _ = subscriber.send(Arc::clone(&msg));
-                }
-            }
-            _ => warn!(target: "notify", "new uncle channel is closed"),
-        }
+    /// Subscribes to the events of `kinds`, in the order they are published
+    pub fn subscribe<S: ToString>(&self, name: S, kinds: &[EventKind]) -> Receiver<Event> {
+        self.subscribe_with(name, kinds, SubscriptionConfig::default())
     }
-
-    fn handle_notify_switch_fork(
-        subscribers: &FnvHashMap<String, Sender<MsgSwitchFork>>,
-        msg: Result<MsgSwitchFork, crossbeam_channel::RecvError>,
-    ) {
-        match msg {
-            Ok(msg) => {
-                trace!(target: "notify", "event switch fork {:?}", msg);
-                for subscriber in subscribers.values() {
-                    let _ = subscriber.send(Arc::clone(&msg));
-                }
-            }
-            _ => warn!(target: "notify", "event 3 channel is closed"),
-        }
+    /// Subscribes to the events of `kinds` passing the filters of `config`,
+    /// queued as it tells, the dropped ones are counted in
+    /// `notify_dropped_events_total`
+    pub fn subscribe_with<S: ToString>(
+        &self,
+        name: S,
+        kinds: &[EventKind],
+        config: SubscriptionConfig,
+    ) -> Receiver<Event> {
+        let name = name.to_string();
+        let (channel, receiver) = Channel::new(&name, &config);
+        let subscriber = Subscriber::Events(kinds.to_vec(), config.filters, channel);
+        self.register(name, subscriber, config.replay);
+        receiver
     }
-}
-
-impl NotifyController {
     pub fn subscribe_new_transaction<S: ToString>(&self, name: S) -> Receiver<MsgNewTransaction> {
-        Request::call(&self.new_transaction_register, (name.to_string(), 128))
-            .expect("Subscribe new transaction failed")
+        let name = name.to_string();
+        let (channel, receiver) = Channel::new(&name, &SubscriptionConfig::default());
+        self.register(name, Subscriber::NewTransaction(channel), false);
+        receiver
     }
     pub fn subscribe_new_tip<S: ToString>(&self, name: S) -> Receiver<MsgNewTip> {
-        Request::call(&self.new_tip_register, (name.to_string(), 128))
-            .expect("Subscribe new tip failed")
+        let name = name.to_string();
+        let (channel, receiver) = Channel::new(&name, &SubscriptionConfig::default());
+        self.register(name, Subscriber::NewTip(channel), false);
+        receiver
     }
     pub fn subscribe_new_uncle<S: ToString>(&self, name: S) -> Receiver<MsgNewUncle> {
-        Request::call(&self.new_uncle_register, (name.to_string(), 128))
-            .expect("Subscribe new uncle failed")
+        let name = name.to_string();
+        let (channel, receiver) = Channel::new(&name, &SubscriptionConfig::default());
+        self.register(name, Subscriber::NewUncle(channel), false);
+        receiver
     }
     pub fn subscribe_switch_fork<S: ToString>(&self, name: S) -> Receiver<MsgSwitchFork> {
-        Request::call(&self.switch_fork_register, (name.to_string(), 128))
-            .expect("Subscribe switch fork failed")
+        let name = name.to_string();
+        let (channel, receiver) = Channel::new(&name, &SubscriptionConfig::default());
+        self.register(name, Subscriber::SwitchFork(channel), false);
+        receiver
+    }
+    pub fn subscribe_pool_event<S: ToString>(&self, name: S) -> Receiver<MsgPoolEvent> {
+        let name = name.to_string();
+        let (channel, receiver) = Channel::new(&name, &SubscriptionConfig::default());
+        self.register(name, Subscriber::Pool(channel), false);
+        receiver
     }
 
+    pub fn notify(&self, event: Event) {
+        let _ = self.notifier.send((Instant::now(), event));
+    }
     pub fn notify_new_transaction(&self) {
-        let _ = self.new_transaction_notifier.send(());
+        self.notify(Event::NewTransaction);
     }
     pub fn notify_new_tip(&self, block: MsgNewTip) {
-        let _ = self.new_tip_notifier.send(block);
+        self.notify(Event::NewTip(block));
     }
     pub fn notify_new_uncle(&self, block: MsgNewUncle) {
-        let _ = self.new_uncle_notifier.send(block);
+        self.notify(Event::NewUncle(block));
     }
-    pub fn notify_switch_fork(&self, txs: MsgSwitchFork) {
-        let _ = self.switch_fork_notifier.send(txs);
+    pub fn notify_switch_fork(&self, fork: MsgSwitchFork) {
+        self.notify(Event::SwitchFork(fork));
+    }
+    pub fn notify_pool_event(&self, event: PoolEvent) {
+        self.notify(Event::Pool(Arc::new(event)));
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use ckb_core::block::BlockBuilder;
+    use ckb_core::header::HeaderBuilder;
+    use ckb_core::transaction::{CellOutput, TransactionBuilder};
 
     #[test]
     fn test_new_transaction() {
@@ -343,7 +617,7 @@ mod tests {
 
     #[test]
     fn test_switch_fork() {
-        let blks = Arc::new(ForkBlocks::default());
+        let blks = Arc::new(ForkSwitch::default());
         let notify = NotifyService::default().start::<&str>(None);
         let receiver1 = notify.subscribe_switch_fork("miner1");
         let receiver2 = notify.subscribe_switch_fork("miner2");
@@ -351,4 +625,159 @@ mod tests {
         assert_eq!(receiver1.recv(), Ok(Arc::clone(&blks)));
         assert_eq!(receiver2.recv(), Ok(blks));
     }
+
+    #[test]
+    fn test_pool_event() {
+        let event = PoolEvent::TxExpired { hash: H256::zero() };
+        let notify = NotifyService::default().start::<&str>(None);
+        let receiver1 = notify.subscribe_pool_event("rpc");
+        let receiver2 = notify.subscribe_pool_event("indexer");
+        notify.notify_pool_event(event.clone());
+        assert_eq!(receiver1.recv(), Ok(Arc::new(event.clone())));
+        assert_eq!(receiver2.recv().unwrap().tx_hash(), event.tx_hash());
+    }
+
+    #[test]
+    fn test_events_in_order() {
+        let tip = Arc::new(Block::default());
+        let fork = Arc::new(ForkSwitch::default());
+        let notify = NotifyService::default().start::<&str>(None);
+        let receiver = notify.subscribe("indexer", &[EventKind::SwitchFork, EventKind::NewTip]);
+        let dropped = notify.subscribe_new_tip("dropped");
+        drop(dropped);
+        notify.notify_new_uncle(Arc::clone(&tip));
+        notify.notify_switch_fork(Arc::clone(&fork));
+        notify.notify_new_tip(Arc::clone(&tip));
+        notify.notify(Event::PeerConnected(1));
+        notify.notify_new_tip(Arc::clone(&tip));
+        assert_eq!(receiver.recv(), Ok(Event::SwitchFork(fork)));
+        assert_eq!(receiver.recv(), Ok(Event::NewTip(Arc::clone(&tip))));
+        assert_eq!(receiver.recv(), Ok(Event::NewTip(tip)));
+    }
+
+    #[test]
+    fn test_overflow() {
+        let tip = Arc::new(Block::default());
+        let notify = NotifyService::default().start::<&str>(None);
+        let kinds = [EventKind::NewTip, EventKind::PeerConnected];
+        let config = |overflow| SubscriptionConfig {
+            capacity: 2,
+            overflow,
+            ..Default::default()
+        };
+        let drop_oldest =
+            notify.subscribe_with("drop_oldest", &kinds, config(Overflow::DropOldest));
+        let coalesce = notify.subscribe_with("coalesce", &kinds, config(Overflow::Coalesce));
+        // Subscribed last, gets every event after the others
+        let block = notify.subscribe_new_tip("block");
+        notify.notify(Event::PeerConnected(1));
+        notify.notify_new_tip(Arc::clone(&tip));
+        notify.notify(Event::PeerConnected(2));
+        notify.notify_new_tip(Arc::clone(&tip));
+        notify.notify_new_tip(Arc::clone(&tip));
+        for _ in 0..3 {
+            assert_eq!(block.recv(), Ok(Arc::clone(&tip)));
+        }
+
+        let received: Vec<_> = drop_oldest.try_iter().collect();
+        assert_eq!(
+            received,
+            vec![Event::NewTip(Arc::clone(&tip)), Event::NewTip(Arc::clone(&tip))]
+        );
+        let received: Vec<_> = coalesce.try_iter().collect();
+        assert_eq!(
+            received,
+            vec![Event::PeerConnected(2), Event::NewTip(Arc::clone(&tip))]
+        );
+        let dropped = |name: &str| {
+            registry()
+                .counter(DROPPED_EVENTS, vec![("subscriber", name.to_owned())])
+                .get()
+        };
+        assert_eq!(dropped("drop_oldest"), 3);
+        assert_eq!(dropped("coalesce"), 3);
+        let labels = vec![("subscriber", "drop_oldest".to_owned())];
+        assert_eq!(registry().gauge(QUEUE_DEPTH, labels.clone()).get(), 2);
+        let latency = registry().histogram(DELIVERY_DURATIONS, labels, LATENCY_BUCKETS);
+        assert_eq!(latency.snapshot().count, 5);
+    }
+
+    #[test]
+    fn test_filters() {
+        let lock_hash = H256::from_trimmed_hex_str("1").unwrap();
+        let block = |number, lock: &H256| {
+            let tx = TransactionBuilder::default()
+                .output(CellOutput::new(100, vec![], lock.clone(), None))
+                .build();
+            Arc::new(
+                BlockBuilder::default()
+                    .commit_transaction(tx)
+                    .with_header_builder(HeaderBuilder::default().number(number)),
+            )
+        };
+        let notify = NotifyService::default().start::<&str>(None);
+        let config = SubscriptionConfig {
+            filters: vec![Filter::BlocksAbove(10), Filter::LockHash(lock_hash.clone())],
+            ..Default::default()
+        };
+        let kinds = [EventKind::NewTip, EventKind::SwitchFork, EventKind::PeerConnected];
+        let receiver = notify.subscribe_with("filtered", &kinds, config);
+        let tips = notify.subscribe_new_tip("tips");
+        notify.notify_new_tip(block(11, &H256::zero()));
+        notify.notify_new_tip(block(10, &lock_hash));
+        notify.notify_new_tip(block(12, &lock_hash));
+        notify.notify(Event::PeerConnected(1));
+        let fork = ForkSwitch::new(vec![block(12, &lock_hash)], vec![block(9, &H256::zero())]);
+        notify.notify_switch_fork(Arc::new(fork));
+        notify.notify_new_tip(block(13, &H256::zero()));
+        for _ in 0..4 {
+            tips.recv().unwrap();
+        }
+
+        let received: Vec<_> = receiver.try_iter().collect();
+        assert_eq!(
+            received,
+            vec![Event::NewTip(block(12, &lock_hash)), Event::PeerConnected(1)]
+        );
+    }
+
+    #[test]
+    fn test_replay() {
+        let tip1 = Arc::new(Block::default());
+        let tip2 = Arc::new(
+            BlockBuilder::default().with_header_builder(HeaderBuilder::default().number(1)),
+        );
+        let notify = NotifyService::default().start::<&str>(None);
+        let tips = notify.subscribe_new_tip("tips");
+        notify.notify_new_tip(Arc::clone(&tip1));
+        notify.notify(Event::PeerConnected(1));
+        notify.notify_new_transaction();
+        notify.notify_new_tip(Arc::clone(&tip2));
+        for _ in 0..2 {
+            tips.recv().unwrap();
+        }
+
+        let kinds = [EventKind::NewTip, EventKind::PeerConnected];
+        let config = SubscriptionConfig {
+            replay: true,
+            ..Default::default()
+        };
+        let receiver = notify.subscribe_with("late", &kinds, config.clone());
+        assert_eq!(
+            receiver.try_iter().collect::<Vec<_>>(),
+            vec![
+                Event::NewTip(Arc::clone(&tip1)),
+                Event::PeerConnected(1),
+                Event::NewTip(Arc::clone(&tip2)),
+            ]
+        );
+        let config = SubscriptionConfig {
+            capacity: 1,
+            ..config
+        };
+        let receiver = notify.subscribe_with("late", &kinds, config);
+        assert_eq!(receiver.try_iter().collect::<Vec<_>>(), vec![Event::NewTip(tip2)]);
+        let receiver = notify.subscribe("not_replayed", &kinds);
+        assert!(receiver.try_recv().is_err());
+    }
 }
@@ -0,0 +1,205 @@
+//! Mirrors the new tips, the fork switches and the transactions added to the
+//! pool onto a Unix domain socket, for the programs which do not link against
+//! the node. Every connected client reads one JSON object per line:
+//!
+//! ```text
+//! {"type":"new_tip","number":12,"block_hash":"0x..."}
+//! {"type":"switch_fork","detached":["0x..."],"attached":["0x..."]}
+//! {"type":"new_transaction","tx_hash":"0x..."}
+//! ```
+//!
+//! A client which does not read for a second, or which falls 256 lines
+//! behind, is disconnected. The clients are written
+//! by their own threads, so a slow one does not delay the others.
+
+use crate::{Event, EventKind, NotifyController, Overflow, PoolEvent, SubscriptionConfig};
+use ckb_core::block::Block;
+use crossbeam_channel::{Sender, TrySendError};
+use log::{debug, error, info};
+use std::fs;
+use std::io::{self, Write};
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+const PUBLISHER_SUBSCRIBER: &str = "publisher";
+const PUBLISHER_CAPACITY: usize = 1024;
+const CLIENT_QUEUE_SIZE: usize = 256;
+const WRITE_TIMEOUT_SECS: u64 = 1;
+
+/// Listens on `path`, replacing the socket a previous run left, and
+/// publishes until the notify service stops. Any other file at `path` is
+/// kept and the bind fails.
+pub fn start_publisher(notify: &NotifyController, path: &Path) -> io::Result<()> {
+    if let Ok(metadata) = fs::symlink_metadata(path) {
+        if metadata.file_type().is_socket() {
+            fs::remove_file(path)?;
+        }
+    }
+    let listener = UnixListener::bind(path)?;
+    // Accepted by the publisher before each line, so a client connected
+    // before an event is notified gets it
+    listener.set_nonblocking(true)?;
+
+    // Falling behind drops the oldest events rather than stalling the chain
+    let event_receiver = notify.subscribe_with(
+        PUBLISHER_SUBSCRIBER,
+        &[EventKind::NewTip, EventKind::SwitchFork, EventKind::Pool],
+        SubscriptionConfig {
+            capacity: PUBLISHER_CAPACITY,
+            overflow: Overflow::DropOldest,
+            ..Default::default()
+        },
+    );
+    thread::Builder::new()
+        .name("publisher".to_string())
+        .spawn(move || {
+            let mut clients: Vec<Sender<Arc<String>>> = Vec::new();
+            for event in event_receiver {
+                if let Some(line) = to_line(&event) {
+                    accept_clients(&listener, &mut clients);
+                    write_line(&mut clients, &Arc::new(line));
+                }
+            }
+        })?;
+    info!(target: "notify", "publishing events on {}", path.display());
+    Ok(())
+}
+
+fn to_line(event: &Event) -> Option<String> {
+    let line = match event {
+        Event::NewTip(block) => format!(
+            r#"{{"type":"new_tip","number":{},"block_hash":"{:#x}"}}"#,
+            block.header().number(),
+            block.header().hash()
+        ),
+        Event::SwitchFork(fork) => format!(
+            r#"{{"type":"switch_fork","detached":[{}],"attached":[{}]}}"#,
+            block_hashes(&fork.detached),
+            block_hashes(&fork.attached)
+        ),
+        Event::Pool(pool_event) => match **pool_event {
+            PoolEvent::TxAdded { ref hash, .. } => {
+                format!(r#"{{"type":"new_transaction","tx_hash":"{:#x}"}}"#, hash)
+            }
+            _ => return None,
+        },
+        _ => return None,
+    };
+    Some(line + "\n")
+}
+
+fn accept_clients(listener: &UnixListener, clients: &mut Vec<Sender<Arc<String>>>) {
+    loop {
+        match listener.accept().and_then(|(stream, _)| spawn_client(stream)) {
+            Ok(client) => clients.push(client),
+            Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => break,
+            Err(err) => {
+                error!(target: "notify", "publisher accept: {}", err);
+                break;
+            }
+        }
+    }
+}
+
+// Writes the lines queued for the client on its own thread, the thread
+// stops once the client is gone or dropped
+fn spawn_client(mut stream: UnixStream) -> io::Result<Sender<Arc<String>>> {
+    stream.set_nonblocking(false)?;
+    stream.set_write_timeout(Some(Duration::from_secs(WRITE_TIMEOUT_SECS)))?;
+    let (sender, receiver) = crossbeam_channel::bounded::<Arc<String>>(CLIENT_QUEUE_SIZE);
+    thread::Builder::new()
+        .name("publisher_client".to_string())
+        .spawn(move || {
+            for line in receiver {
+                if let Err(err) = stream.write_all(line.as_bytes()) {
+                    debug!(target: "notify", "publisher client gone: {}", err);
+                    break;
+                }
+            }
+        })?;
+    Ok(sender)
+}
+
+fn write_line(clients: &mut Vec<Sender<Arc<String>>>, line: &Arc<String>) {
+    clients.retain(|client| match client.try_send(Arc::clone(line)) {
+        Ok(()) => true,
+        Err(TrySendError::Full(_)) => {
+            debug!(target: "notify", "publisher client fell behind");
+            false
+        }
+        Err(TrySendError::Disconnected(_)) => false,
+    });
+}
+
+fn block_hashes(blocks: &[Block]) -> String {
+    blocks
+        .iter()
+        .map(|block| format!(r#""{:#x}""#, block.header().hash()))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ForkSwitch, NotifyService};
+    use numext_fixed_hash::H256;
+    use std::io::{BufRead, BufReader};
+    use std::sync::Arc;
+    use tempfile;
+
+    #[test]
+    fn publish_lines() {
+        let tmp_dir = tempfile::Builder::new()
+            .prefix("publish_lines")
+            .tempdir()
+            .unwrap();
+        let path = tmp_dir.path().join("events.sock");
+        let notify = NotifyService::default().start::<&str>(None);
+        start_publisher(&notify, &path).unwrap();
+        // Accepted before the first line is published
+        let mut client = BufReader::new(UnixStream::connect(&path).unwrap());
+
+        let block = Arc::new(Block::default());
+        let hash = format!("{:#x}", block.header().hash());
+        notify.notify_pool_event(PoolEvent::TxExpired { hash: H256::zero() });
+        notify.notify_pool_event(PoolEvent::TxAdded {
+            hash: H256::zero(),
+            size: 1,
+        });
+        notify.notify_switch_fork(Arc::new(ForkSwitch::new(vec![], vec![(*block).clone()])));
+        notify.notify_new_tip(block);
+
+        let mut lines = Vec::new();
+        for _ in 0..3 {
+            let mut line = String::new();
+            client.read_line(&mut line).unwrap();
+            lines.push(line);
+        }
+        assert_eq!(
+            lines,
+            vec![
+                format!(r#"{{"type":"new_transaction","tx_hash":"{:#x}"}}"#, H256::zero()) + "\n",
+                format!(r#"{{"type":"switch_fork","detached":[],"attached":["{}"]}}"#, hash) + "\n",
+                format!(r#"{{"type":"new_tip","number":0,"block_hash":"{}"}}"#, hash) + "\n",
+            ]
+        );
+    }
+
+    #[test]
+    fn keep_other_files() {
+        let tmp_dir = tempfile::Builder::new()
+            .prefix("keep_other_files")
+            .tempdir()
+            .unwrap();
+        let path = tmp_dir.path().join("events.sock");
+        fs::write(&path, "data").unwrap();
+        let notify = NotifyService::default().start::<&str>(None);
+        assert!(start_publisher(&notify, &path).is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "data");
+    }
+}
@@ -2,20 +2,25 @@ use crate::txs_pool::pool::TransactionPoolService;
 use crate::txs_pool::trace::{Action, TxTrace};
 use crate::txs_pool::types::*;
 use ckb_chain::chain::{ChainBuilder, ChainController};
+use ckb_chain::test_utils::Fork;
 use ckb_chain_spec::consensus::Consensus;
 use ckb_core::block::{Block, BlockBuilder};
 use ckb_core::cell::{CellProvider, CellStatus};
+use ckb_core::generator::{Generator, GeneratorConfig};
 use ckb_core::header::HeaderBuilder;
 use ckb_core::script::Script;
 use ckb_core::transaction::*;
 use ckb_db::memorydb::MemoryKeyValueDB;
-use ckb_notify::{ForkBlocks, MsgNewTip, MsgSwitchFork, NotifyService};
+use ckb_notify::{
+    Event, EventKind, ForkSwitch, MsgPoolEvent, NotifyService, PoolEvent, RemovedReason,
+};
 use ckb_shared::index::ChainIndex;
 use ckb_shared::shared::{ChainProvider, Shared, SharedBuilder};
 use ckb_shared::store::ChainKVStore;
+use ckb_time::{unix_time_as_millis, Clock};
+use ckb_verification::{CellIndex, TransactionError};
 use crossbeam_channel::select;
 use crossbeam_channel::{self, Receiver};
-use faketime::unix_time_as_millis;
 use log::error;
 use numext_fixed_hash::H256;
 use std::fs::File;
@@ -23,7 +28,6 @@ use std::io::Read;
 use std::path::Path;
 use std::sync::Arc;
 use std::time;
-use tempfile::TempPath;
 
 macro_rules! expect_output_parent {
     ($pool:expr, $expected:pat, $( $output:expr ),+ ) => {
@@ -86,6 +90,7 @@ fn test_proposal_pool() {
         .build();
 
     pool.service.reconcile_block(&block);
+    pool.service.complete_verifications();
 
     assert_eq!(0, pool.service.pool_size());
     assert_eq!(20, pool.service.orphan_size());
@@ -457,24 +462,634 @@ fn test_switch_fork() {
         .with_header_builder(HeaderBuilder::default().number(block_number + 2));
 
     pool.service.reconcile_block(&block01);
+    pool.service.complete_verifications();
     pool.service.reconcile_block(&block02);
+    pool.service.complete_verifications();
 
     let olds = vec![block02, block01];
     let news = vec![block11, block12];
 
-    let fb = ForkBlocks::new(olds, news);
+    let fb = ForkSwitch::new(olds, news);
 
     pool.service.switch_fork(&fb);
+    pool.service.complete_verifications();
 
     let mtxs = pool.service.get_mineable_transactions(10);
 
     assert_eq!(mtxs, vec![txs[3].clone(), txs[6].clone(), txs[5].clone()]);
 }
 
+#[test]
+fn test_readd_detached_transactions() {
+    let mut pool = TestPool::<ChainKVStore<MemoryKeyValueDB>>::simple();
+    let tx = test_transaction(&[OutPoint::new(pool.tx_hash.clone(), 0)], 2);
+    let id = tx.proposal_short_id();
+    pool.service.add_to_pool(tx.clone()).unwrap();
+    assert_eq!(pool.service.get_status(&id), Some(TxStatus::Mineable));
+
+    let ancestor = pool.shared.chain_state().read().tip_hash();
+    Fork::new(&pool.shared, &pool.chain, &ancestor).push(vec![tx.clone()], vec![]);
+    pool.handle_notify_messages();
+    assert_eq!(pool.service.get_status(&id), None);
+
+    // The branch without the transaction becomes the main chain
+    let mut fork = Fork::new(&pool.shared, &pool.chain, &ancestor);
+    fork.overtake();
+    assert!(fork.is_main());
+    pool.handle_notify_messages();
+    assert_eq!(pool.service.get_status(&id), Some(TxStatus::Mineable));
+    assert_eq!(pool.service.get_mineable_transactions(10), vec![tx]);
+}
+
+#[test]
+fn test_add_pool_batch() {
+    let mut pool = TestPool::<ChainKVStore<MemoryKeyValueDB>>::simple();
+
+    let parent = test_transaction(&[OutPoint::new(pool.tx_hash.clone(), 0)], 2);
+    let child = test_transaction(&[OutPoint::new(parent.hash().clone(), 0)], 1);
+    let grandchild = test_transaction(&[OutPoint::new(child.hash().clone(), 0)], 1);
+    let tx = test_transaction(&[OutPoint::new(pool.tx_hash.clone(), 1)], 1);
+    let double_spent = test_transaction(&[OutPoint::new(pool.tx_hash.clone(), 1)], 2);
+
+    let results = pool
+        .service
+        .add_to_pool_batch(vec![grandchild, child, tx, double_spent, parent]);
+
+    assert_eq!(
+        results.iter().map(Result::is_ok).collect::<Vec<_>>(),
+        vec![true, true, true, false, true]
+    );
+    match results[3] {
+        Err(PoolError::DoubleSpent) => {}
+        ref x => panic!("Unexpected result {:?}", x),
+    }
+    assert_eq!(pool.service.pool_size(), 4);
+    assert_eq!(pool.service.orphan_size(), 0);
+}
+
+#[test]
+fn test_verify_off_service_thread() {
+    let mut pool = TestPool::<ChainKVStore<MemoryKeyValueDB>>::simple();
+
+    let tx1 = test_transaction(&[OutPoint::new(pool.tx_hash.clone(), 0)], 2);
+    let tx2 = test_transaction(&[OutPoint::new(pool.tx_hash.clone(), 1)], 2);
+    let (done1, results1) = crossbeam_channel::bounded(1);
+    let (done2, results2) = crossbeam_channel::bounded(1);
+    pool.service.add_to_pool_batch_then(vec![tx1], move |_, results| {
+        let _ = done1.send(results);
+    });
+    pool.service.add_to_pool_batch_then(vec![tx2.clone()], move |_, results| {
+        let _ = done2.send(results);
+    });
+    // Added once the service gets the results back
+    assert_eq!(pool.service.pool_size(), 0);
+    assert!(results1.try_recv().is_err());
+
+    // tx2 is committed while it is verified
+    apply_transactions(vec![tx2], vec![], &mut pool);
+    match results1.try_recv().expect("verified").as_slice() {
+        [Ok(InsertionResult::Normal)] => {}
+        results => panic!("unexpected results {:?}", results),
+    }
+    match results2.try_recv().expect("verified").as_slice() {
+        [Err(PoolError::Committed)] => {}
+        results => panic!("unexpected results {:?}", results),
+    }
+    assert_eq!(pool.service.pool_size(), 1);
+}
+
+#[test]
+fn test_clear_drops_verifying() {
+    let mut pool = TestPool::<ChainKVStore<MemoryKeyValueDB>>::simple();
+
+    let tx = test_transaction(&[OutPoint::new(pool.tx_hash.clone(), 0)], 2);
+    let (done, results) = crossbeam_channel::bounded(1);
+    pool.service.add_to_pool_batch_then(vec![tx.clone()], move |_, results| {
+        let _ = done.send(results);
+    });
+    pool.service.clear();
+    pool.service.complete_verifications();
+
+    match results.try_recv().expect("verified").as_slice() {
+        [Err(PoolError::Cleared)] => {}
+        results => panic!("unexpected results {:?}", results),
+    }
+    assert_eq!(pool.service.get_status(&tx.proposal_short_id()), None);
+    assert_eq!(pool.service.total_size(), 0);
+}
+
+#[test]
+fn test_add_generated_transactions() {
+    let mut pool = TestPool::<ChainKVStore<MemoryKeyValueDB>>::simple();
+    let config = GeneratorConfig {
+        inputs: 2,
+        outputs: 3,
+        output_data: 8,
+        ..Default::default()
+    };
+    let mut generator = Generator::new(config, 1);
+    let number = pool.shared.chain_state().read().tip_number() + 1;
+    let cellbase = generator.cellbase(number, 10, 10_000_000);
+    apply_transactions(vec![cellbase], vec![], &mut pool);
+
+    // Chained transactions, spending the outputs of the previous ones
+    let results = pool.service.add_to_pool_batch(generator.transactions(20));
+    assert!(results.iter().all(Result::is_ok), "{:?}", results);
+    assert_eq!(pool.service.pool_size(), 20);
+
+    generator.config_mut().valid_signatures = false;
+    for result in pool.service.add_to_pool_batch(generator.transactions(5)) {
+        match result {
+            Err(PoolError::InvalidTx(TransactionError::ScriptFailure(..))) => {}
+            x => panic!("Unexpected result {:?}", x),
+        }
+    }
+    assert_eq!(pool.service.pool_size(), 20);
+}
+
+#[test]
+fn test_add_transactions() {
+    let mut pool = TestPool::<ChainKVStore<MemoryKeyValueDB>>::simple();
+
+    let cellbase = TransactionBuilder::default()
+        .input(CellInput::new_cellbase_input(0))
+        .output(CellOutput::default())
+        .build();
+    let child = test_transaction(&[OutPoint::new(cellbase.hash().clone(), 0)], 1);
+    let tx = test_transaction(&[OutPoint::new(pool.tx_hash.clone(), 0)], 1);
+
+    let results = pool
+        .service
+        .add_transactions(vec![cellbase, child, tx.clone()]);
+
+    match results.as_slice() {
+        [Err(PoolError::Cellbase), Err(PoolError::ParentRejected), Ok(hash)] => {
+            assert_eq!(hash, &tx.hash())
+        }
+        x => panic!("Unexpected results {:?}", x),
+    }
+}
+
+#[test]
+fn test_add_transactions_parents_first() {
+    let mut pool = TestPool::<ChainKVStore<MemoryKeyValueDB>>::simple();
+
+    let parent = test_transaction(&[OutPoint::new(pool.tx_hash.clone(), 0)], 2);
+    let child = test_transaction(&[OutPoint::new(parent.hash().clone(), 0)], 1);
+    let ids = vec![parent.proposal_short_id(), child.proposal_short_id()];
+    apply_transactions(vec![], ids.clone(), &mut pool);
+
+    // The child is listed first but verified after its parent, not kept as an orphan
+    let results = pool
+        .service
+        .add_transactions(vec![child.clone(), parent.clone()]);
+
+    match results.as_slice() {
+        [Ok(child_hash), Ok(parent_hash)] => {
+            assert_eq!(child_hash, &child.hash());
+            assert_eq!(parent_hash, &parent.hash());
+        }
+        x => panic!("Unexpected results {:?}", x),
+    }
+    for id in &ids {
+        assert_eq!(pool.service.get_status(id), Some(TxStatus::Mineable));
+    }
+}
+
+#[test]
+fn test_transaction_status() {
+    let mut pool = TestPool::<ChainKVStore<MemoryKeyValueDB>>::simple();
+
+    let tx1 = test_transaction(&[OutPoint::new(pool.tx_hash.clone(), 0)], 2);
+    let tx2 = test_transaction(&[OutPoint::new(tx1.hash().clone(), 0)], 1);
+    let id1 = tx1.proposal_short_id();
+    let id2 = tx2.proposal_short_id();
+
+    assert_eq!(pool.service.get_status(&id1), None);
+
+    pool.service.add_transaction(tx1.clone()).unwrap();
+    assert_eq!(pool.service.get_status(&id1), Some(TxStatus::Pending));
+
+    pool.service.add_to_pool(tx2.clone()).unwrap();
+    assert_eq!(pool.service.get_status(&id2), Some(TxStatus::Orphan));
+
+    pool.service.add_to_pool(tx1).unwrap();
+    assert_eq!(pool.service.get_status(&id2), Some(TxStatus::Mineable));
+}
+
+#[test]
+fn test_move_transaction() {
+    let mut pool = TestPool::<ChainKVStore<MemoryKeyValueDB>>::simple();
+
+    let tx1 = test_transaction(&[OutPoint::new(pool.tx_hash.clone(), 0)], 2);
+    let tx2 = test_transaction(&[OutPoint::new(tx1.hash().clone(), 0)], 1);
+    let id1 = tx1.proposal_short_id();
+    let id2 = tx2.proposal_short_id();
+
+    pool.service.add_transaction(tx1).unwrap();
+    // Not proposed on chain yet
+    match pool.service.move_transaction(&id1, TxStatus::Proposed) {
+        Err(PoolError::NotProposed) => {}
+        result => panic!("unexpected result {:?}", result),
+    }
+    assert_eq!(pool.service.get_status(&id1), Some(TxStatus::Pending));
+
+    apply_transactions(vec![], vec![id1.clone()], &mut pool);
+    assert_eq!(pool.service.get_status(&id1), Some(TxStatus::Mineable));
+    pool.service.add_to_pool(tx2).unwrap();
+    assert_eq!(pool.service.get_status(&id2), Some(TxStatus::Mineable));
+
+    // The descendant leaves the mineable set along
+    assert_eq!(
+        pool.service.move_transaction(&id1, TxStatus::Pending).unwrap(),
+        TxStatus::Pending
+    );
+    assert_eq!(pool.service.get_status(&id1), Some(TxStatus::Pending));
+    assert_eq!(pool.service.get_status(&id2), Some(TxStatus::Pending));
+    assert_eq!(pool.service.pool_size(), 0);
+
+    // Its proposal is still on record
+    assert_eq!(
+        pool.service.move_transaction(&id1, TxStatus::Mineable).unwrap(),
+        TxStatus::Mineable
+    );
+    assert_eq!(pool.service.pool_size(), 1);
+    assert_eq!(pool.service.pending_size(), 1);
+
+    match pool.service.move_transaction(&id1, TxStatus::Orphan) {
+        Err(PoolError::InvalidStage) => {}
+        result => panic!("unexpected result {:?}", result),
+    }
+    let unknown = test_transaction(&[OutPoint::new(pool.tx_hash.clone(), 9)], 1);
+    match pool
+        .service
+        .move_transaction(&unknown.proposal_short_id(), TxStatus::Pending)
+    {
+        Err(PoolError::NotInPool) => {}
+        result => panic!("unexpected result {:?}", result),
+    }
+}
+
+#[test]
+fn test_move_transaction_rejected_kept_pending() {
+    let mut pool = TestPool::<ChainKVStore<MemoryKeyValueDB>>::with_config(PoolConfig {
+        max_pool_size: 0,
+        ..Default::default()
+    });
+
+    let tx1 = test_transaction(&[OutPoint::new(pool.tx_hash.clone(), 0)], 2);
+    let tx2 = test_transaction(&[OutPoint::new(pool.tx_hash.clone(), 1)], 1);
+    let id1 = tx1.proposal_short_id();
+
+    pool.service.add_transaction(tx1).unwrap();
+    apply_transactions(vec![], vec![id1.clone()], &mut pool);
+    assert_eq!(pool.service.get_status(&id1), Some(TxStatus::Mineable));
+    pool.service.move_transaction(&id1, TxStatus::Pending).unwrap();
+    pool.service.add_to_pool(tx2).unwrap();
+
+    // The full pool refuses it, it stays pending instead of being dropped
+    match pool.service.move_transaction(&id1, TxStatus::Mineable) {
+        Err(PoolError::OverCapacity) => {}
+        result => panic!("unexpected result {:?}", result),
+    }
+    assert_eq!(pool.service.get_status(&id1), Some(TxStatus::Pending));
+    assert_eq!(pool.service.pending_size(), 1);
+}
+
+#[test]
+fn test_dry_run_transaction() {
+    let mut pool = TestPool::<ChainKVStore<MemoryKeyValueDB>>::simple();
+
+    let tx = test_transaction(&[OutPoint::new(pool.tx_hash.clone(), 0)], 2);
+    assert!(pool.service.dry_run_transaction(tx.clone(), None).is_ok());
+    assert_eq!(pool.service.total_size(), 0);
+    assert_eq!(pool.service.get_status(&tx.proposal_short_id()), None);
+
+    let unknown = test_transaction(&[OutPoint::new(H256::zero(), 0)], 1);
+    match pool.service.dry_run_transaction(unknown, None) {
+        Err(PoolError::InvalidTx(TransactionError::UnknownInput(CellIndex::Input(0)))) => {}
+        ret => panic!("unexpected dry run result {:?}", ret),
+    }
+}
+
+#[test]
+fn test_info_and_clear() {
+    let mut pool = TestPool::<ChainKVStore<MemoryKeyValueDB>>::simple();
+
+    let tx1 = test_transaction(&[OutPoint::new(pool.tx_hash.clone(), 0)], 2);
+    let tx2 = test_transaction(&[OutPoint::new(tx1.hash().clone(), 0)], 1);
+    let tx3 = test_transaction(&[OutPoint::new(pool.tx_hash.clone(), 1)], 1);
+
+    pool.service.add_to_pool(tx2.clone()).unwrap();
+    pool.service.add_local_transaction(tx3.clone()).unwrap();
+    let info = pool.service.info();
+    assert_eq!((info.pending, info.mineable, info.orphan), (1, 0, 1));
+
+    pool.service.add_to_pool(tx1.clone()).unwrap();
+    let info = pool.service.info();
+    assert_eq!((info.pending, info.mineable, info.orphan), (1, 2, 0));
+
+    while pool.pool_event_receiver.try_recv().is_ok() {}
+    pool.service.clear();
+    assert_eq!(pool.service.info().pending, 0);
+    assert_eq!(pool.service.total_size(), 0);
+    assert_eq!(pool.service.get_status(&tx2.proposal_short_id()), None);
+    assert!(pool.service.get_local_transactions().is_empty());
+
+    // Each dropped transaction is notified
+    let removed: Vec<_> = pool
+        .pool_event_receiver
+        .try_iter()
+        .map(|event| match event.as_ref() {
+            PoolEvent::TxRemoved {
+                hash,
+                reason: RemovedReason::Cleared,
+            } => hash.clone(),
+            x => panic!("Unexpected pool event {:?}", x),
+        })
+        .collect();
+    assert_eq!(removed.len(), 3);
+    for tx in &[tx1, tx2, tx3] {
+        assert!(removed.contains(&tx.hash()));
+    }
+}
+
+#[test]
+fn test_set_min_fee_rate() {
+    let mut pool = TestPool::<ChainKVStore<MemoryKeyValueDB>>::simple();
+    assert_eq!(pool.service.estimate_fee_rate(3), 0);
+
+    pool.service.set_min_fee_rate(2000);
+    assert_eq!(pool.service.estimate_fee_rate(3), 2000);
+}
+
+#[test]
+fn test_local_transactions() {
+    let mut pool = TestPool::<ChainKVStore<MemoryKeyValueDB>>::simple();
+
+    let tx1 = test_transaction(&[OutPoint::new(pool.tx_hash.clone(), 0)], 2);
+    let tx2 = test_transaction(&[OutPoint::new(pool.tx_hash.clone(), 1)], 2);
+
+    pool.service.add_local_transaction(tx1.clone()).unwrap();
+    pool.service.add_transaction(tx2.clone()).unwrap();
+
+    assert_eq!(pool.service.get_local_transactions(), vec![tx1.clone()]);
+
+    let block_number = { pool.shared.chain_state().read().tip_number() };
+    let cellbase = TransactionBuilder::default()
+        .input(CellInput::new_cellbase_input(block_number + 1))
+        .output(CellOutput::default())
+        .build();
+    let block = BlockBuilder::default()
+        .header(HeaderBuilder::default().number(block_number + 1).build())
+        .commit_transaction(cellbase)
+        .commit_transaction(tx1)
+        .build();
+
+    pool.service.reconcile_block(&block);
+    pool.service.complete_verifications();
+
+    assert!(pool.service.get_local_transactions().is_empty());
+}
+
+#[test]
+fn test_pending_admission() {
+    let mut pool = TestPool::<ChainKVStore<MemoryKeyValueDB>>::with_config(PoolConfig {
+        max_pending_size: 1,
+        min_fee_rate: 1000,
+        free_tx_allowance: 0,
+        ..Default::default()
+    });
+    let root = pool.tx_hash.clone();
+    let input = |index| [OutPoint::new(root.clone(), index)];
+    // The root outputs hold 100_000_000 each, the rest is the fee
+    let free = test_transaction_with_capacity(&input(0), 1, 100_000_000);
+    let paying = test_transaction_with_capacity(&input(1), 1, 99_000_000);
+    let other = test_transaction_with_capacity(&input(2), 1, 99_000_000);
+
+    match pool.service.add_transaction(free.clone()) {
+        Err(PoolError::LowFeeRate) => {}
+        ret => panic!("unexpected add result {:?}", ret),
+    }
+    match pool.service.add_transaction(paying.clone()) {
+        Ok(InsertionResult::Unknown) => {}
+        ret => panic!("unexpected add result {:?}", ret),
+    }
+    match pool.service.add_transaction(other.clone()) {
+        Err(PoolError::OverCapacity) => {}
+        ret => panic!("unexpected add result {:?}", ret),
+    }
+    assert_eq!(pool.service.pending_size(), 1);
+    assert_eq!(pool.service.get_status(&free.proposal_short_id()), None);
+    assert_eq!(pool.service.get_status(&other.proposal_short_id()), None);
+
+    // The local transactions are bound by neither
+    pool.service.add_local_transaction(free.clone()).unwrap();
+    assert_eq!(
+        pool.service.get_status(&free.proposal_short_id()),
+        Some(TxStatus::Pending)
+    );
+}
+
+#[test]
+fn test_package_limits_across_stages() {
+    let mut pool = TestPool::<ChainKVStore<MemoryKeyValueDB>>::with_config(PoolConfig {
+        max_ancestors_count: 1,
+        max_descendants_count: 1,
+        ..Default::default()
+    });
+    let capacity = 100_000_000;
+    let tx1 =
+        test_transaction_with_capacity(&[OutPoint::new(pool.tx_hash.clone(), 0)], 2, capacity);
+    let tx2 =
+        test_transaction_with_capacity(&[OutPoint::new(tx1.hash().clone(), 0)], 1, capacity / 2);
+    let tx3 =
+        test_transaction_with_capacity(&[OutPoint::new(tx2.hash().clone(), 0)], 1, capacity / 2);
+    let tx4 =
+        test_transaction_with_capacity(&[OutPoint::new(tx1.hash().clone(), 1)], 1, capacity / 2);
+
+    // The parents waiting in pending count as the mineable ones
+    for tx in &[&tx1, &tx2] {
+        match pool.service.add_transaction((*tx).clone()) {
+            Ok(InsertionResult::Unknown) => {}
+            ret => panic!("unexpected add result {:?}", ret),
+        }
+    }
+    match pool.service.add_transaction(tx3) {
+        Err(PoolError::ExceededMaximumAncestorsCount) => {}
+        ret => panic!("unexpected add result {:?}", ret),
+    }
+    match pool.service.add_transaction(tx4) {
+        Err(PoolError::ExceededMaximumDescendantsCount) => {}
+        ret => panic!("unexpected add result {:?}", ret),
+    }
+    assert_eq!(pool.service.pending_size(), 2);
+}
+
+#[test]
+fn test_evict_lowest_fee_rate() {
+    let mut pool = TestPool::<ChainKVStore<MemoryKeyValueDB>>::with_config(PoolConfig {
+        max_pool_size: 2,
+        min_fee_rate: 0,
+        ..Default::default()
+    });
+    let root = pool.tx_hash.clone();
+    let input = |index| [OutPoint::new(root.clone(), index)];
+    // The root outputs hold 100_000_000 each, the rest is the fee
+    let high = test_transaction_with_capacity(&input(0), 1, 99_000_000);
+    let low = test_transaction_with_capacity(&input(1), 1, 99_999_000);
+    let middle = test_transaction_with_capacity(&input(2), 1, 99_500_000);
+    let local = test_transaction_with_capacity(&input(3), 1, 99_999_999);
+    for tx in &[&high, &low, &middle] {
+        pool.service.add_to_pool((*tx).clone()).unwrap();
+    }
+    assert_eq!(pool.service.pool_size(), 3);
+
+    let block_number = { pool.shared.chain_state().read().tip_number() };
+    let block = BlockBuilder::default()
+        .header(HeaderBuilder::default().number(block_number + 1).build())
+        .proposal_transactions(vec![local.proposal_short_id()])
+        .build();
+    pool.service.reconcile_block(&block);
+    pool.service.complete_verifications();
+    while pool.pool_event_receiver.try_recv().is_ok() {}
+
+    // The full pool makes room for the local transaction, though it pays less than all of them
+    pool.service.add_local_transaction(local.clone()).unwrap();
+    assert_eq!(pool.service.get_status(&low.proposal_short_id()), None);
+    match pool.pool_event_receiver.try_recv().as_ref().map(|event| event.as_ref()) {
+        Ok(PoolEvent::TxRemoved {
+            hash,
+            reason: RemovedReason::Evicted,
+        }) => assert_eq!(hash, &low.hash()),
+        x => panic!("Unexpected pool event {:?}", x),
+    }
+    for tx in &[&high, &middle, &local] {
+        assert_eq!(
+            pool.service.get_status(&tx.proposal_short_id()),
+            Some(TxStatus::Mineable)
+        );
+    }
+}
+
+#[test]
+fn test_save_and_load_local_transactions() {
+    let pool = TestPool::<ChainKVStore<MemoryKeyValueDB>>::simple();
+    let tmp_dir = tempfile::Builder::new()
+        .prefix("test_save_and_load_local_transactions")
+        .tempdir()
+        .unwrap();
+    let path = tmp_dir.path().join("local_txs");
+
+    let parent = test_transaction(&[OutPoint::new(pool.tx_hash.clone(), 0)], 2);
+    let child = test_transaction(&[OutPoint::new(parent.hash().clone(), 0)], 1);
+    let relayed = test_transaction(&[OutPoint::new(pool.tx_hash.clone(), 1)], 1);
+
+    let notify = NotifyService::default().start::<&str>(None);
+    let shared = pool.shared.clone();
+    let controller = pool.service.start::<&str>(None);
+    assert_eq!(controller.load_local_transactions(&path).unwrap(), 0);
+    controller.add_local_transaction(parent.clone()).unwrap();
+    controller.add_local_transaction(child.clone()).unwrap();
+    controller.add_transaction(relayed.clone()).unwrap();
+    assert_eq!(controller.save_local_transactions(&path).unwrap(), 2);
+
+    // A restarted pool gets the local transactions back
+    let restarted = TransactionPoolService::new(PoolConfig::default(), shared, notify)
+        .start::<&str>(None);
+    assert_eq!(restarted.load_local_transactions(&path).unwrap(), 2);
+    let local = restarted.get_local_transactions();
+    assert_eq!(local.len(), 2);
+    assert!(local.contains(&parent) && local.contains(&child));
+    assert_eq!(restarted.get_transaction(relayed.proposal_short_id()), None);
+}
+
+#[test]
+fn test_pool_events() {
+    let mut pool = TestPool::<ChainKVStore<MemoryKeyValueDB>>::simple();
+
+    let tx1 = test_transaction(&[OutPoint::new(pool.tx_hash.clone(), 0)], 2);
+    let tx2 = test_transaction(&[OutPoint::new(pool.tx_hash.clone(), 1)], 2);
+    let tx3 = test_transaction(&[OutPoint::new(pool.tx_hash.clone(), 1)], 1);
+
+    pool.service.add_to_pool(tx1.clone()).unwrap();
+    pool.service.add_to_pool(tx2.clone()).unwrap();
+
+    match pool.pool_event_receiver.recv().as_ref().map(|e| e.as_ref()) {
+        Ok(PoolEvent::TxAdded { hash, size }) => {
+            assert_eq!(hash, &tx1.hash());
+            assert_eq!(*size, tx1.serialized_size());
+        }
+        x => panic!("Unexpected pool event {:?}", x),
+    }
+    match pool.pool_event_receiver.recv().as_ref().map(|e| e.as_ref()) {
+        Ok(PoolEvent::TxAdded { hash, .. }) => assert_eq!(hash, &tx2.hash()),
+        x => panic!("Unexpected pool event {:?}", x),
+    }
+
+    let block_number = { pool.shared.chain_state().read().tip_number() };
+    let cellbase = TransactionBuilder::default()
+        .input(CellInput::new_cellbase_input(block_number + 1))
+        .output(CellOutput::default())
+        .build();
+    let block = BlockBuilder::default()
+        .header(HeaderBuilder::default().number(block_number + 1).build())
+        .commit_transaction(cellbase)
+        .commit_transaction(tx1.clone())
+        .commit_transaction(tx3.clone())
+        .build();
+
+    pool.service.reconcile_block(&block);
+    pool.service.complete_verifications();
+
+    match pool.pool_event_receiver.recv().as_ref().map(|e| e.as_ref()) {
+        Ok(PoolEvent::TxConfirmed {
+            hash, block_hash, ..
+        }) => {
+            assert_eq!(hash, &tx1.hash());
+            assert_eq!(block_hash, &block.header().hash());
+        }
+        x => panic!("Unexpected pool event {:?}", x),
+    }
+    match pool.pool_event_receiver.recv().as_ref().map(|e| e.as_ref()) {
+        Ok(PoolEvent::TxReplaced { hash, by }) => {
+            assert_eq!(hash, &tx2.hash());
+            assert_eq!(by, &tx3.hash());
+        }
+        x => panic!("Unexpected pool event {:?}", x),
+    }
+}
+
+#[test]
+fn test_proposal_timeout_events() {
+    let mut pool = TestPool::<ChainKVStore<MemoryKeyValueDB>>::simple();
+
+    let tx = test_transaction(&[OutPoint::new(pool.tx_hash.clone(), 0)], 2);
+    let id = tx.proposal_short_id();
+    apply_transactions(vec![], vec![id.clone()], &mut pool);
+    pool.service.add_transaction(tx.clone()).unwrap();
+    assert_eq!(pool.service.get_status(&id), Some(TxStatus::Mineable));
+
+    for _ in 0..20 {
+        if pool.service.get_status(&id) == Some(TxStatus::Pending) {
+            break;
+        }
+        apply_transactions(vec![], vec![], &mut pool);
+    }
+    assert_eq!(pool.service.get_status(&id), Some(TxStatus::Pending));
+
+    // Back in pending, so it did not expire
+    let events: Vec<_> = pool.pool_event_receiver.try_iter().collect();
+    match events.iter().map(|e| e.as_ref()).collect::<Vec<_>>().as_slice() {
+        [PoolEvent::TxAdded { hash, .. }] => assert_eq!(hash, &tx.hash()),
+        x => panic!("Unexpected pool events {:?}", x),
+    }
+}
+
 fn prepare_trace(
     pool: &mut TestPool<ChainKVStore<MemoryKeyValueDB>>,
-    faketime_file: &TempPath,
-) -> Transaction {
+    clock: &Clock,
+) -> (Transaction, Block) {
     let tx = test_transaction(&[OutPoint::new(pool.tx_hash.clone(), 0)], 2);
 
     let block_number = { pool.shared.chain_state().read().tip_number() };
@@ -491,20 +1106,20 @@ fn prepare_trace(
         .proposal_transactions(vec![tx.proposal_short_id()])
         .build();
 
-    faketime::write_millis(faketime_file, 9102).expect("write millis");
+    clock.set(9102);
 
     pool.service.reconcile_block(&block);
-    tx
+    pool.service.complete_verifications();
+    (tx, block)
 }
 
-#[cfg(not(disable_faketime))]
 #[test]
 fn test_get_transaction_traces() {
     let mut pool = TestPool::<ChainKVStore<MemoryKeyValueDB>>::simple();
-    let faketime_file = faketime::millis_tempfile(8102).expect("create faketime file");
-    faketime::enable(&faketime_file);
+    let clock = Clock::new(8102);
+    let _scope = clock.enter();
 
-    let tx = prepare_trace(&mut pool, &faketime_file);
+    let (tx, proposal_block) = prepare_trace(&mut pool, &clock);
     let tx_hash = tx.hash();
 
     let trace = pool.service.get_transaction_traces(&tx_hash);
@@ -525,13 +1140,17 @@ fn test_get_transaction_traces() {
             }],
         ) => assert_eq!(
             proposal_info,
-            concat!("ProposalShortId(0xda495f694cac79513d00) proposed ",
-            "in block number(2)-hash(0xb42c5305777987f80112e862a3e722c1d0e68c671f1d8920d16ebfc6783a6467)")
+            &format!(
+                "{:?} proposed in block number({:?})-hash({:#x})",
+                tx.proposal_short_id(),
+                proposal_block.header().number(),
+                proposal_block.header().hash()
+            )
         ),
         _ => assert!(false),
     }
 
-    faketime::write_millis(&faketime_file, 9103).expect("write millis");
+    clock.set(9103);
     let block = apply_transactions(vec![tx.clone()], vec![], &mut pool);
     let trace = pool.service.get_transaction_traces(&tx_hash);
     match trace.map(|t| t.as_slice()) {
@@ -570,15 +1189,32 @@ struct TestPool<CI> {
     chain: ChainController,
     shared: Shared<CI>,
     tx_hash: H256,
-    new_tip_receiver: Receiver<MsgNewTip>,
-    switch_fork_receiver: Receiver<MsgSwitchFork>,
+    chain_event_receiver: Receiver<Event>,
+    pool_event_receiver: Receiver<MsgPoolEvent>,
 }
 
 impl<CI: ChainIndex + 'static> TestPool<CI> {
     fn simple() -> TestPool<ChainKVStore<MemoryKeyValueDB>> {
+        Self::with_config(PoolConfig {
+            max_pool_size: 1000,
+            max_orphan_size: 1000,
+            max_proposal_size: 1000,
+            max_cache_size: 1000,
+            max_pending_size: 1000,
+            max_ancestors_count: 1000,
+            max_descendants_count: 1000,
+            min_fee_rate: 0,
+            free_tx_allowance: 0,
+            max_local_size: 1000,
+            trace: Some(100),
+        })
+    }
+
+    fn with_config(config: PoolConfig) -> TestPool<ChainKVStore<MemoryKeyValueDB>> {
         let notify = NotifyService::default().start::<&str>(None);
-        let new_tip_receiver = notify.subscribe_new_tip("txs_pool");
-        let switch_fork_receiver = notify.subscribe_switch_fork("txs_pool");
+        let chain_event_receiver =
+            notify.subscribe("txs_pool", &[EventKind::SwitchFork, EventKind::NewTip]);
+        let pool_event_receiver = notify.subscribe_pool_event("test");
         let shared = SharedBuilder::<ChainKVStore<MemoryKeyValueDB>>::new_memory()
             .consensus(Consensus::default())
             .build();
@@ -587,18 +1223,7 @@ impl<CI: ChainIndex + 'static> TestPool<CI> {
             .build();
         let chain_controller = chain_service.start::<&str>(None);
 
-        let tx_pool_service = TransactionPoolService::new(
-            PoolConfig {
-                max_pool_size: 1000,
-                max_orphan_size: 1000,
-                max_proposal_size: 1000,
-                max_cache_size: 1000,
-                max_pending_size: 1000,
-                trace: Some(100),
-            },
-            shared.clone(),
-            notify.clone(),
-        );
+        let tx_pool_service = TransactionPoolService::new(config, shared.clone(), notify.clone());
 
         let default_script_hash = create_valid_script().type_hash();
         let tx = TransactionBuilder::default()
@@ -620,8 +1245,8 @@ impl<CI: ChainIndex + 'static> TestPool<CI> {
             chain: chain_controller,
             shared,
             tx_hash: tx.hash().clone(),
-            new_tip_receiver,
-            switch_fork_receiver,
+            chain_event_receiver,
+            pool_event_receiver,
         };
         apply_transactions(transactions, vec![], &mut pool);
         pool
@@ -630,17 +1255,13 @@ impl<CI: ChainIndex + 'static> TestPool<CI> {
     fn handle_notify_messages(&mut self) {
         loop {
             select! {
-                recv(self.new_tip_receiver) -> msg => match msg {
-                    Ok(block) => self.service.reconcile_block(&block),
-                    _ => {
-                        error!(target: "txs_pool", "channel new_tip_receiver closed");
-                        break;
+                recv(self.chain_event_receiver) -> msg => match msg {
+                    Ok(event) => {
+                        self.service.handle_chain_event(Ok(event));
+                        self.service.complete_verifications();
                     }
-                },
-                recv(self.switch_fork_receiver) -> msg => match msg {
-                    Ok(blocks) => self.service.switch_fork(&blocks),
                     _ => {
-                        error!(target: "txs_pool", "channel switch_fork_receiver closed");
+                        error!(target: "txs_pool", "channel chain_event_receiver closed");
                         break;
                     }
                 },
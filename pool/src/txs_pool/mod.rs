@@ -7,5 +7,6 @@ pub mod types;
 pub use self::pool::{TransactionPoolController, TransactionPoolService};
 pub use self::trace::TxTrace;
 pub use self::types::{
-    Orphan, PendingQueue, Pool, PoolConfig, PoolError, ProposedQueue, TxStage, TxoStatus,
+    FeeEstimator, Orphan, PendingQueue, Pool, PoolConfig, PoolError, ProposedQueue, TxPoolInfo,
+    TxStage, TxStatus, TxoStatus,
 };
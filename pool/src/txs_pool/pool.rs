@@ -1,34 +1,78 @@
 //! Top-level Pool type, methods, and tests
 use super::trace::{TxTrace, TxTraceMap};
 use super::types::{
-    InsertionResult, Orphan, PendingQueue, Pool, PoolConfig, PoolError, ProposedQueue, TxStage,
-    TxoStatus,
+    FeeEstimator, FreeTxBucket, InsertionResult, Orphan, PendingQueue, Pool, PoolConfig,
+    PoolError, ProposedQueue, TxPoolInfo, TxStage, TxStatus, TxoStatus,
 };
 use ckb_core::block::Block;
-use ckb_core::cell::{CellProvider, CellStatus};
+use ckb_core::cell::{CellProvider, CellStatus, ResolvedTransaction};
 use ckb_core::service::{Request, DEFAULT_CHANNEL_SIZE, SIGNAL_CHANNEL_SIZE};
-use ckb_core::transaction::{OutPoint, ProposalShortId, Transaction};
-use ckb_notify::{ForkBlocks, MsgNewTip, MsgSwitchFork, NotifyController};
+use ckb_core::transaction::{CellOutput, OutPoint, ProposalShortId, Transaction};
+use ckb_core::{BlockNumber, Capacity, Cycle};
+use ckb_metrics::{registry, Gauge};
+use ckb_notify::{Event, EventKind, ForkSwitch, NotifyController, PoolEvent, RemovedReason};
 use ckb_shared::index::ChainIndex;
 use ckb_shared::shared::{ChainProvider, Shared};
+use ckb_time::unix_time_as_millis;
 use ckb_verification::{TransactionError, TransactionVerifier};
 use crossbeam_channel::{self, select, Receiver, Sender};
-use faketime::unix_time_as_millis;
+use fnv::{FnvHashMap, FnvHashSet};
 use log::error;
 use lru_cache::LruCache;
 use numext_fixed_hash::H256;
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use std::collections::VecDeque;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::Path;
 use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::Arc;
+use std::sync::{Arc, Condvar, Mutex};
 use std::thread;
+use std::time::{Duration, Instant};
+use std::vec;
 use stop_handler::{SignalSender, StopHandler};
 
-#[cfg(test)]
-use ckb_core::BlockNumber;
-
 const TXS_POOL_SUBSCRIBER: &str = "txs_pool";
+// Locally submitted transactions are no longer rebroadcast after this time
+const LOCAL_TX_EXPIRY: u64 = 24 * 60 * 60 * 1000; // 24 hours
+
+// Sizes of the queues, set after every request
+struct PoolMetrics {
+    pending: Arc<Gauge>,
+    proposed: Arc<Gauge>,
+    mineable: Arc<Gauge>,
+    orphan: Arc<Gauge>,
+    conflict: Arc<Gauge>,
+}
+
+impl PoolMetrics {
+    fn new() -> Self {
+        let gauge = |queue: &str| {
+            registry().gauge("tx_pool_transactions", vec![("queue", queue.to_owned())])
+        };
+        PoolMetrics {
+            pending: gauge("pending"),
+            proposed: gauge("proposed"),
+            mineable: gauge("mineable"),
+            orphan: gauge("orphan"),
+            conflict: gauge("conflict"),
+        }
+    }
+
+    fn update(&self, info: &TxPoolInfo) {
+        self.pending.set(info.pending);
+        self.proposed.set(info.proposed);
+        self.mineable.set(info.mineable);
+        self.orphan.set(info.orphan);
+        self.conflict.set(info.conflict);
+    }
+}
 
 pub type TxsArgs = (usize, usize);
 pub type TxsReturn = (Vec<ProposalShortId>, Vec<Transaction>);
+pub type MoveReturn = Result<TxStatus, PoolError>;
+type ReconciledTip = Arc<(Mutex<H256>, Condvar)>;
 
 #[derive(Clone)]
 pub struct TransactionPoolController {
@@ -36,10 +80,21 @@ pub struct TransactionPoolController {
     get_potential_transactions_sender: Sender<Request<(), Vec<Transaction>>>,
     contains_key_sender: Sender<Request<ProposalShortId, bool>>,
     get_transaction_sender: Sender<Request<ProposalShortId, Option<Transaction>>>,
+    get_transaction_status_sender: Sender<Request<ProposalShortId, Option<TxStatus>>>,
+    move_transaction_sender: Sender<Request<(ProposalShortId, TxStatus), MoveReturn>>,
     add_transaction_sender: Sender<Request<Transaction, Result<InsertionResult, PoolError>>>,
+    add_local_transaction_sender: Sender<Request<Transaction, Result<InsertionResult, PoolError>>>,
+    add_transactions_sender: Sender<Request<Vec<Transaction>, Vec<Result<H256, PoolError>>>>,
+    get_local_transactions_sender: Sender<Request<(), Vec<Transaction>>>,
     reg_trace_sender: Sender<Request<Transaction, Result<InsertionResult, PoolError>>>,
     get_trace_sender: Sender<Request<H256, Option<Vec<TxTrace>>>>,
+    get_info_sender: Sender<Request<(), TxPoolInfo>>,
+    clear_sender: Sender<Request<(), ()>>,
+    estimate_fee_rate_sender: Sender<Request<BlockNumber, Capacity>>,
+    set_min_fee_rate_sender: Sender<Request<Capacity, ()>>,
+    dry_run_transaction_sender: Sender<Request<(Transaction, Option<Cycle>), Result<Cycle, PoolError>>>,
     last_txs_updated_at: Arc<AtomicUsize>,
+    reconciled_tip: ReconciledTip,
     stop: StopHandler<()>,
 }
 
@@ -54,12 +109,27 @@ struct TransactionPoolReceivers {
     get_potential_transactions_receiver: Receiver<Request<(), Vec<Transaction>>>,
     contains_key_receiver: Receiver<Request<ProposalShortId, bool>>,
     get_transaction_receiver: Receiver<Request<ProposalShortId, Option<Transaction>>>,
+    get_transaction_status_receiver: Receiver<Request<ProposalShortId, Option<TxStatus>>>,
+    move_transaction_receiver: Receiver<Request<(ProposalShortId, TxStatus), MoveReturn>>,
     add_transaction_receiver: Receiver<Request<Transaction, Result<InsertionResult, PoolError>>>,
+    add_local_transaction_receiver:
+        Receiver<Request<Transaction, Result<InsertionResult, PoolError>>>,
+    get_local_transactions_receiver: Receiver<Request<(), Vec<Transaction>>>,
+    add_transactions_receiver: Receiver<Request<Vec<Transaction>, Vec<Result<H256, PoolError>>>>,
     reg_trace_receiver: Receiver<Request<Transaction, Result<InsertionResult, PoolError>>>,
     get_trace_receiver: Receiver<Request<H256, Option<Vec<TxTrace>>>>,
+    get_info_receiver: Receiver<Request<(), TxPoolInfo>>,
+    clear_receiver: Receiver<Request<(), ()>>,
+    estimate_fee_rate_receiver: Receiver<Request<BlockNumber, Capacity>>,
+    set_min_fee_rate_receiver: Receiver<Request<Capacity, ()>>,
+    dry_run_transaction_receiver: Receiver<Request<(Transaction, Option<Cycle>), Result<Cycle, PoolError>>>,
 }
 
 impl TransactionPoolController {
+    pub fn stop(&self) {
+        self.stop.stop();
+    }
+
     pub fn get_proposal_commit_transactions(
         &self,
         max_prop: usize,
@@ -85,10 +155,48 @@ impl TransactionPoolController {
         Request::call(&self.get_transaction_sender, id).expect("get_transaction() failed")
     }
 
+    pub fn get_transaction_status(&self, id: ProposalShortId) -> Option<TxStatus> {
+        Request::call(&self.get_transaction_status_sender, id)
+            .expect("get_transaction_status() failed")
+    }
+
+    /// Move a transaction to `stage`, see `TransactionPoolService::move_transaction`
+    pub fn move_transaction(&self, id: ProposalShortId, stage: TxStatus) -> MoveReturn {
+        Request::call(&self.move_transaction_sender, (id, stage))
+            .expect("move_transaction() failed")
+    }
+
     pub fn add_transaction(&self, tx: Transaction) -> Result<InsertionResult, PoolError> {
         Request::call(&self.add_transaction_sender, tx).expect("add_transaction() failed")
     }
 
+    /// Like `add_transaction` but returns immediately, the result is sent to the returned
+    /// receiver once the transaction is verified.
+    pub fn submit_transaction(
+        &self,
+        tx: Transaction,
+    ) -> Receiver<Result<InsertionResult, PoolError>> {
+        Request::send(&self.add_transaction_sender, tx)
+    }
+
+    /// Add a transaction submitted through this node, it will be returned by
+    /// `get_local_transactions` until it is committed or expired.
+    pub fn add_local_transaction(&self, tx: Transaction) -> Result<InsertionResult, PoolError> {
+        Request::call(&self.add_local_transaction_sender, tx)
+            .expect("add_local_transaction() failed")
+    }
+
+    /// Add transactions, the parents in the batch before their children, a transaction
+    /// spending a rejected one is rejected as well. The results are in the order of `txs`.
+    pub fn add_transactions(&self, txs: Vec<Transaction>) -> Vec<Result<H256, PoolError>> {
+        Request::call(&self.add_transactions_sender, txs).expect("add_transactions() failed")
+    }
+
+    pub fn get_local_transactions(&self) -> Vec<Transaction> {
+        Request::call(&self.get_local_transactions_sender, ())
+            .expect("get_local_transactions() failed")
+    }
+
     pub fn trace_transaction(&self, tx: Transaction) -> Result<InsertionResult, PoolError> {
         Request::call(&self.reg_trace_sender, tx).expect("trace_transaction() failed")
     }
@@ -100,9 +208,111 @@ impl TransactionPoolController {
     pub fn get_last_txs_updated_at(&self) -> u64 {
         self.last_txs_updated_at.load(Ordering::SeqCst) as u64
     }
+
+    /// Waits until the pool reconciled the new tip `hash`, up to `timeout`.
+    /// Returns false when it is not reconciled by then.
+    pub fn wait_for_tip(&self, hash: &H256, timeout: Duration) -> bool {
+        let (ref tip, ref reconciled) = *self.reconciled_tip;
+        let deadline = Instant::now() + timeout;
+        let mut tip = tip.lock().expect("reconciled tip lock");
+        while &*tip != hash {
+            let now = Instant::now();
+            if now >= deadline {
+                return false;
+            }
+            tip = reconciled
+                .wait_timeout(tip, deadline - now)
+                .expect("reconciled tip lock")
+                .0;
+        }
+        true
+    }
+
+    pub fn get_info(&self) -> TxPoolInfo {
+        Request::call(&self.get_info_sender, ()).expect("get_info() failed")
+    }
+
+    /// Drop every transaction in the pool, the proposals seen on chain are kept.
+    /// Each of them is notified as removed.
+    pub fn clear(&self) {
+        Request::call(&self.clear_sender, ()).expect("clear() failed")
+    }
+
+    /// Fee rate per 1000 bytes for a transaction to be committed within `blocks` blocks
+    pub fn estimate_fee_rate(&self, blocks: BlockNumber) -> Capacity {
+        Request::call(&self.estimate_fee_rate_sender, blocks).expect("estimate_fee_rate() failed")
+    }
+
+    /// Change the minimal fee rate per 1000 bytes, the transactions already in the pool are kept
+    pub fn set_min_fee_rate(&self, rate: Capacity) {
+        Request::call(&self.set_min_fee_rate_sender, rate).expect("set_min_fee_rate() failed")
+    }
+
+    /// Verify a transaction against the chain and the pool without adding it, returns the
+    /// cycles its scripts take. They run at most `max_cycles`, and never more than the
+    /// cycles a transaction may take.
+    pub fn dry_run_transaction(
+        &self,
+        tx: Transaction,
+        max_cycles: Option<Cycle>,
+    ) -> Result<Cycle, PoolError> {
+        Request::call(&self.dry_run_transaction_sender, (tx, max_cycles))
+            .expect("dry_run_transaction() failed")
+    }
+
+    /// Write the local transactions to `path`, which `load_local_transactions` adds back after
+    /// a restart. The relayed ones are relayed again by the peers.
+    pub fn save_local_transactions(&self, path: &Path) -> io::Result<usize> {
+        let txs = self.get_local_transactions();
+        // A crash while writing leaves the previous file
+        let tmp = path.with_extension("tmp");
+        let mut writer = BufWriter::new(File::create(&tmp)?);
+        bincode::serialize_into(&mut writer, &txs)
+            .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+        writer.flush()?;
+        writer.get_ref().sync_all()?;
+        fs::rename(&tmp, path)?;
+        Ok(txs.len())
+    }
+
+    /// Add back the local transactions saved to `path`, the ones committed or no longer valid
+    /// meanwhile are dropped. Returns how many are added.
+    pub fn load_local_transactions(&self, path: &Path) -> io::Result<usize> {
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(0),
+            Err(err) => return Err(err),
+        };
+        let mut txs: Vec<Transaction> = bincode::deserialize_from(BufReader::new(file))
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+
+        // The transactions are saved in no particular order, a child is added once its
+        // parent is
+        let mut added = 0;
+        loop {
+            let before = txs.len();
+            txs.retain(|tx| match self.add_local_transaction(tx.clone()) {
+                Ok(_) => {
+                    added += 1;
+                    false
+                }
+                Err(PoolError::InvalidTx(TransactionError::UnknownInput(_))) => true,
+                Err(_) => false,
+            });
+            if txs.is_empty() || txs.len() == before {
+                break;
+            }
+        }
+        Ok(added)
+    }
 }
 
 /// The pool itself.
+// The transactions verified by a job, with the result of each
+type Verified = Vec<(ResolvedTransaction, Result<Cycle, TransactionError>)>;
+type Continuation<CI> = Box<dyn FnMut(&mut TransactionPoolService<CI>) + Send>;
+type VerifiedContinuation<CI> = Box<dyn FnMut(&mut TransactionPoolService<CI>, Verified) + Send>;
+
 pub struct TransactionPoolService<CI> {
     config: PoolConfig,
     /// The short id that has not been proposed
@@ -120,8 +330,30 @@ pub struct TransactionPoolService<CI> {
     notify: NotifyController,
 
     trace: TxTraceMap,
+    /// Locally submitted transactions and their submission time
+    local: FnvHashMap<ProposalShortId, u64>,
+    /// Allowance for transactions below the minimum fee rate
+    free_tx_bucket: FreeTxBucket,
+    fee_estimator: FeeEstimator,
+
+    /// Runs the scripts of the transactions off the service thread, which
+    /// gets the results back on `verified_receiver`
+    verifier: ThreadPool,
+    verified_sender: Sender<(u64, Verified)>,
+    verified_receiver: Receiver<(u64, Verified)>,
+    /// What to do with the results of each job being verified
+    verifying: FnvHashMap<u64, VerifiedContinuation<CI>>,
+    next_job: u64,
+    /// Bumped by `clear`, the transactions verified meanwhile are dropped
+    generation: u64,
+    /// Continuations run by the service loop, never by the code scheduling
+    /// them, so that long chains of them do not grow the stack
+    ready: VecDeque<Continuation<CI>>,
 
     last_txs_updated_at: Arc<AtomicUsize>,
+    /// The last tip the pool reconciled, chain events are handled on the
+    /// service thread after the chain moved on
+    reconciled_tip: ReconciledTip,
 }
 
 impl<CI> CellProvider for TransactionPoolService<CI>
@@ -160,7 +392,15 @@ where
         let prop_cap = ProposedQueue::cap();
         let ids = shared.union_proposal_ids_n(n, prop_cap);
         let trace_size = config.trace.unwrap_or(0);
+        let free_tx_allowance = config.free_tx_allowance;
         let last_txs_updated_at = Arc::new(AtomicUsize::new(0));
+        let tip_hash = shared.chain_state().read().tip_hash();
+        let reconciled_tip = Arc::new((Mutex::new(tip_hash), Condvar::new()));
+        let verifier = ThreadPoolBuilder::new()
+            .thread_name(|index| format!("PoolVerifier{}", index))
+            .build()
+            .expect("Start the transaction verifier failed!");
+        let (verified_sender, verified_receiver) = crossbeam_channel::unbounded();
 
         TransactionPoolService {
             config,
@@ -172,7 +412,93 @@ where
             shared,
             notify,
             last_txs_updated_at,
+            reconciled_tip,
             trace: TxTraceMap::new(trace_size),
+            local: FnvHashMap::default(),
+            free_tx_bucket: FreeTxBucket::new(free_tx_allowance),
+            fee_estimator: FeeEstimator::new(),
+            verifier,
+            verified_sender,
+            verified_receiver,
+            verifying: FnvHashMap::default(),
+            next_job: 0,
+            generation: 0,
+            ready: VecDeque::new(),
+        }
+    }
+
+    // Verifies `rtxs` on the verifier threads, `then` gets them back with the
+    // results on the service thread
+    fn verify_async<F>(&mut self, rtxs: Vec<ResolvedTransaction>, max_cycles: Cycle, then: F)
+    where
+        F: FnOnce(&mut Self, Verified) + Send + 'static,
+    {
+        let job = self.next_job;
+        self.next_job += 1;
+        let mut then = Some(then);
+        self.verifying.insert(
+            job,
+            Box::new(move |pool: &mut Self, verified| {
+                if let Some(then) = then.take() {
+                    then(pool, verified)
+                }
+            }),
+        );
+        let verified_sender = self.verified_sender.clone();
+        self.verifier.spawn(move || {
+            let results: Vec<_> = rtxs
+                .par_iter()
+                .map(|rtx| TransactionVerifier::new(rtx).verify(max_cycles))
+                .collect();
+            let _ = verified_sender.send((job, rtxs.into_iter().zip(results).collect()));
+        });
+    }
+
+    fn complete_verification(&mut self, (job, verified): (u64, Verified)) {
+        if let Some(mut then) = self.verifying.remove(&job) {
+            then(self, verified);
+        }
+        self.run_ready();
+    }
+
+    // Schedules `then` with `ret` for the service loop
+    fn finish<F, R>(&mut self, then: F, ret: R)
+    where
+        F: FnOnce(&mut Self, R) + Send + 'static,
+        R: Send + 'static,
+    {
+        let mut then = Some((then, ret));
+        self.ready.push_back(Box::new(move |pool: &mut Self| {
+            if let Some((then, ret)) = then.take() {
+                then(pool, ret)
+            }
+        }));
+    }
+
+    fn run_ready(&mut self) {
+        while let Some(mut then) = self.ready.pop_front() {
+            then(self);
+        }
+    }
+
+    // Completes the verifications, the ones sending `response` and the ones
+    // they lead to, for the callers needing a result at once
+    fn wait<R>(&mut self, response: Receiver<R>) -> R {
+        self.complete_verifications();
+        response
+            .try_recv()
+            .expect("sent once the verifications complete")
+    }
+
+    /// Waits for the transactions being verified, and adds the valid ones
+    pub(crate) fn complete_verifications(&mut self) {
+        self.run_ready();
+        while !self.verifying.is_empty() {
+            let job = self
+                .verified_receiver
+                .recv()
+                .expect("the service holds a sender");
+            self.complete_verification(job);
         }
     }
 
@@ -187,21 +513,49 @@ where
             crossbeam_channel::bounded(DEFAULT_CHANNEL_SIZE);
         let (get_transaction_sender, get_transaction_receiver) =
             crossbeam_channel::bounded(DEFAULT_CHANNEL_SIZE);
+        let (get_transaction_status_sender, get_transaction_status_receiver) =
+            crossbeam_channel::bounded(DEFAULT_CHANNEL_SIZE);
+        let (move_transaction_sender, move_transaction_receiver) =
+            crossbeam_channel::bounded(DEFAULT_CHANNEL_SIZE);
         let (add_transaction_sender, add_transaction_receiver) =
             crossbeam_channel::bounded(DEFAULT_CHANNEL_SIZE);
+        let (add_local_transaction_sender, add_local_transaction_receiver) =
+            crossbeam_channel::bounded(DEFAULT_CHANNEL_SIZE);
+        let (get_local_transactions_sender, get_local_transactions_receiver) =
+            crossbeam_channel::bounded(DEFAULT_CHANNEL_SIZE);
+        let (add_transactions_sender, add_transactions_receiver) =
+            crossbeam_channel::bounded(DEFAULT_CHANNEL_SIZE);
         let (reg_trace_sender, reg_trace_receiver) =
             crossbeam_channel::bounded(DEFAULT_CHANNEL_SIZE);
         let (get_trace_sender, get_trace_receiver) =
             crossbeam_channel::bounded(DEFAULT_CHANNEL_SIZE);
+        let (get_info_sender, get_info_receiver) = crossbeam_channel::bounded(DEFAULT_CHANNEL_SIZE);
+        let (clear_sender, clear_receiver) = crossbeam_channel::bounded(DEFAULT_CHANNEL_SIZE);
+        let (estimate_fee_rate_sender, estimate_fee_rate_receiver) =
+            crossbeam_channel::bounded(DEFAULT_CHANNEL_SIZE);
+        let (set_min_fee_rate_sender, set_min_fee_rate_receiver) =
+            crossbeam_channel::bounded(DEFAULT_CHANNEL_SIZE);
+        let (dry_run_transaction_sender, dry_run_transaction_receiver) =
+            crossbeam_channel::bounded(DEFAULT_CHANNEL_SIZE);
 
         let receivers = TransactionPoolReceivers {
             get_proposal_commit_transactions_receiver,
             get_potential_transactions_receiver,
             contains_key_receiver,
             get_transaction_receiver,
+            get_transaction_status_receiver,
+            move_transaction_receiver,
             add_transaction_receiver,
+            add_local_transaction_receiver,
+            get_local_transactions_receiver,
+            add_transactions_receiver,
             reg_trace_receiver,
             get_trace_receiver,
+            get_info_receiver,
+            clear_receiver,
+            estimate_fee_rate_receiver,
+            set_min_fee_rate_receiver,
+            dry_run_transaction_receiver,
         };
 
         let mut thread_builder = thread::Builder::new();
@@ -210,19 +564,29 @@ where
             thread_builder = thread_builder.name(name.to_string());
         }
 
-        let new_tip_receiver = self.notify.subscribe_new_tip(TXS_POOL_SUBSCRIBER);
-        let switch_fork_receiver = self.notify.subscribe_switch_fork(TXS_POOL_SUBSCRIBER);
+        // A fork switch is followed by its new tip, on the same channel
+        let chain_event_receiver = self
+            .notify
+            .subscribe(TXS_POOL_SUBSCRIBER, &[EventKind::SwitchFork, EventKind::NewTip]);
 
         let last_txs_updated_at = Arc::clone(&self.last_txs_updated_at);
+        let reconciled_tip = Arc::clone(&self.reconciled_tip);
+        let metrics = PoolMetrics::new();
+        let verified_receiver = self.verified_receiver.clone();
         let thread = thread_builder
-            .spawn(move || loop {
+            .spawn(ckb_time::inherit(move || loop {
                 select!{
                     recv(signal_receiver) -> _ => {
+                        // answer the requests being verified
+                        self.complete_verifications();
                         break;
                     },
 
-                    recv(new_tip_receiver) -> msg => self.handle_new_tip(msg),
-                    recv(switch_fork_receiver) -> msg => self.handle_switch_fork(msg),
+                    recv(chain_event_receiver) -> msg => self.handle_chain_event(msg),
+
+                    recv(verified_receiver) -> msg => if let Ok(job) = msg {
+                        self.complete_verification(job);
+                    },
 
                     recv(receivers.get_proposal_commit_transactions_receiver) -> msg => {
                         self.handle_get_proposal_commit_transactions(msg)
@@ -251,17 +615,65 @@ where
                             error!(target: "txs_pool", "channel get_transaction_receiver closed");
                         }
                     },
+                    recv(receivers.get_transaction_status_receiver) -> msg => match msg {
+                        Ok(Request { responder, arguments: id }) => {
+                            let _ = responder.send(self.get_status(&id));
+                        }
+                        _ => {
+                            error!(target: "txs_pool", "channel get_transaction_status_receiver closed");
+                        }
+                    },
+                    recv(receivers.move_transaction_receiver) -> msg => match msg {
+                        Ok(Request { responder, arguments: (id, stage) }) => {
+                            let _ = responder.send(self.move_transaction(&id, stage));
+                        }
+                        _ => {
+                            error!(target: "txs_pool", "channel move_transaction_receiver closed");
+                        }
+                    },
                     recv(receivers.add_transaction_receiver) -> msg => match msg {
                         Ok(Request { responder, arguments: tx }) => {
-                            let _ = responder.send(self.add_transaction(tx));
+                            self.add_transaction_then(tx, move |_, ret| {
+                                let _ = responder.send(ret);
+                            });
                         }
                         _ => {
                             error!(target: "txs_pool", "channel add_transaction_receiver closed");
                         }
                     },
+                    recv(receivers.add_local_transaction_receiver) -> msg => match msg {
+                        Ok(Request { responder, arguments: tx }) => {
+                            self.add_local_transaction_then(tx, move |_, ret| {
+                                let _ = responder.send(ret);
+                            });
+                        }
+                        _ => {
+                            error!(target: "txs_pool", "channel add_local_transaction_receiver closed");
+                        }
+                    },
+                    recv(receivers.get_local_transactions_receiver) -> msg => match msg {
+                        Ok(Request { responder, ..}) => {
+                            let _ = responder.send(self.get_local_transactions());
+                        }
+                        _ => {
+                            error!(target: "txs_pool", "channel get_local_transactions_receiver closed");
+                        }
+                    },
+                    recv(receivers.add_transactions_receiver) -> msg => match msg {
+                        Ok(Request { responder, arguments: txs }) => {
+                            self.add_transactions_then(txs, move |_, results| {
+                                let _ = responder.send(results);
+                            });
+                        }
+                        _ => {
+                            error!(target: "txs_pool", "channel add_transactions_receiver closed");
+                        }
+                    },
                     recv(receivers.reg_trace_receiver) -> msg => match msg {
                         Ok(Request { responder, arguments: tx }) => {
-                            let _ = responder.send(self.trace_transaction(tx));
+                            self.trace_transaction_then(tx, move |_, ret| {
+                                let _ = responder.send(ret);
+                            });
                         }
                         _ => {
                             error!(target: "txs_pool", "channel reg_trace_receiver closed");
@@ -274,9 +686,53 @@ where
                         _ => {
                             error!(target: "txs_pool", "channel get_trace_receiver closed");
                         }
+                    },
+                    recv(receivers.get_info_receiver) -> msg => match msg {
+                        Ok(Request { responder, ..}) => {
+                            let _ = responder.send(self.info());
+                        }
+                        _ => {
+                            error!(target: "txs_pool", "channel get_info_receiver closed");
+                        }
+                    },
+                    recv(receivers.clear_receiver) -> msg => match msg {
+                        Ok(Request { responder, ..}) => {
+                            let _ = responder.send(self.clear());
+                        }
+                        _ => {
+                            error!(target: "txs_pool", "channel clear_receiver closed");
+                        }
+                    },
+                    recv(receivers.estimate_fee_rate_receiver) -> msg => match msg {
+                        Ok(Request { responder, arguments: blocks }) => {
+                            let _ = responder.send(self.estimate_fee_rate(blocks));
+                        }
+                        _ => {
+                            error!(target: "txs_pool", "channel estimate_fee_rate_receiver closed");
+                        }
+                    },
+                    recv(receivers.set_min_fee_rate_receiver) -> msg => match msg {
+                        Ok(Request { responder, arguments: rate }) => {
+                            let _ = responder.send(self.set_min_fee_rate(rate));
+                        }
+                        _ => {
+                            error!(target: "txs_pool", "channel set_min_fee_rate_receiver closed");
+                        }
+                    },
+                    recv(receivers.dry_run_transaction_receiver) -> msg => match msg {
+                        Ok(Request { responder, arguments: (tx, max_cycles) }) => {
+                            self.dry_run_transaction_then(tx, max_cycles, move |_, ret| {
+                                let _ = responder.send(ret);
+                            });
+                        }
+                        _ => {
+                            error!(target: "txs_pool", "channel dry_run_transaction_receiver closed");
+                        }
                     }
                 }
-            }).expect("Start TransactionPoolService failed!");
+                self.run_ready();
+                metrics.update(&self.info());
+            })).expect("Start TransactionPoolService failed!");
 
         let stop = StopHandler::new(SignalSender::Crossbeam(signal_sender), thread);
 
@@ -285,28 +741,43 @@ where
             get_potential_transactions_sender,
             contains_key_sender,
             get_transaction_sender,
+            get_transaction_status_sender,
+            move_transaction_sender,
             add_transaction_sender,
+            add_local_transaction_sender,
+            get_local_transactions_sender,
+            add_transactions_sender,
             reg_trace_sender,
             get_trace_sender,
+            get_info_sender,
+            clear_sender,
+            estimate_fee_rate_sender,
+            set_min_fee_rate_sender,
+            dry_run_transaction_sender,
             last_txs_updated_at,
+            reconciled_tip,
             stop,
         }
     }
 
-    fn handle_new_tip(&mut self, msg: Result<MsgNewTip, crossbeam_channel::RecvError>) {
+    pub(crate) fn handle_chain_event(&mut self, msg: Result<Event, crossbeam_channel::RecvError>) {
         match msg {
-            Ok(block) => self.reconcile_block(&block),
-            _ => {
-                error!(target: "txs_pool", "channel new_tip_receiver closed");
+            Ok(Event::NewTip(block)) => {
+                // Reconciled once its proposed transactions are verified too
+                let hash = block.header().hash();
+                let reconciled_tip = Arc::clone(&self.reconciled_tip);
+                self.reconcile_block_then(&block, move |_| {
+                    let (ref tip, ref reconciled) = *reconciled_tip;
+                    *tip.lock().expect("reconciled tip lock") = hash;
+                    reconciled.notify_all();
+                });
+            }
+            Ok(Event::SwitchFork(blocks)) => self.switch_fork(&blocks),
+            Ok(event) => {
+                error!(target: "txs_pool", "unexpected event {:?}", event.kind());
             }
-        }
-    }
-
-    fn handle_switch_fork(&mut self, msg: Result<MsgSwitchFork, crossbeam_channel::RecvError>) {
-        match msg {
-            Ok(blocks) => self.switch_fork(&blocks),
             _ => {
-                error!(target: "txs_pool", "channel switch_fork_receiver closed");
+                error!(target: "txs_pool", "channel chain_event_receiver closed");
             }
         }
     }
@@ -330,8 +801,8 @@ where
         }
     }
 
-    pub(crate) fn switch_fork(&mut self, blks: &ForkBlocks) {
-        for b in blks.old_blks() {
+    pub(crate) fn switch_fork(&mut self, blks: &ForkSwitch) {
+        for b in &blks.detached {
             let bn = b.header().number();
             let mut txs = b.commit_transactions().to_vec();
             txs.reverse();
@@ -381,8 +852,8 @@ where
         }
 
         // We may not need readd timeout transactions in pool, because new main chain is mostly longer
-        for blk in blks.new_blks() {
-            self.reconcile_block(&blk);
+        for blk in &blks.attached {
+            self.reconcile_block(blk);
         }
     }
 
@@ -404,6 +875,78 @@ where
             .or_else(|| self.cache.get(id).cloned())
     }
 
+    pub(crate) fn get_status(&self, id: &ProposalShortId) -> Option<TxStatus> {
+        if self.pending.contains_key(id) {
+            Some(TxStatus::Pending)
+        } else if self.proposed.contains_key(id) {
+            Some(TxStatus::Proposed)
+        } else if self.pool.contains_key(id) {
+            Some(TxStatus::Mineable)
+        } else if self.orphan.contains_key(id) {
+            Some(TxStatus::Orphan)
+        } else if self.cache.contains_key(id) {
+            Some(TxStatus::Conflict)
+        } else {
+            None
+        }
+    }
+
+    /// Moves the transaction `id` to `stage` and returns the stage it reached.
+    ///
+    /// Back to `Pending` it is taken from any other stage, waiting to be
+    /// proposed again, and a mineable one takes its descendants along. Only a
+    /// pending transaction moves forward to `Proposed` or `Mineable`, when the
+    /// chain proposed it: it reaches the stage its proposal allows, which may
+    /// be behind `stage`.
+    pub(crate) fn move_transaction(
+        &mut self,
+        id: &ProposalShortId,
+        stage: TxStatus,
+    ) -> Result<TxStatus, PoolError> {
+        let current = self.get_status(id).ok_or(PoolError::NotInPool)?;
+        match (current, stage) {
+            (TxStatus::Pending, TxStatus::Pending) => Ok(TxStatus::Pending),
+            (_, TxStatus::Pending) => {
+                let txs = match current {
+                    TxStatus::Proposed => self.proposed.remove_transaction(id).map(|tx| vec![tx]),
+                    TxStatus::Mineable => self.pool.remove(id),
+                    TxStatus::Orphan => self.orphan.remove(id).map(|tx| vec![tx]),
+                    _ => self.cache.remove(id).map(|tx| vec![tx]),
+                };
+                for tx in txs.unwrap_or_default() {
+                    if self.config.trace_enable() {
+                        self.trace.timeout(&tx.hash(), "moved back to pending");
+                    }
+                    self.pending.insert(tx.proposal_short_id(), tx);
+                }
+                self.last_txs_updated_at
+                    .store(unix_time_as_millis() as usize, Ordering::SeqCst);
+                Ok(TxStatus::Pending)
+            }
+            (TxStatus::Pending, TxStatus::Proposed) | (TxStatus::Pending, TxStatus::Mineable) => {
+                let tx = self.pending.remove(id).expect("pending transaction");
+                match self.proposed.insert(tx) {
+                    TxStage::Mineable(tx) => {
+                        if let Err(error) = self.add_to_pool(tx.clone()) {
+                            // Waits for the next move, unless it was cached as a double spend
+                            if self.get_status(id).is_none() {
+                                self.pending.insert(id.clone(), tx);
+                            }
+                            return Err(error);
+                        }
+                        self.get_status(id).ok_or(PoolError::NotInPool)
+                    }
+                    TxStage::Proposed => Ok(TxStatus::Proposed),
+                    TxStage::Unknown(tx) | TxStage::Fork(tx) | TxStage::TimeOut(tx) => {
+                        self.pending.insert(id.clone(), tx);
+                        Err(PoolError::NotProposed)
+                    }
+                }
+            }
+            _ => Err(PoolError::InvalidStage),
+        }
+    }
+
     /// Get the size of transactions in the pool
     pub(crate) fn pool_size(&self) -> usize {
         self.pool.size()
@@ -440,36 +983,268 @@ where
         self.pool_size() + self.orphan_size()
     }
 
+    pub(crate) fn info(&self) -> TxPoolInfo {
+        TxPoolInfo {
+            pending: self.pending.size(),
+            proposed: self.proposed.size(),
+            mineable: self.pool.size(),
+            orphan: self.orphan.size(),
+            conflict: self.cache.len(),
+            last_txs_updated_at: self.last_txs_updated_at.load(Ordering::SeqCst) as u64,
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        let dropped: Vec<H256> = self
+            .pending
+            .transactions()
+            .chain(self.proposed.transactions())
+            .chain(self.pool.vertices.values().map(|entry| &entry.transaction))
+            .chain(self.orphan.vertices.values().map(|entry| &entry.transaction))
+            .map(Transaction::hash)
+            .collect();
+        let n = self.shared.chain_state().read().tip_number();
+        let ids = self.shared.union_proposal_ids_n(n, ProposedQueue::cap());
+        self.pending = PendingQueue::new();
+        self.proposed = ProposedQueue::new(n, ids);
+        self.pool = Pool::new();
+        self.orphan = Orphan::new();
+        self.cache.clear();
+        self.local.clear();
+        self.generation += 1;
+        self.fee_estimator.forget_all();
+        self.last_txs_updated_at
+            .store(unix_time_as_millis() as usize, Ordering::SeqCst);
+        for hash in dropped {
+            self.notify.notify_pool_event(PoolEvent::TxRemoved {
+                hash,
+                reason: RemovedReason::Cleared,
+            });
+        }
+    }
+
     pub(crate) fn add_transaction(
         &mut self,
         tx: Transaction,
     ) -> Result<InsertionResult, PoolError> {
+        let (done, response) = crossbeam_channel::bounded(1);
+        self.add_transaction_then(tx, move |_, ret| {
+            let _ = done.send(ret);
+        });
+        self.wait(response)
+    }
+
+    /// Adds the transaction, `then` gets the result once it is verified
+    pub(crate) fn add_transaction_then<F>(&mut self, tx: Transaction, then: F)
+    where
+        F: FnOnce(&mut Self, Result<InsertionResult, PoolError>) + Send + 'static,
+    {
+        self.add_transaction_cycles_then(tx, None, then)
+    }
+
+    // `cycles` are the ones of a transaction already verified on the cells it spends, it is
+    // not verified again once mineable
+    fn add_transaction_cycles_then<F>(&mut self, tx: Transaction, cycles: Option<Cycle>, then: F)
+    where
+        F: FnOnce(&mut Self, Result<InsertionResult, PoolError>) + Send + 'static,
+    {
+        let _scope = ckb_profiler::scope("add_transaction");
+        if tx.is_cellbase() {
+            return self.finish(then, Err(PoolError::Cellbase));
+        }
+        let tip = self.shared.chain_state().read().tip_number();
+        self.fee_estimator.track(tx.proposal_short_id(), tip);
+
         match { self.proposed.insert(tx) } {
-            TxStage::Mineable(x) => self.add_to_pool(x),
+            TxStage::Mineable(x) => match cycles {
+                Some(cycles) => {
+                    let ret = self.add_verified_to_pool(x, cycles);
+                    self.finish(then, ret)
+                }
+                None => self.add_to_pool_then(x, then),
+            },
             TxStage::Unknown(x) => {
+                if let Err(error) = self.check_pending_admission(&x) {
+                    self.fee_estimator.forget(&x.proposal_short_id());
+                    return self.finish(then, Err(error));
+                }
                 self.pending.insert(x.proposal_short_id(), x);
-                Ok(InsertionResult::Unknown)
+                self.finish(then, Ok(InsertionResult::Unknown))
+            }
+            _ => self.finish(then, Ok(InsertionResult::Proposed)),
+        }
+    }
+
+    // The transactions wait in pending until they are proposed, within `max_pending_size`
+    // unless they are local, and they pay the fee rate of the pool at once. The ones spending
+    // cells the pool does not know pay none.
+    fn check_pending_admission(&mut self, tx: &Transaction) -> Result<(), PoolError> {
+        let id = tx.proposal_short_id();
+        if self.pending.size() >= self.config.max_pending_size && !self.local.contains_key(&id)
+        {
+            return Err(PoolError::OverCapacity);
+        }
+        let rtx = self.resolve_submission(tx)?;
+        self.check_fee_rate(&rtx)
+    }
+
+    pub(crate) fn add_transactions(
+        &mut self,
+        txs: Vec<Transaction>,
+    ) -> Vec<Result<H256, PoolError>> {
+        let (done, response) = crossbeam_channel::bounded(1);
+        self.add_transactions_then(txs, move |_, results| {
+            let _ = done.send(results);
+        });
+        self.wait(response)
+    }
+
+    /// Adds the parents in the batch before their children, `then` gets the results in the
+    /// order of `txs`
+    pub(crate) fn add_transactions_then<F>(&mut self, txs: Vec<Transaction>, then: F)
+    where
+        F: FnOnce(&mut Self, Vec<Result<H256, PoolError>>) + Send + 'static,
+    {
+        let order = topological_order(&txs);
+        let mut txs: Vec<_> = txs.into_iter().map(Some).collect();
+        let sorted: Vec<_> = order
+            .iter()
+            .map(|index| txs[*index].take().expect("each index once"))
+            .collect();
+        let results = Vec::with_capacity(sorted.len());
+        let rejected = FnvHashSet::default();
+        self.add_transactions_from(sorted.into_iter(), results, rejected, move |pool, sorted| {
+            let mut results: Vec<_> = (0..order.len()).map(|_| None).collect();
+            for (index, ret) in order.into_iter().zip(sorted) {
+                results[index] = Some(ret);
+            }
+            let results = results
+                .into_iter()
+                .map(|ret| ret.expect("one result per transaction"))
+                .collect();
+            then(pool, results)
+        });
+    }
+
+    // Adds the transactions one after the other, so that a transaction spending
+    // one rejected earlier is known to be
+    fn add_transactions_from<F>(
+        &mut self,
+        mut txs: vec::IntoIter<Transaction>,
+        mut results: Vec<Result<H256, PoolError>>,
+        mut rejected: FnvHashSet<H256>,
+        then: F,
+    ) where
+        F: FnOnce(&mut Self, Vec<Result<H256, PoolError>>) + Send + 'static,
+    {
+        while let Some(tx) = txs.next() {
+            let hash = tx.hash();
+            let spends_rejected = tx
+                .input_pts()
+                .iter()
+                .chain(tx.dep_pts().iter())
+                .any(|o| rejected.contains(&o.hash));
+            if spends_rejected {
+                rejected.insert(hash);
+                results.push(Err(PoolError::ParentRejected));
+                continue;
+            }
+            return self.add_transaction_then(tx, move |pool, ret| {
+                let ret = ret.map(|_| hash.clone());
+                if ret.is_err() {
+                    rejected.insert(hash);
+                }
+                results.push(ret);
+                pool.add_transactions_from(txs, results, rejected, then)
+            });
+        }
+        self.finish(then, results)
+    }
+
+    pub(crate) fn add_local_transaction(
+        &mut self,
+        tx: Transaction,
+    ) -> Result<InsertionResult, PoolError> {
+        let (done, response) = crossbeam_channel::bounded(1);
+        self.add_local_transaction_then(tx, move |_, ret| {
+            let _ = done.send(ret);
+        });
+        self.wait(response)
+    }
+
+    pub(crate) fn add_local_transaction_then<F>(&mut self, tx: Transaction, then: F)
+    where
+        F: FnOnce(&mut Self, Result<InsertionResult, PoolError>) + Send + 'static,
+    {
+        self.verify_submission_then(tx, move |pool, ret| match ret {
+            Ok((tx, cycles)) => {
+                let id = tx.proposal_short_id();
+                // over the budget it is handled like a relayed transaction
+                let tracked = pool.local.len() < pool.config.max_local_size;
+                if tracked {
+                    pool.local.insert(id, unix_time_as_millis());
+                }
+                pool.add_transaction_cycles_then(tx, Some(cycles), move |pool, ret| {
+                    if tracked && ret.is_err() {
+                        pool.local.remove(&id);
+                    }
+                    then(pool, ret)
+                });
+            }
+            Err(error) => then(pool, Err(error)),
+        });
+    }
+
+    /// Get the locally submitted transactions which are neither committed nor expired
+    pub(crate) fn get_local_transactions(&mut self) -> Vec<Transaction> {
+        let now = unix_time_as_millis();
+        self.local
+            .retain(|_, submitted_at| *submitted_at + LOCAL_TX_EXPIRY > now);
+
+        let mut txs = Vec::with_capacity(self.local.len());
+        let mut stale = Vec::new();
+        for id in self.local.keys() {
+            match self.get(id) {
+                Some(tx) => txs.push(tx),
+                None => stale.push(*id),
             }
-            _ => Ok(InsertionResult::Proposed),
         }
+        for id in stale {
+            self.local.remove(&id);
+        }
+        txs
     }
 
     pub(crate) fn trace_transaction(
         &mut self,
         tx: Transaction,
     ) -> Result<InsertionResult, PoolError> {
+        let (done, response) = crossbeam_channel::bounded(1);
+        self.trace_transaction_then(tx, move |_, ret| {
+            let _ = done.send(ret);
+        });
+        self.wait(response)
+    }
+
+    pub(crate) fn trace_transaction_then<F>(&mut self, tx: Transaction, then: F)
+    where
+        F: FnOnce(&mut Self, Result<InsertionResult, PoolError>) + Send + 'static,
+    {
         let tx_hash = tx.hash();
         match { self.proposed.insert(tx) } {
-            TxStage::Mineable(x) => self.add_to_pool(x),
+            TxStage::Mineable(x) => self.add_to_pool_then(x, then),
             TxStage::Unknown(x) => {
+                if let Err(error) = self.check_pending_admission(&x) {
+                    return self.finish(then, Err(error));
+                }
                 if self.config.trace_enable() {
                     self.trace
                         .add_pending(&tx_hash, "unknown tx, add to pending");
                 }
                 self.pending.insert(x.proposal_short_id(), x);
-                Ok(InsertionResult::Unknown)
+                self.finish(then, Ok(InsertionResult::Unknown))
             }
-            _ => Ok(InsertionResult::Proposed),
+            _ => self.finish(then, Ok(InsertionResult::Proposed)),
         }
     }
 
@@ -506,8 +1281,225 @@ where
 
     /// Attempts to add a transaction to the memory pool.
     pub(crate) fn add_to_pool(&mut self, tx: Transaction) -> Result<InsertionResult, PoolError> {
+        self.add_to_pool_batch(vec![tx])
+            .pop()
+            .expect("one result per transaction")
+    }
+
+    fn add_to_pool_then<F>(&mut self, tx: Transaction, then: F)
+    where
+        F: FnOnce(&mut Self, Result<InsertionResult, PoolError>) + Send + 'static,
+    {
+        self.add_to_pool_batch_then(vec![tx], move |pool, mut results| {
+            then(pool, results.pop().expect("one result per transaction"))
+        });
+    }
+
+    // Adds a transaction verified on the same cells, without verifying it again
+    fn add_verified_to_pool(
+        &mut self,
+        tx: Transaction,
+        cycles: Cycle,
+    ) -> Result<InsertionResult, PoolError> {
+        let (rtx, unknowns) = self.resolve_for_pool(tx)?;
+        if unknowns.is_empty() {
+            self.add_verified(rtx, cycles)
+        } else {
+            Ok(self.add_orphan(rtx.transaction, unknowns))
+        }
+    }
+
+    pub(crate) fn dry_run_transaction(
+        &mut self,
+        tx: Transaction,
+        max_cycles: Option<Cycle>,
+    ) -> Result<Cycle, PoolError> {
+        let (done, response) = crossbeam_channel::bounded(1);
+        self.dry_run_transaction_then(tx, max_cycles, move |_, ret| {
+            let _ = done.send(ret);
+        });
+        self.wait(response)
+    }
+
+    /// Resolves the transaction on the pool and verifies it on the verifier threads, leaving
+    /// the pool untouched
+    pub(crate) fn dry_run_transaction_then<F>(
+        &mut self,
+        tx: Transaction,
+        max_cycles: Option<Cycle>,
+        then: F,
+    ) where
+        F: FnOnce(&mut Self, Result<Cycle, PoolError>) + Send + 'static,
+    {
+        if tx.is_cellbase() {
+            return self.finish(then, Err(PoolError::Cellbase));
+        }
+        let rtx = self.resolve_transaction(&tx);
+        let max_tx_cycles = self.shared.consensus().max_tx_cycles();
+        let max_cycles = max_cycles.map_or(max_tx_cycles, |cycles| cycles.min(max_tx_cycles));
+        self.verify_async(vec![rtx], max_cycles, move |pool, mut verified| {
+            let (_, ret) = verified.pop().expect("one transaction verified");
+            then(pool, ret.map_err(PoolError::InvalidTx))
+        });
+    }
+
+    // A relayed transaction is verified once proposed, a local one is verified at once so
+    // that its submitter learns why it is rejected. The outputs of the transactions waiting
+    // for their proposal are spendable.
+    fn verify_submission_then<F>(&mut self, tx: Transaction, then: F)
+    where
+        F: FnOnce(&mut Self, Result<(Transaction, Cycle), PoolError>) + Send + 'static,
+    {
+        let rtx = match self.resolve_submission(&tx) {
+            Ok(rtx) => rtx,
+            Err(error) => return self.finish(then, Err(error)),
+        };
+        let max_cycles = self.shared.consensus().max_tx_cycles();
+        let generation = self.generation;
+        self.verify_async(vec![rtx], max_cycles, move |pool, mut verified| {
+            if pool.generation != generation {
+                return then(pool, Err(PoolError::Cleared));
+            }
+            let (rtx, ret) = verified.pop().expect("one transaction verified");
+            let ret = ret
+                .map_err(PoolError::InvalidTx)
+                .and_then(|cycles| pool.check_fee_rate(&rtx).map(|_| cycles))
+                .map(|cycles| (rtx.transaction, cycles));
+            then(pool, ret)
+        });
+    }
+
+    fn resolve_submission(&mut self, tx: &Transaction) -> Result<ResolvedTransaction, PoolError> {
+        if tx.is_cellbase() {
+            return Err(PoolError::Cellbase);
+        }
+        let id = tx.proposal_short_id();
+        if self.pending.contains_key(&id) || self.proposed.contains_key(&id) {
+            return Err(PoolError::AlreadyInPool);
+        }
+        self.check_duplicate(tx)?;
+        self.check_package_limits(tx)?;
+
+        let mut rtx = self.resolve_transaction(tx);
+        let input_cells = rtx.input_cells.iter_mut().zip(tx.input_pts());
+        let dep_cells = rtx.dep_cells.iter_mut().zip(tx.dep_pts());
+        for (cs, o) in input_cells.chain(dep_cells) {
+            if cs.is_unknown() {
+                if let Some(output) = self.queued_output(&o) {
+                    *cs = CellStatus::Live(output);
+                }
+            }
+        }
+        Ok(rtx)
+    }
+
+    fn queued_output(&self, o: &OutPoint) -> Option<CellOutput> {
+        let id = ProposalShortId::from_h256(&o.hash);
+        self.pending
+            .get(&id)
+            .or_else(|| self.proposed.get(&id))
+            .filter(|tx| tx.hash() == o.hash)
+            .and_then(|tx| tx.outputs().get(o.index as usize).cloned())
+    }
+
+    /// Attempts to add transactions to the memory pool, fully resolved transactions are
+    /// verified in parallel. A transaction spending outputs of another one in the same batch
+    /// is verified once its parent is in the pool.
+    pub(crate) fn add_to_pool_batch(
+        &mut self,
+        txs: Vec<Transaction>,
+    ) -> Vec<Result<InsertionResult, PoolError>> {
+        let (done, response) = crossbeam_channel::bounded(1);
+        self.add_to_pool_batch_then(txs, move |_, results| {
+            let _ = done.send(results);
+        });
+        self.wait(response)
+    }
+
+    /// Like `add_to_pool_batch` without waiting, `then` gets the results once the batch is
+    /// verified
+    pub(crate) fn add_to_pool_batch_then<F>(&mut self, txs: Vec<Transaction>, then: F)
+    where
+        F: FnOnce(&mut Self, Vec<Result<InsertionResult, PoolError>>) + Send + 'static,
+    {
+        let results = (0..txs.len()).map(|_| None).collect();
+        let queue = txs.into_iter().enumerate().collect();
+        self.add_to_pool_round(results, queue, then);
+    }
+
+    // Verifies the transactions of `queue` resolved on the pool, the ones spending others
+    // of the queue wait for the next round
+    fn add_to_pool_round<F>(
+        &mut self,
+        mut results: Vec<Option<Result<InsertionResult, PoolError>>>,
+        queue: Vec<(usize, Transaction)>,
+        then: F,
+    ) where
+        F: FnOnce(&mut Self, Vec<Result<InsertionResult, PoolError>>) + Send + 'static,
+    {
+        if queue.is_empty() {
+            let results = results
+                .into_iter()
+                .map(|ret| ret.expect("every transaction is handled"))
+                .collect();
+            return self.finish(then, results);
+        }
+
+        let queued: FnvHashSet<H256> = queue.iter().map(|(_, tx)| tx.hash()).collect();
+        let mut indexes = Vec::new();
+        let mut resolved = Vec::new();
+        let mut deferred = Vec::new();
+        for (index, tx) in queue {
+            match self.resolve_for_pool(tx) {
+                Ok((rtx, unknowns)) => {
+                    if unknowns.is_empty() {
+                        indexes.push(index);
+                        resolved.push(rtx);
+                    } else if unknowns.iter().any(|o| queued.contains(&o.hash)) {
+                        deferred.push((index, rtx.transaction, unknowns));
+                    } else {
+                        results[index] = Some(Ok(self.add_orphan(rtx.transaction, unknowns)));
+                    }
+                }
+                Err(error) => results[index] = Some(Err(error)),
+            }
+        }
+
+        if resolved.is_empty() {
+            // parents in the batch were all rejected
+            for (index, tx, unknowns) in deferred {
+                results[index] = Some(Ok(self.add_orphan(tx, unknowns)));
+            }
+            return self.add_to_pool_round(results, Vec::new(), then);
+        }
+
+        let max_cycles = self.shared.consensus().max_tx_cycles();
+        let generation = self.generation;
+        self.verify_async(resolved, max_cycles, move |pool, verified| {
+            if pool.generation != generation {
+                let dropped = indexes.into_iter().chain(deferred.into_iter().map(|(i, _, _)| i));
+                for index in dropped {
+                    results[index] = Some(Err(PoolError::Cleared));
+                }
+                return pool.add_to_pool_round(results, Vec::new(), then);
+            }
+            for (index, (rtx, ret)) in indexes.into_iter().zip(verified) {
+                let ret = ret
+                    .map_err(PoolError::InvalidTx)
+                    .and_then(|cycles| pool.add_verified(rtx, cycles));
+                results[index] = Some(ret);
+            }
+            let queue = deferred.into_iter().map(|(i, tx, _)| (i, tx)).collect();
+            pool.add_to_pool_round(results, queue, then);
+        });
+    }
+
+    fn resolve_for_pool(
+        &mut self,
+        tx: Transaction,
+    ) -> Result<(ResolvedTransaction, Vec<OutPoint>), PoolError> {
         // Do we have the capacity to accept this transaction?
-        self.is_acceptable()?;
+        self.is_acceptable(&tx.proposal_short_id())?;
 
         if tx.is_cellbase() {
             return Err(PoolError::Cellbase);
@@ -515,99 +1507,211 @@ where
 
         self.check_duplicate(&tx)?;
 
+        self.check_package_limits(&tx)?;
+
         let inputs = tx.input_pts();
         let deps = tx.dep_pts();
 
         let mut unknowns = Vec::new();
 
-        {
-            let rtx = self.resolve_transaction(&tx);
+        let rtx = self.resolve_transaction(&tx);
 
-            for (i, cs) in rtx.input_cells.iter().enumerate() {
-                match cs {
-                    CellStatus::Unknown => {
-                        unknowns.push(inputs[i].clone());
-                    }
-                    CellStatus::Dead => {
-                        self.cache.insert(tx.proposal_short_id(), tx);
-                        return Err(PoolError::DoubleSpent);
-                    }
-                    _ => {}
+        for (i, cs) in rtx.input_cells.iter().enumerate() {
+            match cs {
+                CellStatus::Unknown => {
+                    unknowns.push(inputs[i].clone());
+                }
+                CellStatus::Dead => {
+                    self.cache.insert(tx.proposal_short_id(), tx);
+                    return Err(PoolError::DoubleSpent);
                 }
+                _ => {}
             }
+        }
 
-            for (i, cs) in rtx.dep_cells.iter().enumerate() {
-                match cs {
-                    CellStatus::Unknown => {
-                        unknowns.push(deps[i].clone());
-                    }
-                    CellStatus::Dead => {
-                        self.cache.insert(tx.proposal_short_id(), tx);
-                        return Err(PoolError::DoubleSpent);
-                    }
-                    _ => {}
+        for (i, cs) in rtx.dep_cells.iter().enumerate() {
+            match cs {
+                CellStatus::Unknown => {
+                    unknowns.push(deps[i].clone());
+                }
+                CellStatus::Dead => {
+                    self.cache.insert(tx.proposal_short_id(), tx);
+                    return Err(PoolError::DoubleSpent);
                 }
+                _ => {}
             }
+        }
 
-            if unknowns.is_empty() {
-                // TODO: Parallel
-                TransactionVerifier::new(&rtx)
-                    .verify(self.shared.consensus().max_block_cycles())
-                    .map_err(PoolError::InvalidTx)?;
-            }
+        Ok((rtx, unknowns))
+    }
+
+    fn add_orphan(&mut self, tx: Transaction, unknowns: Vec<OutPoint>) -> InsertionResult {
+        if self.config.trace_enable() {
+            self.trace
+                .add_orphan(&tx.hash(), format!("unknowns {:?}", unknowns));
         }
+        self.orphan.add_transaction(tx, unknowns.into_iter());
+        InsertionResult::Orphan
+    }
 
+    fn add_verified(
+        &mut self,
+        rtx: ResolvedTransaction,
+        cycles: Cycle,
+    ) -> Result<InsertionResult, PoolError> {
+        let unknowns = self.recheck(&rtx.transaction)?;
         if !unknowns.is_empty() {
-            if self.config.trace_enable() {
-                self.trace
-                    .add_orphan(&tx.hash(), format!("unknowns {:?}", unknowns));
-            }
-            self.orphan.add_transaction(tx, unknowns.into_iter());
-            return Ok(InsertionResult::Orphan);
-        } else {
-            if self.config.trace_enable() {
-                self.trace
-                    .add_commit(&tx.hash(), "add to commit pool".to_string());
-            }
-            self.last_txs_updated_at
-                .store(unix_time_as_millis() as usize, Ordering::SeqCst);
-            self.pool.add_transaction(tx.clone());
-            self.reconcile_orphan(&tx);
+            return Ok(self.add_orphan(rtx.transaction, unknowns));
         }
+        self.check_fee_rate(&rtx)?;
+        let fee_rate = self.record_fee_rate(&rtx);
+
+        let tx = rtx.transaction;
+
+        if self.config.trace_enable() {
+            self.trace.add_commit(
+                &tx.hash(),
+                format!("add to commit pool, scripts take {} cycles", cycles),
+            );
+        }
+        self.last_txs_updated_at
+            .store(unix_time_as_millis() as usize, Ordering::SeqCst);
+        self.notify.notify_pool_event(PoolEvent::TxAdded {
+            hash: tx.hash(),
+            size: tx.serialized_size(),
+        });
+        self.pool.add_transaction(tx.clone());
+        self.pool.set_fee_rate(&tx.proposal_short_id(), fee_rate);
+        self.reconcile_orphan(&tx);
 
         Ok(InsertionResult::Normal)
     }
 
+    // The chain and the pool may have changed while `tx` was verified, returns the cells
+    // it misses now
+    fn recheck(&mut self, tx: &Transaction) -> Result<Vec<OutPoint>, PoolError> {
+        let id = tx.proposal_short_id();
+        if self.pool.contains_key(&id) {
+            return Err(PoolError::AlreadyInPool);
+        }
+        if self.shared.get_transaction_address(&tx.hash()).is_some() {
+            return Err(PoolError::Committed);
+        }
+        let rtx = self.resolve_transaction(tx);
+        // a transaction verified in the same batch may have spent the same cells
+        if rtx.is_double_spend() {
+            self.cache.insert(id, tx.clone());
+            return Err(PoolError::DoubleSpent);
+        }
+        let inputs = tx.input_pts().into_iter().zip(&rtx.input_cells);
+        let deps = tx.dep_pts().into_iter().zip(&rtx.dep_cells);
+        Ok(inputs
+            .chain(deps)
+            .filter(|(_, cs)| cs.is_unknown())
+            .map(|(o, _)| o)
+            .collect())
+    }
+
     /// Updates the pool and orphan pool with new transactions.
     pub(crate) fn reconcile_orphan(&mut self, tx: &Transaction) {
         let txs = self.orphan.reconcile_transaction(tx);
+        if txs.is_empty() {
+            return;
+        }
+        let rtxs = txs.iter().map(|tx| self.resolve_transaction(tx)).collect();
+        let max_cycles = self.shared.consensus().max_tx_cycles();
+        let generation = self.generation;
+        self.verify_async(rtxs, max_cycles, move |pool, verified| {
+            if pool.generation != generation {
+                return;
+            }
+            for (rtx, rs) in verified {
+                pool.add_reconciled_orphan(rtx, rs);
+            }
+        });
+    }
 
-        for tx in txs {
-            let rtx = self.resolve_transaction(&tx);
-            let rs =
-                TransactionVerifier::new(&rtx).verify(self.shared.consensus().max_block_cycles());
-            if self.config.trace_enable() {
-                self.trace.add_commit(
-                    &tx.hash(),
-                    format!(
-                        "removed from orphan, prepare add to commit, verify result {:?}",
-                        rs
-                    ),
-                );
-            }
-            if rs.is_ok() {
-                self.last_txs_updated_at
-                    .store(unix_time_as_millis() as usize, Ordering::SeqCst);
-                self.pool.add_transaction(tx);
-            } else if rs == Err(TransactionError::DoubleSpent) {
-                self.cache.insert(tx.proposal_short_id(), tx);
+    fn add_reconciled_orphan(
+        &mut self,
+        rtx: ResolvedTransaction,
+        rs: Result<Cycle, TransactionError>,
+    ) {
+        let tx = rtx.transaction.clone();
+        if self.config.trace_enable() {
+            self.trace.add_commit(
+                &tx.hash(),
+                format!(
+                    "removed from orphan, prepare add to commit, verify result {:?}",
+                    rs
+                ),
+            );
+        }
+        if rs.is_ok() {
+            match self.recheck(&tx) {
+                Ok(ref unknowns) if unknowns.is_empty() => {}
+                Ok(unknowns) => {
+                    self.add_orphan(tx, unknowns);
+                    return;
+                }
+                Err(PoolError::DoubleSpent) => {
+                    self.notify.notify_pool_event(PoolEvent::TxRemoved {
+                        hash: tx.hash(),
+                        reason: RemovedReason::Conflict,
+                    });
+                    return;
+                }
+                // committed or added meanwhile
+                Err(_) => return,
             }
+            if let Err(error) = self
+                .check_package_limits(&tx)
+                .and_then(|_| self.check_fee_rate(&rtx))
+            {
+                if self.config.trace_enable() {
+                    self.trace
+                        .add_commit(&tx.hash(), format!("dropped, reason: {:?}", error));
+                }
+                self.notify.notify_pool_event(PoolEvent::TxRemoved {
+                    hash: tx.hash(),
+                    reason: RemovedReason::Rejected,
+                });
+                return;
+            }
+            let fee_rate = self.record_fee_rate(&rtx);
+            self.last_txs_updated_at
+                .store(unix_time_as_millis() as usize, Ordering::SeqCst);
+            self.notify.notify_pool_event(PoolEvent::TxAdded {
+                hash: tx.hash(),
+                size: tx.serialized_size(),
+            });
+            let id = tx.proposal_short_id();
+            self.pool.add_transaction(tx);
+            self.pool.set_fee_rate(&id, fee_rate);
+        } else if let Err(TransactionError::DoubleSpent(_)) = rs {
+            self.notify.notify_pool_event(PoolEvent::TxRemoved {
+                hash: tx.hash(),
+                reason: RemovedReason::Conflict,
+            });
+            self.cache.insert(tx.proposal_short_id(), tx);
+        } else {
+            self.notify.notify_pool_event(PoolEvent::TxRemoved {
+                hash: tx.hash(),
+                reason: RemovedReason::Invalid,
+            });
         }
     }
 
     /// Updates the pool with the details of a new block.
     // TODO: call it in order
     pub(crate) fn reconcile_block(&mut self, b: &Block) {
+        self.reconcile_block_then(b, |_| {});
+    }
+
+    /// Like `reconcile_block`, calling `then` once the transactions it proposed are added
+    fn reconcile_block_then<F>(&mut self, b: &Block, then: F)
+    where
+        F: FnOnce(&mut Self) + Send + 'static,
+    {
         let txs = b.commit_transactions();
         let bn = b.header().number();
         let ids = b.union_proposal_ids();
@@ -639,7 +1743,32 @@ where
                         ),
                     );
                 }
-                self.pool.commit_transaction(tx);
+                let id = tx.proposal_short_id();
+                self.local.remove(&id);
+                self.fee_estimator.commit(&id, bn);
+                if self.pool.contains_key(&id) {
+                    self.notify.notify_pool_event(PoolEvent::TxConfirmed {
+                        hash: tx.hash(),
+                        block_hash: b.header().hash(),
+                        block_number: bn,
+                    });
+                }
+                let inputs = tx.input_pts();
+                for rtx in self.pool.commit_transaction(tx) {
+                    self.fee_estimator.forget(&rtx.proposal_short_id());
+                    let event = if rtx.input_pts().iter().any(|i| inputs.contains(i)) {
+                        PoolEvent::TxReplaced {
+                            hash: rtx.hash(),
+                            by: tx.hash(),
+                        }
+                    } else {
+                        PoolEvent::TxRemoved {
+                            hash: rtx.hash(),
+                            reason: RemovedReason::Conflict,
+                        }
+                    };
+                    self.notify.notify_pool_event(event);
+                }
             }
         }
 
@@ -654,7 +1783,7 @@ where
                                     "tx proposal timeout, removed from pool, readd to pending",
                                 );
                             }
-                            self.pending.insert(tx.proposal_short_id(), tx);
+                            self.readd_to_pending(tx);
                         }
                     } else if let Some(tx) = self.orphan.remove(id) {
                         if self.config.trace_enable() {
@@ -663,7 +1792,7 @@ where
                                 "tx proposal timeout, removed from orphan, readd to pending",
                             );
                         }
-                        self.pending.insert(tx.proposal_short_id(), tx);
+                        self.readd_to_pending(tx);
                     }
                 }
             }
@@ -694,11 +1823,29 @@ where
         };
 
         // We can sort it by some rules
-        for tx in new_txs {
-            let tx_hash = tx.hash();
-            if let Err(error) = self.add_to_pool(tx) {
-                error!(target: "txs_pool", "Failed to add proposed tx {:} to pool, reason: {:?}", tx_hash, error);
+        let hashes: Vec<H256> = new_txs.iter().map(Transaction::hash).collect();
+        self.add_to_pool_batch_then(new_txs, move |pool, results| {
+            for (tx_hash, ret) in hashes.into_iter().zip(results) {
+                if let Err(error) = ret {
+                    error!(target: "txs_pool", "Failed to add proposed tx {:} to pool, reason: {:?}", tx_hash, error);
+                }
             }
+            then(pool);
+        });
+    }
+
+    // Puts back in pending a transaction whose proposal timed out, it stays in the pool
+    // unless pending is full, then it expires
+    fn readd_to_pending(&mut self, tx: Transaction) {
+        if self.pending.size() < self.config.max_pending_size {
+            self.pending.insert(tx.proposal_short_id(), tx);
+        } else {
+            if self.config.trace_enable() {
+                self.trace.timeout(&tx.hash(), "pending is full, removed from pool");
+            }
+            self.fee_estimator.forget(&tx.proposal_short_id());
+            self.notify
+                .notify_pool_event(PoolEvent::TxExpired { hash: tx.hash() });
         }
     }
 
@@ -711,15 +1858,180 @@ where
         self.pool.resolve_conflict(tx);
     }
 
-    /// Whether the pool is full
-    fn is_acceptable(&self) -> Result<(), PoolError> {
-        if self.total_size() > self.config.max_pool_size {
-            // TODO evict old/large transactions instead
+    /// Whether the pool is full, a local transaction may evict a relayed one
+    fn is_acceptable(&mut self, id: &ProposalShortId) -> Result<(), PoolError> {
+        if self.total_size() > self.config.max_pool_size
+            && !(self.local.contains_key(id) && self.evict())
+        {
             return Err(PoolError::OverCapacity);
         }
         Ok(())
     }
 
+    // Remove a relayed orphan, or the relayed transaction without local descendants paying
+    // the lowest fee rate, the newest of them at equal rates. The transactions readded from
+    // detached blocks are not verified again and count as paying none.
+    fn evict(&mut self) -> bool {
+        let removed = {
+            let local = &self.local;
+            let orphan = self
+                .orphan
+                .vertices
+                .keys()
+                .find(|id| !local.contains_key(id))
+                .cloned();
+            if let Some(id) = orphan {
+                self.orphan.remove(&id).map(|tx| vec![tx])
+            } else {
+                let pool = &self.pool;
+                let candidate = pool
+                    .vertices
+                    .iter()
+                    .rev()
+                    .filter(|(id, _)| {
+                        !local.contains_key(id)
+                            && pool
+                                .get_descendants(id)
+                                .iter()
+                                .all(|cid| !local.contains_key(cid))
+                    })
+                    .min_by_key(|(_, entry)| entry.fee_rate)
+                    .map(|(id, _)| *id);
+                candidate.and_then(|id| self.pool.remove(&id))
+            }
+        };
+
+        match removed {
+            Some(txs) => {
+                for tx in txs {
+                    self.notify.notify_pool_event(PoolEvent::TxRemoved {
+                        hash: tx.hash(),
+                        reason: RemovedReason::Evicted,
+                    });
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Check that the transaction does not build an oversized unconfirmed chain
+    // The ancestors and descendants are counted across the staged sets, the parents waiting
+    // for their proposal or for their own parents count as the mineable ones
+    fn check_package_limits(&self, tx: &Transaction) -> Result<(), PoolError> {
+        let mut ancestors = FnvHashSet::default();
+        let mut queue: VecDeque<OutPoint> =
+            tx.input_pts().into_iter().chain(tx.dep_pts()).collect();
+        while let Some(o) = queue.pop_front() {
+            let id = ProposalShortId::from_h256(&o.hash);
+            if let Some(parent) = self.staged_transaction(&id) {
+                if parent.hash() == o.hash && ancestors.insert(id) {
+                    queue.extend(parent.input_pts());
+                    queue.extend(parent.dep_pts());
+                }
+            }
+        }
+
+        if ancestors.len() > self.config.max_ancestors_count {
+            return Err(PoolError::ExceededMaximumAncestorsCount);
+        }
+        if ancestors.is_empty() {
+            return Ok(());
+        }
+
+        let mut children: FnvHashMap<ProposalShortId, Vec<ProposalShortId>> =
+            FnvHashMap::default();
+        let staged = self
+            .pool
+            .vertices
+            .values()
+            .map(|entry| &entry.transaction)
+            .chain(self.orphan.vertices.values().map(|entry| &entry.transaction))
+            .chain(self.pending.transactions())
+            .chain(self.proposed.transactions());
+        for child in staged {
+            let child_id = child.proposal_short_id();
+            for o in child.input_pts().into_iter().chain(child.dep_pts()) {
+                children
+                    .entry(ProposalShortId::from_h256(&o.hash))
+                    .or_insert_with(Vec::new)
+                    .push(child_id);
+            }
+        }
+        let id = tx.proposal_short_id();
+        for ancestor in &ancestors {
+            let mut descendants = FnvHashSet::default();
+            let mut queue: VecDeque<&ProposalShortId> = VecDeque::new();
+            queue.push_back(ancestor);
+            while let Some(parent) = queue.pop_front() {
+                for child in children.get(parent).into_iter().flatten() {
+                    if *child != id && descendants.insert(*child) {
+                        queue.push_back(child);
+                    }
+                }
+            }
+            if descendants.len() + 1 > self.config.max_descendants_count {
+                return Err(PoolError::ExceededMaximumDescendantsCount);
+            }
+        }
+
+        Ok(())
+    }
+
+    // The transaction of `id` in any of the staged sets
+    fn staged_transaction(&self, id: &ProposalShortId) -> Option<&Transaction> {
+        self.pool
+            .get(id)
+            .or_else(|| self.orphan.get(id))
+            .or_else(|| self.pending.get(id))
+            .or_else(|| self.proposed.get(id))
+    }
+
+    // Check that the transaction pays enough fee, or fits in the free allowance
+    fn check_fee_rate(&mut self, rtx: &ResolvedTransaction) -> Result<(), PoolError> {
+        if self.config.min_fee_rate == 0
+            || self
+                .local
+                .contains_key(&rtx.transaction.proposal_short_id())
+        {
+            return Ok(());
+        }
+
+        let size = rtx.transaction.serialized_size();
+        let min_fee = (size as u64).saturating_mul(self.config.min_fee_rate) / 1000;
+        if rtx.fee().unwrap_or(0) >= min_fee
+            || self
+                .free_tx_bucket
+                .try_consume(size, unix_time_as_millis())
+        {
+            Ok(())
+        } else {
+            Err(PoolError::LowFeeRate)
+        }
+    }
+
+    fn record_fee_rate(&mut self, rtx: &ResolvedTransaction) -> Capacity {
+        let size = rtx.transaction.serialized_size().max(1) as u64;
+        let fee_rate = rtx.fee().unwrap_or(0).saturating_mul(1000) / size;
+        let tip = self.shared.chain_state().read().tip_number();
+        self.fee_estimator
+            .set_fee_rate(rtx.transaction.proposal_short_id(), tip, fee_rate);
+        fee_rate
+    }
+
+    pub(crate) fn estimate_fee_rate(&self, blocks: BlockNumber) -> Capacity {
+        let tip = self.shared.chain_state().read().tip_number();
+        // Without enough samples any rate the pool accepts is expected to do
+        self.fee_estimator
+            .estimate(tip, blocks)
+            .unwrap_or(0)
+            .max(self.config.min_fee_rate)
+    }
+
+    pub(crate) fn set_min_fee_rate(&mut self, rate: Capacity) {
+        self.config.min_fee_rate = rate;
+    }
+
     // Check that the transaction is not in the pool or chain
     fn check_duplicate(&self, tx: &Transaction) -> Result<(), PoolError> {
         let h = tx.hash();
@@ -737,3 +2049,39 @@ where
         Ok(())
     }
 }
+
+// The indexes of `txs` with the parents in the batch before their children, in
+// the given order otherwise
+fn topological_order(txs: &[Transaction]) -> Vec<usize> {
+    let positions: FnvHashMap<H256, usize> = txs
+        .iter()
+        .enumerate()
+        .map(|(index, tx)| (tx.hash(), index))
+        .collect();
+    let mut visited = vec![false; txs.len()];
+    let mut order = Vec::with_capacity(txs.len());
+    for root in 0..txs.len() {
+        let mut stack = vec![(root, false)];
+        while let Some((index, expanded)) = stack.pop() {
+            if expanded {
+                order.push(index);
+                continue;
+            }
+            if visited[index] {
+                continue;
+            }
+            visited[index] = true;
+            stack.push((index, true));
+            let tx = &txs[index];
+            let parents = tx.input_pts().into_iter().chain(tx.dep_pts()).rev();
+            for o in parents {
+                if let Some(parent) = positions.get(&o.hash) {
+                    if !visited[*parent] {
+                        stack.push((*parent, false));
+                    }
+                }
+            }
+        }
+    }
+    order
+}
@@ -1,4 +1,4 @@
-use faketime::unix_time_as_millis;
+use ckb_time::unix_time_as_millis;
 use lru_cache::LruCache;
 use numext_fixed_hash::H256;
 use serde_derive::Serialize;
@@ -103,8 +103,7 @@ mod tests {
         let tx = TransactionBuilder::default().build();
         let tx_hash = tx.hash();
 
-        let faketime_file = faketime::millis_tempfile(9102).expect("create faketime file");
-        faketime::enable(&faketime_file);
+        let _clock = ckb_time::Clock::new(9102).enter();
 
         map.add_pending(&tx_hash, "pending");
         map.proposed(&tx_hash, "proposed");
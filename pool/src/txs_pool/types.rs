@@ -3,20 +3,29 @@
 
 use ckb_chain_spec::consensus::{TRANSACTION_PROPAGATION_TIME, TRANSACTION_PROPAGATION_TIMEOUT};
 use ckb_core::transaction::{CellOutput, OutPoint, ProposalShortId, Transaction};
-use ckb_core::BlockNumber;
+use ckb_core::{BlockNumber, Capacity};
 use ckb_verification::TransactionError;
 use fnv::{FnvHashMap, FnvHashSet};
 use linked_hash_map::LinkedHashMap;
-use occupied_capacity::OccupiedCapacity;
 use serde_derive::{Deserialize, Serialize};
-use std::collections::VecDeque;
+use std::collections::{BTreeMap, VecDeque};
 use std::hash::Hash;
 use std::iter::Iterator;
+use std::mem;
 
 const BUFF_QUE_LEN: u64 = 100;
-
-/// Transaction pool configuration
+// Transactions followed by the fee estimator until committed
+const MAX_TRACKED_TXS: usize = 10_000;
+// Committed transactions the fee estimates are based on
+const MAX_FEE_SAMPLES: usize = 10_000;
+const MIN_FEE_SAMPLES: usize = 10;
+// Share of the transactions paying the estimated fee rate or more which were
+// committed in time
+const FEE_SUCCESS_PERCENT: usize = 85;
+
+/// Transaction pool configuration, the missing keys take the default values
 #[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
 pub struct PoolConfig {
     /// Maximum capacity of the pool in number of transactions
     pub max_pool_size: usize,
@@ -24,6 +33,17 @@ pub struct PoolConfig {
     pub max_proposal_size: usize,
     pub max_cache_size: usize,
     pub max_pending_size: usize,
+    /// Maximum number of in-pool ancestors a transaction may have
+    pub max_ancestors_count: usize,
+    /// Maximum number of in-pool descendants any transaction may have
+    pub max_descendants_count: usize,
+    /// Minimum fee per 1000 bytes required for admission and relay
+    pub min_fee_rate: Capacity,
+    /// Bytes of transactions paying less than `min_fee_rate` accepted per minute
+    pub free_tx_allowance: usize,
+    /// Maximum number of locally submitted transactions which are rebroadcast and
+    /// exempted from eviction and fee filtering
+    pub max_local_size: usize,
     pub trace: Option<usize>,
 }
 
@@ -35,6 +55,11 @@ impl Default for PoolConfig {
             max_proposal_size: 10000,
             max_cache_size: 1000,
             max_pending_size: 10000,
+            max_ancestors_count: 25,
+            max_descendants_count: 25,
+            min_fee_rate: 0,
+            free_tx_allowance: 0,
+            max_local_size: 1000,
             trace: Some(100),
         }
     }
@@ -62,6 +87,33 @@ pub enum InsertionResult {
     Unknown,
 }
 
+/// The stage a transaction is currently at in the pool
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum TxStatus {
+    /// Not proposed yet
+    Pending,
+    /// Proposed, waiting for the proposal window
+    Proposed,
+    /// Verified and ready to be committed
+    Mineable,
+    /// Missing inputs or deps
+    Orphan,
+    /// Conflicts with other transactions
+    Conflict,
+}
+
+/// Number of transactions at each stage of the pool
+#[derive(PartialEq, Eq, Clone, Copy, Default, Debug)]
+pub struct TxPoolInfo {
+    pub pending: usize,
+    pub proposed: usize,
+    pub mineable: usize,
+    pub orphan: usize,
+    pub conflict: usize,
+    /// Time in milliseconds the mineable transactions last changed
+    pub last_txs_updated_at: u64,
+}
+
 #[derive(PartialEq, Clone, Debug)]
 pub enum TxStage {
     Unknown(Transaction),
@@ -91,6 +143,24 @@ pub enum PoolError {
     TimeOut,
     /// BlockNumber is not right
     InvalidBlockNumber,
+    /// Too many in-pool ancestors
+    ExceededMaximumAncestorsCount,
+    /// Too many in-pool descendants
+    ExceededMaximumDescendantsCount,
+    /// Fee rate is below `min_fee_rate` and free allowance is used up
+    LowFeeRate,
+    /// Spends a transaction rejected earlier in the same batch
+    ParentRejected,
+    /// No transaction with the id is in the pool
+    NotInPool,
+    /// The chain has not proposed the transaction within the proposal window
+    NotProposed,
+    /// The transaction cannot move to the stage from its own
+    InvalidStage,
+    /// Committed by a block while it was verified
+    Committed,
+    /// The pool was cleared while it was verified
+    Cleared,
 }
 
 /// An entry in the transaction pool.
@@ -102,16 +172,256 @@ pub struct PoolEntry {
     pub refs_count: usize,
     /// Bytes size
     pub bytes_size: usize,
+    /// Fee per 1000 bytes, 0 until the transaction is verified
+    pub fee_rate: Capacity,
 }
 
 impl PoolEntry {
     /// Create new transaction pool entry
     pub fn new(tx: Transaction, count: usize) -> PoolEntry {
         PoolEntry {
-            bytes_size: tx.occupied_capacity(),
+            bytes_size: tx.serialized_size(),
             transaction: tx,
             refs_count: count,
+            fee_rate: 0,
+        }
+    }
+}
+
+/// Budget for transactions below the minimum fee rate, it drains linearly so
+/// at most `limit` bytes get in per minute.
+#[derive(Default, Debug)]
+pub struct FreeTxBucket {
+    limit: usize,
+    used: usize,
+    updated_at: u64,
+}
+
+impl FreeTxBucket {
+    pub fn new(limit: usize) -> Self {
+        FreeTxBucket {
+            limit,
+            used: 0,
+            updated_at: 0,
+        }
+    }
+
+    pub fn try_consume(&mut self, size: usize, now: u64) -> bool {
+        let elapsed = now.saturating_sub(self.updated_at) as usize;
+        let drained = elapsed.saturating_mul(self.limit) / 60_000;
+        self.used = self.used.saturating_sub(drained);
+        self.updated_at = now;
+
+        if self.used + size > self.limit {
+            false
+        } else {
+            self.used += size;
+            true
+        }
+    }
+}
+
+#[derive(Debug)]
+struct TrackedTx {
+    seen_at: BlockNumber,
+    fee_rate: Option<Capacity>,
+}
+
+/// The samples of the fee rates sharing their two most significant digits
+#[derive(Default, Debug)]
+struct FeeBucket {
+    /// Committed transactions by the number of blocks they waited
+    committed: BTreeMap<BlockNumber, usize>,
+    /// Tracked transactions by the block they were first seen at
+    waiting: BTreeMap<BlockNumber, usize>,
+}
+
+impl FeeBucket {
+    fn is_empty(&self) -> bool {
+        self.committed.is_empty() && self.waiting.is_empty()
+    }
+
+    // The samples and the ones in time, counting the transactions waiting longer than
+    // `blocks` at `tip` as late
+    fn outcomes(&self, tip: BlockNumber, blocks: BlockNumber) -> (usize, usize) {
+        let committed: usize = self.committed.values().sum();
+        let in_time: usize = self.committed.range(..=blocks).map(|(_, n)| n).sum();
+        let waiting: usize = if tip > blocks {
+            self.waiting.range(..tip - blocks).map(|(_, n)| n).sum()
+        } else {
+            0
+        };
+        (committed + waiting, in_time)
+    }
+}
+
+fn fee_bucket(fee_rate: Capacity) -> Capacity {
+    let mut scale = 1;
+    while fee_rate / scale >= 100 {
+        scale *= 10;
+    }
+    fee_rate / scale * scale
+}
+
+// Applies `f` to the bucket of `fee_rate`, which is dropped once empty
+fn update_bucket<F>(buckets: &mut BTreeMap<Capacity, FeeBucket>, fee_rate: Capacity, f: F)
+where
+    F: FnOnce(&mut FeeBucket),
+{
+    let key = fee_bucket(fee_rate);
+    let empty = match buckets.get_mut(&key) {
+        Some(bucket) => {
+            f(bucket);
+            bucket.is_empty()
         }
+        None => false,
+    };
+    if empty {
+        buckets.remove(&key);
+    }
+}
+
+fn stop_waiting(buckets: &mut BTreeMap<Capacity, FeeBucket>, tx: &TrackedTx) {
+    if let Some(fee_rate) = tx.fee_rate {
+        let seen_at = tx.seen_at;
+        update_bucket(buckets, fee_rate, |bucket| count_out(&mut bucket.waiting, seen_at));
+    }
+}
+
+fn count_in(counts: &mut BTreeMap<BlockNumber, usize>, key: BlockNumber) {
+    *counts.entry(key).or_insert(0) += 1;
+}
+
+fn count_out(counts: &mut BTreeMap<BlockNumber, usize>, key: BlockNumber) {
+    if let Some(n) = counts.get_mut(&key) {
+        *n -= 1;
+        if *n == 0 {
+            counts.remove(&key);
+        }
+    }
+}
+
+/// Follows the transactions from the block they are first seen at until they
+/// are committed, to estimate the fee rate, per 1000 bytes, needed to be
+/// committed within a number of blocks. The samples are kept in buckets of
+/// fee rates as they come, so an estimate goes through the buckets only.
+#[derive(Default, Debug)]
+pub struct FeeEstimator {
+    tracked: LinkedHashMap<ProposalShortId, TrackedTx>,
+    /// Fee rate and blocks waited of the recently committed transactions
+    samples: VecDeque<(Capacity, BlockNumber)>,
+    buckets: BTreeMap<Capacity, FeeBucket>,
+}
+
+impl FeeEstimator {
+    pub fn new() -> Self {
+        FeeEstimator::default()
+    }
+
+    pub fn track(&mut self, id: ProposalShortId, number: BlockNumber) {
+        if self.tracked.contains_key(&id) {
+            return;
+        }
+        if self.tracked.len() >= MAX_TRACKED_TXS {
+            if let Some((_, tx)) = self.tracked.pop_front() {
+                stop_waiting(&mut self.buckets, &tx);
+            }
+        }
+        self.tracked.insert(
+            id,
+            TrackedTx {
+                seen_at: number,
+                fee_rate: None,
+            },
+        );
+    }
+
+    /// Records the fee rate of a verified transaction, it is tracked from
+    /// `number` if not seen before
+    pub fn set_fee_rate(&mut self, id: ProposalShortId, number: BlockNumber, fee_rate: Capacity) {
+        self.track(id, number);
+        let buckets = &mut self.buckets;
+        if let Some(tx) = self.tracked.get_mut(&id) {
+            stop_waiting(buckets, tx);
+            let bucket = buckets.entry(fee_bucket(fee_rate)).or_default();
+            count_in(&mut bucket.waiting, tx.seen_at);
+            tx.fee_rate = Some(fee_rate);
+        }
+    }
+
+    pub fn commit(&mut self, id: &ProposalShortId, number: BlockNumber) {
+        if let Some(tx) = self.tracked.remove(id) {
+            stop_waiting(&mut self.buckets, &tx);
+            if let Some(fee_rate) = tx.fee_rate {
+                if self.samples.len() >= MAX_FEE_SAMPLES {
+                    if let Some((fee_rate, waited)) = self.samples.pop_front() {
+                        update_bucket(&mut self.buckets, fee_rate, |bucket| {
+                            count_out(&mut bucket.committed, waited)
+                        });
+                    }
+                }
+                let waited = number.saturating_sub(tx.seen_at);
+                self.samples.push_back((fee_rate, waited));
+                let bucket = self.buckets.entry(fee_bucket(fee_rate)).or_default();
+                count_in(&mut bucket.committed, waited);
+            }
+        }
+    }
+
+    pub fn forget(&mut self, id: &ProposalShortId) {
+        if let Some(tx) = self.tracked.remove(id) {
+            stop_waiting(&mut self.buckets, &tx);
+        }
+    }
+
+    pub fn forget_all(&mut self) {
+        self.tracked.clear();
+        let buckets = mem::replace(&mut self.buckets, BTreeMap::new());
+        self.buckets = buckets
+            .into_iter()
+            .filter_map(|(fee_rate, mut bucket)| {
+                bucket.waiting.clear();
+                if bucket.is_empty() {
+                    None
+                } else {
+                    Some((fee_rate, bucket))
+                }
+            })
+            .collect();
+    }
+
+    /// Lowest fee rate at which most of the transactions paying as much were
+    /// committed within `blocks` blocks, the ones waiting longer at `tip`
+    /// count as late. None without enough samples.
+    pub fn estimate(&self, tip: BlockNumber, blocks: BlockNumber) -> Option<Capacity> {
+        let outcomes: Vec<_> = self
+            .buckets
+            .iter()
+            .rev()
+            .map(|(fee_rate, bucket)| (*fee_rate, bucket.outcomes(tip, blocks)))
+            .filter(|(_, (total, _))| *total > 0)
+            .collect();
+        if outcomes.iter().map(|(_, (total, _))| total).sum::<usize>() < MIN_FEE_SAMPLES {
+            return None;
+        }
+
+        // Goes down the fee rates by ranges of enough samples, until one of
+        // them is mostly late
+        let mut estimate = None;
+        let (mut total, mut in_time) = (0, 0);
+        for (fee_rate, (bucket_total, bucket_in_time)) in outcomes {
+            total += bucket_total;
+            in_time += bucket_in_time;
+            if total >= MIN_FEE_SAMPLES {
+                if in_time * 100 < total * FEE_SUCCESS_PERCENT {
+                    break;
+                }
+                estimate = Some(fee_rate);
+                total = 0;
+                in_time = 0;
+            }
+        }
+        estimate
     }
 }
 
@@ -254,6 +564,56 @@ impl Pool {
             .and_then(|x| x.transaction.get_output(o.index as usize))
     }
 
+    /// Get the in-pool ancestors of a transaction, both direct and indirect.
+    /// The transaction itself does not need to be in the pool.
+    pub fn get_ancestors(&self, tx: &Transaction) -> FnvHashSet<ProposalShortId> {
+        let mut ancestors = FnvHashSet::default();
+        let mut queue: VecDeque<OutPoint> =
+            tx.input_pts().into_iter().chain(tx.dep_pts()).collect();
+
+        while let Some(o) = queue.pop_front() {
+            let id = ProposalShortId::from_h256(&o.hash);
+            if let Some(x) = self.vertices.get(&id) {
+                if ancestors.insert(id) {
+                    queue.extend(x.transaction.input_pts());
+                    queue.extend(x.transaction.dep_pts());
+                }
+            }
+        }
+
+        ancestors
+    }
+
+    /// Get the in-pool descendants of a transaction, both direct and indirect.
+    pub fn get_descendants(&self, id: &ProposalShortId) -> FnvHashSet<ProposalShortId> {
+        let mut descendants = FnvHashSet::default();
+        let mut queue = VecDeque::new();
+
+        if let Some(x) = self.vertices.get(id) {
+            queue.extend(x.transaction.output_pts());
+        }
+
+        while let Some(o) = queue.pop_front() {
+            let mut children = Vec::new();
+            if let Some(Some(cid)) = self.edges.get_inner(&o) {
+                children.push(*cid);
+            }
+            if let Some(ids) = self.edges.get_deps(&o) {
+                children.extend(ids.iter().cloned());
+            }
+
+            for cid in children {
+                if let Some(x) = self.vertices.get(&cid) {
+                    if descendants.insert(cid) {
+                        queue.extend(x.transaction.output_pts());
+                    }
+                }
+            }
+        }
+
+        descendants
+    }
+
     pub fn remove_vertex(&mut self, id: &ProposalShortId, rtxs: &mut Vec<Transaction>) {
         if let Some(x) = self.vertices.remove(id) {
             let tx = x.transaction;
@@ -338,6 +698,12 @@ impl Pool {
         self.vertices.insert(id, PoolEntry::new(tx, count));
     }
 
+    pub fn set_fee_rate(&mut self, id: &ProposalShortId, fee_rate: Capacity) {
+        if let Some(entry) = self.vertices.get_mut(id) {
+            entry.fee_rate = fee_rate;
+        }
+    }
+
     /// Readd a verified transaction which is rolled back from chain. Since the rolled back
     /// transaction should depend on any transaction in the pool, it is safe to skip some checking.
     pub fn readd_transaction(&mut self, tx: &Transaction) {
@@ -373,8 +739,8 @@ impl Pool {
         }
     }
 
-    ///Commit proposed transaction
-    pub fn commit_transaction(&mut self, tx: &Transaction) {
+    /// Commit proposed transaction, returns the transactions removed for conflicting with it
+    pub fn commit_transaction(&mut self, tx: &Transaction) -> Vec<Transaction> {
         let outputs = tx.output_pts();
         let inputs = tx.input_pts();
         let deps = tx.dep_pts();
@@ -401,25 +767,30 @@ impl Pool {
             for d in deps {
                 self.edges.delete_value_in_deps(&d, &id)
             }
+
+            Vec::new()
         } else {
-            self.resolve_conflict(tx);
+            self.resolve_conflict(tx)
         }
     }
 
-    pub fn resolve_conflict(&mut self, tx: &Transaction) {
+    pub fn resolve_conflict(&mut self, tx: &Transaction) -> Vec<Transaction> {
         let inputs = tx.input_pts();
+        let mut rtxs = Vec::new();
 
         for i in inputs {
             if let Some(id) = self.edges.remove_outer(&i) {
-                self.remove(&id);
+                self.remove_vertex(&id, &mut rtxs);
             }
 
             if let Some(x) = self.edges.remove_deps(&i) {
                 for id in x {
-                    self.remove(&id);
+                    self.remove_vertex(&id, &mut rtxs);
                 }
             }
         }
+
+        rtxs
     }
 
     /// Get n transactions in topology
@@ -580,6 +951,10 @@ impl PendingQueue {
         self.inner.remove(id)
     }
 
+    pub fn transactions(&self) -> impl Iterator<Item = &Transaction> {
+        self.inner.values()
+    }
+
     pub fn fetch(&self, n: usize) -> Vec<ProposalShortId> {
         self.inner
             .values()
@@ -652,6 +1027,10 @@ impl ProposedQueue {
         self.buff.contains_key(id)
     }
 
+    pub fn transactions(&self) -> impl Iterator<Item = &Transaction> {
+        self.buff.values()
+    }
+
     pub fn get_ids(&self, bn: BlockNumber) -> Option<&FnvHashSet<ProposalShortId>> {
         if self.tip < bn {
             return None;
@@ -714,6 +1093,11 @@ impl ProposedQueue {
         self.buff.insert(id, tx);
     }
 
+    /// Takes the transaction out, its proposal stays on record
+    pub fn remove_transaction(&mut self, id: &ProposalShortId) -> Option<Transaction> {
+        self.buff.remove(id)
+    }
+
     pub fn push_back(&mut self, ids: Vec<ProposalShortId>) {
         let id_set: FnvHashSet<ProposalShortId> = ids
             .into_iter()
@@ -971,6 +1355,91 @@ mod tests {
         assert_eq!(pool.edges.outer_len(), 2);
     }
 
+    #[test]
+    fn test_ancestors_and_descendants() {
+        let tx1 = build_tx(vec![(H256::zero(), 1)], 2);
+        let tx1_hash = tx1.hash().clone();
+        let tx2 = build_tx(vec![(tx1_hash.clone(), 0)], 1);
+        let tx2_hash = tx2.hash().clone();
+        let tx3 = build_tx(vec![(tx2_hash.clone(), 0), (tx1_hash.clone(), 1)], 1);
+        let tx4 = build_tx(vec![(H256::zero(), 2)], 1);
+
+        let id1 = tx1.proposal_short_id();
+        let id2 = tx2.proposal_short_id();
+        let id3 = tx3.proposal_short_id();
+
+        let mut pool = Pool::new();
+        pool.add_transaction(tx1.clone());
+        pool.add_transaction(tx2.clone());
+
+        let ancestors = pool.get_ancestors(&tx3);
+        assert_eq!(2, ancestors.len());
+        assert!(ancestors.contains(&id1) && ancestors.contains(&id2));
+        assert!(pool.get_ancestors(&tx4).is_empty());
+
+        pool.add_transaction(tx3.clone());
+
+        let descendants = pool.get_descendants(&id1);
+        assert_eq!(2, descendants.len());
+        assert!(descendants.contains(&id2) && descendants.contains(&id3));
+        assert_eq!(1, pool.get_descendants(&id2).len());
+        assert!(pool.get_descendants(&id3).is_empty());
+
+        pool.commit_transaction(&tx1);
+        assert_eq!(1, pool.get_ancestors(&tx3).len());
+    }
+
+    #[test]
+    fn test_free_tx_bucket() {
+        let mut bucket = FreeTxBucket::new(1000);
+
+        assert!(bucket.try_consume(600, 0));
+        assert!(!bucket.try_consume(600, 0));
+        assert!(bucket.try_consume(400, 0));
+        assert!(!bucket.try_consume(1, 1));
+        // half a minute later half of the allowance is available again
+        assert!(bucket.try_consume(500, 30_000));
+        assert!(!bucket.try_consume(1, 30_000));
+
+        let mut disabled = FreeTxBucket::new(0);
+        assert!(!disabled.try_consume(1, 0));
+    }
+
+    #[test]
+    fn test_fee_estimator() {
+        let mut estimator = FeeEstimator::new();
+        let id = |i| build_tx(vec![(H256::zero(), i)], 1).proposal_short_id();
+        assert_eq!(estimator.estimate(100, 2), None);
+
+        // Paying 1000 or more gets committed in the next block, less takes 5
+        for i in 0..40 {
+            let fee_rate = 500 + u64::from(i % 4) * 250;
+            estimator.track(id(i), 10);
+            estimator.set_fee_rate(id(i), 10, fee_rate);
+            estimator.commit(&id(i), if fee_rate >= 1000 { 11 } else { 15 });
+        }
+        assert_eq!(estimator.estimate(20, 1), Some(1000));
+        assert_eq!(estimator.estimate(20, 5), Some(500));
+
+        // Waiting transactions paying 1250 are late for 1 block
+        for i in 40..60 {
+            estimator.set_fee_rate(id(i), 18, 1250);
+        }
+        assert_eq!(estimator.estimate(20, 1), None);
+        assert_eq!(estimator.estimate(20, 2), Some(1000));
+        estimator.forget_all();
+        assert_eq!(estimator.estimate(20, 1), Some(1000));
+        assert_eq!(estimator.buckets.len(), 4);
+    }
+
+    #[test]
+    fn test_fee_bucket() {
+        assert_eq!(fee_bucket(0), 0);
+        assert_eq!(fee_bucket(99), 99);
+        assert_eq!(fee_bucket(1250), 1200);
+        assert_eq!(fee_bucket(u64::max_value()), 18_000_000_000_000_000_000);
+    }
+
     #[test]
     fn test_pending_queue() {
         let mut pending = PendingQueue::new();
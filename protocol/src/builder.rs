@@ -244,6 +244,7 @@ impl<'a> FbsHeaders<'a> {
     pub fn build<'b>(
         fbb: &mut FlatBufferBuilder<'b>,
         headers: &[Header],
+        pruned_below: BlockNumber,
     ) -> WIPOffset<FbsHeaders<'b>> {
         let vec = headers
             .iter()
@@ -252,6 +253,7 @@ impl<'a> FbsHeaders<'a> {
         let headers = fbb.create_vector(&vec);
         let mut builder = HeadersBuilder::new(fbb);
         builder.add_headers(headers);
+        builder.add_pruned_below(pruned_below);
         builder.finish()
     }
 }
@@ -315,8 +317,9 @@ impl<'a> SyncMessage<'a> {
     pub fn build_headers<'b>(
         fbb: &mut FlatBufferBuilder<'b>,
         headers: &[Header],
+        pruned_below: BlockNumber,
     ) -> WIPOffset<SyncMessage<'b>> {
-        let fbs_headers = FbsHeaders::build(fbb, headers);
+        let fbs_headers = FbsHeaders::build(fbb, headers, pruned_below);
         let mut builder = SyncMessageBuilder::new(fbb);
         builder.add_payload_type(SyncPayload::Headers);
         builder.add_payload(fbs_headers.as_union_value());
@@ -602,10 +605,12 @@ impl<'a> TimeMessage<'a> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use ckb_core::arbitrary;
     use ckb_core::block::BlockBuilder;
     use ckb_core::header::HeaderBuilder;
     use ckb_core::transaction::TransactionBuilder;
     use flatbuffers::get_root;
+    use proptest::{proptest, proptest_helper};
 
     #[test]
     fn build_and_convert_header() {
@@ -647,4 +652,53 @@ mod tests {
         let fbs_compact_block = get_root::<CompactBlock>(builder.finished_data());
         assert_eq!(1, fbs_compact_block.prefilled_transactions().unwrap().len());
     }
+
+    // Decoding what was encoded gives back every field, the headers and
+    // blocks compare by hash
+    proptest! {
+        #[test]
+        fn header_round_trip(header in arbitrary::header()) {
+            let builder = &mut FlatBufferBuilder::new();
+            let b = FbsHeader::build(builder, &header);
+            builder.finish(b, None);
+
+            let decoded: Header = get_root::<FbsHeader>(builder.finished_data()).into();
+            assert_eq!(decoded.hash(), header.hash());
+        }
+
+        #[test]
+        fn transaction_round_trip(transaction in arbitrary::transaction()) {
+            let builder = &mut FlatBufferBuilder::new();
+            let b = FbsTransaction::build(builder, &transaction);
+            builder.finish(b, None);
+
+            let decoded: Transaction =
+                get_root::<FbsTransaction>(builder.finished_data()).into();
+            assert_eq!(decoded, transaction);
+            assert_eq!(decoded.hash(), transaction.hash());
+        }
+
+        #[test]
+        fn out_point_round_trip(out_point in arbitrary::out_point()) {
+            let builder = &mut FlatBufferBuilder::new();
+            let b = FbsOutPoint::build(builder, &out_point);
+            builder.finish(b, None);
+
+            let decoded: OutPoint = get_root::<FbsOutPoint>(builder.finished_data()).into();
+            assert_eq!(decoded, out_point);
+        }
+
+        #[test]
+        fn block_round_trip(block in arbitrary::block()) {
+            let builder = &mut FlatBufferBuilder::new();
+            let b = FbsBlock::build(builder, &block);
+            builder.finish(b, None);
+
+            let decoded: Block = get_root::<FbsBlock>(builder.finished_data()).into();
+            assert_eq!(decoded.header().hash(), block.header().hash());
+            assert_eq!(decoded.uncles(), block.uncles());
+            assert_eq!(decoded.commit_transactions(), block.commit_transactions());
+            assert_eq!(decoded.proposal_transactions(), block.proposal_transactions());
+        }
+    }
 }
@@ -943,26 +943,34 @@ impl<'a> Headers<'a> {
         _fbb: &'mut_bldr mut flatbuffers::FlatBufferBuilder<'bldr>,
         args: &'args HeadersArgs<'args>) -> flatbuffers::WIPOffset<Headers<'bldr>> {
       let mut builder = HeadersBuilder::new(_fbb);
+      builder.add_pruned_below(args.pruned_below);
       if let Some(x) = args.headers { builder.add_headers(x); }
       builder.finish()
     }
 
     pub const VT_HEADERS: flatbuffers::VOffsetT = 4;
+    pub const VT_PRUNED_BELOW: flatbuffers::VOffsetT = 6;
 
   #[inline]
   pub fn headers(&self) -> Option<flatbuffers::Vector<flatbuffers::ForwardsUOffset<Header<'a>>>> {
     self._tab.get::<flatbuffers::ForwardsUOffset<flatbuffers::Vector<flatbuffers::ForwardsUOffset<Header<'a>>>>>(Headers::VT_HEADERS, None)
   }
+  #[inline]
+  pub fn pruned_below(&self) -> u64 {
+    self._tab.get::<u64>(Headers::VT_PRUNED_BELOW, Some(0)).unwrap()
+  }
 }
 
 pub struct HeadersArgs<'a> {
     pub headers: Option<flatbuffers::WIPOffset<flatbuffers::Vector<'a , flatbuffers::ForwardsUOffset<Header<'a >>>>>,
+    pub pruned_below: u64,
 }
 impl<'a> Default for HeadersArgs<'a> {
     #[inline]
     fn default() -> Self {
         HeadersArgs {
             headers: None,
+            pruned_below: 0,
         }
     }
 }
@@ -976,6 +984,10 @@ impl<'a: 'b, 'b> HeadersBuilder<'a, 'b> {
     self.fbb_.push_slot_always::<flatbuffers::WIPOffset<_>>(Headers::VT_HEADERS, headers);
   }
   #[inline]
+  pub fn add_pruned_below(&mut self, pruned_below: u64) {
+    self.fbb_.push_slot::<u64>(Headers::VT_PRUNED_BELOW, pruned_below, 0);
+  }
+  #[inline]
   pub fn new(_fbb: &'b mut flatbuffers::FlatBufferBuilder<'a>) -> HeadersBuilder<'a, 'b> {
     let start = _fbb.start_table();
     HeadersBuilder {
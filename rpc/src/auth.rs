@@ -0,0 +1,228 @@
+//! Authentication of the calls to the modules listed in
+//! [AuthConfig](crate::config::AuthConfig).
+//!
+//! The credentials are read from the `Authorization` header of the HTTP
+//! request, a request calling a protected method without them is answered
+//! with an error for each of its calls.
+
+use crate::config::AuthConfig;
+use crate::limit::InFlight;
+use futures::future::{self, Either, FutureResult};
+use futures::Future;
+use jsonrpc_core::{Call, Error, ErrorCode, Metadata, Middleware, Output, Request, Response};
+use jsonrpc_http_server::hyper::header::AUTHORIZATION;
+use jsonrpc_http_server::hyper::{Body, Request as HttpRequest};
+use std::collections::HashSet;
+use std::sync::Arc;
+
+const UNAUTHORIZED: i64 = -32020;
+
+#[derive(Clone, Debug, Default)]
+pub(crate) struct RequestMeta {
+    authorized: bool,
+    // The request is in flight until its calls complete and drop the metadata
+    in_flight: Option<Arc<InFlight>>,
+}
+
+impl RequestMeta {
+    pub fn new(authorized: bool, in_flight: InFlight) -> RequestMeta {
+        RequestMeta {
+            authorized,
+            in_flight: Some(Arc::new(in_flight)),
+        }
+    }
+}
+
+impl Metadata for RequestMeta {}
+
+/// `Authorization` header values granting access
+pub(crate) struct Credentials {
+    accepted: Vec<String>,
+}
+
+impl Credentials {
+    pub fn new(config: &AuthConfig) -> Credentials {
+        let mut accepted = Vec::new();
+        if let Some(ref token) = config.bearer_token {
+            accepted.push(format!("Bearer {}", token));
+        }
+        if let Some(ref basic_auth) = config.basic_auth {
+            accepted.push(format!("Basic {}", base64::encode(basic_auth)));
+        }
+        Credentials { accepted }
+    }
+
+    pub fn is_authorized(&self, request: &HttpRequest<Body>) -> bool {
+        request
+            .headers()
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .map_or(false, |value| self.accepts(value))
+    }
+
+    /// Whether the `Authorization` header `value` grants access
+    pub fn accepts(&self, value: &str) -> bool {
+        // Every accepted value is compared, so that the time taken does not
+        // tell which one nor how much of it matched
+        self.accepted.iter().fold(false, |authorized, accepted| {
+            constant_time_eq(accepted.as_bytes(), value.as_bytes()) | authorized
+        })
+    }
+}
+
+// Compares the bytes of equal length all, only the length may be told apart
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+pub(crate) struct AuthMiddleware {
+    protected: HashSet<String>,
+}
+
+impl AuthMiddleware {
+    /// Requires the credentials to call the `protected` methods
+    pub fn new(protected: HashSet<String>) -> AuthMiddleware {
+        AuthMiddleware { protected }
+    }
+
+    fn is_protected(&self, call: &Call) -> bool {
+        match call {
+            Call::MethodCall(call) => self.protected.contains(&call.method),
+            Call::Notification(notification) => self.protected.contains(&notification.method),
+            _ => false,
+        }
+    }
+}
+
+impl Middleware<RequestMeta> for AuthMiddleware {
+    type Future = FutureResult<Option<Response>, ()>;
+
+    fn on_request<F, X>(
+        &self,
+        request: Request,
+        meta: RequestMeta,
+        next: F,
+    ) -> Either<Self::Future, X>
+    where
+        F: FnOnce(Request, RequestMeta) -> X + Send,
+        X: Future<Item = Option<Response>, Error = ()> + Send + 'static,
+    {
+        let denied = !meta.authorized
+            && match request {
+                Request::Single(ref call) => self.is_protected(call),
+                Request::Batch(ref calls) => calls.iter().any(|call| self.is_protected(call)),
+            };
+        if !denied {
+            return Either::B(next(request, meta));
+        }
+        let response = match request {
+            Request::Single(call) => reject(&call).map(Response::Single),
+            Request::Batch(calls) => {
+                Some(Response::Batch(calls.iter().filter_map(reject).collect()))
+            }
+        };
+        Either::A(future::ok(response))
+    }
+}
+
+fn reject(call: &Call) -> Option<Output> {
+    match call {
+        Call::MethodCall(call) => {
+            let error = Error {
+                code: ErrorCode::ServerError(UNAUTHORIZED),
+                message: "unauthorized".to_owned(),
+                data: None,
+            };
+            Some(Output::from(Err(error), call.id.clone(), call.jsonrpc))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jsonrpc_core::{MetaIoHandler, Params, Value};
+
+    fn credentials() -> Credentials {
+        Credentials::new(&AuthConfig {
+            modules: Vec::new(),
+            bearer_token: Some("secret".to_owned()),
+            basic_auth: Some("user:password".to_owned()),
+        })
+    }
+
+    fn request(authorization: Option<&str>) -> HttpRequest<Body> {
+        let mut builder = HttpRequest::builder();
+        if let Some(authorization) = authorization {
+            builder.header(AUTHORIZATION, authorization);
+        }
+        builder.body(Body::empty()).expect("valid request")
+    }
+
+    fn handler() -> MetaIoHandler<RequestMeta, AuthMiddleware> {
+        let protected = vec!["protected".to_owned()].into_iter().collect();
+        let mut io = MetaIoHandler::with_middleware(AuthMiddleware::new(protected));
+        io.add_method("protected", |_: Params| Ok::<_, Error>(Value::Bool(true)));
+        io.add_method("public", |_: Params| Ok::<_, Error>(Value::Bool(true)));
+        io
+    }
+
+    fn call(method: &str, authorized: bool) -> String {
+        let meta = RequestMeta {
+            authorized,
+            in_flight: None,
+        };
+        let request = format!(
+            r#"{{"jsonrpc": "2.0", "method": "{}", "params": [], "id": 1}}"#,
+            method
+        );
+        handler()
+            .handle_request_sync(&request, meta)
+            .expect("a response")
+    }
+
+    #[test]
+    fn test_credentials() {
+        let credentials = credentials();
+        assert!(credentials.is_authorized(&request(Some("Bearer secret"))));
+        let basic = format!("Basic {}", base64::encode("user:password"));
+        assert!(credentials.is_authorized(&request(Some(&basic))));
+
+        assert!(!credentials.is_authorized(&request(None)));
+        assert!(!credentials.is_authorized(&request(Some("Bearer wrong"))));
+        assert!(!credentials.is_authorized(&request(Some("Bearer secret2"))));
+        assert!(!credentials.is_authorized(&request(Some("Bearer"))));
+        assert!(!credentials.is_authorized(&request(Some("Basic secret"))));
+    }
+
+    #[test]
+    fn test_protected_methods() {
+        let allowed = r#"{"jsonrpc":"2.0","result":true,"id":1}"#;
+        assert_eq!(call("protected", true), allowed);
+        assert_eq!(call("public", true), allowed);
+        assert_eq!(call("public", false), allowed);
+        assert_eq!(
+            call("protected", false),
+            r#"{"jsonrpc":"2.0","error":{"code":-32020,"message":"unauthorized"},"id":1}"#
+        );
+    }
+
+    #[test]
+    fn test_batch_with_protected_method() {
+        let meta = RequestMeta::default();
+        let request = r#"[
+            {"jsonrpc": "2.0", "method": "public", "params": [], "id": 1},
+            {"jsonrpc": "2.0", "method": "protected", "params": [], "id": 2}
+        ]"#;
+        let response = handler()
+            .handle_request_sync(request, meta)
+            .expect("a response");
+        assert_eq!(
+            response,
+            r#"[{"jsonrpc":"2.0","error":{"code":-32020,"message":"unauthorized"},"id":1},"#
+                .to_owned()
+                + r#"{"jsonrpc":"2.0","error":{"code":-32020,"message":"unauthorized"},"id":2}]"#
+        );
+    }
+}
@@ -1,4 +1,7 @@
+use ckb_core::Cycle;
 use serde_derive::Deserialize;
+use std::net::IpAddr;
+use std::path::PathBuf;
 
 #[derive(Clone, Debug, Copy, Eq, PartialEq, Deserialize)]
 pub enum Module {
@@ -7,14 +10,63 @@ pub enum Module {
     Miner,
     Pool,
     Trace,
+    Admin,
+    Indexer,
+    Dev,
+    Debug,
 }
 
 #[derive(Clone, Debug, PartialEq, Deserialize)]
 pub struct Config {
     pub listen_address: String,
+    /// Subscriptions are served over WebSocket on this address, when set
+    pub ws_listen_address: Option<String>,
     pub threads: Option<usize>,
     pub modules: Vec<Module>,
     pub max_request_body_size: usize,
+    /// Origins browsers may call from, `*` for any and `null` for local
+    /// files. Any origin is allowed by default.
+    pub cors_allowed_origins: Option<Vec<String>>,
+    /// Accepted `Host` headers, such as the name of a proxy in front of the
+    /// node. Any host is accepted by default.
+    pub allowed_hosts: Option<Vec<String>>,
+    pub auth: Option<AuthConfig>,
+    /// Requests served at once, the others are answered 429 until some
+    /// complete
+    pub max_in_flight_requests: Option<usize>,
+    pub rate_limit: Option<RateLimitConfig>,
+    /// Reverse proxies whose `X-Forwarded-For` header tells the clients apart
+    /// for the rate limit, which is refused without them
+    pub trusted_proxies: Option<Vec<IpAddr>>,
+    /// Connected peers the node needs to answer `/ready` with 200, 1 by
+    /// default
+    pub ready_min_peers: Option<usize>,
+    /// Cycles the scripts of a `dry_run_transaction` may take, the ones of a
+    /// transaction by default
+    pub dry_run_max_cycles: Option<Cycle>,
+    /// Where `backup_db` writes the backups, set by the node to the
+    /// `backups` directory of its data directory
+    #[serde(skip)]
+    pub backup_dir: Option<PathBuf>,
+}
+
+/// Credentials required to call the methods of `modules`, the other modules
+/// stay public
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct AuthConfig {
+    pub modules: Vec<Module>,
+    /// Accepted as `Authorization: Bearer <token>`
+    pub bearer_token: Option<String>,
+    /// `<username>:<password>` accepted as basic authentication
+    pub basic_auth: Option<String>,
+}
+
+/// Requests a client may send: `burst` at once, refilled at
+/// `requests_per_second`. The others are answered 429.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct RateLimitConfig {
+    pub requests_per_second: u32,
+    pub burst: u32,
 }
 
 impl Config {
@@ -37,4 +89,26 @@ impl Config {
     pub(crate) fn trace_enable(&self) -> bool {
         self.modules.contains(&Module::Trace)
     }
+
+    pub(crate) fn admin_enable(&self) -> bool {
+        self.modules.contains(&Module::Admin)
+    }
+
+    pub(crate) fn indexer_enable(&self) -> bool {
+        self.modules.contains(&Module::Indexer)
+    }
+
+    pub(crate) fn dev_enable(&self) -> bool {
+        self.modules.contains(&Module::Dev)
+    }
+
+    pub(crate) fn debug_enable(&self) -> bool {
+        self.modules.contains(&Module::Debug)
+    }
+
+    pub(crate) fn requires_auth(&self, module: Module) -> bool {
+        self.auth
+            .as_ref()
+            .map_or(false, |auth| auth.modules.contains(&module))
+    }
 }
@@ -0,0 +1,146 @@
+//! `GET /health` and `GET /ready` on the HTTP address of the RPC, for the
+//! probes of load balancers and orchestrators. They are answered `200 OK`, or
+//! `503 Service Unavailable` with the reason, and are not rate limited.
+//!
+//! - `/health`: the node runs and its db can be read
+//! - `/ready`: the node is healthy, out of the initial block download and
+//!   connected to `rpc.ready_min_peers` peers at least
+
+use crate::limit::{respond, RequestLimiter};
+use ckb_network::NetworkService;
+use ckb_shared::index::ChainIndex;
+use ckb_shared::shared::Shared;
+use ckb_sync::Synchronizer;
+use jsonrpc_http_server::hyper::{Body, Method, Request, StatusCode};
+use jsonrpc_http_server::{RequestMiddleware, RequestMiddlewareAction};
+use std::sync::Arc;
+
+const HEALTH_PATH: &str = "/health";
+const READY_PATH: &str = "/ready";
+const DEFAULT_READY_MIN_PEERS: usize = 1;
+
+pub(crate) struct HealthCheck<CI: ChainIndex> {
+    shared: Shared<CI>,
+    network: Arc<NetworkService>,
+    // Tells the initial block download
+    synchronizer: Synchronizer<CI>,
+    min_peers: usize,
+    // Passed the other requests
+    limiter: RequestLimiter,
+}
+
+impl<CI: ChainIndex + 'static> HealthCheck<CI> {
+    pub fn new(
+        shared: Shared<CI>,
+        network: Arc<NetworkService>,
+        synchronizer: Synchronizer<CI>,
+        min_peers: Option<usize>,
+        limiter: RequestLimiter,
+    ) -> Self {
+        HealthCheck {
+            shared,
+            network,
+            synchronizer,
+            min_peers: min_peers.unwrap_or(DEFAULT_READY_MIN_PEERS),
+            limiter,
+        }
+    }
+
+    fn health(&self) -> Result<(), String> {
+        health(&self.shared)
+    }
+
+    fn ready(&self) -> Result<(), String> {
+        self.health()?;
+        ready(
+            &self.synchronizer,
+            self.network.peers().len(),
+            self.min_peers,
+        )
+    }
+}
+
+fn health<CI: ChainIndex>(shared: &Shared<CI>) -> Result<(), String> {
+    let tip_hash = shared.chain_state().read().tip_hash();
+    match shared.store().contains_header(&tip_hash) {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(format!("tip {:#x} is missing in the db", tip_hash)),
+        Err(err) => Err(format!("read the db failed: {:?}", err)),
+    }
+}
+
+// The checks of a healthy node
+fn ready<CI: ChainIndex>(
+    synchronizer: &Synchronizer<CI>,
+    peers: usize,
+    min_peers: usize,
+) -> Result<(), String> {
+    if synchronizer.is_initial_block_download() {
+        return Err("in initial block download".to_owned());
+    }
+    if peers < min_peers {
+        return Err(format!("{} peers connected, {} needed", peers, min_peers));
+    }
+    Ok(())
+}
+
+impl<CI: ChainIndex + 'static> RequestMiddleware for HealthCheck<CI> {
+    fn on_request(&self, request: Request<Body>) -> RequestMiddlewareAction {
+        // A node shutting down is neither healthy nor ready
+        if request.method() != Method::GET || self.limiter.is_closing() {
+            return self.limiter.on_request(request);
+        }
+        let result = match request.uri().path() {
+            HEALTH_PATH => self.health(),
+            READY_PATH => self.ready(),
+            _ => return self.limiter.on_request(request),
+        };
+        match result {
+            Ok(()) => respond(StatusCode::OK, "ok"),
+            Err(reason) => respond(StatusCode::SERVICE_UNAVAILABLE, &reason),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ckb_chain::test_utils::start_chain;
+    use ckb_chain_spec::consensus::Consensus;
+    use ckb_core::block::BlockBuilder;
+    use ckb_core::header::HeaderBuilder;
+    use ckb_notify::NotifyService;
+    use ckb_sync::{Config as SyncConfig, MAX_TIP_AGE};
+    use ckb_time::Clock;
+
+    const GENESIS_TIMESTAMP: u64 = 1_000_000;
+
+    #[test]
+    fn test_health_and_ready() {
+        let clock = Clock::new(GENESIS_TIMESTAMP);
+        let _scope = clock.enter();
+        let genesis = BlockBuilder::default()
+            .with_header_builder(HeaderBuilder::default().timestamp(GENESIS_TIMESTAMP));
+        let consensus = Consensus::default().set_genesis_block(genesis);
+        let notify = NotifyService::default().start::<&str>(None);
+        let (chain, shared) = start_chain(consensus, notify, false);
+        let synchronizer = Synchronizer::new(chain, shared.clone(), SyncConfig::default());
+
+        assert_eq!(health(&shared), Ok(()));
+        assert_eq!(ready(&synchronizer, 1, 1), Ok(()));
+        assert_eq!(
+            ready(&synchronizer, 0, 1),
+            Err("0 peers connected, 1 needed".to_owned())
+        );
+
+        // The tip is too old once the clock moves on, as the synchronizer
+        // tells it
+        clock.advance(MAX_TIP_AGE + 1);
+        assert!(synchronizer.is_initial_block_download());
+        assert_eq!(
+            ready(&synchronizer, 1, 1),
+            Err("in initial block download".to_owned())
+        );
+        assert_eq!(health(&shared), Ok(()));
+    }
+}
@@ -1,6 +1,10 @@
+mod auth;
 mod config;
+mod health;
+mod limit;
 mod module;
 mod server;
 
-pub use crate::config::Config;
+pub use crate::config::{AuthConfig, Config, Module, RateLimitConfig};
+pub use crate::module::{RuntimeConfig, RuntimeOverrides};
 pub use crate::server::RpcServer;
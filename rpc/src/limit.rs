@@ -0,0 +1,438 @@
+//! Limits on the requests served, see
+//! [RateLimitConfig](crate::config::RateLimitConfig).
+//!
+//! A request over a limit is answered `429 Too Many Requests` before its body
+//! is read, and one coming while the server closes `503 Service Unavailable`.
+//! The HTTP server does not tell the address of the peer, so clients are told
+//! apart by the `X-Forwarded-For` header of the
+//! [trusted proxies](crate::config::Config::trusted_proxies): the client is the
+//! last address not of a trusted proxy, the address the first proxy saw. The
+//! rate limit needs them and is refused without, since the clients would all
+//! share one limit and any of them could lock the others out. The clients
+//! reaching the node directly, without a trusted proxy, still share one.
+//!
+//! The WebSocket handshakes are checked as the HTTP requests, and the calls
+//! on a connection are then counted in flight and rate limited by connection.
+
+use crate::auth::Credentials;
+use crate::config::RateLimitConfig;
+use ckb_util::{Mutex, RwLock};
+use futures::future::{self, Either};
+use futures::Future;
+use jsonrpc_core::{Call, Error, ErrorCode, Middleware, Output, Request as RpcRequest};
+use jsonrpc_http_server::hyper::header::HeaderValue;
+use jsonrpc_http_server::hyper::{Body, Request, StatusCode};
+use jsonrpc_http_server::{RequestMiddleware, RequestMiddlewareAction, Response};
+use jsonrpc_pubsub::Session;
+use jsonrpc_ws_server::{ws, MiddlewareAction};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const FORWARDED_FOR: &str = "x-forwarded-for";
+const LIMITED: i64 = -32050;
+// The clients tracked at most, the new ones share the bucket of the direct
+// clients until the next sweep drops the refilled buckets
+const MAX_TRACKED_CLIENTS: usize = 10_000;
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+impl Bucket {
+    fn tokens_at(&self, now: Instant, rate: f64, burst: f64) -> f64 {
+        // Another request may have updated the bucket since `now` was taken
+        let elapsed = now.max(self.updated_at) - self.updated_at;
+        (self.tokens + seconds(elapsed) * rate).min(burst)
+    }
+}
+
+struct Buckets {
+    clients: HashMap<String, Bucket>,
+    swept_at: Instant,
+}
+
+#[derive(Clone)]
+pub(crate) struct RequestLimiter {
+    // Both changed at runtime by the admin RPC
+    rate_limit: Arc<RwLock<Option<RateLimitConfig>>>,
+    buckets: Arc<Mutex<Buckets>>,
+    trusted_proxies: Arc<Vec<IpAddr>>,
+    max_in_flight: Arc<RwLock<Option<usize>>>,
+    in_flight: Arc<AtomicUsize>,
+    closing: Arc<AtomicBool>,
+}
+
+/// Counts a request as in flight until dropped
+#[derive(Debug)]
+pub(crate) struct InFlight(Arc<AtomicUsize>);
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl RequestLimiter {
+    pub fn new(
+        rate_limit: Option<RateLimitConfig>,
+        max_in_flight: Option<usize>,
+        trusted_proxies: Vec<IpAddr>,
+    ) -> Self {
+        RequestLimiter {
+            rate_limit: Arc::new(RwLock::new(rate_limit)),
+            buckets: Arc::new(Mutex::new(Buckets {
+                clients: HashMap::new(),
+                swept_at: Instant::now(),
+            })),
+            trusted_proxies: Arc::new(trusted_proxies),
+            max_in_flight: Arc::new(RwLock::new(max_in_flight)),
+            in_flight: Arc::new(AtomicUsize::new(0)),
+            closing: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Whether the clients can be told apart, which the rate limit needs
+    pub fn has_trusted_proxies(&self) -> bool {
+        !self.trusted_proxies.is_empty()
+    }
+
+    pub fn set_rate_limit(&self, rate_limit: Option<RateLimitConfig>) {
+        *self.rate_limit.write() = rate_limit;
+    }
+
+    pub fn set_max_in_flight(&self, max_in_flight: Option<usize>) {
+        *self.max_in_flight.write() = max_in_flight;
+    }
+
+    /// Refuses the new requests, the ones in flight are still served
+    pub fn close(&self) {
+        self.closing.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_closing(&self) -> bool {
+        self.closing.load(Ordering::SeqCst)
+    }
+
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::SeqCst)
+    }
+
+    pub fn enter(&self) -> InFlight {
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+        InFlight(Arc::clone(&self.in_flight))
+    }
+
+    fn is_busy(&self) -> bool {
+        self.max_in_flight
+            .read()
+            .map_or(false, |max| self.in_flight.load(Ordering::SeqCst) >= max)
+    }
+
+    fn client(&self, request: &Request<Body>) -> String {
+        self.forwarded_client(
+            request
+                .headers()
+                .get_all(FORWARDED_FOR)
+                .iter()
+                .filter_map(|value| value.to_str().ok()),
+        )
+    }
+
+    // The client forwarded for by the trusted proxies in the `X-Forwarded-For`
+    // values, the empty one of the direct clients otherwise
+    fn forwarded_client<'a>(&self, values: impl Iterator<Item = &'a str>) -> String {
+        if self.trusted_proxies.is_empty() {
+            return String::new();
+        }
+        let forwarded: Vec<&str> = values
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+            .collect();
+        forwarded
+            .into_iter()
+            .rev()
+            .find(|address| {
+                address
+                    .parse::<IpAddr>()
+                    .map_or(true, |ip| !self.trusted_proxies.contains(&ip))
+            })
+            .unwrap_or("")
+            .to_owned()
+    }
+
+    fn take_token(&self, client: &str, now: Instant) -> bool {
+        let config = match *self.rate_limit.read() {
+            Some(ref config) => config.clone(),
+            None => return true,
+        };
+        let rate = f64::from(config.requests_per_second);
+        let burst = f64::from(config.burst.max(1));
+
+        let mut buckets = self.buckets.lock();
+        if now >= buckets.swept_at + SWEEP_INTERVAL {
+            buckets
+                .clients
+                .retain(|_, bucket| bucket.tokens_at(now, rate, burst) < burst);
+            buckets.swept_at = now;
+        }
+        let tracked = buckets.clients.contains_key(client);
+        let client = if tracked || buckets.clients.len() < MAX_TRACKED_CLIENTS {
+            client
+        } else {
+            ""
+        };
+        let bucket = buckets.clients.entry(client.to_owned()).or_insert(Bucket {
+            tokens: burst,
+            updated_at: now,
+        });
+        bucket.tokens = bucket.tokens_at(now, rate, burst);
+        bucket.updated_at = bucket.updated_at.max(now);
+        if bucket.tokens < 1.0 {
+            return false;
+        }
+        bucket.tokens -= 1.0;
+        true
+    }
+}
+
+impl RequestMiddleware for RequestLimiter {
+    fn on_request(&self, request: Request<Body>) -> RequestMiddlewareAction {
+        if self.is_closing() {
+            return respond(StatusCode::SERVICE_UNAVAILABLE, "shutting down");
+        }
+        if self.is_busy() {
+            return respond(StatusCode::TOO_MANY_REQUESTS, "too many requests in flight");
+        }
+        let client = self.client(&request);
+        if !self.take_token(&client, Instant::now()) {
+            return respond(StatusCode::TOO_MANY_REQUESTS, "rate limit exceeded");
+        }
+        request.into()
+    }
+}
+
+/// Checks the WebSocket handshakes as the HTTP requests, and their
+/// credentials when any module requires them, since the subscriptions tell
+/// the transactions of the node
+pub(crate) struct HandshakeLimiter {
+    limiter: RequestLimiter,
+    credentials: Option<Credentials>,
+}
+
+impl HandshakeLimiter {
+    pub fn new(limiter: RequestLimiter, credentials: Option<Credentials>) -> HandshakeLimiter {
+        HandshakeLimiter {
+            limiter,
+            credentials,
+        }
+    }
+}
+
+impl jsonrpc_ws_server::RequestMiddleware for HandshakeLimiter {
+    fn process(&self, request: &ws::Request) -> MiddlewareAction {
+        let values = |name: &str| -> Vec<String> {
+            request
+                .headers()
+                .iter()
+                .filter(|(header, _)| header.eq_ignore_ascii_case(name))
+                .map(|(_, value)| String::from_utf8_lossy(value).into_owned())
+                .collect()
+        };
+        let forwarded = values(FORWARDED_FOR);
+        let client = self
+            .limiter
+            .forwarded_client(forwarded.iter().map(String::as_str));
+        let (status, reason, message) = if self.limiter.is_closing() {
+            (503, "Service Unavailable", "shutting down")
+        } else if !self.limiter.take_token(&client, Instant::now()) {
+            (429, "Too Many Requests", "rate limit exceeded")
+        } else if self.credentials.as_ref().map_or(false, |credentials| {
+            !values("authorization")
+                .iter()
+                .any(|value| credentials.accepts(value))
+        }) {
+            (401, "Unauthorized", "unauthorized")
+        } else {
+            return MiddlewareAction::Proceed;
+        };
+        MiddlewareAction::Respond {
+            response: ws::Response::new(status, reason, format!("{}\n", message).into_bytes()),
+            validate_origin: false,
+            validate_hosts: false,
+        }
+    }
+}
+
+/// Counts the calls of the WebSocket connections in flight and rate limits
+/// each connection on its own, the calls over a limit are answered with an
+/// error
+pub(crate) struct CallLimiter(pub RequestLimiter);
+
+impl Middleware<Option<Arc<Session>>> for CallLimiter {
+    type Future = Box<dyn Future<Item = Option<jsonrpc_core::Response>, Error = ()> + Send>;
+
+    fn on_request<F, X>(
+        &self,
+        request: RpcRequest,
+        meta: Option<Arc<Session>>,
+        next: F,
+    ) -> Either<Self::Future, X>
+    where
+        F: FnOnce(RpcRequest, Option<Arc<Session>>) -> X + Send,
+        X: Future<Item = Option<jsonrpc_core::Response>, Error = ()> + Send + 'static,
+    {
+        // The session of the connection, by address as the subscriptions
+        let connection = meta
+            .as_ref()
+            .map_or(0, |session| &**session as *const Session as usize);
+        let refused = if self.0.is_closing() {
+            Some("shutting down")
+        } else if self.0.is_busy() {
+            Some("too many requests in flight")
+        } else if !self
+            .0
+            .take_token(&format!("ws:{}", connection), Instant::now())
+        {
+            Some("rate limit exceeded")
+        } else {
+            None
+        };
+        if let Some(message) = refused {
+            return Either::A(Box::new(future::ok(refuse(request, message))));
+        }
+        let in_flight = self.0.enter();
+        Either::A(Box::new(next(request, meta).then(move |response| {
+            drop(in_flight);
+            response
+        })))
+    }
+}
+
+fn refuse(request: RpcRequest, message: &str) -> Option<jsonrpc_core::Response> {
+    let output = |call: &Call| match call {
+        Call::MethodCall(call) => {
+            let error = Error {
+                code: ErrorCode::ServerError(LIMITED),
+                message: message.to_owned(),
+                data: None,
+            };
+            Some(Output::from(Err(error), call.id.clone(), call.jsonrpc))
+        }
+        _ => None,
+    };
+    match request {
+        RpcRequest::Single(call) => output(&call).map(jsonrpc_core::Response::Single),
+        RpcRequest::Batch(calls) => Some(jsonrpc_core::Response::Batch(
+            calls.iter().filter_map(output).collect(),
+        )),
+    }
+}
+
+/// Answers with `message` as plain text, without calling the methods
+pub(crate) fn respond(code: StatusCode, message: &str) -> RequestMiddlewareAction {
+    Response {
+        code,
+        content_type: HeaderValue::from_static("text/plain; charset=utf-8"),
+        content: format!("{}\n", message),
+    }
+    .into()
+}
+
+fn seconds(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + f64::from(duration.subsec_nanos()) / 1e9
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter(burst: u32, trusted_proxies: Vec<IpAddr>) -> RequestLimiter {
+        let rate_limit = RateLimitConfig {
+            requests_per_second: 1,
+            burst,
+        };
+        RequestLimiter::new(Some(rate_limit), None, trusted_proxies)
+    }
+
+    fn forwarded_for(values: &[&str]) -> Request<Body> {
+        let mut builder = Request::builder();
+        for value in values {
+            builder.header(FORWARDED_FOR, *value);
+        }
+        builder.body(Body::empty()).expect("valid request")
+    }
+
+    #[test]
+    fn test_header_ignored_without_trusted_proxies() {
+        let limiter = limiter(1, Vec::new());
+        assert!(!limiter.has_trusted_proxies());
+        assert_eq!(limiter.client(&forwarded_for(&["1.2.3.4"])), "");
+    }
+
+    #[test]
+    fn test_client_forwarded_for_by_trusted_proxies() {
+        let proxy: IpAddr = "10.0.0.1".parse().unwrap();
+        let limiter = limiter(1, vec![proxy]);
+        // The client may prepend any address, the proxy appends the one it saw
+        let request = forwarded_for(&["6.6.6.6, 1.2.3.4", "10.0.0.1"]);
+        assert_eq!(limiter.client(&request), "1.2.3.4");
+        assert_eq!(limiter.client(&forwarded_for(&["10.0.0.1"])), "");
+        assert_eq!(limiter.client(&forwarded_for(&[])), "");
+        // The handshakes give the values as the HTTP requests
+        let values = vec!["6.6.6.6, 1.2.3.4", "10.0.0.1"];
+        assert_eq!(limiter.forwarded_client(values.into_iter()), "1.2.3.4");
+    }
+
+    #[test]
+    fn test_ws_calls_limited() {
+        let limiter = limiter(1, Vec::new());
+        let mut io = jsonrpc_core::MetaIoHandler::with_middleware(CallLimiter(limiter.clone()));
+        io.add_method("ping", |_| Ok(jsonrpc_core::Value::from("pong")));
+        let request = r#"{"jsonrpc":"2.0","method":"ping","id":1}"#;
+        assert_eq!(
+            io.handle_request_sync(request, None),
+            Some(r#"{"jsonrpc":"2.0","result":"pong","id":1}"#.to_owned())
+        );
+        assert_eq!(limiter.in_flight(), 0);
+        assert_eq!(
+            io.handle_request_sync(request, None).expect("a response"),
+            r#"{"jsonrpc":"2.0","error":{"code":-32050,"message":"rate limit exceeded"},"#
+                .to_owned()
+                + r#""id":1}"#
+        );
+    }
+
+    #[test]
+    fn test_buckets_refill() {
+        let limiter = limiter(2, Vec::new());
+        let now = Instant::now();
+        assert!(limiter.take_token("a", now));
+        assert!(limiter.take_token("a", now));
+        assert!(!limiter.take_token("a", now));
+        assert!(limiter.take_token("b", now));
+        assert!(limiter.take_token("a", now + Duration::from_secs(1)));
+        assert!(!limiter.take_token("a", now + Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn test_refilled_buckets_swept() {
+        let limiter = limiter(1, Vec::new());
+        let now = Instant::now();
+        for client in 0..MAX_TRACKED_CLIENTS {
+            assert!(limiter.take_token(&client.to_string(), now));
+        }
+        // Over the limit the new clients share one bucket until the sweep
+        assert!(limiter.take_token("new", now));
+        assert!(!limiter.take_token("newer", now));
+        assert_eq!(limiter.buckets.lock().clients.len(), MAX_TRACKED_CLIENTS + 1);
+
+        let later = now + SWEEP_INTERVAL;
+        assert!(limiter.take_token("newer", later));
+        assert_eq!(limiter.buckets.lock().clients.len(), 1);
+    }
+}
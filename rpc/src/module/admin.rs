@@ -0,0 +1,299 @@
+use crate::config::RateLimitConfig;
+use crate::limit::RequestLimiter;
+use ckb_core::Capacity;
+use ckb_miner::BlockAssemblerController;
+use ckb_network::NetworkService;
+use ckb_pool::txs_pool::TransactionPoolController;
+use ckb_shared::index::ChainIndex;
+use ckb_shared::shared::Shared;
+use ckb_shared::store::ChainStore;
+use ckb_util::{Mutex, MutexGuard};
+use jsonrpc_core::{Error, Result};
+use jsonrpc_macros::build_rpc_trait;
+use log::info;
+use serde::{Deserialize, Deserializer};
+use serde_derive::Deserialize;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+/// The settings `update_config` changes, the ones missing are kept. The
+/// limits of the RPC are cleared by `null`.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RuntimeConfig {
+    pub log_filter: Option<String>,
+    pub max_inbound_peers: Option<u32>,
+    pub max_outbound_peers: Option<u32>,
+    pub min_fee_rate: Option<Capacity>,
+    pub miner_paused: Option<bool>,
+    #[serde(default, deserialize_with = "deserialize_clearable")]
+    pub rate_limit: Option<Option<RateLimitConfig>>,
+    #[serde(default, deserialize_with = "deserialize_clearable")]
+    pub max_in_flight_requests: Option<Option<usize>>,
+}
+
+// A setting given as `null` is `Some(None)`, a missing one `None`
+fn deserialize_clearable<'de, D, T>(
+    deserializer: D,
+) -> std::result::Result<Option<Option<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+impl RuntimeConfig {
+    // Every setting is checked before any is applied, so that applying them
+    // cannot fail
+    fn validate(&self) -> std::result::Result<(), String> {
+        let mut errors = Vec::new();
+        if let Some(ref filter) = self.log_filter {
+            if let Err(err) = logger::check_filter(filter) {
+                errors.push(err);
+            }
+        }
+        if self.max_outbound_peers == Some(0) {
+            errors.push("max_outbound_peers must be above 0".to_owned());
+        }
+        if let Some(Some(ref rate_limit)) = self.rate_limit {
+            if rate_limit.requests_per_second == 0 {
+                errors.push("rate_limit.requests_per_second must be above 0".to_owned());
+            }
+        }
+        if self.max_in_flight_requests == Some(Some(0)) {
+            errors.push("max_in_flight_requests must be above 0".to_owned());
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors.join(", "))
+        }
+    }
+
+    // The settings of `newer` replace the ones of `self`, its log filter is
+    // merged after the one of `self` as the logger merges them
+    fn merge(&mut self, newer: RuntimeConfig) {
+        if let Some(filter) = newer.log_filter {
+            self.log_filter = Some(match self.log_filter.take() {
+                Some(older) => format!("{},{}", older, filter),
+                None => filter,
+            });
+        }
+        self.max_inbound_peers = newer.max_inbound_peers.or(self.max_inbound_peers);
+        self.max_outbound_peers = newer.max_outbound_peers.or(self.max_outbound_peers);
+        self.min_fee_rate = newer.min_fee_rate.or(self.min_fee_rate);
+        self.miner_paused = newer.miner_paused.or(self.miner_paused);
+        self.rate_limit = newer.rate_limit.or_else(|| self.rate_limit.take());
+        self.max_in_flight_requests = newer
+            .max_in_flight_requests
+            .or(self.max_in_flight_requests);
+    }
+}
+
+/// The settings changed by `update_config` since the node started, which a
+/// reload of the config file applies again over the ones of the file. Its
+/// lock is held while settings are applied, so that the changes do not
+/// interleave.
+#[derive(Clone, Default)]
+pub struct RuntimeOverrides(Arc<Mutex<RuntimeConfig>>);
+
+impl RuntimeOverrides {
+    pub fn lock(&self) -> MutexGuard<RuntimeConfig> {
+        self.0.lock()
+    }
+}
+
+build_rpc_trait! {
+    pub trait AdminRpc {
+        // Backs up the chain store to the directory `name` in the backups
+        // directory of the node, see ChainStore::backup
+        // curl -d '{"id": 2, "jsonrpc": "2.0", "method":"backup_db","params": ["ckb-20190101"]}' -H 'content-type:application/json' 'http://localhost:8114'
+        #[rpc(name = "backup_db")]
+        fn backup_db(&self, _name: String) -> Result<()>;
+
+        // curl -d '{"id": 2, "jsonrpc": "2.0", "method":"set_log_filter","params": ["sync=debug,network=warn"]}' -H 'content-type:application/json' 'http://localhost:8114'
+        #[rpc(name = "set_log_filter")]
+        fn set_log_filter(&self, _filter: String) -> Result<()>;
+
+        // curl -d '{"id": 2, "jsonrpc": "2.0", "method":"get_log_filter","params": []}' -H 'content-type:application/json' 'http://localhost:8114'
+        #[rpc(name = "get_log_filter")]
+        fn get_log_filter(&self) -> Result<String>;
+
+        // Changes the settings given at once, or none when one is invalid
+        // curl -d '{"id": 2, "jsonrpc": "2.0", "method":"update_config","params": [{"max_outbound_peers": 8, "miner_paused": true}]}' -H 'content-type:application/json' 'http://localhost:8114'
+        #[rpc(name = "update_config")]
+        fn update_config(&self, _config: RuntimeConfig) -> Result<()>;
+
+        // curl -d '{"id": 2, "jsonrpc": "2.0", "method":"clear_tx_pool","params": []}' -H 'content-type:application/json' 'http://localhost:8114'
+        #[rpc(name = "clear_tx_pool")]
+        fn clear_tx_pool(&self) -> Result<()>;
+    }
+}
+
+pub(crate) struct AdminRpcImpl<CI> {
+    pub shared: Shared<CI>,
+    pub network: Arc<NetworkService>,
+    pub tx_pool: TransactionPoolController,
+    pub block_assembler: BlockAssemblerController,
+    pub limiter: RequestLimiter,
+    pub backup_dir: Option<PathBuf>,
+    pub overrides: RuntimeOverrides,
+}
+
+// A backup is a directory right in `dir`, a request tells no other path of the node
+fn backup_path(dir: &Path, name: &str) -> std::result::Result<PathBuf, String> {
+    let mut components = Path::new(name).components();
+    match (components.next(), components.next()) {
+        (Some(Component::Normal(_)), None) => Ok(dir.join(name)),
+        _ => Err(format!("invalid backup name {:?}", name)),
+    }
+}
+
+impl<CI: ChainIndex + 'static> AdminRpc for AdminRpcImpl<CI> {
+    fn backup_db(&self, name: String) -> Result<()> {
+        let dir = self.backup_dir.as_ref().ok_or_else(|| {
+            let mut error = Error::internal_error();
+            error.message = "no backup directory".to_owned();
+            error
+        })?;
+        let path = backup_path(dir, &name).map_err(Error::invalid_params)?;
+        self.shared.store().backup(&path).map_err(|err| {
+            let mut error = Error::internal_error();
+            error.message = format!("backup failed: {:?}", err);
+            error
+        })?;
+        info!(target: "rpc", "chain store backed up to {}", path.display());
+        Ok(())
+    }
+
+    fn set_log_filter(&self, filter: String) -> Result<()> {
+        logger::set_filter(&filter).map_err(Error::invalid_params)?;
+        info!(target: "rpc", "log filter set to {}", logger::filter_spec());
+        Ok(())
+    }
+
+    fn get_log_filter(&self) -> Result<String> {
+        Ok(logger::filter_spec())
+    }
+
+    fn update_config(&self, config: RuntimeConfig) -> Result<()> {
+        config.validate().map_err(Error::invalid_params)?;
+        if let Some(Some(_)) = config.rate_limit {
+            if !self.limiter.has_trusted_proxies() {
+                return Err(Error::invalid_params("rate_limit needs rpc.trusted_proxies"));
+            }
+        }
+        let mut overrides = self.overrides.lock();
+        if let Some(ref filter) = config.log_filter {
+            logger::set_filter(filter).expect("checked filter");
+        }
+        if config.max_inbound_peers.is_some() || config.max_outbound_peers.is_some() {
+            let (max_inbound, max_outbound) = self.network.peer_limits();
+            self.network.set_peer_limits(
+                config.max_inbound_peers.unwrap_or(max_inbound),
+                config.max_outbound_peers.unwrap_or(max_outbound),
+            );
+        }
+        if let Some(min_fee_rate) = config.min_fee_rate {
+            self.tx_pool.set_min_fee_rate(min_fee_rate);
+        }
+        if let Some(paused) = config.miner_paused {
+            self.block_assembler.set_paused(paused);
+        }
+        if let Some(ref rate_limit) = config.rate_limit {
+            self.limiter.set_rate_limit(rate_limit.clone());
+        }
+        if let Some(max_in_flight) = config.max_in_flight_requests {
+            self.limiter.set_max_in_flight(max_in_flight);
+        }
+        info!(target: "rpc", "config updated: {:?}", config);
+        overrides.merge(config);
+        Ok(())
+    }
+
+    fn clear_tx_pool(&self) -> Result<()> {
+        self.tx_pool.clear();
+        info!(target: "rpc", "tx pool cleared");
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backup_path() {
+        let dir = Path::new("/data/backups");
+        assert_eq!(
+            backup_path(dir, "ckb-20190101"),
+            Ok(dir.join("ckb-20190101"))
+        );
+        for name in &["", ".", "..", "../db", "a/b", "/etc", "/"] {
+            assert!(backup_path(dir, name).is_err(), "{:?}", name);
+        }
+    }
+
+    fn parse(json: &str) -> RuntimeConfig {
+        serde_json::from_str(json).expect("runtime config")
+    }
+
+    #[test]
+    fn test_clear_rpc_limits() {
+        assert_eq!(parse("{}"), RuntimeConfig::default());
+        let config = parse(r#"{"rate_limit": null, "max_in_flight_requests": null}"#);
+        assert_eq!(config.rate_limit, Some(None));
+        assert_eq!(config.max_in_flight_requests, Some(None));
+        let config = parse(
+            r#"{"rate_limit": {"requests_per_second": 5, "burst": 10},
+                "max_in_flight_requests": 3}"#,
+        );
+        assert_eq!(
+            config.rate_limit,
+            Some(Some(RateLimitConfig {
+                requests_per_second: 5,
+                burst: 10,
+            }))
+        );
+        assert_eq!(config.max_in_flight_requests, Some(Some(3)));
+    }
+
+    #[test]
+    fn test_validate_every_setting() {
+        let config = parse(
+            r#"{"log_filter": "sync=loud", "max_outbound_peers": 0,
+                "max_in_flight_requests": 0}"#,
+        );
+        let errors = config.validate().unwrap_err();
+        assert!(errors.contains("sync"), "{}", errors);
+        assert!(errors.contains("max_outbound_peers"), "{}", errors);
+        assert!(errors.contains("max_in_flight_requests"), "{}", errors);
+        assert!(!errors.contains("rate_limit"), "{}", errors);
+
+        let config = parse(r#"{"log_filter": "sync=debug", "rate_limit": null}"#);
+        assert_eq!(config.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_merge_overrides() {
+        let mut overrides = parse(
+            r#"{"log_filter": "sync=debug", "max_inbound_peers": 10,
+                "max_in_flight_requests": 3}"#,
+        );
+        overrides.merge(parse(
+            r#"{"log_filter": "network=warn", "min_fee_rate": 100,
+                "max_in_flight_requests": null}"#,
+        ));
+        assert_eq!(
+            overrides,
+            RuntimeConfig {
+                log_filter: Some("sync=debug,network=warn".to_owned()),
+                max_inbound_peers: Some(10),
+                min_fee_rate: Some(100),
+                max_in_flight_requests: Some(None),
+                ..Default::default()
+            }
+        );
+    }
+}
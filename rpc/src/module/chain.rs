@@ -1,12 +1,19 @@
+use ckb_chain::chain::ChainController;
+use ckb_core::address::{Address, AddressNetwork};
 use ckb_core::cell::CellProvider;
+use ckb_core::transaction::ProposalShortId;
 use ckb_core::BlockNumber;
+use ckb_pool::txs_pool::{TransactionPoolController, TxStatus};
 use ckb_shared::{
     index::ChainIndex,
     shared::{ChainProvider, Shared},
 };
 use jsonrpc_core::{Error, Result};
 use jsonrpc_macros::build_rpc_trait;
-use jsonrpc_types::{Block, CellOutputWithOutPoint, CellWithStatus, Header, OutPoint, Transaction};
+use jsonrpc_types::{
+    Block, BlockInterval, BlockchainInfo, CellOutputWithOutPoint, CellWithStatus, Header, OutPoint,
+    TransactionWithStatus,
+};
 use numext_fixed_hash::H256;
 
 build_rpc_trait! {
@@ -14,8 +21,15 @@ build_rpc_trait! {
         #[rpc(name = "get_block")]
         fn get_block(&self, _hash: H256) -> Result<Option<Block>>;
 
+        #[rpc(name = "get_block_by_number")]
+        fn get_block_by_number(&self, _number: BlockNumber) -> Result<Option<Block>>;
+
+        #[rpc(name = "get_header")]
+        fn get_header(&self, _hash: H256) -> Result<Option<Header>>;
+
+        // Looks for the transaction in the main chain, then in the pool
         #[rpc(name = "get_transaction")]
-        fn get_transaction(&self, _hash: H256) -> Result<Option<Transaction>>;
+        fn get_transaction(&self, _hash: H256) -> Result<Option<TransactionWithStatus>>;
 
         #[rpc(name = "get_block_hash")]
         fn get_block_hash(&self, _number: u64) -> Result<Option<H256>>;
@@ -36,11 +50,16 @@ build_rpc_trait! {
 
         #[rpc(name = "get_tip_block_number")]
         fn get_tip_block_number(&self) -> Result<BlockNumber>;
+
+        #[rpc(name = "get_blockchain_info")]
+        fn get_blockchain_info(&self) -> Result<BlockchainInfo>;
     }
 }
 
 pub(crate) struct ChainRpcImpl<CI> {
     pub shared: Shared<CI>,
+    pub chain: ChainController,
+    pub tx_pool: TransactionPoolController,
 }
 
 impl<CI: ChainIndex + 'static> ChainRpc for ChainRpcImpl<CI> {
@@ -48,8 +67,55 @@ impl<CI: ChainIndex + 'static> ChainRpc for ChainRpcImpl<CI> {
         Ok(self.shared.block(&hash).as_ref().map(Into::into))
     }
 
-    fn get_transaction(&self, hash: H256) -> Result<Option<Transaction>> {
-        Ok(self.shared.get_transaction(&hash).as_ref().map(Into::into))
+    fn get_block_by_number(&self, number: BlockNumber) -> Result<Option<Block>> {
+        Ok(self
+            .shared
+            .block_hash(number)
+            .and_then(|hash| self.shared.block(&hash))
+            .as_ref()
+            .map(Into::into))
+    }
+
+    fn get_header(&self, hash: H256) -> Result<Option<Header>> {
+        Ok(self.shared.block_header(&hash).as_ref().map(Into::into))
+    }
+
+    fn get_transaction(&self, hash: H256) -> Result<Option<TransactionWithStatus>> {
+        if let Some(address) = self.shared.get_transaction_address(&hash) {
+            let number = self
+                .shared
+                .block_number(&address.block_hash)
+                .ok_or_else(Error::internal_error)?;
+            let transaction = self
+                .shared
+                .get_transaction(&hash)
+                .ok_or_else(Error::internal_error)?;
+            let tip_number = self.shared.chain_state().read().tip_number();
+            return Ok(Some(TransactionWithStatus {
+                transaction: (&transaction).into(),
+                status: "committed".to_string(),
+                block_hash: Some(address.block_hash),
+                confirmations: Some(tip_number + 1 - number),
+            }));
+        }
+
+        let id = ProposalShortId::from_h256(&hash);
+        let transaction = match self.tx_pool.get_transaction(id) {
+            // Short ids may collide
+            Some(transaction) if transaction.hash() == hash => transaction,
+            _ => return Ok(None),
+        };
+        let status = match self.tx_pool.get_transaction_status(id) {
+            Some(TxStatus::Proposed) | Some(TxStatus::Mineable) => "proposed",
+            Some(_) => "pending",
+            None => return Ok(None),
+        };
+        Ok(Some(TransactionWithStatus {
+            transaction: (&transaction).into(),
+            status: status.to_string(),
+            block_hash: None,
+            confirmations: None,
+        }))
     }
 
     fn get_block_hash(&self, number: BlockNumber) -> Result<Option<H256>> {
@@ -67,6 +133,7 @@ impl<CI: ChainIndex + 'static> ChainRpc for ChainRpcImpl<CI> {
         from: BlockNumber,
         to: BlockNumber,
     ) -> Result<Vec<CellOutputWithOutPoint>> {
+        let network = AddressNetwork::from_network_id(self.shared.consensus().network_id());
         let mut result = Vec::new();
         for block_number in from..=to {
             if let Some(block_hash) = self.shared.block_hash(block_number) {
@@ -89,6 +156,7 @@ impl<CI: ChainIndex + 'static> ChainRpc for ChainRpcImpl<CI> {
                                 },
                                 capacity: output.capacity,
                                 lock: output.lock.clone(),
+                                address: Address::new(network, output.lock.clone()).to_string(),
                             });
                         }
                     }
@@ -105,4 +173,19 @@ impl<CI: ChainIndex + 'static> ChainRpc for ChainRpcImpl<CI> {
     fn get_tip_block_number(&self) -> Result<BlockNumber> {
         Ok(self.shared.chain_state().read().tip_number())
     }
+
+    fn get_blockchain_info(&self) -> Result<BlockchainInfo> {
+        let stats = self.chain.stats();
+        Ok(BlockchainInfo {
+            tip_header: (&stats.tip_header).into(),
+            total_difficulty: stats.total_difficulty,
+            total_transactions_count: stats.total_transactions_count,
+            live_cells_count: stats.live_cells_count,
+            average_block_intervals: stats
+                .average_block_intervals
+                .into_iter()
+                .map(|(blocks, average)| BlockInterval { blocks, average })
+                .collect(),
+        })
+    }
 }
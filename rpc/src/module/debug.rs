@@ -0,0 +1,68 @@
+use ckb_profiler::Kind;
+use jsonrpc_core::{Error, Result};
+use jsonrpc_macros::build_rpc_trait;
+use log::info;
+
+build_rpc_trait! {
+    pub trait DebugRpc {
+        // Starts a `cpu` or `heap` profile of the scopes the node opens
+        // curl -d '{"id": 2, "jsonrpc": "2.0", "method":"start_profile","params": ["cpu"]}' -H 'content-type:application/json' 'http://localhost:8114'
+        #[rpc(name = "start_profile")]
+        fn start_profile(&self, _kind: String) -> Result<()>;
+
+        // Stops the profile and returns its folded stacks
+        // curl -d '{"id": 2, "jsonrpc": "2.0", "method":"stop_profile","params": []}' -H 'content-type:application/json' 'http://localhost:8114'
+        #[rpc(name = "stop_profile")]
+        fn stop_profile(&self) -> Result<String>;
+    }
+}
+
+pub(crate) struct DebugRpcImpl;
+
+impl DebugRpc for DebugRpcImpl {
+    fn start_profile(&self, kind: String) -> Result<()> {
+        let kind = kind.parse::<Kind>().map_err(Error::invalid_params)?;
+        ckb_profiler::start(kind).map_err(Error::invalid_params)?;
+        info!(target: "rpc", "{:?} profile started", kind);
+        Ok(())
+    }
+
+    // The profile is sent back rather than written to a file, a request tells
+    // no path of the node
+    fn stop_profile(&self) -> Result<String> {
+        let profile = ckb_profiler::stop().map_err(Error::invalid_params)?;
+        let mut folded = Vec::new();
+        profile.write_folded(&mut folded).expect("write to a vector");
+        info!(
+            target: "rpc",
+            "{:?} profile of {:?} stopped, {} stacks",
+            profile.kind,
+            profile.duration,
+            profile.stacks().len()
+        );
+        Ok(String::from_utf8(folded).expect("utf-8 scope names"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stop_profile_returns_folded_stacks() {
+        let rpc = DebugRpcImpl;
+        assert!(rpc.stop_profile().is_err());
+        assert!(rpc.start_profile("wall".to_owned()).is_err());
+
+        rpc.start_profile("cpu".to_owned()).unwrap();
+        {
+            let _scope = ckb_profiler::scope("rpc_test");
+        }
+        let folded = rpc.stop_profile().unwrap();
+        assert!(
+            folded.lines().any(|line| line.contains(";rpc_test ")),
+            "{}",
+            folded
+        );
+    }
+}
@@ -0,0 +1,46 @@
+use ckb_chain::chain::ChainController;
+use ckb_miner::{seal_block, BlockAssemblerController};
+use jsonrpc_core::{Error, Result};
+use jsonrpc_macros::build_rpc_trait;
+use log::info;
+use numext_fixed_hash::H256;
+
+build_rpc_trait! {
+    pub trait DevRpc {
+        // Seals the current block template right away and processes it, for
+        // chain specs whose pow engine accepts any seal, such as Dummy
+        #[rpc(name = "generate_block")]
+        fn generate_block(&self) -> Result<H256>;
+
+        // Freezes the clock of the node at `timestamp`, in milliseconds
+        #[rpc(name = "set_faketime")]
+        fn set_faketime(&self, _timestamp: u64) -> Result<()>;
+    }
+}
+
+pub(crate) struct DevRpcImpl {
+    pub block_assembler: BlockAssemblerController,
+    pub chain: ChainController,
+}
+
+impl DevRpc for DevRpcImpl {
+    fn generate_block(&self) -> Result<H256> {
+        let template = self
+            .block_assembler
+            .get_block_template(None, None, None)
+            .map_err(|_| Error::internal_error())?;
+        let block = seal_block(template, &self.chain).map_err(|err| {
+            let mut error = Error::internal_error();
+            error.message = err;
+            error
+        })?;
+        info!(target: "rpc", "generated block {}", block.header().number());
+        Ok(block.header().hash())
+    }
+
+    fn set_faketime(&self, timestamp: u64) -> Result<()> {
+        ckb_time::freeze(timestamp);
+        info!(target: "rpc", "froze the clock at {}", timestamp);
+        Ok(())
+    }
+}
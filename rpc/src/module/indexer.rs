@@ -0,0 +1,147 @@
+use ckb_core::address::{Address, AddressNetwork};
+use ckb_core::BlockNumber;
+use ckb_db::kvdb::KeyValueDB;
+use ckb_indexer::IndexerController;
+use jsonrpc_core::{Error, Result};
+use jsonrpc_macros::build_rpc_trait;
+use jsonrpc_types::{
+    Balance, CellOutputWithOutPoint, LockHashIndexState, TransactionPoint, WatchedAccount,
+};
+use numext_fixed_hash::H256;
+
+const MAX_PER_PAGE: u64 = 100;
+
+build_rpc_trait! {
+    pub trait IndexerRpc {
+        // Live cells of the lock created in the blocks `from` to `to`, in
+        // the order they were indexed
+        #[rpc(name = "get_cells_by_lock_hash")]
+        fn get_cells_by_lock_hash(
+            &self,
+            _lock_hash: H256,
+            _from: BlockNumber,
+            _to: BlockNumber
+        ) -> Result<Vec<CellOutputWithOutPoint>>;
+
+        // Transactions creating or spending cells of the lock, oldest first,
+        // `per_page` of them from the page `page`, counted from 0
+        #[rpc(name = "get_transactions_by_lock_hash")]
+        fn get_transactions_by_lock_hash(
+            &self,
+            _lock_hash: H256,
+            _page: u64,
+            _per_page: u64
+        ) -> Result<Vec<TransactionPoint>>;
+
+        #[rpc(name = "get_lock_hash_index_states")]
+        fn get_lock_hash_index_states(&self) -> Result<Option<LockHashIndexState>>;
+
+        // Watches the lock, whose balance is then kept up to date. Returns
+        // false when it is already watched.
+        #[rpc(name = "watch_lock_hash")]
+        fn watch_lock_hash(&self, _lock_hash: H256) -> Result<bool>;
+
+        #[rpc(name = "unwatch_lock_hash")]
+        fn unwatch_lock_hash(&self, _lock_hash: H256) -> Result<bool>;
+
+        // Null when the lock is not watched
+        #[rpc(name = "get_balance")]
+        fn get_balance(&self, _lock_hash: H256) -> Result<Option<Balance>>;
+
+        #[rpc(name = "get_watched_accounts")]
+        fn get_watched_accounts(&self) -> Result<Vec<WatchedAccount>>;
+    }
+}
+
+pub(crate) struct IndexerRpcImpl<T: KeyValueDB> {
+    pub indexer: IndexerController<T>,
+    pub network: AddressNetwork,
+}
+
+impl<T: KeyValueDB + 'static> IndexerRpc for IndexerRpcImpl<T> {
+    fn get_cells_by_lock_hash(
+        &self,
+        lock_hash: H256,
+        from: BlockNumber,
+        to: BlockNumber,
+    ) -> Result<Vec<CellOutputWithOutPoint>> {
+        Ok(self
+            .indexer
+            .get_live_cells_in(&lock_hash, from, to)
+            .into_iter()
+            .map(|cell| CellOutputWithOutPoint {
+                out_point: cell.out_point.into(),
+                capacity: cell.output.capacity,
+                address: Address::new(self.network, cell.output.lock.clone()).to_string(),
+                lock: cell.output.lock,
+            })
+            .collect())
+    }
+
+    fn get_transactions_by_lock_hash(
+        &self,
+        lock_hash: H256,
+        page: u64,
+        per_page: u64,
+    ) -> Result<Vec<TransactionPoint>> {
+        if per_page > MAX_PER_PAGE {
+            return Err(Error::invalid_params(format!(
+                "per_page should be at most {}",
+                MAX_PER_PAGE
+            )));
+        }
+        Ok(self
+            .indexer
+            .get_transactions_page(
+                &lock_hash,
+                page.saturating_mul(per_page) as usize,
+                per_page as usize,
+            )
+            .into_iter()
+            .map(|point| TransactionPoint {
+                tx_hash: point.tx_hash,
+                block_number: point.block_number,
+                index: point.index as u32,
+            })
+            .collect())
+    }
+
+    fn get_lock_hash_index_states(&self) -> Result<Option<LockHashIndexState>> {
+        Ok(self
+            .indexer
+            .tip()
+            .map(|(block_number, block_hash)| LockHashIndexState {
+                block_number,
+                block_hash,
+            }))
+    }
+
+    fn watch_lock_hash(&self, lock_hash: H256) -> Result<bool> {
+        Ok(self.indexer.watch(&lock_hash))
+    }
+
+    fn unwatch_lock_hash(&self, lock_hash: H256) -> Result<bool> {
+        Ok(self.indexer.unwatch(&lock_hash))
+    }
+
+    fn get_balance(&self, lock_hash: H256) -> Result<Option<Balance>> {
+        Ok(self.indexer.get_balance(&lock_hash).map(|balance| Balance {
+            capacity: balance.capacity,
+            cells_count: balance.cells_count,
+        }))
+    }
+
+    fn get_watched_accounts(&self) -> Result<Vec<WatchedAccount>> {
+        Ok(self
+            .indexer
+            .get_watched()
+            .into_iter()
+            .map(|(lock_hash, balance)| WatchedAccount {
+                address: Address::new(self.network, lock_hash.clone()).to_string(),
+                lock_hash,
+                capacity: balance.capacity,
+                cells_count: balance.cells_count,
+            })
+            .collect())
+    }
+}
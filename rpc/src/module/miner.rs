@@ -1,19 +1,25 @@
 use ckb_chain::chain::ChainController;
+use ckb_chain::error::ProcessBlockError;
 use ckb_core::block::Block as CoreBlock;
 use ckb_miner::BlockAssemblerController;
 use ckb_network::NetworkService;
 use ckb_protocol::RelayMessage;
 use ckb_shared::{index::ChainIndex, shared::Shared};
 use ckb_sync::RELAY_PROTOCOL_ID;
+use ckb_verification::Error as VerifyError;
 use flatbuffers::FlatBufferBuilder;
-use jsonrpc_core::{Error, Result};
+use jsonrpc_core::{Error, ErrorCode, Result, Value};
 use jsonrpc_macros::build_rpc_trait;
 use jsonrpc_types::{Block, BlockTemplate};
 use log::debug;
 use numext_fixed_hash::H256;
+use serde_json::json;
 use std::collections::HashSet;
 use std::sync::Arc;
 
+const BLOCK_REJECTED: i64 = -32030;
+const MINING_PAUSED: i64 = -32031;
+
 build_rpc_trait! {
     pub trait MinerRpc {
         // curl -d '{"id": 2, "jsonrpc": "2.0", "method":"get_block_template","params": ["0x1b1c832d02fdb4339f9868c8a8636c3d9dd10bd53ac7ce99595825bd6beeffb3", 1000, 1000]}' -H 'content-type:application/json' 'http://localhost:8114'
@@ -40,28 +46,71 @@ impl<CI: ChainIndex + 'static> MinerRpc for MinerRpcImpl<CI> {
         bytes_limit: Option<u64>,
         max_version: Option<u32>,
     ) -> Result<BlockTemplate> {
+        if self.block_assembler.is_paused() {
+            return Err(Error {
+                code: ErrorCode::ServerError(MINING_PAUSED),
+                message: "mining is paused".to_owned(),
+                data: None,
+            });
+        }
         self.block_assembler
             .get_block_template(cycles_limit, bytes_limit, max_version)
-            .map_err(|_| Error::internal_error())
+            .map_err(|err| {
+                let mut error = Error::internal_error();
+                error.message = format!("get_block_template failed: {:?}", err);
+                error
+            })
     }
 
     fn submit_block(&self, _work_id: String, data: Block) -> Result<H256> {
         let block: Arc<CoreBlock> = Arc::new(data.into());
         let ret = self.chain.process_block(Arc::clone(&block));
-        if ret.is_ok() {
-            // announce new block
-            self.network.with_protocol_context(RELAY_PROTOCOL_ID, |nc| {
-                let fbb = &mut FlatBufferBuilder::new();
-                let message = RelayMessage::build_compact_block(fbb, &block, &HashSet::new());
-                fbb.finish(message, None);
-                for peer in nc.connected_peers() {
-                    let _ = nc.send(peer, fbb.finished_data().to_vec());
-                }
-            });
-            Ok(block.header().hash().clone())
-        } else {
-            debug!(target: "rpc", "submit_block process_block {:?}", ret);
-            Err(Error::internal_error())
-        }
+        debug!(target: "rpc", "submit_block process_block {:?}", ret);
+        ret.map_err(rejection)?;
+
+        // announce new block
+        self.network.with_protocol_context(RELAY_PROTOCOL_ID, |nc| {
+            let fbb = &mut FlatBufferBuilder::new();
+            let message = RelayMessage::build_compact_block(fbb, &block, &HashSet::new());
+            fbb.finish(message, None);
+            for peer in nc.connected_peers() {
+                let _ = nc.send(peer, fbb.finished_data().to_vec());
+            }
+        });
+        Ok(block.header().hash().clone())
     }
 }
+
+// As for the rejected transactions, the data tells the failed rule in a
+// machine readable way
+fn rejection(error: ProcessBlockError) -> Error {
+    let message = format!("block rejected: {:?}", error);
+    let (reason, detail) = match error {
+        ProcessBlockError::Verification(error) => match error {
+            VerifyError::Pow(error) => ("invalid_pow", debug_value(error)),
+            VerifyError::Timestamp(error) => ("invalid_timestamp", debug_value(error)),
+            VerifyError::Number(error) => ("invalid_number", debug_value(error)),
+            VerifyError::Difficulty(error) => ("invalid_difficulty", debug_value(error)),
+            VerifyError::Transactions((index, error)) => (
+                "invalid_transaction",
+                json!({ "index": index, "error": format!("{:?}", error) }),
+            ),
+            VerifyError::UnknownParent(hash) => ("unknown_parent", json!(hash)),
+            VerifyError::Uncles(error) => ("invalid_uncles", debug_value(error)),
+            VerifyError::Cellbase(error) => ("invalid_cellbase", debug_value(error)),
+            VerifyError::Commit(error) => ("invalid_commit", debug_value(error)),
+            VerifyError::ExceededMaximumCycles => ("exceeded_maximum_cycles", Value::Null),
+            error => ("invalid_block", debug_value(error)),
+        },
+        ProcessBlockError::Shared(error) => ("internal", debug_value(error)),
+    };
+    Error {
+        code: ErrorCode::ServerError(BLOCK_REJECTED),
+        message,
+        data: Some(json!({ "reason": reason, "detail": detail })),
+    }
+}
+
+fn debug_value<T: std::fmt::Debug>(value: T) -> Value {
+    json!(format!("{:?}", value))
+}
@@ -1,13 +1,24 @@
+mod admin;
 mod chain;
+mod debug;
+mod dev;
+mod indexer;
 mod miner;
 mod net;
 mod pool;
+mod subscription;
 mod test;
 mod trace;
 
+pub(crate) use self::admin::{AdminRpc, AdminRpcImpl};
+pub use self::admin::{RuntimeConfig, RuntimeOverrides};
 pub(crate) use self::chain::{ChainRpc, ChainRpcImpl};
+pub(crate) use self::debug::{DebugRpc, DebugRpcImpl};
+pub(crate) use self::dev::{DevRpc, DevRpcImpl};
+pub(crate) use self::indexer::{IndexerRpc, IndexerRpcImpl};
 pub(crate) use self::miner::{MinerRpc, MinerRpcImpl};
 pub(crate) use self::net::{NetworkRpc, NetworkRpcImpl};
 pub(crate) use self::pool::{PoolRpc, PoolRpcImpl};
+pub(crate) use self::subscription::{SubscriptionRpc, SubscriptionRpcImpl};
 pub(crate) use self::test::{IntegrationTestRpc, IntegrationTestRpcImpl};
 pub(crate) use self::trace::{TraceRpc, TraceRpcImpl};
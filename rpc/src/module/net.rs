@@ -1,8 +1,8 @@
-use build_info::{get_version, Version};
+use build_info::Version;
 use ckb_network::NetworkService;
 use jsonrpc_core::Result;
 use jsonrpc_macros::build_rpc_trait;
-use jsonrpc_types::{LocalNode, NodeAddress};
+use jsonrpc_types::{LocalNode, Node, NodeAddress};
 use std::sync::Arc;
 
 const MAX_ADDRS: usize = 50;
@@ -12,17 +12,25 @@ build_rpc_trait! {
         // curl -d '{"id": 2, "jsonrpc": "2.0", "method":"local_node_info","params": []}' -H 'content-type:application/json' 'http://localhost:8114'
         #[rpc(name = "local_node_info")]
         fn local_node_info(&self) -> Result<LocalNode>;
+
+        // curl -d '{"id": 2, "jsonrpc": "2.0", "method":"get_peers","params": []}' -H 'content-type:application/json' 'http://localhost:8114'
+        #[rpc(name = "get_peers")]
+        fn get_peers(&self) -> Result<Vec<Node>>;
     }
 }
 
 pub(crate) struct NetworkRpcImpl {
     pub network: Arc<NetworkService>,
+    pub version: Version,
 }
 
 impl NetworkRpc for NetworkRpcImpl {
     fn local_node_info(&self) -> Result<LocalNode> {
         Ok(LocalNode {
-            version: get_version!().to_string(),
+            version: self.version.to_string(),
+            commit_hash: self.version.commit_hash.clone(),
+            build_time: self.version.build_time.clone(),
+            features: self.version.features.clone(),
             node_id: self.network.node_id(),
             addresses: self
                 .network
@@ -32,4 +40,19 @@ impl NetworkRpc for NetworkRpcImpl {
                 .collect(),
         })
     }
+
+    fn get_peers(&self) -> Result<Vec<Node>> {
+        Ok(self
+            .network
+            .peers()
+            .into_iter()
+            .map(|peer| Node {
+                node_id: peer.peer_id.to_base58(),
+                address: peer.connected_addr.to_string(),
+                is_outbound: peer.is_outbound(),
+                version: peer.identify_info.map(|info| info.client_version),
+                last_ping_time: peer.last_ping_time,
+            })
+            .collect())
+    }
 }
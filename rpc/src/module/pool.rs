@@ -1,35 +1,58 @@
-use ckb_core::transaction::Transaction as CoreTransaction;
+use ckb_core::transaction::{ProposalShortId, Transaction as CoreTransaction};
+use ckb_core::{BlockNumber, Capacity, Cycle};
 use ckb_network::NetworkService;
-use ckb_pool::txs_pool::TransactionPoolController;
+use ckb_pool::txs_pool::{PoolError, TransactionPoolController};
 use ckb_protocol::RelayMessage;
 use ckb_sync::RELAY_PROTOCOL_ID;
+use ckb_verification::{CellIndex, TransactionError};
 use flatbuffers::FlatBufferBuilder;
-use jsonrpc_core::Result;
+use jsonrpc_core::{Error, ErrorCode, Result, Value};
 use jsonrpc_macros::build_rpc_trait;
-use jsonrpc_types::Transaction;
+use jsonrpc_types::{DryRunResult, Transaction, TxPoolInfo};
 use log::debug;
 use numext_fixed_hash::H256;
+use serde_json::json;
 use std::sync::Arc;
 
+const TRANSACTION_REJECTED: i64 = -32010;
+
 build_rpc_trait! {
     pub trait PoolRpc {
         // curl -d '{"id": 2, "jsonrpc": "2.0", "method":"send_transaction","params": [{"version":2, "deps":[], "inputs":[], "outputs":[]}]}' -H 'content-type:application/json' 'http://localhost:8114'
         #[rpc(name = "send_transaction")]
         fn send_transaction(&self, _tx: Transaction) -> Result<H256>;
+
+        // curl -d '{"id": 2, "jsonrpc": "2.0", "method":"tx_pool_info","params": []}' -H 'content-type:application/json' 'http://localhost:8114'
+        #[rpc(name = "tx_pool_info")]
+        fn tx_pool_info(&self) -> Result<TxPoolInfo>;
+
+        // curl -d '{"id": 2, "jsonrpc": "2.0", "method":"get_pool_transaction","params": ["0xa093b2e820f3f2202a6802314ece2ee8c4e3a8ba2be5bc5e1e1e4f0dbb2e4a43"]}' -H 'content-type:application/json' 'http://localhost:8114'
+        #[rpc(name = "get_pool_transaction")]
+        fn get_pool_transaction(&self, _hash: H256) -> Result<Option<Transaction>>;
+
+        // curl -d '{"id": 2, "jsonrpc": "2.0", "method":"estimate_fee","params": [3]}' -H 'content-type:application/json' 'http://localhost:8114'
+        #[rpc(name = "estimate_fee")]
+        fn estimate_fee(&self, _expect_confirm_blocks: BlockNumber) -> Result<Capacity>;
+
+        // curl -d '{"id": 2, "jsonrpc": "2.0", "method":"dry_run_transaction","params": [{"version":2, "deps":[], "inputs":[], "outputs":[]}]}' -H 'content-type:application/json' 'http://localhost:8114'
+        #[rpc(name = "dry_run_transaction")]
+        fn dry_run_transaction(&self, _tx: Transaction) -> Result<DryRunResult>;
     }
 }
 
 pub(crate) struct PoolRpcImpl {
     pub network: Arc<NetworkService>,
     pub tx_pool: TransactionPoolController,
+    pub dry_run_max_cycles: Option<Cycle>,
 }
 
 impl PoolRpc for PoolRpcImpl {
     fn send_transaction(&self, tx: Transaction) -> Result<H256> {
         let tx: CoreTransaction = tx.into();
         let tx_hash = tx.hash().clone();
-        let pool_result = self.tx_pool.add_transaction(tx.clone());
+        let pool_result = self.tx_pool.add_local_transaction(tx.clone());
         debug!(target: "rpc", "send_transaction add to pool result: {:?}", pool_result);
+        pool_result.map_err(rejection)?;
 
         let fbb = &mut FlatBufferBuilder::new();
         let message = RelayMessage::build_transaction(fbb, &tx);
@@ -43,4 +66,235 @@ impl PoolRpc for PoolRpcImpl {
         });
         Ok(tx_hash)
     }
+
+    fn tx_pool_info(&self) -> Result<TxPoolInfo> {
+        let info = self.tx_pool.get_info();
+        Ok(TxPoolInfo {
+            pending: info.pending as u32,
+            proposed: info.proposed as u32,
+            mineable: info.mineable as u32,
+            orphan: info.orphan as u32,
+            conflict: info.conflict as u32,
+            last_txs_updated_at: info.last_txs_updated_at,
+        })
+    }
+
+    fn get_pool_transaction(&self, hash: H256) -> Result<Option<Transaction>> {
+        // Short ids may collide, the hash tells the one asked for
+        let id = ProposalShortId::from_h256(&hash);
+        Ok(self
+            .tx_pool
+            .get_transaction(id)
+            .filter(|tx| tx.hash() == hash)
+            .map(|tx| (&tx).into()))
+    }
+
+    fn estimate_fee(&self, expect_confirm_blocks: BlockNumber) -> Result<Capacity> {
+        Ok(self.tx_pool.estimate_fee_rate(expect_confirm_blocks))
+    }
+
+    fn dry_run_transaction(&self, tx: Transaction) -> Result<DryRunResult> {
+        let cycles = self
+            .tx_pool
+            .dry_run_transaction(tx.into(), self.dry_run_max_cycles)
+            .map_err(rejection)?;
+        Ok(DryRunResult { cycles })
+    }
+}
+
+// The data of the error tells the reason in a machine readable way, with the
+// input or dep at fault when there is one
+fn rejection(error: PoolError) -> Error {
+    let message = format!("transaction rejected: {:?}", error);
+    let (reason, detail) = match error {
+        PoolError::LowFeeRate => ("insufficient_fee", Value::Null),
+        PoolError::DoubleSpent => ("double_spent", Value::Null),
+        PoolError::InvalidTx(TransactionError::DoubleSpent(index)) => {
+            ("double_spent", cell_index(index))
+        }
+        PoolError::InvalidTx(TransactionError::UnknownInput(index)) => {
+            ("unknown_input", cell_index(index))
+        }
+        PoolError::InvalidTx(TransactionError::InvalidScript(index)) => {
+            ("invalid_script", json!({ "input": index }))
+        }
+        PoolError::InvalidTx(TransactionError::ScriptFailure(error, cycles)) => (
+            "script_failure",
+            json!({ "error": format!("{:?}", error), "cycles": cycles }),
+        ),
+        PoolError::InvalidTx(error) => ("invalid_transaction", json!(format!("{:?}", error))),
+        PoolError::AlreadyInPool => ("already_in_pool", Value::Null),
+        PoolError::OverCapacity => ("pool_full", Value::Null),
+        error => ("rejected", json!(format!("{:?}", error))),
+    };
+    Error {
+        code: ErrorCode::ServerError(TRANSACTION_REJECTED),
+        message,
+        data: Some(json!({ "reason": reason, "detail": detail })),
+    }
+}
+
+fn cell_index(index: CellIndex) -> Value {
+    match index {
+        CellIndex::Input(index) => json!({ "input": index }),
+        CellIndex::Dep(index) => json!({ "dep": index }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ckb_chain::chain::{ChainBuilder, ChainController};
+    use ckb_core::block::Block;
+    use ckb_core::generator::{Generator, GeneratorConfig};
+    use ckb_core::script::Script;
+    use ckb_core::transaction::{CellInput, OutPoint, TransactionBuilder};
+    use ckb_db::memorydb::MemoryKeyValueDB;
+    use ckb_notify::NotifyService;
+    use ckb_pool::txs_pool::{PoolConfig, TransactionPoolService};
+    use ckb_shared::shared::{ChainProvider, Shared, SharedBuilder};
+    use ckb_shared::store::ChainKVStore;
+
+    struct TestNode {
+        chain: ChainController,
+        shared: Shared<ChainKVStore<MemoryKeyValueDB>>,
+        tx_pool: TransactionPoolController,
+        generator: Generator,
+    }
+
+    impl TestNode {
+        // The generator owns the outputs of the first block
+        fn new(config: PoolConfig) -> TestNode {
+            let notify = NotifyService::default().start::<&str>(None);
+            let shared = SharedBuilder::<ChainKVStore<MemoryKeyValueDB>>::new_memory().build();
+            let chain = ChainBuilder::new(shared.clone(), notify.clone())
+                .verification(false)
+                .build()
+                .start::<&str>(None);
+            let tx_pool =
+                TransactionPoolService::new(config, shared.clone(), notify).start::<&str>(None);
+            let generator_config = GeneratorConfig {
+                txs: 0,
+                ..Default::default()
+            };
+            let mut node = TestNode {
+                chain,
+                shared,
+                tx_pool,
+                generator: Generator::new(generator_config, 1),
+            };
+            node.mine();
+            node
+        }
+
+        fn mine(&mut self) -> Arc<Block> {
+            let tip = self.shared.chain_state().read().tip_header().clone();
+            let block = Arc::new(self.generator.block(&tip, 10_000_000));
+            self.chain.process_block(Arc::clone(&block)).unwrap();
+            block
+        }
+
+        fn submit(&self, tx: CoreTransaction) -> Result<()> {
+            self.tx_pool
+                .add_local_transaction(tx)
+                .map(|_| ())
+                .map_err(rejection)
+        }
+    }
+
+    fn reason(error: &Error) -> Value {
+        assert_eq!(error.code, ErrorCode::ServerError(TRANSACTION_REJECTED));
+        error.data.as_ref().expect("rejection data")["reason"].clone()
+    }
+
+    fn detail(error: &Error) -> Value {
+        error.data.as_ref().expect("rejection data")["detail"].clone()
+    }
+
+    #[test]
+    fn test_accepted_and_already_in_pool() {
+        let mut node = TestNode::new(PoolConfig::default());
+        let parent = node.generator.transaction().expect("funded");
+        // Spends an output of the pending parent
+        let child = node.generator.transaction().expect("funded");
+        assert_eq!(node.submit(parent.clone()), Ok(()));
+        assert_eq!(node.submit(child), Ok(()));
+
+        let error = node.submit(parent).unwrap_err();
+        assert_eq!(reason(&error), json!("already_in_pool"));
+    }
+
+    #[test]
+    fn test_unknown_input() {
+        let mut node = TestNode::new(PoolConfig::default());
+        let funded = node.generator.transaction().expect("funded");
+        let tx = TransactionBuilder::default()
+            .transaction(funded)
+            .input(CellInput::new(OutPoint::new(H256::zero(), 0), Script::default()))
+            .build();
+
+        let error = node.submit(tx).unwrap_err();
+        assert_eq!(reason(&error), json!("unknown_input"));
+        assert_eq!(detail(&error), json!({ "input": 1 }));
+    }
+
+    #[test]
+    fn test_script_failure() {
+        let mut node = TestNode::new(PoolConfig::default());
+        node.generator.config_mut().valid_signatures = false;
+        let tx = node.generator.transaction().expect("funded");
+
+        let error = node.submit(tx).unwrap_err();
+        assert_eq!(reason(&error), json!("script_failure"));
+        // The signature check ran before the failure
+        assert!(detail(&error)["cycles"].as_u64().expect("cycles") > 0);
+    }
+
+    #[test]
+    fn test_double_spent() {
+        let mut node = TestNode::new(PoolConfig::default());
+        node.generator.config_mut().txs = 1;
+        let block = node.mine();
+        let spent = &block.commit_transactions()[1].inputs()[0];
+        let funded = node.generator.transaction().expect("funded");
+        let tx = TransactionBuilder::default()
+            .transaction(funded)
+            .input(spent.clone())
+            .build();
+
+        let error = node.submit(tx).unwrap_err();
+        assert_eq!(reason(&error), json!("double_spent"));
+        assert_eq!(detail(&error), json!({ "input": 1 }));
+    }
+
+    #[test]
+    fn test_dry_run_cycle_cap() {
+        let mut node = TestNode::new(PoolConfig::default());
+        let tx = node.generator.transaction().expect("funded");
+
+        let cycles = node.tx_pool.dry_run_transaction(tx.clone(), None).unwrap();
+        assert!(cycles > 1);
+        let capped = node.tx_pool.dry_run_transaction(tx.clone(), Some(cycles));
+        assert_eq!(capped.unwrap(), cycles);
+        let error = node
+            .tx_pool
+            .dry_run_transaction(tx, Some(cycles - 1))
+            .map_err(rejection)
+            .unwrap_err();
+        assert_eq!(reason(&error), json!("script_failure"));
+    }
+
+    #[test]
+    fn test_insufficient_fee() {
+        // Without a local budget the fee rate of relayed transactions applies
+        let mut node = TestNode::new(PoolConfig {
+            min_fee_rate: 1000,
+            max_local_size: 0,
+            ..Default::default()
+        });
+        let tx = node.generator.transaction().expect("funded");
+
+        let error = node.submit(tx).unwrap_err();
+        assert_eq!(reason(&error), json!("insufficient_fee"));
+    }
 }
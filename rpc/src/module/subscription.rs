@@ -0,0 +1,615 @@
+use ckb_core::block::Block as CoreBlock;
+use ckb_core::transaction::{OutPoint, ProposalShortId, Transaction};
+use ckb_core::BlockNumber;
+use ckb_db::kvdb::KeyValueDB;
+use ckb_indexer::IndexerController;
+use ckb_notify::{
+    Event, EventKind, NotifyController, Overflow, PoolEvent, SubscriptionConfig,
+};
+use ckb_pool::txs_pool::TransactionPoolController;
+use ckb_util::Mutex;
+use crossbeam_channel::{self, Sender, TrySendError};
+use futures::Future;
+use jsonrpc_core::{Error, ErrorCode, Result, Value};
+use jsonrpc_macros::{build_rpc_trait, pubsub};
+use jsonrpc_pubsub::{Session, SubscriptionId};
+use jsonrpc_types::{Block, LockHashTransaction};
+use log::{debug, error, warn};
+use numext_fixed_hash::H256;
+use serde_derive::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::sync::Arc;
+use std::thread;
+
+const SUBSCRIPTION_SUBSCRIBER: &str = "rpc_subscription";
+const NOTIFY_CAPACITY: usize = 1024;
+// Notifications waiting for the connection of a subscription, which is
+// dropped once they are too many
+const PENDING_NOTIFICATIONS: usize = 256;
+// The cells spent by the blocks deeper than this below the tip are forgotten, a fork
+// switch detaching them does not bring the cells back
+const SPENT_KEPT_BLOCKS: BlockNumber = 100;
+// Each subscription has its own notifier thread
+const MAX_SUBSCRIPTIONS: usize = 1024;
+const MAX_CONNECTION_SUBSCRIPTIONS: usize = 32;
+const SUBSCRIPTION_REJECTED: i64 = -32040;
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Topic {
+    NewTipBlock,
+    NewTransaction,
+    /// Transactions spending or creating cells of the lock hash, given as
+    /// `{"lock_hash": "0x..."}`
+    LockHash(H256),
+    /// Transactions spending or creating cells of the locks watched by the
+    /// indexer when subscribing
+    WatchedAccounts,
+}
+
+build_rpc_trait! {
+    pub trait SubscriptionRpc {
+        type Metadata;
+
+        #[pubsub(name = "subscribe")] {
+            // Notifications of the topic are sent until unsubscribed or the
+            // connection is closed
+            #[rpc(name = "subscribe")]
+            fn subscribe(&self, Self::Metadata, pubsub::Subscriber<Value>, Topic);
+
+            #[rpc(name = "unsubscribe")]
+            fn unsubscribe(&self, SubscriptionId) -> Result<bool>;
+        }
+    }
+}
+
+struct Subscription {
+    topic: Topic,
+    // The session of the connection, by address, 0 without one
+    connection: usize,
+    // To the thread notifying the connection, so that a slow one stalls
+    // neither the publisher nor the other subscriptions
+    notifier: Sender<Value>,
+    watch: Watch,
+}
+
+/// The locks of a lock hash or watched accounts topic and their live cells,
+/// from the indexer when subscribing and then followed from the notified
+/// transactions
+#[derive(Default, Debug)]
+struct Watch {
+    locks: HashSet<H256>,
+    cells: HashSet<OutPoint>,
+    // The cells spent by committed transactions, by the transaction and its
+    // block number, live again when the block is detached
+    spent: HashMap<OutPoint, (H256, BlockNumber)>,
+}
+
+impl Watch {
+    fn new(locks: HashSet<H256>, cells: HashSet<OutPoint>) -> Watch {
+        Watch {
+            locks,
+            cells,
+            spent: HashMap::new(),
+        }
+    }
+
+    fn is_watching(&self) -> bool {
+        !self.locks.is_empty()
+    }
+
+    // A transaction in the pool, which is notified again once committed
+    fn add_pending(&mut self, tx: &Transaction) -> bool {
+        if !self.is_watching() {
+            return false;
+        }
+        let spends = tx.input_pts().iter().any(|out_point| self.cells.contains(out_point));
+        self.add_outputs(tx) || spends
+    }
+
+    fn commit(&mut self, tx: &Transaction, number: BlockNumber) -> bool {
+        if !self.is_watching() {
+            return false;
+        }
+        let mut affected = false;
+        for out_point in tx.input_pts() {
+            if self.cells.remove(&out_point) {
+                self.spent.insert(out_point, (tx.hash(), number));
+                affected = true;
+            }
+        }
+        self.add_outputs(tx) || affected
+    }
+
+    // A transaction of a detached block: its outputs are gone and the cells it
+    // spent are live again. The transactions of a block are detached in the
+    // reverse order, so that a spent output is restored before it is gone.
+    fn detach(&mut self, tx: &Transaction) -> bool {
+        if !self.is_watching() {
+            return false;
+        }
+        let hash = tx.hash();
+        let mut affected = false;
+        for index in 0..tx.outputs().len() {
+            affected |= self.cells.remove(&OutPoint::new(hash.clone(), index as u32));
+        }
+        for out_point in tx.input_pts() {
+            if self.spent.get(&out_point).map_or(false, |(by, _)| *by == hash) {
+                self.spent.remove(&out_point);
+                self.cells.insert(out_point);
+                affected = true;
+            }
+        }
+        affected
+    }
+
+    fn prune(&mut self, tip: BlockNumber) {
+        self.spent.retain(|_, (_, number)| *number + SPENT_KEPT_BLOCKS >= tip);
+    }
+
+    fn add_outputs(&mut self, tx: &Transaction) -> bool {
+        let mut affected = false;
+        for (index, output) in tx.outputs().iter().enumerate() {
+            if self.locks.contains(&output.lock) {
+                self.cells.insert(OutPoint::new(tx.hash(), index as u32));
+                affected = true;
+            }
+        }
+        affected
+    }
+}
+
+type Subscriptions = Arc<Mutex<HashMap<SubscriptionId, Subscription>>>;
+
+pub(crate) struct SubscriptionRpcImpl<T: KeyValueDB> {
+    subscriptions: Subscriptions,
+    // Sent to the new subscribers of the new tips, behind the subscriptions
+    // lock to never be older than a published one
+    last_tip: Arc<Mutex<Option<Block>>>,
+    indexer: IndexerController<T>,
+}
+
+impl<T: KeyValueDB + 'static> SubscriptionRpcImpl<T> {
+    /// Publishes the new tips and the transactions added to the pool to the
+    /// subscribers, until the notify service stops
+    pub fn new(
+        notify: &NotifyController,
+        tx_pool: TransactionPoolController,
+        indexer: IndexerController<T>,
+    ) -> SubscriptionRpcImpl<T> {
+        // A transaction added to the pool comes before the block committing
+        // it, and a fork switch before its new tip. A stalled connection must
+        // not stall the chain, the oldest events are dropped instead. The
+        // replay tells the tip of a node which started before the RPC.
+        let event_receiver = notify.subscribe_with(
+            SUBSCRIPTION_SUBSCRIBER,
+            &[EventKind::NewTip, EventKind::SwitchFork, EventKind::Pool],
+            SubscriptionConfig {
+                capacity: NOTIFY_CAPACITY,
+                overflow: Overflow::DropOldest,
+                replay: true,
+                ..Default::default()
+            },
+        );
+        let subscriptions = Subscriptions::default();
+        let publisher = Arc::clone(&subscriptions);
+        let last_tip = Arc::new(Mutex::new(None));
+        let publisher_last_tip = Arc::clone(&last_tip);
+        thread::Builder::new()
+            .name("rpc_subscription".to_string())
+            .spawn(move || loop {
+                match event_receiver.recv() {
+                    Ok(Event::NewTip(block)) => {
+                        let mut subscriptions = publisher.lock();
+                        let json_block: Block = block.as_ref().into();
+                        publish(
+                            &mut subscriptions,
+                            |subscription| subscription.topic == Topic::NewTipBlock,
+                            &json_block,
+                        );
+                        *publisher_last_tip.lock() = Some(json_block);
+                        publish_committed(&mut subscriptions, &block);
+                        let tip = block.header().number();
+                        for subscription in subscriptions.values_mut() {
+                            subscription.watch.prune(tip);
+                        }
+                    }
+                    Ok(Event::SwitchFork(fork)) => {
+                        let mut subscriptions = publisher.lock();
+                        for block in &fork.detached {
+                            publish_detached(&mut subscriptions, block);
+                        }
+                        // The last attached block comes as the new tip
+                        let attached = fork.attached.len().saturating_sub(1);
+                        for block in &fork.attached[..attached] {
+                            publish_committed(&mut subscriptions, block);
+                        }
+                    }
+                    Ok(Event::Pool(event)) => {
+                        if let PoolEvent::TxAdded { ref hash, .. } = *event {
+                            publish_pool_transaction(&publisher, &tx_pool, hash);
+                        }
+                    }
+                    Ok(event) => {
+                        error!(target: "rpc", "unexpected event {:?}", event.kind());
+                    }
+                    _ => {
+                        error!(target: "rpc", "channel event_receiver closed");
+                        break;
+                    }
+                }
+            })
+            .expect("Start rpc subscription failed");
+
+        SubscriptionRpcImpl {
+            subscriptions,
+            last_tip,
+            indexer,
+        }
+    }
+}
+
+impl<T: KeyValueDB + 'static> SubscriptionRpc for SubscriptionRpcImpl<T> {
+    type Metadata = Option<Arc<Session>>;
+
+    fn subscribe(
+        &self,
+        meta: Self::Metadata,
+        subscriber: pubsub::Subscriber<Value>,
+        topic: Topic,
+    ) {
+        let connection = meta
+            .as_ref()
+            .map_or(0, |session| &**session as *const Session as usize);
+        let locks: HashSet<_> = match topic {
+            Topic::LockHash(ref lock_hash) => vec![lock_hash.clone()].into_iter().collect(),
+            Topic::WatchedAccounts => self
+                .indexer
+                .get_watched()
+                .into_iter()
+                .map(|(lock_hash, _)| lock_hash)
+                .collect(),
+            _ => HashSet::new(),
+        };
+        let cells = locks
+            .iter()
+            .flat_map(|lock_hash| self.indexer.get_live_cells(lock_hash))
+            .map(|cell| cell.out_point)
+            .collect();
+        let watch = Watch::new(locks, cells);
+
+        let mut subscriptions = self.subscriptions.lock();
+        let of_connection = subscriptions
+            .values()
+            .filter(|subscription| subscription.connection == connection)
+            .count();
+        if subscriptions.len() >= MAX_SUBSCRIPTIONS
+            || of_connection >= MAX_CONNECTION_SUBSCRIPTIONS
+        {
+            let _ = subscriber.reject(rejection("too many subscriptions"));
+            return;
+        }
+        let id = random_id();
+        let notifier = match start_notifier(id.clone(), Arc::clone(&self.subscriptions)) {
+            Ok(notifier) => notifier,
+            Err(err) => {
+                error!(target: "rpc", "start rpc notifier failed: {}", err);
+                let _ = subscriber.reject(rejection("can not notify the subscription"));
+                return;
+            }
+        };
+        let sink = match subscriber.assign_id(id.clone()) {
+            Ok(sink) => sink,
+            Err(()) => return,
+        };
+        debug!(target: "rpc", "subscribe {:?} to {:?}", id, topic);
+        // The notifier thread waits for the sink, then the notifications
+        let _ = notifier.sink.send(sink);
+        if topic == Topic::NewTipBlock {
+            if let Some(ref block) = *self.last_tip.lock() {
+                let value = serde_json::to_value(block).expect("serialize block");
+                // The channel is empty
+                let _ = notifier.notifications.try_send(value);
+            }
+        }
+        // The subscriptions of a closed connection are dropped at once, not
+        // when their next notification fails
+        if of_connection == 0 {
+            if let Some(ref session) = meta {
+                let dropped = Arc::clone(&self.subscriptions);
+                session.on_drop(move || {
+                    dropped
+                        .lock()
+                        .retain(|_, subscription| subscription.connection != connection)
+                });
+            }
+        }
+        let subscription = Subscription {
+            topic,
+            connection,
+            notifier: notifier.notifications,
+            watch,
+        };
+        subscriptions.insert(id, subscription);
+    }
+
+    /// The ids are random, only the connection which subscribed knows them
+    fn unsubscribe(&self, id: SubscriptionId) -> Result<bool> {
+        Ok(self.subscriptions.lock().remove(&id).is_some())
+    }
+}
+
+fn random_id() -> SubscriptionId {
+    let bytes: [u8; 16] = rand::random();
+    let hex: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+    SubscriptionId::String(format!("0x{}", hex))
+}
+
+fn rejection(message: &str) -> Error {
+    Error {
+        code: ErrorCode::ServerError(SUBSCRIPTION_REJECTED),
+        message: message.to_owned(),
+        data: None,
+    }
+}
+
+struct Notifier {
+    sink: Sender<pubsub::Sink<Value>>,
+    notifications: Sender<Value>,
+}
+
+// Notifies the connection of the subscription `id`, once given its sink,
+// until it is dropped, or drops it when the connection is closed
+fn start_notifier(id: SubscriptionId, subscriptions: Subscriptions) -> io::Result<Notifier> {
+    let (sink_sender, sink_receiver) = crossbeam_channel::bounded(1);
+    let (notifier, notifications) = crossbeam_channel::bounded(PENDING_NOTIFICATIONS);
+    thread::Builder::new()
+        .name("rpc_notifier".to_string())
+        .spawn(move || {
+            let sink: pubsub::Sink<Value> = match sink_receiver.recv() {
+                Ok(sink) => sink,
+                Err(_) => return,
+            };
+            for value in notifications {
+                if sink.notify(Ok(value)).wait().is_err() {
+                    subscriptions.lock().remove(&id);
+                    break;
+                }
+            }
+        })?;
+    Ok(Notifier {
+        sink: sink_sender,
+        notifications: notifier,
+    })
+}
+
+fn publish_pool_transaction(
+    subscriptions: &Subscriptions,
+    tx_pool: &TransactionPoolController,
+    hash: &H256,
+) {
+    let watching = {
+        let mut subscriptions = subscriptions.lock();
+        publish(
+            &mut subscriptions,
+            |subscription| subscription.topic == Topic::NewTransaction,
+            hash,
+        );
+        subscriptions
+            .values()
+            .any(|subscription| subscription.watch.is_watching())
+    };
+    // Only the lock hash and watched accounts topics need the transaction
+    // itself
+    if !watching {
+        return;
+    }
+    let tx = match tx_pool.get_transaction(ProposalShortId::from_h256(hash)) {
+        Some(ref tx) if tx.hash() == *hash => tx.clone(),
+        _ => return,
+    };
+    let message = LockHashTransaction {
+        tx_hash: hash.clone(),
+        block_hash: None,
+        detached: false,
+    };
+    publish(
+        &mut subscriptions.lock(),
+        |subscription| subscription.watch.add_pending(&tx),
+        &message,
+    );
+}
+
+fn publish_committed(
+    subscriptions: &mut HashMap<SubscriptionId, Subscription>,
+    block: &CoreBlock,
+) {
+    let block_hash = block.header().hash();
+    let number = block.header().number();
+    for tx in block.commit_transactions() {
+        let message = LockHashTransaction {
+            tx_hash: tx.hash(),
+            block_hash: Some(block_hash.clone()),
+            detached: false,
+        };
+        publish(
+            subscriptions,
+            |subscription| subscription.watch.commit(tx, number),
+            &message,
+        );
+    }
+}
+
+fn publish_detached(
+    subscriptions: &mut HashMap<SubscriptionId, Subscription>,
+    block: &CoreBlock,
+) {
+    let block_hash = block.header().hash();
+    for tx in block.commit_transactions().iter().rev() {
+        let message = LockHashTransaction {
+            tx_hash: tx.hash(),
+            block_hash: Some(block_hash.clone()),
+            detached: true,
+        };
+        publish(
+            subscriptions,
+            |subscription| subscription.watch.detach(tx),
+            &message,
+        );
+    }
+}
+
+// Every subscription is passed to `filter`, the ones whose connection is
+// closed or too slow to take the notification are dropped: they would miss
+// it
+fn publish<F, M>(
+    subscriptions: &mut HashMap<SubscriptionId, Subscription>,
+    mut filter: F,
+    message: &M,
+) where
+    F: FnMut(&mut Subscription) -> bool,
+    M: serde::Serialize,
+{
+    let value = match serde_json::to_value(message) {
+        Ok(value) => value,
+        Err(err) => {
+            error!(target: "rpc", "serialize notification: {}", err);
+            return;
+        }
+    };
+    let closed: Vec<_> = subscriptions
+        .iter_mut()
+        .filter_map(|(id, subscription)| {
+            if !filter(subscription) {
+                return None;
+            }
+            match subscription.notifier.try_send(value.clone()) {
+                Ok(()) => None,
+                Err(TrySendError::Full(_)) => {
+                    warn!(target: "rpc", "drop the subscription {:?} falling behind", id);
+                    Some(id.clone())
+                }
+                Err(TrySendError::Disconnected(_)) => Some(id.clone()),
+            }
+        })
+        .collect();
+    for id in closed {
+        subscriptions.remove(&id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ckb_chain::test_utils::{start_chain, Fork};
+    use ckb_chain_spec::consensus::Consensus;
+    use ckb_core::script::Script;
+    use ckb_core::transaction::{CellInput, CellOutput, TransactionBuilder};
+    use ckb_notify::NotifyService;
+    use crossbeam_channel::Receiver;
+
+    fn subscribe(
+        subscriptions: &mut HashMap<SubscriptionId, Subscription>,
+        id: u64,
+        capacity: usize,
+    ) -> Receiver<Value> {
+        let (notifier, notifications) = crossbeam_channel::bounded(capacity);
+        let subscription = Subscription {
+            topic: Topic::NewTransaction,
+            connection: 0,
+            notifier,
+            watch: Watch::default(),
+        };
+        subscriptions.insert(SubscriptionId::Number(id), subscription);
+        notifications
+    }
+
+    #[test]
+    fn test_publish_without_waiting() {
+        let mut subscriptions = HashMap::new();
+        let fast = subscribe(&mut subscriptions, 0, 2);
+        let _slow = subscribe(&mut subscriptions, 1, 1);
+        let closed = subscribe(&mut subscriptions, 2, 1);
+        drop(closed);
+
+        publish(&mut subscriptions, |_| true, &1);
+        // The closed subscription is dropped, the others took the first one
+        assert_eq!(subscriptions.len(), 2);
+        publish(&mut subscriptions, |_| true, &2);
+        // The slow one, which did not take the first one, is dropped
+        let ids: Vec<_> = subscriptions.keys().cloned().collect();
+        assert_eq!(ids, vec![SubscriptionId::Number(0)]);
+        assert_eq!(fast.try_iter().collect::<Vec<_>>(), vec![json(1), json(2)]);
+
+        publish(&mut subscriptions, |_| false, &3);
+        assert!(fast.try_recv().is_err());
+    }
+
+    fn json(value: u64) -> Value {
+        serde_json::to_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_random_ids() {
+        let ids: HashSet<_> = (0..100).map(|_| random_id()).collect();
+        assert_eq!(ids.len(), 100);
+        assert!(ids.iter().all(|id| match id {
+            SubscriptionId::String(id) => id.len() == 34 && id.starts_with("0x"),
+            _ => false,
+        }));
+    }
+
+    #[test]
+    fn test_watch_follows_fork_switch() {
+        let notify = NotifyService::default().start::<&str>(None);
+        let switch_fork_receiver = notify.subscribe_switch_fork("test");
+        let (chain, shared) = start_chain(Consensus::default(), notify, false);
+        let lock = H256::from_slice(&[1u8; 32]).unwrap();
+        let mut watch = Watch::new(vec![lock.clone()].into_iter().collect(), HashSet::new());
+
+        // The second block spends the cellbase of the first one
+        let mut main = Fork::from_number(&shared, &chain, 0).lock(lock.clone());
+        main.extend(1);
+        let first_cellbase = main.blocks()[0].commit_transactions()[0].clone();
+        let spend = TransactionBuilder::default()
+            .input(CellInput::new(
+                OutPoint::new(first_cellbase.hash(), 0),
+                Script::default(),
+            ))
+            .output(CellOutput::new(1, Vec::new(), H256::zero(), None))
+            .build();
+        main.push(vec![spend.clone()], vec![]);
+        let second_cellbase = main.blocks()[1].commit_transactions()[0].clone();
+        for block in main.blocks() {
+            for tx in block.commit_transactions() {
+                assert!(watch.commit(tx, block.header().number()));
+            }
+        }
+        let live: HashSet<_> = vec![OutPoint::new(second_cellbase.hash(), 0)]
+            .into_iter()
+            .collect();
+        assert_eq!(watch.cells, live);
+
+        let mut fork = Fork::from_number(&shared, &chain, 0);
+        fork.overtake();
+        let fork_switch = switch_fork_receiver.recv().unwrap();
+        let detached: Vec<_> = fork_switch
+            .detached
+            .iter()
+            .flat_map(|block| block.commit_transactions().iter().rev())
+            .filter(|tx| watch.detach(tx))
+            .map(|tx| tx.hash())
+            .collect();
+        assert_eq!(
+            detached,
+            vec![spend.hash(), second_cellbase.hash(), first_cellbase.hash()]
+        );
+        assert!(watch.cells.is_empty());
+        assert!(watch.spent.is_empty());
+        assert!(fork_switch.attached.iter().all(|block| block
+            .commit_transactions()
+            .iter()
+            .all(|tx| !watch.commit(tx, block.header().number()))));
+    }
+}
@@ -1,114 +1,237 @@
-use crate::config::Config;
+use crate::auth::{AuthMiddleware, Credentials, RequestMeta};
+use crate::config::{Config, Module};
+use crate::health::HealthCheck;
+use crate::limit::{CallLimiter, HandshakeLimiter, RequestLimiter};
 use crate::module::{
-    ChainRpc, ChainRpcImpl, IntegrationTestRpc, IntegrationTestRpcImpl, MinerRpc, MinerRpcImpl,
-    NetworkRpc, NetworkRpcImpl, PoolRpc, PoolRpcImpl, TraceRpc, TraceRpcImpl,
+    AdminRpc, AdminRpcImpl, ChainRpc, ChainRpcImpl, DebugRpc, DebugRpcImpl, DevRpc, DevRpcImpl,
+    IndexerRpc, IndexerRpcImpl, IntegrationTestRpc, IntegrationTestRpcImpl, MinerRpc, MinerRpcImpl,
+    NetworkRpc, NetworkRpcImpl, PoolRpc, PoolRpcImpl, RuntimeOverrides, SubscriptionRpc,
+    SubscriptionRpcImpl, TraceRpc, TraceRpcImpl,
 };
+use build_info::Version;
 use ckb_chain::chain::ChainController;
+use ckb_core::address::AddressNetwork;
+use ckb_db::kvdb::KeyValueDB;
+use ckb_indexer::IndexerController;
 use ckb_miner::BlockAssemblerController;
 use ckb_network::NetworkService;
+use ckb_notify::NotifyController;
 use ckb_pool::txs_pool::TransactionPoolController;
 use ckb_pow::Clicker;
 use ckb_shared::index::ChainIndex;
-use ckb_shared::shared::Shared;
+use ckb_shared::shared::{ChainProvider, Shared};
+use ckb_sync::Synchronizer;
 use futures::sync::oneshot;
-use jsonrpc_core::IoHandler;
-use jsonrpc_http_server::{Server, ServerBuilder};
+use jsonrpc_core::{MetaIoHandler, RemoteProcedure};
+use jsonrpc_http_server::{hyper, Server, ServerBuilder};
+use jsonrpc_pubsub::{PubSubHandler, Session};
 use jsonrpc_server_utils::cors::AccessControlAllowOrigin;
-use jsonrpc_server_utils::hosts::DomainsValidation;
+use jsonrpc_server_utils::hosts::{DomainsValidation, Host};
 use log::{error, info};
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+// Methods of the public modules which are costly or change the node, protected as soon as
+// any module is
+const GUARDED_METHODS: &[&str] = &["dry_run_transaction", "clear_tx_pool"];
+// The requests still in flight then are cut off
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(10);
+const DRAIN_INTERVAL: Duration = Duration::from_millis(10);
+
+type Methods = HashMap<String, RemoteProcedure<RequestMeta>>;
 
 pub struct RpcServer {
     server: Option<Server>,
     thread: Option<JoinHandle<()>>,
     closes: Option<Vec<oneshot::Sender<()>>>,
+    ws_listen_address: Option<String>,
+    ws_server: Option<jsonrpc_ws_server::Server>,
+    // Required by the WebSocket handshakes when any module is protected
+    ws_credentials: Option<Credentials>,
+    limiter: RequestLimiter,
+    overrides: RuntimeOverrides,
 }
 
 impl RpcServer {
-    pub fn new<CI: ChainIndex + 'static>(
+    #[allow(clippy::too_many_arguments)]
+    pub fn new<CI: ChainIndex + 'static, T: KeyValueDB + 'static>(
         config: Config,
         network: Arc<NetworkService>,
         shared: Shared<CI>,
         tx_pool: TransactionPoolController,
         chain: ChainController,
         block_assembler: BlockAssemblerController,
+        indexer: IndexerController<T>,
+        synchronizer: Synchronizer<CI>,
         test_engine: Option<Arc<Clicker>>,
+        version: Version,
     ) -> RpcServer
     where
         CI: ChainIndex,
     {
-        let mut io = IoHandler::new();
+        let mut modules: Vec<(Module, Methods)> = Vec::new();
+        let limiter = RequestLimiter::new(
+            config.rate_limit.clone(),
+            config.max_in_flight_requests,
+            config.trusted_proxies.clone().unwrap_or_default(),
+        );
+        let overrides = RuntimeOverrides::default();
 
         if config.chain_enable() {
-            io.extend_with(
-                ChainRpcImpl {
-                    shared: shared.clone(),
-                }
-                .to_delegate(),
-            );
+            let rpc = ChainRpcImpl {
+                shared: shared.clone(),
+                chain: chain.clone(),
+                tx_pool: tx_pool.clone(),
+            };
+            modules.push((Module::Chain, rpc.to_delegate().into()));
         }
 
         if config.pool_enable() {
-            io.extend_with(
-                PoolRpcImpl {
-                    network: Arc::clone(&network),
-                    tx_pool: tx_pool.clone(),
-                }
-                .to_delegate(),
-            );
+            let rpc = PoolRpcImpl {
+                network: Arc::clone(&network),
+                tx_pool: tx_pool.clone(),
+                dry_run_max_cycles: config.dry_run_max_cycles,
+            };
+            modules.push((Module::Pool, rpc.to_delegate().into()));
+        }
+
+        if config.admin_enable() {
+            let rpc = AdminRpcImpl {
+                shared: shared.clone(),
+                network: Arc::clone(&network),
+                tx_pool: tx_pool.clone(),
+                block_assembler: block_assembler.clone(),
+                limiter: limiter.clone(),
+                backup_dir: config.backup_dir.clone(),
+                overrides: overrides.clone(),
+            };
+            modules.push((Module::Admin, rpc.to_delegate().into()));
+        }
+
+        if config.indexer_enable() {
+            let rpc = IndexerRpcImpl {
+                indexer,
+                network: AddressNetwork::from_network_id(shared.consensus().network_id()),
+            };
+            modules.push((Module::Indexer, rpc.to_delegate().into()));
+        }
+
+        if config.dev_enable() {
+            let rpc = DevRpcImpl {
+                block_assembler: block_assembler.clone(),
+                chain: chain.clone(),
+            };
+            modules.push((Module::Dev, rpc.to_delegate().into()));
+        }
+
+        if config.debug_enable() {
+            modules.push((Module::Debug, DebugRpcImpl.to_delegate().into()));
         }
 
         if config.miner_enable() {
-            io.extend_with(
-                MinerRpcImpl {
-                    shared,
-                    block_assembler,
-                    chain,
-                    network: Arc::clone(&network),
-                }
-                .to_delegate(),
-            );
+            let rpc = MinerRpcImpl {
+                shared: shared.clone(),
+                block_assembler,
+                chain,
+                network: Arc::clone(&network),
+            };
+            modules.push((Module::Miner, rpc.to_delegate().into()));
         }
 
         if config.net_enable() {
-            io.extend_with(
-                NetworkRpcImpl {
-                    network: Arc::clone(&network),
-                }
-                .to_delegate(),
-            );
+            let rpc = NetworkRpcImpl {
+                network: Arc::clone(&network),
+                version,
+            };
+            modules.push((Module::Net, rpc.to_delegate().into()));
         }
 
         if config.trace_enable() {
-            io.extend_with(
-                TraceRpcImpl {
-                    network: Arc::clone(&network),
-                    tx_pool,
-                }
-                .to_delegate(),
-            );
+            let rpc = TraceRpcImpl {
+                network: Arc::clone(&network),
+                tx_pool,
+            };
+            modules.push((Module::Trace, rpc.to_delegate().into()));
         }
 
-        if test_engine.is_some() {
-            io.extend_with(
-                IntegrationTestRpcImpl {
-                    network,
-                    test_engine: test_engine.expect("pow engine supply"),
-                }
-                .to_delegate(),
-            );
+        let mut protected: HashSet<String> = modules
+            .iter()
+            .filter(|(module, _)| config.requires_auth(*module))
+            .flat_map(|(_, methods)| methods.keys().cloned())
+            .collect();
+        // The test methods submit blocks and add peers, they are protected
+        // as soon as any module is
+        let test_methods: Option<Methods> = test_engine.map(|test_engine| {
+            IntegrationTestRpcImpl {
+                network: Arc::clone(&network),
+                test_engine,
+            }
+            .to_delegate()
+            .into()
+        });
+        if !protected.is_empty() {
+            protected.extend(GUARDED_METHODS.iter().map(|method| (*method).to_owned()));
+            if let Some(ref methods) = test_methods {
+                protected.extend(methods.keys().cloned());
+            }
+        }
+        let ws_credentials = if protected.is_empty() {
+            None
+        } else {
+            config.auth.as_ref().map(Credentials::new)
+        };
+        let mut io = MetaIoHandler::with_middleware(AuthMiddleware::new(protected));
+        for (_, methods) in modules {
+            io.extend_with(methods);
+        }
+        if let Some(methods) = test_methods {
+            io.extend_with(methods);
         }
 
-        let mut server = ServerBuilder::new(io)
-            .cors(DomainsValidation::AllowOnly(vec![
-                AccessControlAllowOrigin::Null,
-                AccessControlAllowOrigin::Any,
-            ]))
-            .threads(config.threads.unwrap_or_else(num_cpus::get))
-            .max_request_body_size(config.max_request_body_size)
-            .start_http(&config.listen_address.parse().unwrap())
-            .expect("Jsonrpc initialize");
+        let cors = match config.cors_allowed_origins {
+            Some(ref origins) => origins
+                .iter()
+                .map(|origin| AccessControlAllowOrigin::from(origin.as_str()))
+                .collect(),
+            None => vec![AccessControlAllowOrigin::Null, AccessControlAllowOrigin::Any],
+        };
+        let credentials = config.auth.as_ref().map(Credentials::new);
+        let requests = limiter.clone();
+        let health_check = HealthCheck::new(
+            shared,
+            network,
+            synchronizer,
+            config.ready_min_peers,
+            limiter.clone(),
+        );
+        let mut server = ServerBuilder::with_meta_extractor(
+            io,
+            move |request: &hyper::Request<hyper::Body>| {
+                let authorized = credentials
+                    .as_ref()
+                    .map_or(false, |credentials| credentials.is_authorized(request));
+                RequestMeta::new(authorized, requests.enter())
+            },
+        )
+        .request_middleware(health_check)
+        .cors(DomainsValidation::AllowOnly(cors))
+        .allowed_hosts(match config.allowed_hosts {
+            Some(ref hosts) => DomainsValidation::AllowOnly(
+                hosts.iter().map(|host| Host::from(host.as_str())).collect(),
+            ),
+            None => DomainsValidation::Disabled,
+        })
+        .threads(config.threads.unwrap_or_else(num_cpus::get))
+        .max_request_body_size(config.max_request_body_size)
+        .start_http(
+            &config
+                .listen_address
+                .parse()
+                .expect("rpc listen_address should be ip:port"),
+        )
+        .expect("Jsonrpc initialize");
 
         let closes = server.take_close();
 
@@ -117,9 +240,57 @@ impl RpcServer {
             server: Some(server),
             closes,
             thread: None,
+            ws_listen_address: config.ws_listen_address,
+            ws_server: None,
+            ws_credentials,
+            limiter,
+            overrides,
         }
     }
 
+    /// The settings changed by `update_config`, to apply them again when the
+    /// config file is reloaded
+    pub fn runtime_overrides(&self) -> RuntimeOverrides {
+        self.overrides.clone()
+    }
+
+    /// Serves the subscriptions to the `notify` events on the configured
+    /// WebSocket address, if any
+    pub fn start_subscription<T: KeyValueDB + 'static>(
+        &mut self,
+        notify: &NotifyController,
+        tx_pool: TransactionPoolController,
+        indexer: IndexerController<T>,
+    ) {
+        let address = match self.ws_listen_address {
+            Some(ref address) => address,
+            None => return,
+        };
+        let mut io = PubSubHandler::new(MetaIoHandler::with_middleware(CallLimiter(
+            self.limiter.clone(),
+        )));
+        io.extend_with(SubscriptionRpcImpl::new(notify, tx_pool, indexer).to_delegate());
+
+        let server = jsonrpc_ws_server::ServerBuilder::with_meta_extractor(
+            io,
+            |context: &jsonrpc_ws_server::RequestContext| {
+                Some(Arc::new(Session::new(context.sender())))
+            },
+        )
+        .request_middleware(HandshakeLimiter::new(
+            self.limiter.clone(),
+            self.ws_credentials.take(),
+        ))
+        .start(
+            &address
+                .parse()
+                .expect("rpc ws_listen_address should be ip:port"),
+        )
+        .expect("Jsonrpc ws initialize");
+        info!(target: "rpc", "Now listening for subscriptions on {:?}", server.addr());
+        self.ws_server = Some(server);
+    }
+
     pub fn start(&mut self) {
         let server = self.server.take().expect("Jsonrpc start only once");
 
@@ -136,7 +307,24 @@ impl RpcServer {
         self.thread = Some(thread);
     }
 
+    /// Refuses the new requests and closes the server once the ones in
+    /// flight are answered
     pub fn close(mut self) {
+        if let Some(ws_server) = self.ws_server.take() {
+            ws_server.close();
+        }
+        self.limiter.close();
+        let started_at = Instant::now();
+        while self.limiter.in_flight() > 0 {
+            if started_at.elapsed() >= DRAIN_TIMEOUT {
+                error!(
+                    target: "rpc",
+                    "close with {} requests in flight", self.limiter.in_flight()
+                );
+                break;
+            }
+            thread::sleep(DRAIN_INTERVAL);
+        }
         if let Some(thread) = self.thread.take() {
             let closes = self.closes.take().expect("jsonrpc only close once");
 
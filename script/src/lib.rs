@@ -1,9 +1,11 @@
 mod cost_model;
+mod multisig;
 mod syscalls;
 mod verify;
 
 use ckb_vm::Error as VMInternalError;
 
+pub use crate::multisig::SIGNATURE_VERIFY_CYCLES;
 pub use crate::verify::TransactionScriptsVerifier;
 
 #[derive(Debug, PartialEq, Clone, Copy, Eq)]
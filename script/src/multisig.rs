@@ -0,0 +1,44 @@
+use crate::ScriptError;
+use ckb_core::multisig::multisig_keys;
+use ckb_core::script::Script;
+use ckb_core::Cycle;
+use crypto::secp::Signature;
+use numext_fixed_hash::H256;
+
+// Charged for every signature check, roughly what the secp256k1 verify
+// script takes in the VM
+pub const SIGNATURE_VERIFY_CYCLES: Cycle = 1_000_000;
+
+/// Natively verifies a built-in multisig script, see
+/// [ckb_core::multisig](ckb_core::multisig) for the layout. Signatures must
+/// follow the order of the keys they are made with. A failure comes with the
+/// cycles consumed until it.
+pub fn verify_multisig(
+    script: &Script,
+    message: &H256,
+    max_cycles: Cycle,
+) -> Result<Cycle, (ScriptError, Cycle)> {
+    let (threshold, pubkeys) = multisig_keys(script).ok_or((ScriptError::ArgumentError, 0))?;
+    if script.args.len() < threshold || script.args.iter().any(|arg| arg.len() != 65) {
+        return Err((ScriptError::ArgumentError, 0));
+    }
+
+    let mut cycles = 0;
+    let mut pubkeys = pubkeys.iter();
+    for arg in &script.args {
+        let signature = Signature::from(arg.clone());
+        loop {
+            let pubkey = pubkeys
+                .next()
+                .ok_or((ScriptError::ValidationFailure(1), cycles))?;
+            cycles += SIGNATURE_VERIFY_CYCLES;
+            if cycles > max_cycles {
+                return Err((ScriptError::ExceededMaximumCycles, max_cycles));
+            }
+            if pubkey.verify(message, &signature).is_ok() {
+                break;
+            }
+        }
+    }
+    Ok(cycles)
+}
@@ -1,9 +1,11 @@
 use crate::{
     cost_model::instruction_cycles,
+    multisig::verify_multisig,
     syscalls::{build_tx, Debugger, LoadCell, LoadCellByField, LoadInputByField, LoadTx},
     ScriptError,
 };
 use ckb_core::cell::ResolvedTransaction;
+use ckb_core::multisig::{is_multisig_script, signing_message};
 use ckb_core::script::Script;
 use ckb_core::transaction::{CellInput, CellOutput};
 use ckb_core::Cycle;
@@ -25,6 +27,7 @@ pub struct TransactionScriptsVerifier<'a> {
     input_cells: Vec<&'a CellOutput>,
     dep_cells: Vec<&'a CellOutput>,
     hash: H256,
+    signing_message: H256,
 }
 
 impl<'a> TransactionScriptsVerifier<'a> {
@@ -69,6 +72,7 @@ impl<'a> TransactionScriptsVerifier<'a> {
             input_cells,
             dep_cells,
             hash: rtx.transaction.hash().clone(),
+            signing_message: signing_message(&rtx.transaction),
         }
     }
 
@@ -146,62 +150,68 @@ impl<'a> TransactionScriptsVerifier<'a> {
         current_cell: &'a CellOutput,
         current_input: Option<&'a CellInput>,
         max_cycles: Cycle,
-    ) -> Result<Cycle, ScriptError> {
+    ) -> Result<Cycle, (ScriptError, Cycle)> {
+        if is_multisig_script(script) {
+            return verify_multisig(script, &self.signing_message, max_cycles);
+        }
         let mut args = vec![b"verify".to_vec()];
-        self.extract_script(script, &mut args)
-            .and_then(|script_binary| {
-                args.extend_from_slice(&script.args.as_slice());
-
-                let mut machine = DefaultMachine::<u64, SparseMemory>::new_with_cost_model(
-                    Box::new(instruction_cycles),
-                    max_cycles,
-                );
-                machine.add_syscall_module(Box::new(self.build_load_tx()));
-                machine.add_syscall_module(Box::new(self.build_load_cell(current_cell)));
-                machine.add_syscall_module(Box::new(self.build_load_cell_by_field(current_cell)));
-                machine.add_syscall_module(Box::new(self.build_load_input_by_field(current_input)));
-                machine.add_syscall_module(Box::new(Debugger::new(prefix)));
-                machine
-                    .run(script_binary, &args)
-                    .map_err(ScriptError::VMError)
-                    .and_then(|code| {
-                        if code == 0 {
-                            Ok(machine.cycles())
-                        } else {
-                            Err(ScriptError::ValidationFailure(code))
-                        }
-                    })
-            })
+        let script_binary = self
+            .extract_script(script, &mut args)
+            .map_err(|e| (e, 0))?;
+        args.extend_from_slice(&script.args.as_slice());
+
+        let mut machine = DefaultMachine::<u64, SparseMemory>::new_with_cost_model(
+            Box::new(instruction_cycles),
+            max_cycles,
+        );
+        machine.add_syscall_module(Box::new(self.build_load_tx()));
+        machine.add_syscall_module(Box::new(self.build_load_cell(current_cell)));
+        machine.add_syscall_module(Box::new(self.build_load_cell_by_field(current_cell)));
+        machine.add_syscall_module(Box::new(self.build_load_input_by_field(current_input)));
+        machine.add_syscall_module(Box::new(Debugger::new(prefix)));
+        let ret = machine.run(script_binary, &args);
+        let cycles = machine.cycles().min(max_cycles);
+        match ret {
+            Ok(0) => Ok(cycles),
+            Ok(code) => Err((ScriptError::ValidationFailure(code), cycles)),
+            Err(e) => Err((ScriptError::VMError(e), cycles)),
+        }
     }
 
     pub fn verify(&self, max_cycles: Cycle) -> Result<Cycle, ScriptError> {
+        self.verify_counting(max_cycles).map_err(|(e, _)| e)
+    }
+
+    /// Like `verify`, a failure comes with the cycles consumed until it, so that
+    /// the submitter of a rejected transaction learns what it cost
+    pub fn verify_counting(&self, max_cycles: Cycle) -> Result<Cycle, (ScriptError, Cycle)> {
         let mut cycles = 0;
         for (i, input) in self.inputs.iter().enumerate() {
             let prefix = format!("Transaction {}, input {}", self.hash, i);
-            let cycle = self.verify_script(&input.unlock, &prefix, self.input_cells[i], Some(input), max_cycles - cycles).map_err(|e| {
+            let cycle = self.verify_script(&input.unlock, &prefix, self.input_cells[i], Some(input), max_cycles - cycles).map_err(|(e, consumed)| {
                 info!(target: "script", "Error validating input {} of transaction {}: {:?}", i, self.hash, e);
-                e
+                (e, cycles + consumed)
             })?;
             let current_cycles = cycles
                 .checked_add(cycle)
-                .ok_or(ScriptError::ExceededMaximumCycles)?;
+                .ok_or((ScriptError::ExceededMaximumCycles, max_cycles))?;
             if current_cycles > max_cycles {
-                return Err(ScriptError::ExceededMaximumCycles);
+                return Err((ScriptError::ExceededMaximumCycles, max_cycles));
             }
             cycles = current_cycles;
         }
         for (i, output) in self.outputs.iter().enumerate() {
             if let Some(ref type_) = output.type_ {
                 let prefix = format!("Transaction {}, output {}", self.hash, i);
-                let cycle = self.verify_script(type_, &prefix, output, None, max_cycles - cycles).map_err(|e| {
+                let cycle = self.verify_script(type_, &prefix, output, None, max_cycles - cycles).map_err(|(e, consumed)| {
                     info!(target: "script", "Error validating output {} of transaction {}: {:?}", i, self.hash, e);
-                    e
+                    (e, cycles + consumed)
                 })?;
                 let current_cycles = cycles
                     .checked_add(cycle)
-                    .ok_or(ScriptError::ExceededMaximumCycles)?;
+                    .ok_or((ScriptError::ExceededMaximumCycles, max_cycles))?;
                 if current_cycles > max_cycles {
-                    return Err(ScriptError::ExceededMaximumCycles);
+                    return Err((ScriptError::ExceededMaximumCycles, max_cycles));
                 }
                 cycles = current_cycles;
             }
@@ -213,11 +223,14 @@ impl<'a> TransactionScriptsVerifier<'a> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::SIGNATURE_VERIFY_CYCLES;
     use ckb_core::cell::CellStatus;
+    use ckb_core::multisig::{multisig_lock, multisig_script, sign};
     use ckb_core::script::Script;
+    use ckb_core::signing::{default_lock, default_unlock_script, sign_inputs};
     use ckb_core::transaction::{CellInput, CellOutput, OutPoint, TransactionBuilder};
     use ckb_core::Capacity;
-    use crypto::secp::Generator;
+    use crypto::secp::{Generator, Privkey};
     use faster_hex::hex_encode;
     use hash::sha3_256;
     use numext_fixed_hash::H256;
@@ -586,4 +599,96 @@ mod tests {
 
         assert!(verifier.verify(100_000_000).is_err());
     }
+
+    fn multisig_rtx(keys: usize, threshold: u8, signers: &[usize]) -> ResolvedTransaction {
+        let gen = Generator::new();
+        let privkeys: Vec<Privkey> = (0..keys).map(|_| gen.random_privkey()).collect();
+        let pubkeys: Vec<_> = privkeys.iter().map(|key| key.pubkey().unwrap()).collect();
+
+        let input = CellInput::new(
+            OutPoint::new(H256::zero(), 0),
+            multisig_script(threshold, &pubkeys, Vec::new()),
+        );
+        let unsigned = TransactionBuilder::default()
+            .input(input.clone())
+            .output(CellOutput::new(50, vec![], H256::zero(), None))
+            .build();
+        let signatures = signers
+            .iter()
+            .map(|i| sign(&unsigned, &privkeys[*i]).unwrap())
+            .collect();
+        let transaction = TransactionBuilder::default()
+            .transaction(unsigned)
+            .inputs_clear()
+            .input(CellInput::new(
+                input.previous_output,
+                multisig_script(threshold, &pubkeys, signatures),
+            ))
+            .build();
+
+        let cell = CellOutput::new(100, vec![], multisig_lock(threshold, &pubkeys), None);
+        ResolvedTransaction {
+            transaction,
+            dep_cells: vec![],
+            input_cells: vec![CellStatus::Live(cell)],
+        }
+    }
+
+    #[test]
+    fn check_multisig() {
+        let rtx = multisig_rtx(3, 2, &[0, 2]);
+        let verifier = TransactionScriptsVerifier::new(&rtx);
+        assert_eq!(verifier.verify(100_000_000), Ok(3 * SIGNATURE_VERIFY_CYCLES));
+        assert_eq!(
+            verifier.verify(SIGNATURE_VERIFY_CYCLES),
+            Err(ScriptError::ExceededMaximumCycles)
+        );
+    }
+
+    #[test]
+    fn check_multisig_not_enough_signatures() {
+        let rtx = multisig_rtx(3, 2, &[1]);
+        let verifier = TransactionScriptsVerifier::new(&rtx);
+        assert_eq!(verifier.verify(100_000_000), Err(ScriptError::ArgumentError));
+    }
+
+    #[test]
+    fn check_multisig_wrong_order() {
+        let rtx = multisig_rtx(3, 2, &[2, 0]);
+        let verifier = TransactionScriptsVerifier::new(&rtx);
+        assert_eq!(verifier.verify(100_000_000), Err(ScriptError::ValidationFailure(1)));
+        // The first key was checked before the signature of the third one
+        assert_eq!(
+            verifier.verify_counting(100_000_000),
+            Err((
+                ScriptError::ValidationFailure(1),
+                3 * SIGNATURE_VERIFY_CYCLES
+            ))
+        );
+    }
+
+    #[test]
+    fn check_default_lock_signed() {
+        let privkey = Generator::new().random_privkey();
+        let pubkey = privkey.pubkey().unwrap();
+        let unsigned = TransactionBuilder::default()
+            .input(CellInput::new(
+                OutPoint::new(H256::zero(), 0),
+                default_unlock_script(&pubkey),
+            ))
+            .output(CellOutput::new(50, vec![], H256::zero(), None))
+            .build();
+        let rtx = ResolvedTransaction {
+            transaction: sign_inputs(&unsigned, &[privkey]).unwrap(),
+            dep_cells: vec![],
+            input_cells: vec![CellStatus::Live(CellOutput::new(
+                100,
+                vec![],
+                default_lock(&pubkey),
+                None,
+            ))],
+        };
+        let verifier = TransactionScriptsVerifier::new(&rtx);
+        assert_eq!(verifier.verify(100_000_000), Ok(SIGNATURE_VERIFY_CYCLES));
+    }
 }
@@ -0,0 +1,260 @@
+//! Append-only storage of old main chain block bodies.
+//!
+//! Bodies are appended in block number order to `bodies.dat`, in the same
+//! flat serialization as in the db, so the stored transaction addresses stay
+//! valid. `bodies.idx` holds the offset and length of every body as two
+//! little endian `u64`, the body of block `n` being the `n`th entry.
+
+use ckb_core::BlockNumber;
+use ckb_util::Mutex;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+const INDEX_ENTRY_SIZE: u64 = 16;
+
+pub struct AncientStore {
+    dir: PathBuf,
+    inner: Mutex<Inner>,
+}
+
+struct Inner {
+    data: File,
+    index: File,
+    len: BlockNumber,
+    data_len: u64,
+}
+
+impl AncientStore {
+    pub fn open<P: AsRef<Path>>(dir: P) -> io::Result<AncientStore> {
+        fs::create_dir_all(dir.as_ref())?;
+        let open = |name| {
+            OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .open(dir.as_ref().join(name))
+        };
+        let data = open("bodies.dat")?;
+        let index = open("bodies.idx")?;
+        let mut inner = Inner {
+            len: index.metadata()?.len() / INDEX_ENTRY_SIZE,
+            data_len: data.metadata()?.len(),
+            data,
+            index,
+        };
+        inner.repair()?;
+        Ok(AncientStore {
+            dir: dir.as_ref().to_path_buf(),
+            inner: Mutex::new(inner),
+        })
+    }
+
+    /// Opens an existing store to read the bodies it has now, while a node
+    /// may still append to it
+    pub fn open_read_only<P: AsRef<Path>>(dir: P) -> io::Result<AncientStore> {
+        let data = File::open(dir.as_ref().join("bodies.dat"))?;
+        let index = File::open(dir.as_ref().join("bodies.idx"))?;
+        let mut inner = Inner {
+            len: index.metadata()?.len() / INDEX_ENTRY_SIZE,
+            data_len: data.metadata()?.len(),
+            data,
+            index,
+        };
+        // Skips a body being appended, as the repair on open but without
+        // writing
+        while inner.len > 0 {
+            let (offset, length) = inner.entry(inner.len - 1)?;
+            if offset + length <= inner.data_len {
+                break;
+            }
+            inner.len -= 1;
+        }
+        Ok(AncientStore {
+            dir: dir.as_ref().to_path_buf(),
+            inner: Mutex::new(inner),
+        })
+    }
+
+    /// Number of stored bodies, the next one to append is the body of this
+    /// block number
+    pub fn len(&self) -> BlockNumber {
+        self.inner.lock().len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Appends the body of block `number`, which is then the last one. A body
+    /// already stored, by an append whose db commit was lost in a crash, is
+    /// kept if it is the same one.
+    pub fn append(&self, number: BlockNumber, body: &[u8]) -> io::Result<()> {
+        let mut inner = self.inner.lock();
+        if number < inner.len {
+            let (offset, length) = inner.entry(number)?;
+            let mut stored = vec![0u8; length as usize];
+            inner.data.seek(SeekFrom::Start(offset))?;
+            inner.data.read_exact(&mut stored)?;
+            if stored == body {
+                return Ok(());
+            }
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("ancient block {} is another body", number),
+            ));
+        }
+        if number != inner.len {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("expect ancient block {}, got {}", inner.len, number),
+            ));
+        }
+        let offset = inner.data_len;
+        inner.data.seek(SeekFrom::Start(offset))?;
+        inner.data.write_all(body)?;
+        inner.data.sync_data()?;
+
+        let mut entry = [0u8; INDEX_ENTRY_SIZE as usize];
+        entry[..8].copy_from_slice(&offset.to_le_bytes());
+        entry[8..].copy_from_slice(&(body.len() as u64).to_le_bytes());
+        inner.index.seek(SeekFrom::Start(number * INDEX_ENTRY_SIZE))?;
+        inner.index.write_all(&entry)?;
+        inner.index.sync_data()?;
+
+        inner.data_len += body.len() as u64;
+        inner.len += 1;
+        Ok(())
+    }
+
+    /// Copies the bodies stored so far to a new ancient store in `dir`, which
+    /// must be missing or empty
+    pub fn backup<P: AsRef<Path>>(&self, dir: P) -> io::Result<()> {
+        let (len, data_len) = {
+            let inner = self.inner.lock();
+            (inner.len, inner.data_len)
+        };
+        // Appends only write past these lengths
+        fs::create_dir_all(dir.as_ref())?;
+        if dir.as_ref().read_dir()?.next().is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} is not empty", dir.as_ref().display()),
+            ));
+        }
+        let copy = |name, length| -> io::Result<()> {
+            let mut from = File::open(self.dir.join(name))?;
+            let mut to = File::create(dir.as_ref().join(name))?;
+            io::copy(&mut (&mut from).take(length), &mut to)?;
+            to.sync_all()
+        };
+        copy("bodies.dat", data_len)?;
+        copy("bodies.idx", len * INDEX_ENTRY_SIZE)
+    }
+
+    /// Body of the block `number`, or the `range` of it
+    pub fn read(
+        &self,
+        number: BlockNumber,
+        range: Option<&Range<usize>>,
+    ) -> io::Result<Option<Vec<u8>>> {
+        let mut inner = self.inner.lock();
+        if number >= inner.len {
+            return Ok(None);
+        }
+        let (offset, length) = inner.entry(number)?;
+        let (start, end) = match range {
+            Some(range) if range.start > range.end || range.end as u64 > length => {
+                return Ok(None);
+            }
+            Some(range) => (range.start as u64, range.end as u64),
+            None => (0, length),
+        };
+        let mut body = vec![0u8; (end - start) as usize];
+        inner.data.seek(SeekFrom::Start(offset + start))?;
+        inner.data.read_exact(&mut body)?;
+        Ok(Some(body))
+    }
+}
+
+impl Inner {
+    fn entry(&mut self, number: BlockNumber) -> io::Result<(u64, u64)> {
+        let mut entry = [0u8; INDEX_ENTRY_SIZE as usize];
+        self.index.seek(SeekFrom::Start(number * INDEX_ENTRY_SIZE))?;
+        self.index.read_exact(&mut entry)?;
+        let mut offset = [0u8; 8];
+        let mut length = [0u8; 8];
+        offset.copy_from_slice(&entry[..8]);
+        length.copy_from_slice(&entry[8..]);
+        Ok((u64::from_le_bytes(offset), u64::from_le_bytes(length)))
+    }
+
+    // Drops the entries whose body was not fully written before a crash,
+    // and the trailing bytes no entry points to
+    fn repair(&mut self) -> io::Result<()> {
+        while self.len > 0 {
+            let (offset, length) = self.entry(self.len - 1)?;
+            if offset + length <= self.data_len {
+                self.data_len = offset + length;
+                break;
+            }
+            self.len -= 1;
+        }
+        if self.len == 0 {
+            self.data_len = 0;
+        }
+        self.index.set_len(self.len * INDEX_ENTRY_SIZE)?;
+        self.data.set_len(self.data_len)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile;
+
+    #[test]
+    fn append_and_read() {
+        let tmp_dir = tempfile::Builder::new()
+            .prefix("ancient_append_and_read")
+            .tempdir()
+            .unwrap();
+        let store = AncientStore::open(tmp_dir.path()).unwrap();
+        assert!(store.is_empty());
+        store.append(0, &[1, 2, 3]).unwrap();
+        store.append(1, &[]).unwrap();
+        store.append(2, &[4, 5]).unwrap();
+        assert!(store.append(4, &[6]).is_err());
+        // Appending a stored body again keeps it
+        store.append(1, &[]).unwrap();
+        assert!(store.append(0, &[1, 2]).is_err());
+        assert_eq!(store.len(), 3);
+
+        assert_eq!(store.read(0, None).unwrap(), Some(vec![1, 2, 3]));
+        assert_eq!(store.read(1, None).unwrap(), Some(vec![]));
+        assert_eq!(store.read(2, Some(&(1..2))).unwrap(), Some(vec![5]));
+        assert_eq!(store.read(2, Some(&(1..3))).unwrap(), None);
+        assert_eq!(store.read(3, None).unwrap(), None);
+        drop(store);
+
+        // A body lost in a crash drops its entry
+        OpenOptions::new()
+            .write(true)
+            .open(tmp_dir.path().join("bodies.dat"))
+            .unwrap()
+            .set_len(4)
+            .unwrap();
+        let store = AncientStore::open(tmp_dir.path()).unwrap();
+        assert_eq!(store.len(), 2);
+        store.append(2, &[7]).unwrap();
+        assert_eq!(store.read(2, None).unwrap(), Some(vec![7]));
+        assert_eq!(store.read(0, None).unwrap(), Some(vec![1, 2, 3]));
+
+        store.backup(tmp_dir.path().join("backup")).unwrap();
+        let backup = AncientStore::open(tmp_dir.path().join("backup")).unwrap();
+        assert_eq!(backup.len(), 3);
+        assert_eq!(backup.read(2, None).unwrap(), Some(vec![7]));
+    }
+}
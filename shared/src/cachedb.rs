@@ -1,9 +1,10 @@
 use ckb_db::batch::{Batch, Col, Operation};
-use ckb_db::kvdb::{KeyValueDB, Result};
+use ckb_db::kvdb::{KeyValueDB, KeyValueIter, Result};
 use ckb_util::RwLock;
 use fnv::FnvHashMap;
 use lru_cache::LruCache;
 use std::ops::Range;
+use std::path::Path;
 
 type CacheTable = FnvHashMap<Col, LruCache<Vec<u8>, Vec<u8>>>;
 pub type CacheCols = (u32, usize);
@@ -41,20 +42,24 @@ where
     }
 
     fn write(&self, batch: Batch) -> Result<()> {
+        // The cache lock is held during the write so that readers never see
+        // the db and the cache disagree, and the cache is only updated once
+        // the batch is written.
         let mut cache_guard = self.cache.write();
-        batch.operations.iter().for_each(|op| match op {
+        self.db.write(batch.clone())?;
+        batch.operations.into_iter().for_each(|op| match op {
             Operation::Insert { col, key, value } => {
                 if let Some(lru) = cache_guard.get_mut(&col) {
-                    lru.insert(key.clone(), value.clone());
+                    lru.insert(key, value);
                 }
             }
             Operation::Delete { col, key } => {
                 if let Some(lru) = cache_guard.get_mut(&col) {
-                    lru.remove(key);
+                    lru.remove(&key);
                 }
             }
         });
-        self.db.write(batch)
+        Ok(())
     }
 
     fn read(&self, col: Col, key: &[u8]) -> Result<Option<Vec<u8>>> {
@@ -84,4 +89,35 @@ where
         }
         self.db.partial_read(col, key, range)
     }
+
+    fn iter_from<'a>(&'a self, col: Col, from: &[u8]) -> Result<KeyValueIter<'a>> {
+        // The cache is written through, so the db is up to date
+        self.db.iter_from(col, from)
+    }
+
+    fn size(&self, col: Col) -> Result<u64> {
+        self.db.size(col)
+    }
+
+    fn compact(&self, col: Col) -> Result<()> {
+        self.db.compact(col)
+    }
+
+    fn backup(&self, path: &Path) -> Result<()> {
+        self.db.backup(path)
+    }
+
+    fn sync(&self) -> Result<()> {
+        self.db.sync()
+    }
+
+    fn catch_up(&self) -> Result<()> {
+        // Whatever the primary deleted meanwhile may still be cached
+        let mut cache_guard = self.cache.write();
+        self.db.catch_up()?;
+        for lru in cache_guard.values_mut() {
+            lru.clear();
+        }
+        Ok(())
+    }
 }
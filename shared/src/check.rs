@@ -0,0 +1,366 @@
+//! Consistency check of the chain store, to run on a stopped node after a
+//! crash or a disk error.
+//!
+//! The main chain is checked from genesis to the tip: the hash index has no
+//! gap, every header links to its parent and every block has its body, or is
+//! pruned. The cell set is rebuilt from the bodies as on startup, which finds
+//! inputs spending unknown or spent outputs, and the hash to number index and
+//! the transaction addresses are checked on the way.
+
+use crate::error::SharedError;
+use crate::index::ChainIndex;
+use crate::txo_set::TxoSet;
+use ckb_core::header::{BlockNumber, Header};
+use ckb_core::transaction::{OutPoint, Transaction};
+use numext_fixed_hash::H256;
+use std::collections::HashSet;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Inconsistency {
+    /// No main chain block hash is indexed at this number
+    MissingBlockHash,
+    /// The header is missing, or is the one of another number
+    MissingHeader(H256),
+    /// The header does not link to the previous main chain block
+    BrokenLink(H256),
+    /// The block has neither its body nor a pruned one
+    MissingBody(H256),
+    /// The block is not indexed by hash, or with another number
+    MissingBlockNumber(H256),
+    /// The transaction is not indexed, or points to another block
+    MissingTransactionAddress(H256),
+    /// An input spends an output which no earlier block created
+    UnknownInput(OutPoint),
+    DoubleSpend(OutPoint),
+    /// The tip is not the main chain block at its number
+    WrongTip(H256),
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CheckReport {
+    /// Main chain blocks found consistent enough to be walked, from genesis
+    pub checked: BlockNumber,
+    /// Inconsistencies with the number of the block they were found at
+    pub inconsistencies: Vec<(BlockNumber, Inconsistency)>,
+    /// The tip after repairing, when the main chain was broken below it
+    pub rewound_to: Option<BlockNumber>,
+}
+
+impl CheckReport {
+    pub fn is_consistent(&self) -> bool {
+        self.inconsistencies.is_empty()
+    }
+}
+
+/// Checks the main chain of `store`. With `repair`, the indexes are rebuilt
+/// from the blocks and a main chain broken below its tip is rewound to the
+/// last block before the break. The blocks above are dropped, so that they are
+/// received and verified again, and the cell set rebuilt on startup is the
+/// one of the new tip. Spending inconsistencies are only reported.
+pub fn check_db<CI: ChainIndex>(store: &CI, repair: bool) -> Result<CheckReport, SharedError> {
+    let mut report = CheckReport::default();
+    let tip = match store.get_tip_header() {
+        Some(tip) => tip,
+        None => return Ok(report),
+    };
+    // Fully spent transactions of pruned blocks are gone, spending them is
+    // not an error
+    let pruned = store.get_pruned_below() > 0;
+    let mut txo_set = TxoSet::new();
+    let mut parent: Option<Header> = None;
+    let mut reindex: Vec<(BlockNumber, H256, Vec<Transaction>)> = Vec::new();
+    let mut broken = None;
+
+    for number in 0..=tip.number() {
+        let mut found = |inconsistency| report.inconsistencies.push((number, inconsistency));
+        let hash = match store.get_block_hash(number) {
+            Some(hash) => hash,
+            None => {
+                found(Inconsistency::MissingBlockHash);
+                broken = Some(number);
+                break;
+            }
+        };
+        let header = match store.get_header(&hash) {
+            Some(ref header) if header.number() == number => header.clone(),
+            _ => {
+                found(Inconsistency::MissingHeader(hash));
+                broken = Some(number);
+                break;
+            }
+        };
+        if let Some(ref parent) = parent {
+            if header.parent_hash() != &parent.hash() {
+                found(Inconsistency::BrokenLink(hash));
+                broken = Some(number);
+                break;
+            }
+        }
+
+        let mut stale_index = store.get_block_number(&hash) != Some(number);
+        if stale_index {
+            found(Inconsistency::MissingBlockNumber(hash.clone()));
+        }
+        match store.get_block_body(&hash) {
+            Some(transactions) => {
+                for (index, transaction) in transactions.iter().enumerate() {
+                    let tx_hash = transaction.hash();
+                    let indexed = store
+                        .get_transaction_address(&tx_hash)
+                        .map_or(false, |address| {
+                            address.block_hash == hash && address.index == index
+                        });
+                    if !indexed {
+                        found(Inconsistency::MissingTransactionAddress(tx_hash.clone()));
+                        stale_index = true;
+                    }
+                    for input in transaction.input_pts() {
+                        match txo_set.is_spent(&input) {
+                            _ if input.is_null() => {}
+                            Some(false) => txo_set.mark_spent(&input),
+                            Some(true) => found(Inconsistency::DoubleSpend(input)),
+                            None if !pruned => found(Inconsistency::UnknownInput(input)),
+                            None => {}
+                        }
+                    }
+                    txo_set.insert(tx_hash, transaction.outputs().len());
+                }
+                if stale_index {
+                    reindex.push((number, hash, transactions));
+                }
+            }
+            None => match store.get_pruned_block(&hash) {
+                Some(pruned_block) => {
+                    for tx_hash in pruned_block.live_transactions {
+                        match store.get_transaction(&tx_hash) {
+                            Some(transaction) => {
+                                txo_set.insert(tx_hash, transaction.outputs().len())
+                            }
+                            None => found(Inconsistency::MissingTransactionAddress(tx_hash)),
+                        }
+                    }
+                    for input in pruned_block.inputs {
+                        txo_set.mark_spent(&input);
+                    }
+                    if stale_index {
+                        reindex.push((number, hash, Vec::new()));
+                    }
+                }
+                None => {
+                    found(Inconsistency::MissingBody(hash));
+                    broken = Some(number);
+                    break;
+                }
+            },
+        }
+        report.checked += 1;
+        parent = Some(header);
+    }
+
+    let wrong_tip = broken.is_none() && parent.as_ref().map(Header::hash) != Some(tip.hash());
+    if wrong_tip {
+        report
+            .inconsistencies
+            .push((tip.number(), Inconsistency::WrongTip(tip.hash())));
+    }
+    // Nothing to rewind to when genesis is broken
+    let new_tip = parent.filter(|_| wrong_tip || broken.is_some());
+    if !repair || (reindex.is_empty() && new_tip.is_none()) {
+        return Ok(report);
+    }
+
+    store.save_with_batch(|batch| {
+        for (number, hash, transactions) in &reindex {
+            store.insert_block_number(batch, hash, *number);
+            if !transactions.is_empty() {
+                store.insert_transaction_address(batch, hash, transactions);
+            }
+        }
+        if let Some(ref new_tip) = new_tip {
+            // The blocks indexed above the new tip, and the ones the tip header
+            // links to, which a broken index may miss
+            let mut detached = HashSet::new();
+            for number in new_tip.number() + 1..=tip.number() {
+                if let Some(hash) = store.get_block_hash(number) {
+                    store.delete_block_hash(batch, number);
+                    detached.insert(hash);
+                }
+            }
+            let mut header = Some(tip.clone());
+            while let Some(detached_header) =
+                header.filter(|header| header.number() > new_tip.number())
+            {
+                header = store.get_header(detached_header.parent_hash());
+                detached.insert(detached_header.hash());
+            }
+            for hash in &detached {
+                if let Some(transactions) = store.get_block_body(hash) {
+                    let indexed: Vec<_> = transactions
+                        .into_iter()
+                        .filter(|transaction| {
+                            store
+                                .get_transaction_address(&transaction.hash())
+                                .map_or(false, |address| &address.block_hash == hash)
+                        })
+                        .collect();
+                    store.delete_transaction_address(batch, &indexed);
+                }
+                store.delete_block_number(batch, hash);
+                store.delete_block(batch, hash);
+            }
+            store.insert_tip_header(batch, new_tip);
+        }
+        Ok(())
+    })?;
+    report.rewound_to = new_tip.map(|header| header.number());
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shared::{ChainProvider, Shared, SharedBuilder};
+    use crate::store::{ChainKVStore, ChainStore};
+    use crate::COLUMN_BLOCK_BODY;
+    use ckb_core::block::{Block, BlockBuilder};
+    use ckb_core::header::HeaderBuilder;
+    use ckb_core::transaction::{CellInput, CellOutput, TransactionBuilder};
+    use ckb_db::memorydb::MemoryKeyValueDB;
+
+    #[test]
+    fn check_and_repair() {
+        let shared = SharedBuilder::<ChainKVStore<MemoryKeyValueDB>>::new_memory().build();
+        let store = shared.store();
+        let mut blocks: Vec<Block> = vec![shared.consensus().genesis_block().clone()];
+        for number in 1..6 {
+            let transaction = TransactionBuilder::default()
+                .input(CellInput::new_cellbase_input(number))
+                .output(CellOutput::new(100, vec![], H256::zero(), None))
+                .build();
+            let header = HeaderBuilder::default()
+                .parent_hash(blocks.last().unwrap().header().hash())
+                .number(number);
+            let block = BlockBuilder::default()
+                .commit_transaction(transaction)
+                .with_header_builder(header);
+            blocks.push(block);
+        }
+        store
+            .save_with_batch(|batch| {
+                for block in &blocks[1..] {
+                    let hash = block.header().hash();
+                    store.insert_block(batch, block);
+                    store.insert_block_hash(batch, block.header().number(), &hash);
+                    store.insert_block_number(batch, &hash, block.header().number());
+                    store.insert_transaction_address(batch, &hash, block.commit_transactions());
+                }
+                store.insert_tip_header(batch, blocks[5].header());
+                Ok(())
+            })
+            .unwrap();
+
+        let report = check_db(store.as_ref(), false).unwrap();
+        assert!(report.is_consistent());
+        assert_eq!(report.checked, 6);
+
+        let lost_tx = blocks[2].commit_transactions()[0].hash();
+        store
+            .save_with_batch(|batch| {
+                store.delete_transaction_address(batch, blocks[2].commit_transactions());
+                store.delete_block_hash(batch, 4);
+                Ok(())
+            })
+            .unwrap();
+        let report = check_db(store.as_ref(), false).unwrap();
+        assert_eq!(
+            report.inconsistencies,
+            vec![
+                (2, Inconsistency::MissingTransactionAddress(lost_tx.clone())),
+                (4, Inconsistency::MissingBlockHash),
+            ]
+        );
+        assert_eq!(report.checked, 4);
+        assert_eq!(report.rewound_to, None);
+
+        let report = check_db(store.as_ref(), true).unwrap();
+        assert_eq!(report.rewound_to, Some(3));
+        assert_eq!(store.get_tip_header().as_ref(), Some(blocks[3].header()));
+        assert!(store.get_block_hash(5).is_none());
+        assert!(store.get_transaction_address(&lost_tx).is_some());
+        assert!(check_db(store.as_ref(), false).unwrap().is_consistent());
+    }
+
+    #[test]
+    fn rewind_drops_the_detached_blocks() {
+        let shared = SharedBuilder::<ChainKVStore<MemoryKeyValueDB>>::new_memory().build();
+        let store = shared.store();
+        let mut blocks: Vec<Block> = vec![shared.consensus().genesis_block().clone()];
+        for number in 1..6 {
+            let parent = blocks.last().unwrap();
+            let cellbase = TransactionBuilder::default()
+                .input(CellInput::new_cellbase_input(number))
+                .output(CellOutput::new(100, vec![], H256::zero(), None))
+                .build();
+            let mut builder = BlockBuilder::default().commit_transaction(cellbase);
+            // Spends the cellbase of the parent
+            if number > 1 {
+                let spent = OutPoint::new(parent.commit_transactions()[0].hash(), 0);
+                builder = builder.commit_transaction(
+                    TransactionBuilder::default()
+                        .input(CellInput::new(spent, Default::default()))
+                        .output(CellOutput::new(100, vec![], H256::zero(), None))
+                        .build(),
+                );
+            }
+            let header = HeaderBuilder::default()
+                .parent_hash(parent.header().hash())
+                .number(number);
+            blocks.push(builder.with_header_builder(header));
+        }
+        store
+            .save_with_batch(|batch| {
+                for block in &blocks[1..] {
+                    let hash = block.header().hash();
+                    store.insert_block(batch, block);
+                    store.insert_block_ext(batch, &hash, &Default::default());
+                    store.insert_block_hash(batch, block.header().number(), &hash);
+                    store.insert_block_number(batch, &hash, block.header().number());
+                    store.insert_transaction_address(batch, &hash, block.commit_transactions());
+                }
+                store.insert_tip_header(batch, blocks[5].header());
+                Ok(())
+            })
+            .unwrap();
+
+        // The body of block 4 is lost
+        let lost = blocks[4].header().hash();
+        store
+            .save_with_batch(|batch| {
+                batch.delete(COLUMN_BLOCK_BODY, lost.to_vec());
+                Ok(())
+            })
+            .unwrap();
+        let report = check_db(store.as_ref(), true).unwrap();
+        assert_eq!(
+            report.inconsistencies,
+            vec![(4, Inconsistency::MissingBody(lost))]
+        );
+        assert_eq!(report.rewound_to, Some(3));
+
+        for block in &blocks[4..] {
+            let hash = block.header().hash();
+            assert!(store.get_header(&hash).is_none());
+            assert!(store.get_block_ext(&hash).is_none());
+            assert!(store.get_block_number(&hash).is_none());
+            let tx_hash = block.commit_transactions()[1].hash();
+            assert!(store.get_transaction_address(&tx_hash).is_none());
+        }
+        // The cellbase block 4 spent is live again
+        let txo_set = Shared::<ChainKVStore<MemoryKeyValueDB>>::init_txo_set(store, 3);
+        let cellbase = OutPoint::new(blocks[3].commit_transactions()[0].hash(), 0);
+        assert_eq!(txo_set.is_spent(&cellbase), Some(false));
+        let spent = OutPoint::new(blocks[2].commit_transactions()[0].hash(), 0);
+        assert_eq!(txo_set.is_spent(&spent), Some(true));
+        assert!(check_db(store.as_ref(), false).unwrap().is_consistent());
+    }
+}
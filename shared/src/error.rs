@@ -6,6 +6,8 @@ pub enum SharedError {
     InvalidOutput,
     InvalidTransaction,
     DB(DBError),
+    Ancient(String),
+    Migration(String),
 }
 
 impl From<DBError> for SharedError {
@@ -1,7 +1,11 @@
 use crate::flat_serializer::serialized_addresses;
 use crate::store::{ChainKVStore, ChainStore};
-use crate::{COLUMN_BLOCK_BODY, COLUMN_INDEX, COLUMN_META, COLUMN_TRANSACTION_ADDR};
+use crate::{
+    COLUMN_BLOCK_HASH, COLUMN_DEPLOYMENT_STATE, COLUMN_INDEX, COLUMN_META,
+    COLUMN_PRUNED_TRANSACTION, COLUMN_TRANSACTION_ADDR,
+};
 use bincode::{deserialize, serialize};
+use ckb_chain_spec::versionbits::ThresholdState;
 use ckb_core::block::Block;
 use ckb_core::extras::{BlockExt, TransactionAddress};
 use ckb_core::header::{BlockNumber, Header};
@@ -9,8 +13,10 @@ use ckb_core::transaction::{Transaction, TransactionBuilder};
 use ckb_db::batch::Batch;
 use ckb_db::kvdb::KeyValueDB;
 use numext_fixed_hash::H256;
+use std::ops::Range;
 
-const META_TIP_HEADER_KEY: &[u8] = b"TIP_HEADER";
+pub(crate) const META_TIP_HEADER_KEY: &[u8] = b"TIP_HEADER";
+const META_PRUNED_BELOW_KEY: &[u8] = b"PRUNED_BELOW";
 
 // maintain chain index, extend chainstore
 pub trait ChainIndex: ChainStore {
@@ -20,14 +26,54 @@ pub trait ChainIndex: ChainStore {
     fn get_tip_header(&self) -> Option<Header>;
     fn get_transaction(&self, h: &H256) -> Option<Transaction>;
     fn get_transaction_address(&self, hash: &H256) -> Option<TransactionAddress>;
+    /// Bodies of the main chain blocks below this number, except genesis, are pruned
+    fn get_pruned_below(&self) -> BlockNumber;
+    /// Visits the main chain headers in `range` in order, stops at the first
+    /// missing one.
+    fn header_iter<'a>(
+        &'a self,
+        range: Range<BlockNumber>,
+    ) -> Box<dyn Iterator<Item = Header> + 'a>;
+    /// Visits the main chain blocks in `range` in order, stops at the first
+    /// missing or pruned one.
+    fn block_iter<'a>(
+        &'a self,
+        range: Range<BlockNumber>,
+    ) -> Box<dyn Iterator<Item = Block> + 'a>;
+
+    /// State of the deployment signalled by `bit` for the period following
+    /// the block `boundary`
+    fn get_deployment_state(&self, bit: u8, boundary: &H256) -> Option<ThresholdState>;
 
     fn insert_block_hash(&self, batch: &mut Batch, number: BlockNumber, hash: &H256);
     fn delete_block_hash(&self, batch: &mut Batch, number: BlockNumber);
     fn insert_block_number(&self, batch: &mut Batch, hash: &H256, number: BlockNumber);
     fn delete_block_number(&self, batch: &mut Batch, hash: &H256);
     fn insert_tip_header(&self, batch: &mut Batch, h: &Header);
+    fn insert_pruned_below(&self, batch: &mut Batch, number: BlockNumber);
     fn insert_transaction_address(&self, batch: &mut Batch, block_hash: &H256, txs: &[Transaction]);
+    /// Stores the address of a transaction whose block body is not stored
+    fn insert_pruned_transaction_address(
+        &self,
+        batch: &mut Batch,
+        tx_hash: &H256,
+        address: &TransactionAddress,
+    );
     fn delete_transaction_address(&self, batch: &mut Batch, txs: &[Transaction]);
+    fn insert_deployment_state(
+        &self,
+        batch: &mut Batch,
+        bit: u8,
+        boundary: &H256,
+        state: ThresholdState,
+    );
+}
+
+fn deployment_state_key(bit: u8, boundary: &H256) -> Vec<u8> {
+    let mut key = Vec::with_capacity(33);
+    key.push(bit);
+    key.extend_from_slice(boundary.as_bytes());
+    key
 }
 
 impl<T: 'static + KeyValueDB> ChainIndex for ChainKVStore<T> {
@@ -38,6 +84,7 @@ impl<T: 'static + KeyValueDB> ChainIndex for ChainKVStore<T> {
                 received_at: genesis.header().timestamp(),
                 total_difficulty: genesis.header().difficulty().clone(),
                 total_uncles_count: 0,
+                total_transactions_count: genesis.commit_transactions().len() as u64,
                 valid: Some(true),
             };
 
@@ -66,8 +113,7 @@ impl<T: 'static + KeyValueDB> ChainIndex for ChainKVStore<T> {
     }
 
     fn get_block_hash(&self, number: BlockNumber) -> Option<H256> {
-        let key = serialize(&number).unwrap();
-        self.get(COLUMN_INDEX, &key)
+        self.get(COLUMN_BLOCK_HASH, &number.to_be_bytes())
             .map(|raw| H256::from_slice(&raw[..]).expect("db safe access"))
     }
 
@@ -84,16 +130,14 @@ impl<T: 'static + KeyValueDB> ChainIndex for ChainKVStore<T> {
 
     fn get_transaction(&self, h: &H256) -> Option<Transaction> {
         self.get_transaction_address(h)
-            .and_then(|d| {
-                self.partial_get(
-                    COLUMN_BLOCK_BODY,
-                    d.block_hash.as_bytes(),
-                    &(d.offset..(d.offset + d.length)),
-                )
-            })
+            .and_then(|d| self.get_body(&d.block_hash, Some(&(d.offset..(d.offset + d.length)))))
             .map(|ref serialized_transaction| {
                 TransactionBuilder::new(serialized_transaction).build()
             })
+            .or_else(|| {
+                self.get(COLUMN_PRUNED_TRANSACTION, h.as_bytes())
+                    .map(|raw| deserialize(&raw[..]).unwrap())
+            })
     }
 
     fn get_transaction_address(&self, h: &H256) -> Option<TransactionAddress> {
@@ -101,13 +145,55 @@ impl<T: 'static + KeyValueDB> ChainIndex for ChainKVStore<T> {
             .map(|raw| deserialize(&raw[..]).unwrap())
     }
 
+    fn get_pruned_below(&self) -> BlockNumber {
+        self.get(COLUMN_META, META_PRUNED_BELOW_KEY)
+            .map(|raw| deserialize(&raw[..]).unwrap())
+            .unwrap_or(0)
+    }
+
+    fn header_iter<'a>(
+        &'a self,
+        range: Range<BlockNumber>,
+    ) -> Box<dyn Iterator<Item = Header> + 'a> {
+        Box::new(
+            self.block_hash_iter(range)
+                .map(move |hash| self.get_header(&hash))
+                .take_while(Option::is_some)
+                .map(Option::unwrap),
+        )
+    }
+
+    fn block_iter<'a>(
+        &'a self,
+        range: Range<BlockNumber>,
+    ) -> Box<dyn Iterator<Item = Block> + 'a> {
+        Box::new(
+            self.block_hash_iter(range)
+                .map(move |hash| self.get_block(&hash))
+                .take_while(Option::is_some)
+                .map(Option::unwrap),
+        )
+    }
+
+    fn get_deployment_state(&self, bit: u8, boundary: &H256) -> Option<ThresholdState> {
+        self.get(COLUMN_DEPLOYMENT_STATE, &deployment_state_key(bit, boundary))
+            .map(|raw| deserialize(&raw[..]).unwrap())
+    }
+
     fn insert_tip_header(&self, batch: &mut Batch, h: &Header) {
         batch.insert(COLUMN_META, META_TIP_HEADER_KEY.to_vec(), h.hash().to_vec());
     }
 
+    fn insert_pruned_below(&self, batch: &mut Batch, number: BlockNumber) {
+        batch.insert(
+            COLUMN_META,
+            META_PRUNED_BELOW_KEY.to_vec(),
+            serialize(&number).unwrap(),
+        );
+    }
+
     fn insert_block_hash(&self, batch: &mut Batch, number: BlockNumber, hash: &H256) {
-        let key = serialize(&number).unwrap();
-        batch.insert(COLUMN_INDEX, key, hash.to_vec());
+        batch.insert(COLUMN_BLOCK_HASH, number.to_be_bytes().to_vec(), hash.to_vec());
     }
 
     fn insert_block_number(&self, batch: &mut Batch, hash: &H256, number: BlockNumber) {
@@ -124,6 +210,7 @@ impl<T: 'static + KeyValueDB> ChainIndex for ChainKVStore<T> {
         for (id, tx) in txs.iter().enumerate() {
             let address = TransactionAddress {
                 block_hash: block_hash.clone(),
+                index: id,
                 offset: addresses[id].offset,
                 length: addresses[id].length,
             };
@@ -135,6 +222,19 @@ impl<T: 'static + KeyValueDB> ChainIndex for ChainKVStore<T> {
         }
     }
 
+    fn insert_pruned_transaction_address(
+        &self,
+        batch: &mut Batch,
+        tx_hash: &H256,
+        address: &TransactionAddress,
+    ) {
+        batch.insert(
+            COLUMN_TRANSACTION_ADDR,
+            tx_hash.to_vec(),
+            serialize(address).unwrap(),
+        );
+    }
+
     fn delete_transaction_address(&self, batch: &mut Batch, txs: &[Transaction]) {
         for tx in txs {
             batch.delete(COLUMN_TRANSACTION_ADDR, tx.hash().to_vec());
@@ -142,13 +242,26 @@ impl<T: 'static + KeyValueDB> ChainIndex for ChainKVStore<T> {
     }
 
     fn delete_block_hash(&self, batch: &mut Batch, number: BlockNumber) {
-        let key = serialize(&number).unwrap();
-        batch.delete(COLUMN_INDEX, key);
+        batch.delete(COLUMN_BLOCK_HASH, number.to_be_bytes().to_vec());
     }
 
     fn delete_block_number(&self, batch: &mut Batch, hash: &H256) {
         batch.delete(COLUMN_INDEX, hash.to_vec());
     }
+
+    fn insert_deployment_state(
+        &self,
+        batch: &mut Batch,
+        bit: u8,
+        boundary: &H256,
+        state: ThresholdState,
+    ) {
+        batch.insert(
+            COLUMN_DEPLOYMENT_STATE,
+            deployment_state_key(bit, boundary),
+            serialize(&state).unwrap(),
+        );
+    }
 }
 
 #[cfg(test)]
@@ -156,6 +269,8 @@ mod tests {
     use super::super::COLUMNS;
     use super::*;
     use ckb_chain_spec::consensus::Consensus;
+    use ckb_core::block::BlockBuilder;
+    use ckb_core::header::HeaderBuilder;
     use ckb_db::diskdb::RocksDB;
     use tempfile;
 
@@ -184,5 +299,49 @@ mod tests {
         );
 
         assert_eq!(block.header(), &store.get_tip_header().unwrap());
+
+        for (index, tx) in block.commit_transactions().iter().enumerate() {
+            let address = store.get_transaction_address(&tx.hash()).unwrap();
+            assert_eq!(hash, address.block_hash);
+            assert_eq!(index, address.index);
+            assert_eq!(Some(tx), store.get_transaction(&tx.hash()).as_ref());
+        }
+    }
+
+    #[test]
+    fn block_and_header_iter() {
+        let tmp_dir = tempfile::Builder::new()
+            .prefix("block_and_header_iter")
+            .tempdir()
+            .unwrap();
+        let db = RocksDB::open(tmp_dir, COLUMNS);
+        let store = ChainKVStore::new(db);
+        // More than 256 blocks, so that the little endian order would differ
+        let blocks: Vec<Block> = (0..300)
+            .map(|number| {
+                BlockBuilder::default()
+                    .header(HeaderBuilder::default().number(number).build())
+                    .build()
+            })
+            .collect();
+        store
+            .save_with_batch(|batch| {
+                for block in &blocks {
+                    store.insert_block(batch, block);
+                    store.insert_block_hash(batch, block.header().number(), &block.header().hash());
+                }
+                Ok(())
+            })
+            .unwrap();
+
+        let headers: Vec<Header> = store.header_iter(250..260).collect();
+        let expected: Vec<Header> = blocks[250..260]
+            .iter()
+            .map(|block| block.header().clone())
+            .collect();
+        assert_eq!(headers, expected);
+        assert_eq!(store.block_iter(0..300).collect::<Vec<_>>(), blocks);
+        assert_eq!(store.block_iter(290..400).count(), 10);
+        assert_eq!(store.block_iter(5..5).count(), 0);
     }
 }
@@ -7,21 +7,26 @@
 //! - [Chain](chain::chain::Chain) represent a struct which
 //!   implement `ChainProvider`
 
+pub mod ancient;
 pub mod block_median_time_context;
 pub mod cachedb;
+pub mod check;
 pub mod error;
 mod flat_serializer;
 pub mod index;
+pub mod metricsdb;
+pub mod migration;
 pub mod shared;
 pub mod store;
 #[cfg(test)]
 mod tests;
 pub mod txo_set;
+pub mod versionbits;
 
 use ckb_db::batch::Col;
 
 // REMEMBER to update the const defined in util/avl/src/lib.rs as well
-pub const COLUMNS: u32 = 12;
+pub const COLUMNS: u32 = 16;
 pub const COLUMN_INDEX: Col = Some(0);
 pub const COLUMN_BLOCK_HEADER: Col = Some(1);
 pub const COLUMN_BLOCK_BODY: Col = Some(2);
@@ -32,3 +37,33 @@ pub const COLUMN_EXT: Col = Some(7);
 pub const COLUMN_BLOCK_TRANSACTION_ADDRESSES: Col = Some(9);
 pub const COLUMN_BLOCK_TRANSACTION_IDS: Col = Some(10);
 pub const COLUMN_BLOCK_PROPOSAL_IDS: Col = Some(11);
+pub const COLUMN_PRUNED_BLOCK: Col = Some(12);
+pub const COLUMN_PRUNED_TRANSACTION: Col = Some(13);
+// Main chain block hashes keyed by big endian number, so they are stored in order
+pub const COLUMN_BLOCK_HASH: Col = Some(14);
+pub const COLUMN_DEPLOYMENT_STATE: Col = Some(15);
+
+/// Names of the columns, so that they can be sized and compacted one by one
+pub const COLUMN_NAMES: &[(Col, &str)] = &[
+    (COLUMN_INDEX, "index"),
+    (COLUMN_BLOCK_HEADER, "headers"),
+    (COLUMN_BLOCK_BODY, "bodies"),
+    (COLUMN_BLOCK_UNCLE, "uncles"),
+    (COLUMN_META, "meta"),
+    (COLUMN_TRANSACTION_ADDR, "transaction_addresses"),
+    (COLUMN_EXT, "block_exts"),
+    (COLUMN_BLOCK_TRANSACTION_ADDRESSES, "body_addresses"),
+    (COLUMN_BLOCK_TRANSACTION_IDS, "body_transaction_ids"),
+    (COLUMN_BLOCK_PROPOSAL_IDS, "proposal_ids"),
+    (COLUMN_PRUNED_BLOCK, "pruned_blocks"),
+    (COLUMN_PRUNED_TRANSACTION, "pruned_transactions"),
+    (COLUMN_BLOCK_HASH, "block_hashes"),
+    (COLUMN_DEPLOYMENT_STATE, "deployment_states"),
+];
+
+pub fn column_by_name(name: &str) -> Option<Col> {
+    COLUMN_NAMES
+        .iter()
+        .find(|(_, column_name)| *column_name == name)
+        .map(|(col, _)| *col)
+}
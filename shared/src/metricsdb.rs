@@ -0,0 +1,198 @@
+//! Counts of the reads, writes and scans of every column of a db and their
+//! latencies, reported to the [ckb_metrics] registry as
+//! `db_operations_total` and `db_operation_duration_us`, labelled by
+//! `column` and `operation`. Batch writes are timed as a whole.
+
+use ckb_db::batch::{Batch, Col, Operation};
+use ckb_db::kvdb::{KeyValueDB, KeyValueIter, Result};
+use ckb_metrics::{registry, Counter, Histogram, LATENCY_BUCKETS};
+use fnv::FnvHashMap;
+use std::ops::Range;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Instant;
+
+const OPERATIONS: &str = "db_operations_total";
+const DURATIONS: &str = "db_operation_duration_us";
+
+struct ColumnMetrics {
+    gets: Arc<Counter>,
+    puts: Arc<Counter>,
+    deletes: Arc<Counter>,
+    iterates: Arc<Counter>,
+    get_durations: Arc<Histogram>,
+    iterate_durations: Arc<Histogram>,
+}
+
+impl ColumnMetrics {
+    fn new(column: &str) -> Self {
+        let labels = |operation: &str| {
+            vec![
+                ("column", column.to_owned()),
+                ("operation", operation.to_owned()),
+            ]
+        };
+        let counter = |operation| registry().counter(OPERATIONS, labels(operation));
+        let histogram =
+            |operation| registry().histogram(DURATIONS, labels(operation), LATENCY_BUCKETS);
+        ColumnMetrics {
+            gets: counter("get"),
+            puts: counter("put"),
+            deletes: counter("delete"),
+            iterates: counter("iterate"),
+            get_durations: histogram("get"),
+            iterate_durations: histogram("iterate"),
+        }
+    }
+}
+
+pub struct MetricsDB<T>
+where
+    T: KeyValueDB,
+{
+    db: T,
+    columns: FnvHashMap<Col, ColumnMetrics>,
+    write_durations: Arc<Histogram>,
+}
+
+impl<T> MetricsDB<T>
+where
+    T: KeyValueDB,
+{
+    /// Columns missing from `names` are labelled by index
+    pub fn new(db: T, names: &[(Col, &str)]) -> Self {
+        let name = |col: Col| {
+            names
+                .iter()
+                .find(|(c, _)| *c == col)
+                .map(|(_, name)| (*name).to_owned())
+                .unwrap_or_else(|| match col {
+                    Some(col) => format!("c{}", col),
+                    None => "default".to_owned(),
+                })
+        };
+        let columns = (0..db.cols())
+            .map(Some)
+            .chain(Some(None))
+            .map(|col| (col, ColumnMetrics::new(&name(col))))
+            .collect();
+        let write_labels = vec![
+            ("column", "all".to_owned()),
+            ("operation", "write".to_owned()),
+        ];
+        MetricsDB {
+            db,
+            columns,
+            write_durations: registry().histogram(DURATIONS, write_labels, LATENCY_BUCKETS),
+        }
+    }
+
+    fn get<R, F: FnOnce(&T) -> Result<R>>(&self, col: Col, f: F) -> Result<R> {
+        let start = Instant::now();
+        let result = f(&self.db);
+        if let Some(metrics) = self.columns.get(&col) {
+            metrics.gets.inc();
+            metrics.get_durations.observe_duration(start.elapsed());
+        }
+        result
+    }
+}
+
+impl<T> KeyValueDB for MetricsDB<T>
+where
+    T: KeyValueDB,
+{
+    fn cols(&self) -> u32 {
+        self.db.cols()
+    }
+
+    fn write(&self, batch: Batch) -> Result<()> {
+        for op in &batch.operations {
+            let (col, put) = match op {
+                Operation::Insert { col, .. } => (col, true),
+                Operation::Delete { col, .. } => (col, false),
+            };
+            if let Some(metrics) = self.columns.get(col) {
+                if put {
+                    metrics.puts.inc();
+                } else {
+                    metrics.deletes.inc();
+                }
+            }
+        }
+        let start = Instant::now();
+        let result = self.db.write(batch);
+        self.write_durations.observe_duration(start.elapsed());
+        result
+    }
+
+    fn read(&self, col: Col, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.get(col, |db| db.read(col, key))
+    }
+
+    fn len(&self, col: Col, key: &[u8]) -> Result<Option<usize>> {
+        self.get(col, |db| db.len(col, key))
+    }
+
+    fn partial_read(&self, col: Col, key: &[u8], range: &Range<usize>) -> Result<Option<Vec<u8>>> {
+        self.get(col, |db| db.partial_read(col, key, range))
+    }
+
+    fn iter_from<'a>(&'a self, col: Col, from: &[u8]) -> Result<KeyValueIter<'a>> {
+        let start = Instant::now();
+        let result = self.db.iter_from(col, from);
+        if let Some(metrics) = self.columns.get(&col) {
+            metrics.iterates.inc();
+            metrics.iterate_durations.observe_duration(start.elapsed());
+        }
+        result
+    }
+
+    fn size(&self, col: Col) -> Result<u64> {
+        self.db.size(col)
+    }
+
+    fn compact(&self, col: Col) -> Result<()> {
+        self.db.compact(col)
+    }
+
+    fn backup(&self, path: &Path) -> Result<()> {
+        self.db.backup(path)
+    }
+
+    fn sync(&self) -> Result<()> {
+        self.db.sync()
+    }
+
+    fn catch_up(&self) -> Result<()> {
+        self.db.catch_up()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ckb_db::memorydb::MemoryKeyValueDB;
+
+    #[test]
+    fn count_operations() {
+        let db = MetricsDB::new(MemoryKeyValueDB::open(2), &[(Some(1), "metrics_test")]);
+        let metrics = &db.columns[&Some(1)];
+        let (gets, puts, deletes) = (metrics.gets.get(), metrics.puts.get(), metrics.deletes.get());
+
+        let mut batch = Batch::default();
+        batch.insert(Some(1), vec![1], vec![1]);
+        batch.insert(Some(1), vec![2], vec![2]);
+        batch.delete(Some(1), vec![3]);
+        db.write(batch).unwrap();
+        assert_eq!(db.read(Some(1), &[1]).unwrap(), Some(vec![1]));
+        assert_eq!(db.len(Some(1), &[2]).unwrap(), Some(1));
+        assert_eq!(db.iter_prefix(Some(1), &[]).unwrap().count(), 2);
+
+        assert_eq!(metrics.gets.get() - gets, 2);
+        assert_eq!(metrics.puts.get() - puts, 2);
+        assert_eq!(metrics.deletes.get() - deletes, 1);
+        assert!(metrics.iterates.get() >= 1);
+        assert!(metrics.get_durations.snapshot().count >= 2);
+    }
+}
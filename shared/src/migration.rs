@@ -0,0 +1,211 @@
+//! Upgrades of the chain DB layout.
+//!
+//! The layout version is stored in the meta column. A DB without one is
+//! either empty, and gets the current version, or predates versioning and is
+//! refused: its blocks are hashed and its headers, extras and block exts are
+//! encoded as before, which no migration can rewrite without the blocks
+//! being verified again, so it has to be synced again. Opening an older
+//! versioned DB runs the missing migrations in order, after backing it up,
+//! and stores the new version after each of them so an interrupted upgrade
+//! resumes where it stopped.
+
+use crate::error::SharedError;
+use crate::index::META_TIP_HEADER_KEY;
+use crate::{COLUMN_BLOCK_HASH, COLUMN_INDEX, COLUMN_META};
+use bincode::{deserialize, serialize};
+use ckb_db::batch::Batch;
+use ckb_db::kvdb::KeyValueDB;
+use log::info;
+use std::path::Path;
+
+pub const SCHEMA_VERSION: u32 = 2;
+const META_SCHEMA_VERSION_KEY: &[u8] = b"SCHEMA_VERSION";
+// Pairs written per batch by the migrations
+const MIGRATION_BATCH_SIZE: usize = 10_000;
+
+pub struct Migration {
+    /// Version of the DB once migrated
+    pub version: u32,
+    pub description: &'static str,
+    pub run: fn(&dyn KeyValueDB) -> Result<(), SharedError>,
+}
+
+pub const MIGRATIONS: &[Migration] = &[Migration {
+    version: 2,
+    description: "move the main chain block hashes to their own column",
+    run: move_block_hashes,
+}];
+
+pub fn schema_version(db: &dyn KeyValueDB) -> Result<Option<u32>, SharedError> {
+    Ok(db
+        .read(COLUMN_META, META_SCHEMA_VERSION_KEY)?
+        .map(|raw| deserialize(&raw[..]).expect("deserialize schema version")))
+}
+
+/// Brings `db` to [SCHEMA_VERSION], backing it up first to `backup` if there
+/// is anything to migrate.
+pub fn migrate<T: KeyValueDB>(db: &T, backup: Option<&Path>) -> Result<(), SharedError> {
+    let version = match schema_version(db)? {
+        Some(version) => version,
+        None if db.read(COLUMN_META, META_TIP_HEADER_KEY)?.is_none() => {
+            return set_schema_version(db, SCHEMA_VERSION);
+        }
+        None => {
+            return Err(SharedError::Migration(
+                "the db predates the versioned layout and cannot be migrated, \
+                 remove it to sync again"
+                    .to_string(),
+            ));
+        }
+    };
+    if version > SCHEMA_VERSION {
+        return Err(SharedError::Migration(format!(
+            "db schema version {} is newer than {}",
+            version, SCHEMA_VERSION
+        )));
+    }
+    if version == SCHEMA_VERSION {
+        return Ok(());
+    }
+
+    if let Some(backup) = backup {
+        info!(target: "shared", "backing up the db to {}", backup.display());
+        db.backup(backup)?;
+    }
+    for migration in MIGRATIONS.iter().filter(|m| m.version > version) {
+        info!(
+            target: "shared",
+            "migrating the db to version {}: {}", migration.version, migration.description
+        );
+        (migration.run)(db)?;
+        set_schema_version(db, migration.version)?;
+    }
+    info!(target: "shared", "db migrated to version {}", SCHEMA_VERSION);
+    Ok(())
+}
+
+fn set_schema_version(db: &dyn KeyValueDB, version: u32) -> Result<(), SharedError> {
+    let mut batch = Batch::new();
+    batch.insert(
+        COLUMN_META,
+        META_SCHEMA_VERSION_KEY.to_vec(),
+        serialize(&version).expect("serialize schema version"),
+    );
+    db.write(batch)?;
+    Ok(())
+}
+
+// The block hashes were indexed in the index column by bincode number, which
+// is little endian and so does not keep them in order. The other keys of the
+// index column are block hashes, a db already moved has nothing left to move.
+fn move_block_hashes(db: &dyn KeyValueDB) -> Result<(), SharedError> {
+    let mut moved = 0;
+    loop {
+        let mut batch = Batch::new();
+        for (key, value) in db
+            .iter_from(COLUMN_INDEX, &[])?
+            .filter(|(key, _)| key.len() == 8)
+            .take(MIGRATION_BATCH_SIZE)
+        {
+            let number: u64 = deserialize(&key[..]).expect("deserialize block number");
+            batch.insert(COLUMN_BLOCK_HASH, number.to_be_bytes().to_vec(), value);
+            batch.delete(COLUMN_INDEX, key);
+        }
+        if batch.operations.is_empty() {
+            return Ok(());
+        }
+        moved += batch.operations.len() / 2;
+        db.write(batch)?;
+        info!(target: "shared", "moved {} block hashes", moved);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::COLUMNS;
+    use ckb_db::diskdb::RocksDB;
+    use ckb_db::memorydb::MemoryKeyValueDB;
+    use tempfile;
+
+    #[test]
+    fn migrate_empty_db() {
+        let db = MemoryKeyValueDB::open(COLUMNS as usize);
+        migrate(&db, None).unwrap();
+        assert_eq!(schema_version(&db).unwrap(), Some(SCHEMA_VERSION));
+    }
+
+    #[test]
+    fn migrate_block_hashes() {
+        let tmp_dir = tempfile::Builder::new()
+            .prefix("migrate_block_hashes")
+            .tempdir()
+            .unwrap();
+        let db = MemoryKeyValueDB::open(COLUMNS as usize);
+        let mut batch = Batch::new();
+        batch.insert(COLUMN_META, META_TIP_HEADER_KEY.to_vec(), vec![0; 32]);
+        batch.insert(
+            COLUMN_META,
+            META_SCHEMA_VERSION_KEY.to_vec(),
+            serialize(&1u32).unwrap(),
+        );
+        batch.insert(COLUMN_INDEX, vec![1; 32], serialize(&1u64).unwrap());
+        for number in 0..300u64 {
+            let hash = number.to_le_bytes().to_vec();
+            batch.insert(COLUMN_INDEX, serialize(&number).unwrap(), hash);
+        }
+        db.write(batch).unwrap();
+
+        let backup_path = tmp_dir.path().join("backup");
+        migrate(&db, Some(&backup_path)).unwrap();
+        assert_eq!(schema_version(&db).unwrap(), Some(SCHEMA_VERSION));
+        let hashes: Vec<_> = db
+            .iter_from(COLUMN_BLOCK_HASH, &[])
+            .unwrap()
+            .map(|(_, value)| value)
+            .collect();
+        let expected: Vec<_> = (0..300u64).map(|n| n.to_le_bytes().to_vec()).collect();
+        assert_eq!(hashes, expected);
+        let index: Vec<_> = db.iter_from(COLUMN_INDEX, &[]).unwrap().collect();
+        assert_eq!(index, vec![(vec![1; 32], serialize(&1u64).unwrap())]);
+
+        // The backup is the db before the migration
+        let backup = RocksDB::open(&backup_path, COLUMNS);
+        assert_eq!(schema_version(&backup).unwrap(), Some(1));
+        assert_eq!(
+            backup.read(COLUMN_INDEX, &serialize(&7u64).unwrap()).unwrap(),
+            Some(7u64.to_le_bytes().to_vec())
+        );
+
+        // Nothing left to do, the existing backup is not touched
+        migrate(&db, Some(&backup_path)).unwrap();
+    }
+
+    #[test]
+    fn refuse_unversioned_db() {
+        let tmp_dir = tempfile::Builder::new()
+            .prefix("refuse_unversioned_db")
+            .tempdir()
+            .unwrap();
+        let db_path = tmp_dir.path().join("db");
+        // A db written before the layout was versioned: a tip and the block
+        // hashes indexed by bincode number
+        {
+            let db = RocksDB::open(&db_path, COLUMNS);
+            let mut batch = Batch::new();
+            batch.insert(COLUMN_META, META_TIP_HEADER_KEY.to_vec(), vec![0; 32]);
+            batch.insert(COLUMN_INDEX, serialize(&0u64).unwrap(), vec![0; 32]);
+            db.write(batch).unwrap();
+        }
+
+        let db = RocksDB::open(&db_path, COLUMNS);
+        let backup_path = tmp_dir.path().join("backup");
+        match migrate(&db, Some(&backup_path)) {
+            Err(SharedError::Migration(reason)) => assert!(reason.contains("sync again")),
+            x => panic!("Unexpected result {:?}", x),
+        }
+        // The db is left as it was
+        assert_eq!(schema_version(&db).unwrap(), None);
+        assert!(!backup_path.exists());
+    }
+}
@@ -1,27 +1,35 @@
+use crate::ancient::AncientStore;
 use crate::block_median_time_context::BlockMedianTimeContext;
 use crate::cachedb::CacheDB;
 use crate::error::SharedError;
 use crate::index::ChainIndex;
+use crate::metricsdb::MetricsDB;
+use crate::migration::migrate;
 use crate::store::ChainKVStore;
 use crate::txo_set::{TxoSet, TxoSetDiff};
-use crate::{COLUMNS, COLUMN_BLOCK_HEADER};
+use crate::{COLUMNS, COLUMN_BLOCK_HEADER, COLUMN_NAMES};
 use ckb_chain_spec::consensus::Consensus;
+use ckb_chain_spec::difficulty::DifficultyProvider;
 use ckb_core::block::Block;
 use ckb_core::cell::{CellProvider, CellStatus};
-use ckb_core::extras::BlockExt;
+use ckb_core::extras::{BlockExt, TransactionAddress};
 use ckb_core::header::{BlockNumber, Header};
-use ckb_core::transaction::{Capacity, OutPoint, ProposalShortId, Transaction};
+use ckb_core::transaction::{Capacity, CellOutput, OutPoint, ProposalShortId, Transaction};
 use ckb_core::uncle::UncleBlock;
+use ckb_db::config::DBConfig;
 use ckb_db::diskdb::RocksDB;
 use ckb_db::kvdb::KeyValueDB;
 use ckb_db::memorydb::MemoryKeyValueDB;
-use ckb_util::RwLock;
+use ckb_util::{Mutex, RwLock};
 use fnv::FnvHashSet;
+use lru_cache::LruCache;
 use numext_fixed_hash::H256;
 use numext_fixed_uint::U256;
 use std::path::Path;
 use std::sync::Arc;
 
+pub const CELL_CACHE_SIZE: usize = 65536;
+
 #[derive(Default, Debug, PartialEq, Clone, Eq)]
 pub struct ChainState {
     tip_header: Header,
@@ -79,6 +87,9 @@ pub struct Shared<CI> {
     store: Arc<CI>,
     chain_state: Arc<RwLock<ChainState>>,
     consensus: Arc<Consensus>,
+    // Outputs never change once stored, so the cache is only filled and
+    // evicted, the spent state is always read from the txo set
+    cell_cache: Arc<Mutex<LruCache<OutPoint, CellOutput>>>,
 }
 
 // https://github.com/rust-lang/rust/issues/40754
@@ -88,12 +99,17 @@ impl<CI: ChainIndex> ::std::clone::Clone for Shared<CI> {
             store: Arc::clone(&self.store),
             chain_state: Arc::clone(&self.chain_state),
             consensus: Arc::clone(&self.consensus),
+            cell_cache: Arc::clone(&self.cell_cache),
         }
     }
 }
 
 impl<CI: ChainIndex> Shared<CI> {
     pub fn new(store: CI, consensus: Consensus) -> Self {
+        Self::with_cell_cache_size(store, consensus, CELL_CACHE_SIZE)
+    }
+
+    pub fn with_cell_cache_size(store: CI, consensus: Consensus, cell_cache_size: usize) -> Self {
         let chain_state = {
             // check head in store or save the genesis block as head
             let header = {
@@ -125,6 +141,7 @@ impl<CI: ChainIndex> Shared<CI> {
             store: Arc::new(store),
             chain_state,
             consensus: Arc::new(consensus),
+            cell_cache: Arc::new(Mutex::new(LruCache::new(cell_cache_size))),
         }
     }
 
@@ -136,21 +153,52 @@ impl<CI: ChainIndex> Shared<CI> {
         &self.store
     }
 
+    /// Caches the outputs of `transactions`, so that spending them soon
+    /// after does not hit the store
+    pub fn warm_cell_cache(&self, transactions: &[Transaction]) {
+        let mut cell_cache = self.cell_cache.lock();
+        for transaction in transactions {
+            let hash = transaction.hash();
+            for (index, output) in transaction.outputs().iter().enumerate() {
+                cell_cache.insert(OutPoint::new(hash.clone(), index as u32), output.clone());
+            }
+        }
+    }
+
     pub fn init_txo_set(store: &CI, number: u64) -> TxoSet {
         let mut txo_set = TxoSet::new();
 
         for n in 0..=number {
             let hash = store.get_block_hash(n).unwrap();
-            for tx in store.get_block_body(&hash).unwrap() {
-                let inputs = tx.input_pts();
-                let tx_hash = tx.hash();
-                let output_len = tx.outputs().len();
-
-                for o in inputs {
-                    txo_set.mark_spent(&o);
+            match store.get_block_body(&hash) {
+                Some(txs) => {
+                    for tx in txs {
+                        let inputs = tx.input_pts();
+                        let tx_hash = tx.hash();
+                        let output_len = tx.outputs().len();
+
+                        for o in inputs {
+                            txo_set.mark_spent(&o);
+                        }
+
+                        txo_set.insert(tx_hash, output_len);
+                    }
+                }
+                None => {
+                    let pruned = store
+                        .get_pruned_block(&hash)
+                        .expect("pruned block must be stored");
+                    // fully spent transactions are gone, their outputs become unknown
+                    for tx_hash in pruned.live_transactions {
+                        let tx = store
+                            .get_transaction(&tx_hash)
+                            .expect("live transaction must be stored");
+                        txo_set.insert(tx_hash, tx.outputs().len());
+                    }
+                    for o in pruned.inputs {
+                        txo_set.mark_spent(&o);
+                    }
                 }
-
-                txo_set.insert(tx_hash, output_len);
             }
         }
 
@@ -173,11 +221,18 @@ impl<CI: ChainIndex> CellProvider for Shared<CI> {
             if f {
                 CellStatus::Dead
             } else {
+                if let Some(output) = self.cell_cache.lock().get_mut(out_point) {
+                    return CellStatus::Live(output.clone());
+                }
                 let transaction = self
                     .store
                     .get_transaction(&out_point.hash)
                     .expect("transaction must exist");
-                CellStatus::Live(transaction.outputs()[index].clone())
+                let output = transaction.outputs()[index].clone();
+                self.cell_cache
+                    .lock()
+                    .insert(out_point.clone(), output.clone());
+                CellStatus::Live(output)
             }
         } else {
             CellStatus::Unknown
@@ -208,6 +263,9 @@ pub trait ChainProvider: Sync + Send {
 
     fn get_transaction(&self, hash: &H256) -> Option<Transaction>;
 
+    /// The block hash and position of a committed transaction, always indexed
+    fn get_transaction_address(&self, hash: &H256) -> Option<TransactionAddress>;
+
     fn contain_transaction(&self, hash: &H256) -> bool;
 
     fn block_reward(&self, block_number: BlockNumber) -> Capacity;
@@ -266,13 +324,16 @@ impl<CI: ChainIndex> ChainProvider for Shared<CI> {
         self.store.get_transaction(hash)
     }
 
+    fn get_transaction_address(&self, hash: &H256) -> Option<TransactionAddress> {
+        self.store.get_transaction_address(hash)
+    }
+
     fn contain_transaction(&self, hash: &H256) -> bool {
         self.store.get_transaction_address(hash).is_some()
     }
 
-    fn block_reward(&self, _block_number: BlockNumber) -> Capacity {
-        // TODO: block reward calculation algorithm
-        self.consensus.initial_block_reward()
+    fn block_reward(&self, block_number: BlockNumber) -> Capacity {
+        self.consensus.block_reward(block_number)
     }
 
     fn get_ancestor(&self, base: &H256, number: BlockNumber) -> Option<Header> {
@@ -371,54 +432,28 @@ impl<CI: ChainIndex> ChainProvider for Shared<CI> {
         Ok(fee)
     }
 
-    // T_interval = L / C_m
-    // HR_m = HR_last/ (1 + o)
-    // Diff= HR_m * T_interval / H = Diff_last * o_last / o
-    #[allow(clippy::op_ref)]
     fn calculate_difficulty(&self, last: &Header) -> Option<U256> {
-        let last_hash = last.hash();
-        let last_number = last.number();
-        let last_difficulty = last.difficulty();
-
-        let interval = self.consensus.difficulty_adjustment_interval();
+        self.consensus
+            .difficulty_engine()
+            .next_difficulty(&self.consensus, self, last)
+    }
 
-        if (last_number + 1) % interval != 0 {
-            return Some(last_difficulty.clone());
-        }
+    fn consensus(&self) -> &Consensus {
+        &*self.consensus
+    }
+}
 
-        let start = last_number.saturating_sub(interval);
-        if let Some(start_header) = self.get_ancestor(&last_hash, start) {
-            let start_total_uncles_count = self
-                .block_ext(&start_header.hash())
-                .expect("block_ext exist")
-                .total_uncles_count;
-
-            let last_total_uncles_count = self
-                .block_ext(&last_hash)
-                .expect("block_ext exist")
-                .total_uncles_count;
-
-            let difficulty = last_difficulty
-                * U256::from(last_total_uncles_count - start_total_uncles_count)
-                * U256::from((1.0 / self.consensus.orphan_rate_target()) as u64)
-                / U256::from(interval);
-
-            let min_difficulty = self.consensus.min_difficulty();
-            let max_difficulty = last_difficulty * 2u32;
-            if &difficulty > &max_difficulty {
-                return Some(max_difficulty);
-            }
+impl<CI: ChainIndex> DifficultyProvider for Shared<CI> {
+    fn header(&self, hash: &H256) -> Option<Header> {
+        self.block_header(hash)
+    }
 
-            if &difficulty < min_difficulty {
-                return Some(min_difficulty.clone());
-            }
-            return Some(difficulty);
-        }
-        None
+    fn ancestor(&self, base: &H256, number: BlockNumber) -> Option<Header> {
+        self.get_ancestor(base, number)
     }
 
-    fn consensus(&self) -> &Consensus {
-        &*self.consensus
+    fn total_uncles_count(&self, hash: &H256) -> Option<u64> {
+        self.block_ext(hash).map(|ext| ext.total_uncles_count)
     }
 }
 
@@ -438,20 +473,33 @@ impl<CI: ChainIndex> BlockMedianTimeContext for Shared<CI> {
 pub struct SharedBuilder<CI> {
     store: CI,
     consensus: Option<Consensus>,
+    cell_cache_size: usize,
 }
 
 impl<CI: ChainIndex> SharedBuilder<CI> {
     pub fn new_memory() -> SharedBuilder<ChainKVStore<MemoryKeyValueDB>> {
         let db = MemoryKeyValueDB::open(COLUMNS as usize);
+        migrate(&db, None).expect("db migration");
         SharedBuilder::<ChainKVStore<MemoryKeyValueDB>>::new_simple(db)
     }
 
-    pub fn new_rocks<P: AsRef<Path>>(path: P) -> SharedBuilder<ChainKVStore<CacheDB<RocksDB>>> {
-        let db = CacheDB::new(
-            RocksDB::open(path, COLUMNS),
-            &[(COLUMN_BLOCK_HEADER.unwrap(), 4096)],
-        );
-        SharedBuilder::<ChainKVStore<CacheDB<RocksDB>>>::new_simple(db)
+    /// Opens the db at `path`, migrating it if its layout is older, in which
+    /// case it is backed up first to `path` with a `backup` extension, which
+    /// must then be missing or empty.
+    pub fn new_rocks<P: AsRef<Path>>(
+        path: P,
+        config: &DBConfig,
+    ) -> SharedBuilder<ChainKVStore<CacheDB<MetricsDB<RocksDB>>>> {
+        let backup_path = path.as_ref().with_extension("backup");
+        let db = RocksDB::open_with_config(path, COLUMNS, config);
+        migrate(&db, Some(&backup_path)).unwrap_or_else(|err| match err {
+            SharedError::Migration(reason) => panic!("db migration failed: {}", reason),
+            err => panic!("db migration failed: {:?}", err),
+        });
+        // Below the cache, so that only the operations reaching RocksDB count
+        let db = MetricsDB::new(db, COLUMN_NAMES);
+        let db = CacheDB::new(db, &[(COLUMN_BLOCK_HEADER.unwrap(), 4096)]);
+        SharedBuilder::<ChainKVStore<CacheDB<MetricsDB<RocksDB>>>>::new_simple(db)
     }
 
     pub fn new_simple<T: 'static + KeyValueDB>(db: T) -> SharedBuilder<ChainKVStore<T>> {
@@ -460,6 +508,7 @@ impl<CI: ChainIndex> SharedBuilder<CI> {
         SharedBuilder {
             store: ChainKVStore::new(db),
             consensus: Some(consensus),
+            cell_cache_size: CELL_CACHE_SIZE,
         }
     }
 
@@ -468,8 +517,23 @@ impl<CI: ChainIndex> SharedBuilder<CI> {
         self
     }
 
+    pub fn cell_cache_size(mut self, value: usize) -> Self {
+        self.cell_cache_size = value;
+        self
+    }
+
     pub fn build(self) -> Shared<CI> {
         let consensus = self.consensus.unwrap_or_else(Consensus::default);
-        Shared::new(self.store, consensus)
+        Shared::with_cell_cache_size(self.store, consensus, self.cell_cache_size)
+    }
+}
+
+impl<T: 'static + KeyValueDB> SharedBuilder<ChainKVStore<T>> {
+    /// Keeps the bodies of the old main chain blocks in an ancient store at
+    /// `path`
+    pub fn ancient<P: AsRef<Path>>(mut self, path: P) -> Self {
+        let ancient = AncientStore::open(path).expect("ancient store open");
+        self.store = self.store.with_ancient(ancient);
+        self
     }
 }
@@ -1,30 +1,47 @@
+use crate::ancient::AncientStore;
 use crate::error::SharedError;
 use crate::flat_serializer::{serialize as flat_serialize, Address};
+use crate::txo_set::TxoSet;
 use crate::{
-    COLUMN_BLOCK_BODY, COLUMN_BLOCK_HEADER, COLUMN_BLOCK_PROPOSAL_IDS,
-    COLUMN_BLOCK_TRANSACTION_ADDRESSES, COLUMN_BLOCK_TRANSACTION_IDS, COLUMN_BLOCK_UNCLE,
-    COLUMN_EXT,
+    column_by_name, COLUMN_BLOCK_BODY, COLUMN_BLOCK_HASH, COLUMN_BLOCK_HEADER,
+    COLUMN_BLOCK_PROPOSAL_IDS, COLUMN_BLOCK_TRANSACTION_ADDRESSES, COLUMN_BLOCK_TRANSACTION_IDS,
+    COLUMN_BLOCK_UNCLE, COLUMN_EXT, COLUMN_INDEX, COLUMN_META, COLUMN_NAMES, COLUMN_PRUNED_BLOCK,
+    COLUMN_PRUNED_TRANSACTION, COLUMN_TRANSACTION_ADDR,
 };
 use bincode::{deserialize, serialize};
 use ckb_core::block::{Block, BlockBuilder};
-use ckb_core::extras::BlockExt;
-use ckb_core::header::{Header, HeaderBuilder};
-use ckb_core::transaction::{ProposalShortId, Transaction, TransactionBuilder};
+use ckb_core::extras::{BlockExt, TransactionAddress};
+use ckb_core::header::{BlockNumber, Header, HeaderBuilder};
+use ckb_core::transaction::{OutPoint, ProposalShortId, Transaction, TransactionBuilder};
 use ckb_core::uncle::UncleBlock;
 use ckb_db::batch::{Batch, Col};
 use ckb_db::kvdb::KeyValueDB;
 use numext_fixed_hash::H256;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::ops::Range;
+use std::path::Path;
 use std::sync::Arc;
 
+// Bodies of the main chain blocks below this number are in the ancient store
+const META_FROZEN_BELOW_KEY: &[u8] = b"FROZEN_BELOW";
+
 pub struct ChainKVStore<T: KeyValueDB> {
     pub db: Arc<T>,
+    ancient: Option<AncientStore>,
 }
 
 impl<T: 'static + KeyValueDB> ChainKVStore<T> {
     pub fn new(db: T) -> Self {
         let db = Arc::new(db);
-        ChainKVStore { db }
+        ChainKVStore { db, ancient: None }
+    }
+
+    /// Keeps the bodies of the old main chain blocks in `ancient`, see
+    /// [ChainStore::freeze_block](ChainStore::freeze_block)
+    pub fn with_ancient(mut self, ancient: AncientStore) -> Self {
+        self.ancient = Some(ancient);
+        self
     }
 
     pub fn get(&self, col: Col, key: &[u8]) -> Option<Vec<u8>> {
@@ -36,6 +53,78 @@ impl<T: 'static + KeyValueDB> ChainKVStore<T> {
             .partial_read(col, key, range)
             .expect("db operation should be ok")
     }
+
+    /// Reads what the node wrote since, when the db is a follower of the db
+    /// of a node, see [RocksDB::open_secondary](ckb_db::diskdb::RocksDB::open_secondary)
+    pub fn catch_up(&self) {
+        self.db.catch_up().expect("db operation should be ok");
+    }
+
+    /// Approximate bytes stored in every column, by name
+    pub fn column_sizes(&self) -> Vec<(&'static str, u64)> {
+        COLUMN_NAMES
+            .iter()
+            .map(|(col, name)| {
+                let size = self.db.size(*col).expect("db operation should be ok");
+                (*name, size)
+            })
+            .collect()
+    }
+
+    /// Compacts the column `name`, returns false when there is no such column
+    pub fn compact_column(&self, name: &str) -> bool {
+        match column_by_name(name) {
+            Some(col) => {
+                self.db.compact(col).expect("db operation should be ok");
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Serialized body of a block, or the `range` of it, from the db or the
+    /// ancient store
+    pub fn get_body(&self, h: &H256, range: Option<&Range<usize>>) -> Option<Vec<u8>> {
+        let body = match range {
+            Some(range) => self.partial_get(COLUMN_BLOCK_BODY, h.as_bytes(), range),
+            None => self.get(COLUMN_BLOCK_BODY, h.as_bytes()),
+        };
+        body.or_else(|| {
+            let ancient = self.ancient.as_ref()?;
+            // Only main chain blocks are indexed by hash
+            let number: BlockNumber = self
+                .get(COLUMN_INDEX, h.as_bytes())
+                .map(|raw| deserialize(&raw[..]).unwrap())?;
+            ancient
+                .read(number, range)
+                .expect("ancient store operation should be ok")
+        })
+    }
+
+    /// Visits the main chain block hashes in `range` in order, with a single
+    /// scan over the block hash index.
+    pub fn block_hash_iter<'a>(
+        &'a self,
+        range: Range<BlockNumber>,
+    ) -> impl Iterator<Item = H256> + 'a {
+        self.db
+            .iter_range(
+                COLUMN_BLOCK_HASH,
+                &range.start.to_be_bytes(),
+                &range.end.to_be_bytes(),
+            )
+            .expect("db operation should be ok")
+            .map(|(_, value)| H256::from_slice(&value).expect("db safe access"))
+    }
+}
+
+/// What is left of a block once its body is pruned: the cells it spent, so
+/// that the txo set can still be rebuilt, and the transactions which still
+/// have live outputs.
+#[derive(Clone, Serialize, Deserialize, PartialEq, Eq, Debug, Default)]
+pub struct PrunedBlock {
+    pub inputs: Vec<OutPoint>,
+    pub live_transactions: Vec<H256>,
 }
 
 pub struct ChainStoreHeaderIterator<'a, T: ChainStore>
@@ -49,16 +138,55 @@ where
 pub trait ChainStore: Sync + Send {
     fn get_block(&self, block_hash: &H256) -> Option<Block>;
     fn get_header(&self, block_hash: &H256) -> Option<Header>;
+    /// Whether the header of `block_hash` is stored, telling the db errors
+    /// the other getters panic on
+    fn contains_header(&self, block_hash: &H256) -> Result<bool, SharedError>;
     fn get_block_body(&self, block_hash: &H256) -> Option<Vec<Transaction>>;
     fn get_block_proposal_txs_ids(&self, h: &H256) -> Option<Vec<ProposalShortId>>;
     fn get_block_uncles(&self, block_hash: &H256) -> Option<Vec<UncleBlock>>;
     fn get_block_ext(&self, block_hash: &H256) -> Option<BlockExt>;
+    fn get_pruned_block(&self, block_hash: &H256) -> Option<PrunedBlock>;
     fn insert_block(&self, batch: &mut Batch, b: &Block);
     fn insert_block_ext(&self, batch: &mut Batch, block_hash: &H256, ext: &BlockExt);
+    /// Drops a block with its ext, as if it were never received. Its indexes
+    /// are left to the caller.
+    fn delete_block(&self, batch: &mut Batch, block_hash: &H256);
+    /// Drops the body of a block, keeping the transactions whose outputs are
+    /// not fully spent in `txo_set`.
+    fn prune_block(&self, batch: &mut Batch, b: &Block, txo_set: &TxoSet);
+    /// Drops the transactions of `tx_hashes` kept by the pruned blocks once
+    /// their outputs are fully spent in `txo_set`, the ones the blocks pruned
+    /// in `batch` spend.
+    fn prune_spent_transactions(&self, batch: &mut Batch, tx_hashes: &[H256], txo_set: &TxoSet);
+    /// Stores a block whose body is already pruned, the commit transactions
+    /// of `b` are only the ones still having live outputs.
+    fn insert_pruned_block(&self, batch: &mut Batch, b: &Block, pruned: &PrunedBlock);
+    /// Writes what `f` puts in the batch in a single atomic write, and
+    /// nothing if `f` fails.
     fn save_with_batch<F: FnOnce(&mut Batch) -> Result<(), SharedError>>(
         &self,
         f: F,
     ) -> Result<(), SharedError>;
+    /// Number of main chain blocks whose body was moved to the ancient store,
+    /// `None` without one. The ancient store may hold a few more bodies,
+    /// appended before a crash lost the batch dropping them from the db.
+    fn ancient_len(&self) -> Option<BlockNumber>;
+    /// Appends the body of the main chain block `number` to the ancient store
+    /// and drops it from the db. Blocks are frozen in order, the next one is
+    /// `ancient_len`, and freezing one appended before a crash only drops it.
+    fn freeze_block(
+        &self,
+        batch: &mut Batch,
+        number: BlockNumber,
+        hash: &H256,
+    ) -> Result<(), SharedError>;
+    /// Copies the store as it is now to `dir`, the db to `dir/db` and the
+    /// ancient store, if any, to `dir/ancient`, which must be missing or
+    /// empty. The copy is laid out as the data directory of a node.
+    fn backup(&self, dir: &Path) -> Result<(), SharedError>;
+    /// Makes the db writes done so far durable, the ancient store is synced
+    /// on every append
+    fn sync(&self) -> Result<(), SharedError>;
 
     /// Visits block headers backward to genesis.
     fn headers_iter<'a>(&'a self, head: Header) -> ChainStoreHeaderIterator<'a, Self>
@@ -101,22 +229,23 @@ impl<'a, T: ChainStore> Iterator for ChainStoreHeaderIterator<'a, T> {
 impl<T: 'static + KeyValueDB> ChainStore for ChainKVStore<T> {
     // TODO error log
     fn get_block(&self, h: &H256) -> Option<Block> {
-        self.get_header(h).map(|header| {
-            let commit_transactions = self
-                .get_block_body(h)
-                .expect("block transactions must be stored");
+        self.get_header(h).and_then(|header| {
+            // pruned block has no body
+            let commit_transactions = self.get_block_body(h)?;
             let uncles = self
                 .get_block_uncles(h)
                 .expect("block uncles must be stored");
             let proposal_transactions = self
                 .get_block_proposal_txs_ids(h)
                 .expect("block proposal_ids must be stored");
-            BlockBuilder::default()
-                .header(header)
-                .uncles(uncles)
-                .commit_transactions(commit_transactions)
-                .proposal_transactions(proposal_transactions)
-                .build()
+            Some(
+                BlockBuilder::default()
+                    .header(header)
+                    .uncles(uncles)
+                    .commit_transactions(commit_transactions)
+                    .proposal_transactions(proposal_transactions)
+                    .build(),
+            )
         })
     }
 
@@ -125,6 +254,13 @@ impl<T: 'static + KeyValueDB> ChainStore for ChainKVStore<T> {
             .map(|ref raw| HeaderBuilder::new(raw).build())
     }
 
+    fn contains_header(&self, h: &H256) -> Result<bool, SharedError> {
+        self.db
+            .read(COLUMN_BLOCK_HEADER, h.as_bytes())
+            .map(|raw| raw.is_some())
+            .map_err(Into::into)
+    }
+
     fn get_block_uncles(&self, h: &H256) -> Option<Vec<UncleBlock>> {
         // TODO Q use builder
         self.get(COLUMN_BLOCK_UNCLE, h.as_bytes())
@@ -140,19 +276,18 @@ impl<T: 'static + KeyValueDB> ChainStore for ChainKVStore<T> {
         self.get(COLUMN_BLOCK_TRANSACTION_ADDRESSES, h.as_bytes())
             .and_then(|serialized_addresses| {
                 let addresses: Vec<Address> = deserialize(&serialized_addresses).unwrap();
-                self.get(COLUMN_BLOCK_BODY, h.as_bytes())
-                    .map(|serialized_body| {
-                        let txs: Vec<TransactionBuilder> = addresses
-                            .iter()
-                            .filter_map(|address| {
-                                serialized_body
-                                    .get(address.offset..(address.offset + address.length))
-                                    .map(TransactionBuilder::new)
-                            })
-                            .collect();
-
-                        txs
-                    })
+                self.get_body(h, None).map(|serialized_body| {
+                    let txs: Vec<TransactionBuilder> = addresses
+                        .iter()
+                        .filter_map(|address| {
+                            serialized_body
+                                .get(address.offset..(address.offset + address.length))
+                                .map(TransactionBuilder::new)
+                        })
+                        .collect();
+
+                    txs
+                })
             })
             .map(|txs| txs.into_iter().map(|tx| tx.build()).collect())
     }
@@ -162,6 +297,11 @@ impl<T: 'static + KeyValueDB> ChainStore for ChainKVStore<T> {
             .map(|raw| deserialize(&raw[..]).unwrap())
     }
 
+    fn get_pruned_block(&self, block_hash: &H256) -> Option<PrunedBlock> {
+        self.get(COLUMN_PRUNED_BLOCK, block_hash.as_bytes())
+            .map(|raw| deserialize(&raw[..]).unwrap())
+    }
+
     fn save_with_batch<F: FnOnce(&mut Batch) -> Result<(), SharedError>>(
         &self,
         f: F,
@@ -172,6 +312,57 @@ impl<T: 'static + KeyValueDB> ChainStore for ChainKVStore<T> {
         Ok(())
     }
 
+    fn ancient_len(&self) -> Option<BlockNumber> {
+        self.ancient.as_ref().map(|_| {
+            self.get(COLUMN_META, META_FROZEN_BELOW_KEY)
+                .map(|raw| deserialize(&raw[..]).unwrap())
+                .unwrap_or(0)
+        })
+    }
+
+    fn freeze_block(
+        &self,
+        batch: &mut Batch,
+        number: BlockNumber,
+        hash: &H256,
+    ) -> Result<(), SharedError> {
+        let ancient = self
+            .ancient
+            .as_ref()
+            .ok_or_else(|| SharedError::Ancient("no ancient store".to_owned()))?;
+        let body = self
+            .get(COLUMN_BLOCK_BODY, hash.as_bytes())
+            .ok_or_else(|| SharedError::Ancient(format!("block {} has no body", number)))?;
+        // A body appended without the batch being written is kept in both
+        // places, and still read from the db, until it is frozen again
+        ancient
+            .append(number, &body)
+            .map_err(|err| SharedError::Ancient(err.to_string()))?;
+        batch.delete(COLUMN_BLOCK_BODY, hash.to_vec());
+        batch.insert(
+            COLUMN_META,
+            META_FROZEN_BELOW_KEY.to_vec(),
+            serialize(&(number + 1)).unwrap(),
+        );
+        Ok(())
+    }
+
+    fn backup(&self, dir: &Path) -> Result<(), SharedError> {
+        // The db goes first: bodies frozen meanwhile are then in both copies
+        // rather than in none
+        self.db.backup(&dir.join("db"))?;
+        if let Some(ref ancient) = self.ancient {
+            ancient
+                .backup(dir.join("ancient"))
+                .map_err(|err| SharedError::Ancient(err.to_string()))?;
+        }
+        Ok(())
+    }
+
+    fn sync(&self) -> Result<(), SharedError> {
+        self.db.sync().map_err(Into::into)
+    }
+
     fn insert_block(&self, batch: &mut Batch, b: &Block) {
         let hash = b.header().hash().to_vec();
         let txs_ids = b
@@ -212,13 +403,126 @@ impl<T: 'static + KeyValueDB> ChainStore for ChainKVStore<T> {
     fn insert_block_ext(&self, batch: &mut Batch, block_hash: &H256, ext: &BlockExt) {
         batch.insert(COLUMN_EXT, block_hash.to_vec(), serialize(&ext).unwrap());
     }
+
+    fn delete_block(&self, batch: &mut Batch, block_hash: &H256) {
+        for col in &[
+            COLUMN_BLOCK_HEADER,
+            COLUMN_BLOCK_TRANSACTION_IDS,
+            COLUMN_BLOCK_UNCLE,
+            COLUMN_BLOCK_BODY,
+            COLUMN_BLOCK_PROPOSAL_IDS,
+            COLUMN_BLOCK_TRANSACTION_ADDRESSES,
+            COLUMN_PRUNED_BLOCK,
+            COLUMN_EXT,
+        ] {
+            batch.delete(*col, block_hash.to_vec());
+        }
+    }
+
+    fn prune_block(&self, batch: &mut Batch, b: &Block, txo_set: &TxoSet) {
+        let hash = b.header().hash().to_vec();
+        let mut pruned = PrunedBlock::default();
+        for tx in b.commit_transactions() {
+            if !tx.is_cellbase() {
+                pruned.inputs.extend(tx.input_pts());
+            }
+            let tx_hash = tx.hash();
+            let is_live = txo_set
+                .get(&tx_hash)
+                .map_or(false, |meta| !meta.is_fully_spent());
+            if is_live {
+                batch.insert(
+                    COLUMN_PRUNED_TRANSACTION,
+                    tx_hash.to_vec(),
+                    serialize(tx).expect("serializing transaction should be ok"),
+                );
+                pruned.live_transactions.push(tx_hash);
+            }
+        }
+        batch.insert(
+            COLUMN_PRUNED_BLOCK,
+            hash.clone(),
+            serialize(&pruned).expect("serializing pruned block should be ok"),
+        );
+        batch.delete(COLUMN_BLOCK_BODY, hash);
+    }
+
+    fn prune_spent_transactions(&self, batch: &mut Batch, tx_hashes: &[H256], txo_set: &TxoSet) {
+        // A pruned block drops all its spent transactions at once, they are
+        // read from the db which does not see the batch
+        let mut spent_by_block: HashMap<H256, Vec<&H256>> = HashMap::new();
+        for tx_hash in tx_hashes {
+            let fully_spent = txo_set
+                .get(tx_hash)
+                .map_or(false, |meta| meta.is_fully_spent());
+            if !fully_spent || self.get(COLUMN_PRUNED_TRANSACTION, tx_hash.as_bytes()).is_none() {
+                continue;
+            }
+            let address: TransactionAddress = self
+                .get(COLUMN_TRANSACTION_ADDR, tx_hash.as_bytes())
+                .map(|raw| deserialize(&raw[..]).unwrap())
+                .expect("pruned transaction address must be stored");
+            spent_by_block
+                .entry(address.block_hash)
+                .or_insert_with(Vec::new)
+                .push(tx_hash);
+            batch.delete(COLUMN_PRUNED_TRANSACTION, tx_hash.to_vec());
+        }
+        for (block_hash, spent) in spent_by_block {
+            let mut pruned = self
+                .get_pruned_block(&block_hash)
+                .expect("pruned block must be stored");
+            pruned
+                .live_transactions
+                .retain(|tx_hash| !spent.contains(&tx_hash));
+            batch.insert(
+                COLUMN_PRUNED_BLOCK,
+                block_hash.to_vec(),
+                serialize(&pruned).expect("serializing pruned block should be ok"),
+            );
+        }
+    }
+
+    fn insert_pruned_block(&self, batch: &mut Batch, b: &Block, pruned: &PrunedBlock) {
+        let hash = b.header().hash().to_vec();
+        batch.insert(
+            COLUMN_BLOCK_HEADER,
+            hash.clone(),
+            serialize(b.header()).expect("serializing header should be ok"),
+        );
+        batch.insert(
+            COLUMN_BLOCK_UNCLE,
+            hash.clone(),
+            serialize(b.uncles()).expect("serializing uncles should be ok"),
+        );
+        batch.insert(
+            COLUMN_BLOCK_PROPOSAL_IDS,
+            hash.clone(),
+            serialize(b.proposal_transactions())
+                .expect("serializing proposal_transactions should be ok"),
+        );
+        for tx in b.commit_transactions() {
+            batch.insert(
+                COLUMN_PRUNED_TRANSACTION,
+                tx.hash().to_vec(),
+                serialize(tx).expect("serializing transaction should be ok"),
+            );
+        }
+        batch.insert(
+            COLUMN_PRUNED_BLOCK,
+            hash,
+            serialize(pruned).expect("serializing pruned block should be ok"),
+        );
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::super::COLUMNS;
     use super::*;
+    use crate::index::ChainIndex;
     use ckb_chain_spec::consensus::Consensus;
+    use ckb_core::transaction::{CellInput, CellOutput};
     use ckb_db::diskdb::RocksDB;
     use tempfile;
 
@@ -242,6 +546,25 @@ mod tests {
         assert_eq!(block, &store.get_block(&hash).unwrap());
     }
 
+    #[test]
+    fn failed_batch_writes_nothing() {
+        let tmp_dir = tempfile::Builder::new()
+            .prefix("failed_batch_writes_nothing")
+            .tempdir()
+            .unwrap();
+        let db = RocksDB::open(tmp_dir, COLUMNS);
+        let store = ChainKVStore::new(db);
+        let consensus = Consensus::default();
+        let block = consensus.genesis_block();
+
+        let ret = store.save_with_batch(|batch| {
+            store.insert_block(batch, &block);
+            Err(SharedError::InvalidInput)
+        });
+        assert!(ret.is_err());
+        assert!(store.get_header(&block.header().hash()).is_none());
+    }
+
     #[test]
     fn save_and_get_block_with_transactions() {
         let tmp_dir = tempfile::Builder::new()
@@ -265,6 +588,30 @@ mod tests {
         assert_eq!(block, store.get_block(&hash).unwrap());
     }
 
+    #[test]
+    fn column_sizes_and_compaction() {
+        let tmp_dir = tempfile::Builder::new()
+            .prefix("column_sizes_and_compaction")
+            .tempdir()
+            .unwrap();
+        let db = RocksDB::open(tmp_dir, COLUMNS);
+        let store = ChainKVStore::new(db);
+        let consensus = Consensus::default();
+        store
+            .save_with_batch(|batch| {
+                store.insert_block(batch, consensus.genesis_block());
+                Ok(())
+            })
+            .unwrap();
+
+        assert!(store.compact_column("headers"));
+        assert!(!store.compact_column("unknown"));
+        let sizes = store.column_sizes();
+        assert_eq!(sizes.len(), COLUMN_NAMES.len());
+        assert!(sizes.iter().any(|(name, size)| *name == "headers" && *size > 0));
+        assert!(sizes.iter().any(|(name, size)| *name == "meta" && *size == 0));
+    }
+
     #[test]
     fn save_and_get_block_ext() {
         let tmp_dir = tempfile::Builder::new()
@@ -280,6 +627,7 @@ mod tests {
             received_at: block.header().timestamp(),
             total_difficulty: block.header().difficulty().clone(),
             total_uncles_count: block.uncles().len() as u64,
+            total_transactions_count: block.commit_transactions().len() as u64,
             valid: Some(true),
         };
 
@@ -292,4 +640,153 @@ mod tests {
         assert!(ret.is_ok());
         assert_eq!(ext, store.get_block_ext(&hash).unwrap());
     }
+
+    #[test]
+    fn prune_block() {
+        let tmp_dir = tempfile::Builder::new()
+            .prefix("prune_block")
+            .tempdir()
+            .unwrap();
+        let db = RocksDB::open(tmp_dir, COLUMNS);
+        let store = ChainKVStore::new(db);
+        let spent_tx = TransactionBuilder::default()
+            .output(CellOutput::new(1, vec![1], H256::zero(), None))
+            .build();
+        let live_tx = TransactionBuilder::default()
+            .input(CellInput::new(
+                OutPoint::new(spent_tx.hash(), 0),
+                Default::default(),
+            ))
+            .output(CellOutput::new(1, vec![2], H256::zero(), None))
+            .build();
+        let block = BlockBuilder::default()
+            .commit_transaction(spent_tx.clone())
+            .commit_transaction(live_tx.clone())
+            .build();
+
+        let mut txo_set = TxoSet::new();
+        txo_set.insert(spent_tx.hash(), 1);
+        txo_set.insert(live_tx.hash(), 1);
+        txo_set.mark_spent(&OutPoint::new(spent_tx.hash(), 0));
+
+        let hash = block.header().hash();
+        let ret = store.save_with_batch(|batch| {
+            store.insert_block(batch, &block);
+            Ok(())
+        });
+        assert!(ret.is_ok());
+        let ret = store.save_with_batch(|batch| {
+            store.prune_block(batch, &block, &txo_set);
+            Ok(())
+        });
+        assert!(ret.is_ok());
+
+        assert_eq!(store.get_block(&hash), None);
+        assert_eq!(store.get_header(&hash).as_ref(), Some(block.header()));
+        assert_eq!(
+            store.get_pruned_block(&hash),
+            Some(PrunedBlock {
+                inputs: vec![OutPoint::new(spent_tx.hash(), 0)],
+                live_transactions: vec![live_tx.hash()],
+            })
+        );
+    }
+
+    #[test]
+    fn prune_spent_transactions() {
+        let tmp_dir = tempfile::Builder::new()
+            .prefix("prune_spent_transactions")
+            .tempdir()
+            .unwrap();
+        let db = RocksDB::open(tmp_dir, COLUMNS);
+        let store = ChainKVStore::new(db);
+        let kept_tx = TransactionBuilder::default()
+            .outputs(vec![CellOutput::new(1, vec![1], H256::zero(), None); 2])
+            .build();
+        let block = BlockBuilder::default()
+            .commit_transaction(kept_tx.clone())
+            .build();
+        let hash = block.header().hash();
+
+        let mut txo_set = TxoSet::new();
+        txo_set.insert(kept_tx.hash(), 2);
+        txo_set.mark_spent(&OutPoint::new(kept_tx.hash(), 0));
+        store
+            .save_with_batch(|batch| {
+                store.insert_block(batch, &block);
+                store.insert_transaction_address(batch, &hash, block.commit_transactions());
+                Ok(())
+            })
+            .unwrap();
+        store
+            .save_with_batch(|batch| {
+                store.prune_block(batch, &block, &txo_set);
+                Ok(())
+            })
+            .unwrap();
+
+        // Still live, it is kept
+        store
+            .save_with_batch(|batch| {
+                store.prune_spent_transactions(batch, &[kept_tx.hash()], &txo_set);
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(store.get_transaction(&kept_tx.hash()), Some(kept_tx.clone()));
+
+        txo_set.mark_spent(&OutPoint::new(kept_tx.hash(), 1));
+        store
+            .save_with_batch(|batch| {
+                store.prune_spent_transactions(batch, &[kept_tx.hash()], &txo_set);
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(store.get_transaction(&kept_tx.hash()), None);
+        assert_eq!(
+            store.get(COLUMN_PRUNED_TRANSACTION, kept_tx.hash().as_bytes()),
+            None
+        );
+        assert_eq!(
+            store.get_pruned_block(&hash),
+            Some(PrunedBlock {
+                inputs: vec![],
+                live_transactions: vec![],
+            })
+        );
+    }
+
+    #[test]
+    fn freeze_block_after_lost_batch() {
+        let tmp_dir = tempfile::Builder::new()
+            .prefix("freeze_block_after_lost_batch")
+            .tempdir()
+            .unwrap();
+        let db = RocksDB::open(tmp_dir.path().join("db"), COLUMNS);
+        let ancient = AncientStore::open(tmp_dir.path().join("ancient")).unwrap();
+        let store = ChainKVStore::new(db).with_ancient(ancient);
+        let consensus = Consensus::default();
+        let block = consensus.genesis_block();
+        let hash = block.header().hash();
+        store
+            .save_with_batch(|batch| {
+                store.insert_block(batch, &block);
+                store.insert_block_number(batch, &hash, 0);
+                Ok(())
+            })
+            .unwrap();
+
+        // The body is appended but the batch dropping it is lost in a crash
+        let mut batch = Batch::new();
+        store.freeze_block(&mut batch, 0, &hash).unwrap();
+        drop(batch);
+        assert_eq!(store.ancient_len(), Some(0));
+
+        store
+            .save_with_batch(|batch| store.freeze_block(batch, 0, &hash))
+            .unwrap();
+        assert_eq!(store.ancient_len(), Some(1));
+        assert_eq!(store.ancient.as_ref().map(AncientStore::len), Some(1));
+        assert_eq!(store.get(COLUMN_BLOCK_BODY, hash.as_bytes()), None);
+        assert_eq!(&store.get_block(&hash).unwrap(), block);
+    }
 }
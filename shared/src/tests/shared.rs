@@ -1,9 +1,17 @@
 use crate::{
     block_median_time_context::BlockMedianTimeContext,
+    index::ChainIndex,
     shared::{Shared, SharedBuilder},
     store::{ChainKVStore, ChainStore},
 };
-use ckb_core::{block::BlockBuilder, header::HeaderBuilder};
+use ckb_chain_spec::consensus::Consensus;
+use ckb_chain_spec::versionbits::{Deployment, ThresholdState, VERSIONBITS_TOP_BITS};
+use ckb_core::cell::{CellProvider, CellStatus};
+use ckb_core::transaction::{CellInput, CellOutput, OutPoint, TransactionBuilder};
+use ckb_core::{
+    block::BlockBuilder,
+    header::{Header, HeaderBuilder},
+};
 use ckb_db::{kvdb::KeyValueDB, memorydb::MemoryKeyValueDB};
 use numext_fixed_hash::H256;
 
@@ -42,7 +50,7 @@ where
 fn test_block_median_time() {
     let shared = new_shared();
     assert!(shared.block_median_time(&H256::zero()).is_none());
-    let now = faketime::unix_time_as_millis();
+    let now = ckb_time::unix_time_as_millis();
     let block_hashes = insert_block_timestamps(shared.store(), &[now]);
     assert_eq!(
         shared
@@ -58,3 +66,128 @@ fn test_block_median_time() {
         17
     );
 }
+
+#[test]
+fn test_cell_cache() {
+    let tx = TransactionBuilder::default()
+        .input(CellInput::new(OutPoint::null(), Default::default()))
+        .output(CellOutput::new(100, vec![], H256::zero(), None))
+        .build();
+    let genesis = BlockBuilder::default()
+        .commit_transaction(tx.clone())
+        .with_header_builder(HeaderBuilder::default());
+    let shared = SharedBuilder::<ChainKVStore<MemoryKeyValueDB>>::new_memory()
+        .consensus(Consensus::default().set_genesis_block(genesis))
+        .cell_cache_size(2)
+        .build();
+
+    let out_point = OutPoint::new(tx.hash(), 0);
+    let live = CellStatus::Live(tx.outputs()[0].clone());
+    // The first lookup fills the cache, the second one is served from it
+    assert_eq!(shared.cell(&out_point), live);
+    assert_eq!(shared.cell(&out_point), live);
+
+    // Cached outputs are not live unless the txo set knows them
+    let unknown = TransactionBuilder::default()
+        .output(CellOutput::new(200, vec![], H256::zero(), None))
+        .build();
+    shared.warm_cell_cache(&[unknown.clone()]);
+    assert_eq!(
+        shared.cell(&OutPoint::new(unknown.hash(), 0)),
+        CellStatus::Unknown
+    );
+}
+
+#[test]
+fn test_deployment_state() {
+    let deployment = Deployment {
+        name: "test".to_owned(),
+        bit: 0,
+        start: 10,
+        timeout: 100,
+    };
+    let consensus = Consensus::default()
+        .set_deployments(vec![deployment.clone()])
+        .set_versionbits_period(10)
+        .set_versionbits_threshold(8);
+    let shared = SharedBuilder::<ChainKVStore<MemoryKeyValueDB>>::new_memory()
+        .consensus(consensus.clone())
+        .build();
+
+    // 8 blocks of the second period signal the deployment
+    let mut headers = vec![consensus.genesis_block().header().clone()];
+    for number in 1..40 {
+        let version = if number >= 10 && number < 18 {
+            VERSIONBITS_TOP_BITS | deployment.mask()
+        } else {
+            0
+        };
+        let header = HeaderBuilder::default()
+            .parent_hash(headers.last().unwrap().hash())
+            .number(number)
+            .version(version)
+            .build();
+        headers.push(header);
+    }
+    let store = shared.store();
+    store
+        .save_with_batch(|batch| {
+            for header in &headers[1..] {
+                let block = BlockBuilder::default().header(header.clone()).build();
+                store.insert_block(batch, &block);
+                store.insert_block_hash(batch, header.number(), &header.hash());
+                store.insert_block_number(batch, &header.hash(), header.number());
+            }
+            Ok(())
+        })
+        .expect("insert blocks");
+
+    let state = |parent: &Header| shared.deployment_state(&deployment, parent);
+    assert_eq!(state(&headers[5]), ThresholdState::Defined);
+    assert_eq!(state(&headers[9]), ThresholdState::Started);
+    assert_eq!(state(&headers[19]), ThresholdState::LockedIn);
+    assert_eq!(state(&headers[28]), ThresholdState::LockedIn);
+    assert_eq!(state(&headers[29]), ThresholdState::Active);
+    // The reads store nothing, the inserted period boundaries are stored
+    assert_eq!(store.get_deployment_state(deployment.bit, &headers[19].hash()), None);
+    for header in &headers[1..] {
+        store
+            .save_with_batch(|batch| {
+                shared.record_deployment_states(batch, header);
+                Ok(())
+            })
+            .unwrap();
+    }
+    assert_eq!(store.get_deployment_state(deployment.bit, &headers[18].hash()), None);
+    assert_eq!(
+        store.get_deployment_state(deployment.bit, &headers[19].hash()),
+        Some(ThresholdState::LockedIn)
+    );
+    assert_eq!(state(&headers[29]), ThresholdState::Active);
+
+    // Recorded along with the block, from the header not stored yet
+    let fork = HeaderBuilder::default()
+        .header(headers[39].clone())
+        .nonce(1)
+        .build();
+    store
+        .save_with_batch(|batch| {
+            let block = BlockBuilder::default().header(fork.clone()).build();
+            store.insert_block(batch, &block);
+            shared.record_deployment_states(batch, &fork);
+            Ok(())
+        })
+        .unwrap();
+    assert_eq!(
+        store.get_deployment_state(deployment.bit, &fork.hash()),
+        Some(ThresholdState::Active)
+    );
+
+    assert_eq!(
+        shared.signalling_version(&headers[15], 0),
+        VERSIONBITS_TOP_BITS | deployment.mask()
+    );
+    assert_eq!(shared.signalling_version(&headers[35], 0), 0);
+    assert!(shared.is_deployment_active("test", &headers[35]));
+    assert!(!shared.is_deployment_active("test", &headers[25]));
+}
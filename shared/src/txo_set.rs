@@ -74,6 +74,14 @@ impl TxoSet {
         }
     }
 
+    /// Number of unspent outputs
+    pub fn live_cells_count(&self) -> u64 {
+        self.inner
+            .values()
+            .map(|meta| (0..meta.len()).filter(|i| !meta.is_spent(*i)).count() as u64)
+            .sum()
+    }
+
     pub fn update(&mut self, diff: TxoSetDiff) {
         self.rollback(diff.old_inputs, diff.old_outputs);
         self.forward(diff.new_inputs, diff.new_outputs);
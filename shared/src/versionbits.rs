@@ -0,0 +1,154 @@
+//! Deployment states of the version bits soft-forks, see
+//! [ckb_chain_spec::versionbits](ckb_chain_spec::versionbits).
+//!
+//! The state of a period is decided by the last block of the previous one,
+//! it is stored in the chain DB keyed by the hash of that block so it holds
+//! on every fork. Only the chain thread stores them, in the batch inserting
+//! that block, the reads compute the missing ones without storing them.
+
+use crate::index::ChainIndex;
+use crate::shared::{ChainProvider, Shared};
+use ckb_chain_spec::versionbits::{Deployment, ThresholdState, VERSIONBITS_TOP_BITS};
+use ckb_core::header::Header;
+use ckb_core::Version;
+use ckb_db::batch::Batch;
+use numext_fixed_hash::H256;
+
+impl<CI: ChainIndex> Shared<CI> {
+    /// State of `deployment` for the child of `parent`
+    pub fn deployment_state(&self, deployment: &Deployment, parent: &Header) -> ThresholdState {
+        self.compute_deployment_state(deployment, parent).0
+    }
+
+    /// Adds to `batch` the states of the deployments for the period after
+    /// `header` when it ends one. Called by the chain thread in the batch
+    /// inserting `header`, which is read from its ancestors only.
+    pub fn record_deployment_states(&self, batch: &mut Batch, header: &Header) {
+        let period = self.consensus().versionbits_period().max(1);
+        if (header.number() + 1) % period != 0 {
+            return;
+        }
+        for deployment in self.consensus().deployments() {
+            let (_, states) =
+                self.walk_deployment_state(deployment, Some(header.clone()), period);
+            for (hash, state) in states {
+                self.store()
+                    .insert_deployment_state(batch, deployment.bit, &hash, state);
+            }
+        }
+    }
+
+    // The state for the child of `parent` and the ones of the boundaries
+    // computed on the way, which were not stored
+    fn compute_deployment_state(
+        &self,
+        deployment: &Deployment,
+        parent: &Header,
+    ) -> (ThresholdState, Vec<(H256, ThresholdState)>) {
+        let period = self.consensus().versionbits_period().max(1);
+        let boundary = self.period_boundary(parent, period);
+        self.walk_deployment_state(deployment, boundary, period)
+    }
+
+    // The state decided by `boundary` and the ones computed on the way. The
+    // boundary itself is not read from the store, only its ancestors.
+    fn walk_deployment_state(
+        &self,
+        deployment: &Deployment,
+        mut boundary: Option<Header>,
+        period: u64,
+    ) -> (ThresholdState, Vec<(H256, ThresholdState)>) {
+        let threshold = self.consensus().versionbits_threshold();
+
+        // Walks back the period boundaries until a known state
+        let mut boundaries = Vec::new();
+        let mut state = loop {
+            let header = match boundary {
+                Some(header) => header,
+                None => break ThresholdState::Defined,
+            };
+            if let Some(state) = self
+                .store()
+                .get_deployment_state(deployment.bit, &header.hash())
+            {
+                break state;
+            }
+            if header.number() + 1 < deployment.start {
+                break ThresholdState::Defined;
+            }
+            boundary = if header.number() >= period {
+                self.get_ancestor(header.parent_hash(), header.number() - period)
+            } else {
+                None
+            };
+            boundaries.push(header);
+        };
+
+        let mut states = Vec::with_capacity(boundaries.len());
+        for header in boundaries.iter().rev() {
+            let signalled = self.count_signalled(deployment, header, period);
+            state = deployment.next_state(state, header.number(), signalled, threshold);
+            states.push((header.hash(), state));
+        }
+        (state, states)
+    }
+
+    /// Version of the child of `parent`: `base` when no deployment is
+    /// signalling, otherwise the version bits of the signalling ones.
+    pub fn signalling_version(&self, parent: &Header, base: Version) -> Version {
+        let bits = self
+            .consensus()
+            .deployments()
+            .iter()
+            .filter(|deployment| match self.deployment_state(deployment, parent) {
+                ThresholdState::Started | ThresholdState::LockedIn => true,
+                _ => false,
+            })
+            .fold(0, |bits, deployment| bits | deployment.mask());
+        if bits == 0 {
+            base
+        } else {
+            VERSIONBITS_TOP_BITS | bits
+        }
+    }
+
+    /// Whether the rules of the deployment `name` apply to the child of
+    /// `parent`
+    pub fn is_deployment_active(&self, name: &str, parent: &Header) -> bool {
+        self.consensus()
+            .deployment(name)
+            .map_or(false, |deployment| {
+                self.deployment_state(deployment, parent) == ThresholdState::Active
+            })
+    }
+
+    // Last block of the period before the one of the child of `parent`
+    fn period_boundary(&self, parent: &Header, period: u64) -> Option<Header> {
+        let child = parent.number() + 1;
+        if child < period {
+            return None;
+        }
+        self.get_ancestor(&parent.hash(), child - child % period - 1)
+    }
+
+    // Blocks signalling `deployment` in the period ending with `last`
+    fn count_signalled(&self, deployment: &Deployment, last: &Header, period: u64) -> u64 {
+        let mut signalled = 0;
+        let mut header = Some(last.clone());
+        for _ in 0..period {
+            let current = match header {
+                Some(current) => current,
+                None => break,
+            };
+            if deployment.signals(current.version()) {
+                signalled += 1;
+            }
+            header = if current.number() > 0 {
+                self.block_header(current.parent_hash())
+            } else {
+                None
+            };
+        }
+        signalled
+    }
+}
@@ -1,3 +1,6 @@
+use crate::difficulty::{DifficultyAdjustment, DifficultyAlgorithm};
+use crate::reward::RewardSchedule;
+use crate::versionbits::Deployment;
 use ckb_core::block::{Block, BlockBuilder};
 use ckb_core::header::HeaderBuilder;
 use ckb_core::transaction::Capacity;
@@ -14,6 +17,7 @@ pub const TRANSACTION_PROPAGATION_TIMEOUT: BlockNumber = 10;
 pub const CELLBASE_MATURITY: usize = 100;
 // TODO: should adjust this value based on CKB average block time
 pub const MEDIAN_TIME_BLOCK_COUNT: usize = 11;
+pub const MAX_BLOCK_TIME_DRIFT: u64 = 15 * 1000; // 15s
 
 //TODO：find best ORPHAN_RATE_TARGET
 pub const ORPHAN_RATE_TARGET: f32 = 0.1;
@@ -21,14 +25,20 @@ pub const POW_TIME_SPAN: u64 = 12 * 60 * 60 * 1000; // 12 hours
 pub const POW_SPACING: u64 = 15 * 1000; //15s
 
 pub const MAX_BLOCK_CYCLES: Cycle = 100_000_000;
+pub const MAX_TX_CYCLES: Cycle = 20_000_000;
 pub const MAX_BLOCK_BYTES: u64 = 10_000_000; // 10mb
 pub const BLOCK_VERSION: u32 = 0;
+// Deployments lock in once 95% of the blocks of a period signal them
+pub const VERSIONBITS_PERIOD: BlockNumber = 2016;
+pub const VERSIONBITS_THRESHOLD: u64 = 1916;
 
 #[derive(Clone, PartialEq, Debug)]
 pub struct Consensus {
     pub id: String,
+    pub network_id: u32,
     pub genesis_block: Block,
     pub initial_block_reward: Capacity,
+    pub reward_schedule: RewardSchedule,
     pub max_uncles_age: usize,
     pub max_uncles_len: usize,
     pub orphan_rate_target: f32,
@@ -37,18 +47,29 @@ pub struct Consensus {
     pub transaction_propagation_time: BlockNumber,
     pub transaction_propagation_timeout: BlockNumber,
     pub pow: Pow,
+    pub difficulty_algorithm: DifficultyAlgorithm,
     // For each input, if the referenced output transaction is cellbase,
     // it must have at least `cellbase_maturity` confirmations;
     // else reject this transaction.
     pub cellbase_maturity: usize,
     // This parameter indicates the count of past blocks used in the median time calculation
     pub median_time_block_count: usize,
+    // Maximum milliseconds a block timestamp may be ahead of the local clock
+    pub max_block_time_drift: u64,
     // Maximum cycles that all the scripts in all the commit transactions can take
     pub max_block_cycles: Cycle,
+    // Maximum cycles that the scripts of a single transaction can take
+    pub max_tx_cycles: Cycle,
     // Maximum number of bytes to use for the entire block
     pub max_block_bytes: u64,
     // block version number supported
     pub block_version: Version,
+    // Soft-forks activated by version bits signalling
+    pub deployments: Vec<Deployment>,
+    // Number of blocks in a signalling period
+    pub versionbits_period: BlockNumber,
+    // Signalling blocks in a period needed to lock a deployment in
+    pub versionbits_threshold: u64,
 }
 
 // genesis difficulty should not be zero
@@ -60,20 +81,28 @@ impl Default for Consensus {
         Consensus {
             genesis_block,
             id: "main".to_owned(),
+            network_id: 0,
             max_uncles_age: MAX_UNCLE_AGE,
             max_uncles_len: MAX_UNCLE_LEN,
             initial_block_reward: DEFAULT_BLOCK_REWARD,
+            reward_schedule: RewardSchedule::default(),
             orphan_rate_target: ORPHAN_RATE_TARGET,
             pow_time_span: POW_TIME_SPAN,
             pow_spacing: POW_SPACING,
             transaction_propagation_time: TRANSACTION_PROPAGATION_TIME,
             transaction_propagation_timeout: TRANSACTION_PROPAGATION_TIMEOUT,
             pow: Pow::Dummy,
+            difficulty_algorithm: DifficultyAlgorithm::default(),
             cellbase_maturity: CELLBASE_MATURITY,
             median_time_block_count: MEDIAN_TIME_BLOCK_COUNT,
+            max_block_time_drift: MAX_BLOCK_TIME_DRIFT,
             max_block_cycles: MAX_BLOCK_CYCLES,
+            max_tx_cycles: MAX_TX_CYCLES,
             max_block_bytes: MAX_BLOCK_BYTES,
             block_version: BLOCK_VERSION,
+            deployments: Vec::new(),
+            versionbits_period: VERSIONBITS_PERIOD,
+            versionbits_threshold: VERSIONBITS_THRESHOLD,
         }
     }
 }
@@ -84,6 +113,21 @@ impl Consensus {
         self
     }
 
+    pub fn set_network_id(mut self, network_id: u32) -> Self {
+        self.network_id = network_id;
+        self
+    }
+
+    pub fn set_pow_time_span(mut self, pow_time_span: u64) -> Self {
+        self.pow_time_span = pow_time_span;
+        self
+    }
+
+    pub fn set_pow_spacing(mut self, pow_spacing: u64) -> Self {
+        self.pow_spacing = pow_spacing;
+        self
+    }
+
     pub fn set_genesis_block(mut self, genesis_block: Block) -> Self {
         self.genesis_block = genesis_block;
         self
@@ -94,20 +138,59 @@ impl Consensus {
         self
     }
 
+    pub fn set_reward_schedule(mut self, reward_schedule: RewardSchedule) -> Self {
+        self.reward_schedule = reward_schedule;
+        self
+    }
+
     pub fn set_pow(mut self, pow: Pow) -> Self {
         self.pow = pow;
         self
     }
 
+    pub fn set_difficulty_algorithm(mut self, difficulty_algorithm: DifficultyAlgorithm) -> Self {
+        self.difficulty_algorithm = difficulty_algorithm;
+        self
+    }
+
+    pub fn set_max_block_time_drift(mut self, max_block_time_drift: u64) -> Self {
+        self.max_block_time_drift = max_block_time_drift;
+        self
+    }
+
     pub fn set_max_block_cycles(mut self, max_block_cycles: Cycle) -> Self {
         self.max_block_cycles = max_block_cycles;
         self
     }
 
+    pub fn set_max_tx_cycles(mut self, max_tx_cycles: Cycle) -> Self {
+        self.max_tx_cycles = max_tx_cycles;
+        self
+    }
+
+    pub fn set_deployments(mut self, deployments: Vec<Deployment>) -> Self {
+        self.deployments = deployments;
+        self
+    }
+
+    pub fn set_versionbits_period(mut self, versionbits_period: BlockNumber) -> Self {
+        self.versionbits_period = versionbits_period;
+        self
+    }
+
+    pub fn set_versionbits_threshold(mut self, versionbits_threshold: u64) -> Self {
+        self.versionbits_threshold = versionbits_threshold;
+        self
+    }
+
     pub fn genesis_block(&self) -> &Block {
         &self.genesis_block
     }
 
+    pub fn network_id(&self) -> u32 {
+        self.network_id
+    }
+
     pub fn max_uncles_len(&self) -> usize {
         self.max_uncles_len
     }
@@ -124,6 +207,10 @@ impl Consensus {
         self.initial_block_reward
     }
 
+    pub fn block_reward(&self, number: BlockNumber) -> Capacity {
+        self.reward_schedule.block_reward(self.initial_block_reward, number)
+    }
+
     pub fn difficulty_adjustment_interval(&self) -> BlockNumber {
         self.pow_time_span / self.pow_spacing
     }
@@ -136,6 +223,10 @@ impl Consensus {
         self.pow.engine()
     }
 
+    pub fn difficulty_engine(&self) -> Arc<dyn DifficultyAdjustment> {
+        self.difficulty_algorithm.engine()
+    }
+
     pub fn cellbase_maturity(&self) -> usize {
         self.cellbase_maturity
     }
@@ -144,10 +235,19 @@ impl Consensus {
         self.median_time_block_count
     }
 
+    pub fn max_block_time_drift(&self) -> u64 {
+        self.max_block_time_drift
+    }
+
     pub fn max_block_cycles(&self) -> Cycle {
         self.max_block_cycles
     }
 
+    /// Cycles limit of a single transaction, never above the block limit
+    pub fn max_tx_cycles(&self) -> Cycle {
+        self.max_tx_cycles.min(self.max_block_cycles)
+    }
+
     pub fn max_block_bytes(&self) -> u64 {
         self.max_block_bytes
     }
@@ -155,4 +255,22 @@ impl Consensus {
     pub fn block_version(&self) -> Version {
         self.block_version
     }
+
+    pub fn deployments(&self) -> &[Deployment] {
+        &self.deployments
+    }
+
+    pub fn deployment(&self, name: &str) -> Option<&Deployment> {
+        self.deployments
+            .iter()
+            .find(|deployment| deployment.name == name)
+    }
+
+    pub fn versionbits_period(&self) -> BlockNumber {
+        self.versionbits_period
+    }
+
+    pub fn versionbits_threshold(&self) -> u64 {
+        self.versionbits_threshold
+    }
 }
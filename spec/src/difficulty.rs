@@ -0,0 +1,252 @@
+use crate::consensus::Consensus;
+use ckb_core::header::{BlockNumber, Header};
+use numext_fixed_hash::H256;
+use numext_fixed_uint::U256;
+use serde_derive::Deserialize;
+use std::cmp;
+use std::sync::Arc;
+
+// Solve times are clamped to this multiple of the target spacing so that a
+// single block with a bogus timestamp can not swing the difficulty
+const LWMA_MAX_SOLVE_TIME_FACTOR: u64 = 6;
+
+#[derive(Clone, Copy, Deserialize, Eq, PartialEq, Hash, Debug)]
+pub enum DifficultyAlgorithm {
+    /// Retarget once per `difficulty_adjustment_interval` blocks from the
+    /// uncle rate of the finished epoch
+    Epoch,
+    /// Retarget every block from the linearly weighted solve times of the
+    /// last `window` blocks
+    Lwma { window: BlockNumber },
+}
+
+impl Default for DifficultyAlgorithm {
+    fn default() -> Self {
+        DifficultyAlgorithm::Epoch
+    }
+}
+
+impl DifficultyAlgorithm {
+    pub fn engine(&self) -> Arc<dyn DifficultyAdjustment> {
+        match *self {
+            DifficultyAlgorithm::Epoch => Arc::new(EpochDifficulty),
+            DifficultyAlgorithm::Lwma { window } => Arc::new(LwmaDifficulty::new(window)),
+        }
+    }
+}
+
+/// Chain data the difficulty adjustment depends on
+pub trait DifficultyProvider {
+    fn header(&self, hash: &H256) -> Option<Header>;
+
+    fn ancestor(&self, base: &H256, number: BlockNumber) -> Option<Header>;
+
+    fn total_uncles_count(&self, hash: &H256) -> Option<u64>;
+}
+
+pub trait DifficultyAdjustment: Send + Sync {
+    /// Difficulty of the block following `last`, `None` when the required
+    /// ancestors are unknown
+    fn next_difficulty(
+        &self,
+        consensus: &Consensus,
+        provider: &dyn DifficultyProvider,
+        last: &Header,
+    ) -> Option<U256>;
+}
+
+#[allow(clippy::op_ref)]
+fn clamp_difficulty(consensus: &Consensus, last: &Header, difficulty: U256) -> U256 {
+    let min_difficulty = consensus.min_difficulty();
+    let max_difficulty = last.difficulty() * 2u32;
+    if difficulty > max_difficulty {
+        max_difficulty
+    } else if &difficulty < min_difficulty {
+        min_difficulty.clone()
+    } else {
+        difficulty
+    }
+}
+
+pub struct EpochDifficulty;
+
+impl DifficultyAdjustment for EpochDifficulty {
+    // T_interval = L / C_m
+    // HR_m = HR_last/ (1 + o)
+    // Diff= HR_m * T_interval / H = Diff_last * o_last / o
+    fn next_difficulty(
+        &self,
+        consensus: &Consensus,
+        provider: &dyn DifficultyProvider,
+        last: &Header,
+    ) -> Option<U256> {
+        let last_hash = last.hash();
+        let last_number = last.number();
+        let last_difficulty = last.difficulty();
+
+        let interval = consensus.difficulty_adjustment_interval();
+
+        if (last_number + 1) % interval != 0 {
+            return Some(last_difficulty.clone());
+        }
+
+        let start = last_number.saturating_sub(interval);
+        let start_header = provider.ancestor(&last_hash, start)?;
+        let start_total_uncles_count = provider
+            .total_uncles_count(&start_header.hash())
+            .expect("block_ext exist");
+        let last_total_uncles_count = provider
+            .total_uncles_count(&last_hash)
+            .expect("block_ext exist");
+
+        let difficulty = last_difficulty
+            * U256::from(last_total_uncles_count - start_total_uncles_count)
+            * U256::from((1.0 / consensus.orphan_rate_target()) as u64)
+            / U256::from(interval);
+
+        Some(clamp_difficulty(consensus, last, difficulty))
+    }
+}
+
+pub struct LwmaDifficulty {
+    window: BlockNumber,
+}
+
+impl LwmaDifficulty {
+    pub fn new(window: BlockNumber) -> Self {
+        LwmaDifficulty {
+            window: cmp::max(window, 1),
+        }
+    }
+}
+
+impl DifficultyAdjustment for LwmaDifficulty {
+    // Diff = avg(Diff_i) * T * sum(i) / sum(i * solve_time_i), i = 1..=N
+    fn next_difficulty(
+        &self,
+        consensus: &Consensus,
+        provider: &dyn DifficultyProvider,
+        last: &Header,
+    ) -> Option<U256> {
+        // Keep the genesis difficulty until a full window is available
+        if last.number() < self.window {
+            return Some(last.difficulty().clone());
+        }
+
+        let spacing = cmp::max(consensus.pow_spacing, 1);
+        let max_solve_time = spacing * LWMA_MAX_SOLVE_TIME_FACTOR;
+
+        let mut total_difficulty = U256::zero();
+        let mut weighted_solve_time = 0u64;
+        let mut current = last.clone();
+        for weight in (1..=self.window).rev() {
+            let parent = provider.header(current.parent_hash())?;
+            let solve_time = current
+                .timestamp()
+                .saturating_sub(parent.timestamp())
+                .max(1)
+                .min(max_solve_time);
+            weighted_solve_time += solve_time * weight;
+            total_difficulty = total_difficulty + current.difficulty();
+            current = parent;
+        }
+
+        // avg(Diff_i) * sum(i) = total / N * N * (N + 1) / 2
+        let difficulty = total_difficulty * U256::from(spacing) * U256::from(self.window + 1)
+            / U256::from(2 * weighted_solve_time);
+
+        Some(clamp_difficulty(consensus, last, difficulty))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ckb_core::block::BlockBuilder;
+    use ckb_core::header::HeaderBuilder;
+    use std::collections::HashMap;
+
+    struct MockProvider {
+        headers: HashMap<H256, Header>,
+    }
+
+    impl DifficultyProvider for MockProvider {
+        fn header(&self, hash: &H256) -> Option<Header> {
+            self.headers.get(hash).cloned()
+        }
+
+        fn ancestor(&self, _base: &H256, _number: BlockNumber) -> Option<Header> {
+            None
+        }
+
+        fn total_uncles_count(&self, _hash: &H256) -> Option<u64> {
+            None
+        }
+    }
+
+    // Builds `count` blocks on top of a genesis, each mined `solve_time` ms
+    // after its parent
+    fn build_chain(count: u64, solve_time: u64, difficulty: u64) -> (MockProvider, Header) {
+        let mut headers = HashMap::default();
+        let mut last = HeaderBuilder::default()
+            .difficulty(U256::from(difficulty))
+            .build();
+        headers.insert(last.hash(), last.clone());
+        for number in 1..=count {
+            let header = HeaderBuilder::default()
+                .number(number)
+                .parent_hash(last.hash())
+                .timestamp(last.timestamp() + solve_time)
+                .difficulty(U256::from(difficulty))
+                .build();
+            headers.insert(header.hash(), header.clone());
+            last = header;
+        }
+        (MockProvider { headers }, last)
+    }
+
+    #[test]
+    fn test_lwma_difficulty() {
+        let genesis = HeaderBuilder::default().difficulty(U256::from(100u64));
+        let consensus = Consensus::default()
+            .set_genesis_block(BlockBuilder::default().with_header_builder(genesis))
+            .set_pow_spacing(1000)
+            .set_difficulty_algorithm(DifficultyAlgorithm::Lwma { window: 10 });
+        let engine = consensus.difficulty_engine();
+
+        // Not enough blocks for a full window
+        let (provider, last) = build_chain(5, 500, 1000);
+        assert_eq!(
+            engine.next_difficulty(&consensus, &provider, &last),
+            Some(U256::from(1000u64))
+        );
+
+        // On target
+        let (provider, last) = build_chain(20, 1000, 1000);
+        assert_eq!(
+            engine.next_difficulty(&consensus, &provider, &last),
+            Some(U256::from(1000u64))
+        );
+
+        // Twice as fast as the target, bounded by 2 * last
+        let (provider, last) = build_chain(20, 250, 1000);
+        assert_eq!(
+            engine.next_difficulty(&consensus, &provider, &last),
+            Some(U256::from(2000u64))
+        );
+
+        // Twice as slow as the target
+        let (provider, last) = build_chain(20, 2000, 1000);
+        assert_eq!(
+            engine.next_difficulty(&consensus, &provider, &last),
+            Some(U256::from(500u64))
+        );
+
+        // Very slow, solve times are clamped to 6 * spacing
+        let (provider, last) = build_chain(20, 60_000, 1000);
+        assert_eq!(
+            engine.next_difficulty(&consensus, &provider, &last),
+            Some(U256::from(166u64))
+        );
+    }
+}
@@ -4,13 +4,28 @@
 //!
 //! In order to run a chain different to the official public one,
 //! with a config file specifying chain = "path" under [ckb].
+//!
+//! Spec files are JSON, or TOML when the file extension is `.toml`. The
+//! built-in presets `mainnet`, `testnet`, `dev` and `instant`, the dev chain
+//! accepting any seal, are available through
+//! [ChainSpec::preset](ChainSpec::preset).
+//!
+//! The genesis block is built from the spec with
+//! [ChainSpec::build_genesis](ChainSpec::build_genesis), a spec may declare
+//! the resulting hash to guard against building a different network.
 
-use crate::consensus::Consensus;
-use ckb_core::block::BlockBuilder;
+use crate::consensus::{
+    Consensus, MAX_BLOCK_TIME_DRIFT, MAX_TX_CYCLES, POW_SPACING, POW_TIME_SPAN,
+    VERSIONBITS_PERIOD, VERSIONBITS_THRESHOLD,
+};
+use crate::difficulty::DifficultyAlgorithm;
+use crate::reward::RewardSchedule;
+use crate::versionbits::{Deployment, VERSIONBITS_NUM_BITS};
+use ckb_core::block::{Block, BlockBuilder};
 use ckb_core::header::HeaderBuilder;
 use ckb_core::script::Script;
 use ckb_core::transaction::{CellOutput, Transaction, TransactionBuilder};
-use ckb_core::{Capacity, Cycle};
+use ckb_core::{BlockNumber, Capacity, Cycle};
 use ckb_pow::{Pow, PowEngine};
 use ckb_protocol::Script as FbsScript;
 use flatbuffers::FlatBufferBuilder;
@@ -24,6 +39,23 @@ use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 pub mod consensus;
+pub mod difficulty;
+pub mod reward;
+pub mod versionbits;
+
+const BUNDLED_PREFIX: &str = "bundled:";
+// Cells shipped with the binary, referenced by the presets as `bundled:<name>`
+const BUNDLED_CELLS: &[(&str, &[u8])] = &[(
+    "always_success",
+    include_bytes!("../../nodes_template/spec/cells/always_success"),
+)];
+
+const PRESETS: &[(&str, &str)] = &[
+    ("mainnet", include_str!("../res/mainnet.toml")),
+    ("testnet", include_str!("../res/testnet.toml")),
+    ("dev", include_str!("../res/dev.toml")),
+    ("instant", include_str!("../res/instant.toml")),
+];
 
 #[derive(Clone, PartialEq, Eq, Debug, Deserialize)]
 pub struct ChainSpec {
@@ -36,8 +68,63 @@ pub struct ChainSpec {
 
 #[derive(Clone, PartialEq, Eq, Debug, Deserialize)]
 pub struct Params {
+    /// Nodes only connect to peers of the same network id
+    #[serde(default)]
+    pub network_id: u32,
     pub initial_block_reward: Capacity,
+    /// How the block reward evolves from `initial_block_reward`, `Constant`
+    /// when omitted
+    #[serde(default)]
+    pub reward_schedule: RewardSchedule,
     pub max_block_cycles: Cycle,
+    /// Maximum cycles the scripts of a single transaction can take
+    #[serde(default = "default_max_tx_cycles")]
+    pub max_tx_cycles: Cycle,
+    /// Length of the difficulty adjustment window in milliseconds
+    #[serde(default = "default_pow_time_span")]
+    pub pow_time_span: u64,
+    /// Target block interval in milliseconds
+    #[serde(default = "default_pow_spacing")]
+    pub pow_spacing: u64,
+    /// Milliseconds a block timestamp may run ahead of the local clock
+    #[serde(default = "default_max_block_time_drift")]
+    pub max_block_time_drift: u64,
+    /// Difficulty retargeting, `Epoch` when omitted
+    #[serde(default)]
+    pub difficulty_algorithm: DifficultyAlgorithm,
+    /// Soft-forks activated by version bits signalling
+    #[serde(default)]
+    pub deployments: Vec<Deployment>,
+    /// Number of blocks in a signalling period
+    #[serde(default = "default_versionbits_period")]
+    pub versionbits_period: BlockNumber,
+    /// Signalling blocks in a period needed to lock a deployment in
+    #[serde(default = "default_versionbits_threshold")]
+    pub versionbits_threshold: u64,
+}
+
+fn default_max_tx_cycles() -> Cycle {
+    MAX_TX_CYCLES
+}
+
+fn default_pow_time_span() -> u64 {
+    POW_TIME_SPAN
+}
+
+fn default_pow_spacing() -> u64 {
+    POW_SPACING
+}
+
+fn default_max_block_time_drift() -> u64 {
+    MAX_BLOCK_TIME_DRIFT
+}
+
+fn default_versionbits_period() -> BlockNumber {
+    VERSIONBITS_PERIOD
+}
+
+fn default_versionbits_threshold() -> u64 {
+    VERSIONBITS_THRESHOLD
 }
 
 #[derive(Clone, PartialEq, Eq, Debug, Deserialize)]
@@ -52,11 +139,19 @@ pub struct Genesis {
     pub version: u32,
     pub parent_hash: H256,
     pub timestamp: u64,
-    pub txs_commit: H256,
-    pub txs_proposal: H256,
     pub difficulty: U256,
-    pub cellbase_id: H256,
-    pub uncles_hash: H256,
+    /// Cells allocated at genesis, after the system cells
+    #[serde(default)]
+    pub premine: Vec<PremineCell>,
+    /// Expected hash of the genesis block, not checked when omitted
+    #[serde(default)]
+    pub hash: Option<H256>,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Deserialize)]
+pub struct PremineCell {
+    pub capacity: Capacity,
+    pub lock: H256,
 }
 
 #[derive(Clone, PartialEq, Eq, Debug, Deserialize)]
@@ -64,12 +159,33 @@ pub struct SystemCell {
     pub path: PathBuf,
 }
 
+impl SystemCell {
+    fn read(&self) -> Result<Vec<u8>, Box<Error>> {
+        if let Some(name) = self.bundled_name() {
+            return BUNDLED_CELLS
+                .iter()
+                .find(|(bundled, _)| *bundled == name)
+                .map(|(_, data)| data.to_vec())
+                .ok_or_else(|| format!("Unknown bundled cell: {}", name).into());
+        }
+        let mut file = File::open(&self.path)?;
+        let mut data = Vec::new();
+        file.read_to_end(&mut data)?;
+        Ok(data)
+    }
+
+    fn bundled_name(&self) -> Option<&str> {
+        self.path
+            .to_str()
+            .filter(|path| path.starts_with(BUNDLED_PREFIX))
+            .map(|path| &path[BUNDLED_PREFIX.len()..])
+    }
+}
+
 fn build_system_cell_transaction(cells: &[SystemCell]) -> Result<Transaction, Box<Error>> {
     let mut outputs = Vec::new();
     for system_cell in cells {
-        let mut file = File::open(&system_cell.path)?;
-        let mut data = Vec::new();
-        file.read_to_end(&mut data)?;
+        let data = system_cell.read()?;
 
         let script = Script::new(0, vec![], None, Some(data), vec![]);
         let mut builder = FlatBufferBuilder::new();
@@ -91,42 +207,116 @@ fn build_system_cell_transaction(cells: &[SystemCell]) -> Result<Transaction, Bo
     Ok(TransactionBuilder::default().outputs(outputs).build())
 }
 
+fn build_premine_transaction(cells: &[PremineCell]) -> Transaction {
+    let outputs = cells
+        .iter()
+        .map(|cell| CellOutput::new(cell.capacity, Vec::new(), cell.lock.clone(), None))
+        .collect();
+    TransactionBuilder::default().outputs(outputs).build()
+}
+
 impl ChainSpec {
     pub fn read_from_file<P: AsRef<Path>>(path: P) -> Result<ChainSpec, Box<Error>> {
-        let file = File::open(path.as_ref())?;
-        let mut spec: Self = serde_json::from_reader(file)?;
+        let mut file = File::open(path.as_ref())?;
+        let mut content = String::new();
+        file.read_to_string(&mut content)?;
+        let mut spec: Self = match path.as_ref().extension() {
+            Some(ext) if ext == "toml" => toml::from_str(&content)?,
+            _ => serde_json::from_str(&content)?,
+        };
         spec.resolve_paths(path.as_ref().parent().unwrap());
         Ok(spec)
     }
 
+    /// Built-in spec by name: `mainnet`, `testnet`, `dev` or `instant`
+    pub fn preset(name: &str) -> Result<ChainSpec, Box<Error>> {
+        let content = PRESETS
+            .iter()
+            .find(|(preset, _)| *preset == name)
+            .map(|(_, content)| content)
+            .ok_or_else(|| format!("Unknown chain spec preset: {}", name))?;
+        Ok(toml::from_str(content)?)
+    }
+
+    pub fn is_preset<P: AsRef<Path>>(spec: P) -> bool {
+        spec.as_ref()
+            .to_str()
+            .map_or(false, |name| PRESETS.iter().any(|(preset, _)| *preset == name))
+    }
+
+    /// Loads `spec` as a preset when it names one, otherwise as a path
+    pub fn load<P: AsRef<Path>>(spec: P) -> Result<ChainSpec, Box<Error>> {
+        let spec = spec.as_ref();
+        if Self::is_preset(spec) {
+            Self::preset(spec.to_str().expect("preset name"))
+        } else {
+            Self::read_from_file(spec)
+        }
+    }
+
     pub fn pow_engine(&self) -> Arc<dyn PowEngine> {
         self.pow.engine()
     }
 
-    pub fn to_consensus(&self) -> Result<Consensus, Box<Error>> {
-        let header = HeaderBuilder::default()
+    /// Builds the genesis block: the system cells transaction, followed by
+    /// the premine transaction when there are premine cells. Fails when the
+    /// block does not match the declared genesis hash.
+    pub fn build_genesis(&self) -> Result<Block, Box<Error>> {
+        let header_builder = HeaderBuilder::default()
             .version(self.genesis.version)
             .parent_hash(self.genesis.parent_hash.clone())
             .timestamp(self.genesis.timestamp)
-            .txs_commit(self.genesis.txs_commit.clone())
-            .txs_proposal(self.genesis.txs_proposal.clone())
             .difficulty(self.genesis.difficulty.clone())
             .nonce(self.genesis.seal.nonce)
-            .proof(self.genesis.seal.proof.to_vec())
-            .cellbase_id(self.genesis.cellbase_id.clone())
-            .uncles_hash(self.genesis.uncles_hash.clone())
-            .build();
+            .proof(self.genesis.seal.proof.to_vec());
 
-        let genesis_block = BlockBuilder::default()
-            .commit_transaction(build_system_cell_transaction(&self.system_cells)?)
-            .header(header)
-            .build();
+        let mut builder = BlockBuilder::default()
+            .commit_transaction(build_system_cell_transaction(&self.system_cells)?);
+        if !self.genesis.premine.is_empty() {
+            builder = builder.commit_transaction(build_premine_transaction(&self.genesis.premine));
+        }
+        let genesis_block = builder.with_header_builder(header_builder);
+
+        if let Some(ref expected) = self.genesis.hash {
+            let actual = genesis_block.header().hash();
+            if &actual != expected {
+                return Err(format!(
+                    "Genesis hash mismatch: declared {:x}, built {:x}",
+                    expected, actual
+                )
+                .into());
+            }
+        }
+        Ok(genesis_block)
+    }
+
+    pub fn to_consensus(&self) -> Result<Consensus, Box<Error>> {
+        let genesis_block = self.build_genesis()?;
+        if let Some(deployment) = self
+            .params
+            .deployments
+            .iter()
+            .find(|deployment| deployment.bit >= VERSIONBITS_NUM_BITS)
+        {
+            return Err(format!("Invalid version bit of deployment {}", deployment.name).into());
+        }
+        self.params.reward_schedule.validate()?;
 
         let consensus = Consensus::default()
             .set_id(self.name.clone())
             .set_genesis_block(genesis_block)
             .set_initial_block_reward(self.params.initial_block_reward)
+            .set_reward_schedule(self.params.reward_schedule)
             .set_max_block_cycles(self.params.max_block_cycles)
+            .set_max_tx_cycles(self.params.max_tx_cycles)
+            .set_network_id(self.params.network_id)
+            .set_pow_time_span(self.params.pow_time_span)
+            .set_pow_spacing(self.params.pow_spacing)
+            .set_difficulty_algorithm(self.params.difficulty_algorithm)
+            .set_max_block_time_drift(self.params.max_block_time_drift)
+            .set_deployments(self.params.deployments.clone())
+            .set_versionbits_period(self.params.versionbits_period)
+            .set_versionbits_threshold(self.params.versionbits_threshold)
             .set_pow(self.pow.clone());
 
         Ok(consensus)
@@ -134,7 +324,7 @@ impl ChainSpec {
 
     fn resolve_paths(&mut self, base: &Path) {
         for mut cell in &mut self.system_cells {
-            if cell.path.is_relative() {
+            if cell.path.is_relative() && cell.bundled_name().is_none() {
                 cell.path = base.join(&cell.path);
             }
         }
@@ -161,4 +351,57 @@ pub mod test {
             assert!(cell.path.exists());
         }
     }
+
+    #[test]
+    fn test_chain_spec_presets() {
+        for (name, _) in PRESETS {
+            let spec = ChainSpec::preset(name);
+            assert!(spec.is_ok(), format!("{}: {:?}", name, spec));
+            let consensus = spec.unwrap().to_consensus();
+            assert!(consensus.is_ok(), format!("{}: {:?}", name, consensus));
+        }
+        let testnet = ChainSpec::load("testnet").unwrap();
+        assert_eq!(testnet.params.network_id, 1);
+        assert_eq!(testnet.params.max_tx_cycles, MAX_TX_CYCLES);
+        assert_eq!(
+            testnet.params.difficulty_algorithm,
+            DifficultyAlgorithm::Lwma { window: 60 }
+        );
+        assert_eq!(
+            testnet.params.reward_schedule,
+            RewardSchedule::Halving { interval: 8_409_600 }
+        );
+        assert_eq!(ChainSpec::preset("instant").unwrap().pow, Pow::Dummy);
+        assert!(ChainSpec::preset("unknown").is_err());
+    }
+
+    #[test]
+    fn test_build_genesis() {
+        let mut spec = ChainSpec::preset("dev").unwrap();
+        spec.genesis.premine = vec![
+            PremineCell {
+                capacity: 100,
+                lock: H256::zero(),
+            },
+            PremineCell {
+                capacity: 200,
+                lock: H256::zero(),
+            },
+        ];
+        let genesis = spec.build_genesis().unwrap();
+        let transactions = genesis.commit_transactions();
+        assert_eq!(transactions.len(), 2);
+        assert_eq!(transactions[1].outputs()[1].capacity, 200);
+        assert_eq!(genesis.header().timestamp(), spec.genesis.timestamp);
+        assert_eq!(genesis.header().difficulty(), &spec.genesis.difficulty);
+
+        spec.genesis.hash = Some(genesis.header().hash());
+        assert_eq!(
+            spec.to_consensus().unwrap().genesis_block().header().hash(),
+            genesis.header().hash()
+        );
+
+        spec.genesis.timestamp += 1;
+        assert!(spec.build_genesis().is_err());
+    }
 }
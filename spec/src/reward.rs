@@ -0,0 +1,158 @@
+use ckb_core::header::BlockNumber;
+use ckb_core::transaction::Capacity;
+use serde_derive::Deserialize;
+
+#[derive(Clone, Copy, Deserialize, Eq, PartialEq, Hash, Debug)]
+pub enum RewardSchedule {
+    /// Every block is rewarded `initial_block_reward`
+    Constant,
+    /// The reward halves every `interval` blocks
+    Halving { interval: BlockNumber },
+    /// The reward of the blocks of the `k`th `epoch` is the initial one
+    /// multiplied by `(numerator / denominator)^k`, with 64 fractional bits
+    /// and rounded to the nearest. The numerator is at most the denominator.
+    EpochDecay {
+        epoch: BlockNumber,
+        numerator: u64,
+        denominator: u64,
+    },
+}
+
+impl Default for RewardSchedule {
+    fn default() -> Self {
+        RewardSchedule::Constant
+    }
+}
+
+// Fractional bits of the decay factor, which is at most 1
+const FRACTION_BITS: u32 = 64;
+
+impl RewardSchedule {
+    /// Refuses the periods of 0 blocks, and the decays dividing by 0 or
+    /// growing the reward
+    pub fn validate(&self) -> Result<(), String> {
+        match *self {
+            RewardSchedule::Constant => Ok(()),
+            RewardSchedule::Halving { interval } if interval == 0 => {
+                Err("Invalid reward schedule: halving interval is 0".to_owned())
+            }
+            RewardSchedule::Halving { .. } => Ok(()),
+            RewardSchedule::EpochDecay {
+                epoch,
+                numerator,
+                denominator,
+            } => {
+                if epoch == 0 {
+                    Err("Invalid reward schedule: decay epoch is 0".to_owned())
+                } else if denominator == 0 {
+                    Err("Invalid reward schedule: decay denominator is 0".to_owned())
+                } else if numerator > denominator {
+                    Err("Invalid reward schedule: decay numerator above denominator".to_owned())
+                } else {
+                    Ok(())
+                }
+            }
+        }
+    }
+
+    /// Reward of the block `number` given the genesis era reward `initial`,
+    /// for a validated schedule
+    pub fn block_reward(&self, initial: Capacity, number: BlockNumber) -> Capacity {
+        match *self {
+            RewardSchedule::Constant => initial,
+            RewardSchedule::Halving { interval } => {
+                let halvings = number / interval.max(1);
+                if halvings >= 64 {
+                    0
+                } else {
+                    initial >> halvings
+                }
+            }
+            RewardSchedule::EpochDecay {
+                epoch,
+                numerator,
+                denominator,
+            } => {
+                let epochs = number / epoch.max(1);
+                // Refused by `validate`, the reward would grow
+                if numerator >= denominator {
+                    return initial;
+                }
+                // Exponentiation by squaring, the factors staying below 1
+                let mut factor =
+                    (u128::from(numerator) << FRACTION_BITS) / u128::from(denominator);
+                let mut decay = 1u128 << FRACTION_BITS;
+                let mut exponent = epochs;
+                while exponent > 0 && decay > 0 {
+                    if exponent & 1 == 1 {
+                        decay = (decay * factor) >> FRACTION_BITS;
+                    }
+                    factor = (factor * factor) >> FRACTION_BITS;
+                    exponent >>= 1;
+                }
+                let half = 1u128 << (FRACTION_BITS - 1);
+                ((u128::from(initial) * decay + half) >> FRACTION_BITS) as Capacity
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_block_reward() {
+        assert_eq!(RewardSchedule::Constant.block_reward(100, 1_000_000), 100);
+
+        let halving = RewardSchedule::Halving { interval: 10 };
+        assert_eq!(halving.block_reward(100, 0), 100);
+        assert_eq!(halving.block_reward(100, 9), 100);
+        assert_eq!(halving.block_reward(100, 10), 50);
+        assert_eq!(halving.block_reward(100, 25), 25);
+        assert_eq!(halving.block_reward(100, 10_000), 0);
+
+        let decay = RewardSchedule::EpochDecay {
+            epoch: 10,
+            numerator: 9,
+            denominator: 10,
+        };
+        assert_eq!(decay.block_reward(1000, 9), 1000);
+        assert_eq!(decay.block_reward(1000, 10), 900);
+        assert_eq!(decay.block_reward(1000, 20), 810);
+        assert_eq!(decay.block_reward(1000, 1_000_000), 0);
+        // Far epochs take as long
+        assert_eq!(decay.block_reward(1000, u64::max_value()), 0);
+
+        let constant = RewardSchedule::EpochDecay {
+            epoch: 1,
+            numerator: 3,
+            denominator: 3,
+        };
+        assert_eq!(constant.block_reward(1000, u64::max_value()), 1000);
+        let slow = RewardSchedule::EpochDecay {
+            epoch: 1,
+            numerator: 999_999,
+            denominator: 1_000_000,
+        };
+        assert_eq!(slow.block_reward(Capacity::max_value(), 0), Capacity::max_value());
+        assert!(slow.block_reward(1_000_000, 1_000_000) < 368_000);
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(RewardSchedule::Constant.validate().is_ok());
+        assert!(RewardSchedule::Halving { interval: 10 }.validate().is_ok());
+        assert!(RewardSchedule::Halving { interval: 0 }.validate().is_err());
+        let decay = |epoch, numerator, denominator| RewardSchedule::EpochDecay {
+            epoch,
+            numerator,
+            denominator,
+        };
+        assert!(decay(10, 9, 10).validate().is_ok());
+        assert!(decay(10, 10, 10).validate().is_ok());
+        assert!(decay(0, 9, 10).validate().is_err());
+        assert!(decay(10, 9, 0).validate().is_err());
+        assert!(decay(10, 11, 10).validate().is_err());
+    }
+}
@@ -0,0 +1,114 @@
+//! Soft-fork deployments signalled with header version bits.
+//!
+//! The chain is divided in periods of `versionbits_period` blocks. A
+//! deployment is `Defined` until the period containing its `start` block,
+//! then `Started`; a `Started` deployment is `LockedIn` once a period has at
+//! least `versionbits_threshold` blocks signalling its bit, and `Active` one
+//! period later. It is `Failed` when not locked in before `timeout`.
+//!
+//! A signalling header version has [VERSIONBITS_TOP_BITS] in its top three
+//! bits and the bits of the deployments it supports below.
+
+use ckb_core::{BlockNumber, Version};
+use serde_derive::{Deserialize, Serialize};
+
+pub const VERSIONBITS_TOP_BITS: Version = 0x2000_0000;
+pub const VERSIONBITS_TOP_MASK: Version = 0xE000_0000;
+pub const VERSIONBITS_NUM_BITS: u8 = 29;
+
+#[derive(Clone, Copy, Serialize, Deserialize, Eq, PartialEq, Hash, Debug)]
+pub enum ThresholdState {
+    Defined,
+    Started,
+    LockedIn,
+    Active,
+    Failed,
+}
+
+#[derive(Clone, Deserialize, Eq, PartialEq, Hash, Debug)]
+pub struct Deployment {
+    pub name: String,
+    /// Version bit signalling the deployment, below `VERSIONBITS_NUM_BITS`
+    pub bit: u8,
+    /// Signalling starts with the period containing this block
+    pub start: BlockNumber,
+    /// The deployment fails if not locked in by the period containing this
+    /// block
+    pub timeout: BlockNumber,
+}
+
+impl Deployment {
+    pub fn mask(&self) -> Version {
+        1 << self.bit
+    }
+
+    pub fn signals(&self, version: Version) -> bool {
+        version & VERSIONBITS_TOP_MASK == VERSIONBITS_TOP_BITS && version & self.mask() != 0
+    }
+
+    /// State of the period which starts after block `last`, given the state
+    /// of the period ending with `last` and how many of its blocks signalled.
+    pub fn next_state(
+        &self,
+        state: ThresholdState,
+        last: BlockNumber,
+        signalled: u64,
+        threshold: u64,
+    ) -> ThresholdState {
+        let next = last + 1;
+        match state {
+            ThresholdState::Defined => {
+                if next >= self.timeout {
+                    ThresholdState::Failed
+                } else if next >= self.start {
+                    ThresholdState::Started
+                } else {
+                    ThresholdState::Defined
+                }
+            }
+            ThresholdState::Started => {
+                if next >= self.timeout {
+                    ThresholdState::Failed
+                } else if signalled >= threshold {
+                    ThresholdState::LockedIn
+                } else {
+                    ThresholdState::Started
+                }
+            }
+            ThresholdState::LockedIn => ThresholdState::Active,
+            ThresholdState::Active | ThresholdState::Failed => state,
+        }
+    }
+}
+
+/// Headers either use the legacy version or signal with version bits
+pub fn is_valid_version(version: Version, block_version: Version) -> bool {
+    version == block_version || version & VERSIONBITS_TOP_MASK == VERSIONBITS_TOP_BITS
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_next_state() {
+        let deployment = Deployment {
+            name: "test".to_owned(),
+            bit: 1,
+            start: 20,
+            timeout: 50,
+        };
+        assert!(deployment.signals(VERSIONBITS_TOP_BITS | 2));
+        assert!(!deployment.signals(VERSIONBITS_TOP_BITS | 1));
+        assert!(!deployment.signals(2));
+
+        let next = |state, last, signalled| deployment.next_state(state, last, signalled, 8);
+        assert_eq!(next(ThresholdState::Defined, 9, 10), ThresholdState::Defined);
+        assert_eq!(next(ThresholdState::Defined, 19, 10), ThresholdState::Started);
+        assert_eq!(next(ThresholdState::Started, 29, 7), ThresholdState::Started);
+        assert_eq!(next(ThresholdState::Started, 29, 8), ThresholdState::LockedIn);
+        assert_eq!(next(ThresholdState::Started, 49, 10), ThresholdState::Failed);
+        assert_eq!(next(ThresholdState::LockedIn, 59, 0), ThresholdState::Active);
+        assert_eq!(next(ThresholdState::Active, 69, 0), ThresholdState::Active);
+    }
+}
@@ -1,12 +1,11 @@
-// use build_info::Version;
-use build_info::{get_version, Version};
+use crate::helper::version;
 use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
 
-const CKB_CONFIG_HELP: &str = "Specify the configuration file PATH. Tries ckb.json, nodes/default.json in working directory when omitted.";
-const MINER_CONFIG_HELP: &str = "Specify the configuration file PATH. Tries miner.json, nodes/miner.json in working directory when omitted.";
+const CKB_CONFIG_HELP: &str = "Specify the configuration file PATH. Tries ckb.toml, ckb.json, nodes/default.toml, nodes/default.json in working directory when omitted.";
+const MINER_CONFIG_HELP: &str = "Specify the configuration file PATH. Tries miner.toml, miner.json, nodes/miner.toml, nodes/miner.json in working directory when omitted.";
 
 pub fn get_matches() -> ArgMatches<'static> {
-    let version = get_version!();
+    let version = version();
 
     App::new("ckb")
         .author("Nervos Core Dev <dev@nervos.org>")
@@ -14,17 +13,47 @@ pub fn get_matches() -> ArgMatches<'static> {
         .version(version.short().as_str())
         .long_version(version.long().as_str())
         .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(init())
         .subcommand(run())
         .subcommand(miner())
         .subcommand(export())
         .subcommand(import())
+        .subcommand(snapshot())
+        .subcommand(restore())
+        .subcommand(restore_backup())
+        .subcommand(check_db())
+        .subcommand(peer_id())
+        .subcommand(keystore())
         .subcommand(cli())
         .get_matches()
 }
 
+fn init() -> App<'static, 'static> {
+    SubCommand::with_name("init")
+        .about("Write the node and miner configs with the development chain spec")
+        .arg(
+            Arg::with_name("dir")
+                .short("C")
+                .long("dir")
+                .value_name("PATH")
+                .takes_value(true)
+                .help("Specify the directory to write to, defaults to the working directory."),
+        )
+        .arg(
+            Arg::with_name("force")
+                .long("force")
+                .help("Overwrite the existing files."),
+        )
+}
+
 fn run() -> App<'static, 'static> {
     SubCommand::with_name("run")
         .arg(arg_config_with_help(CKB_CONFIG_HELP))
+        .arg(
+            Arg::with_name("dev")
+                .long("dev")
+                .help("Run a local development chain, sealing a block as soon as a transaction arrives, with the Dev RPC module. Data in dev/ of the working directory when no config file is given."),
+        )
         .about("Running ckb node")
 }
 
@@ -85,6 +114,206 @@ fn import() -> App<'static, 'static> {
         )
 }
 
+fn snapshot() -> App<'static, 'static> {
+    SubCommand::with_name("snapshot")
+        .about("Write a snapshot of the chain state")
+        .arg(arg_config_with_help(CKB_CONFIG_HELP))
+        .arg(
+            Arg::with_name("number")
+                .short("n")
+                .long("number")
+                .value_name("NUMBER")
+                .takes_value(true)
+                .help("Specify the snapshot block number, defaults to the tip."),
+        )
+        .arg(
+            Arg::with_name("target")
+                .short("t")
+                .long("target")
+                .value_name("PATH")
+                .required(true)
+                .index(1)
+                .help("Specify the snapshot target path."),
+        )
+}
+
+fn restore() -> App<'static, 'static> {
+    SubCommand::with_name("restore")
+        .about("Initialize an empty node from a chain state snapshot")
+        .arg(arg_config_with_help(CKB_CONFIG_HELP))
+        .arg(
+            Arg::with_name("source")
+                .short("s")
+                .long("source")
+                .value_name("PATH")
+                .required(true)
+                .index(1)
+                .help("Specify the snapshot path."),
+        )
+}
+
+fn restore_backup() -> App<'static, 'static> {
+    SubCommand::with_name("restore_backup")
+        .about("Initialize an empty node from a backup of the chain store")
+        .arg(arg_config_with_help(CKB_CONFIG_HELP))
+        .arg(
+            Arg::with_name("source")
+                .short("s")
+                .long("source")
+                .value_name("PATH")
+                .required(true)
+                .index(1)
+                .help("Specify the backup path."),
+        )
+}
+
+fn check_db() -> App<'static, 'static> {
+    SubCommand::with_name("check_db")
+        .about("Check the chain store for inconsistencies, repairing needs the node stopped")
+        .arg(arg_config_with_help(CKB_CONFIG_HELP))
+        .arg(
+            Arg::with_name("repair")
+                .long("repair")
+                .help("Rebuild the indexes and rewind a broken main chain."),
+        )
+}
+
+fn peer_id() -> App<'static, 'static> {
+    SubCommand::with_name("peer_id")
+        .about("Print the peer id of the node, generating its network key when missing")
+        .arg(arg_config_with_help(CKB_CONFIG_HELP))
+}
+
+fn arg_password_file() -> Arg<'static, 'static> {
+    Arg::with_name("password-file")
+        .long("password-file")
+        .value_name("PATH")
+        .takes_value(true)
+        .help("Read the password from the first line of PATH instead of stdin.")
+}
+
+fn keystore() -> App<'static, 'static> {
+    SubCommand::with_name("keystore")
+        .about("Manage the secp256k1 keys encrypted in the keystore of the data directory")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(
+            SubCommand::with_name("new")
+                .about("Generate a key and print its pubkey hash")
+                .arg(arg_config_with_help(CKB_CONFIG_HELP))
+                .arg(arg_password_file()),
+        )
+        .subcommand(
+            SubCommand::with_name("list")
+                .about("Print the pubkey hashes of the keys")
+                .arg(arg_config_with_help(CKB_CONFIG_HELP))
+                .arg(
+                    Arg::with_name("seeds")
+                        .long("seeds")
+                        .help("Print the pubkey hashes of the master keys of the seeds instead."),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("import")
+                .about("Encrypt a private key into the keystore and print its pubkey hash")
+                .arg(arg_config_with_help(CKB_CONFIG_HELP))
+                .arg(arg_password_file())
+                .arg(
+                    Arg::with_name("privkey-path")
+                        .value_name("PATH")
+                        .required(true)
+                        .index(1)
+                        .help("Specify the file holding the private key in hex."),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("export")
+                .about("Decrypt a key and print its private key")
+                .arg(arg_config_with_help(CKB_CONFIG_HELP))
+                .arg(arg_password_file())
+                .arg(
+                    Arg::with_name("pubkey-hash")
+                        .value_name("HASH")
+                        .required(true)
+                        .index(1)
+                        .help("Specify the pubkey hash of the key."),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("new-seed")
+                .about("Generate a seed deriving keys and print the pubkey hash of its master key")
+                .arg(arg_config_with_help(CKB_CONFIG_HELP))
+                .arg(arg_password_file()),
+        )
+        .subcommand(
+            SubCommand::with_name("import-seed")
+                .about("Encrypt a seed into the keystore and print the pubkey hash of its master key")
+                .arg(arg_config_with_help(CKB_CONFIG_HELP))
+                .arg(arg_password_file())
+                .arg(
+                    Arg::with_name("seed-path")
+                        .value_name("PATH")
+                        .required(true)
+                        .index(1)
+                        .help("Specify the file holding the seed in hex."),
+                )
+                .arg(
+                    Arg::with_name("mnemonic")
+                        .long("mnemonic")
+                        .help("Read a BIP39 mnemonic from the file instead, its words are not checked."),
+                )
+                .arg(
+                    Arg::with_name("passphrase-file")
+                        .long("passphrase-file")
+                        .value_name("PATH")
+                        .takes_value(true)
+                        .requires("mnemonic")
+                        .help("Read the BIP39 passphrase of the mnemonic from the first line of PATH, empty by default."),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("export-seed")
+                .about("Decrypt a seed and print it, to back up the keys it derives")
+                .arg(arg_config_with_help(CKB_CONFIG_HELP))
+                .arg(arg_password_file())
+                .arg(arg_seed()),
+        )
+        .subcommand(
+            SubCommand::with_name("derive")
+                .about("Print the pubkey hash, the default lock and its address of a key derived from a seed")
+                .arg(arg_config_with_help(CKB_CONFIG_HELP))
+                .arg(arg_password_file())
+                .arg(arg_seed())
+                .arg(
+                    Arg::with_name("index")
+                        .long("index")
+                        .value_name("INDEX")
+                        .takes_value(true)
+                        .conflicts_with("path")
+                        .help("Derive the receiving key INDEX, at m/44'/309'/0'/0/INDEX, defaults to 0."),
+                )
+                .arg(
+                    Arg::with_name("path")
+                        .long("path")
+                        .value_name("PATH")
+                        .takes_value(true)
+                        .help("Derive the key at the BIP32 path, like m/44'/309'/0'/0/0."),
+                )
+                .arg(
+                    Arg::with_name("export")
+                        .long("export")
+                        .help("Print the private key too."),
+                ),
+        )
+}
+
+fn arg_seed() -> Arg<'static, 'static> {
+    Arg::with_name("seed")
+        .value_name("HASH")
+        .required(true)
+        .index(1)
+        .help("Specify the pubkey hash of the master key of the seed.")
+}
+
 fn cli() -> App<'static, 'static> {
     SubCommand::with_name("cli")
         .about("Running ckb cli")
@@ -0,0 +1,58 @@
+use super::super::setup::Setup;
+use ckb_db::diskdb::RocksDB;
+use ckb_shared::ancient::AncientStore;
+use ckb_shared::check::check_db as check;
+use ckb_shared::store::ChainKVStore;
+use ckb_shared::COLUMNS;
+use clap::ArgMatches;
+use log::{info, warn};
+
+pub fn check_db(setup: &Setup, matches: &ArgMatches) {
+    let repair = matches.is_present("repair");
+
+    // The store is opened as is, building a shared would need it consistent.
+    // A mere check can run along with the node.
+    let _lock = if repair {
+        Some(setup.lock_data_dir())
+    } else {
+        None
+    };
+    let db_path = setup.dirs.db();
+    let db = if repair {
+        RocksDB::open_with_config(db_path, COLUMNS, &setup.configs.db)
+    } else {
+        RocksDB::open_read_only(db_path, COLUMNS, &setup.configs.db)
+    };
+    let mut store = ChainKVStore::new(db);
+    if setup.configs.chain.ancient_depth.is_some() {
+        let ancient_path = setup.dirs.join("ancient");
+        let ancient = if repair {
+            AncientStore::open(ancient_path)
+        } else {
+            AncientStore::open_read_only(ancient_path)
+        };
+        let ancient = ancient.expect("ancient store open");
+        store = store.with_ancient(ancient);
+    }
+
+    let report = check(&store, repair).unwrap_or_else(|e| panic!("Check error {:?} ", e));
+    for (number, inconsistency) in &report.inconsistencies {
+        warn!(target: "main", "block {}: {:?}", number, inconsistency);
+    }
+    info!(
+        target: "main",
+        "Checked {} blocks, found {} inconsistencies",
+        report.checked,
+        report.inconsistencies.len()
+    );
+    if let Some(number) = report.rewound_to {
+        info!(target: "main", "Rewound the main chain to block {}", number);
+    }
+    if repair {
+        // The node checks the db again on start until repaired
+        setup.dirs.clear_crashed().expect("clear the crash marker");
+    }
+    if !report.is_consistent() && !repair {
+        info!(target: "main", "Run with --repair to rebuild the indexes and rewind the chain");
+    }
+}
@@ -0,0 +1,66 @@
+use clap::ArgMatches;
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::Path;
+
+// The node and miner configs of `nodes_template` with the chain spec they use
+const TEMPLATE_FILES: &[(&str, &[u8])] = &[
+    ("ckb.toml", include_bytes!("../../nodes_template/default.toml")),
+    ("miner.toml", include_bytes!("../../nodes_template/miner.toml")),
+    ("spec/dev.json", include_bytes!("../../nodes_template/spec/dev.json")),
+    (
+        "spec/cells/always_success",
+        include_bytes!("../../nodes_template/spec/cells/always_success"),
+    ),
+];
+
+pub fn init(matches: &ArgMatches) {
+    let dir = Path::new(matches.value_of("dir").unwrap_or("."));
+    if let Err(err) = init_dir(dir, matches.is_present("force")) {
+        eprintln!("Failed to init {}: {}", dir.display(), err);
+        ::std::process::exit(1);
+    }
+}
+
+fn init_dir(dir: &Path, force: bool) -> io::Result<()> {
+    if !force {
+        let existing = TEMPLATE_FILES
+            .iter()
+            .map(|(name, _)| dir.join(name))
+            .find(|path| path.exists());
+        if let Some(path) = existing {
+            return Err(io::Error::new(
+                ErrorKind::AlreadyExists,
+                format!("{} exists, --force overwrites it", path.display()),
+            ));
+        }
+    }
+    for (name, content) in TEMPLATE_FILES {
+        let path = dir.join(name);
+        fs::create_dir_all(path.parent().expect("template file in a dir"))?;
+        fs::write(&path, content)?;
+        println!("wrote {}", path.display());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::setup::Setup;
+    use tempfile;
+
+    #[test]
+    fn test_init_dir() {
+        let tmp_dir = tempfile::Builder::new()
+            .prefix("test_init_dir")
+            .tempdir()
+            .unwrap();
+        init_dir(tmp_dir.path(), false).unwrap();
+        let setup = Setup::setup(tmp_dir.path().join("ckb.toml")).unwrap();
+        assert_eq!(setup.configs.data_dir, tmp_dir.path().join("default"));
+
+        assert!(init_dir(tmp_dir.path(), false).is_err());
+        init_dir(tmp_dir.path(), true).unwrap();
+    }
+}
@@ -0,0 +1,175 @@
+use super::super::setup::Setup;
+use ckb_core::address::{Address, AddressNetwork};
+use ckb_core::signing::default_lock;
+use ckb_keystore::hd::DerivationPath;
+use ckb_keystore::{pubkey_hash, KeyStore};
+use clap::ArgMatches;
+use crypto::secp::Privkey;
+use faster_hex::{hex_decode, hex_string};
+use numext_fixed_hash::H256;
+use std::fs;
+use std::io::{self, BufRead};
+use std::process;
+use std::str::FromStr;
+
+pub fn keystore(setup: &Setup, command: &str, matches: &ArgMatches) {
+    let keystore = KeyStore::open(setup.dirs.join("keystore")).expect("keystore open");
+    let result = match command {
+        "new" => keystore
+            .generate(&password(matches))
+            .map(|hash| println!("{:#x}", hash)),
+        "list" => {
+            let hashes = if matches.is_present("seeds") {
+                keystore.list_seeds()
+            } else {
+                keystore.list()
+            };
+            hashes.map(|hashes| {
+                for hash in hashes {
+                    println!("{:#x}", hash);
+                }
+            })
+        }
+        "import" => {
+            let content = read_hex_file(matches.value_of("privkey-path").expect("privkey path"));
+            let privkey =
+                Privkey::from_str(&content).unwrap_or_else(|_| exit("invalid private key"));
+            keystore
+                .import(&privkey, &password(matches))
+                .map(|hash| println!("{:#x}", hash))
+        }
+        "export" => {
+            let hash = parse_hash(matches.value_of("pubkey-hash").expect("pubkey hash"));
+            keystore
+                .export(&hash, &password(matches))
+                .map(|privkey| println!("{:#x}", *privkey))
+        }
+        "new-seed" => keystore
+            .generate_seed(&password(matches))
+            .map(|hash| println!("{:#x}", hash)),
+        "import-seed" if matches.is_present("mnemonic") => {
+            let path = matches.value_of("seed-path").expect("seed path");
+            let mnemonic = fs::read_to_string(path)
+                .unwrap_or_else(|err| exit(&format!("read {}: {}", path, err)));
+            let passphrase = match matches.value_of("passphrase-file") {
+                Some(path) => fs::read_to_string(path)
+                    .map(|content| content.lines().next().unwrap_or_default().to_owned())
+                    .unwrap_or_else(|err| exit(&format!("read {}: {}", path, err))),
+                None => String::new(),
+            };
+            keystore
+                .import_mnemonic(&mnemonic, &passphrase, &password(matches))
+                .map(|hash| println!("{:#x}", hash))
+        }
+        "import-seed" => {
+            let content = read_hex_file(matches.value_of("seed-path").expect("seed path"));
+            let mut seed = vec![0; content.len() / 2];
+            if content.len() % 2 != 0 || hex_decode(content.as_bytes(), &mut seed).is_err() {
+                exit("invalid seed");
+            }
+            keystore
+                .import_seed(&seed, &password(matches))
+                .map(|hash| println!("{:#x}", hash))
+        }
+        "export-seed" => {
+            let hash = parse_hash(matches.value_of("seed").expect("seed"));
+            keystore
+                .export_seed(&hash, &password(matches))
+                .map(|seed| println!("{}", hex_string(&seed).expect("hex string")))
+        }
+        "derive" => {
+            let hash = parse_hash(matches.value_of("seed").expect("seed"));
+            let network = AddressNetwork::from_network_id(setup.chain_spec.params.network_id);
+            let path = match (matches.value_of("path"), matches.value_of("index")) {
+                (Some(path), _) => DerivationPath::from_str(path).unwrap_or_else(|err| exit(&err)),
+                (None, index) => {
+                    let index = index.unwrap_or("0");
+                    let index = index.parse().unwrap_or_else(|_| exit("invalid index"));
+                    DerivationPath::receiving(index)
+                }
+            };
+            keystore
+                .derive(&hash, &password(matches), &path)
+                .map(|privkey| {
+                    let pubkey = privkey.pubkey().expect("derived key");
+                    println!("path: {}", path);
+                    println!("pubkey_hash: {:#x}", pubkey_hash(&pubkey));
+                    let lock = default_lock(&pubkey);
+                    println!("lock: {:#x}", lock);
+                    println!("address: {}", Address::new(network, lock));
+                    if matches.is_present("export") {
+                        println!("privkey: {:#x}", *privkey);
+                    }
+                })
+        }
+        _ => unreachable!(),
+    };
+    if let Err(err) = result {
+        exit(&err.to_string());
+    }
+}
+
+fn read_hex_file(path: &str) -> String {
+    let content = fs::read_to_string(path).unwrap_or_else(|err| {
+        exit(&format!("read {}: {}", path, err));
+    });
+    content.trim().trim_start_matches("0x").to_owned()
+}
+
+fn parse_hash(hash: &str) -> H256 {
+    H256::from_hex_str(hash.trim_start_matches("0x")).unwrap_or_else(|_| exit("invalid hash"))
+}
+
+// The first line of the password file or of stdin, so that it can be piped
+fn password(matches: &ArgMatches) -> String {
+    let line = match matches.value_of("password-file") {
+        Some(path) => fs::read_to_string(path)
+            .map(|content| content.lines().next().unwrap_or_default().to_owned()),
+        None => {
+            eprintln!("Password:");
+            read_password_line()
+        }
+    };
+    let line = line.unwrap_or_else(|err| exit(&format!("read password: {}", err)));
+    let password = line.trim_end_matches(|c| c == '\r' || c == '\n').to_owned();
+    if password.is_empty() {
+        exit("empty password");
+    }
+    password
+}
+
+fn read_line() -> io::Result<String> {
+    let mut line = String::new();
+    io::stdin().lock().read_line(&mut line).map(|_| line)
+}
+
+// The typed password is not echoed when stdin is a terminal
+#[cfg(unix)]
+fn read_password_line() -> io::Result<String> {
+    use std::os::unix::io::AsRawFd;
+    use termios::{tcsetattr, Termios, ECHO, TCSANOW};
+
+    let fd = io::stdin().as_raw_fd();
+    let original = match Termios::from_fd(fd) {
+        Ok(original) => original,
+        // Piped
+        Err(_) => return read_line(),
+    };
+    let mut silent = original;
+    silent.c_lflag &= !ECHO;
+    tcsetattr(fd, TCSANOW, &silent)?;
+    let line = read_line();
+    tcsetattr(fd, TCSANOW, &original)?;
+    eprintln!();
+    line
+}
+
+#[cfg(not(unix))]
+fn read_password_line() -> io::Result<String> {
+    read_line()
+}
+
+fn exit(message: &str) -> ! {
+    eprintln!("{}", message);
+    process::exit(1);
+}
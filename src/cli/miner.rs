@@ -3,20 +3,26 @@ use ckb_chain_spec::ChainSpec;
 use ckb_miner::{Client, Miner, MinerConfig};
 use ckb_util::RwLock;
 use clap::ArgMatches;
+use config_tool::{Config as ConfigTool, File};
 use crossbeam_channel::unbounded;
-use dir::Directories;
+use dir::DataDir;
 use logger::{self, Config as LogConfig};
 use serde_derive::Deserialize;
 use std::error::Error;
-use std::fs::File;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::thread;
 
-const DEFAULT_CONFIG_PATHS: &[&str] = &["miner.json", "nodes/miner.json"];
+const DEFAULT_CONFIG_PATHS: &[&str] = &[
+    "miner.toml",
+    "miner.json",
+    "nodes/miner.toml",
+    "nodes/miner.json",
+];
 
 #[derive(Clone, Debug, Deserialize)]
 struct Config {
+    #[serde(default)]
     pub logger: LogConfig,
     #[serde(flatten)]
     pub miner: MinerConfig,
@@ -34,16 +40,15 @@ impl Config {
             self.data_dir = base.join(&self.data_dir);
         }
 
-        let dirs = Directories::new(&self.data_dir);
         if let Some(ref file) = self.logger.file {
-            let path = dirs.join("logs");
-            self.logger.file = Some(path.join(file));
+            self.logger.file = Some(DataDir::new(&self.data_dir).logs().join(file));
         }
     }
 
     pub fn read_from_file<P: AsRef<Path>>(path: P) -> Result<Config, Box<Error>> {
-        let file = File::open(path.as_ref())?;
-        let mut config: Self = serde_json::from_reader(file)?;
+        let mut config_tool = ConfigTool::new();
+        config_tool.merge(File::from(path.as_ref()))?;
+        let mut config: Self = config_tool.try_into()?;
         config.resolve_paths(path.as_ref().parent().unwrap());
         Ok(config)
     }
@@ -53,13 +58,13 @@ pub fn miner(matches: &ArgMatches) {
     let config_path = get_config_path(matches);
 
     let config = Config::read_from_file(config_path).unwrap_or_else(|e| {
-        eprintln!("Invalid config file {:?}", e);
+        eprintln!("Invalid config file: {}", e);
         ::std::process::exit(1);
     });
 
     logger::init(config.logger.clone()).expect("Init Logger");
 
-    let chain_spec = ChainSpec::read_from_file(&config.chain).expect("Load chain spec");
+    let chain_spec = ChainSpec::load(&config.chain).expect("Load chain spec");
 
     let (new_work_tx, new_work_rx) = unbounded();
 
@@ -1,11 +1,19 @@
 mod args;
+mod check_db;
 mod export;
 mod import;
+mod init;
+mod keystore;
 mod miner;
 mod run_impl;
+mod snapshot;
 
 pub use self::args::get_matches;
+pub use self::check_db::check_db;
 pub use self::export::export;
 pub use self::import::import;
+pub use self::init::init;
+pub use self::keystore::keystore;
 pub use self::miner::miner;
-pub use self::run_impl::{keygen, run, type_hash};
+pub use self::run_impl::{keygen, peer_id, run, type_hash};
+pub use self::snapshot::{restore, restore_backup, snapshot};
@@ -1,42 +1,141 @@
-use crate::helper::wait_for_exit;
+use crate::helper::{handle_panic, handle_reload, version, wait_for_exit};
 use crate::Setup;
 use ckb_chain::chain::{ChainBuilder, ChainController};
 use ckb_core::script::Script;
+use ckb_core::BlockNumber;
+use ckb_db::config::DBBackend;
 use ckb_db::diskdb::RocksDB;
-use ckb_miner::{BlockAssembler, BlockAssemblerController};
+use ckb_db::kvdb::KeyValueDB;
+use ckb_db::memorydb::MemoryKeyValueDB;
+use ckb_indexer::{IndexerController, IndexerService, COLUMNS as INDEXER_COLUMNS};
+use ckb_miner::{BlockAssembler, BlockAssemblerController, InstantSealer};
 use ckb_network::CKBProtocol;
 use ckb_network::NetworkConfig;
 use ckb_network::NetworkService;
-use ckb_notify::{NotifyController, NotifyService};
+use ckb_notify::{start_publisher, NotifyController, NotifyService};
 use ckb_pool::txs_pool::{PoolConfig, TransactionPoolController, TransactionPoolService};
 use ckb_pow::PowEngine;
-use ckb_rpc::{Config as RpcConfig, RpcServer};
+use ckb_rpc::{Config as RpcConfig, RpcServer, RuntimeOverrides};
 use ckb_shared::cachedb::CacheDB;
+use ckb_shared::check::check_db;
 use ckb_shared::index::ChainIndex;
 use ckb_shared::shared::{ChainProvider, Shared, SharedBuilder};
-use ckb_shared::store::ChainKVStore;
+use ckb_shared::store::{ChainKVStore, ChainStore};
 use ckb_sync::{
     NetTimeProtocol, Relayer, Synchronizer, RELAY_PROTOCOL_ID, SYNC_PROTOCOL_ID, TIME_PROTOCOL_ID,
 };
 use crypto::secp::Generator;
-use log::info;
+use log::{error, info, warn};
 use numext_fixed_hash::H256;
+use stop_handler::Shutdown;
+use std::panic;
+use std::path::Path;
 use std::sync::Arc;
 
+// The threads writing the stores, the node aborts when one of them panics
+const CRITICAL_THREADS: &[&str] = &["main", "ChainService", "IndexerService"];
+
 pub fn run(setup: Setup) {
+    // Held until the node exits
+    let _lock = setup.lock_data_dir();
+
+    if let Some(ref faketime_file) = setup.configs.sync.faketime_file {
+        info!(target: "main", "read the clock from {}", faketime_file.display());
+        faketime::enable(faketime_file);
+    }
+
     let consensus = setup.chain_spec.to_consensus().unwrap();
+    match setup.configs.db.backend {
+        DBBackend::RocksDB => {
+            let db_path = setup.dirs.db();
+            let builder = SharedBuilder::<ChainKVStore<CacheDB<RocksDB>>>::new_rocks(
+                &db_path,
+                &setup.configs.db,
+            );
+            let shared = with_ancient(builder, &setup).consensus(consensus).build();
+            let indexer_db = RocksDB::open(setup.dirs.join("indexer"), INDEXER_COLUMNS);
+            run_node(setup, shared, indexer_db)
+        }
+        DBBackend::Memory => {
+            info!(target: "main", "the chain is kept in memory and lost on exit");
+            // Without an ancient store, which the config refuses for this backend
+            let shared = SharedBuilder::<ChainKVStore<MemoryKeyValueDB>>::new_memory()
+                .consensus(consensus)
+                .build();
+            run_node(setup, shared, MemoryKeyValueDB::open(INDEXER_COLUMNS))
+        }
+    }
+}
+
+fn with_ancient<T: 'static + KeyValueDB>(
+    builder: SharedBuilder<ChainKVStore<T>>,
+    setup: &Setup,
+) -> SharedBuilder<ChainKVStore<T>> {
+    if setup.configs.chain.ancient_depth.is_some() {
+        builder.ancient(setup.dirs.join("ancient"))
+    } else {
+        builder
+    }
+}
+
+// The indexer is kept in the backend of the chain, so that a chain in memory
+// is not indexed into the data dir
+fn run_node<CI: ChainIndex + 'static, T: KeyValueDB + 'static>(
+    setup: Setup,
+    shared: Shared<CI>,
+    indexer_db: T,
+) {
     let pow_engine = setup.chain_spec.pow_engine();
-    let db_path = setup.dirs.join("db");
+    let config_path = setup.config_path.clone();
 
-    let shared = SharedBuilder::<ChainKVStore<CacheDB<RocksDB>>>::new_rocks(&db_path)
-        .consensus(consensus)
-        .build();
+    check_after_crash(&setup, &shared);
 
     let notify = NotifyService::default().start(Some("notify"));
+    if let Some(ref socket) = setup.configs.notify.publisher_socket {
+        start_publisher(&notify, socket).unwrap_or_else(|err| {
+            eprintln!("Start the publisher on {} failed: {}", socket.display(), err);
+            ::std::process::exit(1);
+        });
+    }
+    if let Some(ref address) = setup.configs.metrics.listen_address {
+        ckb_metrics::start_server(address.as_str(), ckb_metrics::registry()).unwrap_or_else(|err| {
+            eprintln!("Start the metrics server on {} failed: {}", address, err);
+            ::std::process::exit(1);
+        });
+    }
+    ckb_metrics::start_monitor(setup.configs.metrics.monitor.clone(), setup.dirs.db())
+        .expect("Start resource monitor failed");
 
-    let chain_controller = setup_chain(shared.clone(), notify.clone());
+    let chain_controller = setup_chain(
+        shared.clone(),
+        notify.clone(),
+        setup.configs.chain.prune_depth,
+        setup.configs.chain.ancient_depth,
+    );
     info!(target: "main", "chain genesis hash: {:#x}", shared.genesis_hash());
     let tx_pool_controller = setup_tx_pool(setup.configs.pool, shared.clone(), notify.clone());
+    let local_txs_path = setup.dirs.join("tx_pool").join("local_txs");
+    match tx_pool_controller.load_local_transactions(&local_txs_path) {
+        Ok(0) => {}
+        Ok(count) => info!(target: "main", "added back {} local transactions", count),
+        Err(err) => warn!(target: "main", "load the local transactions failed: {}", err),
+    }
+
+    // The peer store writes every change to its file, the db and the local
+    // transactions are flushed. The threads handling the peer messages are
+    // not critical, a peer may make them panic.
+    let flushed = shared.clone();
+    let flushed_tx_pool = tx_pool_controller.clone();
+    let flushed_txs_path = local_txs_path.clone();
+    handle_panic(setup.dirs.clone(), CRITICAL_THREADS, move || {
+        if let Err(err) = flushed.store().sync() {
+            error!(target: "main", "sync the db failed: {:?}", err);
+        }
+        if let Err(err) = flushed_tx_pool.save_local_transactions(&flushed_txs_path) {
+            error!(target: "main", "save the local transactions failed: {}", err);
+        }
+    });
+    let indexer_controller = setup_indexer(indexer_db, shared.clone(), &notify);
 
     let block_assembler = BlockAssembler::new(
         shared.clone(),
@@ -44,12 +143,27 @@ pub fn run(setup: Setup) {
         setup.configs.block_assembler.type_hash,
     );
     let block_assembler_controller = block_assembler.start(Some("MinerAgent"), &notify);
+    let sealer_controller = if setup.configs.block_assembler.instant_seal {
+        info!(target: "main", "blocks are sealed as soon as transactions enter the pool");
+        let sealer = InstantSealer::new(
+            block_assembler_controller.clone(),
+            chain_controller.clone(),
+            tx_pool_controller.clone(),
+        );
+        Some(sealer.start(Some("InstantSealer"), &notify))
+    } else {
+        None
+    };
 
-    let synchronizer = Arc::new(Synchronizer::new(
-        chain_controller.clone(),
-        shared.clone(),
-        setup.configs.sync,
-    ));
+    let net_time_checker = Arc::new(NetTimeProtocol::from_config(&setup.configs.sync));
+
+    let synchronizer = Arc::new(
+        Synchronizer::new(chain_controller.clone(), shared.clone(), setup.configs.sync)
+            .with_notify(notify.clone()),
+    );
+
+    // Tells the RPC the initial block download
+    let rpc_synchronizer = Synchronizer::clone(&synchronizer);
 
     let relayer = Arc::new(Relayer::new(
         chain_controller.clone(),
@@ -58,10 +172,14 @@ pub fn run(setup: Setup) {
         synchronizer.peers(),
     ));
 
-    let net_time_checker = Arc::new(NetTimeProtocol::default());
-
-    let network_config = NetworkConfig::from(setup.configs.network);
-    let protocol_base_name = "ckb";
+    let mut network_config = NetworkConfig::from(setup.configs.network);
+    // Told to the peers when identifying
+    network_config.client_version = version().to_string();
+    // peers of other networks speak different protocols
+    let protocol_base_name = match shared.consensus().network_id() {
+        0 => "ckb".to_string(),
+        network_id => format!("ckb-{}", network_id),
+    };
     let protocols = vec![
         CKBProtocol::new(
             protocol_base_name.to_string(),
@@ -87,32 +205,144 @@ pub fn run(setup: Setup) {
             .expect("Create and start network"),
     );
 
-    let rpc_server = setup_rpc(
+    let mut rpc_server = setup_rpc(
         setup.configs.rpc,
         &pow_engine,
         Arc::clone(&network),
-        shared,
-        tx_pool_controller,
-        chain_controller,
-        block_assembler_controller,
+        shared.clone(),
+        tx_pool_controller.clone(),
+        chain_controller.clone(),
+        block_assembler_controller.clone(),
+        indexer_controller.clone(),
+        rpc_synchronizer,
+    );
+    rpc_server.start_subscription(
+        &notify,
+        tx_pool_controller.clone(),
+        indexer_controller.clone(),
     );
 
+    if let Some(config_path) = config_path {
+        let network = Arc::clone(&network);
+        let tx_pool_controller = tx_pool_controller.clone();
+        let overrides = rpc_server.runtime_overrides();
+        handle_reload(move || reload(&config_path, &network, &tx_pool_controller, &overrides));
+    }
+
     wait_for_exit();
 
     info!(target: "main", "Finishing work, please wait...");
 
-    rpc_server.close();
-    info!(target: "main", "Jsonrpc shutdown");
+    // Every service is stopped before the ones it calls, and the notify
+    // service after the ones publishing events. The peer store writes every
+    // change to its file, the local transactions are saved before the pool
+    // stops.
+    Shutdown::default()
+        .step("jsonrpc", move || rpc_server.close())
+        .step("sealer", move || {
+            if let Some(sealer_controller) = sealer_controller {
+                sealer_controller.stop();
+            }
+        })
+        .step("miner", move || block_assembler_controller.stop())
+        .step("network", move || network.close())
+        .step("indexer", move || indexer_controller.stop())
+        .step("transaction pool", move || {
+            match tx_pool_controller.save_local_transactions(&local_txs_path) {
+                Ok(count) => info!(target: "main", "saved {} local transactions", count),
+                Err(err) => error!(target: "main", "save the local transactions failed: {}", err),
+            }
+            tx_pool_controller.stop()
+        })
+        .step("chain", move || chain_controller.stop())
+        .step("notify", move || notify.stop())
+        .step("db", move || {
+            if let Err(err) = shared.store().sync() {
+                error!(target: "main", "sync the db failed: {:?}", err);
+            }
+        })
+        .run();
+    // A panic while the node exits afterwards is no crash
+    let _ = panic::take_hook();
+}
 
-    network.close();
-    info!(target: "main", "Network shutdown");
+// The stores are checked before use when the last node using them panicked,
+// the node exits when they need a repair
+fn check_after_crash<CI: ChainIndex + 'static>(setup: &Setup, shared: &Shared<CI>) {
+    let reason = match setup.dirs.crashed() {
+        Some(reason) => reason,
+        None => return,
+    };
+    warn!(target: "main", "the last run crashed: {}", reason.trim());
+    info!(target: "main", "checking the db, please wait...");
+    let report = check_db(shared.store().as_ref(), false)
+        .unwrap_or_else(|err| panic!("Check error {:?}", err));
+    if !report.is_consistent() {
+        for (number, inconsistency) in &report.inconsistencies {
+            warn!(target: "main", "block {}: {:?}", number, inconsistency);
+        }
+        eprintln!(
+            "Found {} inconsistencies in the db, run `ckb check_db --repair`",
+            report.inconsistencies.len()
+        );
+        ::std::process::exit(1);
+    }
+    info!(target: "main", "checked {} blocks, the db is consistent", report.checked);
+    setup.dirs.clear_crashed().expect("clear the crash marker");
+}
+
+// Applies the settings which can change without restarting the node. The
+// connected peers above new peer limits and the transactions in the pool
+// below a new min fee rate are kept. The settings changed by the
+// `update_config` RPC are kept over the ones of the file.
+fn reload(
+    config_path: &Path,
+    network: &NetworkService,
+    tx_pool: &TransactionPoolController,
+    overrides: &RuntimeOverrides,
+) {
+    let configs = match Setup::setup(config_path) {
+        Ok(setup) => setup.configs,
+        Err(err) => {
+            error!(target: "main", "reload {} failed: {}", config_path.display(), err);
+            return;
+        }
+    };
+    let overrides = overrides.lock();
+    if let Some(ref filter) = configs.logger.filter {
+        if let Err(err) = logger::set_filter(filter) {
+            error!(target: "main", "reload the log filter failed: {}", err);
+        }
+    }
+    if let Some(ref filter) = overrides.log_filter {
+        logger::set_filter(filter).expect("checked filter");
+    }
+    network.set_peer_limits(
+        overrides
+            .max_inbound_peers
+            .unwrap_or_else(|| configs.network.max_inbound_peers()),
+        overrides
+            .max_outbound_peers
+            .unwrap_or_else(|| configs.network.max_outbound_peers()),
+    );
+    tx_pool.set_min_fee_rate(overrides.min_fee_rate.unwrap_or(configs.pool.min_fee_rate));
+    info!(
+        target: "main",
+        "reloaded the log filter, peer limits and min fee rate from {}",
+        config_path.display()
+    );
 }
 
 fn setup_chain<CI: ChainIndex + 'static>(
     shared: Shared<CI>,
     notify: NotifyController,
+    prune_depth: Option<BlockNumber>,
+    ancient_depth: Option<BlockNumber>,
 ) -> ChainController {
-    let chain_service = ChainBuilder::new(shared, notify).build();
+    let chain_service = ChainBuilder::new(shared, notify)
+        .prune_depth(prune_depth)
+        .ancient_depth(ancient_depth)
+        .build();
     chain_service.start(Some("ChainService"))
 }
 
@@ -125,7 +355,17 @@ fn setup_tx_pool<CI: ChainIndex + 'static>(
     tx_pool_service.start(Some("TransactionPoolService"))
 }
 
-fn setup_rpc<CI: ChainIndex + 'static>(
+fn setup_indexer<CI: ChainIndex + 'static, T: KeyValueDB + 'static>(
+    db: T,
+    shared: Shared<CI>,
+    notify: &NotifyController,
+) -> IndexerController<T> {
+    let indexer_service = IndexerService::new(shared, db);
+    indexer_service.start(Some("IndexerService"), notify)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn setup_rpc<CI: ChainIndex + 'static, T: KeyValueDB + 'static>(
     config: RpcConfig,
     pow: &Arc<dyn PowEngine>,
     network: Arc<NetworkService>,
@@ -133,6 +373,8 @@ fn setup_rpc<CI: ChainIndex + 'static>(
     tx_pool: TransactionPoolController,
     chain: ChainController,
     agent: BlockAssemblerController,
+    indexer: IndexerController<T>,
+    synchronizer: Synchronizer<CI>,
 ) -> RpcServer {
     use ckb_pow::Clicker;
 
@@ -142,7 +384,18 @@ fn setup_rpc<CI: ChainIndex + 'static>(
         .downcast_ref::<Clicker>()
         .map(|pow| Arc::new(pow.clone()));
 
-    let mut server = RpcServer::new(config, network, shared, tx_pool, chain, agent, pow);
+    let mut server = RpcServer::new(
+        config,
+        network,
+        shared,
+        tx_pool,
+        chain,
+        agent,
+        indexer,
+        synchronizer,
+        pow,
+        version(),
+    );
     server.start();
     server
 }
@@ -160,3 +413,17 @@ pub fn keygen() {
     let result: H256 = Generator::new().random_privkey().into();
     println!("{:#x}", result);
 }
+
+/// Prints the peer id of the node, generating its network key first if it has
+/// none yet, as starting the node does
+pub fn peer_id(setup: &Setup) {
+    let network_config = NetworkConfig::from(setup.configs.network.clone());
+    let private_key = network_config
+        .fetch_private_key()
+        .expect("network key generated")
+        .unwrap_or_else(|err| {
+            eprintln!("Invalid network key: {}", err);
+            ::std::process::exit(1);
+        });
+    println!("{}", private_key.to_peer_id().to_base58());
+}
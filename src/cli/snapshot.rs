@@ -0,0 +1,76 @@
+use super::super::setup::Setup;
+use ckb_chain::snapshot::{create_snapshot, restore_snapshot};
+use ckb_core::BlockNumber;
+use ckb_db::diskdb::RocksDB;
+use ckb_shared::cachedb::CacheDB;
+use ckb_shared::shared::SharedBuilder;
+use ckb_shared::ancient::AncientStore;
+use ckb_shared::store::{ChainKVStore, ChainStore};
+use ckb_shared::COLUMNS;
+use clap::{value_t, ArgMatches};
+use log::info;
+use std::path::PathBuf;
+
+pub fn snapshot(setup: &Setup, matches: &ArgMatches) {
+    let target = value_t!(matches.value_of("target"), String).unwrap_or_else(|e| e.exit());
+
+    let _lock = setup.lock_data_dir();
+    let db_path = setup.dirs.db();
+
+    let shared = SharedBuilder::<ChainKVStore<CacheDB<RocksDB>>>::new_rocks(
+        &db_path,
+        &setup.configs.db,
+    )
+    .consensus(setup.chain_spec.to_consensus().unwrap())
+    .build();
+    let number = if matches.is_present("number") {
+        value_t!(matches.value_of("number"), BlockNumber).unwrap_or_else(|e| e.exit())
+    } else {
+        shared.chain_state().read().tip_number()
+    };
+
+    create_snapshot(&shared, number, &PathBuf::from(target))
+        .unwrap_or_else(|e| panic!("Snapshot error {:?} ", e));
+    info!(target: "main", "Snapshot at block {} written", number);
+}
+
+pub fn restore(setup: &Setup, matches: &ArgMatches) {
+    let source = value_t!(matches.value_of("source"), String).unwrap_or_else(|e| e.exit());
+
+    let _lock = setup.lock_data_dir();
+    let db_path = setup.dirs.db();
+
+    let store = ChainKVStore::new(RocksDB::open(&db_path, COLUMNS));
+    let number = restore_snapshot(
+        &store,
+        &setup.chain_spec.to_consensus().unwrap(),
+        &PathBuf::from(source),
+    )
+    .unwrap_or_else(|e| panic!("Restore error {:?} ", e));
+    info!(target: "main", "Restored snapshot at block {}", number);
+}
+
+pub fn restore_backup(setup: &Setup, matches: &ArgMatches) {
+    let source = value_t!(matches.value_of("source"), String).unwrap_or_else(|e| e.exit());
+    let source = PathBuf::from(source);
+
+    // A backup is laid out as a data directory, restoring it is backing it up
+    // to the data directory of the node. It is only read, a wrong path is not
+    // made an empty db.
+    let _lock = setup.lock_data_dir();
+    let db_path = source.join("db");
+    if !db_path.is_dir() {
+        panic!("Restore error: no backup db at {}", db_path.display());
+    }
+    let db = RocksDB::open_read_only(&db_path, COLUMNS, &setup.configs.db);
+    let mut store = ChainKVStore::new(db);
+    let ancient_path = source.join("ancient");
+    if ancient_path.exists() {
+        let ancient = AncientStore::open_read_only(&ancient_path).expect("ancient store open");
+        store = store.with_ancient(ancient);
+    }
+    store
+        .backup(setup.dirs.base())
+        .unwrap_or_else(|e| panic!("Restore error {:?} ", e));
+    info!(target: "main", "Restored backup {}", source.display());
+}
@@ -1,8 +1,25 @@
+use build_info::{get_version, Version};
 use ckb_util::{Condvar, Mutex};
 use ctrlc;
+use dir::DataDir;
+use log::error;
+use std::panic;
 use std::path::PathBuf;
+use std::process;
 use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
 
+// The flush may wait for the panicking thread, it is not waited for longer
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The version of the node with the details of its build, which the other
+/// crates only know when given
+pub fn version() -> Version {
+    get_version!()
+}
+
+/// Blocks until the process receives SIGINT or SIGTERM
 pub fn wait_for_exit() {
     let exit = Arc::new((Mutex::new(()), Condvar::new()));
 
@@ -17,6 +34,76 @@ pub fn wait_for_exit() {
     exit.1.wait(&mut l);
 }
 
+/// Marks the data directory crashed and flushes what it can with `flush` when
+/// one of the `critical` threads panics, then aborts: the node must not go on
+/// without them. The other threads, such as the ones handling the messages of
+/// the peers, die alone. `flush` runs in its own thread, the node aborts
+/// after `FLUSH_TIMEOUT` when it has not returned.
+pub fn handle_panic<F>(dirs: DataDir, critical: &'static [&'static str], flush: F)
+where
+    F: Fn() + Send + Sync + 'static,
+{
+    let flush = Arc::new(flush);
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        default_hook(info);
+        let current = thread::current();
+        let name = current.name().unwrap_or("unnamed");
+        error!(target: "main", "thread {} {}", name, info);
+        if !critical.contains(&name) {
+            return;
+        }
+        if let Err(err) = dirs.mark_crashed(&info.to_string()) {
+            error!(target: "main", "mark the data directory crashed failed: {}", err);
+        }
+
+        let (done_sender, done_receiver) = crossbeam_channel::bounded(1);
+        let flush = Arc::clone(&flush);
+        let flushing = thread::Builder::new()
+            .name("flush".to_string())
+            .spawn(move || {
+                flush();
+                let _ = done_sender.send(());
+            });
+        match flushing {
+            Ok(_) => {
+                if done_receiver.recv_timeout(FLUSH_TIMEOUT).is_err() {
+                    error!(target: "main", "the flush did not finish, aborting anyway");
+                }
+            }
+            Err(err) => error!(target: "main", "start the flush thread failed: {}", err),
+        }
+        logger::flush();
+        process::abort();
+    }));
+}
+
+/// Calls `reload` from a thread whenever the process receives SIGHUP
+#[cfg(unix)]
+pub fn handle_reload<F>(reload: F)
+where
+    F: Fn() + Send + 'static,
+{
+    use signal_hook::iterator::Signals;
+
+    let signals = Signals::new(&[signal_hook::SIGHUP]).expect("register SIGHUP");
+    thread::Builder::new()
+        .name("reload".to_string())
+        .spawn(move || {
+            for _ in signals.forever() {
+                reload();
+            }
+        })
+        .expect("Start reload thread failed");
+}
+
+#[cfg(not(unix))]
+pub fn handle_reload<F>(_reload: F)
+where
+    F: Fn() + Send + 'static,
+{
+}
+
 pub fn require_path_exists(path: PathBuf) -> Option<PathBuf> {
     if path.exists() {
         Some(path)
@@ -2,9 +2,16 @@ mod cli;
 mod helper;
 mod setup;
 
+use crate::helper::to_absolute_path;
 use crate::setup::{get_config_path, Setup};
 use clap::ArgMatches;
 use log::info;
+use std::path::PathBuf;
+
+// Counts the allocations only during a heap profile
+#[cfg(feature = "heap-profile")]
+#[global_allocator]
+static ALLOCATOR: ckb_profiler::CountingAllocator = ckb_profiler::CountingAllocator;
 
 fn main() {
     // Always print backtrace on panic.
@@ -18,12 +25,34 @@ fn main() {
             ("keygen", _) => cli::keygen(),
             _ => unreachable!(),
         },
+        ("init", Some(init_matches)) => cli::init(&init_matches),
+        ("peer_id", Some(peer_id_matches)) => cli::peer_id(&setup(&peer_id_matches)),
         ("run", Some(run_matches)) => {
-            cli::run(setup(&run_matches));
+            if run_matches.is_present("dev") {
+                cli::run(dev_setup(&run_matches));
+            } else {
+                cli::run(setup(&run_matches));
+            }
         }
         ("miner", Some(miner_matches)) => cli::miner(&miner_matches),
         ("export", Some(export_matches)) => cli::export(&setup(&export_matches), export_matches),
         ("import", Some(import_matches)) => cli::import(&setup(&import_matches), import_matches),
+        ("snapshot", Some(snapshot_matches)) => {
+            cli::snapshot(&setup(&snapshot_matches), snapshot_matches)
+        }
+        ("restore", Some(restore_matches)) => {
+            cli::restore(&setup(&restore_matches), restore_matches)
+        }
+        ("restore_backup", Some(restore_matches)) => {
+            cli::restore_backup(&setup(&restore_matches), restore_matches)
+        }
+        ("check_db", Some(check_matches)) => cli::check_db(&setup(&check_matches), check_matches),
+        ("keystore", Some(keystore_matches)) => match keystore_matches.subcommand() {
+            (command, Some(command_matches)) => {
+                cli::keystore(&setup(&command_matches), command, command_matches)
+            }
+            _ => unreachable!(),
+        },
         _ => unreachable!(),
     }
 
@@ -39,7 +68,7 @@ fn setup(matches: &ArgMatches<'static>) -> Setup {
         }
         Err(e) => {
             eprintln!(
-                "Failed to setup with config {}, cause err: {:?}",
+                "Failed to setup with config {}: {}",
                 config_path.display(),
                 e
             );
@@ -49,3 +78,21 @@ fn setup(matches: &ArgMatches<'static>) -> Setup {
     info!(target: "main", "Setup with config {}", config_path.display());
     setup
 }
+
+// Without a config file, no default one is searched
+fn dev_setup(matches: &ArgMatches<'static>) -> Setup {
+    let config_path = matches
+        .value_of("config")
+        .map(|path| to_absolute_path(PathBuf::from(path)));
+    match Setup::dev(config_path.as_ref().map(PathBuf::as_path)) {
+        Ok(setup) => {
+            logger::init(setup.configs.logger.clone()).expect("Init Logger");
+            info!(target: "main", "Setup a development chain in {}", setup.dirs.base().display());
+            setup
+        }
+        Err(e) => {
+            eprintln!("Failed to setup the development chain: {}", e);
+            ::std::process::exit(1);
+        }
+    }
+}
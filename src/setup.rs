@@ -1,42 +1,79 @@
 use crate::helper::{require_path_exists, to_absolute_path};
 use ckb_chain_spec::ChainSpec;
+use ckb_core::BlockNumber;
+use ckb_db::config::{DBBackend, DBConfig};
+use ckb_metrics::Config as MetricsConfig;
 use ckb_miner::BlockAssemblerConfig;
 use ckb_network::Config as NetworkConfig;
+use ckb_notify::Config as NotifyConfig;
 use ckb_pool::txs_pool::PoolConfig;
-use ckb_rpc::Config as RpcConfig;
+use ckb_rpc::{Config as RpcConfig, Module as RpcModule};
 use ckb_sync::Config as SyncConfig;
 use clap::ArgMatches;
-use config_tool::{Config as ConfigTool, File};
-use dir::Directories;
+use config_tool::{Config as ConfigTool, File, FileFormat};
+use dir::{DataDir, DataDirLock};
 use logger::Config as LogConfig;
 use serde_derive::Deserialize;
+use std::env;
 use std::error::Error;
+use std::net::SocketAddr;
 use std::path::{Path, PathBuf};
 
-const DEFAULT_CONFIG_PATHS: &[&str] = &["ckb.json", "nodes/default.json"];
+// TOML or JSON, picked by the extension
+const DEFAULT_CONFIG_PATHS: &[&str] = &[
+    "ckb.toml",
+    "ckb.json",
+    "nodes/default.toml",
+    "nodes/default.json",
+];
+
+// The configs of `run --dev` without a config file
+const DEV_CONFIG_TEMPLATE: &str = include_str!("../nodes_template/default.toml");
+const DEV_DATA_DIR: &str = "dev";
+// Lets a dapp chain many transactions before committing them
+const DEV_MAX_CHAINED_TXS: usize = 1000;
 
 #[derive(Clone, Debug)]
 pub struct Setup {
     pub configs: Configs,
     pub chain_spec: ChainSpec,
-    pub dirs: Directories,
+    pub dirs: DataDir,
+    /// The file the configs are read from, to read them again on reload
+    pub config_path: Option<PathBuf>,
 }
 
 #[derive(Clone, Debug, Deserialize)]
 pub struct ChainConfig {
     pub spec: PathBuf,
+    /// Keep only the bodies of the latest `prune_depth` blocks, unset to keep all
+    #[serde(default)]
+    pub prune_depth: Option<BlockNumber>,
+    /// Move the bodies of blocks older than `ancient_depth` to the append-only
+    /// ancient store, unset to keep all in the db. Refused along with
+    /// `prune_depth`, and with the `Memory` backend whose chain the on-disk
+    /// store would outlive.
+    #[serde(default)]
+    pub ancient_depth: Option<BlockNumber>,
 }
 
 #[derive(Clone, Debug, Deserialize)]
 pub struct Configs {
     pub data_dir: PathBuf,
     pub chain: ChainConfig,
+    #[serde(default)]
+    pub db: DBConfig,
+    #[serde(default)]
     pub logger: LogConfig,
     pub network: NetworkConfig,
     pub rpc: RpcConfig,
     pub block_assembler: BlockAssemblerConfig,
     pub sync: SyncConfig,
+    #[serde(default)]
     pub pool: PoolConfig,
+    #[serde(default)]
+    pub notify: NotifyConfig,
+    #[serde(default)]
+    pub metrics: MetricsConfig,
 }
 
 pub fn get_config_path(matches: &ArgMatches) -> PathBuf {
@@ -62,24 +99,40 @@ fn find_default_config_path() -> Option<PathBuf> {
 
 impl Setup {
     pub(crate) fn with_configs(mut configs: Configs) -> Result<Self, Box<Error>> {
-        let dirs = Directories::new(&configs.data_dir);
+        configs.validate()?;
+        let dirs = DataDir::new(&configs.data_dir);
+        dirs.create()?;
 
         if let Some(file) = configs.logger.file {
-            let path = dirs.join("logs");
-            configs.logger.file = Some(path.join(file));
+            configs.logger.file = Some(dirs.logs().join(file));
+        }
+
+        if let Some(socket) = configs.notify.publisher_socket.take() {
+            configs.notify.publisher_socket = Some(configs.data_dir.join(socket));
         }
 
+        configs.rpc.backup_dir = Some(dirs.backups());
+
         if configs.network.config_dir_path.is_none() {
-            configs.network.config_dir_path =
-                Some(dirs.join("network").to_string_lossy().to_string());
+            configs.network.config_dir_path = Some(dirs.network().to_string_lossy().to_string());
         }
 
-        let chain_spec = ChainSpec::read_from_file(&configs.chain.spec)?;
+        let chain_spec = ChainSpec::load(&configs.chain.spec)?;
 
         Ok(Setup {
             configs,
             chain_spec,
             dirs,
+            config_path: None,
+        })
+    }
+
+    /// Locks the data directory for the commands writing to it, exits when
+    /// another process uses it
+    pub fn lock_data_dir(&self) -> DataDirLock {
+        self.dirs.lock().unwrap_or_else(|err| {
+            eprintln!("Failed to lock the data directory: {}", err);
+            ::std::process::exit(1);
         })
     }
 
@@ -91,16 +144,139 @@ impl Setup {
         let mut configs: Configs = config_tool.try_into()?;
         configs.resolve_paths(config_path.as_ref().parent().unwrap());
 
-        Self::with_configs(configs)
+        let mut setup = Self::with_configs(configs)?;
+        setup.config_path = Some(config_path.as_ref().to_path_buf());
+        Ok(setup)
+    }
+
+    /// A local development chain, see [Configs::apply_dev]. The configs are
+    /// read from `config_path` when given, otherwise they are the default
+    /// template with the data in `dev/` of the working directory.
+    pub fn dev(config_path: Option<&Path>) -> Result<Self, Box<Error>> {
+        let mut config_tool = ConfigTool::new();
+        let base = match config_path {
+            Some(path) => {
+                config_tool.merge(File::from(path))?;
+                path.parent().unwrap().to_path_buf()
+            }
+            None => {
+                config_tool.merge(File::from_str(DEV_CONFIG_TEMPLATE, FileFormat::Toml))?;
+                env::current_dir()?
+            }
+        };
+
+        let mut configs: Configs = config_tool.try_into()?;
+        if config_path.is_none() {
+            configs.data_dir = PathBuf::from(DEV_DATA_DIR);
+        }
+        configs.apply_dev();
+        configs.resolve_paths(&base);
+
+        let mut setup = Self::with_configs(configs)?;
+        setup.config_path = config_path.map(Path::to_path_buf);
+        Ok(setup)
     }
 }
 
 impl Configs {
+    /// Runs the `instant` chain spec, sealing a block as soon as a
+    /// transaction enters the pool, which admits the transactions paying no
+    /// fee and long chains of unconfirmed ones. The Dev rpc module is served,
+    /// on localhost only, and the node dials no bootnode.
+    pub fn apply_dev(&mut self) {
+        self.chain.spec = PathBuf::from("instant");
+        self.block_assembler.instant_seal = true;
+        self.pool.min_fee_rate = 0;
+        self.pool.max_ancestors_count = DEV_MAX_CHAINED_TXS;
+        self.pool.max_descendants_count = DEV_MAX_CHAINED_TXS;
+        if !self.rpc.modules.contains(&RpcModule::Dev) {
+            self.rpc.modules.push(RpcModule::Dev);
+        }
+        if let Ok(address) = self.rpc.listen_address.parse::<SocketAddr>() {
+            let localhost = SocketAddr::from(([127, 0, 0, 1], address.port()));
+            self.rpc.listen_address = localhost.to_string();
+        }
+        self.network.bootnodes.clear();
+    }
+
+    /// Checks the values the sections can not check when deserialized,
+    /// telling every invalid key
+    fn validate(&self) -> Result<(), String> {
+        let mut errors = Vec::new();
+        let socket_addresses = Some(&self.rpc.listen_address)
+            .into_iter()
+            .map(|address| ("rpc.listen_address", address))
+            .chain(
+                self.rpc
+                    .ws_listen_address
+                    .iter()
+                    .map(|address| ("rpc.ws_listen_address", address)),
+            )
+            .chain(
+                self.metrics
+                    .listen_address
+                    .iter()
+                    .map(|address| ("metrics.listen_address", address)),
+            );
+        for (key, address) in socket_addresses {
+            if address.parse::<SocketAddr>().is_err() {
+                errors.push(format!("{} {:?} is not ip:port", key, address));
+            }
+        }
+        if self.chain.ancient_depth.is_some() {
+            if self.chain.prune_depth.is_some() {
+                errors.push(
+                    "chain.prune_depth and chain.ancient_depth exclude each other".to_owned(),
+                );
+            }
+            if self.db.backend == DBBackend::Memory {
+                errors.push("chain.ancient_depth needs the RocksDB db.backend".to_owned());
+            }
+        }
+        if self.rpc.modules.is_empty() {
+            errors.push("rpc.modules is empty".to_owned());
+        }
+        if let Some(ref auth) = self.rpc.auth {
+            if !auth.modules.is_empty()
+                && auth.bearer_token.is_none()
+                && auth.basic_auth.is_none()
+            {
+                errors.push("rpc.auth needs a bearer_token or a basic_auth".to_owned());
+            }
+        }
+        if let Some(ref rate_limit) = self.rpc.rate_limit {
+            if rate_limit.requests_per_second == 0 {
+                errors.push("rpc.rate_limit.requests_per_second must be above 0".to_owned());
+            }
+            // Without them the clients can not be told apart
+            if self.rpc.trusted_proxies.as_ref().map_or(true, Vec::is_empty) {
+                errors.push("rpc.rate_limit needs rpc.trusted_proxies".to_owned());
+            }
+        }
+        if self.network.max_peers == 0 {
+            errors.push("network.max_peers must be above 0".to_owned());
+        }
+        if self.network.outbound_peers_ratio == Some(0) {
+            errors.push("network.outbound_peers_ratio must be above 0".to_owned());
+        }
+        if self.pool.max_pool_size == 0 {
+            errors.push("pool.max_pool_size must be above 0".to_owned());
+        }
+        if self.sync.orphan_block_limit == 0 {
+            errors.push("sync.orphan_block_limit must be above 0".to_owned());
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(format!("invalid config: {}", errors.join(", ")))
+        }
+    }
+
     fn resolve_paths(&mut self, base: &Path) {
         if self.data_dir.is_relative() {
             self.data_dir = base.join(&self.data_dir);
         }
-        if self.chain.spec.is_relative() {
+        if self.chain.spec.is_relative() && !ChainSpec::is_preset(&self.chain.spec) {
             self.chain.spec = base.join(&self.chain.spec);
         }
     }
@@ -146,11 +322,7 @@ pub mod test {
                 "version": 0,
                 "parent_hash": "0x0000000000000000000000000000000000000000000000000000000000000000",
                 "timestamp": 0,
-                "txs_commit": "0x0000000000000000000000000000000000000000000000000000000000000000",
-                "txs_proposal": "0x0000000000000000000000000000000000000000000000000000000000000000",
-                "difficulty": "0x233",
-                "cellbase_id": "0x0000000000000000000000000000000000000000000000000000000000000000",
-                "uncles_hash": "0x0000000000000000000000000000000000000000000000000000000000000000"
+                "difficulty": "0x233"
             },
             "params": {
                 "initial_block_reward": 233,
@@ -218,4 +390,92 @@ pub mod test {
         assert!(setup.is_ok());
         assert_eq!(setup.unwrap().chain_spec.name, "ckb_test_custom");
     }
+
+    #[test]
+    fn test_toml_template() {
+        let template_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("nodes_template");
+        let from_json = Setup::setup(template_dir.join("default.json")).unwrap();
+        let from_toml = Setup::setup(template_dir.join("default.toml")).unwrap();
+        assert_eq!(
+            format!("{:?}", from_toml.configs),
+            format!("{:?}", from_json.configs)
+        );
+    }
+
+    #[test]
+    fn test_dev_setup() {
+        let tmp_dir = tempfile::Builder::new()
+            .prefix("test_dev_setup")
+            .tempdir()
+            .unwrap();
+        let config_path = tmp_dir.path().join("ckb.toml");
+        write_file(&config_path, DEV_CONFIG_TEMPLATE);
+
+        let setup = Setup::dev(Some(&config_path)).unwrap();
+        assert_eq!(setup.chain_spec.name, "ckb_instant");
+        assert!(setup.configs.block_assembler.instant_seal);
+        assert!(setup.configs.rpc.modules.contains(&RpcModule::Dev));
+        assert_eq!(setup.configs.rpc.listen_address, "127.0.0.1:8114");
+        assert_eq!(setup.configs.data_dir, tmp_dir.path().join("default"));
+    }
+
+    #[test]
+    fn test_invalid_config() {
+        let tmp_dir = tempfile::Builder::new()
+            .prefix("test_invalid_config")
+            .tempdir()
+            .unwrap();
+
+        let test_conifg = r#"{
+            "rpc": {
+                "listen_address": "0.0.0.0",
+                "auth": {
+                    "modules": ["Admin"]
+                },
+                "rate_limit": {
+                    "requests_per_second": 5,
+                    "burst": 10
+                }
+            },
+            "pool": {
+                "max_pool_size": 0
+            }
+        }"#;
+        let config_path = tmp_dir.path().join("config.json");
+        write_file(&config_path, test_conifg);
+        let err = override_default_config_file(&config_path).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid config: rpc.listen_address \"0.0.0.0\" is not ip:port, \
+             rpc.auth needs a bearer_token or a basic_auth, \
+             rpc.rate_limit needs rpc.trusted_proxies, \
+             pool.max_pool_size must be above 0"
+        );
+    }
+
+    #[test]
+    fn test_invalid_ancient_config() {
+        let tmp_dir = tempfile::Builder::new()
+            .prefix("test_invalid_ancient_config")
+            .tempdir()
+            .unwrap();
+
+        let test_conifg = r#"{
+            "chain": {
+                "prune_depth": 1000,
+                "ancient_depth": 1000
+            },
+            "db": {
+                "backend": "Memory"
+            }
+        }"#;
+        let config_path = tmp_dir.path().join("config.json");
+        write_file(&config_path, test_conifg);
+        let err = override_default_config_file(&config_path).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid config: chain.prune_depth and chain.ancient_depth exclude each other, \
+             chain.ancient_depth needs the RocksDB db.backend"
+        );
+    }
 }
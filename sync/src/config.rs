@@ -1,14 +1,42 @@
 use serde_derive::Deserialize;
+use std::path::PathBuf;
+
+// 2 hours
+const TOLERANT_TIME_OFFSET: u64 = 2 * 60 * 60 * 1000;
+const MAX_PEER_TIME_OFFSET: u64 = 2 * 60 * 60 * 1000;
 
 #[derive(Clone, Debug, Deserialize)]
 pub struct Config {
     pub orphan_block_limit: usize,
+    /// Milliseconds the local clock may be off the network median before
+    /// warning the user
+    #[serde(default = "default_tolerant_time_offset")]
+    pub tolerant_time_offset: u64,
+    /// Peers whose clock is off the local one by more than this many
+    /// milliseconds are banned, their timestamps can not be trusted
+    #[serde(default = "default_max_peer_time_offset")]
+    pub max_peer_time_offset: u64,
+    /// Read the clock from this faketime file instead of the system clock,
+    /// for test networks. Ignored by builds with faketime disabled.
+    #[serde(default)]
+    pub faketime_file: Option<PathBuf>,
+}
+
+fn default_tolerant_time_offset() -> u64 {
+    TOLERANT_TIME_OFFSET
+}
+
+fn default_max_peer_time_offset() -> u64 {
+    MAX_PEER_TIME_OFFSET
 }
 
 impl Config {
     pub fn default() -> Self {
         Config {
             orphan_block_limit: 1024,
+            tolerant_time_offset: TOLERANT_TIME_OFFSET,
+            max_peer_time_offset: MAX_PEER_TIME_OFFSET,
+            faketime_file: None,
         }
     }
 }
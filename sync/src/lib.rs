@@ -48,3 +48,6 @@ pub const EVICTION_HEADERS_RESPONSE_TIME: u64 = 120 * 1000; // 2 minutes
 pub const MAX_LOCATOR_SIZE: usize = 101;
 
 pub const BLOCK_DOWNLOAD_TIMEOUT: u64 = 30 * 1000; // 30s
+
+// Interval to re-announce the transactions submitted through this node
+pub const TX_REBROADCAST_INTERVAL: u64 = 10 * 60 * 1000; // 10 minutes
@@ -1,13 +1,16 @@
+use crate::Config;
 use ckb_network::{CKBProtocolContext, CKBProtocolHandler, PeerIndex};
 use ckb_protocol::TimeMessage;
 use ckb_util::RwLock;
 use flatbuffers::{get_root, FlatBufferBuilder};
 use log::{debug, warn};
 use std::collections::VecDeque;
+use std::time::Duration;
 
 const TOLERANT_OFFSET: u64 = 7_200_000;
 const MIN_SAMPLES: usize = 5;
 const MAX_SAMPLES: usize = 11;
+const PEER_TIME_SKEW_BAN_SECS: u64 = 60 * 60;
 
 /// Collect and check time offset samples
 pub struct NetTimeChecker {
@@ -69,22 +72,43 @@ impl Default for NetTimeChecker {
     }
 }
 
-/// Collect time offset samples from network peers and send notify to user if offset is too large
-pub struct NetTimeProtocol(RwLock<NetTimeChecker>);
+/// Collect time offset samples from network peers and send notify to user if offset is too large.
+/// Peers whose offset exceeds `max_peer_offset` are banned instead of sampled.
+pub struct NetTimeProtocol {
+    checker: RwLock<NetTimeChecker>,
+    max_peer_offset: u64,
+}
 
 impl NetTimeProtocol {
-    pub fn new(min_samples: usize, max_samples: usize, tolerant_offset: u64) -> Self {
-        NetTimeProtocol(RwLock::new(NetTimeChecker::new(
-            min_samples,
-            max_samples,
-            tolerant_offset,
-        )))
+    pub fn new(
+        min_samples: usize,
+        max_samples: usize,
+        tolerant_offset: u64,
+        max_peer_offset: u64,
+    ) -> Self {
+        NetTimeProtocol {
+            checker: RwLock::new(NetTimeChecker::new(
+                min_samples,
+                max_samples,
+                tolerant_offset,
+            )),
+            max_peer_offset,
+        }
+    }
+
+    pub fn from_config(config: &Config) -> Self {
+        NetTimeProtocol::new(
+            MIN_SAMPLES,
+            MAX_SAMPLES,
+            config.tolerant_time_offset,
+            config.max_peer_time_offset,
+        )
     }
 }
 
 impl Default for NetTimeProtocol {
     fn default() -> Self {
-        NetTimeProtocol(RwLock::new(NetTimeChecker::default()))
+        NetTimeProtocol::from_config(&Config::default())
     }
 }
 
@@ -93,7 +117,7 @@ impl CKBProtocolHandler for NetTimeProtocol {
     fn received(&self, nc: Box<CKBProtocolContext>, peer: PeerIndex, data: &[u8]) {
         // collect time sample from outbound peer
         if nc.session_info(peer).map(|s| s.peer.is_outbound()) == Some(true) {
-            let now: u64 = faketime::unix_time_as_millis();
+            let now: u64 = ckb_time::unix_time_as_millis();
             let timestamp: u64 = match get_root::<TimeMessage>(&data)
                 .payload()
                 .map(|time| time.timestamp())
@@ -102,7 +126,12 @@ impl CKBProtocolHandler for NetTimeProtocol {
                 None => return,
             };
             let offset: i64 = (i128::from(now) - i128::from(timestamp)) as i64;
-            let mut net_time_checker = self.0.write();
+            if offset.abs() as u64 > self.max_peer_offset {
+                warn!(target: "network", "ban peer {} for {}ms time offset", peer, offset);
+                nc.ban_peer(peer, Duration::from_secs(PEER_TIME_SKEW_BAN_SECS));
+                return;
+            }
+            let mut net_time_checker = self.checker.write();
             debug!(target: "network", "new net time offset sample {}ms", offset);
             net_time_checker.add_sample(offset);
             if let Err(offset) = net_time_checker.check() {
@@ -114,7 +143,7 @@ impl CKBProtocolHandler for NetTimeProtocol {
     fn connected(&self, nc: Box<CKBProtocolContext>, peer: PeerIndex) {
         // send local time to inbound peers
         if nc.session_info(peer).map(|s| s.peer.is_inbound()) == Some(true) {
-            let now = faketime::unix_time_as_millis();
+            let now = ckb_time::unix_time_as_millis();
             let fbb = &mut FlatBufferBuilder::new();
             let message = TimeMessage::build_time(fbb, now);
             fbb.finish(message, None);
@@ -127,6 +156,62 @@ impl CKBProtocolHandler for NetTimeProtocol {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::TIME_PROTOCOL_ID;
+    use ckb_network::test_utils::MockProtocolContext;
+    use ckb_network::{random_peer_id, Endpoint, PeerInfo, SessionInfo, ToMultiaddr};
+    use ckb_time::Clock;
+
+    fn time_message(timestamp: u64) -> Vec<u8> {
+        let fbb = &mut FlatBufferBuilder::new();
+        let message = TimeMessage::build_time(fbb, timestamp);
+        fbb.finish(message, None);
+        fbb.finished_data().to_vec()
+    }
+
+    #[test]
+    fn test_ban_skewed_peer() {
+        let _clock = Clock::new(1_000_000_000).enter();
+        let protocol = NetTimeProtocol::new(MIN_SAMPLES, MAX_SAMPLES, TOLERANT_OFFSET, 1_000);
+        let nc = MockProtocolContext::new(TIME_PROTOCOL_ID);
+        nc.connect(0);
+        nc.connect(1);
+
+        protocol.received(nc.boxed(), 0, &time_message(1_000_000_000 - 1_001));
+        protocol.received(nc.boxed(), 1, &time_message(1_000_000_000 - 1_000));
+        assert_eq!(
+            nc.banned(),
+            vec![(0, Duration::from_secs(PEER_TIME_SKEW_BAN_SECS))]
+        );
+        assert_eq!(nc.connected_peers(), vec![1]);
+        assert!(nc.disconnected().is_empty());
+        assert_eq!(protocol.checker.read().samples, vec![1_000]);
+    }
+
+    #[test]
+    fn test_send_time_to_inbound_peer() {
+        let _clock = Clock::new(1_000_000_000).enter();
+        let protocol = NetTimeProtocol::default();
+        let nc = MockProtocolContext::new(TIME_PROTOCOL_ID);
+        nc.connect(0);
+        nc.connect_with(
+            1,
+            SessionInfo {
+                peer: PeerInfo {
+                    peer_id: random_peer_id().unwrap(),
+                    endpoint_role: Endpoint::Listener,
+                    last_ping_time: None,
+                    connected_addr: "/ip4/127.0.0.1".to_multiaddr().unwrap(),
+                    identify_info: None,
+                },
+                protocol_version: None,
+            },
+        );
+
+        protocol.connected(nc.boxed(), 0);
+        protocol.connected(nc.boxed(), 1);
+        assert!(nc.sent_to(0).is_empty());
+        assert_eq!(nc.sent_to(1), vec![time_message(1_000_000_000)]);
+    }
 
     #[test]
     fn test_samples_collect() {
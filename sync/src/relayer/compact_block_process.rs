@@ -8,6 +8,7 @@ use ckb_shared::shared::ChainProvider;
 use ckb_util::RwLockUpgradableReadGuard;
 use ckb_verification::{HeaderResolverWrapper, HeaderVerifier, Verifier};
 use flatbuffers::FlatBufferBuilder;
+use log::debug;
 use numext_fixed_hash::H256;
 use std::sync::Arc;
 
@@ -50,35 +51,50 @@ where
                     relayer: self.relayer,
                 },
                 Arc::clone(&self.relayer.shared.consensus().pow_engine()),
+                self.relayer.shared.consensus().max_block_time_drift(),
             );
 
-            if header_verifier.verify(&resolver).is_ok() {
-                self.relayer
-                    .request_proposal_txs(self.nc, self.peer, &compact_block);
+            match header_verifier.verify(&resolver) {
+                Ok(_) => {
+                    self.relayer
+                        .request_proposal_txs(self.nc, self.peer, &compact_block);
 
-                match self.relayer.reconstruct_block(&compact_block, Vec::new()) {
-                    (Some(block), _) => {
-                        self.relayer
-                            .accept_block(self.nc, self.peer, &Arc::new(block))
-                    }
-                    (None, missing_indexes) => {
-                        {
-                            let mut write_guard =
-                                RwLockUpgradableReadGuard::upgrade(pending_compact_blocks);
-                            write_guard.insert(block_hash.clone(), compact_block.clone());
+                    match self.relayer.reconstruct_block(&compact_block, Vec::new()) {
+                        (Some(block), _) => {
+                            self.relayer
+                                .accept_block(self.nc, self.peer, &Arc::new(block))
                         }
+                        (None, missing_indexes) => {
+                            {
+                                let mut write_guard =
+                                    RwLockUpgradableReadGuard::upgrade(pending_compact_blocks);
+                                write_guard.insert(block_hash.clone(), compact_block.clone());
+                            }
 
-                        let fbb = &mut FlatBufferBuilder::new();
-                        let message = RelayMessage::build_get_block_transactions(
-                            fbb,
-                            &block_hash,
-                            &missing_indexes
-                                .into_iter()
-                                .map(|i| i as u32)
-                                .collect::<Vec<_>>(),
-                        );
-                        fbb.finish(message, None);
-                        let _ = self.nc.send(self.peer, fbb.finished_data().to_vec());
+                            let fbb = &mut FlatBufferBuilder::new();
+                            let message = RelayMessage::build_get_block_transactions(
+                                fbb,
+                                &block_hash,
+                                &missing_indexes
+                                    .into_iter()
+                                    .map(|i| i as u32)
+                                    .collect::<Vec<_>>(),
+                            );
+                            fbb.finish(message, None);
+                            let _ = self.nc.send(self.peer, fbb.finished_data().to_vec());
+                        }
+                    }
+                }
+                Err(error) => {
+                    debug!(
+                        target: "relay",
+                        "compact block header {:#x} verify error {}",
+                        block_hash,
+                        error
+                    );
+                    let misbehavior = error.misbehavior();
+                    if misbehavior > 0 {
+                        self.relayer.peers.misbehavior(self.peer, misbehavior);
                     }
                 }
             }
@@ -16,7 +16,9 @@ use self::get_block_proposal_process::GetBlockProposalProcess;
 use self::get_block_transactions_process::GetBlockTransactionsProcess;
 use self::transaction_process::TransactionProcess;
 use crate::types::Peers;
+use crate::TX_REBROADCAST_INTERVAL;
 use ckb_chain::chain::ChainController;
+use ckb_chain::error::ProcessBlockError;
 use ckb_core::block::{Block, BlockBuilder};
 use ckb_core::transaction::{ProposalShortId, Transaction};
 use ckb_network::{CKBProtocolContext, CKBProtocolHandler, PeerIndex, TimerToken};
@@ -34,6 +36,7 @@ use std::sync::Arc;
 use std::time::Duration;
 
 pub const TX_PROPOSAL_TOKEN: TimerToken = 0;
+pub const TX_REBROADCAST_TOKEN: TimerToken = 1;
 
 #[derive(Clone)]
 pub struct Relayer<CI: ChainIndex> {
@@ -148,6 +151,12 @@ where
             }
         } else {
             debug!(target: "relay", "accept_block verify error {:?}", ret);
+            if let Err(ProcessBlockError::Verification(error)) = ret {
+                let misbehavior = error.misbehavior();
+                if misbehavior > 0 {
+                    self.peers.misbehavior(peer, misbehavior);
+                }
+            }
         }
     }
 
@@ -239,6 +248,31 @@ where
         }
     }
 
+    fn rebroadcast_local_transactions(&self, nc: &CKBProtocolContext) {
+        let txs = self.tx_pool.get_local_transactions();
+        if txs.is_empty() {
+            return;
+        }
+
+        let peers = nc.connected_peers();
+        let transaction_filters = self.peers.transaction_filters.read();
+        for tx in txs {
+            debug!(target: "relay", "rebroadcast local transaction {:#x}", tx.hash());
+            let fbb = &mut FlatBufferBuilder::new();
+            let message = RelayMessage::build_transaction(fbb, &tx);
+            fbb.finish(message, None);
+
+            for peer in &peers {
+                if transaction_filters
+                    .get(peer)
+                    .map_or(true, |filter| filter.contains(&tx))
+                {
+                    let _ = nc.send(*peer, fbb.finished_data().to_vec());
+                }
+            }
+        }
+    }
+
     pub fn get_block(&self, hash: &H256) -> Option<Block> {
         self.shared.block(hash)
     }
@@ -254,11 +288,16 @@ where
 {
     fn initialize(&self, nc: Box<CKBProtocolContext>) {
         let _ = nc.register_timer(TX_PROPOSAL_TOKEN, Duration::from_millis(100));
+        let _ = nc.register_timer(
+            TX_REBROADCAST_TOKEN,
+            Duration::from_millis(TX_REBROADCAST_INTERVAL),
+        );
     }
 
     fn received(&self, nc: Box<CKBProtocolContext>, peer: PeerIndex, data: &[u8]) {
         // TODO use flatbuffers verifier
         let msg = get_root::<RelayMessage>(data);
+        let _context = logger::context(vec![("peer", peer.to_string())]);
         debug!(target: "relay", "msg {:?}", msg.payload_type());
         self.process(nc.as_ref(), peer, msg);
     }
@@ -276,6 +315,7 @@ where
     fn timer_triggered(&self, nc: Box<CKBProtocolContext>, token: TimerToken) {
         match token as usize {
             TX_PROPOSAL_TOKEN => self.prune_tx_proposal_request(nc.as_ref()),
+            TX_REBROADCAST_TOKEN => self.rebroadcast_local_transactions(nc.as_ref()),
             _ => unreachable!(),
         }
     }
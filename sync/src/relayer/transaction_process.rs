@@ -1,6 +1,7 @@
 use crate::relayer::Relayer;
 use ckb_core::transaction::Transaction;
 use ckb_network::{CKBProtocolContext, PeerIndex};
+use ckb_pool::txs_pool::types::InsertionResult;
 use ckb_protocol::{RelayMessage, Transaction as FbsTransaction};
 use ckb_shared::index::ChainIndex;
 use flatbuffers::FlatBufferBuilder;
@@ -32,23 +33,27 @@ where
 
     pub fn execute(self) {
         let tx: Transaction = (*self.message).into();
-        if self.relayer.tx_pool.add_transaction(tx.clone()).is_ok() {
-            let fbb = &mut FlatBufferBuilder::new();
-            let message = RelayMessage::build_transaction(fbb, &tx);
-            fbb.finish(message, None);
+        // The pending and orphan transactions are not verified yet, they are not relayed
+        match self.relayer.tx_pool.add_transaction(tx.clone()) {
+            Ok(InsertionResult::Normal) | Ok(InsertionResult::Proposed) => {}
+            _ => return,
+        }
+
+        let fbb = &mut FlatBufferBuilder::new();
+        let message = RelayMessage::build_transaction(fbb, &tx);
+        fbb.finish(message, None);
 
-            for peer_id in self.nc.connected_peers() {
-                if peer_id != self.peer
-                    && self
-                        .relayer
-                        .peers()
-                        .transaction_filters
-                        .read()
-                        .get(&peer_id)
-                        .map_or(true, |filter| filter.contains(&tx))
-                {
-                    let _ = self.nc.send(peer_id, fbb.finished_data().to_vec());
-                }
+        for peer_id in self.nc.connected_peers() {
+            if peer_id != self.peer
+                && self
+                    .relayer
+                    .peers()
+                    .transaction_filters
+                    .read()
+                    .get(&peer_id)
+                    .map_or(true, |filter| filter.contains(&tx))
+            {
+                let _ = self.nc.send(peer_id, fbb.finished_data().to_vec());
             }
         }
     }
@@ -8,8 +8,8 @@ use ckb_core::header::Header;
 use ckb_network::PeerIndex;
 use ckb_shared::index::ChainIndex;
 use ckb_shared::shared::ChainProvider;
+use ckb_time::unix_time_as_millis;
 use ckb_util::{try_option, RwLockUpgradableReadGuard};
-use faketime::unix_time_as_millis;
 use log::debug;
 use numext_fixed_hash::H256;
 use numext_fixed_uint::U256;
@@ -176,6 +176,18 @@ where
 
         debug_assert!(best_known_header.number() > fixed_last_common_header.number());
 
+        // The peer can't serve the bodies of the blocks it has pruned
+        let pruned_below = self.synchronizer.peers.pruned_below(self.peer);
+        if fixed_last_common_header.number() + 1 < pruned_below {
+            debug!(
+                target: "sync",
+                "[block downloader] peer={} pruned blocks below {}",
+                self.peer,
+                pruned_below
+            );
+            return None;
+        }
+
         let window_end = fixed_last_common_header.number() + BLOCK_DOWNLOAD_WINDOW;
         let max_height = cmp::min(window_end + 1, best_known_header.number());
 
@@ -62,7 +62,7 @@ mod tests {
     use ckb_chain_spec::consensus::Consensus;
     use ckb_core::block::BlockBuilder;
     use ckb_core::header::{Header, HeaderBuilder};
-    use faketime::unix_time_as_millis;
+    use ckb_time::unix_time_as_millis;
     use std::collections::HashSet;
     use std::iter::FromIterator;
 
@@ -65,7 +65,8 @@ where
                 debug!(target: "sync", "\nheaders len={}\n", headers.len());
 
                 let fbb = &mut FlatBufferBuilder::new();
-                let message = SyncMessage::build_headers(fbb, &headers);
+                let pruned_below = self.synchronizer.shared.store().get_pruned_below();
+                let message = SyncMessage::build_headers(fbb, &headers, pruned_below);
                 fbb.finish(message, None);
                 let _ = self.nc.send(self.peer, fbb.finished_data().to_vec());
             } else {
@@ -1,5 +1,6 @@
 use crate::synchronizer::{BlockStatus, Synchronizer};
 use crate::MAX_HEADERS_LEN;
+use ckb_chain_spec::versionbits::is_valid_version;
 use ckb_core::header::Header;
 use ckb_network::{CKBProtocolContext, PeerIndex};
 use ckb_protocol::{FlatbuffersVectorIterator, Headers};
@@ -197,9 +198,11 @@ where
     pub fn accept_first(&self, first: &Header) -> ValidationResult {
         let parent = self.synchronizer.get_header(&first.parent_hash());
         let resolver = VerifierResolver::new(parent.as_ref(), &first, &self.synchronizer);
+        let consensus = self.synchronizer.shared.consensus();
         let verifier = HeaderVerifier::new(
             resolver.clone(),
-            Arc::clone(&self.synchronizer.shared.consensus().pow_engine()),
+            Arc::clone(&consensus.pow_engine()),
+            consensus.max_block_time_drift(),
         );
         let acceptor =
             HeaderAcceptor::new(first, self.peer, &self.synchronizer, resolver, verifier);
@@ -209,6 +212,10 @@ where
     pub fn execute(self) {
         debug!(target: "sync", "HeadersProcess begin");
 
+        self.synchronizer
+            .peers
+            .set_pruned_below(self.peer, self.message.pruned_below());
+
         if self.is_oversize() {
             self.synchronizer.peers.misbehavior(self.peer, 20);
             debug!(target: "sync", "HeadersProcess is_oversize");
@@ -244,9 +251,11 @@ where
         for window in headers.windows(2) {
             if let [parent, header] = &window {
                 let resolver = VerifierResolver::new(Some(&parent), &header, &self.synchronizer);
+                let consensus = self.synchronizer.shared.consensus();
                 let verifier = HeaderVerifier::new(
                     resolver.clone(),
-                    Arc::clone(&self.synchronizer.shared.consensus().pow_engine()),
+                    Arc::clone(&consensus.pow_engine()),
+                    consensus.max_block_time_drift(),
                 );
                 let acceptor =
                     HeaderAcceptor::new(&header, self.peer, &self.synchronizer, resolver, verifier);
@@ -359,27 +368,16 @@ where
     }
 
     pub fn non_contextual_check(&self, state: &mut ValidationResult) -> Result<(), ()> {
-        self.verifier.verify(&self.resolver).map_err(|error| match error {
-            VerifyError::Pow(e) => {
-                debug!(target: "sync", "HeadersProcess accept {:?} pow", self.header.number());
-                state.dos(Some(ValidationError::Verify(VerifyError::Pow(e))), 100);
-            }
-            VerifyError::Difficulty(e) => {
-                debug!(target: "sync", "HeadersProcess accept {:?} difficulty", self.header.number());
-                state.dos(
-                    Some(ValidationError::Verify(VerifyError::Difficulty(e))),
-                    50,
-                );
-            }
-            error => {
-                debug!(target: "sync", "HeadersProcess accept {:?} {:?}", self.header.number(), error);
-                state.invalid(Some(ValidationError::Verify(error)));
-            }
+        self.verifier.verify(&self.resolver).map_err(|error| {
+            debug!(target: "sync", "HeadersProcess accept {:?} {}", self.header.number(), error);
+            let misbehavior = error.misbehavior();
+            state.dos(Some(ValidationError::Verify(error)), misbehavior);
         })
     }
 
     pub fn version_check(&self, state: &mut ValidationResult) -> Result<(), ()> {
-        if self.header.version() != 0 {
+        let block_version = self.synchronizer.consensus().block_version();
+        if !is_valid_version(self.header.version(), block_version) {
             state.invalid(Some(ValidationError::Version));
             Err(())
         } else {
@@ -26,12 +26,14 @@ use ckb_chain::error::ProcessBlockError;
 use ckb_chain_spec::consensus::Consensus;
 use ckb_core::block::Block;
 use ckb_core::header::{BlockNumber, Header};
+use ckb_metrics::registry;
 use ckb_network::{CKBProtocolContext, CKBProtocolHandler, PeerIndex, Severity, TimerToken};
+use ckb_notify::{Event, NotifyController};
 use ckb_protocol::{SyncMessage, SyncPayload};
 use ckb_shared::index::ChainIndex;
 use ckb_shared::shared::{ChainProvider, Shared};
+use ckb_time::unix_time_as_millis;
 use ckb_util::{try_option, RwLock, RwLockUpgradableReadGuard};
-use faketime::unix_time_as_millis;
 use flatbuffers::{get_root, FlatBufferBuilder};
 use log::{debug, info, warn};
 use numext_fixed_hash::H256;
@@ -80,6 +82,8 @@ pub struct Synchronizer<CI: ChainIndex> {
     pub config: Arc<Config>,
     pub orphan_block_pool: Arc<OrphanBlockPool>,
     pub outbound_peers_with_protect: Arc<AtomicUsize>,
+    /// Told of the peers connecting and disconnecting
+    notify: Option<NotifyController>,
 }
 
 // https://github.com/rust-lang/rust/issues/40754
@@ -96,6 +100,7 @@ impl<CI: ChainIndex> ::std::clone::Clone for Synchronizer<CI> {
             config: Arc::clone(&self.config),
             orphan_block_pool: Arc::clone(&self.orphan_block_pool),
             outbound_peers_with_protect: Arc::clone(&self.outbound_peers_with_protect),
+            notify: self.notify.clone(),
         }
     }
 }
@@ -121,7 +126,7 @@ impl<CI: ChainIndex> Synchronizer<CI> {
         let best_known_header = HeaderView::new(header, total_difficulty, total_uncles_count);
         let orphan_block_limit = config.orphan_block_limit;
 
-        Synchronizer {
+        let synchronizer = Synchronizer {
             config: Arc::new(config),
             chain,
             shared,
@@ -132,7 +137,43 @@ impl<CI: ChainIndex> Synchronizer<CI> {
             header_map: Arc::new(RwLock::new(HashMap::new())),
             n_sync: Arc::new(AtomicUsize::new(0)),
             outbound_peers_with_protect: Arc::new(AtomicUsize::new(0)),
-        }
+            notify: None,
+        };
+        synchronizer.register_metrics();
+        synchronizer
+    }
+
+    // The collector only holds weak references, it is unregistered once the
+    // synchronizer is dropped
+    fn register_metrics(&self) {
+        let orphan_block_pool = Arc::downgrade(&self.orphan_block_pool);
+        let peers = Arc::downgrade(&self.peers);
+        let best_known_header = Arc::downgrade(&self.best_known_header);
+        let registry = registry();
+        let orphan_blocks = registry.gauge("sync_orphan_blocks", Vec::new());
+        let peers_count = registry.gauge("sync_peers", Vec::new());
+        let best_known_number = registry.gauge("sync_best_known_header_number", Vec::new());
+        registry.on_collect(move || {
+            let (orphan_block_pool, peers, best_known_header) = match (
+                orphan_block_pool.upgrade(),
+                peers.upgrade(),
+                best_known_header.upgrade(),
+            ) {
+                (Some(orphan_block_pool), Some(peers), Some(best_known_header)) => {
+                    (orphan_block_pool, peers, best_known_header)
+                }
+                _ => return false,
+            };
+            orphan_blocks.set(orphan_block_pool.len());
+            peers_count.set(peers.state.read().len());
+            best_known_number.set(best_known_header.read().number() as usize);
+            true
+        });
+    }
+
+    pub fn with_notify(mut self, notify: NotifyController) -> Self {
+        self.notify = Some(notify);
+        self
     }
 
     fn process(&self, nc: &CKBProtocolContext, peer: PeerIndex, message: SyncMessage) {
@@ -425,7 +466,15 @@ impl<CI: ChainIndex> Synchronizer<CI> {
     }
 
     fn accept_block(&self, peer: PeerIndex, block: &Arc<Block>) -> Result<(), ProcessBlockError> {
-        self.chain.process_block(Arc::clone(&block))?;
+        if let Err(err) = self.chain.process_block(Arc::clone(&block)) {
+            if let ProcessBlockError::Verification(ref error) = err {
+                let misbehavior = error.misbehavior();
+                if misbehavior > 0 {
+                    self.peers.misbehavior(peer, misbehavior);
+                }
+            }
+            return Err(err);
+        }
         self.mark_block_stored(block.header().hash().clone());
         self.peers.set_last_common_header(peer, &block.header());
         Ok(())
@@ -688,6 +737,7 @@ where
     fn received(&self, nc: Box<CKBProtocolContext>, peer: PeerIndex, data: &[u8]) {
         // TODO use flatbuffers verifier
         let msg = get_root::<SyncMessage>(&data);
+        let _context = logger::context(vec![("peer", peer.to_string())]);
         debug!(target: "sync", "msg {:?}", msg.payload_type());
         self.process(nc.as_ref(), peer, msg);
     }
@@ -695,11 +745,17 @@ where
     fn connected(&self, nc: Box<CKBProtocolContext>, peer: PeerIndex) {
         debug!(target: "sync", "init_getheaders peer={:?} connected", peer);
         self.on_connected(nc.as_ref(), peer);
+        if let Some(ref notify) = self.notify {
+            notify.notify(Event::PeerConnected(peer));
+        }
     }
 
     fn disconnected(&self, _nc: Box<CKBProtocolContext>, peer: PeerIndex) {
         info!(target: "sync", "peer={} SyncProtocol.disconnected", peer);
         self.peers.disconnected(peer);
+        if let Some(ref notify) = self.notify {
+            notify.notify(Event::PeerDisconnected(peer));
+        }
     }
 
     fn timer_triggered(&self, nc: Box<CKBProtocolContext>, token: TimerToken) {
@@ -733,23 +789,17 @@ mod tests {
     use ckb_core::header::{Header, HeaderBuilder};
     use ckb_core::transaction::{CellInput, CellOutput, Transaction, TransactionBuilder};
     use ckb_db::memorydb::MemoryKeyValueDB;
-    use ckb_network::{
-        random_peer_id, CKBProtocolContext, Endpoint, Error as NetworkError, PeerIndex, PeerInfo,
-        ProtocolId, SessionInfo, Severity, TimerToken, ToMultiaddr,
-    };
+    use ckb_network::test_utils::MockProtocolContext;
+    use ckb_network::PeerIndex;
     use ckb_notify::{NotifyController, NotifyService};
     use ckb_protocol::{Block as FbsBlock, Headers as FbsHeaders};
     use ckb_shared::index::ChainIndex;
     use ckb_shared::shared::SharedBuilder;
     use ckb_shared::store::ChainKVStore;
-    use ckb_util::Mutex;
-    #[cfg(not(disable_faketime))]
-    use faketime;
+    use ckb_time::Clock;
     use flatbuffers::FlatBufferBuilder;
-    use fnv::{FnvHashMap, FnvHashSet};
+    use fnv::FnvHashSet;
     use numext_fixed_uint::U256;
-    use std::ops::Deref;
-    use std::time::Duration;
 
     fn start_chain(
         consensus: Option<Consensus>,
@@ -1043,23 +1093,21 @@ mod tests {
         }
     }
 
-    #[derive(Clone)]
-    struct DummyNetworkContext {
-        pub sessions: FnvHashMap<PeerIndex, SessionInfo>,
-        pub disconnected: Arc<Mutex<FnvHashSet<PeerIndex>>>,
+    fn mock_network_context(peer_num: usize) -> MockProtocolContext {
+        let network_context = MockProtocolContext::new(crate::SYNC_PROTOCOL_ID);
+        for peer in 0..peer_num {
+            network_context.connect(peer);
+        }
+        network_context
     }
 
-    fn mock_session_info() -> SessionInfo {
-        SessionInfo {
-            peer: PeerInfo {
-                peer_id: random_peer_id().unwrap(),
-                endpoint_role: Endpoint::Dialer,
-                last_ping_time: None,
-                connected_addr: "/ip4/127.0.0.1".to_multiaddr().expect("parse multiaddr"),
-                identify_info: None,
-            },
-            protocol_version: None,
-        }
+    // The peers evicted, which are reported
+    fn reported_peers(network_context: &MockProtocolContext) -> FnvHashSet<PeerIndex> {
+        network_context
+            .reported()
+            .into_iter()
+            .map(|(peer, _)| peer)
+            .collect()
     }
 
     fn mock_header_view(total_difficulty: u64) -> HeaderView {
@@ -1070,63 +1118,6 @@ mod tests {
         )
     }
 
-    impl CKBProtocolContext for DummyNetworkContext {
-        /// Send a packet over the network to another peer.
-        fn send(&self, _peer: PeerIndex, _data: Vec<u8>) -> Result<(), NetworkError> {
-            Ok(())
-        }
-
-        /// Send a packet over the network to another peer using specified protocol.
-        fn send_protocol(
-            &self,
-            _peer: PeerIndex,
-            _protocol: ProtocolId,
-            _data: Vec<u8>,
-        ) -> Result<(), NetworkError> {
-            Ok(())
-        }
-        /// Report peer. Depending on the report, peer may be disconnected and possibly banned.
-        fn report_peer(&self, peer: PeerIndex, _reason: Severity) {
-            self.disconnected.lock().insert(peer);
-        }
-
-        fn ban_peer(&self, _peer: PeerIndex, _duration: Duration) {}
-
-        /// Register a new IO timer. 'IoHandler::timeout' will be called with the token.
-        fn register_timer(&self, _token: TimerToken, _delay: Duration) -> Result<(), NetworkError> {
-            unimplemented!();
-        }
-
-        /// Returns information on p2p session
-        fn session_info(&self, peer: PeerIndex) -> Option<SessionInfo> {
-            self.sessions.get(&peer).cloned()
-        }
-        /// Returns max version for a given protocol.
-        fn protocol_version(&self, _peer: PeerIndex, _protocol: ProtocolId) -> Option<u8> {
-            unimplemented!();
-        }
-
-        fn disconnect(&self, _peer: PeerIndex) {}
-        fn protocol_id(&self) -> ProtocolId {
-            unimplemented!();
-        }
-
-        fn connected_peers(&self) -> Vec<PeerIndex> {
-            unimplemented!();
-        }
-    }
-
-    fn mock_network_context(peer_num: usize) -> DummyNetworkContext {
-        let mut sessions = FnvHashMap::default();
-        for peer in 0..peer_num {
-            sessions.insert(peer, mock_session_info());
-        }
-        DummyNetworkContext {
-            sessions,
-            disconnected: Arc::new(Mutex::new(FnvHashSet::default())),
-        }
-    }
-
     #[test]
     fn test_sync_process() {
         let _ = env_logger::try_init();
@@ -1181,7 +1172,7 @@ mod tests {
         );
 
         let fbb = &mut FlatBufferBuilder::new();
-        let fbs_headers = FbsHeaders::build(fbb, &headers);
+        let fbs_headers = FbsHeaders::build(fbb, &headers, 0);
         fbb.finish(fbs_headers, None);
         let fbs_headers = get_root::<FbsHeaders>(fbb.finished_data());
 
@@ -1233,19 +1224,18 @@ mod tests {
         assert!(new_tip_receiver.recv().is_ok());
     }
 
-    #[cfg(not(disable_faketime))]
     #[test]
     fn test_header_sync_timeout() {
         use std::iter::FromIterator;
-        let faketime_file = faketime::millis_tempfile(0).expect("create faketime file");
-        faketime::enable(&faketime_file);
+        let clock = Clock::new(0);
+        let _scope = clock.enter();
 
         let (chain_controller, shared, _notify) = start_chain(None, None);
 
         let synchronizer = gen_synchronizer(chain_controller.clone(), shared.clone());
 
         let network_context = mock_network_context(5);
-        faketime::write_millis(&faketime_file, MAX_TIP_AGE * 2).expect("write millis");
+        clock.set(MAX_TIP_AGE * 2);
         assert!(synchronizer.is_initial_block_download());
         let peers = synchronizer.peers();
         // protect should not effect headers_timeout
@@ -1253,19 +1243,17 @@ mod tests {
         peers.on_connected(1, 0, false);
         peers.on_connected(2, MAX_TIP_AGE * 2, false);
         synchronizer.eviction(&network_context);
-        let disconnected = network_context.disconnected.lock();
         assert_eq!(
-            disconnected.deref(),
-            &FnvHashSet::from_iter(vec![0, 1].into_iter())
+            reported_peers(&network_context),
+            FnvHashSet::from_iter(vec![0, 1].into_iter())
         )
     }
 
-    #[cfg(not(disable_faketime))]
     #[test]
     fn test_chain_sync_timeout() {
         use std::iter::FromIterator;
-        let faketime_file = faketime::millis_tempfile(0).expect("create faketime file");
-        faketime::enable(&faketime_file);
+        let clock = Clock::new(0);
+        let _scope = clock.enter();
 
         let consensus = Consensus::default();
         let header = HeaderBuilder::default()
@@ -1298,7 +1286,7 @@ mod tests {
         peers.new_header_received(5, &mock_header_view(3));
         synchronizer.eviction(&network_context);
         {
-            assert!({ network_context.disconnected.lock().is_empty() });
+            assert!(network_context.reported().is_empty());
             let peer_state = peers.state.read();
             assert_eq!(peer_state.get(&0).unwrap().chain_sync.protect, true);
             assert_eq!(peer_state.get(&1).unwrap().chain_sync.protect, true);
@@ -1343,14 +1331,14 @@ mod tests {
                 CHAIN_SYNC_TIMEOUT
             );
         }
-        faketime::write_millis(&faketime_file, CHAIN_SYNC_TIMEOUT + 1).expect("write millis");
+        clock.set(CHAIN_SYNC_TIMEOUT + 1);
         synchronizer.eviction(&network_context);
         {
             let peer_state = peers.state.read();
             // No evidence yet that our peer has synced to a chain with work equal to that
             // of our tip, when we first detected it was behind. Send a single getheaders
             // message to give the peer a chance to update us.
-            assert!({ network_context.disconnected.lock().is_empty() });
+            assert!(network_context.reported().is_empty());
             assert_eq!(
                 peer_state.get(&3).unwrap().chain_sync.timeout,
                 unix_time_as_millis() + EVICTION_HEADERS_RESPONSE_TIME
@@ -1360,18 +1348,13 @@ mod tests {
                 unix_time_as_millis() + EVICTION_HEADERS_RESPONSE_TIME
             );
         }
-        faketime::write_millis(
-            &faketime_file,
-            unix_time_as_millis() + EVICTION_HEADERS_RESPONSE_TIME + 1,
-        )
-        .expect("write millis");
+        clock.advance(EVICTION_HEADERS_RESPONSE_TIME + 1);
         synchronizer.eviction(&network_context);
         {
             // Peer(3,4) run out of time to catch up!
-            let disconnected = network_context.disconnected.lock();
             assert_eq!(
-                disconnected.deref(),
-                &FnvHashSet::from_iter(vec![3, 4].into_iter())
+                reported_peers(&network_context),
+                FnvHashSet::from_iter(vec![3, 4].into_iter())
             )
         }
     }
@@ -9,9 +9,7 @@ use std::thread;
 use std::time::Duration;
 
 mod filter;
-#[cfg(not(disable_faketime))]
 mod relayer;
-#[cfg(not(disable_faketime))]
 mod synchronizer;
 
 #[derive(Default)]
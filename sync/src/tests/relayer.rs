@@ -1,4 +1,4 @@
-use crate::relayer::TX_PROPOSAL_TOKEN;
+use crate::relayer::{TX_PROPOSAL_TOKEN, TX_REBROADCAST_TOKEN};
 use crate::tests::TestNode;
 use crate::{Relayer, RELAY_PROTOCOL_ID};
 use ckb_chain::chain::{ChainBuilder, ChainController};
@@ -8,12 +8,14 @@ use ckb_core::header::HeaderBuilder;
 use ckb_core::script::Script;
 use ckb_core::transaction::{CellInput, CellOutput, OutPoint, TransactionBuilder};
 use ckb_db::memorydb::MemoryKeyValueDB;
+use ckb_network::test_utils::MockProtocolContext;
+use ckb_network::CKBProtocolHandler;
 use ckb_notify::NotifyService;
 use ckb_pool::txs_pool::{PoolConfig, TransactionPoolService};
 use ckb_protocol::RelayMessage;
 use ckb_shared::shared::{ChainProvider, Shared, SharedBuilder};
 use ckb_shared::store::ChainKVStore;
-use faketime::{self, unix_time_as_millis};
+use ckb_time::{unix_time_as_millis, Clock};
 use flatbuffers::get_root;
 use flatbuffers::FlatBufferBuilder;
 use numext_fixed_hash::H256;
@@ -28,15 +30,12 @@ use std::{thread, time};
 
 #[test]
 fn relay_compact_block_with_one_tx() {
-    let faketime_file = faketime::millis_tempfile(0).expect("create faketime file");
-    faketime::enable(&faketime_file);
-    let thread_name = format!("FAKETIME={}", faketime_file.display());
-
-    // Use the same thread name for all child threads, so the time is mocked in all these threads.
-    // This is required because the test relies on the determined timestamp. Now all the threads
-    // freeze the timestamp at UNIX EPOCH.
-    let (mut node1, shared1, chain_controller1) = setup_node(&thread_name, 3);
-    let (mut node2, shared2, _chain_controller2) = setup_node(&thread_name, 3);
+    // The test relies on the determined timestamps, the services and the node
+    // threads spawned with `inherit` freeze the time at the unix epoch.
+    let _clock = Clock::new(0).enter();
+
+    let (mut node1, shared1, chain_controller1) = setup_node(3);
+    let (mut node2, shared2, _chain_controller2) = setup_node(3);
     let barrier = Arc::new(Barrier::new(2));
 
     node1.connect(&mut node2, RELAY_PROTOCOL_ID);
@@ -44,8 +43,7 @@ fn relay_compact_block_with_one_tx() {
     let (signal_tx1, _) = channel();
     let barrier1 = Arc::clone(&barrier);
     thread::Builder::new()
-        .name(thread_name)
-        .spawn(move || {
+        .spawn(ckb_time::inherit(move || {
             let last_block = shared1
                 .block(&shared1.chain_state().read().tip_hash())
                 .unwrap();
@@ -81,8 +79,7 @@ fn relay_compact_block_with_one_tx() {
                     .parent_hash(last_block.header().hash().clone())
                     .number(number)
                     .timestamp(timestamp)
-                    .difficulty(difficulty)
-                    .cellbase_id(cellbase.hash().clone());
+                    .difficulty(difficulty);
 
                 BlockBuilder::default()
                     .commit_transaction(cellbase)
@@ -117,8 +114,7 @@ fn relay_compact_block_with_one_tx() {
                     .parent_hash(last_block.header().hash().clone())
                     .number(number)
                     .timestamp(timestamp)
-                    .difficulty(difficulty)
-                    .cellbase_id(cellbase.hash().clone());
+                    .difficulty(difficulty);
 
                 BlockBuilder::default()
                     .commit_transaction(cellbase)
@@ -139,7 +135,7 @@ fn relay_compact_block_with_one_tx() {
 
             node1.start(&signal_tx1, |_| false);
             barrier1.wait();
-        })
+        }))
         .expect("thread spawn");
 
     let barrier2 = Arc::clone(&barrier);
@@ -167,22 +163,18 @@ fn relay_compact_block_with_one_tx() {
 
 #[test]
 fn relay_compact_block_with_missing_indexs() {
-    let faketime_file = faketime::millis_tempfile(0).expect("create faketime file");
-    faketime::enable(&faketime_file);
-    let thread_name = format!("FAKETIME={}", faketime_file.display());
+    // The test relies on the determined timestamps, the services and the node
+    // threads spawned with `inherit` freeze the time at the unix epoch.
+    let _clock = Clock::new(0).enter();
 
-    // Use the same thread name for all child threads, so the time is mocked in all these threads.
-    // This is required because the test relies on the determined timestamp. Now all the threads
-    // freeze the timestamp at UNIX EPOCH.
-    let (mut node1, shared1, chain_controller1) = setup_node(&thread_name, 3);
-    let (mut node2, shared2, _chain_controller2) = setup_node(&thread_name, 3);
+    let (mut node1, shared1, chain_controller1) = setup_node(3);
+    let (mut node2, shared2, _chain_controller2) = setup_node(3);
 
     node1.connect(&mut node2, RELAY_PROTOCOL_ID);
 
     let (signal_tx1, _) = channel();
     thread::Builder::new()
-        .name(thread_name)
-        .spawn(move || {
+        .spawn(ckb_time::inherit(move || {
             let last_block = shared1
                 .block(&shared1.chain_state().read().tip_hash())
                 .unwrap();
@@ -222,8 +214,7 @@ fn relay_compact_block_with_missing_indexs() {
                     .parent_hash(last_block.header().hash().clone())
                     .number(number)
                     .timestamp(timestamp)
-                    .difficulty(difficulty)
-                    .cellbase_id(cellbase.hash().clone());
+                    .difficulty(difficulty);
 
                 BlockBuilder::default()
                     .commit_transaction(cellbase)
@@ -258,8 +249,7 @@ fn relay_compact_block_with_missing_indexs() {
                     .parent_hash(last_block.header().hash().clone())
                     .number(number)
                     .timestamp(timestamp)
-                    .difficulty(difficulty)
-                    .cellbase_id(cellbase.hash().clone());
+                    .difficulty(difficulty);
 
                 BlockBuilder::default()
                     .commit_transaction(cellbase)
@@ -279,7 +269,7 @@ fn relay_compact_block_with_missing_indexs() {
             }
 
             node1.start(&signal_tx1, |_| false);
-        })
+        }))
         .expect("thread spawn");
 
     let (signal_tx2, signal_rx2) = channel();
@@ -299,13 +289,37 @@ fn relay_compact_block_with_missing_indexs() {
     assert_eq!(shared2.chain_state().read().tip_number(), 5);
 }
 
+#[test]
+fn relayer_registers_timers() {
+    let (relayer, _shared, _chain_controller) = setup_relayer(0);
+    let nc = MockProtocolContext::new(RELAY_PROTOCOL_ID);
+    relayer.initialize(nc.boxed());
+
+    let tokens: Vec<_> = nc.timers().into_iter().map(|(token, _)| token).collect();
+    assert_eq!(tokens, vec![TX_PROPOSAL_TOKEN, TX_REBROADCAST_TOKEN]);
+    assert!(nc.sent().is_empty());
+}
+
 fn setup_node(
-    thread_name: &str,
     height: u64,
 ) -> (
     TestNode,
     Shared<ChainKVStore<MemoryKeyValueDB>>,
     ChainController,
+) {
+    let (relayer, shared, chain_controller) = setup_relayer(height);
+    let mut node = TestNode::default();
+    let protocol = Arc::new(relayer) as Arc<_>;
+    node.add_protocol(RELAY_PROTOCOL_ID, &protocol, &[TX_PROPOSAL_TOKEN]);
+    (node, shared, chain_controller)
+}
+
+fn setup_relayer(
+    height: u64,
+) -> (
+    Relayer<ChainKVStore<MemoryKeyValueDB>>,
+    Shared<ChainKVStore<MemoryKeyValueDB>>,
+    ChainController,
 ) {
     let mut block = BlockBuilder::default().with_header_builder(
         HeaderBuilder::default()
@@ -318,10 +332,10 @@ fn setup_node(
         .consensus(consensus)
         .build();
 
-    let notify = NotifyService::default().start(Some(thread_name));
+    let notify = NotifyService::default().start(None::<&str>);
     let tx_pool_service =
         TransactionPoolService::new(PoolConfig::default(), shared.clone(), notify.clone());
-    let tx_pool_controller = tx_pool_service.start(Some(thread_name));
+    let tx_pool_controller = tx_pool_service.start(None::<&str>);
 
     let chain_service = ChainBuilder::new(shared.clone(), notify)
         .verification(false)
@@ -344,8 +358,7 @@ fn setup_node(
             .parent_hash(block.header().hash().clone())
             .number(number)
             .timestamp(timestamp)
-            .difficulty(difficulty)
-            .cellbase_id(cellbase.hash().clone());
+            .difficulty(difficulty);
 
         block = BlockBuilder::default()
             .commit_transaction(cellbase)
@@ -362,11 +375,7 @@ fn setup_node(
         tx_pool_controller,
         Arc::new(Default::default()),
     );
-
-    let mut node = TestNode::default();
-    let protocol = Arc::new(relayer) as Arc<_>;
-    node.add_protocol(RELAY_PROTOCOL_ID, &protocol, &[TX_PROPOSAL_TOKEN]);
-    (node, shared, chain_controller)
+    (relayer, shared, chain_controller)
 }
 
 // This helper is copied from pool test
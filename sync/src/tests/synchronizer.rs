@@ -11,7 +11,7 @@ use ckb_notify::NotifyService;
 use ckb_protocol::SyncMessage;
 use ckb_shared::shared::{ChainProvider, Shared, SharedBuilder};
 use ckb_shared::store::ChainKVStore;
-use faketime::{self, unix_time_as_millis};
+use ckb_time::{unix_time_as_millis, Clock};
 use flatbuffers::get_root;
 use numext_fixed_uint::U256;
 use std::sync::mpsc::channel;
@@ -20,19 +20,18 @@ use std::thread;
 
 #[test]
 fn basic_sync() {
-    let faketime_file = faketime::millis_tempfile(0).expect("create faketime file");
-    faketime::enable(&faketime_file);
-    let thread_name = format!("FAKETIME={}", faketime_file.display());
+    // The blocks are timestamped from a clock frozen at the unix epoch, which
+    // the services and the node threads run on
+    let _clock = Clock::new(0).enter();
 
-    let (mut node1, shared1) = setup_node(&thread_name, 1);
-    let (mut node2, shared2) = setup_node(&thread_name, 3);
+    let (mut node1, shared1) = setup_node(1);
+    let (mut node2, shared2) = setup_node(3);
 
     node1.connect(&mut node2, SYNC_PROTOCOL_ID);
 
     let (signal_tx1, signal_rx1) = channel();
     thread::Builder::new()
-        .name(thread_name.clone())
-        .spawn(move || {
+        .spawn(ckb_time::inherit(move || {
             node1.start(&signal_tx1, |data| {
                 let msg = get_root::<SyncMessage>(data);
                 // terminate thread after 3 blocks
@@ -40,15 +39,14 @@ fn basic_sync() {
                     .map(|block| block.header().unwrap().number() == 3)
                     .unwrap_or(false)
             });
-        })
+        }))
         .expect("thread spawn");
 
     let (signal_tx2, _) = channel();
     thread::Builder::new()
-        .name(thread_name)
-        .spawn(move || {
+        .spawn(ckb_time::inherit(move || {
             node2.start(&signal_tx2, |_| false);
-        })
+        }))
         .expect("thread spawn");
 
     // Wait node1 receive block from node2
@@ -61,10 +59,7 @@ fn basic_sync() {
     );
 }
 
-fn setup_node(
-    thread_name: &str,
-    height: u64,
-) -> (TestNode, Shared<ChainKVStore<MemoryKeyValueDB>>) {
+fn setup_node(height: u64) -> (TestNode, Shared<ChainKVStore<MemoryKeyValueDB>>) {
     let mut block = BlockBuilder::default().with_header_builder(
         HeaderBuilder::default()
             .timestamp(unix_time_as_millis())
@@ -75,7 +70,7 @@ fn setup_node(
     let shared = SharedBuilder::<ChainKVStore<MemoryKeyValueDB>>::new_memory()
         .consensus(consensus)
         .build();
-    let notify = NotifyService::default().start(Some(thread_name));
+    let notify = NotifyService::default().start(None::<&str>);
 
     let chain_service = ChainBuilder::new(shared.clone(), notify)
         .verification(false)
@@ -5,8 +5,8 @@ use ckb_core::block::Block;
 use ckb_core::header::{BlockNumber, Header};
 use ckb_core::transaction::Transaction;
 use ckb_network::PeerIndex;
+use ckb_time::unix_time_as_millis;
 use ckb_util::RwLock;
-use faketime::unix_time_as_millis;
 use fnv::{FnvHashMap, FnvHashSet};
 use log::debug;
 use numext_fixed_hash::H256;
@@ -55,6 +55,8 @@ pub struct PeerState {
     pub headers_sync_timeout: Option<u64>,
     pub disconnect: bool,
     pub chain_sync: ChainSyncState,
+    // the peer has pruned the bodies of the blocks below this number
+    pub pruned_below: BlockNumber,
 }
 
 #[derive(Default)]
@@ -137,6 +139,7 @@ impl Peers {
                     headers_sync_timeout: Some(predicted_headers_sync_time),
                     disconnect: false,
                     chain_sync,
+                    pruned_below: 0,
                 }
             });
     }
@@ -160,6 +163,19 @@ impl Peers {
             .or_insert_with(|| header_view.clone());
     }
 
+    pub fn pruned_below(&self, peer: PeerIndex) -> BlockNumber {
+        self.state
+            .read()
+            .get(&peer)
+            .map_or(0, |state| state.pruned_below)
+    }
+
+    pub fn set_pruned_below(&self, peer: PeerIndex, number: BlockNumber) {
+        if let Some(state) = self.state.write().get_mut(&peer) {
+            state.pruned_below = number;
+        }
+    }
+
     pub fn getheaders_received(&self, _peer: PeerIndex) {
         // TODO:
     }
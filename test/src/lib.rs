@@ -0,0 +1,33 @@
+//! # The Test Harness Library
+//!
+//! Several full nodes in one process, connected by an in-memory transport,
+//! to test the sync and relay protocols end to end:
+//!
+//! ```ignore
+//! let net = Net::new(2);
+//! net[0].mine_blocks(5);
+//! net.connect(0, 1);
+//! net.assert_converged(5);
+//! ```
+//!
+//! Every node runs the chain, pool, synchronizer, relayer and time
+//! protocols, as `ckb run` does, on a memory store. The nodes of a [Net]
+//! start from the same genesis block.
+//!
+//! A net started by [Net::simulated] delivers the messages on a virtual
+//! clock instead, with the latencies and losses of its [SimConfig], and its
+//! [Scheduler] partitions the nodes, so that a test of forks and network
+//! failures replays the same from its seed.
+
+mod net;
+mod node;
+mod sim;
+mod transport;
+
+#[cfg(test)]
+mod tests;
+
+pub use crate::net::{Net, DEFAULT_TIMEOUT};
+pub use crate::node::{always_success_script, Node, NodeShared};
+pub use crate::sim::{Scheduler, SimConfig};
+pub use crate::transport::{Delivery, MemoryTransport};
@@ -0,0 +1,197 @@
+use crate::node::Node;
+use crate::sim::{as_millis, Scheduler, SimConfig};
+use crate::transport::Delivery;
+use ckb_chain_spec::consensus::Consensus;
+use ckb_core::block::BlockBuilder;
+use ckb_core::header::HeaderBuilder;
+use ckb_time::{unix_time_as_millis, Clock, Scope};
+use numext_fixed_hash::H256;
+use numext_fixed_uint::U256;
+use std::ops::Index;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Long enough for the sync timers, which fire every second, to fetch a few
+/// hundred blocks
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+// Where the clock of a simulation starts, so that its blocks do not depend on
+// the day it runs
+const SIMULATION_START: u64 = 1_550_000_000_000;
+
+/// Nodes on the same chain, stopped when dropped
+pub struct Net {
+    nodes: Vec<Node>,
+    scheduler: Option<Arc<Scheduler>>,
+    // The clock of the simulation, entered by the thread creating the net
+    _scope: Option<Scope>,
+}
+
+impl Net {
+    /// Starts `count` nodes, not connected yet, on the same recent genesis
+    /// block
+    pub fn new(count: usize) -> Net {
+        Net::with_consensus(count, recent_consensus())
+    }
+
+    pub fn with_consensus(count: usize, consensus: Consensus) -> Net {
+        Net {
+            nodes: start_nodes(count, &consensus, &Delivery::Threads),
+            scheduler: None,
+            _scope: None,
+        }
+    }
+
+    /// Starts `count` nodes on the network simulated by `config`, on a
+    /// virtual clock the current thread enters until the net is dropped.
+    /// Nothing happens on the network but in [Net::run_for] and the waits,
+    /// which run the simulation instead of sleeping.
+    pub fn simulated(count: usize, config: SimConfig) -> Net {
+        let clock = Clock::new(SIMULATION_START);
+        let scope = clock.enter();
+        let scheduler = Arc::new(Scheduler::new(clock, config));
+        let delivery = Delivery::Simulated(Arc::clone(&scheduler));
+        Net {
+            nodes: start_nodes(count, &recent_consensus(), &delivery),
+            scheduler: Some(scheduler),
+            _scope: Some(scope),
+        }
+    }
+
+    /// The scheduler of a simulated net, which partitions and heals it
+    pub fn scheduler(&self) -> Option<&Arc<Scheduler>> {
+        self.scheduler.as_ref()
+    }
+
+    /// Lets the network run for `duration`, of the virtual clock in a
+    /// simulation
+    pub fn run_for(&self, duration: Duration) {
+        match self.scheduler {
+            Some(ref scheduler) => scheduler.run_for(as_millis(duration)),
+            None => thread::sleep(duration),
+        }
+    }
+
+    pub fn nodes(&self) -> &[Node] {
+        &self.nodes
+    }
+
+    /// Connects the node `from` to `to`, the outbound side being `from`
+    pub fn connect(&self, from: usize, to: usize) {
+        self.nodes[from].connect(&self.nodes[to]);
+    }
+
+    pub fn disconnect(&self, from: usize, to: usize) {
+        self.nodes[from].disconnect(&self.nodes[to]);
+    }
+
+    /// Connects each node to the next one
+    pub fn connect_line(&self) {
+        for index in 1..self.nodes.len() {
+            self.connect(index - 1, index);
+        }
+    }
+
+    /// Connects every pair of nodes
+    pub fn connect_all(&self) {
+        for from in 0..self.nodes.len() {
+            for to in from + 1..self.nodes.len() {
+                self.connect(from, to);
+            }
+        }
+    }
+
+    /// Polls `condition` until it holds, false when it still does not after
+    /// `timeout`
+    pub fn wait_until<F: Fn(&Net) -> bool>(&self, timeout: Duration, condition: F) -> bool {
+        let start = Instant::now();
+        let start_millis = unix_time_as_millis();
+        loop {
+            if condition(self) {
+                return true;
+            }
+            let elapsed = match self.scheduler {
+                Some(_) => Duration::from_millis(unix_time_as_millis() - start_millis),
+                None => start.elapsed(),
+            };
+            if elapsed >= timeout {
+                return false;
+            }
+            self.run_for(POLL_INTERVAL);
+        }
+    }
+
+    /// The tips of the nodes, by node
+    pub fn tips(&self) -> Vec<(u64, H256)> {
+        self.nodes
+            .iter()
+            .map(|node| (node.tip_number(), node.tip_hash()))
+            .collect()
+    }
+
+    pub fn is_converged(&self) -> bool {
+        let tips = self.tips();
+        tips.iter().all(|tip| *tip == tips[0])
+    }
+
+    /// Waits until every node has the same tip, which must be at `number`
+    pub fn assert_converged(&self, number: u64) {
+        if !self.wait_until(DEFAULT_TIMEOUT, Net::is_converged) {
+            panic!("nodes did not converge, tips {:?}", self.tips());
+        }
+        assert_eq!(self.nodes[0].tip_number(), number, "converged tip");
+    }
+
+    /// Waits until the pool of every node has the transaction
+    pub fn assert_relayed(&self, hash: &H256) {
+        let relayed = self.wait_until(DEFAULT_TIMEOUT, |net| {
+            net.nodes.iter().all(|node| node.pool_contains(hash))
+        });
+        if !relayed {
+            let missing: Vec<_> = self
+                .nodes
+                .iter()
+                .filter(|node| !node.pool_contains(hash))
+                .map(Node::name)
+                .collect();
+            panic!("transaction {:#x} not relayed to {:?}", hash, missing);
+        }
+    }
+}
+
+impl Index<usize> for Net {
+    type Output = Node;
+
+    fn index(&self, index: usize) -> &Node {
+        &self.nodes[index]
+    }
+}
+
+impl Drop for Net {
+    fn drop(&mut self) {
+        for node in &self.nodes {
+            node.stop();
+        }
+        if let Some(ref scheduler) = self.scheduler {
+            scheduler.clear();
+        }
+    }
+}
+
+// The genesis block is as recent as the clock, so that the nodes are not in
+// the initial block download
+fn recent_consensus() -> Consensus {
+    let genesis = BlockBuilder::default().with_header_builder(
+        HeaderBuilder::default()
+            .timestamp(unix_time_as_millis())
+            .difficulty(U256::from(1000u64)),
+    );
+    Consensus::default().set_genesis_block(genesis)
+}
+
+fn start_nodes(count: usize, consensus: &Consensus, delivery: &Delivery) -> Vec<Node> {
+    (0..count)
+        .map(|index| Node::start(index, consensus.clone(), delivery))
+        .collect()
+}
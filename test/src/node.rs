@@ -0,0 +1,254 @@
+use crate::transport::{Delivery, MemoryTransport};
+use ckb_chain::chain::{ChainBuilder, ChainController};
+use ckb_chain_spec::consensus::Consensus;
+use ckb_core::block::{Block, BlockBuilder};
+use ckb_core::header::HeaderBuilder;
+use ckb_core::script::Script;
+use ckb_core::transaction::{
+    CellInput, CellOutput, ProposalShortId, Transaction, TransactionBuilder,
+};
+use ckb_core::BlockNumber;
+use ckb_db::memorydb::MemoryKeyValueDB;
+use ckb_metrics::Registry;
+use ckb_network::CKBProtocolHandler;
+use ckb_notify::{NotifyController, NotifyService};
+use ckb_pool::txs_pool::types::InsertionResult;
+use ckb_pool::txs_pool::{PoolConfig, PoolError, TransactionPoolController, TransactionPoolService};
+use ckb_protocol::RelayMessage;
+use ckb_shared::shared::{ChainProvider, Shared, SharedBuilder};
+use ckb_shared::store::ChainKVStore;
+use ckb_sync::{
+    Config as SyncConfig, NetTimeProtocol, Relayer, Synchronizer, RELAY_PROTOCOL_ID,
+    SYNC_PROTOCOL_ID, TIME_PROTOCOL_ID,
+};
+use ckb_time::unix_time_as_millis;
+use flatbuffers::FlatBufferBuilder;
+use numext_fixed_hash::H256;
+use std::cmp;
+use std::collections::HashSet;
+use std::sync::Arc;
+
+pub type NodeShared = Shared<ChainKVStore<MemoryKeyValueDB>>;
+
+// Enough for every transaction a test submits
+const MAX_PROPOSALS: usize = 1000;
+const MAX_TRANSACTIONS: usize = 1000;
+
+/// A full node in the process: chain, pool, synchronizer and relayer over a
+/// [MemoryTransport]. The blocks are not verified by the chain, the ones the
+/// node mines have no proof of work, but the transactions entering the pool
+/// are. Each node reports its metrics into its own registry.
+pub struct Node {
+    name: String,
+    metrics: Arc<Registry>,
+    shared: NodeShared,
+    chain: ChainController,
+    tx_pool: TransactionPoolController,
+    notify: NotifyController,
+    transport: Arc<MemoryTransport>,
+}
+
+impl Node {
+    /// Starts the node `index` on the chain of `consensus`, the nodes of a
+    /// test share one to start from the same genesis block
+    pub fn start(index: usize, consensus: Consensus, delivery: &Delivery) -> Node {
+        Node::start_with_pool(index, consensus, PoolConfig::default(), delivery)
+    }
+
+    pub fn start_with_pool(
+        index: usize,
+        consensus: Consensus,
+        pool_config: PoolConfig,
+        delivery: &Delivery,
+    ) -> Node {
+        let name = format!("node{}", index);
+        let metrics = Arc::new(Registry::default());
+        let _metrics_scope = ckb_metrics::enter(&metrics);
+        let shared = SharedBuilder::<ChainKVStore<MemoryKeyValueDB>>::new_memory()
+            .consensus(consensus)
+            .build();
+        let notify = NotifyService::default().start(Some(format!("{}-notify", name)));
+        let tx_pool =
+            TransactionPoolService::new(pool_config, shared.clone(), notify.clone())
+                .start(Some(format!("{}-pool", name)));
+        let chain = ChainBuilder::new(shared.clone(), notify.clone())
+            .verification(false)
+            .build()
+            .start(Some(format!("{}-chain", name)));
+
+        let synchronizer = Synchronizer::new(chain.clone(), shared.clone(), SyncConfig::default())
+            .with_notify(notify.clone());
+        let relayer = Relayer::new(
+            chain.clone(),
+            shared.clone(),
+            tx_pool.clone(),
+            synchronizer.peers(),
+        );
+        let net_time_checker = NetTimeProtocol::from_config(&SyncConfig::default());
+        let protocols: Vec<(_, Arc<dyn CKBProtocolHandler>)> = vec![
+            (SYNC_PROTOCOL_ID, Arc::new(synchronizer)),
+            (RELAY_PROTOCOL_ID, Arc::new(relayer)),
+            (TIME_PROTOCOL_ID, Arc::new(net_time_checker)),
+        ];
+        let transport =
+            MemoryTransport::start(format!("{}-network", name), index, protocols, delivery);
+
+        Node {
+            name,
+            metrics,
+            shared,
+            chain,
+            tx_pool,
+            notify,
+            transport,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn metrics(&self) -> &Arc<Registry> {
+        &self.metrics
+    }
+
+    pub fn shared(&self) -> &NodeShared {
+        &self.shared
+    }
+
+    pub fn chain(&self) -> &ChainController {
+        &self.chain
+    }
+
+    pub fn tx_pool(&self) -> &TransactionPoolController {
+        &self.tx_pool
+    }
+
+    /// The transport of the node, which also sends raw messages to its peers
+    pub fn transport(&self) -> &Arc<MemoryTransport> {
+        &self.transport
+    }
+
+    pub fn tip_number(&self) -> BlockNumber {
+        self.shared.chain_state().read().tip_number()
+    }
+
+    pub fn tip_hash(&self) -> H256 {
+        self.shared.chain_state().read().tip_hash()
+    }
+
+    /// Connects to `other`, this node being the outbound side
+    pub fn connect(&self, other: &Node) {
+        MemoryTransport::connect(&self.transport, &other.transport).expect("connect nodes");
+    }
+
+    pub fn disconnect(&self, other: &Node) {
+        if let Some(peer) = self.transport.peer_index(&other.transport) {
+            self.transport.disconnect(peer);
+        }
+    }
+
+    pub fn is_connected(&self, other: &Node) -> bool {
+        self.transport.peer_index(&other.transport).is_some()
+    }
+
+    /// Mines a block on the tip with the transactions of the pool, then
+    /// announces it to the peers as the miner RPC does
+    pub fn mine(&self) -> Block {
+        let (proposals, transactions) = self
+            .tx_pool
+            .get_proposal_commit_transactions(MAX_PROPOSALS, MAX_TRANSACTIONS);
+        self.mine_block(proposals, transactions)
+    }
+
+    /// Mines a block of `proposals` committing `transactions`, which the
+    /// chain accepts unproposed as it does not verify the blocks
+    pub fn mine_block(
+        &self,
+        proposals: Vec<ProposalShortId>,
+        transactions: Vec<Transaction>,
+    ) -> Block {
+        let tip = self.shared.chain_state().read().tip_header().clone();
+        let number = tip.number() + 1;
+        let cellbase = TransactionBuilder::default()
+            .input(CellInput::new_cellbase_input(number))
+            .output(CellOutput::new(
+                self.shared.consensus().block_reward(number),
+                Vec::new(),
+                always_success_script().type_hash(),
+                None,
+            ))
+            .build();
+        // The outputs of the same transactions can not tell the blocks of
+        // two nodes apart, the nonce does, without drawing it, so that a
+        // simulation replays the same blocks
+        let header_builder = HeaderBuilder::default()
+            .parent_hash(tip.hash())
+            .number(number)
+            .timestamp(cmp::max(tip.timestamp() + 1, unix_time_as_millis()))
+            .difficulty(
+                self.shared
+                    .calculate_difficulty(&tip)
+                    .expect("calculate difficulty"),
+            )
+            .nonce(self.transport.id() as u64);
+        let block = BlockBuilder::default()
+            .commit_transaction(cellbase)
+            .commit_transactions(transactions)
+            .proposal_transactions(proposals)
+            .with_header_builder(header_builder);
+
+        self.chain
+            .process_block(Arc::new(block.clone()))
+            .expect("process mined block");
+        let fbb = &mut FlatBufferBuilder::new();
+        let message = RelayMessage::build_compact_block(fbb, &block, &HashSet::new());
+        fbb.finish(message, None);
+        self.transport
+            .broadcast(RELAY_PROTOCOL_ID, fbb.finished_data());
+        block
+    }
+
+    pub fn mine_blocks(&self, count: usize) -> Vec<Block> {
+        (0..count).map(|_| self.mine()).collect()
+    }
+
+    /// Adds `tx` to the pool then relays it to the peers, as the pool RPC
+    /// does
+    pub fn submit_transaction(&self, tx: &Transaction) -> Result<InsertionResult, PoolError> {
+        let result = self.tx_pool.add_local_transaction(tx.clone())?;
+        let fbb = &mut FlatBufferBuilder::new();
+        let message = RelayMessage::build_transaction(fbb, tx);
+        fbb.finish(message, None);
+        self.transport
+            .broadcast(RELAY_PROTOCOL_ID, fbb.finished_data());
+        Ok(result)
+    }
+
+    pub fn pool_contains(&self, hash: &H256) -> bool {
+        self.tx_pool
+            .get_transaction(ProposalShortId::from_h256(hash))
+            .map(|tx| &tx.hash() == hash)
+            .unwrap_or(false)
+    }
+
+    /// Whether the transaction is committed in the main chain
+    pub fn is_committed(&self, hash: &H256) -> bool {
+        self.shared.get_transaction(hash).is_some()
+    }
+
+    /// Stops the services of the node, which disconnects from its peers
+    pub fn stop(&self) {
+        self.transport.stop();
+        self.chain.stop();
+        self.tx_pool.stop();
+        self.notify.stop();
+    }
+}
+
+/// The script unlocking the outputs of the mined cellbases, which accepts
+/// any transaction
+pub fn always_success_script() -> Script {
+    let binary = include_bytes!("../../nodes_template/spec/cells/always_success");
+    Script::new(0, Vec::new(), None, Some(binary.to_vec()), Vec::new())
+}
@@ -0,0 +1,308 @@
+use crate::transport::{self, Event, MemoryTransport};
+use ckb_network::{ProtocolId, TimerToken};
+use ckb_time::Clock;
+use ckb_util::Mutex;
+use fnv::FnvHashMap;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::cmp::{self, Ordering};
+use std::collections::BinaryHeap;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// The network of a simulation, the latencies are in milliseconds
+#[derive(Clone, Debug)]
+pub struct SimConfig {
+    /// Seeds the draws of latencies and losses, the same seed replays the
+    /// same network
+    pub seed: u64,
+    pub min_latency: u64,
+    pub max_latency: u64,
+    /// The probability a message is lost
+    pub loss: f64,
+}
+
+impl Default for SimConfig {
+    fn default() -> Self {
+        SimConfig {
+            seed: 0,
+            min_latency: 10,
+            max_latency: 100,
+            loss: 0.0,
+        }
+    }
+}
+
+enum Action {
+    Deliver {
+        from: usize,
+        to: usize,
+        event: Event,
+    },
+    Timer {
+        to: usize,
+        protocol: ProtocolId,
+        token: TimerToken,
+        interval: u64,
+    },
+    // The group of each node, the nodes in none of the groups being together
+    Partition(Option<FnvHashMap<usize, usize>>),
+}
+
+struct Entry {
+    at: u64,
+    seq: u64,
+    action: Action,
+}
+
+// Ordered so that the heap pops the earliest entry first, then the first
+// scheduled among the ones at the same time
+impl Ord for Entry {
+    fn cmp(&self, other: &Entry) -> Ordering {
+        (other.at, other.seq).cmp(&(self.at, self.seq))
+    }
+}
+
+impl PartialOrd for Entry {
+    fn partial_cmp(&self, other: &Entry) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Entry {
+    fn eq(&self, other: &Entry) -> bool {
+        (self.at, self.seq) == (other.at, other.seq)
+    }
+}
+
+impl Eq for Entry {}
+
+struct State {
+    queue: BinaryHeap<Entry>,
+    seq: u64,
+    rng: StdRng,
+    groups: Option<FnvHashMap<usize, usize>>,
+    // The time of the last message on each link, which later ones can not
+    // overtake
+    last_at: FnvHashMap<(usize, usize), u64>,
+    transports: FnvHashMap<usize, Arc<MemoryTransport>>,
+}
+
+impl State {
+    fn schedule(&mut self, at: u64, action: Action) {
+        self.seq += 1;
+        self.queue.push(Entry {
+            at,
+            seq: self.seq,
+            action,
+        });
+    }
+
+    fn is_partitioned(&self, from: usize, to: usize) -> bool {
+        match self.groups {
+            Some(ref groups) => groups.get(&from) != groups.get(&to),
+            None => false,
+        }
+    }
+}
+
+/// Delivers the events of the transports of a simulation, by one thread, in
+/// the order of a virtual clock which only moves by [Scheduler::run_for].
+///
+/// Every message between two nodes takes a latency drawn from the seeded
+/// random generator and may be lost, and is dropped when a partition
+/// separates the nodes as it arrives. The connections, disconnections and
+/// timers are never lost. The services of the nodes still run on their
+/// threads, on the virtual clock, so a run only replays the same when the
+/// nodes do not race them.
+pub struct Scheduler {
+    clock: Clock,
+    config: SimConfig,
+    state: Mutex<State>,
+}
+
+impl Scheduler {
+    pub fn new(clock: Clock, config: SimConfig) -> Scheduler {
+        let state = State {
+            queue: BinaryHeap::new(),
+            seq: 0,
+            rng: StdRng::seed_from_u64(config.seed),
+            groups: None,
+            last_at: FnvHashMap::default(),
+            transports: FnvHashMap::default(),
+        };
+        Scheduler {
+            clock,
+            config,
+            state: Mutex::new(state),
+        }
+    }
+
+    /// The virtual time in milliseconds since the unix epoch
+    pub fn now(&self) -> u64 {
+        self.clock.now()
+    }
+
+    pub(crate) fn register(&self, transport: &Arc<MemoryTransport>) {
+        self.state
+            .lock()
+            .transports
+            .insert(transport.id(), Arc::clone(transport));
+    }
+
+    pub(crate) fn deliver(&self, from: usize, to: usize, event: Event) {
+        let now = self.now();
+        let mut state = self.state.lock();
+        if from == to {
+            state.schedule(now, Action::Deliver { from, to, event });
+            return;
+        }
+        if let Event::Received(..) = event {
+            if self.config.loss > 0.0 && state.rng.gen_bool(self.config.loss) {
+                return;
+            }
+        }
+        let latency = state
+            .rng
+            .gen_range(self.config.min_latency, self.config.max_latency + 1);
+        let last_at = state.last_at.get(&(from, to)).cloned().unwrap_or(0);
+        let at = cmp::max(now + latency, last_at);
+        state.last_at.insert((from, to), at);
+        state.schedule(at, Action::Deliver { from, to, event });
+    }
+
+    pub(crate) fn register_timer(
+        &self,
+        to: usize,
+        protocol: ProtocolId,
+        token: TimerToken,
+        delay: Duration,
+    ) {
+        let interval = as_millis(delay);
+        let at = self.now() + interval;
+        self.state.lock().schedule(
+            at,
+            Action::Timer {
+                to,
+                protocol,
+                token,
+                interval,
+            },
+        );
+    }
+
+    /// Separates the nodes of each group from the others at once, the nodes
+    /// in none of the groups being another
+    pub fn partition(&self, groups: &[&[usize]]) {
+        self.state.lock().groups = Some(group_map(groups));
+    }
+
+    /// Reconnects every node
+    pub fn heal(&self) {
+        self.state.lock().groups = None;
+    }
+
+    /// Partitions the nodes when the clock reaches `at`
+    pub fn schedule_partition(&self, at: u64, groups: &[&[usize]]) {
+        self.state
+            .lock()
+            .schedule(at, Action::Partition(Some(group_map(groups))));
+    }
+
+    /// Heals the partition when the clock reaches `at`
+    pub fn schedule_heal(&self, at: u64) {
+        self.state.lock().schedule(at, Action::Partition(None));
+    }
+
+    /// Handles the events due in the next `millis`, in turn, then moves the
+    /// clock to the end of them
+    pub fn run_for(&self, millis: u64) {
+        let target = self.now() + millis;
+        loop {
+            let entry = {
+                let mut state = self.state.lock();
+                let due = state.queue.peek().map_or(false, |entry| entry.at <= target);
+                if due {
+                    state.queue.pop()
+                } else {
+                    None
+                }
+            };
+            let entry = match entry {
+                Some(entry) => entry,
+                None => break,
+            };
+            if entry.at > self.now() {
+                self.clock.set(entry.at);
+            }
+            self.run(entry.action);
+        }
+        self.clock.set(target);
+    }
+
+    // The lock is released while the handlers run, as they deliver new
+    // events
+    fn run(&self, action: Action) {
+        match action {
+            Action::Deliver { from, to, event } => {
+                let transport = {
+                    let state = self.state.lock();
+                    if let Event::Received(..) = event {
+                        if state.is_partitioned(from, to) {
+                            return;
+                        }
+                    }
+                    state.transports.get(&to).cloned()
+                };
+                if let Some(transport) = transport {
+                    transport::handle(&transport, event);
+                }
+            }
+            Action::Timer {
+                to,
+                protocol,
+                token,
+                interval,
+            } => {
+                let transport = self.state.lock().transports.get(&to).cloned();
+                if let Some(transport) = transport {
+                    if transport.is_stopped() {
+                        return;
+                    }
+                    transport::handle(&transport, Event::Timer(protocol, token));
+                    let at = self.now() + interval;
+                    self.state.lock().schedule(
+                        at,
+                        Action::Timer {
+                            to,
+                            protocol,
+                            token,
+                            interval,
+                        },
+                    );
+                }
+            }
+            Action::Partition(groups) => self.state.lock().groups = groups,
+        }
+    }
+
+    /// Drops the pending events and the transports, which refer to the
+    /// scheduler
+    pub(crate) fn clear(&self) {
+        let mut state = self.state.lock();
+        state.queue.clear();
+        state.transports.clear();
+    }
+}
+
+pub(crate) fn as_millis(duration: Duration) -> u64 {
+    duration.as_secs() * 1000 + u64::from(duration.subsec_millis())
+}
+
+fn group_map(groups: &[&[usize]]) -> FnvHashMap<usize, usize> {
+    groups
+        .iter()
+        .enumerate()
+        .flat_map(|(group, nodes)| nodes.iter().map(move |node| (*node, group)))
+        .collect()
+}
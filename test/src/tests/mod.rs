@@ -0,0 +1,3 @@
+mod relay;
+mod sim;
+mod sync;
@@ -0,0 +1,45 @@
+use crate::{always_success_script, Net, DEFAULT_TIMEOUT};
+use ckb_core::transaction::{CellInput, CellOutput, OutPoint, TransactionBuilder};
+
+#[test]
+fn relay_new_blocks() {
+    let net = Net::new(3);
+    net.connect_line();
+    net[0].mine();
+    net.assert_converged(1);
+    net[2].mine();
+    net.assert_converged(2);
+}
+
+#[test]
+fn relay_and_commit_transaction() {
+    let net = Net::new(2);
+    net.connect(0, 1);
+    let block = net[0].mine();
+    net.assert_converged(1);
+
+    let cellbase = &block.commit_transactions()[0];
+    let tx = TransactionBuilder::default()
+        .input(CellInput::new(
+            OutPoint::new(cellbase.hash(), 0),
+            always_success_script(),
+        ))
+        .output(CellOutput::new(
+            cellbase.outputs()[0].capacity,
+            Vec::new(),
+            always_success_script().type_hash(),
+            None,
+        ))
+        .build();
+    net[0].submit_transaction(&tx).expect("submit transaction");
+    net.assert_relayed(&tx.hash());
+
+    // The blocks of the other node propose then commit it
+    let committed = net.wait_until(DEFAULT_TIMEOUT, |net| {
+        net[1].mine();
+        net[1].is_committed(&tx.hash())
+    });
+    assert!(committed, "transaction not committed");
+    net.assert_converged(net[1].tip_number());
+    assert!(net[0].is_committed(&tx.hash()));
+}
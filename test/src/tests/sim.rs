@@ -0,0 +1,76 @@
+use crate::{Net, SimConfig};
+use numext_fixed_hash::H256;
+use std::time::Duration;
+
+// Forks mined by every node of a connected net, until it converges
+fn converged_tip(seed: u64) -> (u64, H256) {
+    let config = SimConfig {
+        seed,
+        ..Default::default()
+    };
+    let net = Net::simulated(3, config);
+    net.connect_all();
+    for round in 0..6 {
+        net[round % 3].mine();
+        net.run_for(Duration::from_millis(30));
+    }
+    net.assert_converged(net[0].tip_number());
+    net.tips()[0].clone()
+}
+
+#[test]
+fn same_seed_replays_the_same_network() {
+    assert_eq!(converged_tip(7), converged_tip(7));
+}
+
+#[test]
+fn messages_take_the_latency() {
+    let config = SimConfig {
+        min_latency: 200,
+        max_latency: 200,
+        ..Default::default()
+    };
+    let net = Net::simulated(2, config);
+    net.connect(0, 1);
+    net[0].mine();
+    net.run_for(Duration::from_millis(150));
+    assert_eq!(net[1].tip_number(), 0);
+    net.run_for(Duration::from_millis(100));
+    assert_eq!(net[1].tip_number(), 1);
+}
+
+#[test]
+fn lost_messages_never_arrive() {
+    let config = SimConfig {
+        loss: 1.0,
+        ..Default::default()
+    };
+    let net = Net::simulated(2, config);
+    net.connect(0, 1);
+    net[0].mine();
+    net.run_for(Duration::from_secs(5));
+    assert_eq!(net[1].tip_number(), 0);
+    assert!(net[1].is_connected(&net[0]));
+}
+
+#[test]
+fn longer_fork_wins_after_healing_partition() {
+    let net = Net::simulated(4, SimConfig::default());
+    net.connect_all();
+    net[0].mine_blocks(2);
+    net.assert_converged(2);
+
+    let scheduler = net.scheduler().expect("simulated net");
+    scheduler.partition(&[&[0, 1], &[2, 3]]);
+    net[0].mine_blocks(2);
+    net[2].mine_blocks(4);
+    net.run_for(Duration::from_secs(5));
+    assert_eq!(net[1].tip_hash(), net[0].tip_hash());
+    assert_eq!(net[3].tip_hash(), net[2].tip_hash());
+    assert_ne!(net[0].tip_hash(), net[2].tip_hash());
+
+    scheduler.schedule_heal(scheduler.now() + 1000);
+    net.run_for(Duration::from_millis(500));
+    assert_eq!(net[0].tip_number(), 4);
+    net.assert_converged(6);
+}
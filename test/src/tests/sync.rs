@@ -0,0 +1,56 @@
+use crate::Net;
+
+#[test]
+fn sync_blocks_mined_before_connecting() {
+    let net = Net::new(2);
+    net[0].mine_blocks(5);
+    net.connect(0, 1);
+    net.assert_converged(5);
+}
+
+#[test]
+fn nodes_report_their_own_metrics() {
+    let net = Net::new(2);
+    net[0].mine_blocks(3);
+    let tip_number = |index: usize| {
+        net[index]
+            .metrics()
+            .gauge("chain_tip_number", Vec::new())
+            .get()
+    };
+    assert_eq!(tip_number(0), 3);
+    assert_eq!(tip_number(1), 0);
+}
+
+#[test]
+fn sync_through_intermediate_node() {
+    let net = Net::new(3);
+    net[0].mine_blocks(3);
+    net.connect_line();
+    net.assert_converged(3);
+}
+
+#[test]
+fn switch_to_longer_fork() {
+    let net = Net::new(2);
+    net[0].mine_blocks(3);
+    net[1].mine_blocks(5);
+    assert_ne!(net[0].tip_hash(), net[1].tip_hash());
+
+    net.connect(0, 1);
+    net.assert_converged(5);
+}
+
+#[test]
+fn sync_after_reconnecting() {
+    let net = Net::new(2);
+    net.connect(0, 1);
+    net[0].mine_blocks(2);
+    net.assert_converged(2);
+
+    net.disconnect(0, 1);
+    assert!(!net[1].is_connected(&net[0]));
+    net[0].mine_blocks(3);
+    net.connect(1, 0);
+    net.assert_converged(5);
+}
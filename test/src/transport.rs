@@ -0,0 +1,357 @@
+use crate::sim::Scheduler;
+use ckb_network::{
+    random_peer_id, CKBProtocolContext, CKBProtocolHandler, Endpoint, Error as NetworkError,
+    PeerIndex, PeerInfo, ProtocolId, SessionInfo, Severity, TimerToken, ToMultiaddr,
+};
+use ckb_util::RwLock;
+use fnv::FnvHashMap;
+use log::debug;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+type Handlers = FnvHashMap<ProtocolId, Arc<dyn CKBProtocolHandler>>;
+
+pub(crate) enum Event {
+    Connected(ProtocolId, PeerIndex),
+    Disconnected(ProtocolId, PeerIndex),
+    Received(ProtocolId, PeerIndex, Vec<u8>),
+    Timer(ProtocolId, TimerToken),
+    Stop,
+}
+
+/// How the events of the transports are delivered
+#[derive(Clone)]
+pub enum Delivery {
+    /// At once, to the events thread of each transport
+    Threads,
+    /// When the simulated clock reaches them, see [Scheduler]
+    Simulated(Arc<Scheduler>),
+}
+
+enum Queue {
+    Thread(Sender<Event>),
+    Simulated(Arc<Scheduler>),
+}
+
+struct Link {
+    remote: Arc<MemoryTransport>,
+    // The index of this node at the remote one
+    remote_index: PeerIndex,
+    session: SessionInfo,
+}
+
+/// The network of a node, whose messages to its peers are queued to their
+/// transports instead of sent over sockets. Every event of a node, messages,
+/// connections and timers, is handled in turn, by the events thread of the
+/// transport as the network service does, or by the simulation.
+pub struct MemoryTransport {
+    id: usize,
+    queue: Queue,
+    handlers: Handlers,
+    peers: RwLock<FnvHashMap<PeerIndex, Link>>,
+    next_index: AtomicUsize,
+    stopped: AtomicBool,
+}
+
+impl MemoryTransport {
+    /// Starts the transport `id` of the handlers of `protocols`, which are
+    /// initialized before any other event
+    pub fn start<S: ToString>(
+        thread_name: S,
+        id: usize,
+        protocols: Vec<(ProtocolId, Arc<dyn CKBProtocolHandler>)>,
+        delivery: &Delivery,
+    ) -> Arc<MemoryTransport> {
+        let (queue, receiver) = match delivery {
+            Delivery::Threads => {
+                let (sender, receiver) = channel();
+                (Queue::Thread(sender), Some(receiver))
+            }
+            Delivery::Simulated(scheduler) => (Queue::Simulated(Arc::clone(scheduler)), None),
+        };
+        let transport = Arc::new(MemoryTransport {
+            id,
+            queue,
+            handlers: protocols.into_iter().collect(),
+            peers: RwLock::new(FnvHashMap::default()),
+            next_index: AtomicUsize::new(0),
+            stopped: AtomicBool::new(false),
+        });
+        match (receiver, delivery) {
+            (Some(receiver), _) => {
+                let dispatcher = Arc::clone(&transport);
+                thread::Builder::new()
+                    .name(thread_name.to_string())
+                    .spawn(ckb_time::inherit(move || dispatch(&dispatcher, &receiver)))
+                    .expect("Start memory transport failed");
+            }
+            (None, Delivery::Simulated(scheduler)) => {
+                scheduler.register(&transport);
+                initialize(&transport);
+            }
+            (None, Delivery::Threads) => unreachable!(),
+        }
+        transport
+    }
+
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    /// Connects the transports, `dialer` being the outbound side. Both sides
+    /// learn of the connection before any message sent through it.
+    pub fn connect(
+        dialer: &Arc<MemoryTransport>,
+        listener: &Arc<MemoryTransport>,
+    ) -> Result<(), NetworkError> {
+        let dialer_session = session(Endpoint::Dialer)?;
+        let listener_session = session(Endpoint::Listener)?;
+        let mut dialer_peers = dialer.peers.write();
+        let mut listener_peers = listener.peers.write();
+        let dialer_index = dialer.next_index.fetch_add(1, Ordering::SeqCst);
+        let listener_index = listener.next_index.fetch_add(1, Ordering::SeqCst);
+        dialer_peers.insert(
+            dialer_index,
+            Link {
+                remote: Arc::clone(listener),
+                remote_index: listener_index,
+                session: dialer_session,
+            },
+        );
+        listener_peers.insert(
+            listener_index,
+            Link {
+                remote: Arc::clone(dialer),
+                remote_index: dialer_index,
+                session: listener_session,
+            },
+        );
+        for protocol in dialer.handlers.keys() {
+            dialer.deliver(dialer, Event::Connected(*protocol, dialer_index));
+        }
+        for protocol in listener.handlers.keys() {
+            listener.deliver(listener, Event::Connected(*protocol, listener_index));
+        }
+        Ok(())
+    }
+
+    /// Closes the connection to `peer`, on both sides
+    pub fn disconnect(&self, peer: PeerIndex) {
+        let link = match self.peers.write().remove(&peer) {
+            Some(link) => link,
+            None => return,
+        };
+        debug!(target: "test", "disconnect peer#{}", peer);
+        link.remote.peers.write().remove(&link.remote_index);
+        for protocol in self.handlers.keys() {
+            self.deliver(self, Event::Disconnected(*protocol, peer));
+        }
+        for protocol in link.remote.handlers.keys() {
+            link.remote.deliver(
+                &link.remote,
+                Event::Disconnected(*protocol, link.remote_index),
+            );
+        }
+    }
+
+    pub fn connected_peers(&self) -> Vec<PeerIndex> {
+        self.peers.read().keys().cloned().collect()
+    }
+
+    /// The peer index of `other`
+    pub fn peer_index(&self, other: &Arc<MemoryTransport>) -> Option<PeerIndex> {
+        self.peers
+            .read()
+            .iter()
+            .find(|(_, link)| Arc::ptr_eq(&link.remote, other))
+            .map(|(index, _)| *index)
+    }
+
+    /// Sends `data` to every connected peer
+    pub fn broadcast(&self, protocol: ProtocolId, data: &[u8]) {
+        for link in self.peers.read().values() {
+            self.send_link(link, protocol, data.to_vec());
+        }
+    }
+
+    fn send(&self, protocol: ProtocolId, peer: PeerIndex, data: Vec<u8>) {
+        if let Some(link) = self.peers.read().get(&peer) {
+            self.send_link(link, protocol, data);
+        }
+    }
+
+    fn send_link(&self, link: &Link, protocol: ProtocolId, data: Vec<u8>) {
+        self.deliver(
+            &link.remote,
+            Event::Received(protocol, link.remote_index, data),
+        );
+    }
+
+    // Queues the event of `to` sent by this transport
+    fn deliver(&self, to: &MemoryTransport, event: Event) {
+        match to.queue {
+            Queue::Thread(ref sender) => {
+                let _ = sender.send(event);
+            }
+            Queue::Simulated(ref scheduler) => scheduler.deliver(self.id, to.id, event),
+        }
+    }
+
+    pub(crate) fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::SeqCst)
+    }
+
+    /// Disconnects the peers and stops handling events, the handlers are not
+    /// called anymore
+    pub fn stop(&self) {
+        for peer in self.connected_peers() {
+            self.disconnect(peer);
+        }
+        self.stopped.store(true, Ordering::SeqCst);
+        if let Queue::Thread(ref sender) = self.queue {
+            let _ = sender.send(Event::Stop);
+        }
+    }
+}
+
+fn initialize(transport: &Arc<MemoryTransport>) {
+    for (protocol, handler) in &transport.handlers {
+        handler.initialize(context(transport, *protocol));
+    }
+}
+
+fn dispatch(transport: &Arc<MemoryTransport>, receiver: &Receiver<Event>) {
+    initialize(transport);
+    while let Ok(event) = receiver.recv() {
+        if let Event::Stop = event {
+            break;
+        }
+        handle(transport, event);
+    }
+}
+
+/// Calls the handler of the event, unless the transport is stopped
+pub(crate) fn handle(transport: &Arc<MemoryTransport>, event: Event) {
+    if transport.is_stopped() {
+        return;
+    }
+    let protocol = match event {
+        Event::Connected(protocol, _)
+        | Event::Disconnected(protocol, _)
+        | Event::Received(protocol, _, _)
+        | Event::Timer(protocol, _) => protocol,
+        Event::Stop => return,
+    };
+    let handler = match transport.handlers.get(&protocol) {
+        Some(handler) => handler,
+        None => return,
+    };
+    let nc = context(transport, protocol);
+    match event {
+        Event::Connected(_, peer) => handler.connected(nc, peer),
+        Event::Disconnected(_, peer) => handler.disconnected(nc, peer),
+        Event::Received(_, peer, data) => handler.received(nc, peer, &data),
+        Event::Timer(_, token) => handler.timer_triggered(nc, token),
+        Event::Stop => unreachable!(),
+    }
+}
+
+fn context(
+    transport: &Arc<MemoryTransport>,
+    protocol: ProtocolId,
+) -> Box<dyn CKBProtocolContext> {
+    Box::new(MemoryContext {
+        protocol,
+        transport: Arc::clone(transport),
+    })
+}
+
+fn session(endpoint_role: Endpoint) -> Result<SessionInfo, NetworkError> {
+    Ok(SessionInfo {
+        peer: PeerInfo {
+            peer_id: random_peer_id()?,
+            endpoint_role,
+            last_ping_time: None,
+            connected_addr: "/ip4/127.0.0.1".to_multiaddr().expect("parse multiaddr"),
+            identify_info: None,
+        },
+        protocol_version: None,
+    })
+}
+
+struct MemoryContext {
+    protocol: ProtocolId,
+    transport: Arc<MemoryTransport>,
+}
+
+impl CKBProtocolContext for MemoryContext {
+    fn send(&self, peer: PeerIndex, data: Vec<u8>) -> Result<(), NetworkError> {
+        self.transport.send(self.protocol, peer, data);
+        Ok(())
+    }
+
+    fn send_protocol(
+        &self,
+        peer: PeerIndex,
+        protocol: ProtocolId,
+        data: Vec<u8>,
+    ) -> Result<(), NetworkError> {
+        self.transport.send(protocol, peer, data);
+        Ok(())
+    }
+
+    fn report_peer(&self, peer: PeerIndex, reason: Severity) {
+        debug!(target: "test", "report peer#{}: {:?}", peer, reason);
+    }
+
+    fn ban_peer(&self, peer: PeerIndex, _timeout: Duration) {
+        self.transport.disconnect(peer);
+    }
+
+    fn disconnect(&self, peer: PeerIndex) {
+        self.transport.disconnect(peer);
+    }
+
+    // The timer fires every `delay` until the transport stops
+    fn register_timer(&self, token: TimerToken, delay: Duration) -> Result<(), NetworkError> {
+        let protocol = self.protocol;
+        match self.transport.queue {
+            Queue::Thread(ref sender) => {
+                let sender = sender.clone();
+                thread::spawn(ckb_time::inherit(move || loop {
+                    thread::sleep(delay);
+                    if sender.send(Event::Timer(protocol, token)).is_err() {
+                        break;
+                    }
+                }));
+            }
+            Queue::Simulated(ref scheduler) => {
+                scheduler.register_timer(self.transport.id, protocol, token, delay)
+            }
+        }
+        Ok(())
+    }
+
+    fn session_info(&self, peer: PeerIndex) -> Option<SessionInfo> {
+        self.transport
+            .peers
+            .read()
+            .get(&peer)
+            .map(|link| link.session.clone())
+    }
+
+    fn protocol_version(&self, _peer: PeerIndex, _protocol: ProtocolId) -> Option<u8> {
+        Some(1)
+    }
+
+    fn protocol_id(&self) -> ProtocolId {
+        self.protocol
+    }
+
+    fn connected_peers(&self) -> Vec<PeerIndex> {
+        self.transport.connected_peers()
+    }
+}
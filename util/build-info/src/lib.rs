@@ -1,4 +1,5 @@
 use std::env;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 #[macro_export]
 macro_rules! get_version {
@@ -10,6 +11,18 @@ macro_rules! get_version {
         let host_compiler = $crate::get_channel();
         let commit_describe = option_env!("COMMIT_DESCRIBE").map(|s| s.to_string());
         let commit_date = option_env!("COMMIT_DATE").map(|s| s.to_string());
+        let commit_hash = option_env!("COMMIT_HASH")
+            .filter(|s| !s.is_empty())
+            .map(|s| s.to_string());
+        let build_time = option_env!("BUILD_TIME")
+            .filter(|s| !s.is_empty())
+            .map(|s| s.to_string());
+        let features = option_env!("BUILD_FEATURES")
+            .unwrap_or_default()
+            .split(',')
+            .filter(|s| !s.is_empty())
+            .map(|s| s.to_string())
+            .collect();
         Version {
             major,
             minor,
@@ -17,11 +30,17 @@ macro_rules! get_version {
             host_compiler,
             commit_describe,
             commit_date,
+            commit_hash,
+            build_time,
+            features,
         }
     }};
 }
 
 // some code taken and adapted from RLS and cargo
+/// The version of the crate expanding [get_version], with the build details
+/// its build script forwards, see `build.rs` of ckb
+#[derive(Clone, Debug)]
 pub struct Version {
     pub major: u8,
     pub minor: u8,
@@ -29,6 +48,11 @@ pub struct Version {
     pub host_compiler: Option<String>,
     pub commit_describe: Option<String>,
     pub commit_date: Option<String>,
+    pub commit_hash: Option<String>,
+    /// UTC, as `2019-01-31T08:00:00Z`
+    pub build_time: Option<String>,
+    /// The cargo features enabled
+    pub features: Vec<String>,
 }
 
 impl Version {
@@ -36,8 +60,19 @@ impl Version {
         format!("{}.{}.{}", self.major, self.minor, self.patch)
     }
 
+    /// The version followed by a line for each build detail known
     pub fn long(&self) -> String {
-        format!("{}", self)
+        let mut long = format!("{}", self);
+        if let Some(ref commit_hash) = self.commit_hash {
+            long.push_str(&format!("\ncommit: {}", commit_hash));
+        }
+        if let Some(ref build_time) = self.build_time {
+            long.push_str(&format!("\nbuilt: {}", build_time));
+        }
+        if !self.features.is_empty() {
+            long.push_str(&format!("\nfeatures: {}", self.features.join(", ")));
+        }
+        long
     }
 }
 
@@ -85,3 +120,77 @@ pub fn get_commit_date() -> Option<String> {
         .ok()
         .and_then(|r| String::from_utf8(r.stdout).ok())
 }
+
+pub fn get_commit_hash() -> Option<String> {
+    std::process::Command::new("git")
+        .args(&["rev-parse", "HEAD"])
+        .output()
+        .ok()
+        .and_then(|r| String::from_utf8(r.stdout).ok())
+        .map(|hash| hash.trim().to_string())
+}
+
+/// The time of the build, `SOURCE_DATE_EPOCH` for reproducible builds
+pub fn get_build_time() -> String {
+    let secs = env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|epoch| epoch.parse().ok())
+        .unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|duration| duration.as_secs())
+                .unwrap_or(0)
+        });
+    format_utc(secs)
+}
+
+/// The features of the crate being built, from its build script
+pub fn get_features() -> Vec<String> {
+    let mut features: Vec<_> = env::vars()
+        .filter_map(|(key, _)| {
+            if key.starts_with("CARGO_FEATURE_") {
+                Some(key["CARGO_FEATURE_".len()..].to_lowercase().replace('_', "-"))
+            } else {
+                None
+            }
+        })
+        .collect();
+    features.sort();
+    features
+}
+
+// Seconds since the unix epoch as `YYYY-MM-DDThh:mm:ssZ`, the date from the
+// days with the algorithm of http://howardhinnant.github.io/date_algorithms.html
+fn format_utc(secs: u64) -> String {
+    let (days, secs) = (secs / 86_400, secs % 86_400);
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs % 3600 / 60,
+        secs % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_build_time() {
+        assert_eq!(format_utc(0), "1970-01-01T00:00:00Z");
+        assert_eq!(format_utc(951_782_400), "2000-02-29T00:00:00Z");
+        assert_eq!(format_utc(1_548_921_600 + 3661), "2019-01-31T09:01:01Z");
+    }
+}
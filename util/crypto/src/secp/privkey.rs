@@ -41,6 +41,17 @@ impl Privkey {
         Ok(Pubkey::from(pubkey))
     }
 
+    /// The key plus `tweak` modulo the curve order, which BIP32 derives the
+    /// child keys with. Fails when the tweak is not below the order or the
+    /// sum is zero.
+    pub fn tweak_add(&self, tweak: &H256) -> Result<Privkey, Error> {
+        let context = &SECP256K1;
+        let mut privkey = key::SecretKey::from_slice(context, &self.inner.as_bytes())?;
+        let tweak = key::SecretKey::from_slice(context, tweak.as_bytes())?;
+        privkey.add_assign(context, &tweak)?;
+        Ok(Privkey::from(privkey))
+    }
+
     pub fn from_slice(key: &[u8]) -> Self {
         assert_eq!(32, key.len(), "should provide 32-byte length slice");
 
@@ -1,20 +1,176 @@
-use std::fs;
+//! The data directory of a node:
+//!
+//! - `db/`: the chain db
+//! - `network/`: the network key and peer store
+//! - `logs/`: the log files
+//! - `tmp/`: scratch files, emptied whenever the directory is locked
+//! - `LOCK`: locked by the process using the directory, holding its pid
+//! - `CRASHED`: left by a process which panicked, telling why, until its
+//!   stores are checked
+//!
+//! The other stores, like `indexer/`, `ancient/` and the `backups/` written
+//! over RPC, are made by [DataDir::join] when first used.
+
+use fs2::FileExt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
 use std::path::{Path, PathBuf};
+use std::process;
+
+const DB: &str = "db";
+const NETWORK: &str = "network";
+const LOGS: &str = "logs";
+const TMP: &str = "tmp";
+const BACKUPS: &str = "backups";
+const LOCK: &str = "LOCK";
+const CRASHED: &str = "CRASHED";
 
 #[derive(Debug, PartialEq, Clone)]
-pub struct Directories {
-    pub base: PathBuf,
+pub struct DataDir {
+    base: PathBuf,
 }
 
-impl Directories {
+/// The lock of a data directory, released when dropped or when the process
+/// exits, even killed
+#[derive(Debug)]
+pub struct DataDirLock {
+    _file: File,
+}
+
+impl DataDir {
     pub fn new<P: AsRef<Path>>(base: P) -> Self {
         let base = base.as_ref().to_path_buf();
-        Directories { base }
+        DataDir { base }
+    }
+
+    /// Creates the directories of the layout which are missing
+    pub fn create(&self) -> io::Result<()> {
+        for name in &[DB, NETWORK, LOGS, TMP] {
+            fs::create_dir_all(self.base.join(name))?;
+        }
+        Ok(())
+    }
+
+    pub fn base(&self) -> &Path {
+        &self.base
+    }
+
+    pub fn db(&self) -> PathBuf {
+        self.join(DB)
+    }
+
+    pub fn network(&self) -> PathBuf {
+        self.join(NETWORK)
+    }
+
+    pub fn logs(&self) -> PathBuf {
+        self.join(LOGS)
+    }
+
+    pub fn tmp(&self) -> PathBuf {
+        self.join(TMP)
+    }
+
+    pub fn backups(&self) -> PathBuf {
+        self.join(BACKUPS)
     }
 
+    /// The directory `path` in the data directory, created if missing
     pub fn join<P: AsRef<Path>>(&self, path: P) -> PathBuf {
         let result = self.base.join(path.as_ref());
         fs::create_dir_all(&result).expect("Unable to create dir");
         result
     }
+
+    /// Records that the process using the directory crashed, for `reason`
+    pub fn mark_crashed(&self, reason: &str) -> io::Result<()> {
+        fs::write(self.base.join(CRASHED), reason)
+    }
+
+    /// Why the last process using the directory crashed, until cleared
+    pub fn crashed(&self) -> Option<String> {
+        fs::read_to_string(self.base.join(CRASHED)).ok()
+    }
+
+    pub fn clear_crashed(&self) -> io::Result<()> {
+        let path = self.base.join(CRASHED);
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+
+    /// Locks the directory for this process until the lock is dropped, fails
+    /// when another process holds it
+    pub fn lock(&self) -> io::Result<DataDirLock> {
+        fs::create_dir_all(&self.base)?;
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .open(self.base.join(LOCK))?;
+        if file.try_lock_exclusive().is_err() {
+            let mut owner = String::new();
+            let _ = file.read_to_string(&mut owner);
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                format!(
+                    "{} is used by another process {}",
+                    self.base.display(),
+                    owner.trim()
+                ),
+            ));
+        }
+        file.set_len(0)?;
+        write!(file, "{}", process::id())?;
+
+        // Nobody else uses the scratch files now
+        let tmp = self.base.join(TMP);
+        if tmp.exists() {
+            fs::remove_dir_all(&tmp)?;
+        }
+        fs::create_dir_all(&tmp)?;
+        Ok(DataDirLock { _file: file })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lock_data_dir() {
+        let tmp_dir = tempfile::Builder::new()
+            .prefix("lock_data_dir")
+            .tempdir()
+            .unwrap();
+        let data_dir = DataDir::new(tmp_dir.path().join("data"));
+        data_dir.create().unwrap();
+        assert!(data_dir.base().join("db").is_dir());
+        File::create(data_dir.tmp().join("scratch")).unwrap();
+
+        let lock = data_dir.lock().unwrap();
+        assert!(!data_dir.tmp().join("scratch").exists());
+        let pid = fs::read_to_string(data_dir.base().join(LOCK)).unwrap();
+        assert_eq!(pid, process::id().to_string());
+        assert!(data_dir.lock().is_err());
+
+        drop(lock);
+        assert!(data_dir.lock().is_ok());
+    }
+
+    #[test]
+    fn crash_marker() {
+        let tmp_dir = tempfile::Builder::new()
+            .prefix("crash_marker")
+            .tempdir()
+            .unwrap();
+        let data_dir = DataDir::new(tmp_dir.path());
+        assert_eq!(data_dir.crashed(), None);
+        data_dir.mark_crashed("panicked").unwrap();
+        assert_eq!(data_dir.crashed(), Some("panicked".to_owned()));
+        data_dir.clear_crashed().unwrap();
+        assert_eq!(data_dir.crashed(), None);
+        data_dir.clear_crashed().unwrap();
+    }
 }
@@ -1,5 +1,6 @@
 use crate::format::Format;
 use crate::iter::ChainIterator;
+use ckb_chain::dump::export_blocks;
 use ckb_shared::index::ChainIndex;
 use ckb_shared::shared::{ChainProvider, Shared};
 #[cfg(feature = "progress_bar")]
@@ -43,10 +44,21 @@ impl<CI: ChainIndex> Export<CI> {
         fs::create_dir_all(&self.target)?;
         match self.format {
             Format::Json => self.write_to_json(),
-            _ => Ok(()),
+            Format::Binary => self.write_to_binary(),
         }
     }
 
+    pub fn write_to_binary(self) -> Result<(), Box<Error>> {
+        let tip_number = self.shared.chain_state().read().tip_number();
+        export_blocks(
+            &self.shared,
+            0..tip_number + 1,
+            &self.target.join(self.file_name()),
+        )
+        .map_err(|e| format!("{:?}", e))?;
+        Ok(())
+    }
+
     #[cfg(not(feature = "progress_bar"))]
     pub fn write_to_json(self) -> Result<(), Box<Error>> {
         let f = fs::OpenOptions::new()
@@ -1,6 +1,9 @@
 use crate::format::Format;
 use ckb_chain::chain::ChainController;
+use ckb_chain::dump::import_blocks;
 use ckb_core::block::Block;
+use ckb_shared::index::ChainIndex;
+use ckb_shared::shared::Shared;
 #[cfg(feature = "progress_bar")]
 use indicatif::{ProgressBar, ProgressStyle};
 use serde_json;
@@ -12,19 +15,26 @@ use std::path::PathBuf;
 use std::sync::Arc;
 
 /// Export block date from file to database.
-pub struct Import {
+pub struct Import<CI> {
     /// source file contains block data
     source: PathBuf,
     chain: ChainController,
+    shared: Shared<CI>,
     /// source file format
     format: Format,
 }
 
-impl Import {
-    pub fn new(chain: ChainController, format: Format, source: PathBuf) -> Self {
+impl<CI: ChainIndex> Import<CI> {
+    pub fn new(
+        chain: ChainController,
+        shared: Shared<CI>,
+        format: Format,
+        source: PathBuf,
+    ) -> Self {
         Import {
             format,
             chain,
+            shared,
             source,
         }
     }
@@ -32,10 +42,15 @@ impl Import {
     pub fn execute(self) -> Result<(), Box<Error>> {
         match self.format {
             Format::Json => self.read_from_json(),
-            _ => Ok(()),
+            Format::Binary => self.read_from_binary(),
         }
     }
 
+    pub fn read_from_binary(&self) -> Result<(), Box<Error>> {
+        import_blocks(&self.chain, &self.shared, &self.source).map_err(|e| format!("{:?}", e))?;
+        Ok(())
+    }
+
     #[cfg(not(feature = "progress_bar"))]
     pub fn read_from_json(&self) -> Result<(), Box<Error>> {
         let f = fs::File::open(&self.source)?;
@@ -157,6 +157,30 @@ impl<'a> From<&'a CoreTransaction> for Transaction {
     }
 }
 
+/// A transaction and where it is: `pending` or `proposed` in the pool, or
+/// `committed` in the main chain block `block_hash`. The fields of the
+/// transaction stay at the top level, as `get_transaction` returned them
+/// before it had a status.
+#[derive(Clone, Serialize, PartialEq, Eq, Debug)]
+pub struct TransactionWithStatus {
+    #[serde(flatten)]
+    pub transaction: Transaction,
+    pub status: String,
+    pub block_hash: Option<H256>,
+    /// Main chain blocks from the committing one to the tip
+    pub confirmations: Option<u64>,
+}
+
+/// A transaction spending or creating cells of a subscribed lock hash, added
+/// to the pool or committed in the block `block_hash`, or no longer committed
+/// when the block is `detached` from the main chain
+#[derive(Clone, Serialize, PartialEq, Eq, Debug)]
+pub struct LockHashTransaction {
+    pub tx_hash: H256,
+    pub block_hash: Option<H256>,
+    pub detached: bool,
+}
+
 impl From<Transaction> for CoreTransaction {
     fn from(json: Transaction) -> CoreTransaction {
         let Transaction {
@@ -0,0 +1,20 @@
+use crate::blockchain::Header;
+use ckb_core::BlockNumber;
+use numext_fixed_uint::U256;
+use serde_derive::{Deserialize, Serialize};
+
+#[derive(Clone, Default, Serialize, Deserialize, PartialEq, Eq, Hash, Debug)]
+pub struct BlockchainInfo {
+    pub tip_header: Header,
+    pub total_difficulty: U256,
+    pub total_transactions_count: u64,
+    pub live_cells_count: u64,
+    pub average_block_intervals: Vec<BlockInterval>,
+}
+
+/// Average interval in milliseconds of the latest `blocks` blocks
+#[derive(Clone, Default, Serialize, Deserialize, PartialEq, Eq, Hash, Debug)]
+pub struct BlockInterval {
+    pub blocks: BlockNumber,
+    pub average: u64,
+}
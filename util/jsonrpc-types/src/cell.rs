@@ -12,6 +12,8 @@ pub struct CellOutputWithOutPoint {
     pub out_point: OutPoint,
     pub capacity: Capacity,
     pub lock: H256,
+    /// The lock as an address of the network of the node
+    pub address: String,
 }
 
 #[derive(Serialize)]
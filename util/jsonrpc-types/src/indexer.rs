@@ -0,0 +1,35 @@
+use ckb_core::{BlockNumber, Capacity};
+use numext_fixed_hash::H256;
+use serde_derive::{Deserialize, Serialize};
+
+/// A transaction creating or spending cells of a lock, the `index`th
+/// committed in its block
+#[derive(Clone, Default, Serialize, Deserialize, PartialEq, Eq, Hash, Debug)]
+pub struct TransactionPoint {
+    pub tx_hash: H256,
+    pub block_number: BlockNumber,
+    pub index: u32,
+}
+
+/// The live cells of a watched lock
+#[derive(Clone, Default, Serialize, Deserialize, PartialEq, Eq, Hash, Debug)]
+pub struct Balance {
+    pub capacity: Capacity,
+    pub cells_count: u64,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize, PartialEq, Eq, Hash, Debug)]
+pub struct WatchedAccount {
+    pub lock_hash: H256,
+    /// The lock as an address of the network of the node
+    pub address: String,
+    pub capacity: Capacity,
+    pub cells_count: u64,
+}
+
+/// The main chain block the lock hash index is up to
+#[derive(Clone, Default, Serialize, Deserialize, PartialEq, Eq, Hash, Debug)]
+pub struct LockHashIndexState {
+    pub block_number: BlockNumber,
+    pub block_hash: H256,
+}
@@ -1,15 +1,23 @@
 mod block_template;
 mod blockchain;
+mod blockchain_info;
 mod bytes;
 mod cell;
+mod indexer;
 mod local_node;
+mod pool;
 mod proposal_short_id;
 
 pub use self::block_template::{
     BlockTemplate, CellbaseTemplate, TransactionTemplate, UncleTemplate,
 };
-pub use self::blockchain::{Block, Header, OutPoint, Transaction, UncleBlock};
+pub use self::blockchain::{
+    Block, Header, LockHashTransaction, OutPoint, Transaction, TransactionWithStatus, UncleBlock,
+};
+pub use self::blockchain_info::{BlockInterval, BlockchainInfo};
 pub use self::bytes::Bytes;
 pub use self::cell::{CellOutputWithOutPoint, CellWithStatus};
-pub use self::local_node::{LocalNode, NodeAddress};
+pub use self::indexer::{Balance, LockHashIndexState, TransactionPoint, WatchedAccount};
+pub use self::local_node::{LocalNode, Node, NodeAddress};
+pub use self::pool::{DryRunResult, TxPoolInfo};
 pub use jsonrpc_core::types::{error, id, params, request, response, version};
@@ -3,10 +3,27 @@ use serde_derive::{Deserialize, Serialize};
 #[derive(Clone, Default, Serialize, Deserialize, PartialEq, Eq, Hash, Debug)]
 pub struct LocalNode {
     pub version: String,
+    /// The git commit the node is built at
+    pub commit_hash: Option<String>,
+    /// UTC, as `2019-01-31T08:00:00Z`
+    pub build_time: Option<String>,
+    /// The cargo features the node is built with
+    pub features: Vec<String>,
     pub node_id: String,
     pub addresses: Vec<NodeAddress>,
 }
 
+/// A connected peer
+#[derive(Clone, Default, Serialize, Deserialize, PartialEq, Eq, Hash, Debug)]
+pub struct Node {
+    pub node_id: String,
+    pub address: String,
+    pub is_outbound: bool,
+    /// Client version the peer identified with, if it did
+    pub version: Option<String>,
+    pub last_ping_time: Option<u64>,
+}
+
 #[derive(Clone, Default, Serialize, Deserialize, PartialEq, Eq, Hash, Debug)]
 pub struct NodeAddress {
     pub address: String,
@@ -0,0 +1,20 @@
+use ckb_core::Cycle;
+use serde_derive::{Deserialize, Serialize};
+
+/// Number of transactions at each stage of the pool
+#[derive(Clone, Default, Serialize, Deserialize, PartialEq, Eq, Hash, Debug)]
+pub struct TxPoolInfo {
+    pub pending: u32,
+    pub proposed: u32,
+    pub mineable: u32,
+    pub orphan: u32,
+    pub conflict: u32,
+    /// Time in milliseconds the mineable transactions last changed
+    pub last_txs_updated_at: u64,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize, PartialEq, Eq, Hash, Debug)]
+pub struct DryRunResult {
+    /// Cycles taken by the scripts of the transaction
+    pub cycles: Cycle,
+}
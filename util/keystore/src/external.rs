@@ -0,0 +1,159 @@
+//! A [Signer] delegating to an external program, like the bridge of a
+//! hardware wallet, so that the private keys never enter the node process.
+//!
+//! The program is run with its arguments followed by a command, and answers
+//! on its standard output:
+//!
+//! - `pubkeys`: the public keys it holds, one hex key of 64 bytes per line
+//! - `sign <pubkey> <message>`: the hex 65 bytes recoverable signature of
+//!   the hex 32 bytes message by the key of the hex pubkey
+//!
+//! A non zero exit status fails the command, the standard error telling why.
+
+use ckb_core::signing::{Signer, SigningError};
+use crypto::secp::{Pubkey, Signature};
+use numext_fixed_hash::{H256, H512};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::str::FromStr;
+
+pub struct ExternalSigner {
+    program: PathBuf,
+    args: Vec<String>,
+    pubkeys: Vec<Pubkey>,
+}
+
+impl ExternalSigner {
+    /// Asks `program` run with `args` which keys it holds
+    pub fn new<P: AsRef<Path>>(program: P, args: Vec<String>) -> Result<Self, SigningError> {
+        let mut signer = ExternalSigner {
+            program: program.as_ref().to_path_buf(),
+            args,
+            pubkeys: Vec::new(),
+        };
+        signer.pubkeys = signer
+            .run(&["pubkeys"])?
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| {
+                H512::from_str(line.trim())
+                    .map(Pubkey::from)
+                    .map_err(|_| SigningError::Signer(format!("invalid pubkey {}", line)))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(signer)
+    }
+
+    pub fn pubkeys(&self) -> &[Pubkey] {
+        &self.pubkeys
+    }
+
+    fn run(&self, command: &[&str]) -> Result<String, SigningError> {
+        let output = Command::new(&self.program)
+            .args(&self.args)
+            .args(command)
+            .output()
+            .map_err(|err| {
+                SigningError::Signer(format!("run {}: {}", self.program.display(), err))
+            })?;
+        if !output.status.success() {
+            return Err(SigningError::Signer(format!(
+                "{} {}: {}",
+                self.program.display(),
+                command[0],
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        String::from_utf8(output.stdout)
+            .map_err(|_| SigningError::Signer(format!("{} output not utf8", command[0])))
+    }
+}
+
+impl Signer for ExternalSigner {
+    fn has_key(&self, pubkey: &Pubkey) -> bool {
+        self.pubkeys.contains(pubkey)
+    }
+
+    fn sign(&self, pubkey: &Pubkey, message: &H256) -> Result<Signature, SigningError> {
+        let output = self.run(&["sign", &pubkey.to_string(), &format!("{:x}", message)])?;
+        Signature::from_str(output.trim())
+            .map_err(|_| SigningError::Signer(format!("invalid signature {}", output.trim())))
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use ckb_core::signing::{
+        default_unlock_script, sign_inputs_with, signing_message, verify_inputs,
+    };
+    use ckb_core::transaction::{CellInput, CellOutput, OutPoint, TransactionBuilder};
+    use crypto::secp::Generator;
+    use faster_hex::hex_string;
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+
+    // A signer program holding `pubkey` and answering `signature` to every
+    // sign command
+    fn signer_script(dir: &Path, pubkey: &Pubkey, signature: &str) -> PathBuf {
+        let path = dir.join("signer");
+        let script = format!(
+            "#!/bin/sh\ncase $1 in\n  pubkeys) echo {} ;;\n  sign) echo {} ;;\n  \
+             *) echo unknown command >&2; exit 1 ;;\nesac\n",
+            pubkey, signature
+        );
+        fs::write(&path, script).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        path
+    }
+
+    #[test]
+    fn sign_with_external_signer() {
+        let dir = tempfile::Builder::new()
+            .prefix("external_signer")
+            .tempdir()
+            .unwrap();
+        let privkey = Generator::new().random_privkey();
+        let pubkey = privkey.pubkey().unwrap();
+        let transaction = TransactionBuilder::default()
+            .input(CellInput::new(
+                OutPoint::new(H256::zero(), 0),
+                default_unlock_script(&pubkey),
+            ))
+            .build();
+        let signature = privkey
+            .sign_recoverable(&signing_message(&transaction))
+            .unwrap();
+
+        let signature = hex_string(&signature.serialize()).unwrap();
+        let program = signer_script(dir.path(), &pubkey, &signature);
+        let signer = ExternalSigner::new(&program, Vec::new()).unwrap();
+        assert_eq!(signer.pubkeys(), &[pubkey][..]);
+        let signed = sign_inputs_with(&transaction, &signer).unwrap();
+        assert_eq!(verify_inputs(&signed), Ok(()));
+
+        // The signature of another message is refused
+        let other = TransactionBuilder::default()
+            .transaction(transaction)
+            .output(CellOutput::new(50, vec![], H256::zero(), None))
+            .build();
+        assert_eq!(
+            sign_inputs_with(&other, &signer),
+            Err(SigningError::InvalidSignatures(0))
+        );
+    }
+
+    #[test]
+    fn failing_external_signer() {
+        let dir = tempfile::Builder::new()
+            .prefix("failing_external_signer")
+            .tempdir()
+            .unwrap();
+        let pubkey = Generator::new().random_privkey().pubkey().unwrap();
+        let program = signer_script(dir.path(), &pubkey, "00");
+        let signer = ExternalSigner::new(&program, Vec::new()).unwrap();
+        assert!(signer.sign(&pubkey, &H256::zero()).is_err());
+        assert!(ExternalSigner::new(&program, vec!["--unknown".to_owned()]).is_err());
+        assert!(ExternalSigner::new(dir.path().join("missing"), Vec::new()).is_err());
+    }
+}
@@ -0,0 +1,232 @@
+//! [BIP32](https://github.com/bitcoin/bips/blob/master/bip-0032.mediawiki)
+//! derivation of the private keys of a seed, so that one seed backs up all
+//! the keys of a wallet, and the seeds of the
+//! [BIP39](https://github.com/bitcoin/bips/blob/master/bip-0039.mediawiki)
+//! mnemonics the wallets back up instead of the seed.
+
+use crypto::secp::Privkey;
+use hmac::{Hmac, Mac};
+use numext_fixed_hash::H256;
+use sha2::Sha512;
+use std::fmt;
+use std::str::FromStr;
+use unicode_normalization::UnicodeNormalization;
+
+/// Indexes from this one derive hardened keys, which need the parent private key
+pub const HARDENED: u32 = 1 << 31;
+/// The coin type of CKB in [SLIP44](https://github.com/satoshilabs/slips/blob/master/slip-0044.md)
+pub const COIN_TYPE: u32 = 309;
+
+/// The word counts of the BIP39 mnemonics
+pub const MNEMONIC_WORDS: &[usize] = &[12, 15, 18, 21, 24];
+
+const MASTER_KEY: &[u8] = b"Bitcoin seed";
+const MNEMONIC_SALT: &str = "mnemonic";
+const MNEMONIC_ROUNDS: u32 = 2048;
+
+/// A private key with the chain code deriving its children
+pub struct ExtendedPrivkey {
+    privkey: Privkey,
+    chain_code: [u8; 32],
+}
+
+impl ExtendedPrivkey {
+    /// The master key of `seed`, None when the seed makes an invalid key
+    pub fn from_seed(seed: &[u8]) -> Option<ExtendedPrivkey> {
+        let (key, chain_code) = hmac_sha512(MASTER_KEY, seed);
+        let privkey = Privkey::from(key);
+        privkey.pubkey().ok()?;
+        Some(ExtendedPrivkey {
+            privkey,
+            chain_code,
+        })
+    }
+
+    pub fn privkey(&self) -> &Privkey {
+        &self.privkey
+    }
+
+    /// The child of `index`, None in the rare case BIP32 skips it
+    pub fn derive_child(&self, index: u32) -> Option<ExtendedPrivkey> {
+        let mut data = Vec::with_capacity(37);
+        if index >= HARDENED {
+            data.push(0);
+            data.extend_from_slice(self.privkey.as_bytes());
+        } else {
+            data.extend_from_slice(&self.privkey.pubkey().ok()?.serialize());
+        }
+        data.extend_from_slice(&index.to_be_bytes());
+        let (tweak, chain_code) = hmac_sha512(&self.chain_code, &data);
+        let privkey = self.privkey.tweak_add(&tweak).ok()?;
+        Some(ExtendedPrivkey {
+            privkey,
+            chain_code,
+        })
+    }
+
+    pub fn derive_path(&self, path: &DerivationPath) -> Option<ExtendedPrivkey> {
+        path.0
+            .iter()
+            .try_fold(self.clone_key(), |key, index| key.derive_child(*index))
+    }
+
+    fn clone_key(&self) -> ExtendedPrivkey {
+        ExtendedPrivkey {
+            privkey: Privkey::from(H256::clone(&self.privkey)),
+            chain_code: self.chain_code,
+        }
+    }
+}
+
+/// The 64 bytes seed of `mnemonic` and its `passphrase`, empty for most
+/// wallets, None when the mnemonic has not a BIP39 word count. The words are
+/// neither checked against a wordlist nor their checksum verified, so that
+/// the mnemonics of every language import, and a mistyped word imports
+/// another seed: check the keys derived before funding them.
+pub fn mnemonic_to_seed(mnemonic: &str, passphrase: &str) -> Option<Vec<u8>> {
+    let words: Vec<_> = mnemonic.split_whitespace().collect();
+    if !MNEMONIC_WORDS.contains(&words.len()) {
+        return None;
+    }
+    let mnemonic: String = words.join(" ").nfkd().collect();
+    let salt: String = format!("{}{}", MNEMONIC_SALT, passphrase).nfkd().collect();
+    Some(pbkdf2_hmac_sha512(
+        mnemonic.as_bytes(),
+        salt.as_bytes(),
+        MNEMONIC_ROUNDS,
+    ))
+}
+
+// PBKDF2 of a single block, the 64 bytes of one HMAC-SHA512
+fn pbkdf2_hmac_sha512(password: &[u8], salt: &[u8], rounds: u32) -> Vec<u8> {
+    let prf = Hmac::<Sha512>::new_varkey(password).expect("any key length");
+    let mut mac = prf.clone();
+    mac.input(salt);
+    mac.input(&1u32.to_be_bytes());
+    let mut block = mac.result().code();
+    let mut output = block.to_vec();
+    for _ in 1..rounds {
+        let mut mac = prf.clone();
+        mac.input(&block);
+        block = mac.result().code();
+        for (byte, xor) in output.iter_mut().zip(block.iter()) {
+            *byte ^= xor;
+        }
+    }
+    output
+}
+
+fn hmac_sha512(key: &[u8], data: &[u8]) -> (H256, [u8; 32]) {
+    let mut mac = Hmac::<Sha512>::new_varkey(key).expect("any key length");
+    mac.input(data);
+    let output = mac.result().code();
+    let mut chain_code = [0; 32];
+    chain_code.copy_from_slice(&output[32..]);
+    (H256::from_slice(&output[..32]).expect("32 bytes"), chain_code)
+}
+
+/// The indexes from the master key to a child, written as `m/44'/309'/0'/0/1`,
+/// where `'` marks a hardened index
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DerivationPath(Vec<u32>);
+
+impl DerivationPath {
+    /// The path of the receiving key `index` of the first account,
+    /// `m/44'/309'/0'/0/index` as in BIP44
+    pub fn receiving(index: u32) -> DerivationPath {
+        DerivationPath(vec![44 | HARDENED, COIN_TYPE | HARDENED, HARDENED, 0, index])
+    }
+}
+
+impl FromStr for DerivationPath {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split('/');
+        if parts.next() != Some("m") {
+            return Err(format!("{} does not start with m", s));
+        }
+        parts
+            .map(|part| {
+                let (index, hardened) = if part.ends_with('\'') {
+                    (&part[..part.len() - 1], true)
+                } else {
+                    (part, false)
+                };
+                match index.parse::<u32>() {
+                    Ok(index) if index < HARDENED => {
+                        Ok(if hardened { index | HARDENED } else { index })
+                    }
+                    _ => Err(format!("invalid index {}", part)),
+                }
+            })
+            .collect::<Result<_, _>>()
+            .map(DerivationPath)
+    }
+}
+
+impl fmt::Display for DerivationPath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "m")?;
+        for index in &self.0 {
+            if *index >= HARDENED {
+                write!(f, "/{}'", index - HARDENED)?;
+            } else {
+                write!(f, "/{}", index)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Test vector 1 of BIP32
+    #[test]
+    fn derive_test_vector() {
+        let seed = (0u8..16).collect::<Vec<_>>();
+        let master = ExtendedPrivkey::from_seed(&seed).unwrap();
+        assert_eq!(
+            master.privkey().to_string(),
+            "e8f32e723decf4051aefac8e2c93c9c5b214313817cdb01a1494b917c8436b35"
+        );
+
+        let path: DerivationPath = "m/0'/1".parse().unwrap();
+        assert_eq!(path.to_string(), "m/0'/1");
+        let child = master.derive_path(&path).unwrap();
+        assert_eq!(
+            child.privkey().to_string(),
+            "3c6cb8d0f6a264c91ea8b5030fadaa8e538b020f0a387421a12de9319dc93368"
+        );
+    }
+
+    // Test vector of the Trezor implementation of BIP39
+    #[test]
+    fn mnemonic_test_vector() {
+        let mnemonic = "abandon abandon abandon abandon abandon abandon abandon abandon abandon \
+                        abandon abandon about";
+        let seed = mnemonic_to_seed(mnemonic, "TREZOR").unwrap();
+        assert_eq!(
+            faster_hex::hex_string(&seed).unwrap(),
+            "c55257c360c07c72029aebc1b53c05ed0362ada38ead3e3e9efa3708e53495531f09a6987599d18264c1\
+             e1c92f2cf141630c7a3c4ab7c81b2f001698e7463b04"
+        );
+        // The words may be separated by any whitespace
+        let spaced = mnemonic.replace(" ", " \n ");
+        assert_eq!(mnemonic_to_seed(&spaced, "TREZOR"), Some(seed));
+        assert_eq!(mnemonic_to_seed("abandon about", ""), None);
+    }
+
+    #[test]
+    fn parse_derivation_path() {
+        assert_eq!(
+            "m/44'/309'/0'/0/7".parse::<DerivationPath>(),
+            Ok(DerivationPath::receiving(7))
+        );
+        assert!("44'/0".parse::<DerivationPath>().is_err());
+        assert!("m/2147483648".parse::<DerivationPath>().is_err());
+        assert!("m/a".parse::<DerivationPath>().is_err());
+    }
+}
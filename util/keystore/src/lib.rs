@@ -0,0 +1,562 @@
+//! # The Keystore Library
+//!
+//! The secp256k1 keys of a node, the ones signing its transactions and
+//! locking its mining rewards. Each key is a JSON file in the keystore
+//! directory, named by the [pubkey_hash] of the key:
+//!
+//! ```json
+//! {
+//!   "version": 1,
+//!   "pubkey_hash": "0x...",
+//!   "crypto": {
+//!     "cipher": "aes-128-ctr",
+//!     "cipherparams": { "iv": "..." },
+//!     "ciphertext": "...",
+//!     "kdf": "scrypt",
+//!     "kdfparams": { "dklen": 32, "n": 262144, "r": 8, "p": 1, "salt": "..." },
+//!     "mac": "..."
+//!   }
+//! }
+//! ```
+//!
+//! The private key is encrypted with the first half of the key derived from
+//! the password, the mac is the blake2b hash of the second half and the
+//! ciphertext, which tells a wrong password apart.
+//!
+//! A seed is kept the same way in `<pubkey hash>.seed.json`, named by the
+//! pubkey hash of its master key, and derives the receiving keys of a wallet
+//! with [hd].
+//!
+//! The keys which must not be kept by the node at all sign through an
+//! [external] program instead, like the bridge of a hardware wallet.
+
+pub mod external;
+pub mod hd;
+
+use crate::hd::{DerivationPath, ExtendedPrivkey};
+use aes_ctr::stream_cipher::generic_array::GenericArray;
+use aes_ctr::stream_cipher::{NewStreamCipher, SyncStreamCipher};
+use aes_ctr::Aes128Ctr;
+use constant_time_eq::constant_time_eq;
+use crypto::secp::{Generator, Privkey, Pubkey};
+use faster_hex::{hex_decode, hex_string};
+use hash::blake2b;
+use numext_fixed_hash::H256;
+use scrypt::{scrypt, ScryptParams};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use serde_derive::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::{error, fmt};
+
+const VERSION: u32 = 1;
+const CIPHER: &str = "aes-128-ctr";
+const KDF: &str = "scrypt";
+const DKLEN: usize = 32;
+const SALT_LEN: usize = 32;
+const KEY_LEN: usize = 32;
+/// The seed lengths allowed by BIP32
+pub const MIN_SEED_LEN: usize = 16;
+pub const MAX_SEED_LEN: usize = 64;
+/// scrypt takes about a second and 256MB with n = 2^18
+pub const DEFAULT_SCRYPT_LOG_N: u8 = 18;
+const SCRYPT_R: u32 = 8;
+const SCRYPT_P: u32 = 1;
+/// The kdf params of a key file are bounded, so that a crafted file cannot
+/// take the memory or the time of the node: scrypt takes 128 * r * n bytes
+/// and p times the time of one pass
+const MAX_SCRYPT_MEMORY: u64 = 1 << 30;
+const MAX_SCRYPT_P: u32 = 16;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    /// The key file is not a keystore file of a supported version
+    InvalidFormat(String),
+    /// The private key is not a valid secp256k1 key
+    InvalidKey,
+    /// The seed is too short or too long, or derives an invalid key
+    InvalidSeed,
+    InvalidPassword,
+    NotFound(H256),
+    AlreadyExists(H256),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(err) => write!(f, "{}", err),
+            Error::InvalidFormat(reason) => write!(f, "invalid key file: {}", reason),
+            Error::InvalidKey => write!(f, "invalid secp256k1 private key"),
+            Error::InvalidSeed => write!(f, "invalid seed"),
+            Error::InvalidPassword => write!(f, "invalid password"),
+            Error::NotFound(hash) => write!(f, "key {:#x} not found", hash),
+            Error::AlreadyExists(hash) => write!(f, "key {:#x} already exists", hash),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+/// The blake2b hash of the compressed public key, which names the key in the
+/// keystore and is put in the lock scripts
+pub fn pubkey_hash(pubkey: &Pubkey) -> H256 {
+    blake2b(pubkey.serialize()).into()
+}
+
+pub struct KeyStore {
+    dir: PathBuf,
+    scrypt_log_n: u8,
+}
+
+impl KeyStore {
+    /// Opens the keystore in `dir`, created if missing
+    pub fn open<P: AsRef<Path>>(dir: P) -> io::Result<KeyStore> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        Ok(KeyStore {
+            dir,
+            scrypt_log_n: DEFAULT_SCRYPT_LOG_N,
+        })
+    }
+
+    /// The scrypt cost of the keys stored from now on, the stored keys keep
+    /// theirs
+    pub fn with_scrypt_log_n(mut self, log_n: u8) -> Self {
+        self.scrypt_log_n = log_n;
+        self
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Generates a random key and stores it, returns its pubkey hash
+    pub fn generate(&self, password: &str) -> Result<H256, Error> {
+        let privkey = Generator::new().random_privkey();
+        self.import(&privkey, password)
+    }
+
+    /// Stores `privkey` encrypted with `password`, returns its pubkey hash
+    pub fn import(&self, privkey: &Privkey, password: &str) -> Result<H256, Error> {
+        let pubkey = privkey.pubkey().map_err(|_| Error::InvalidKey)?;
+        let hash = pubkey_hash(&pubkey);
+        self.store(&self.path(&hash), privkey.as_bytes(), &hash, password)?;
+        Ok(hash)
+    }
+
+    /// Decrypts the key of `hash` with `password`
+    pub fn export(&self, hash: &H256, password: &str) -> Result<Privkey, Error> {
+        let plaintext = self.load(&self.path(hash), hash, password)?;
+        if plaintext.len() != KEY_LEN {
+            return Err(Error::InvalidFormat("invalid ciphertext".to_owned()));
+        }
+        let privkey = Privkey::from_slice(&plaintext);
+        let pubkey = privkey.pubkey().map_err(|_| Error::InvalidKey)?;
+        if &pubkey_hash(&pubkey) != hash {
+            return Err(Error::InvalidFormat("pubkey hash mismatch".to_owned()));
+        }
+        Ok(privkey)
+    }
+
+    /// The pubkey hashes of the stored keys, sorted
+    pub fn list(&self) -> Result<Vec<H256>, Error> {
+        self.list_with_suffix(".json")
+    }
+
+    /// Generates a random 32 bytes seed and stores it, returns the pubkey
+    /// hash of its master key
+    pub fn generate_seed(&self, password: &str) -> Result<H256, Error> {
+        let seed: [u8; 32] = rand::random();
+        self.import_seed(&seed, password)
+    }
+
+    /// Stores `seed` encrypted with `password`, returns the pubkey hash of its
+    /// master key
+    pub fn import_seed(&self, seed: &[u8], password: &str) -> Result<H256, Error> {
+        let hash = master_hash(seed)?;
+        self.store(&self.seed_path(&hash), seed, &hash, password)?;
+        Ok(hash)
+    }
+
+    /// Stores the seed of a BIP39 `mnemonic` and its `passphrase`, see
+    /// [hd::mnemonic_to_seed], returns the pubkey hash of its master key
+    pub fn import_mnemonic(
+        &self,
+        mnemonic: &str,
+        passphrase: &str,
+        password: &str,
+    ) -> Result<H256, Error> {
+        let seed = hd::mnemonic_to_seed(mnemonic, passphrase).ok_or(Error::InvalidSeed)?;
+        self.import_seed(&seed, password)
+    }
+
+    /// Decrypts the seed of `hash` with `password`, to back it up
+    pub fn export_seed(&self, hash: &H256, password: &str) -> Result<Vec<u8>, Error> {
+        let seed = self.load(&self.seed_path(hash), hash, password)?;
+        if &master_hash(&seed)? != hash {
+            return Err(Error::InvalidFormat("pubkey hash mismatch".to_owned()));
+        }
+        Ok(seed)
+    }
+
+    /// The pubkey hashes of the master keys of the stored seeds, sorted
+    pub fn list_seeds(&self) -> Result<Vec<H256>, Error> {
+        self.list_with_suffix(".seed.json")
+    }
+
+    /// Derives the key at `path` from the seed of `hash`
+    pub fn derive(
+        &self,
+        hash: &H256,
+        password: &str,
+        path: &DerivationPath,
+    ) -> Result<Privkey, Error> {
+        let seed = self.export_seed(hash, password)?;
+        let master = ExtendedPrivkey::from_seed(&seed).ok_or(Error::InvalidSeed)?;
+        let child = master.derive_path(path).ok_or(Error::InvalidKey)?;
+        Ok(Privkey::from(H256::clone(child.privkey())))
+    }
+
+    fn store(
+        &self,
+        path: &Path,
+        plaintext: &[u8],
+        hash: &H256,
+        password: &str,
+    ) -> Result<(), Error> {
+        let key_file = KeyFile::encrypt(plaintext, hash.clone(), password, self.scrypt_log_n)?;
+        let content = serde_json::to_string_pretty(&key_file).expect("serialize key file");
+
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut file = options.open(path).map_err(|err| {
+            if err.kind() == io::ErrorKind::AlreadyExists {
+                Error::AlreadyExists(hash.clone())
+            } else {
+                Error::Io(err)
+            }
+        })?;
+        file.write_all(content.as_bytes())?;
+        file.sync_all()?;
+        Ok(())
+    }
+
+    fn load(&self, path: &Path, hash: &H256, password: &str) -> Result<Vec<u8>, Error> {
+        let content = fs::read_to_string(path).map_err(|err| {
+            if err.kind() == io::ErrorKind::NotFound {
+                Error::NotFound(hash.clone())
+            } else {
+                Error::Io(err)
+            }
+        })?;
+        let key_file: KeyFile = serde_json::from_str(&content)
+            .map_err(|err| Error::InvalidFormat(err.to_string()))?;
+        if &key_file.pubkey_hash != hash {
+            return Err(Error::InvalidFormat("pubkey hash mismatch".to_owned()));
+        }
+        key_file.decrypt(password)
+    }
+
+    // The files are named by a hash in hex then the suffix
+    fn list_with_suffix(&self, suffix: &str) -> Result<Vec<H256>, Error> {
+        let mut hashes = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let name = entry?.file_name();
+            let hash = name
+                .to_str()
+                .filter(|name| name.ends_with(suffix))
+                .and_then(|name| H256::from_hex_str(&name[..name.len() - suffix.len()]).ok());
+            if let Some(hash) = hash {
+                hashes.push(hash);
+            }
+        }
+        hashes.sort();
+        Ok(hashes)
+    }
+
+    fn path(&self, hash: &H256) -> PathBuf {
+        self.dir.join(format!("{:x}.json", hash))
+    }
+
+    fn seed_path(&self, hash: &H256) -> PathBuf {
+        self.dir.join(format!("{:x}.seed.json", hash))
+    }
+}
+
+fn master_hash(seed: &[u8]) -> Result<H256, Error> {
+    if seed.len() < MIN_SEED_LEN || seed.len() > MAX_SEED_LEN {
+        return Err(Error::InvalidSeed);
+    }
+    let master = ExtendedPrivkey::from_seed(seed).ok_or(Error::InvalidSeed)?;
+    let pubkey = master.privkey().pubkey().map_err(|_| Error::InvalidSeed)?;
+    Ok(pubkey_hash(&pubkey))
+}
+
+#[derive(Serialize, Deserialize)]
+struct KeyFile {
+    version: u32,
+    pubkey_hash: H256,
+    crypto: Crypto,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Crypto {
+    cipher: String,
+    cipherparams: CipherParams,
+    ciphertext: Hex,
+    kdf: String,
+    kdfparams: KdfParams,
+    mac: Hex,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CipherParams {
+    iv: Hex,
+}
+
+#[derive(Serialize, Deserialize)]
+struct KdfParams {
+    dklen: usize,
+    n: u64,
+    r: u32,
+    p: u32,
+    salt: Hex,
+}
+
+impl KdfParams {
+    fn derive_key(&self, password: &str) -> Result<Vec<u8>, Error> {
+        if !self.n.is_power_of_two() || self.n < 2 || self.dklen != DKLEN {
+            return Err(Error::InvalidFormat("unsupported kdf params".to_owned()));
+        }
+        let memory = self.n.checked_mul(128 * u64::from(self.r));
+        if memory.map_or(true, |memory| memory > MAX_SCRYPT_MEMORY) || self.p > MAX_SCRYPT_P {
+            return Err(Error::InvalidFormat("kdf params too costly".to_owned()));
+        }
+        let log_n = self.n.trailing_zeros() as u8;
+        let params = ScryptParams::new(log_n, self.r, self.p)
+            .map_err(|_| Error::InvalidFormat("unsupported kdf params".to_owned()))?;
+        let mut derived_key = vec![0; self.dklen];
+        scrypt(password.as_bytes(), &self.salt.0, &params, &mut derived_key)
+            .expect("derived key length");
+        Ok(derived_key)
+    }
+}
+
+impl KeyFile {
+    fn encrypt(
+        plaintext: &[u8],
+        pubkey_hash: H256,
+        password: &str,
+        scrypt_log_n: u8,
+    ) -> Result<KeyFile, Error> {
+        let salt: [u8; SALT_LEN] = rand::random();
+        let iv: [u8; 16] = rand::random();
+        let kdfparams = KdfParams {
+            dklen: DKLEN,
+            n: 1 << scrypt_log_n,
+            r: SCRYPT_R,
+            p: SCRYPT_P,
+            salt: Hex(salt.to_vec()),
+        };
+        let derived_key = kdfparams.derive_key(password)?;
+        let mut ciphertext = plaintext.to_vec();
+        apply_cipher(&derived_key, &iv, &mut ciphertext);
+        let mac = mac(&derived_key, &ciphertext);
+        Ok(KeyFile {
+            version: VERSION,
+            pubkey_hash,
+            crypto: Crypto {
+                cipher: CIPHER.to_owned(),
+                cipherparams: CipherParams {
+                    iv: Hex(iv.to_vec()),
+                },
+                ciphertext: Hex(ciphertext),
+                kdf: KDF.to_owned(),
+                kdfparams,
+                mac: Hex(mac.to_vec()),
+            },
+        })
+    }
+
+    fn decrypt(&self, password: &str) -> Result<Vec<u8>, Error> {
+        let crypto = &self.crypto;
+        if self.version != VERSION {
+            return Err(Error::InvalidFormat(format!("version {}", self.version)));
+        }
+        if crypto.cipher != CIPHER || crypto.kdf != KDF {
+            return Err(Error::InvalidFormat(format!(
+                "cipher {} with kdf {}",
+                crypto.cipher, crypto.kdf
+            )));
+        }
+        if crypto.cipherparams.iv.0.len() != 16 || crypto.mac.0.len() != 32 {
+            return Err(Error::InvalidFormat("invalid cipher params".to_owned()));
+        }
+
+        let derived_key = crypto.kdfparams.derive_key(password)?;
+        // In constant time, the time taken tells nothing of the mac
+        if !constant_time_eq(&mac(&derived_key, &crypto.ciphertext.0), &crypto.mac.0) {
+            return Err(Error::InvalidPassword);
+        }
+        let mut plaintext = crypto.ciphertext.0.clone();
+        apply_cipher(&derived_key, &crypto.cipherparams.iv.0, &mut plaintext);
+        Ok(plaintext)
+    }
+}
+
+fn apply_cipher(derived_key: &[u8], iv: &[u8], data: &mut [u8]) {
+    let mut cipher = Aes128Ctr::new(
+        GenericArray::from_slice(&derived_key[..16]),
+        GenericArray::from_slice(iv),
+    );
+    cipher.apply_keystream(data);
+}
+
+fn mac(derived_key: &[u8], ciphertext: &[u8]) -> [u8; 32] {
+    let mut input = derived_key[16..].to_vec();
+    input.extend_from_slice(ciphertext);
+    blake2b(input)
+}
+
+// Bytes in plain hex without the 0x prefix, as the other keystores write them
+struct Hex(Vec<u8>);
+
+impl Serialize for Hex {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&hex_string(&self.0).expect("hex string"))
+    }
+}
+
+impl<'de> Deserialize<'de> for Hex {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let hex = String::deserialize(deserializer)?;
+        if hex.len() % 2 != 0 {
+            return Err(de::Error::custom("odd hex length"));
+        }
+        let mut bytes = vec![0; hex.len() / 2];
+        hex_decode(hex.as_bytes(), &mut bytes)
+            .map_err(|err| de::Error::custom(format_args!("{:?}", err)))?;
+        Ok(Hex(bytes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keystore(prefix: &str) -> (tempfile::TempDir, KeyStore) {
+        let tmp_dir = tempfile::Builder::new().prefix(prefix).tempdir().unwrap();
+        // Fast enough for tests
+        let keystore = KeyStore::open(tmp_dir.path().join("keystore"))
+            .unwrap()
+            .with_scrypt_log_n(4);
+        (tmp_dir, keystore)
+    }
+
+    #[test]
+    fn import_export() {
+        let (_tmp_dir, keystore) = keystore("import_export");
+        let generated = keystore.generate("secret").unwrap();
+
+        let privkey = Generator::new().random_privkey();
+        let imported = keystore.import(&privkey, "another").unwrap();
+        assert_eq!(imported, pubkey_hash(&privkey.pubkey().unwrap()));
+        match keystore.import(&privkey, "another") {
+            Err(Error::AlreadyExists(hash)) => assert_eq!(hash, imported),
+            _ => panic!("imported twice"),
+        }
+
+        let mut expected = vec![generated.clone(), imported.clone()];
+        expected.sort();
+        assert_eq!(keystore.list().unwrap(), expected);
+        assert_eq!(keystore.export(&imported, "another").unwrap(), privkey);
+        let exported = keystore.export(&generated, "secret").unwrap();
+        assert_eq!(pubkey_hash(&exported.pubkey().unwrap()), generated);
+    }
+
+    #[test]
+    fn wrong_password() {
+        let (_tmp_dir, keystore) = keystore("wrong_password");
+        let hash = keystore.generate("secret").unwrap();
+        match keystore.export(&hash, "guess") {
+            Err(Error::InvalidPassword) => {}
+            _ => panic!("decrypted with a wrong password"),
+        }
+        match keystore.export(&H256::zero(), "secret") {
+            Err(Error::NotFound(_)) => {}
+            _ => panic!("found a missing key"),
+        }
+    }
+
+    #[test]
+    fn costly_kdf_params() {
+        let (_tmp_dir, keystore) = keystore("costly_kdf_params");
+        let hash = keystore.generate("secret").unwrap();
+        let path = keystore.path(&hash);
+        let original = fs::read_to_string(&path).unwrap();
+        let params = [
+            ("n", serde_json::json!(1u64 << 40)),
+            ("r", serde_json::json!(1u32 << 20)),
+            ("p", serde_json::json!(1u32 << 20)),
+        ];
+        for (param, value) in &params {
+            let mut key_file: serde_json::Value = serde_json::from_str(&original).unwrap();
+            key_file["crypto"]["kdfparams"][param] = value.clone();
+            fs::write(&path, key_file.to_string()).unwrap();
+            match keystore.export(&hash, "secret") {
+                Err(Error::InvalidFormat(_)) => {}
+                _ => panic!("derived with a costly {}", param),
+            }
+        }
+        fs::write(&path, original).unwrap();
+        assert!(keystore.export(&hash, "secret").is_ok());
+    }
+
+    #[test]
+    fn derive_from_seed() {
+        let (_tmp_dir, keystore) = keystore("derive_from_seed");
+        let seed = (0u8..16).collect::<Vec<_>>();
+        let hash = keystore.import_seed(&seed, "secret").unwrap();
+        let generated = keystore.generate_seed("secret").unwrap();
+        // Seeds are not keys
+        assert!(keystore.list().unwrap().is_empty());
+        let mut expected = vec![hash.clone(), generated];
+        expected.sort();
+        assert_eq!(keystore.list_seeds().unwrap(), expected);
+        assert_eq!(keystore.export_seed(&hash, "secret").unwrap(), seed);
+
+        let path = "m/0'/1".parse().unwrap();
+        let derived = keystore.derive(&hash, "secret", &path).unwrap();
+        assert_eq!(
+            derived.to_string(),
+            "3c6cb8d0f6a264c91ea8b5030fadaa8e538b020f0a387421a12de9319dc93368"
+        );
+        let first = keystore.derive(&hash, "secret", &DerivationPath::receiving(0));
+        let second = keystore.derive(&hash, "secret", &DerivationPath::receiving(1));
+        assert_ne!(first.unwrap(), second.unwrap());
+
+        match keystore.import_seed(&seed[..8], "secret") {
+            Err(Error::InvalidSeed) => {}
+            _ => panic!("imported a short seed"),
+        }
+
+        let mnemonic =
+            "legal winner thank year wave sausage worth useful legal winner thank yellow";
+        let hash = keystore.import_mnemonic(mnemonic, "", "secret").unwrap();
+        let seed = keystore.export_seed(&hash, "secret").unwrap();
+        assert_eq!(Some(seed), hd::mnemonic_to_seed(mnemonic, ""));
+    }
+}
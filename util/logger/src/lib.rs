@@ -5,13 +5,32 @@ use env_logger::filter::{Builder, Filter};
 use lazy_static::lazy_static;
 use log::{LevelFilter, SetLoggerError};
 use log::{Log, Metadata, Record};
-use parking_lot::Mutex;
+use parking_lot::{Mutex, RwLock};
 use regex::Regex;
 use serde_derive::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::cell::RefCell;
+use std::fmt::Write as FmtWrite;
 use std::io::Write;
 use std::path::PathBuf;
+use std::str::FromStr;
 use std::{fs, thread};
 
+// A target, or all of them when none, and its maximum level
+type Directive = (Option<String>, LevelFilter);
+
+lazy_static! {
+    // The filter of the installed logger with the directives it is built
+    // from, rebuilt by `set_filter`
+    static ref FILTER: RwLock<(Vec<Directive>, Filter)> =
+        RwLock::new((Vec::new(), build_filter(&[])));
+}
+
+thread_local! {
+    // Fields of the live `LogContext`s of the thread, oldest first
+    static CONTEXT: RefCell<Vec<(&'static str, String)>> = RefCell::new(Vec::new());
+}
+
 enum Message {
     Record(String),
     Terminate,
@@ -21,20 +40,21 @@ enum Message {
 pub struct Logger {
     sender: crossbeam_channel::Sender<Message>,
     handle: Mutex<Option<thread::JoinHandle<()>>>,
-    filter: Filter,
+    format: Format,
 }
 
 impl Logger {
     fn new(config: Config) -> Logger {
-        let mut builder = Builder::new();
-
-        if let Ok(ref env_filter) = std::env::var("NERVOS_LOG") {
-            builder.parse(env_filter);
-        }
-
-        if let Some(ref config_filter) = config.filter {
-            builder.parse(config_filter);
+        let mut directives = Vec::new();
+        let env_filter = std::env::var("NERVOS_LOG").ok();
+        for spec in env_filter.iter().chain(config.filter.iter()) {
+            match parse_directives(spec) {
+                Ok(parsed) => merge_directives(&mut directives, parsed),
+                Err(err) => eprintln!("Ignore log filter {:?}: {}", spec, err),
+            }
         }
+        let filter = build_filter(&directives);
+        *FILTER.write() = (directives, filter);
 
         let (sender, receiver) = unbounded();
         let file = config.file;
@@ -79,20 +99,31 @@ impl Logger {
         Logger {
             sender,
             handle: Mutex::new(Some(tb)),
-            filter: builder.build(),
+            format: config.format,
         }
     }
 
     pub fn filter(&self) -> LevelFilter {
-        self.filter.filter()
+        FILTER.read().1.filter()
     }
 }
 
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum Format {
+    Text,
+    /// One JSON object a line, with the `timestamp`, `thread`, `level`,
+    /// `target`, `message` and the context fields
+    Json,
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
 pub struct Config {
+    /// Comma separated `target=level` or `level` directives, as `NERVOS_LOG`
     pub filter: Option<String>,
     pub color: bool,
     pub file: Option<PathBuf>,
+    pub format: Format,
 }
 
 impl Default for Config {
@@ -101,35 +132,46 @@ impl Default for Config {
             filter: None,
             color: !cfg!(windows),
             file: None,
+            format: Format::Text,
         }
     }
 }
 
+/// Adds fields to the records logged by the thread until dropped, bind it to
+/// a named variable such as `_context`
+#[must_use]
+pub struct LogContext {
+    len: usize,
+}
+
+impl Drop for LogContext {
+    fn drop(&mut self) {
+        CONTEXT.with(|context| {
+            let mut context = context.borrow_mut();
+            let len = context.len() - self.len;
+            context.truncate(len);
+        });
+    }
+}
+
+/// Adds `fields`, like the peer or the block being processed, to the records
+/// logged by the current thread while the returned context lives
+pub fn context(fields: Vec<(&'static str, String)>) -> LogContext {
+    let len = fields.len();
+    CONTEXT.with(|context| context.borrow_mut().extend(fields));
+    LogContext { len }
+}
+
 impl Log for Logger {
     fn enabled(&self, metadata: &Metadata) -> bool {
-        self.filter.enabled(metadata)
+        FILTER.read().1.enabled(metadata)
     }
 
     fn log(&self, record: &Record) {
         // Check if the record is matched by the filter
-        if self.filter.matches(record) {
-            let thread = thread::current();
-            let thread_name = thread.name().unwrap_or_default();
-
-            let with_color = {
-                let thread_name = format!("{}", Colour::Blue.bold().paint(thread_name));
-                let dt: DateTime<Local> = Local::now();
-                let timestamp = dt.format("%Y-%m-%d %H:%M:%S%.3f %Z").to_string();
-                format!(
-                    "{} {} {} {}  {}",
-                    Colour::Black.bold().paint(timestamp),
-                    thread_name,
-                    record.level(),
-                    record.target(),
-                    record.args()
-                )
-            };
-            let _ = self.sender.send(Message::Record(with_color));
+        if FILTER.read().1.matches(record) {
+            let line = format_record(self.format, record);
+            let _ = self.sender.send(Message::Record(line));
         }
     }
 
@@ -140,6 +182,116 @@ impl Log for Logger {
     }
 }
 
+fn format_record(format: Format, record: &Record) -> String {
+    let thread = thread::current();
+    let thread_name = thread.name().unwrap_or_default();
+    let dt: DateTime<Local> = Local::now();
+    let timestamp = dt.format("%Y-%m-%d %H:%M:%S%.3f %Z").to_string();
+    let fields = CONTEXT.with(|context| context.borrow().clone());
+
+    match format {
+        Format::Text => {
+            let mut line = format!(
+                "{} {} {} {}  {}",
+                Colour::Black.bold().paint(timestamp),
+                Colour::Blue.bold().paint(thread_name),
+                record.level(),
+                record.target(),
+                record.args()
+            );
+            for (key, value) in fields {
+                write!(line, " {}={}", key, value).expect("write to string");
+            }
+            line
+        }
+        Format::Json => {
+            let mut object = Map::new();
+            let mut insert = |key: &str, value: String| {
+                object.insert(key.to_owned(), Value::String(value));
+            };
+            insert("timestamp", timestamp);
+            insert("thread", thread_name.to_owned());
+            insert("level", record.level().to_string());
+            insert("target", record.target().to_owned());
+            insert("message", record.args().to_string());
+            for (key, value) in fields {
+                insert(key, value);
+            }
+            Value::Object(object).to_string()
+        }
+    }
+}
+
+fn parse_directives(spec: &str) -> Result<Vec<Directive>, String> {
+    let mut directives = Vec::new();
+    for directive in spec.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+        let mut parts = directive.splitn(2, '=');
+        let target = parts.next().unwrap_or_default();
+        directives.push(match parts.next() {
+            Some(level) => {
+                let level = LevelFilter::from_str(level)
+                    .map_err(|_| format!("invalid level {:?} of {}", level, target))?;
+                (Some(target.to_owned()), level)
+            }
+            // A lone target is logged at every level
+            None => match LevelFilter::from_str(target) {
+                Ok(level) => (None, level),
+                Err(_) => (Some(target.to_owned()), LevelFilter::Trace),
+            },
+        });
+    }
+    Ok(directives)
+}
+
+// The new directive of a target replaces its current one
+fn merge_directives(directives: &mut Vec<Directive>, new: Vec<Directive>) {
+    for (target, level) in new {
+        directives.retain(|(current, _)| *current != target);
+        directives.push((target, level));
+    }
+}
+
+fn build_filter(directives: &[Directive]) -> Filter {
+    let mut builder = Builder::new();
+    for (target, level) in directives {
+        builder.filter(target.as_ref().map(String::as_str), *level);
+    }
+    builder.build()
+}
+
+/// Changes the levels of the targets in `spec` at runtime, in the format of
+/// [Config::filter], the other targets keep theirs
+pub fn set_filter(spec: &str) -> Result<(), String> {
+    let new = parse_directives(spec)?;
+    let mut filter = FILTER.write();
+    merge_directives(&mut filter.0, new);
+    filter.1 = build_filter(&filter.0);
+    log::set_max_level(filter.1.filter());
+    Ok(())
+}
+
+/// Checks `spec` as [set_filter] does, without changing the filter
+pub fn check_filter(spec: &str) -> Result<(), String> {
+    parse_directives(spec).map(|_| ())
+}
+
+/// The directives of the current filter, in the format of [Config::filter]
+pub fn filter_spec() -> String {
+    FILTER
+        .read()
+        .0
+        .iter()
+        .map(|(target, level)| {
+            let level = level.to_string().to_lowercase();
+            match target {
+                Some(target) => format!("{}={}", target, level),
+                None => level,
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
 fn sanitize_color(s: &str) -> String {
     lazy_static! {
         static ref RE: Regex = Regex::new("\x1b\\[[^m]+m").unwrap();
@@ -156,3 +308,57 @@ pub fn init(config: Config) -> Result<(), SetLoggerError> {
 pub fn flush() {
     log::logger().flush()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use log::Level;
+
+    #[test]
+    fn parse_and_merge_directives() {
+        let mut directives = parse_directives("info, sync=debug,network").unwrap();
+        assert_eq!(
+            directives,
+            vec![
+                (None, LevelFilter::Info),
+                (Some("sync".to_owned()), LevelFilter::Debug),
+                (Some("network".to_owned()), LevelFilter::Trace),
+            ]
+        );
+        assert!(parse_directives("sync=loud").is_err());
+
+        merge_directives(&mut directives, parse_directives("sync=warn,chain=off").unwrap());
+        assert_eq!(
+            directives,
+            vec![
+                (None, LevelFilter::Info),
+                (Some("network".to_owned()), LevelFilter::Trace),
+                (Some("sync".to_owned()), LevelFilter::Warn),
+                (Some("chain".to_owned()), LevelFilter::Off),
+            ]
+        );
+    }
+
+    #[test]
+    fn format_json_with_context() {
+        let _context = context(vec![("peer", "3".to_owned())]);
+        let line = {
+            let _context = context(vec![("block", "0x01".to_owned())]);
+            format_record(
+                Format::Json,
+                &Record::builder()
+                    .level(Level::Info)
+                    .target("sync")
+                    .args(format_args!("accepted"))
+                    .build(),
+            )
+        };
+        let value: Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(value["level"], "INFO");
+        assert_eq!(value["target"], "sync");
+        assert_eq!(value["message"], "accepted");
+        assert_eq!(value["peer"], "3");
+        assert_eq!(value["block"], "0x01");
+        CONTEXT.with(|context| assert_eq!(context.borrow().len(), 1));
+    }
+}
@@ -0,0 +1,336 @@
+//! # The Metrics Library
+//!
+//! A [Registry] of counters, gauges and histograms. Subsystems register
+//! their metrics once, by name and labels, and update them lock free; the
+//! registry is visited to report them. The values sampled from a state, like
+//! the number of connected peers, are set by collectors run before reporting.
+//!
+//! The subsystems register into the [registry] of the process, or into the
+//! one the current thread [entered](enter), so that the nodes started in one
+//! process, like the ones of a test, each have theirs.
+//!
+//! [start_server] serves a registry in the Prometheus text format, and
+//! [start_monitor] samples the resources used by the process into it.
+
+mod prometheus;
+mod resources;
+mod server;
+
+pub use crate::prometheus::render;
+pub use crate::resources::{start_monitor, MonitorConfig};
+pub use crate::server::start_server;
+
+use ckb_util::RwLock;
+use lazy_static::lazy_static;
+use serde_derive::Deserialize;
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Upper bounds of the buckets of latency histograms, in microseconds
+pub const LATENCY_BUCKETS: &[u64] = &[
+    10, 50, 100, 500, 1_000, 5_000, 10_000, 50_000, 100_000, 500_000, 1_000_000,
+];
+
+pub type Labels = Vec<(&'static str, String)>;
+
+// Returns false once there is nothing left to collect
+type Collector = Box<dyn Fn() -> bool + Send + Sync>;
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+pub struct Config {
+    /// `ip:port` serving `/metrics`, not served when unset
+    pub listen_address: Option<String>,
+    #[serde(default)]
+    pub monitor: MonitorConfig,
+}
+
+#[derive(Debug, Default)]
+pub struct Counter {
+    value: AtomicUsize,
+}
+
+impl Counter {
+    pub fn inc(&self) {
+        self.add(1);
+    }
+
+    pub fn add(&self, n: usize) {
+        self.value.fetch_add(n, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> usize {
+        self.value.load(Ordering::Relaxed)
+    }
+}
+
+/// A value which goes up and down, like the length of a queue
+#[derive(Debug, Default)]
+pub struct Gauge {
+    value: AtomicUsize,
+}
+
+impl Gauge {
+    pub fn set(&self, value: usize) {
+        self.value.store(value, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> usize {
+        self.value.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Debug)]
+pub struct Histogram {
+    bounds: &'static [u64],
+    // One more than the bounds, for the values above all of them
+    counts: Vec<AtomicUsize>,
+    sum: AtomicUsize,
+}
+
+/// Counts of a histogram, for every bound the values not above it
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HistogramSnapshot {
+    pub buckets: Vec<(u64, usize)>,
+    pub count: usize,
+    pub sum: usize,
+}
+
+impl Histogram {
+    pub fn new(bounds: &'static [u64]) -> Self {
+        Histogram {
+            bounds,
+            counts: (0..=bounds.len()).map(|_| AtomicUsize::new(0)).collect(),
+            sum: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn observe(&self, value: u64) {
+        let bucket = self
+            .bounds
+            .iter()
+            .position(|bound| value <= *bound)
+            .unwrap_or_else(|| self.bounds.len());
+        self.counts[bucket].fetch_add(1, Ordering::Relaxed);
+        self.sum.fetch_add(value as usize, Ordering::Relaxed);
+    }
+
+    /// Observes `duration` in microseconds
+    pub fn observe_duration(&self, duration: Duration) {
+        let micros = duration.as_secs() * 1_000_000 + u64::from(duration.subsec_micros());
+        self.observe(micros);
+    }
+
+    pub fn snapshot(&self) -> HistogramSnapshot {
+        let mut count = 0;
+        let buckets = self
+            .bounds
+            .iter()
+            .zip(&self.counts)
+            .map(|(bound, bucket)| {
+                count += bucket.load(Ordering::Relaxed);
+                (*bound, count)
+            })
+            .collect();
+        count += self.counts[self.bounds.len()].load(Ordering::Relaxed);
+        HistogramSnapshot {
+            buckets,
+            count,
+            sum: self.sum.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum Metric {
+    Counter(Arc<Counter>),
+    Gauge(Arc<Gauge>),
+    Histogram(Arc<Histogram>),
+}
+
+#[derive(Default)]
+pub struct Registry {
+    metrics: RwLock<Vec<(String, Labels, Metric)>>,
+    collectors: RwLock<Vec<Collector>>,
+}
+
+impl Registry {
+    /// The counter `name` with `labels`, registered on first use
+    pub fn counter(&self, name: &str, labels: Labels) -> Arc<Counter> {
+        let metric = self.get_or_register(name, labels, || {
+            Metric::Counter(Arc::new(Counter::default()))
+        });
+        match metric {
+            Metric::Counter(counter) => counter,
+            _ => panic!("metric {} is not a counter", name),
+        }
+    }
+
+    /// The gauge `name` with `labels`, registered on first use
+    pub fn gauge(&self, name: &str, labels: Labels) -> Arc<Gauge> {
+        let metric =
+            self.get_or_register(name, labels, || Metric::Gauge(Arc::new(Gauge::default())));
+        match metric {
+            Metric::Gauge(gauge) => gauge,
+            _ => panic!("metric {} is not a gauge", name),
+        }
+    }
+
+    /// The histogram `name` with `labels`, registered on first use with
+    /// `bounds`
+    pub fn histogram(&self, name: &str, labels: Labels, bounds: &'static [u64]) -> Arc<Histogram> {
+        let metric = self.get_or_register(name, labels, || {
+            Metric::Histogram(Arc::new(Histogram::new(bounds)))
+        });
+        match metric {
+            Metric::Histogram(histogram) => histogram,
+            _ => panic!("metric {} is not a histogram", name),
+        }
+    }
+
+    /// Registers `collector` to run before the metrics are reported, until
+    /// it returns false, such as when the state it samples is dropped. It
+    /// must not register other collectors.
+    pub fn on_collect<F: Fn() -> bool + Send + Sync + 'static>(&self, collector: F) {
+        self.collectors.write().push(Box::new(collector));
+    }
+
+    /// Runs the collectors, and unregisters the ones returning false
+    pub fn collect(&self) {
+        self.collectors.write().retain(|collector| collector());
+    }
+
+    /// Visits the metrics in registration order
+    pub fn visit<F: FnMut(&str, &Labels, &Metric)>(&self, mut f: F) {
+        for (name, labels, metric) in self.metrics.read().iter() {
+            f(name, labels, metric);
+        }
+    }
+
+    fn get_or_register<F: FnOnce() -> Metric>(&self, name: &str, labels: Labels, new: F) -> Metric {
+        let mut metrics = self.metrics.write();
+        if let Some((_, _, metric)) = metrics.iter().find(|(n, l, _)| n == name && *l == labels) {
+            return metric.clone();
+        }
+        let metric = new();
+        metrics.push((name.to_owned(), labels, metric.clone()));
+        metric
+    }
+}
+
+lazy_static! {
+    static ref REGISTRY: Arc<Registry> = Arc::new(Registry::default());
+}
+
+thread_local! {
+    static SCOPED_REGISTRY: RefCell<Option<Arc<Registry>>> = RefCell::new(None);
+}
+
+/// The registry the current thread entered if any, else the one of the
+/// process
+pub fn registry() -> Arc<Registry> {
+    SCOPED_REGISTRY
+        .with(|scoped| scoped.borrow().clone())
+        .unwrap_or_else(|| Arc::clone(&REGISTRY))
+}
+
+/// Registers the metrics of the current thread into `registry` until the
+/// scope is dropped, scopes nest. The subsystems take their metrics when
+/// built, so the ones built in the scope report there from any thread.
+pub fn enter(registry: &Arc<Registry>) -> Scope {
+    let previous = SCOPED_REGISTRY.with(|scoped| scoped.replace(Some(Arc::clone(registry))));
+    Scope { previous }
+}
+
+/// Restores the registry the thread had before [enter] when dropped
+#[must_use]
+pub struct Scope {
+    previous: Option<Arc<Registry>>,
+}
+
+impl Drop for Scope {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        SCOPED_REGISTRY.with(|scoped| *scoped.borrow_mut() = previous);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counter_and_histogram() {
+        let registry = Registry::default();
+        let labels = || vec![("col", "headers".to_owned())];
+        registry.counter("reads", labels()).inc();
+        registry.counter("reads", labels()).add(2);
+        assert_eq!(registry.counter("reads", labels()).get(), 3);
+        assert_eq!(registry.counter("reads", Vec::new()).get(), 0);
+
+        registry.gauge("queued", labels()).set(5);
+        registry.gauge("queued", labels()).set(2);
+        assert_eq!(registry.gauge("queued", labels()).get(), 2);
+
+        let histogram = registry.histogram("latency", labels(), &[10, 100]);
+        histogram.observe(5);
+        histogram.observe(10);
+        histogram.observe(50);
+        histogram.observe_duration(Duration::from_millis(1));
+        assert_eq!(
+            histogram.snapshot(),
+            HistogramSnapshot {
+                buckets: vec![(10, 2), (100, 3)],
+                count: 4,
+                sum: 1065,
+            }
+        );
+
+        let mut names = Vec::new();
+        registry.visit(|name, _, _| names.push(name.to_owned()));
+        assert_eq!(names, vec!["reads", "reads", "queued", "latency"]);
+    }
+
+    #[test]
+    fn collector() {
+        let registry = Arc::new(Registry::default());
+        let weak = Arc::downgrade(&registry);
+        registry.on_collect(move || match weak.upgrade() {
+            Some(registry) => {
+                let reads = registry.counter("reads", Vec::new()).get();
+                registry.gauge("sampled", Vec::new()).set(reads);
+                true
+            }
+            None => false,
+        });
+        registry.counter("reads", Vec::new()).add(7);
+        assert_eq!(registry.gauge("sampled", Vec::new()).get(), 0);
+        registry.collect();
+        assert_eq!(registry.gauge("sampled", Vec::new()).get(), 7);
+
+        let sampled = Arc::new(AtomicUsize::new(0));
+        let weak = Arc::downgrade(&sampled);
+        registry.on_collect(move || weak.upgrade().is_some());
+        assert_eq!(registry.collectors.read().len(), 2);
+        drop(sampled);
+        registry.collect();
+        assert_eq!(registry.collectors.read().len(), 1);
+    }
+
+    #[test]
+    fn scoped_registry() {
+        let node = Arc::new(Registry::default());
+        {
+            let _scope = enter(&node);
+            registry().counter("scoped_registry_total", Vec::new()).inc();
+            assert!(Arc::ptr_eq(&registry(), &node));
+        }
+        assert!(!Arc::ptr_eq(&registry(), &node));
+        assert_eq!(node.counter("scoped_registry_total", Vec::new()).get(), 1);
+        assert_eq!(
+            registry().counter("scoped_registry_total", Vec::new()).get(),
+            0
+        );
+    }
+}
@@ -0,0 +1,107 @@
+//! The [text exposition format](https://prometheus.io/docs/instrumenting/exposition_formats/)
+//! of Prometheus.
+
+use crate::{Labels, Metric, Registry};
+use std::fmt::Write;
+
+/// Runs the collectors of `registry` and renders its metrics, the ones of a
+/// name together under one `TYPE` line
+pub fn render(registry: &Registry) -> String {
+    registry.collect();
+    let mut families: Vec<(String, Vec<(Labels, Metric)>)> = Vec::new();
+    registry.visit(|name, labels, metric| {
+        let sample = (labels.clone(), metric.clone());
+        match families.iter_mut().find(|(n, _)| n == name) {
+            Some((_, samples)) => samples.push(sample),
+            None => families.push((name.to_owned(), vec![sample])),
+        }
+    });
+
+    let mut text = String::new();
+    for (name, samples) in families {
+        let kind = match samples[0].1 {
+            Metric::Counter(_) => "counter",
+            Metric::Gauge(_) => "gauge",
+            Metric::Histogram(_) => "histogram",
+        };
+        writeln!(text, "# TYPE {} {}", name, kind).expect("write to string");
+        for (labels, metric) in samples {
+            match metric {
+                Metric::Counter(counter) => {
+                    write_sample(&mut text, &name, &labels, None, counter.get())
+                }
+                Metric::Gauge(gauge) => write_sample(&mut text, &name, &labels, None, gauge.get()),
+                Metric::Histogram(histogram) => {
+                    let snapshot = histogram.snapshot();
+                    let bucket = format!("{}_bucket", name);
+                    for (bound, count) in snapshot.buckets {
+                        let le = bound.to_string();
+                        write_sample(&mut text, &bucket, &labels, Some(&le), count);
+                    }
+                    write_sample(&mut text, &bucket, &labels, Some("+Inf"), snapshot.count);
+                    let sum = format!("{}_sum", name);
+                    write_sample(&mut text, &sum, &labels, None, snapshot.sum);
+                    let count = format!("{}_count", name);
+                    write_sample(&mut text, &count, &labels, None, snapshot.count);
+                }
+            }
+        }
+    }
+    text
+}
+
+fn write_sample(text: &mut String, name: &str, labels: &Labels, le: Option<&str>, value: usize) {
+    let mut pairs: Vec<String> = labels
+        .iter()
+        .map(|(key, value)| format!("{}=\"{}\"", key, escape(value)))
+        .collect();
+    if let Some(le) = le {
+        pairs.push(format!("le=\"{}\"", le));
+    }
+    let labels = if pairs.is_empty() {
+        String::new()
+    } else {
+        format!("{{{}}}", pairs.join(","))
+    };
+    writeln!(text, "{}{} {}", name, labels, value).expect("write to string");
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_text() {
+        let registry = Registry::default();
+        registry
+            .counter("peers_total", vec![("kind", "in\"bound".to_owned())])
+            .add(2);
+        registry.gauge("depth", Vec::new()).set(3);
+        registry.counter("peers_total", Vec::new()).inc();
+        let histogram = registry.histogram("latency", Vec::new(), &[10, 100]);
+        histogram.observe(5);
+        histogram.observe(500);
+
+        assert_eq!(
+            render(&registry),
+            "# TYPE peers_total counter\n\
+             peers_total{kind=\"in\\\"bound\"} 2\n\
+             peers_total 1\n\
+             # TYPE depth gauge\n\
+             depth 3\n\
+             # TYPE latency histogram\n\
+             latency_bucket{le=\"10\"} 1\n\
+             latency_bucket{le=\"100\"} 1\n\
+             latency_bucket{le=\"+Inf\"} 2\n\
+             latency_sum 505\n\
+             latency_count 2\n"
+        );
+    }
+}
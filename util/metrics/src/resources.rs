@@ -0,0 +1,249 @@
+//! Samples the resources used by the process into the gauges
+//! `process_open_fds`, `process_resident_memory_bytes`, `process_threads` and
+//! `db_disk_usage_bytes`. A resource above its limit is warned about once and
+//! has `resource_limit_exceeded{resource}` set to 1 until it is back under.
+//!
+//! The process is read from `/proc`, only the db usage is sampled on the
+//! systems without it.
+
+use crate::{registry, Gauge, Registry};
+use log::{info, warn};
+use serde_derive::Deserialize;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+const PROC_SELF: &str = "/proc/self";
+const LIMIT_EXCEEDED: &str = "resource_limit_exceeded";
+// The share of the soft limit of open files warned at when none is configured
+const DEFAULT_OPEN_FILES_PERCENT: usize = 80;
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct MonitorConfig {
+    /// Seconds between two samples, not monitored when 0
+    pub interval: u64,
+    /// 80% of the soft limit of the process by default
+    pub max_open_files: Option<usize>,
+    /// In bytes
+    pub max_resident_memory: Option<usize>,
+    /// In bytes, the files under the db directory
+    pub max_db_size: Option<usize>,
+    pub max_threads: Option<usize>,
+}
+
+impl Default for MonitorConfig {
+    fn default() -> Self {
+        MonitorConfig {
+            interval: 60,
+            max_open_files: None,
+            max_resident_memory: None,
+            max_db_size: None,
+            max_threads: None,
+        }
+    }
+}
+
+struct Resource {
+    name: &'static str,
+    sample: Box<dyn Fn() -> Option<usize> + Send>,
+    limit: Option<usize>,
+    gauge: Arc<Gauge>,
+    exceeded: Arc<Gauge>,
+}
+
+impl Resource {
+    fn new<F>(
+        registry: &Registry,
+        name: &'static str,
+        gauge: &str,
+        limit: Option<usize>,
+        sample: F,
+    ) -> Self
+    where
+        F: Fn() -> Option<usize> + Send + 'static,
+    {
+        Resource {
+            name,
+            sample: Box::new(sample),
+            limit,
+            gauge: registry.gauge(gauge, Vec::new()),
+            exceeded: registry.gauge(LIMIT_EXCEEDED, vec![("resource", name.to_owned())]),
+        }
+    }
+
+    fn check(&self) {
+        let value = match (self.sample)() {
+            Some(value) => value,
+            None => return,
+        };
+        self.gauge.set(value);
+        let limit = match self.limit {
+            Some(limit) => limit,
+            None => return,
+        };
+        let was_exceeded = self.exceeded.get() != 0;
+        let exceeded = value > limit;
+        if exceeded && !was_exceeded {
+            warn!(target: "metrics", "{} is {}, above the limit {}", self.name, value, limit);
+        } else if !exceeded && was_exceeded {
+            info!(target: "metrics", "{} is {}, back under the limit {}", self.name, value, limit);
+        }
+        self.exceeded.set(exceeded as usize);
+    }
+}
+
+/// Samples the resources every `config.interval` seconds from a thread, with
+/// the db in `db_path`
+pub fn start_monitor(config: MonitorConfig, db_path: PathBuf) -> io::Result<()> {
+    if config.interval == 0 {
+        return Ok(());
+    }
+    let max_open_files = config.max_open_files.or_else(|| {
+        open_files_soft_limit().map(|limit| limit / 100 * DEFAULT_OPEN_FILES_PERCENT)
+    });
+    let registry = registry();
+    let resources = vec![
+        Resource::new(
+            &registry,
+            "open_files",
+            "process_open_fds",
+            max_open_files,
+            open_files,
+        ),
+        Resource::new(
+            &registry,
+            "resident_memory",
+            "process_resident_memory_bytes",
+            config.max_resident_memory,
+            || status_field("VmRSS").map(|kilobytes| kilobytes * 1024),
+        ),
+        Resource::new(
+            &registry,
+            "threads",
+            "process_threads",
+            config.max_threads,
+            || status_field("Threads"),
+        ),
+        Resource::new(
+            &registry,
+            "db_size",
+            "db_disk_usage_bytes",
+            config.max_db_size,
+            move || dir_size(&db_path).ok().map(|size| size as usize),
+        ),
+    ];
+    let interval = Duration::from_secs(config.interval);
+    thread::Builder::new()
+        .name("resource_monitor".to_string())
+        .spawn(move || loop {
+            for resource in &resources {
+                resource.check();
+            }
+            thread::sleep(interval);
+        })?;
+    Ok(())
+}
+
+fn open_files() -> Option<usize> {
+    fs::read_dir(Path::new(PROC_SELF).join("fd"))
+        .ok()
+        .map(|entries| entries.count())
+}
+
+fn open_files_soft_limit() -> Option<usize> {
+    let limits = fs::read_to_string(Path::new(PROC_SELF).join("limits")).ok()?;
+    parse_soft_limit(&limits, "Max open files")
+}
+
+fn status_field(field: &str) -> Option<usize> {
+    let status = fs::read_to_string(Path::new(PROC_SELF).join("status")).ok()?;
+    parse_status(&status, field)
+}
+
+// `Name:   value [unit]` lines of `/proc/self/status`
+fn parse_status(status: &str, field: &str) -> Option<usize> {
+    status
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.splitn(2, ':');
+            match (parts.next(), parts.next()) {
+                (Some(name), Some(value)) if name == field => Some(value),
+                _ => None,
+            }
+        })
+        .next()?
+        .split_whitespace()
+        .next()?
+        .parse()
+        .ok()
+}
+
+// `Name   soft hard [unit]` lines of `/proc/self/limits`, `None` when unlimited
+fn parse_soft_limit(limits: &str, name: &str) -> Option<usize> {
+    limits
+        .lines()
+        .find(|line| line.starts_with(name))?[name.len()..]
+        .split_whitespace()
+        .next()?
+        .parse()
+        .ok()
+}
+
+fn dir_size(path: &Path) -> io::Result<u64> {
+    let mut size = 0;
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        size += if metadata.is_dir() {
+            dir_size(&entry.path())?
+        } else {
+            metadata.len()
+        };
+    }
+    Ok(size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn parse_proc_files() {
+        let status = "Name:\tckb\nVmRSS:\t  2048 kB\nThreads:\t12\n";
+        assert_eq!(parse_status(status, "VmRSS"), Some(2048));
+        assert_eq!(parse_status(status, "Threads"), Some(12));
+        assert_eq!(parse_status(status, "VmSwap"), None);
+
+        let limits = "Limit                     Soft Limit           Hard Limit           Units\n\
+                      Max open files            1024                 4096                 files\n\
+                      Max locked memory         unlimited            unlimited            bytes\n";
+        assert_eq!(parse_soft_limit(limits, "Max open files"), Some(1024));
+        assert_eq!(parse_soft_limit(limits, "Max locked memory"), None);
+    }
+
+    #[test]
+    fn limit_exceeded() {
+        let registry = Registry::default();
+        let value = Arc::new(AtomicUsize::new(5));
+        let sampled = Arc::clone(&value);
+        let resource = Resource::new(&registry, "test", "test_value", Some(10), move || {
+            Some(sampled.load(Ordering::SeqCst))
+        });
+        let exceeded = || registry.gauge(LIMIT_EXCEEDED, vec![("resource", "test".to_owned())]);
+
+        resource.check();
+        assert_eq!(registry.gauge("test_value", Vec::new()).get(), 5);
+        assert_eq!(exceeded().get(), 0);
+        value.store(11, Ordering::SeqCst);
+        resource.check();
+        assert_eq!(exceeded().get(), 1);
+        value.store(10, Ordering::SeqCst);
+        resource.check();
+        assert_eq!(exceeded().get(), 0);
+    }
+}
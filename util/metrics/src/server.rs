@@ -0,0 +1,178 @@
+//! A minimal HTTP server answering `GET /metrics` with a rendered
+//! [Registry], one request per connection.
+
+use crate::{render, Registry};
+use log::{debug, info};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const METRICS_PATH: &str = "/metrics";
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+// A scraper must not hold the server: its request is read in time and in
+// few bytes, in a thread of its own, and the other connections are closed
+// at once while the threads are busy
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_REQUEST_BYTES: u64 = 8 * 1024;
+const MAX_CONNECTIONS: usize = 16;
+
+/// Listens on `address` and serves the metrics of `registry` from a thread
+pub fn start_server<A: ToSocketAddrs>(address: A, registry: Arc<Registry>) -> io::Result<()> {
+    let listener = TcpListener::bind(address)?;
+    info!(target: "metrics", "serving metrics on {}", listener.local_addr()?);
+    let connections = Arc::new(AtomicUsize::new(0));
+    thread::Builder::new()
+        .name("metrics_server".to_string())
+        .spawn(move || {
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(stream) => accept(stream, &registry, &connections),
+                    Err(err) => debug!(target: "metrics", "accept: {}", err),
+                }
+            }
+        })?;
+    Ok(())
+}
+
+fn accept(stream: TcpStream, registry: &Arc<Registry>, connections: &Arc<AtomicUsize>) {
+    if connections.fetch_add(1, Ordering::SeqCst) >= MAX_CONNECTIONS {
+        connections.fetch_sub(1, Ordering::SeqCst);
+        debug!(target: "metrics", "too many connections, closing one");
+        return;
+    }
+    let registry = Arc::clone(registry);
+    let served = Arc::clone(connections);
+    let spawned = thread::Builder::new()
+        .name("metrics_connection".to_string())
+        .spawn(move || {
+            if let Err(err) = serve(stream, &registry) {
+                debug!(target: "metrics", "serve metrics: {}", err);
+            }
+            served.fetch_sub(1, Ordering::SeqCst);
+        });
+    if let Err(err) = spawned {
+        connections.fetch_sub(1, Ordering::SeqCst);
+        debug!(target: "metrics", "start a connection thread: {}", err);
+    }
+}
+
+// The reads of a stream fail once the deadline passed, however slowly the
+// bytes come
+struct Deadline<'a> {
+    stream: &'a TcpStream,
+    deadline: Instant,
+}
+
+impl<'a> Read for Deadline<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let now = Instant::now();
+        if now >= self.deadline {
+            return Err(io::Error::new(io::ErrorKind::TimedOut, "request timed out"));
+        }
+        self.stream.set_read_timeout(Some(self.deadline - now))?;
+        (&*self.stream).read(buf)
+    }
+}
+
+fn serve(stream: TcpStream, registry: &Registry) -> io::Result<()> {
+    let deadline = Deadline {
+        stream: &stream,
+        deadline: Instant::now() + REQUEST_TIMEOUT,
+    };
+    let mut reader = BufReader::new(deadline.take(MAX_REQUEST_BYTES));
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // The headers are not needed, but must end before the limit
+    let mut header = String::new();
+    loop {
+        header.clear();
+        if reader.read_line(&mut header)? == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "request too large or cut",
+            ));
+        }
+        if header.trim_end().is_empty() {
+            break;
+        }
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let (status, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some(METRICS_PATH)) => ("200 OK", render(registry)),
+        (Some("GET"), _) => ("404 Not Found", "not found\n".to_owned()),
+        _ => ("405 Method Not Allowed", "only GET is allowed\n".to_owned()),
+    };
+    let mut writer = &stream;
+    write!(
+        writer,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        CONTENT_TYPE,
+        body.len(),
+        body
+    )?;
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get(address: &str, path: &str) -> String {
+        let mut stream = TcpStream::connect(address).unwrap();
+        write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    fn start(registry: &Arc<Registry>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        drop(listener);
+        start_server(&address, Arc::clone(registry)).unwrap();
+        address
+    }
+
+    #[test]
+    fn serve_metrics() {
+        let registry = Arc::new(Registry::default());
+        let address = start(&registry);
+        registry.counter("metrics_server_test_total", Vec::new()).inc();
+
+        let response = get(&address, METRICS_PATH);
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("\r\n\r\n"));
+        assert!(response.contains("\nmetrics_server_test_total 1\n"));
+        assert!(get(&address, "/").starts_with("HTTP/1.1 404 Not Found\r\n"));
+    }
+
+    #[test]
+    fn slow_and_large_requests() {
+        let registry = Arc::new(Registry::default());
+        let address = start(&registry);
+
+        // A client which never ends its request holds only its connection
+        let mut slow = TcpStream::connect(&address).unwrap();
+        write!(slow, "GET {} HTTP/1.1\r\n", METRICS_PATH).unwrap();
+        assert!(get(&address, METRICS_PATH).starts_with("HTTP/1.1 200 OK\r\n"));
+
+        let mut large = TcpStream::connect(&address).unwrap();
+        write!(large, "GET {} HTTP/1.1\r\n", METRICS_PATH).unwrap();
+        let header = format!("X-Padding: {}\r\n", "a".repeat(1024));
+        for _ in 0..MAX_REQUEST_BYTES / 1024 {
+            // The server may close the connection before all is written
+            if large.write_all(header.as_bytes()).is_err() {
+                break;
+            }
+        }
+        let mut response = String::new();
+        let _ = large.read_to_string(&mut response);
+        assert!(response.is_empty());
+        drop(slow);
+    }
+}
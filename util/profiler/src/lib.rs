@@ -0,0 +1,279 @@
+//! # The Profiler Library
+//!
+//! Profiles the scopes opened with [scope] on demand, at the cost of an atomic
+//! load when not profiling. A CPU profile weighs every stack of scopes with
+//! the microseconds spent in it, out of the nested scopes. A heap profile
+//! weighs it with the bytes allocated in it, which the [CountingAllocator]
+//! counts when it is the global allocator. It is built with the `heap`
+//! feature only, without it the heap profiles are refused.
+//!
+//! Profiles are written as folded stacks, one `thread;scope;scope weight`
+//! line a stack, which `flamegraph.pl` and `inferno` render.
+
+use ckb_util::Mutex;
+use lazy_static::lazy_static;
+#[cfg(feature = "heap")]
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const OFF: usize = 0;
+const CPU: usize = 1;
+const HEAP: usize = 2;
+
+static ACTIVE: AtomicUsize = AtomicUsize::new(OFF);
+// Tells the scopes entered in a previous profile
+static GENERATION: AtomicUsize = AtomicUsize::new(0);
+
+lazy_static! {
+    static ref PROFILE: Mutex<Option<Running>> = Mutex::new(None);
+}
+
+thread_local! {
+    static STACK: RefCell<Vec<Frame>> = RefCell::new(Vec::new());
+    static ALLOCATED: Cell<u64> = Cell::new(0);
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kind {
+    Cpu,
+    Heap,
+}
+
+impl FromStr for Kind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "cpu" => Ok(Kind::Cpu),
+            "heap" => Ok(Kind::Heap),
+            _ => Err(format!("unknown profile kind {:?}, cpu or heap", s)),
+        }
+    }
+}
+
+struct Running {
+    kind: Kind,
+    started_at: Instant,
+    stacks: HashMap<String, u64>,
+}
+
+/// The weights of the stacks of scopes seen while profiling
+#[derive(Debug)]
+pub struct Profile {
+    pub kind: Kind,
+    pub duration: Duration,
+    stacks: Vec<(String, u64)>,
+}
+
+impl Profile {
+    pub fn stacks(&self) -> &[(String, u64)] {
+        &self.stacks
+    }
+
+    /// Writes the folded stacks, the heaviest first
+    pub fn write_folded<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        for (stack, weight) in &self.stacks {
+            writeln!(writer, "{} {}", stack, weight)?;
+        }
+        writer.flush()
+    }
+}
+
+/// Starts a profile, fails when one is running
+pub fn start(kind: Kind) -> Result<(), String> {
+    if kind == Kind::Heap && !cfg!(feature = "heap") {
+        return Err("heap profiles need a node built with the heap-profile feature".to_owned());
+    }
+    let mut profile = PROFILE.lock();
+    if let Some(ref running) = *profile {
+        return Err(format!("a {:?} profile is running", running.kind));
+    }
+    *profile = Some(Running {
+        kind,
+        started_at: Instant::now(),
+        stacks: HashMap::new(),
+    });
+    GENERATION.fetch_add(1, Ordering::SeqCst);
+    let active = match kind {
+        Kind::Cpu => CPU,
+        Kind::Heap => HEAP,
+    };
+    ACTIVE.store(active, Ordering::SeqCst);
+    Ok(())
+}
+
+/// Stops the running profile, the scopes still open are not in it
+pub fn stop() -> Result<Profile, String> {
+    let mut profile = PROFILE.lock();
+    ACTIVE.store(OFF, Ordering::SeqCst);
+    let running = profile.take().ok_or_else(|| "no profile is running".to_owned())?;
+    let mut stacks: Vec<_> = running.stacks.into_iter().collect();
+    stacks.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    Ok(Profile {
+        kind: running.kind,
+        duration: running.started_at.elapsed(),
+        stacks,
+    })
+}
+
+struct Frame {
+    name: &'static str,
+    generation: usize,
+    start: u64,
+    // The weight of the nested scopes, out of this one
+    nested: u64,
+}
+
+/// Profiled until dropped
+#[must_use]
+pub struct Scope {
+    kind: usize,
+}
+
+/// Opens the scope `name` in the current thread, nested in the ones open
+pub fn scope(name: &'static str) -> Scope {
+    let kind = ACTIVE.load(Ordering::Relaxed);
+    if kind != OFF {
+        let frame = Frame {
+            name,
+            generation: GENERATION.load(Ordering::SeqCst),
+            start: measure(kind),
+            nested: 0,
+        };
+        STACK.with(|stack| stack.borrow_mut().push(frame));
+    }
+    Scope { kind }
+}
+
+impl Drop for Scope {
+    fn drop(&mut self) {
+        if self.kind == OFF {
+            return;
+        }
+        let weight = measure(self.kind);
+        STACK.with(|stack| {
+            let mut stack = stack.borrow_mut();
+            let frame = match stack.pop() {
+                Some(frame) => frame,
+                None => return,
+            };
+            let total = weight.saturating_sub(frame.start);
+            if let Some(parent) = stack.last_mut() {
+                parent.nested += total;
+            }
+            if frame.generation != GENERATION.load(Ordering::SeqCst)
+                || ACTIVE.load(Ordering::SeqCst) != self.kind
+            {
+                return;
+            }
+            let mut key = thread::current().name().unwrap_or("unnamed").to_owned();
+            for outer in stack.iter() {
+                key.push(';');
+                key.push_str(outer.name);
+            }
+            key.push(';');
+            key.push_str(frame.name);
+            if let Some(ref mut running) = *PROFILE.lock() {
+                *running.stacks.entry(key).or_insert(0) += total.saturating_sub(frame.nested);
+            }
+        });
+    }
+}
+
+fn measure(kind: usize) -> u64 {
+    if kind == HEAP {
+        return ALLOCATED.with(Cell::get);
+    }
+    lazy_static! {
+        static ref EPOCH: Instant = Instant::now();
+    }
+    let elapsed = EPOCH.elapsed();
+    elapsed.as_secs() * 1_000_000 + u64::from(elapsed.subsec_micros())
+}
+
+/// The system allocator counting the bytes each thread allocates during a
+/// heap profile, set with `#[global_allocator]`
+#[cfg(feature = "heap")]
+pub struct CountingAllocator;
+
+#[cfg(feature = "heap")]
+impl CountingAllocator {
+    fn count(size: usize) {
+        if ACTIVE.load(Ordering::Relaxed) == HEAP {
+            // No destructor, the counter is there until the thread exits
+            ALLOCATED.with(|allocated| allocated.set(allocated.get() + size as u64));
+        }
+    }
+}
+
+#[cfg(feature = "heap")]
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        Self::count(layout.size());
+        System.alloc(layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        Self::count(layout.size());
+        System.alloc_zeroed(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        Self::count(new_size.saturating_sub(layout.size()));
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn folded_stacks() {
+        assert!(stop().is_err());
+        {
+            let _before = scope("before");
+        }
+        assert_eq!(start(Kind::Heap).is_ok(), cfg!(feature = "heap"));
+        let _ = stop();
+        start(Kind::Cpu).unwrap();
+        assert!(start(Kind::Heap).is_err());
+        {
+            let _outer = scope("outer");
+            thread::sleep(Duration::from_millis(20));
+            let _inner = scope("inner");
+            thread::sleep(Duration::from_millis(10));
+        }
+        let profile = stop().unwrap();
+        assert_eq!(profile.kind, Kind::Cpu);
+
+        let thread = thread::current().name().unwrap().to_owned();
+        let weight = |stack: &str| {
+            let stack = format!("{};{}", thread, stack);
+            profile
+                .stacks()
+                .iter()
+                .find(|(s, _)| *s == stack)
+                .map(|(_, weight)| *weight)
+        };
+        assert!(weight("outer").unwrap() >= 20_000);
+        assert!(weight("outer;inner").unwrap() >= 10_000);
+        assert_eq!(weight("before"), None);
+
+        let mut folded = Vec::new();
+        profile.write_folded(&mut folded).unwrap();
+        let folded = String::from_utf8(folded).unwrap();
+        assert_eq!(folded.lines().count(), 2);
+        assert!(folded.starts_with(&format!("{};outer ", thread)));
+    }
+}
@@ -1,9 +1,10 @@
 use crossbeam_channel::Sender;
 use futures::sync::oneshot;
-use log::error;
+use log::{error, info};
 use parking_lot::Mutex;
 use std::sync::Arc;
 use std::thread::JoinHandle;
+use std::time::Instant;
 
 #[derive(Debug)]
 pub enum SignalSender {
@@ -54,13 +55,99 @@ impl<T> StopHandler<T> {
             .inner
             .take()
             .expect("Stop signal can only be sent once");
+        // The handler is gone when a clone was stopped already
         if let Ok(lock) = Arc::try_unwrap(inner) {
-            let handler = lock.lock().take().expect("Handler can only be taken once");
-            let Handler { signal, thread } = handler;
-            signal.send();
-            if let Err(e) = thread.join() {
-                error!("handler thread join error {:?}", e);
-            };
+            if let Some(handler) = lock.lock().take() {
+                handler.stop();
+            }
+        };
+    }
+
+    /// Stops the thread and waits for it even though other clones are alive,
+    /// their requests fail from now on
+    pub fn stop(&self) {
+        let handler = self
+            .inner
+            .as_ref()
+            .and_then(|inner| inner.lock().take());
+        if let Some(handler) = handler {
+            handler.stop();
+        }
+    }
+}
+
+impl<T> Handler<T> {
+    fn stop(self) {
+        let Handler { signal, thread } = self;
+        signal.send();
+        if let Err(e) = thread.join() {
+            error!("handler thread join error {:?}", e);
         };
     }
 }
+
+/// Runs the steps of a shutdown one after another, in the order added
+#[derive(Default)]
+pub struct Shutdown {
+    steps: Vec<(&'static str, Box<dyn FnMut()>)>,
+}
+
+impl Shutdown {
+    pub fn step<F: FnOnce() + 'static>(mut self, name: &'static str, step: F) -> Self {
+        // A boxed `FnOnce` can not be called
+        let mut step = Some(step);
+        self.steps.push((
+            name,
+            Box::new(move || {
+                if let Some(step) = step.take() {
+                    step()
+                }
+            }),
+        ));
+        self
+    }
+
+    pub fn run(self) {
+        for (name, mut step) in self.steps {
+            let started_at = Instant::now();
+            info!(target: "main", "Stopping {}", name);
+            step();
+            info!(
+                target: "main",
+                "Stopped {} in {:?}", name, started_at.elapsed()
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossbeam_channel::bounded;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use std::thread;
+
+    #[test]
+    fn stop_with_clones() {
+        let (signal_sender, signal_receiver) = bounded(1);
+        let thread = thread::spawn(move || signal_receiver.recv().is_ok());
+        let mut handler = StopHandler::new(SignalSender::Crossbeam(signal_sender), thread);
+        let mut clone = handler.clone();
+        clone.stop();
+        // Already stopped, dropping the clones sends nothing
+        clone.try_send();
+        handler.try_send();
+    }
+
+    #[test]
+    fn shutdown_in_order() {
+        let stopped = Rc::new(RefCell::new(Vec::new()));
+        let (first, second) = (Rc::clone(&stopped), Rc::clone(&stopped));
+        Shutdown::default()
+            .step("first", move || first.borrow_mut().push(1))
+            .step("second", move || second.borrow_mut().push(2))
+            .run();
+        assert_eq!(*stopped.borrow(), vec![1, 2]);
+    }
+}
@@ -0,0 +1,180 @@
+//! # The Time Library
+//!
+//! The unix time read by the node. It comes from the clock scoped to the
+//! current thread if any, else from the time the process is [frozen](freeze)
+//! at if any, else from [faketime], which reads the system clock or, once
+//! enabled, the faketime file.
+//!
+//! A scoped [Clock] only moves when its owner sets or advances it, and is only
+//! seen by the threads which entered it, so tests running concurrently each
+//! have their own time:
+//!
+//! ```
+//! use ckb_time::{unix_time_as_millis, Clock};
+//!
+//! let clock = Clock::new(1_000);
+//! let _scope = clock.enter();
+//! assert_eq!(unix_time_as_millis(), 1_000);
+//! clock.advance(500);
+//! assert_eq!(unix_time_as_millis(), 1_500);
+//! ```
+//!
+//! A thread spawned with [inherit] runs on the clock of the thread spawning it,
+//! which is how the services started in a scope share its clock.
+//!
+//! [freeze] stops the time of every thread not in a scope, without touching
+//! the faketime file or the environment the node may have been started with,
+//! until [unfreeze] returns them to it.
+
+use ckb_util::Mutex;
+use lazy_static::lazy_static;
+use std::cell::RefCell;
+use std::sync::Arc;
+use std::time::Duration;
+
+thread_local! {
+    static SCOPED_CLOCK: RefCell<Option<Clock>> = RefCell::new(None);
+}
+
+lazy_static! {
+    static ref FROZEN_MILLIS: Mutex<Option<u64>> = Mutex::new(None);
+}
+
+/// A clock in milliseconds since the unix epoch, its clones share the time
+#[derive(Clone, Debug)]
+pub struct Clock {
+    millis: Arc<Mutex<u64>>,
+}
+
+impl Clock {
+    pub fn new(millis: u64) -> Clock {
+        Clock {
+            millis: Arc::new(Mutex::new(millis)),
+        }
+    }
+
+    /// The clock the current thread entered, if any
+    pub fn current() -> Option<Clock> {
+        SCOPED_CLOCK.with(|clock| clock.borrow().clone())
+    }
+
+    pub fn now(&self) -> u64 {
+        *self.millis.lock()
+    }
+
+    pub fn set(&self, millis: u64) {
+        *self.millis.lock() = millis;
+    }
+
+    pub fn advance(&self, millis: u64) {
+        *self.millis.lock() += millis;
+    }
+
+    /// Reads the time of the current thread from this clock until the scope
+    /// is dropped, scopes nest
+    pub fn enter(&self) -> Scope {
+        let previous = SCOPED_CLOCK.with(|clock| clock.replace(Some(self.clone())));
+        Scope { previous }
+    }
+}
+
+/// Restores the clock the thread had before [Clock::enter] when dropped
+#[must_use]
+pub struct Scope {
+    previous: Option<Clock>,
+}
+
+impl Drop for Scope {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        SCOPED_CLOCK.with(|clock| *clock.borrow_mut() = previous);
+    }
+}
+
+/// Freezes the time of the process at `millis`, until frozen again or
+/// [unfrozen](unfreeze)
+pub fn freeze(millis: u64) {
+    *FROZEN_MILLIS.lock() = Some(millis);
+}
+
+pub fn unfreeze() {
+    *FROZEN_MILLIS.lock() = None;
+}
+
+fn frozen_millis() -> Option<u64> {
+    *FROZEN_MILLIS.lock()
+}
+
+pub fn unix_time() -> Duration {
+    match Clock::current() {
+        Some(clock) => Duration::from_millis(clock.now()),
+        None => frozen_millis()
+            .map(Duration::from_millis)
+            .unwrap_or_else(faketime::unix_time),
+    }
+}
+
+pub fn unix_time_as_millis() -> u64 {
+    match Clock::current() {
+        Some(clock) => clock.now(),
+        None => frozen_millis().unwrap_or_else(faketime::unix_time_as_millis),
+    }
+}
+
+/// Wraps the body of a thread to run it on the clock of the current thread
+pub fn inherit<F, T>(f: F) -> impl FnOnce() -> T
+where
+    F: FnOnce() -> T,
+{
+    let clock = Clock::current();
+    move || {
+        let _scope = clock.as_ref().map(Clock::enter);
+        f()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn scoped_clocks() {
+        let outer = Clock::new(10);
+        let outer_scope = outer.enter();
+        {
+            let inner = Clock::new(20);
+            let _inner_scope = inner.enter();
+            assert_eq!(unix_time_as_millis(), 20);
+        }
+        assert_eq!(unix_time_as_millis(), 10);
+
+        outer.set(30);
+        let child = thread::spawn(inherit(unix_time_as_millis));
+        assert_eq!(child.join().unwrap(), 30);
+        // A thread not spawned with `inherit` has the clock of the process
+        let other = thread::spawn(|| Clock::current().is_none());
+        assert!(other.join().unwrap());
+
+        drop(outer_scope);
+        assert!(Clock::current().is_none());
+    }
+
+    #[test]
+    fn frozen_process() {
+        freeze(1_000);
+        assert_eq!(unix_time_as_millis(), 1_000);
+        assert_eq!(unix_time(), Duration::from_millis(1_000));
+        let other = thread::spawn(unix_time_as_millis);
+        assert_eq!(other.join().unwrap(), 1_000);
+        // A scoped clock still wins
+        {
+            let clock = Clock::new(20);
+            let _scope = clock.enter();
+            assert_eq!(unix_time_as_millis(), 20);
+        }
+
+        unfreeze();
+        assert!(unix_time_as_millis() > 1_000);
+    }
+}
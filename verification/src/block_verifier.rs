@@ -386,11 +386,15 @@ impl<CP: ChainProvider + Clone> UnclesVerifier<CP> {
     }
 }
 
+/// Verifies the commit transactions of `block`, returns the cycles taken by
+/// all their scripts. Each transaction may take at most `max_tx_cycles`, and
+/// all of them together at most `max_cycles`.
 pub fn verify_transactions<F: Fn(&OutPoint) -> CellStatus>(
     block: &Block,
     max_cycles: Cycle,
+    max_tx_cycles: Cycle,
     cell: F,
-) -> Result<(), Error> {
+) -> Result<Cycle, Error> {
     let mut output_indexs = FnvHashMap::default();
     let mut seen_inputs = FnvHashSet::default();
 
@@ -428,7 +432,7 @@ pub fn verify_transactions<F: Fn(&OutPoint) -> CellStatus>(
             || 0,
             |cycles: Cycle, (index, tx)| {
                 TransactionVerifier::new(&tx)
-                    .verify(max_cycles)
+                    .verify(max_tx_cycles.min(max_cycles))
                     .map_err(|e| Error::Transactions((index, e)))
                     .and_then(|current_cycles| {
                         cycles
@@ -458,7 +462,6 @@ pub fn verify_transactions<F: Fn(&OutPoint) -> CellStatus>(
                     })
             },
         )
-        .map(|_| ())
 }
 
 #[derive(Clone)]
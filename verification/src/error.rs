@@ -1,8 +1,10 @@
-use ckb_core::BlockNumber;
+use ckb_core::{BlockNumber, Cycle};
 use ckb_script::ScriptError;
 use ckb_shared::error::SharedError;
 use numext_fixed_hash::H256;
 use numext_fixed_uint::U256;
+use std::error;
+use std::fmt;
 
 /// Block verification error
 #[derive(Debug, PartialEq, Clone, Eq)]
@@ -123,11 +125,124 @@ pub enum TransactionError {
     Empty,
     /// Sum of all outputs capacity exceed sum of all inputs in the transaction
     OutputsSumOverflow,
-    InvalidScript,
-    ScriptFailure(ScriptError),
+    /// The unlock script of the input does not match the lock of the spent cell
+    InvalidScript(usize),
+    /// A script failed, after consuming the cycles
+    ScriptFailure(ScriptError, Cycle),
     InvalidSignature,
-    DoubleSpent,
-    UnknownInput,
+    /// The referenced cell is already spent
+    DoubleSpent(CellIndex),
+    /// The referenced cell is not found in the chain
+    UnknownInput(CellIndex),
+}
+
+/// Position of a cell referenced by a transaction
+#[derive(Debug, PartialEq, Clone, Copy, Eq)]
+pub enum CellIndex {
+    Input(usize),
+    Dep(usize),
+}
+
+impl Error {
+    /// Misbehavior score of the peer which relayed the failing block or
+    /// header. It is 0 when the failure may be caused by the local view of
+    /// the chain or the local clock rather than by the data itself.
+    pub fn misbehavior(&self) -> u32 {
+        match self {
+            Error::UnknownParent(_)
+            | Error::Chain(_)
+            | Error::Commit(CommitError::AncestorNotFound)
+            | Error::Difficulty(DifficultyError::AncestorNotFound)
+            | Error::Timestamp(TimestampError::BlockTimeTooNew { .. }) => 0,
+            Error::Transactions((_, TransactionError::DoubleSpent(_)))
+            | Error::Transactions((_, TransactionError::UnknownInput(_))) => 0,
+            Error::Difficulty(_) | Error::Number(_) | Error::Timestamp(_) => 50,
+            _ => 100,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Pow(PowError::Boundary { expected, actual }) => write!(
+                f,
+                "pow proof does not meet the difficulty, expected {:#x}, actual {:#x}",
+                expected, actual
+            ),
+            Error::Pow(PowError::InvalidProof) => write!(f, "invalid pow proof"),
+            Error::Timestamp(TimestampError::BlockTimeTooOld { min, found }) => write!(
+                f,
+                "block timestamp {} is not after the median time {}",
+                found, min
+            ),
+            Error::Timestamp(TimestampError::BlockTimeTooNew { max, found }) => write!(
+                f,
+                "block timestamp {} is too far in the future, max {}",
+                found, max
+            ),
+            Error::Number(NumberError { expected, actual }) => write!(
+                f,
+                "invalid block number, expected {}, actual {}",
+                expected, actual
+            ),
+            Error::Difficulty(e) => write!(f, "invalid difficulty: {:?}", e),
+            Error::Transactions((index, e)) => write!(f, "invalid transaction {}: {}", index, e),
+            Error::Chain(e) => write!(f, "chain error: {:?}", e),
+            Error::CommitTransactionsEmpty => write!(f, "no committed transactions"),
+            Error::ProposalTransactionDuplicate => write!(f, "duplicate proposed transactions"),
+            Error::CommitTransactionDuplicate => write!(f, "duplicate committed transactions"),
+            Error::ProposalTransactionsRoot => {
+                write!(f, "proposed transactions root does not match the header")
+            }
+            Error::CommitTransactionsRoot => {
+                write!(f, "committed transactions root does not match the header")
+            }
+            Error::UnknownParent(hash) => write!(f, "unknown parent block {:#x}", hash),
+            Error::Uncles(e) => write!(f, "invalid uncles: {:?}", e),
+            Error::Cellbase(e) => write!(f, "invalid cellbase: {:?}", e),
+            Error::Commit(e) => write!(f, "propose-then-commit rule violated: {:?}", e),
+            Error::ExceededMaximumCycles => write!(f, "scripts exceed the block cycles limit"),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+impl fmt::Display for TransactionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TransactionError::NullInput => write!(f, "null input"),
+            TransactionError::CapacityOverflow => {
+                write!(f, "output data exceeds the output capacity")
+            }
+            TransactionError::DuplicateInputs => write!(f, "duplicate inputs"),
+            TransactionError::Empty => write!(f, "no inputs or outputs"),
+            TransactionError::OutputsSumOverflow => {
+                write!(f, "outputs capacity exceeds inputs capacity")
+            }
+            TransactionError::InvalidScript(index) => {
+                write!(f, "input {} unlock script does not match the cell lock", index)
+            }
+            TransactionError::ScriptFailure(e, cycles) => {
+                write!(f, "script failure after {} cycles: {:?}", cycles, e)
+            }
+            TransactionError::InvalidSignature => write!(f, "invalid signature"),
+            TransactionError::DoubleSpent(cell) => write!(f, "{} is already spent", cell),
+            TransactionError::UnknownInput(cell) => write!(f, "{} is unknown", cell),
+        }
+    }
+}
+
+impl error::Error for TransactionError {}
+
+impl fmt::Display for CellIndex {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CellIndex::Input(index) => write!(f, "input {}", index),
+            CellIndex::Dep(index) => write!(f, "dep {}", index),
+        }
+    }
 }
 
 impl From<SharedError> for Error {
@@ -1,10 +1,9 @@
 use super::Verifier;
 use crate::error::{DifficultyError, Error, NumberError, PowError, TimestampError};
-use crate::shared::ALLOWED_FUTURE_BLOCKTIME;
 use ckb_core::header::Header;
 use ckb_pow::PowEngine;
 use ckb_shared::block_median_time_context::BlockMedianTimeContext;
-use faketime::unix_time_as_millis;
+use ckb_time::unix_time_as_millis;
 use numext_fixed_uint::U256;
 use std::marker::PhantomData;
 use std::sync::Arc;
@@ -19,14 +18,21 @@ pub trait HeaderResolver {
 
 pub struct HeaderVerifier<T, M> {
     pub pow: Arc<dyn PowEngine>,
+    // How far, in milliseconds, a block timestamp may run ahead of the local clock
+    max_block_time_drift: u64,
     block_median_time_context: M,
     _phantom: PhantomData<T>,
 }
 
 impl<T, M: BlockMedianTimeContext + Clone> HeaderVerifier<T, M> {
-    pub fn new(block_median_time_context: M, pow: Arc<dyn PowEngine>) -> Self {
+    pub fn new(
+        block_median_time_context: M,
+        pow: Arc<dyn PowEngine>,
+        max_block_time_drift: u64,
+    ) -> Self {
         HeaderVerifier {
             pow,
+            max_block_time_drift,
             block_median_time_context,
             _phantom: PhantomData,
         }
@@ -44,7 +50,12 @@ impl<T: HeaderResolver, M: BlockMedianTimeContext + Clone> Verifier for HeaderVe
             .parent()
             .ok_or_else(|| Error::UnknownParent(header.parent_hash().clone()))?;
         NumberVerifier::new(parent, header).verify()?;
-        TimestampVerifier::new(self.block_median_time_context.clone(), header).verify()?;
+        TimestampVerifier::new(
+            self.block_median_time_context.clone(),
+            header,
+            self.max_block_time_drift,
+        )
+        .verify()?;
         DifficultyVerifier::verify(target)?;
         Ok(())
     }
@@ -53,14 +64,16 @@ impl<T: HeaderResolver, M: BlockMedianTimeContext + Clone> Verifier for HeaderVe
 pub struct TimestampVerifier<'a, M> {
     header: &'a Header,
     block_median_time_context: M,
+    max_drift: u64,
     now: u64,
 }
 
 impl<'a, M: BlockMedianTimeContext> TimestampVerifier<'a, M> {
-    pub fn new(block_median_time_context: M, header: &'a Header) -> Self {
+    pub fn new(block_median_time_context: M, header: &'a Header, max_drift: u64) -> Self {
         TimestampVerifier {
             block_median_time_context,
             header,
+            max_drift,
             now: unix_time_as_millis(),
         }
     }
@@ -83,7 +96,7 @@ impl<'a, M: BlockMedianTimeContext> TimestampVerifier<'a, M> {
                 found: self.header.timestamp(),
             }));
         }
-        let max = self.now + ALLOWED_FUTURE_BLOCKTIME;
+        let max = self.now + self.max_drift;
         if self.header.timestamp() > max {
             return Err(Error::Timestamp(TimestampError::BlockTimeTooNew {
                 max,
@@ -1,14 +1,13 @@
 mod block_verifier;
 mod error;
 mod header_verifier;
-mod shared;
 mod transaction_verifier;
 
 #[cfg(test)]
 mod tests;
 
 pub use crate::block_verifier::{verify_transactions, BlockVerifier, HeaderResolverWrapper};
-pub use crate::error::{Error, TransactionError};
+pub use crate::error::{CellIndex, DifficultyError, Error, TimestampError, TransactionError};
 pub use crate::header_verifier::{HeaderResolver, HeaderVerifier};
 pub use crate::transaction_verifier::TransactionVerifier;
 
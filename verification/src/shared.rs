@@ -1 +0,0 @@
-pub const ALLOWED_FUTURE_BLOCKTIME: u64 = 15 * 1000; // 15 Second
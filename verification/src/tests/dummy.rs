@@ -1,7 +1,7 @@
 use ckb_chain_spec::consensus::Consensus;
 use ckb_core::block::Block;
 use ckb_core::cell::{CellProvider, CellStatus};
-use ckb_core::extras::BlockExt;
+use ckb_core::extras::{BlockExt, TransactionAddress};
 use ckb_core::header::{BlockNumber, Header};
 use ckb_core::transaction::{Capacity, OutPoint, ProposalShortId, Transaction};
 use ckb_core::uncle::UncleBlock;
@@ -77,6 +77,10 @@ impl ChainProvider for DummyChainProvider {
         panic!("Not implemented!");
     }
 
+    fn get_transaction_address(&self, _hash: &H256) -> Option<TransactionAddress> {
+        panic!("Not implemented!");
+    }
+
     fn contain_transaction(&self, _hash: &H256) -> bool {
         panic!("Not implemented!");
     }
@@ -0,0 +1,36 @@
+use super::super::error::{CellIndex, Error, PowError, TimestampError, TransactionError};
+use numext_fixed_hash::H256;
+
+#[test]
+fn test_error_misbehavior() {
+    assert_eq!(Error::Pow(PowError::InvalidProof).misbehavior(), 100);
+    assert_eq!(Error::CommitTransactionsRoot.misbehavior(), 100);
+    assert_eq!(
+        Error::Transactions((1, TransactionError::DuplicateInputs)).misbehavior(),
+        100
+    );
+    // Depends on the local view of the chain
+    assert_eq!(Error::UnknownParent(H256::zero()).misbehavior(), 0);
+    assert_eq!(
+        Error::Transactions((1, TransactionError::UnknownInput(CellIndex::Input(0))))
+            .misbehavior(),
+        0
+    );
+    // Depends on the local clock
+    assert_eq!(
+        Error::Timestamp(TimestampError::BlockTimeTooNew { max: 1, found: 2 }).misbehavior(),
+        0
+    );
+}
+
+#[test]
+fn test_error_display() {
+    assert_eq!(
+        Error::Transactions((2, TransactionError::DoubleSpent(CellIndex::Dep(1)))).to_string(),
+        "invalid transaction 2: dep 1 is already spent"
+    );
+    assert_eq!(
+        Error::Transactions((1, TransactionError::InvalidScript(0))).to_string(),
+        "invalid transaction 1: input 0 unlock script does not match the cell lock"
+    );
+}
@@ -0,0 +1,94 @@
+use super::super::error::{Error, TimestampError};
+use super::super::header_verifier::TimestampVerifier;
+use ckb_core::header::{Header, HeaderBuilder};
+use ckb_shared::block_median_time_context::BlockMedianTimeContext;
+use ckb_time::Clock;
+use fnv::FnvHashMap;
+use numext_fixed_hash::H256;
+
+#[derive(Clone)]
+struct MockMedianTime {
+    headers: FnvHashMap<H256, Header>,
+}
+
+impl BlockMedianTimeContext for MockMedianTime {
+    fn block_count(&self) -> u32 {
+        11
+    }
+
+    fn timestamp(&self, hash: &H256) -> Option<u64> {
+        self.headers.get(hash).map(Header::timestamp)
+    }
+
+    fn parent_hash(&self, hash: &H256) -> Option<H256> {
+        self.headers
+            .get(hash)
+            .map(|header| header.parent_hash().clone())
+    }
+}
+
+// Chain of `count` headers, block i is stamped at `i * 1000`
+fn build_context(count: u64) -> (MockMedianTime, Header) {
+    let mut headers = FnvHashMap::default();
+    let mut last = HeaderBuilder::default().build();
+    headers.insert(last.hash(), last.clone());
+    for number in 1..count {
+        let header = HeaderBuilder::default()
+            .number(number)
+            .parent_hash(last.hash())
+            .timestamp(number * 1000)
+            .build();
+        headers.insert(header.hash(), header.clone());
+        last = header;
+    }
+    (MockMedianTime { headers }, last)
+}
+
+fn child(parent: &Header, timestamp: u64) -> Header {
+    HeaderBuilder::default()
+        .number(parent.number() + 1)
+        .parent_hash(parent.hash())
+        .timestamp(timestamp)
+        .build()
+}
+
+#[test]
+fn test_timestamp_verifier() {
+    let _clock = Clock::new(100_000).enter();
+
+    // The median of blocks 10..=20 is 15000
+    let (context, tip) = build_context(21);
+
+    let header = child(&tip, 15_000);
+    assert_eq!(
+        TimestampVerifier::new(context.clone(), &header, 5_000).verify(),
+        Err(Error::Timestamp(TimestampError::BlockTimeTooOld {
+            min: 15_000,
+            found: 15_000,
+        }))
+    );
+
+    let header = child(&tip, 15_001);
+    assert!(TimestampVerifier::new(context.clone(), &header, 5_000)
+        .verify()
+        .is_ok());
+
+    let header = child(&tip, 105_000);
+    assert!(TimestampVerifier::new(context.clone(), &header, 5_000)
+        .verify()
+        .is_ok());
+
+    let header = child(&tip, 105_001);
+    assert_eq!(
+        TimestampVerifier::new(context.clone(), &header, 5_000).verify(),
+        Err(Error::Timestamp(TimestampError::BlockTimeTooNew {
+            max: 105_000,
+            found: 105_001,
+        }))
+    );
+
+    // A wider drift accepts the same header
+    assert!(TimestampVerifier::new(context, &header, 10_000)
+        .verify()
+        .is_ok());
+}
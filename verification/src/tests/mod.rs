@@ -1,5 +1,7 @@
 mod block_verifier;
 mod commit_verifier;
 mod dummy;
+mod error;
+mod header_verifier;
 mod transaction_verifier;
 mod uncle_verifier;
@@ -1,10 +1,11 @@
 use super::super::transaction_verifier::{
-    CapacityVerifier, DuplicateInputsVerifier, EmptyVerifier, NullVerifier,
+    CapacityVerifier, DuplicateInputsVerifier, EmptyVerifier, NullVerifier, TransactionVerifier,
 };
 use crate::error::TransactionError;
 use ckb_core::cell::CellStatus;
 use ckb_core::cell::ResolvedTransaction;
-use ckb_core::transaction::{CellInput, CellOutput, OutPoint, TransactionBuilder};
+use ckb_core::generator::{Generator, GeneratorConfig};
+use ckb_core::transaction::{CellInput, CellOutput, OutPoint, Transaction, TransactionBuilder};
 use numext_fixed_hash::H256;
 
 #[test]
@@ -98,3 +99,49 @@ pub fn test_duplicate_inputs() {
         Some(TransactionError::DuplicateInputs)
     );
 }
+
+// Resolves the inputs of `transaction` to the outputs of `known`
+fn resolve(transaction: &Transaction, known: &[Transaction]) -> ResolvedTransaction {
+    let input_cells = transaction
+        .input_pts()
+        .iter()
+        .map(|out_point| {
+            known
+                .iter()
+                .find(|tx| tx.hash() == out_point.hash)
+                .and_then(|tx| tx.outputs().get(out_point.index as usize))
+                .map_or(CellStatus::Unknown, |output| CellStatus::Live(output.clone()))
+        })
+        .collect();
+    ResolvedTransaction {
+        transaction: transaction.clone(),
+        dep_cells: Vec::new(),
+        input_cells,
+    }
+}
+
+#[test]
+pub fn test_generated_transactions() {
+    let config = GeneratorConfig {
+        inputs: 2,
+        outputs: 3,
+        fee: 10,
+        ..Default::default()
+    };
+    let mut generator = Generator::new(config, 1);
+    let mut known = vec![generator.cellbase(0, 10, 10_000_000)];
+    for _ in 0..10 {
+        let transaction = generator.transaction().expect("funded");
+        let rtx = resolve(&transaction, &known);
+        assert!(TransactionVerifier::new(&rtx).verify(100_000_000).is_ok());
+        known.push(transaction);
+    }
+
+    generator.config_mut().valid_signatures = false;
+    let transaction = generator.transaction().expect("funded");
+    let rtx = resolve(&transaction, &known);
+    match TransactionVerifier::new(&rtx).verify(100_000_000) {
+        Err(TransactionError::ScriptFailure(_, _)) => {}
+        ret => panic!("unexpected verify result {:?}", ret),
+    }
+}
@@ -12,8 +12,7 @@ use ckb_db::memorydb::MemoryKeyValueDB;
 use ckb_notify::NotifyService;
 use ckb_shared::shared::{ChainProvider, Shared, SharedBuilder};
 use ckb_shared::store::ChainKVStore;
-#[cfg(not(disable_faketime))]
-use faketime;
+use ckb_time::Clock;
 use numext_fixed_hash::H256;
 use numext_fixed_uint::U256;
 use std::sync::Arc;
@@ -27,7 +26,6 @@ fn gen_block(parent_header: &Header, nonce: u64, difficulty: U256) -> Block {
         .timestamp(now)
         .number(number)
         .difficulty(difficulty)
-        .cellbase_id(cellbase.hash().clone())
         .nonce(nonce);
 
     BlockBuilder::default()
@@ -60,11 +58,11 @@ fn create_cellbase(number: BlockNumber) -> Transaction {
         .build()
 }
 
-#[cfg(not(disable_faketime))]
 #[test]
 fn test_uncle_verifier() {
-    let faketime_file = faketime::millis_tempfile(0).expect("create faketime file");
-    faketime::enable(&faketime_file);
+    // The chain service started below runs on the clock too
+    let clock = Clock::new(0);
+    let _scope = clock.enter();
 
     let mut consensus = Consensus::default();
     consensus.pow_time_span = 10;
@@ -77,7 +75,7 @@ fn test_uncle_verifier() {
     let mut chain1: Vec<Block> = Vec::new();
     let mut chain2: Vec<Block> = Vec::new();
 
-    faketime::write_millis(&faketime_file, 10).expect("write millis");
+    clock.set(10);
 
     let mut parent = shared.block_header(&shared.block_hash(0).unwrap()).unwrap();
     for i in 1..number {
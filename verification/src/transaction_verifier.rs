@@ -1,4 +1,4 @@
-use crate::error::TransactionError;
+use crate::error::{CellIndex, TransactionError};
 use ckb_core::transaction::{Capacity, Transaction};
 use ckb_core::{cell::ResolvedTransaction, Cycle};
 use ckb_script::TransactionScriptsVerifier;
@@ -51,27 +51,27 @@ impl<'a> InputVerifier<'a> {
 
     pub fn verify(&self) -> Result<(), TransactionError> {
         let mut inputs = self.resolved_transaction.transaction.inputs().iter();
-        for cs in &self.resolved_transaction.input_cells {
+        for (index, cs) in self.resolved_transaction.input_cells.iter().enumerate() {
             if cs.is_live() {
                 if let Some(ref input) = cs.get_live() {
                     // TODO: remove this once VM mmap is in place so we can
                     // do P2SH within the VM.
                     if input.lock != inputs.next().unwrap().unlock.type_hash() {
-                        return Err(TransactionError::InvalidScript);
+                        return Err(TransactionError::InvalidScript(index));
                     }
                 }
             } else if cs.is_dead() {
-                return Err(TransactionError::DoubleSpent);
+                return Err(TransactionError::DoubleSpent(CellIndex::Input(index)));
             } else if cs.is_unknown() {
-                return Err(TransactionError::UnknownInput);
+                return Err(TransactionError::UnknownInput(CellIndex::Input(index)));
             }
         }
 
-        for cs in &self.resolved_transaction.dep_cells {
+        for (index, cs) in self.resolved_transaction.dep_cells.iter().enumerate() {
             if cs.is_dead() {
-                return Err(TransactionError::DoubleSpent);
+                return Err(TransactionError::DoubleSpent(CellIndex::Dep(index)));
             } else if cs.is_unknown() {
-                return Err(TransactionError::UnknownInput);
+                return Err(TransactionError::UnknownInput(CellIndex::Dep(index)));
             }
         }
         Ok(())
@@ -91,8 +91,8 @@ impl<'a> ScriptVerifier<'a> {
 
     pub fn verify(&self, max_cycles: Cycle) -> Result<Cycle, TransactionError> {
         TransactionScriptsVerifier::new(&self.resolved_transaction)
-            .verify(max_cycles)
-            .map_err(TransactionError::ScriptFailure)
+            .verify_counting(max_cycles)
+            .map_err(|(e, cycles)| TransactionError::ScriptFailure(e, cycles))
     }
 }
 